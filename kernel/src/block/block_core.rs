@@ -51,6 +51,20 @@ impl fmt::Display for BlockType {
     }
 }
 
+/// What a disk reports about itself, e.g. from ATA IDENTIFY DEVICE
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskIdentity {
+    /// Model number, with the padding trimmed
+    pub model: String,
+    /// Serial number, with the padding trimmed
+    pub serial: String,
+    /// Total number of addressable sectors, which may be more than the block device has if the
+    /// driver can't address all of them
+    pub sectors: u64,
+    /// Whether the disk supports 48-bit LBA
+    pub lba48: bool,
+}
+
 /// Lower-level interface to block device drivers
 pub trait BlockOp {
     /// Read a block sector
@@ -61,6 +75,29 @@ pub trait BlockOp {
     /// wake up after the read operation is complete.
     unsafe fn read(&mut self, sector: BlockSector, buf: &mut [u8]) -> Result<(), BlockError>;
 
+    /// Read `count` contiguous block sectors starting at `start` into `buf`, which holds
+    /// `count * BLOCK_SECTOR_SIZE` bytes
+    ///
+    /// The default implementation reads one sector at a time. Drivers that can transfer several
+    /// sectors with a single command should override it.
+    ///
+    /// # Safety
+    ///
+    /// This function must be called with interrupts enabled. Otherwise, the block device may not
+    /// wake up after the read operation is complete.
+    unsafe fn read_sectors(
+        &mut self,
+        start: BlockSector,
+        count: BlockSector,
+        buf: &mut [u8],
+    ) -> Result<(), BlockError> {
+        debug_assert_eq!(buf.len(), count as usize * BLOCK_SECTOR_SIZE);
+        for (sector, sector_buf) in (start..).zip(buf.chunks_exact_mut(BLOCK_SECTOR_SIZE)) {
+            self.read(sector, sector_buf)?;
+        }
+        Ok(())
+    }
+
     /// Write a block sector
     ///
     /// # Safety
@@ -68,6 +105,28 @@ pub trait BlockOp {
     /// This function must be called with interrupts enabled. Otherwise, the block device may not
     /// wake up after the write operation is complete.
     unsafe fn write(&mut self, sector: BlockSector, buf: &[u8]) -> Result<(), BlockError>;
+
+    /// Make sure every write which has returned is on the device's permanent storage, e.g. by
+    /// flushing the disk's write cache
+    ///
+    /// The default implementation does nothing, which is right for devices without a write cache.
+    ///
+    /// # Safety
+    ///
+    /// This function must be called with interrupts enabled. Otherwise, the block device may not
+    /// wake up after the flush is complete.
+    unsafe fn flush(&mut self) -> Result<(), BlockError> {
+        Ok(())
+    }
+
+    /// Reset the device after a failed operation, so that the next one has a chance of working
+    ///
+    /// The default implementation does nothing.
+    ///
+    /// # Safety
+    ///
+    /// This function must be called with interrupts enabled.
+    unsafe fn reset(&mut self) {}
 }
 
 /// A block device
@@ -86,6 +145,8 @@ pub struct Block {
 
     /// The size of the block device in sectors
     block_size: BlockSector,
+    /// What the disk reported about itself, if this is a whole disk
+    identity: Option<DiskIdentity>,
 
     /// The read count
     read_count: AtomicU32,
@@ -129,6 +190,41 @@ impl Block {
         unsafe { self.driver.lock().read(sector, buf) }
     }
 
+    /// Reads `count` contiguous sectors starting at `start` into `buf`, which must have room for
+    /// exactly `count * BLOCK_SECTOR_SIZE` bytes.
+    ///
+    /// This is equivalent to calling [`Block::read`] on each sector in turn, but lets the driver
+    /// transfer all of them at once.
+    ///
+    /// Panics if interrupts are disabled.
+    pub fn read_sectors(
+        &self,
+        start: BlockSector,
+        count: BlockSector,
+        buf: &mut [u8],
+    ) -> Result<(), BlockError> {
+        assert_eq!(
+            intr_get_level(),
+            IntrLevel::IntrOn,
+            "Block::read_sectors must not be called with interrupts disabled."
+        );
+        if start
+            .checked_add(count)
+            .map_or(true, |end| end > self.block_size)
+        {
+            return Err(BlockError::SectorOutOfBounds);
+        }
+        if buf.len() != count as usize * BLOCK_SECTOR_SIZE {
+            return Err(BlockError::BufferInvalid);
+        }
+        if count == 0 {
+            return Ok(());
+        }
+
+        self.read_count.fetch_add(count, atomic::Ordering::Relaxed);
+        unsafe { self.driver.lock().read_sectors(start, count, buf) }
+    }
+
     /// Writes sector `sector` from `buf`, which must contain `BLOCK_SECTOR_SIZE` bytes. Returns
     /// after the block device has acknowledged receiving the data.
     ///
@@ -156,6 +252,23 @@ impl Block {
         unsafe { self.driver.lock().write(sector, buf) }
     }
 
+    /// Returns once every write made before the call is on the device's permanent storage, so
+    /// that it's ordered before every write made after. For example, a journal can write a record,
+    /// call this, and only then write the changes the record describes.
+    ///
+    /// Blocks don't cache writes themselves, so each write has already been handed to the driver
+    /// by the time it returns, and this only has to flush the device's own write cache.
+    ///
+    /// Panics if interrupts are disabled.
+    pub fn barrier(&self) -> Result<(), BlockError> {
+        assert_eq!(
+            intr_get_level(),
+            IntrLevel::IntrOn,
+            "Block::barrier must not be called with interrupts disabled."
+        );
+        unsafe { self.driver.lock().flush() }
+    }
+
     // Block getters -----------------------------------------------------------
 
     pub fn get_type(&self) -> BlockType {
@@ -170,6 +283,47 @@ impl Block {
     pub fn get_index(&self) -> usize {
         self.index
     }
+    pub fn get_identity(&self) -> Option<&DiskIdentity> {
+        self.identity.as_ref()
+    }
+
+    /// Get an owned handle to this block device, for things like file systems which take
+    /// ownership of the block they're on. Reads and writes go through to this block.
+    pub fn shared(self: &Arc<Self>) -> Block {
+        Block {
+            index: self.index,
+            block_name: self.block_name.clone(),
+            block_type: self.block_type,
+            driver: Mutex::new(Box::new(self.clone())),
+            block_size: self.block_size,
+            identity: self.identity.clone(),
+            read_count: AtomicU32::new(0),
+            write_count: AtomicU32::new(0),
+        }
+    }
+}
+
+impl BlockOp for Arc<Block> {
+    unsafe fn read(&mut self, sector: BlockSector, buf: &mut [u8]) -> Result<(), BlockError> {
+        Block::read(self, sector, buf)
+    }
+
+    unsafe fn read_sectors(
+        &mut self,
+        start: BlockSector,
+        count: BlockSector,
+        buf: &mut [u8],
+    ) -> Result<(), BlockError> {
+        Block::read_sectors(self, start, count, buf)
+    }
+
+    unsafe fn write(&mut self, sector: BlockSector, buf: &[u8]) -> Result<(), BlockError> {
+        Block::write(self, sector, buf)
+    }
+
+    unsafe fn flush(&mut self) -> Result<(), BlockError> {
+        Block::barrier(self)
+    }
 }
 
 impl fmt::Display for Block {
@@ -183,7 +337,18 @@ impl fmt::Display for Block {
             self.block_size,
             self.read_count.load(atomic::Ordering::Relaxed),
             self.write_count.load(atomic::Ordering::Relaxed)
-        )
+        )?;
+        if let Some(identity) = &self.identity {
+            write!(
+                f,
+                ", model \"{}\", serial \"{}\", {} addressable sectors{}",
+                identity.model,
+                identity.serial,
+                identity.sectors,
+                if identity.lba48 { " (LBA48)" } else { "" }
+            )?;
+        }
+        Ok(())
     }
 }
 
@@ -217,6 +382,29 @@ impl BlockManager {
         block_name: &str,
         block_size: BlockSector,
         driver: Box<dyn BlockOp + 'static + Send + Sync>,
+    ) -> usize {
+        self.register(block_type, block_name, block_size, driver, None)
+    }
+
+    /// Like [`BlockManager::register_block`], for a whole disk which has reported its `identity`.
+    pub fn register_disk(
+        &mut self,
+        block_type: BlockType,
+        block_name: &str,
+        block_size: BlockSector,
+        driver: Box<dyn BlockOp + 'static + Send + Sync>,
+        identity: DiskIdentity,
+    ) -> usize {
+        self.register(block_type, block_name, block_size, driver, Some(identity))
+    }
+
+    fn register(
+        &mut self,
+        block_type: BlockType,
+        block_name: &str,
+        block_size: BlockSector,
+        driver: Box<dyn BlockOp + 'static + Send + Sync>,
+        identity: Option<DiskIdentity>,
     ) -> usize {
         let blocks = &mut self.all_blocks;
         let index = blocks.len();
@@ -226,13 +414,17 @@ impl BlockManager {
             driver: Mutex::new(driver),
             index,
             block_size,
+            identity,
             read_count: AtomicU32::new(0),
             write_count: AtomicU32::new(0),
         }));
-        println!(
-            "Registered block device \"{}\" ({} type) with {} sectors",
-            blocks[index].block_name, block_type, block_size,
-        );
+        // there's no console to print to in tests
+        if cfg!(not(test)) {
+            println!(
+                "Registered block device \"{}\" ({} type) with {} sectors",
+                blocks[index].block_name, block_type, block_size,
+            );
+        }
 
         index
     }
@@ -298,8 +490,249 @@ pub mod test {
             block_size: (size / BLOCK_SECTOR_SIZE as u64)
                 .try_into()
                 .expect("file too large"),
+            identity: None,
             read_count: 0.into(),
             write_count: 0.into(),
         }
     }
+
+    /// In-memory block device backed by a gzip-compressed disk image, for testing.
+    ///
+    /// The image is only decompressed the first time a sector is read or written. Writes are
+    /// kept in memory, and never make it back to the compressed image.
+    pub struct GzBlockDevice {
+        compressed: Vec<u8>,
+        data: Option<Vec<u8>>,
+    }
+
+    impl GzBlockDevice {
+        pub fn new(compressed: Vec<u8>) -> Self {
+            Self {
+                compressed,
+                data: None,
+            }
+        }
+        /// Read a gzip-compressed image from the file at `path`.
+        pub fn open(path: &str) -> std::io::Result<Self> {
+            Ok(Self::new(std::fs::read(path)?))
+        }
+        /// Size of the decompressed image in bytes.
+        ///
+        /// This comes from the gzip trailer, so we don't have to decompress the image to find it.
+        /// The trailer only stores the size modulo 2^32, which is fine since images that large
+        /// don't fit in a `BlockSector` count anyways.
+        pub fn size(&self) -> u64 {
+            let trailer: [u8; 4] = self.compressed[self.compressed.len() - 4..]
+                .try_into()
+                .expect("gzip image too short");
+            u32::from_le_bytes(trailer).into()
+        }
+        fn data(&mut self) -> &mut Vec<u8> {
+            self.data.get_or_insert_with(|| {
+                let mut data = vec![];
+                flate2::read::GzDecoder::new(&self.compressed[..])
+                    .read_to_end(&mut data)
+                    .expect("bad gzip image");
+                data
+            })
+        }
+        fn sector_range(sector: BlockSector) -> core::ops::Range<usize> {
+            let start = sector as usize * BLOCK_SECTOR_SIZE;
+            start..start + BLOCK_SECTOR_SIZE
+        }
+        pub fn into_block(self) -> Block {
+            let block_size = (self.size() / BLOCK_SECTOR_SIZE as u64)
+                .try_into()
+                .expect("image too large");
+            Block {
+                index: 0,
+                block_name: "<test gzip image>".into(),
+                block_type: BlockType::FileSystem,
+                driver: Mutex::new(Box::new(self)),
+                block_size,
+                identity: None,
+                read_count: 0.into(),
+                write_count: 0.into(),
+            }
+        }
+    }
+
+    impl BlockOp for GzBlockDevice {
+        unsafe fn read(&mut self, sector: BlockSector, buf: &mut [u8]) -> Result<(), BlockError> {
+            let data = self.data();
+            let sector = data
+                .get(Self::sector_range(sector))
+                .ok_or(BlockError::SectorOutOfBounds)?;
+            buf.copy_from_slice(sector);
+            Ok(())
+        }
+        unsafe fn write(&mut self, sector: BlockSector, buf: &[u8]) -> Result<(), BlockError> {
+            let data = self.data();
+            let sector = data
+                .get_mut(Self::sector_range(sector))
+                .ok_or(BlockError::SectorOutOfBounds)?;
+            sector.copy_from_slice(buf);
+            Ok(())
+        }
+    }
+
+    /// Block device backed by a file on the host, like a loop device, for testing.
+    ///
+    /// Unlike `GzBlockDevice`, writes go straight to the file, so a filesystem image can be
+    /// written to, closed, and opened again to check that changes were persisted.
+    pub struct FileBlockDevice {
+        file: std::fs::File,
+        size: u64,
+    }
+
+    impl FileBlockDevice {
+        /// Open the file at `path` for reading and writing.
+        pub fn open(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+            let file = std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(path)?;
+            let size = file.metadata()?.len();
+            Ok(Self { file, size })
+        }
+        fn offset(sector: BlockSector) -> u64 {
+            u64::from(sector) * BLOCK_SECTOR_SIZE as u64
+        }
+        pub fn into_block(self) -> Block {
+            let block_size = (self.size / BLOCK_SECTOR_SIZE as u64)
+                .try_into()
+                .expect("file too large");
+            Block {
+                index: 0,
+                block_name: "<test file>".into(),
+                block_type: BlockType::FileSystem,
+                driver: Mutex::new(Box::new(self)),
+                block_size,
+                identity: None,
+                read_count: 0.into(),
+                write_count: 0.into(),
+            }
+        }
+    }
+
+    impl BlockOp for FileBlockDevice {
+        unsafe fn read(&mut self, sector: BlockSector, buf: &mut [u8]) -> Result<(), BlockError> {
+            use std::os::unix::fs::FileExt;
+            self.file
+                .read_exact_at(buf, Self::offset(sector))
+                .map_err(|_| BlockError::ReadError)
+        }
+        unsafe fn write(&mut self, sector: BlockSector, buf: &[u8]) -> Result<(), BlockError> {
+            use std::os::unix::fs::FileExt;
+            self.file
+                .write_all_at(buf, Self::offset(sector))
+                .map_err(|_| BlockError::WriteError)
+        }
+        unsafe fn flush(&mut self) -> Result<(), BlockError> {
+            self.file.sync_data().map_err(|_| BlockError::FlushError)
+        }
+    }
+
+    #[test]
+    fn read_sectors_matches_single_reads() {
+        let block = GzBlockDevice::open("tests/fat/simple_fat16.img.gz")
+            .unwrap()
+            .into_block();
+        let count = 70;
+        for start in [0, 1, 33, block.get_size() - count] {
+            let mut multi = vec![0; count as usize * BLOCK_SECTOR_SIZE];
+            block.read_sectors(start, count, &mut multi).unwrap();
+            let mut single = vec![0; count as usize * BLOCK_SECTOR_SIZE];
+            for (sector, buf) in (start..).zip(single.chunks_exact_mut(BLOCK_SECTOR_SIZE)) {
+                block.read(sector, buf).unwrap();
+            }
+            assert!(multi == single, "sectors {start}..{} differ", start + count);
+        }
+        let mut buf = vec![0; 2 * BLOCK_SECTOR_SIZE];
+        assert!(matches!(
+            block.read_sectors(block.get_size() - 1, 2, &mut buf),
+            Err(BlockError::SectorOutOfBounds)
+        ));
+        assert!(matches!(
+            block.read_sectors(0, 3, &mut buf),
+            Err(BlockError::BufferInvalid)
+        ));
+    }
+
+    #[test]
+    fn file_block_device_persists_writes() {
+        let path = std::env::temp_dir().join(format!(
+            "kidneyos-file-block-device-{}.img",
+            std::process::id()
+        ));
+        let mut image = vec![];
+        flate2::read::GzDecoder::new(std::fs::File::open("tests/fat/simple_fat16.img.gz").unwrap())
+            .read_to_end(&mut image)
+            .unwrap();
+        std::fs::write(&path, &image).unwrap();
+
+        let block = FileBlockDevice::open(&path).unwrap().into_block();
+        assert_eq!(block.get_size() as usize, image.len() / BLOCK_SECTOR_SIZE);
+        // Neither FAT nor VSFS can write files yet, so write to the device directly. The image
+        // is nearly empty, so its last sector isn't in use by the filesystem.
+        let last = block.get_size() - 1;
+        let pattern: Vec<u8> = (0..BLOCK_SECTOR_SIZE).map(|i| (i * 7) as u8).collect();
+        block.write(last, &pattern).unwrap();
+        drop(block);
+
+        let block = FileBlockDevice::open(&path).unwrap().into_block();
+        let mut buf = [0; BLOCK_SECTOR_SIZE];
+        block.read(last, &mut buf).unwrap();
+        assert_eq!(buf[..], pattern[..]);
+        block.read(0, &mut buf).unwrap();
+        assert_eq!(buf[..], image[..BLOCK_SECTOR_SIZE]);
+        // The filesystem should still mount from the modified image.
+        crate::fs::fat::FatFS::new(block).unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// Device with a volatile write cache, which records which sectors each flush made durable.
+    struct CachingDevice {
+        dirty: Vec<BlockSector>,
+        flushes: std::sync::Arc<std::sync::Mutex<Vec<Vec<BlockSector>>>>,
+    }
+
+    impl BlockOp for CachingDevice {
+        unsafe fn read(&mut self, _sector: BlockSector, _buf: &mut [u8]) -> Result<(), BlockError> {
+            Ok(())
+        }
+        unsafe fn write(&mut self, sector: BlockSector, _buf: &[u8]) -> Result<(), BlockError> {
+            self.dirty.push(sector);
+            Ok(())
+        }
+        unsafe fn flush(&mut self) -> Result<(), BlockError> {
+            self.flushes
+                .lock()
+                .unwrap()
+                .push(core::mem::take(&mut self.dirty));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn barrier_flushes_earlier_writes() {
+        let flushes = std::sync::Arc::default();
+        let mut manager = BlockManager::new();
+        let device = CachingDevice {
+            dirty: vec![],
+            flushes: std::sync::Arc::clone(&flushes),
+        };
+        let index = manager.register_block(BlockType::Raw, "cache", 8, Box::new(device));
+        let disk = manager.by_id(index).unwrap();
+        let block: &Block = &disk;
+
+        block.write(0, &[b'A'; BLOCK_SECTOR_SIZE]).unwrap();
+        block.barrier().unwrap();
+        assert_eq!(*flushes.lock().unwrap(), [vec![0]]);
+        block.write(1, &[b'B'; BLOCK_SECTOR_SIZE]).unwrap();
+        // Barriers on a handle reach the device too, e.g. for a file system on the block.
+        disk.shared().barrier().unwrap();
+        assert_eq!(*flushes.lock().unwrap(), [vec![0], vec![1]]);
+    }
 }
@@ -14,6 +14,8 @@ pub enum BlockError {
     ReadError,
     /// Error writing to the disk
     WriteError,
+    /// Error flushing the disk's write cache
+    FlushError,
 }
 
 impl Display for BlockError {
@@ -30,6 +32,7 @@ impl Error for BlockError {
             BlockError::BufferInvalid => "Invalid buffer size (not `BLOCK_SECTOR_SIZE`)",
             BlockError::ReadError => "Error reading from the block device",
             BlockError::WriteError => "Error writing to the block device",
+            BlockError::FlushError => "Error flushing the block device's write cache",
         }
     }
 }
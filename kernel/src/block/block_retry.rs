@@ -0,0 +1,178 @@
+use crate::block::block_core::{BlockOp, BlockSector};
+use crate::block::block_error::BlockError;
+#[cfg(not(test))]
+use crate::threading::thread_sleep::sleep_ticks;
+
+/// Number of times a failed read or write is retried by default
+pub const DEFAULT_RETRIES: usize = 3;
+
+/// How many timer ticks to wait before each retry, to give the device a moment to recover
+const RETRY_DELAY_TICKS: u64 = 1;
+
+// Tests don't have threads or a timer to sleep with.
+#[cfg(test)]
+fn sleep_ticks(_ticks: u64) {}
+
+/// A block device driver which retries failed reads, writes and flushes on `T`, resetting it
+/// before each retry, so that transient disk errors don't reach the block layer.
+///
+/// Only read, write and flush errors are retried. Errors in the request itself, like an out of
+/// bounds sector, are returned straight away.
+pub struct RetryBlockOp<T: BlockOp> {
+    inner: T,
+    /// How many times an operation is retried after the first attempt fails
+    retries: usize,
+}
+
+impl<T: BlockOp> RetryBlockOp<T> {
+    /// Wrap `inner`, retrying each failed operation up to `retries` times.
+    pub fn new(inner: T, retries: usize) -> Self {
+        Self { inner, retries }
+    }
+
+    /// Run `op` on the inner driver until it succeeds, fails with an error which isn't worth
+    /// retrying, or has been retried `self.retries` times.
+    ///
+    /// # Safety
+    ///
+    /// This function must be called with interrupts enabled.
+    unsafe fn retry(
+        &mut self,
+        mut op: impl FnMut(&mut T) -> Result<(), BlockError>,
+    ) -> Result<(), BlockError> {
+        let mut result = op(&mut self.inner);
+        for _ in 0..self.retries {
+            if !matches!(
+                result,
+                Err(BlockError::ReadError | BlockError::WriteError | BlockError::FlushError)
+            ) {
+                break;
+            }
+            sleep_ticks(RETRY_DELAY_TICKS);
+            self.inner.reset();
+            result = op(&mut self.inner);
+        }
+        result
+    }
+}
+
+impl<T: BlockOp> BlockOp for RetryBlockOp<T> {
+    unsafe fn read(&mut self, sector: BlockSector, buf: &mut [u8]) -> Result<(), BlockError> {
+        self.retry(|inner| inner.read(sector, buf))
+    }
+
+    unsafe fn read_sectors(
+        &mut self,
+        start: BlockSector,
+        count: BlockSector,
+        buf: &mut [u8],
+    ) -> Result<(), BlockError> {
+        self.retry(|inner| inner.read_sectors(start, count, buf))
+    }
+
+    unsafe fn write(&mut self, sector: BlockSector, buf: &[u8]) -> Result<(), BlockError> {
+        self.retry(|inner| inner.write(sector, buf))
+    }
+
+    unsafe fn flush(&mut self) -> Result<(), BlockError> {
+        self.retry(|inner| inner.flush())
+    }
+
+    unsafe fn reset(&mut self) {
+        self.inner.reset();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::block_core::BLOCK_SECTOR_SIZE;
+
+    /// Fails the first `failures` operations, then reads back whatever was last written.
+    struct FlakyDevice {
+        failures: usize,
+        attempts: usize,
+        resets: usize,
+        data: [u8; BLOCK_SECTOR_SIZE],
+    }
+
+    impl FlakyDevice {
+        fn new(failures: usize) -> Self {
+            Self {
+                failures,
+                attempts: 0,
+                resets: 0,
+                data: [0; BLOCK_SECTOR_SIZE],
+            }
+        }
+
+        fn attempt(&mut self, error: BlockError) -> Result<(), BlockError> {
+            self.attempts += 1;
+            if self.attempts <= self.failures {
+                Err(error)
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    impl BlockOp for FlakyDevice {
+        unsafe fn read(&mut self, sector: BlockSector, buf: &mut [u8]) -> Result<(), BlockError> {
+            if sector != 0 {
+                return Err(BlockError::SectorOutOfBounds);
+            }
+            self.attempt(BlockError::ReadError)?;
+            buf.copy_from_slice(&self.data);
+            Ok(())
+        }
+
+        unsafe fn write(&mut self, sector: BlockSector, buf: &[u8]) -> Result<(), BlockError> {
+            if sector != 0 {
+                return Err(BlockError::SectorOutOfBounds);
+            }
+            self.attempt(BlockError::WriteError)?;
+            self.data.copy_from_slice(buf);
+            Ok(())
+        }
+
+        unsafe fn reset(&mut self) {
+            self.resets += 1;
+        }
+    }
+
+    #[test]
+    fn transient_errors_are_hidden() {
+        let mut block = RetryBlockOp::new(FlakyDevice::new(1), DEFAULT_RETRIES);
+        let pattern = [0xA5; BLOCK_SECTOR_SIZE];
+        unsafe { block.write(0, &pattern) }.unwrap();
+        assert_eq!(block.inner.attempts, 2);
+        assert_eq!(block.inner.resets, 1);
+
+        let mut buf = [0; BLOCK_SECTOR_SIZE];
+        unsafe { block.read(0, &mut buf) }.unwrap();
+        assert_eq!(buf, pattern);
+        // the device works now, so nothing more was retried
+        assert_eq!(block.inner.attempts, 3);
+        assert_eq!(block.inner.resets, 1);
+    }
+
+    #[test]
+    fn gives_up_after_retries() {
+        let mut block = RetryBlockOp::new(FlakyDevice::new(10), 2);
+        let mut buf = [0; BLOCK_SECTOR_SIZE];
+        assert!(matches!(
+            unsafe { block.read(0, &mut buf) },
+            Err(BlockError::ReadError)
+        ));
+        assert_eq!(block.inner.attempts, 3);
+        assert_eq!(block.inner.resets, 2);
+
+        // not worth retrying
+        assert!(matches!(
+            unsafe { block.read(1, &mut buf) },
+            Err(BlockError::SectorOutOfBounds)
+        ));
+        assert_eq!(block.inner.attempts, 3);
+        assert_eq!(block.inner.resets, 2);
+    }
+}
@@ -1,3 +1,4 @@
 pub mod block_core;
 pub mod block_error;
+pub mod block_retry;
 pub mod partitions;
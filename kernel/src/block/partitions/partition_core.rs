@@ -402,6 +402,20 @@ impl BlockOp for Partition {
             .read(sector + self.start, buf)
     }
 
+    unsafe fn read_sectors(
+        &mut self,
+        start: BlockSector,
+        count: BlockSector,
+        buf: &mut [u8],
+    ) -> Result<(), BlockError> {
+        unwrap_system()
+            .block_manager
+            .read()
+            .by_id(self.block_idx)
+            .unwrap()
+            .read_sectors(start + self.start, count, buf)
+    }
+
     unsafe fn write(&mut self, sector: BlockSector, buf: &[u8]) -> Result<(), BlockError> {
         unwrap_system()
             .block_manager
@@ -410,6 +424,15 @@ impl BlockOp for Partition {
             .unwrap()
             .write(sector + self.start, buf)
     }
+
+    unsafe fn flush(&mut self) -> Result<(), BlockError> {
+        unwrap_system()
+            .block_manager
+            .read()
+            .by_id(self.block_idx)
+            .unwrap()
+            .barrier()
+    }
 }
 
 pub fn partition_type_name(ty: u8) -> &'static str {
@@ -0,0 +1,44 @@
+//! The kernel command line, as given to the bootloader, e.g. in `build-support/grub.cfg`:
+//!
+//! ```text
+//! multiboot2 /boot/kernel.bin selftest
+//! ```
+//!
+//! It's a list of options separated by spaces. Options the kernel doesn't know about are ignored,
+//! so they can be passed on to programs later.
+
+/// The options the kernel knows about.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct BootParams {
+    /// Run the self-tests instead of carrying on as usual, then exit QEMU with the result. See
+    /// [`crate::selftest`].
+    pub selftest: bool,
+}
+
+impl BootParams {
+    pub fn parse(cmdline: &str) -> Self {
+        let mut params = Self::default();
+        for option in cmdline.split_ascii_whitespace() {
+            if option == "selftest" {
+                params.selftest = true;
+            }
+        }
+        params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BootParams;
+
+    #[test]
+    fn parse() {
+        assert_eq!(BootParams::parse(""), BootParams::default());
+        assert_eq!(
+            BootParams::parse("  quiet selftest\tfoo=bar "),
+            BootParams { selftest: true }
+        );
+        // options have to match exactly
+        assert_eq!(BootParams::parse("selftests"), BootParams::default());
+    }
+}
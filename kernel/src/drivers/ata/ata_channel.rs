@@ -1,6 +1,7 @@
 #![allow(dead_code)] // Suppress unused warnings
 
 use crate::block::block_core::{BlockSector, BLOCK_SECTOR_SIZE};
+use crate::drivers::ata::ata_dma::{BusMaster, DmaChannel};
 use crate::sync::semaphore::Semaphore;
 use alloc::string::String;
 use kidneyos_shared::println;
@@ -137,13 +138,18 @@ pub struct AtaChannel {
     /// Up'd by interrupt handler
     completion_wait: Semaphore,
 
+    /// Bus master DMA registers and buffers, if the controller can do DMA
+    bus_master: Option<BusMaster>,
+
     /// The devices on this channel
     // Master
     d0_name: [char; 8],
     d0_is_ata: bool,
+    d0_dma: bool,
     // Slave
     d1_name: [char; 8],
     d1_is_ata: bool,
+    d1_dma: bool,
 
     channel_num: u8,
 }
@@ -335,6 +341,16 @@ impl AtaChannel {
     ///
     /// This function must be called with interrupts enabled.
     pub unsafe fn select_sector(&self, dev_no: u8, sector: BlockSector, block: bool) {
+        self.select_sectors(dev_no, sector, 1, block);
+    }
+
+    /// Like [`AtaChannel::select_sector`], but selects `count` sectors starting at `sector`, so
+    /// that the next command transfers all of them. A `count` of 0 means 256 sectors.
+    ///
+    /// # Safety
+    ///
+    /// This function must be called with interrupts enabled.
+    pub unsafe fn select_sectors(&self, dev_no: u8, sector: BlockSector, count: u8, block: bool) {
         self.select_device_wait(dev_no, block);
 
         // https://wiki.osdev.org/ATA_PIO_Mode#28_bit_PIO
@@ -349,7 +365,7 @@ impl AtaChannel {
         // time): outb(0x1F1, 0x00)
 
         // 3. Send the sectorcount to port 0x1F2: outb(0x1F2, (unsigned char) count)
-        outb(self.reg_nsect(), 1);
+        outb(self.reg_nsect(), count);
 
         // 4. Send the low 8 bits of the LBA to port 0x1F3: outb(0x1F3, (unsigned char) LBA))
         outb(self.reg_lbal(), sector as u8);
@@ -371,6 +387,26 @@ impl AtaChannel {
         outb(self.reg_command(), command);
     }
 
+    /// Like [`AtaChannel::issue_pio_command`], for a command which transfers data with DMA, so
+    /// raises only one interrupt, once the whole transfer is done.
+    ///
+    /// # Safety
+    ///
+    /// This function must be called with interrupts enabled.
+    pub unsafe fn issue_dma_command(&mut self, command: u8) {
+        self.expecting_interrupt = true;
+        outb(self.reg_command(), command);
+    }
+
+    /// Returns true if the last command failed, according to the status register.
+    ///
+    /// # Safety
+    ///
+    /// This function must be called with interrupts enabled.
+    pub unsafe fn has_error(&self) -> bool {
+        (inb(self.reg_alt_status()) & (STA_ERR | STA_DF)) != 0
+    }
+
     /// Reads a sector from the channel's data register in PIO mode into `buf`, which must have
     /// room for BLOCK_SECTOR_SIZE bytes.
     ///
@@ -502,10 +538,13 @@ impl AtaChannel {
             irq,
             expecting_interrupt: false,
             completion_wait: Semaphore::new(0),
+            bus_master: None,
             d0_name,
             d0_is_ata: false,
+            d0_dma: false,
             d1_name,
             d1_is_ata: false,
+            d1_dma: false,
             channel_num,
         }
     }
@@ -548,6 +587,50 @@ impl AtaChannel {
         }
     }
 
+    /// Use bus master DMA for the disks on this channel which support it.
+    pub fn set_bus_master(&mut self, bus_master: BusMaster) {
+        self.bus_master = Some(bus_master);
+    }
+
+    /// Sets whether the `dev_no` disk supports DMA, according to IDENTIFY DEVICE.
+    pub fn set_supports_dma(&mut self, dev_no: u8, dma: bool) {
+        if dev_no == 0 {
+            self.d0_dma = dma;
+        } else {
+            self.d1_dma = dma;
+        }
+    }
+
+    /// Returns true if transfers to and from the `dev_no` disk use DMA rather than PIO, which is
+    /// when both the controller and the disk support it.
+    pub fn uses_dma(&self, dev_no: u8) -> bool {
+        self.bus_master.is_some()
+            && if dev_no == 0 {
+                self.d0_dma
+            } else {
+                self.d1_dma
+            }
+    }
+
+    /// Describe the first `sectors` sectors of [`AtaChannel::dma_buffer`] for the next DMA
+    /// transfer, returning the physical address of the PRD table to give [`ata_dma::transfer`].
+    ///
+    /// [`ata_dma::transfer`]: crate::drivers::ata::ata_dma::transfer
+    pub fn prepare_dma(&mut self, sectors: usize) -> u32 {
+        self.bus_master
+            .as_mut()
+            .expect("channel can't do DMA")
+            .prepare(sectors)
+    }
+
+    /// The buffer DMA transfers go through.
+    pub fn dma_buffer(&mut self) -> &mut [u8] {
+        self.bus_master
+            .as_mut()
+            .expect("channel can't do DMA")
+            .buffer()
+    }
+
     pub fn get_channel_num(&self) -> u8 {
         self.channel_num
     }
@@ -587,3 +670,34 @@ impl AtaChannel {
             .is_some()
     }
 }
+
+impl DmaChannel for AtaChannel {
+    unsafe fn read_bus_master(&mut self, reg: u16) -> u8 {
+        self.bus_master
+            .as_ref()
+            .expect("channel can't do DMA")
+            .read(reg)
+    }
+
+    unsafe fn write_bus_master(&mut self, reg: u16, value: u8) {
+        self.bus_master
+            .as_ref()
+            .expect("channel can't do DMA")
+            .write(reg, value);
+    }
+
+    unsafe fn set_prd_table(&mut self, phys_addr: u32) {
+        self.bus_master
+            .as_ref()
+            .expect("channel can't do DMA")
+            .write_prd_table(phys_addr);
+    }
+
+    unsafe fn issue_dma_command(&mut self, command: u8) {
+        AtaChannel::issue_dma_command(self, command);
+    }
+
+    fn wait_for_interrupt(&mut self) {
+        self.sem_down();
+    }
+}
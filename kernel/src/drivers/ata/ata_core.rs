@@ -5,9 +5,12 @@
 #![allow(dead_code)]
 
 use crate::block::block_core::{BlockSector, BlockType, BLOCK_SECTOR_SIZE};
+use crate::block::block_retry::{RetryBlockOp, DEFAULT_RETRIES};
 use crate::block::partitions::partition_core::partition_scan;
 use crate::drivers::ata::ata_channel::AtaChannel;
 use crate::drivers::ata::ata_device::AtaDevice;
+use crate::drivers::ata::ata_dma::{self, BusMaster};
+use crate::drivers::ata::ata_identify::parse_identify;
 use crate::interrupts::{intr_get_level, IntrLevel};
 use crate::sync::mutex::sleep::SleepMutex;
 use crate::system::unwrap_system;
@@ -25,12 +28,26 @@ pub const ATA_READ_SECTOR_RETRY: u8 = 0x20;
 pub const ATA_WRITE_SECTOR_RETRY: u8 = 0x30;
 /// IDENTIFY DEVICE             PIO     8-bit
 pub const ATA_IDENTIFY_DEVICE: u8 = 0xEC;
+/// READ DMA (with retries)     DMA     28-bit
+pub const ATA_READ_DMA: u8 = 0xC8;
+/// WRITE DMA (with retries)    DMA     28-bit
+pub const ATA_WRITE_DMA: u8 = 0xCA;
+/// FLUSH CACHE                 NoData
+pub const ATA_FLUSH_CACHE: u8 = 0xE7;
+
+// IDENTIFY DEVICE data ----------------------------------------------------------------------------
+
+/// Word 49 (capabilities) bit saying the device supports DMA
+const ID_CAPABILITIES_DMA: u16 = 1 << 8;
 
 // Constants ---------------------------------------------------------------------------------------
 
 /// Number of ATA channels
 const CHANNEL_CNT: usize = 2;
 
+/// Number of sectors reachable with 28-bit commands, which are the only ones the driver issues
+const LBA28_SECTORS: u64 = 1 << 28;
+
 lazy_static! {
     pub static ref CHANNELS: [SleepMutex<AtaChannel>; CHANNEL_CNT] = [
         SleepMutex::new(AtaChannel::new(0)),
@@ -56,12 +73,21 @@ pub extern "C" fn ide_init() -> i32 {
 
     let mut present: [[bool; 2]; 2] = [[false; 2]; 2];
 
+    // SAFETY: Nothing else uses PCI configuration space.
+    let bus_master_base = unsafe { ata_dma::find_bus_master() };
+    if bus_master_base.is_none() {
+        println!("IDE: controller can't do DMA, using PIO");
+    }
+
     for (i, c) in CHANNELS.iter().enumerate() {
         let channel = &mut c.lock();
 
         // Initialize the channel
         channel.set_names();
         unsafe { channel.reset(true) };
+        if let Some(bus_master) = bus_master_base.and_then(|base| BusMaster::new(base, i as u8)) {
+            channel.set_bus_master(bus_master);
+        }
 
         // Initialize the devices
         if unsafe { channel.check_device_type(0, true) } {
@@ -111,8 +137,11 @@ unsafe fn identify_ata_device(c: &SleepMutex<AtaChannel>, dev_no: u8, block: boo
     }
     channel.read_sector(&mut id);
 
-    // Calculate capacity.
-    let capacity = usize::from_le_bytes(id[120..124].try_into().unwrap());
+    let capabilities = u16::from_le_bytes(id[98..100].try_into().unwrap());
+    channel.set_supports_dma(dev_no, capabilities & ID_CAPABILITIES_DMA != 0);
+
+    let identity = parse_identify(&id);
+    let capacity = identity.sectors.min(LBA28_SECTORS);
     let name = if dev_no == 0 {
         channel.get_d0_name()
     } else {
@@ -120,20 +149,27 @@ unsafe fn identify_ata_device(c: &SleepMutex<AtaChannel>, dev_no: u8, block: boo
     };
     let name: String = name.iter().collect();
     println!(
-        "channel: {} device: {} name: {} capacity: {}M",
+        "channel: {} device: {} name: {} model: {} capacity: {}M mode: {}",
         channel.get_channel_num(),
         dev_no,
         &name,
-        capacity >> 11
+        &identity.model,
+        capacity >> 11,
+        if channel.uses_dma(dev_no) {
+            "DMA"
+        } else {
+            "PIO"
+        }
     );
 
     let block_manager = &unwrap_system().block_manager;
 
-    let idx = block_manager.write().register_block(
+    let idx = block_manager.write().register_disk(
         BlockType::Raw,
         &name,
         capacity as BlockSector,
-        Box::new(AtaDevice(dev_no)),
+        Box::new(RetryBlockOp::new(AtaDevice(dev_no), DEFAULT_RETRIES)),
+        identity,
     );
 
     // partition_scan(block_manager.read().by_id(idx).unwrap().as_ref());
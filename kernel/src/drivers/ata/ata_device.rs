@@ -1,9 +1,13 @@
 use crate::block::block_core::{BlockOp, BlockSector, BLOCK_SECTOR_SIZE};
 use crate::block::block_error::BlockError;
 use crate::drivers::ata::ata_channel::AtaChannel;
-use crate::drivers::ata::ata_core::CHANNELS;
+use crate::drivers::ata::ata_core::{ATA_FLUSH_CACHE, ATA_READ_DMA, ATA_WRITE_DMA, CHANNELS};
+use crate::drivers::ata::ata_dma::{self, MAX_DMA_SECTORS};
 use crate::drivers::ata::ata_timer::usleep;
 
+/// Maximum number of sectors a single READ SECTORS command can transfer
+const MAX_SECTORS_PER_COMMAND: usize = 256;
+
 #[derive(Copy, Clone, PartialEq)]
 pub struct AtaDevice(pub u8);
 
@@ -22,6 +26,64 @@ impl AtaDevice {
         // Last bit
         self.0 & 0x1
     }
+
+    /// Reads the sectors starting at `start` into `buf` with DMA, issuing one READ DMA command for
+    /// every (up to) MAX_DMA_SECTORS sectors.
+    ///
+    /// # Safety
+    ///
+    /// This function must be called with interrupts enabled, and the channel must use DMA for
+    /// this disk.
+    unsafe fn dma_read(
+        &self,
+        channel: &mut AtaChannel,
+        start: BlockSector,
+        buf: &mut [u8],
+    ) -> Result<(), BlockError> {
+        for (first, chunk) in (start..)
+            .step_by(MAX_DMA_SECTORS)
+            .zip(buf.chunks_mut(MAX_DMA_SECTORS * BLOCK_SECTOR_SIZE))
+        {
+            let chunk_count = chunk.len() / BLOCK_SECTOR_SIZE;
+            let prd_table = channel.prepare_dma(chunk_count);
+            channel.select_sectors(self.get_device_num(), first, chunk_count as u8, true);
+            if !ata_dma::transfer(channel, prd_table, ATA_READ_DMA, true) || channel.has_error() {
+                return Err(BlockError::ReadError);
+            }
+            chunk.copy_from_slice(&channel.dma_buffer()[..chunk.len()]);
+        }
+
+        Ok(())
+    }
+
+    /// Writes `buf` to the sectors starting at `start` with DMA, issuing one WRITE DMA command for
+    /// every (up to) MAX_DMA_SECTORS sectors.
+    ///
+    /// # Safety
+    ///
+    /// This function must be called with interrupts enabled, and the channel must use DMA for
+    /// this disk.
+    unsafe fn dma_write(
+        &self,
+        channel: &mut AtaChannel,
+        start: BlockSector,
+        buf: &[u8],
+    ) -> Result<(), BlockError> {
+        for (first, chunk) in (start..)
+            .step_by(MAX_DMA_SECTORS)
+            .zip(buf.chunks(MAX_DMA_SECTORS * BLOCK_SECTOR_SIZE))
+        {
+            let chunk_count = chunk.len() / BLOCK_SECTOR_SIZE;
+            channel.dma_buffer()[..chunk.len()].copy_from_slice(chunk);
+            let prd_table = channel.prepare_dma(chunk_count);
+            channel.select_sectors(self.get_device_num(), first, chunk_count as u8, true);
+            if !ata_dma::transfer(channel, prd_table, ATA_WRITE_DMA, false) || channel.has_error() {
+                return Err(BlockError::WriteError);
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl BlockOp for AtaDevice {
@@ -37,6 +99,10 @@ impl BlockOp for AtaDevice {
 
         let channel: &mut AtaChannel = &mut CHANNELS[self.get_channel() as usize].lock();
 
+        if channel.uses_dma(self.get_device_num()) {
+            return self.dma_read(channel, sector, buf);
+        }
+
         channel.select_sector(self.get_device_num(), sector, true);
         channel.issue_pio_command(crate::drivers::ata::ata_core::ATA_READ_SECTOR_RETRY);
 
@@ -53,6 +119,52 @@ impl BlockOp for AtaDevice {
         Ok(())
     }
 
+    /// Reads `count` sectors starting at `start` into `buf`, issuing one READ SECTORS command for
+    /// every (up to) 256 sectors instead of one per sector, or one READ DMA command for every (up
+    /// to) MAX_DMA_SECTORS sectors if the disk uses DMA.
+    ///
+    /// # Safety
+    ///
+    /// This function must be called with interrupts enabled
+    unsafe fn read_sectors(
+        &mut self,
+        start: BlockSector,
+        count: BlockSector,
+        buf: &mut [u8],
+    ) -> Result<(), BlockError> {
+        assert_eq!(buf.len(), count as usize * BLOCK_SECTOR_SIZE); // Checked by block layer
+
+        let channel: &mut AtaChannel = &mut CHANNELS[self.get_channel() as usize].lock();
+
+        if channel.uses_dma(self.get_device_num()) {
+            return self.dma_read(channel, start, buf);
+        }
+
+        for (first, chunk) in (start..)
+            .step_by(MAX_SECTORS_PER_COMMAND)
+            .zip(buf.chunks_mut(MAX_SECTORS_PER_COMMAND * BLOCK_SECTOR_SIZE))
+        {
+            // A sector count of 0 means 256 sectors, so this truncation is fine.
+            let chunk_count = (chunk.len() / BLOCK_SECTOR_SIZE) as u8;
+            channel.select_sectors(self.get_device_num(), first, chunk_count, true);
+            channel.issue_pio_command(crate::drivers::ata::ata_core::ATA_READ_SECTOR_RETRY);
+
+            // TODO: find a better way to resolve race condition
+            usleep(1000, true);
+
+            // The disk raises an interrupt each time another sector is ready to be read.
+            for sector_buf in chunk.chunks_exact_mut(BLOCK_SECTOR_SIZE) {
+                channel.sem_down();
+                if !channel.wait_while_busy(true) {
+                    return Err(BlockError::ReadError);
+                }
+                channel.read_sector(sector_buf);
+            }
+        }
+
+        Ok(())
+    }
+
     /// Write sector `sector` to the disk from `buf`, which must contain BLOCK_SECTOR_SIZE bytes.
     ///
     /// Returns after the disk has acknowledged receiving the data.
@@ -67,6 +179,10 @@ impl BlockOp for AtaDevice {
 
         let channel: &mut AtaChannel = &mut CHANNELS[self.get_channel() as usize].lock();
 
+        if channel.uses_dma(self.get_device_num()) {
+            return self.dma_write(channel, sector, buf);
+        }
+
         channel.select_sector(self.get_device_num(), sector, true);
         channel.issue_pio_command(crate::drivers::ata::ata_core::ATA_WRITE_SECTOR_RETRY);
 
@@ -79,4 +195,33 @@ impl BlockOp for AtaDevice {
 
         Ok(())
     }
+
+    /// Flushes the disk's write cache, returning once the disk says everything written to it is
+    /// on the platters.
+    ///
+    /// # Safety
+    ///
+    /// This function must be called with interrupts enabled
+    unsafe fn flush(&mut self) -> Result<(), BlockError> {
+        let channel: &mut AtaChannel = &mut CHANNELS[self.get_channel() as usize].lock();
+
+        channel.select_device_wait(self.get_device_num(), true);
+        channel.issue_pio_command(ATA_FLUSH_CACHE);
+        // FLUSH CACHE doesn't transfer any data, so the disk just interrupts once it's done.
+        channel.sem_down();
+        if channel.has_error() {
+            return Err(BlockError::FlushError);
+        }
+
+        Ok(())
+    }
+
+    /// Soft resets the disk's channel, e.g. after a command failed.
+    ///
+    /// # Safety
+    ///
+    /// This function must be called with interrupts enabled
+    unsafe fn reset(&mut self) {
+        CHANNELS[self.get_channel() as usize].lock().reset(true);
+    }
 }
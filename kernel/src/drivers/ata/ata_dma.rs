@@ -0,0 +1,413 @@
+// Bus master IDE DMA, as done by the PIIX and compatible controllers.
+// Reference: https://wiki.osdev.org/ATA/ATAPI_using_DMA
+// Reference: Programming Interface for Bus Master IDE Controller, Revision 1.0
+
+use crate::block::block_core::BLOCK_SECTOR_SIZE;
+use crate::drivers::pci;
+use crate::mem::dma::{dma_alloc, dma_free, DmaBuffer};
+use core::mem::size_of;
+use kidneyos_shared::mem::PAGE_FRAME_SIZE;
+use kidneyos_shared::serial::{inb, outb, outl};
+
+// Bus master registers, as offsets from the channel's bus master base ------------------------------
+
+/// R/W Command Register
+const BM_COMMAND: u16 = 0;
+/// R/W Status Register
+const BM_STATUS: u16 = 2;
+/// R/W PRD Table Address Register (32 bits)
+const BM_PRDT: u16 = 4;
+
+/// Offset of the secondary channel's bus master registers from the primary's.
+const BM_SECONDARY_OFFSET: u16 = 8;
+
+// Command Register bits ---------------------------------------------------------------------------
+
+/// 0   Start/Stop Bus Master
+const BM_CMD_START: u8 = 0x01;
+/// 3   Read or Write Control
+///
+/// Set when the controller writes to memory, i.e. the disk is being read.
+const BM_CMD_TO_MEMORY: u8 = 0x08;
+
+// Status Register bits ----------------------------------------------------------------------------
+
+/// 0   Bus Master IDE Active
+///
+/// Cleared when the last PRD has been transferred.
+const BM_STA_ACTIVE: u8 = 0x01;
+/// 1   Error, cleared by writing 1 to it
+const BM_STA_ERR: u8 = 0x02;
+/// 2   Interrupt, cleared by writing 1 to it
+///
+/// Set when the disk raises its interrupt, once the transfer is done.
+const BM_STA_IRQ: u8 = 0x04;
+
+// -------------------------------------------------------------------------------------------------
+
+/// PCI class and subclass of IDE controllers.
+const PCI_CLASS_STORAGE: u8 = 0x01;
+const PCI_SUBCLASS_IDE: u8 = 0x01;
+/// Programming interface bit saying the controller can do bus master DMA.
+const PCI_PROG_IF_BUS_MASTER: u8 = 0x80;
+/// BAR bit saying the registers are in I/O space rather than memory.
+const BAR_IO_SPACE: u32 = 0x1;
+
+/// A region of memory can't cross a 64 KiB boundary, and a byte count of 0 means 64 KiB.
+const PRD_BOUNDARY: usize = 0x10000;
+/// Set on the last entry of the PRD table.
+const PRD_END_OF_TABLE: u16 = 0x8000;
+
+/// Most sectors transferred by one command, which is the size of each channel's buffer.
+pub const MAX_DMA_SECTORS: usize = 128;
+
+/// A Physical Region Descriptor, giving one physically contiguous region of memory to transfer.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PrdEntry {
+    phys_addr: u32,
+    /// 0 means 64 KiB
+    byte_count: u16,
+    flags: u16,
+}
+
+/// Describe the `len` bytes of physical memory starting at `phys_addr` in `table`, splitting it so
+/// that no entry crosses a 64 KiB boundary, and returning how many entries were used.
+///
+/// Returns `None` if `table` is too short, or the memory isn't addressable by the controller.
+pub fn fill_prd_table(table: &mut [PrdEntry], phys_addr: usize, len: usize) -> Option<usize> {
+    assert!(len > 0 && len % 2 == 0, "can only transfer whole words");
+    let end = phys_addr.checked_add(len)?;
+    let mut used = 0;
+    let mut addr = phys_addr;
+    while addr < end {
+        let region_end = end.min((addr / PRD_BOUNDARY + 1) * PRD_BOUNDARY);
+        *table.get_mut(used)? = PrdEntry {
+            phys_addr: u32::try_from(addr).ok()?,
+            // 64 KiB wraps around to 0, as it should
+            byte_count: (region_end - addr) as u16,
+            flags: 0,
+        };
+        used += 1;
+        addr = region_end;
+    }
+    table[used - 1].flags = PRD_END_OF_TABLE;
+    Some(used)
+}
+
+/// A channel which can do bus master DMA, which [`transfer`] drives.
+pub trait DmaChannel {
+    /// Read the bus master register at `reg`.
+    ///
+    /// # Safety
+    ///
+    /// Must not be used during a transfer, other than by [`transfer`].
+    unsafe fn read_bus_master(&mut self, reg: u16) -> u8;
+
+    /// Write `value` to the bus master register at `reg`.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`Self::read_bus_master`].
+    unsafe fn write_bus_master(&mut self, reg: u16, value: u8);
+
+    /// Point the controller at the PRD table at `phys_addr`.
+    ///
+    /// # Safety
+    ///
+    /// The table must stay valid until the transfer is done.
+    unsafe fn set_prd_table(&mut self, phys_addr: u32);
+
+    /// Send the READ DMA or WRITE DMA command `command` to the disk, whose sectors have already
+    /// been selected, expecting an interrupt once it's done.
+    ///
+    /// # Safety
+    ///
+    /// Must only be used by [`transfer`].
+    unsafe fn issue_dma_command(&mut self, command: u8);
+
+    /// Wait for the disk's interrupt, which comes once the whole transfer is done.
+    fn wait_for_interrupt(&mut self);
+}
+
+/// Run the transfer described by the PRD table at `prd_table`, with the disk command `command`,
+/// which reads from the disk if `to_memory` is set. The disk's sectors must already be selected.
+///
+/// Rather than polling the controller, this sleeps until the disk's interrupt comes in.
+///
+/// Returns `false` if the controller reported an error.
+///
+/// # Safety
+///
+/// The memory described by the table must be valid to read, or to write if `to_memory` is set,
+/// and not be used by anything else until this returns.
+pub unsafe fn transfer(
+    channel: &mut impl DmaChannel,
+    prd_table: u32,
+    command: u8,
+    to_memory: bool,
+) -> bool {
+    let direction = if to_memory { BM_CMD_TO_MEMORY } else { 0 };
+
+    // Stop anything which was going on, and set up the transfer.
+    channel.write_bus_master(BM_COMMAND, 0);
+    channel.set_prd_table(prd_table);
+    channel.write_bus_master(BM_COMMAND, direction);
+    let status = channel.read_bus_master(BM_STATUS);
+    channel.write_bus_master(BM_STATUS, status | BM_STA_ERR | BM_STA_IRQ);
+
+    channel.issue_dma_command(command);
+    channel.write_bus_master(BM_COMMAND, direction | BM_CMD_START);
+    channel.wait_for_interrupt();
+
+    let status = channel.read_bus_master(BM_STATUS);
+    channel.write_bus_master(BM_COMMAND, direction);
+    channel.write_bus_master(BM_STATUS, status | BM_STA_ERR | BM_STA_IRQ);
+
+    // ACTIVE stays set if the disk transferred less than the table described.
+    status & (BM_STA_ERR | BM_STA_ACTIVE) == 0
+}
+
+/// Find the IDE controller on the PCI bus, and the base port of its bus master registers, if it
+/// can do DMA.
+///
+/// # Safety
+///
+/// Nothing else may be accessing PCI configuration space at the same time.
+pub unsafe fn find_bus_master() -> Option<u16> {
+    let controller = pci::find(PCI_CLASS_STORAGE, PCI_SUBCLASS_IDE)?;
+    let (_, _, prog_if) = controller.class();
+    let bar = controller.bar(4);
+    if prog_if & PCI_PROG_IF_BUS_MASTER == 0 || bar & BAR_IO_SPACE == 0 {
+        return None;
+    }
+    let base = u16::try_from(bar & !0b11).ok().filter(|&base| base != 0)?;
+    controller.enable_bus_mastering();
+    Some(base)
+}
+
+/// The bus master registers of one channel, and the memory used for its transfers.
+pub struct BusMaster {
+    /// Base port of the channel's bus master registers
+    base: u16,
+    /// Holds the PRD table, which mustn't cross a 64 KiB boundary, so fits in one frame
+    prd_table: DmaBuffer,
+    /// What's read from or written to the disk goes through here, since it has to be physically
+    /// contiguous
+    buffer: DmaBuffer,
+}
+
+impl BusMaster {
+    /// Set up channel `channel_num`, given the base port from [`find_bus_master`].
+    ///
+    /// Returns `None` if there isn't enough memory.
+    pub fn new(controller_base: u16, channel_num: u8) -> Option<Self> {
+        let prd_table = dma_alloc(PAGE_FRAME_SIZE).ok()?;
+        let buffer = match dma_alloc(MAX_DMA_SECTORS * BLOCK_SECTOR_SIZE) {
+            Ok(buffer) => buffer,
+            Err(_) => {
+                // SAFETY: The controller hasn't been pointed at it yet.
+                unsafe { dma_free(prd_table) };
+                return None;
+            }
+        };
+        Some(Self {
+            base: controller_base + u16::from(channel_num) * BM_SECONDARY_OFFSET,
+            prd_table,
+            buffer,
+        })
+    }
+
+    /// Write the PRD table describing the first `sectors` sectors of the buffer, returning its
+    /// physical address.
+    pub fn prepare(&mut self, sectors: usize) -> u32 {
+        assert!(sectors <= MAX_DMA_SECTORS);
+        // SAFETY: the frame is only used for the table, and is aligned enough for it.
+        let table = unsafe {
+            core::slice::from_raw_parts_mut(
+                self.prd_table.virt().as_ptr().cast::<PrdEntry>(),
+                PAGE_FRAME_SIZE / size_of::<PrdEntry>(),
+            )
+        };
+        fill_prd_table(table, self.buffer.phys(), sectors * BLOCK_SECTOR_SIZE)
+            .expect("DMA buffer couldn't be described");
+        self.prd_table.phys() as u32
+    }
+
+    /// The buffer which transfers go through.
+    pub fn buffer(&mut self) -> &mut [u8] {
+        self.buffer.as_mut_slice()
+    }
+
+    /// Read the bus master register at `reg`.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`DmaChannel::read_bus_master`].
+    pub unsafe fn read(&self, reg: u16) -> u8 {
+        inb(self.base + reg)
+    }
+
+    /// Write `value` to the bus master register at `reg`.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`DmaChannel::write_bus_master`].
+    pub unsafe fn write(&self, reg: u16, value: u8) {
+        outb(self.base + reg, value);
+    }
+
+    /// Point the controller at the PRD table at `phys_addr`.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`DmaChannel::set_prd_table`].
+    pub unsafe fn write_prd_table(&self, phys_addr: u32) {
+        outl(self.base + BM_PRDT, phys_addr);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn prd_table_layout() {
+        assert_eq!(size_of::<PrdEntry>(), 8);
+
+        let mut table = [PrdEntry::default(); 4];
+        assert_eq!(fill_prd_table(&mut table, 0x12_3400, 0x1000), Some(1));
+        assert_eq!(
+            table[0],
+            PrdEntry {
+                phys_addr: 0x12_3400,
+                byte_count: 0x1000,
+                flags: PRD_END_OF_TABLE,
+            }
+        );
+        // as the controller sees it
+        let bytes: [u8; 8] = unsafe { core::mem::transmute(table[0]) };
+        assert_eq!(bytes, [0x00, 0x34, 0x12, 0x00, 0x00, 0x10, 0x00, 0x80]);
+
+        // split at 64 KiB boundaries, with 64 KiB written as 0
+        let mut table = [PrdEntry::default(); 4];
+        assert_eq!(fill_prd_table(&mut table, 0x1_F000, 0x1_2000), Some(3));
+        assert_eq!(
+            table[..3],
+            [
+                PrdEntry {
+                    phys_addr: 0x1_F000,
+                    byte_count: 0x1000,
+                    flags: 0,
+                },
+                PrdEntry {
+                    phys_addr: 0x2_0000,
+                    byte_count: 0,
+                    flags: 0,
+                },
+                PrdEntry {
+                    phys_addr: 0x3_0000,
+                    byte_count: 0x1000,
+                    flags: PRD_END_OF_TABLE,
+                },
+            ]
+        );
+
+        // not enough room
+        assert_eq!(fill_prd_table(&mut table[..2], 0x1_F000, 0x1_2000), None);
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum Event {
+        Read(u16),
+        Write(u16, u8),
+        PrdTable(u32),
+        Command(u8),
+        Interrupt,
+    }
+
+    /// Pretends to be a channel, finishing the transfer (possibly with an error) when it's
+    /// started, and raising the interrupt once it's waited for.
+    struct SimulatedChannel {
+        events: Vec<Event>,
+        status: u8,
+        fail: bool,
+    }
+
+    impl DmaChannel for SimulatedChannel {
+        unsafe fn read_bus_master(&mut self, reg: u16) -> u8 {
+            self.events.push(Event::Read(reg));
+            assert_eq!(reg, BM_STATUS);
+            self.status
+        }
+
+        unsafe fn write_bus_master(&mut self, reg: u16, value: u8) {
+            self.events.push(Event::Write(reg, value));
+            match reg {
+                BM_COMMAND if value & BM_CMD_START != 0 => self.status |= BM_STA_ACTIVE,
+                BM_STATUS => self.status &= !(value & (BM_STA_ERR | BM_STA_IRQ)),
+                _ => {}
+            }
+        }
+
+        unsafe fn set_prd_table(&mut self, phys_addr: u32) {
+            self.events.push(Event::PrdTable(phys_addr));
+        }
+
+        unsafe fn issue_dma_command(&mut self, command: u8) {
+            self.events.push(Event::Command(command));
+        }
+
+        fn wait_for_interrupt(&mut self) {
+            self.events.push(Event::Interrupt);
+            assert!(self.status & BM_STA_ACTIVE != 0, "waited before starting");
+            self.status &= !BM_STA_ACTIVE;
+            self.status |= BM_STA_IRQ;
+            if self.fail {
+                self.status |= BM_STA_ERR;
+            }
+        }
+    }
+
+    #[test]
+    fn completion_by_interrupt() {
+        const READ_DMA: u8 = 0xC8;
+
+        let mut channel = SimulatedChannel {
+            events: Vec::new(),
+            // left over from a previous transfer
+            status: BM_STA_IRQ,
+            fail: false,
+        };
+        assert!(unsafe { transfer(&mut channel, 0x1000, READ_DMA, true) });
+        assert_eq!(
+            channel.events,
+            [
+                Event::Write(BM_COMMAND, 0),
+                Event::PrdTable(0x1000),
+                Event::Write(BM_COMMAND, BM_CMD_TO_MEMORY),
+                Event::Read(BM_STATUS),
+                Event::Write(BM_STATUS, BM_STA_ERR | BM_STA_IRQ),
+                Event::Command(READ_DMA),
+                Event::Write(BM_COMMAND, BM_CMD_TO_MEMORY | BM_CMD_START),
+                // nothing is polled while the transfer runs
+                Event::Interrupt,
+                Event::Read(BM_STATUS),
+                Event::Write(BM_COMMAND, BM_CMD_TO_MEMORY),
+                Event::Write(BM_STATUS, BM_STA_ERR | BM_STA_IRQ),
+            ]
+        );
+        assert_eq!(channel.status, 0);
+
+        let mut channel = SimulatedChannel {
+            events: Vec::new(),
+            status: 0,
+            fail: true,
+        };
+        assert!(!unsafe { transfer(&mut channel, 0x1000, 0xCA, false) });
+        assert!(channel
+            .events
+            .contains(&Event::Write(BM_COMMAND, BM_CMD_START)));
+        assert_eq!(channel.status, 0);
+    }
+}
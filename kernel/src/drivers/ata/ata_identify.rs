@@ -0,0 +1,103 @@
+// Parsing the data returned by IDENTIFY DEVICE.
+// Reference: https://wiki.osdev.org/ATA_PIO_Mode#IDENTIFY_command
+// Reference: ATA/ATAPI-6, section 8.15 IDENTIFY DEVICE
+
+use crate::block::block_core::{DiskIdentity, BLOCK_SECTOR_SIZE};
+use alloc::string::String;
+
+// Words of the IDENTIFY DEVICE data ---------------------------------------------------------------
+
+/// Words 10-19: Serial number (20 ASCII characters)
+const ID_SERIAL: core::ops::Range<usize> = 10..20;
+/// Words 27-46: Model number (40 ASCII characters)
+const ID_MODEL: core::ops::Range<usize> = 27..47;
+/// Words 60-61: Total number of user addressable sectors with 28-bit commands
+const ID_LBA28_SECTORS: usize = 60;
+/// Word 83: Command sets supported
+const ID_COMMAND_SETS: usize = 83;
+/// Words 100-103: Total number of user addressable sectors with 48-bit commands
+const ID_LBA48_SECTORS: usize = 100;
+
+/// Word 83 bit saying the 48-bit Address feature set is supported
+const ID_COMMAND_SETS_LBA48: u16 = 1 << 10;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Word number `word` of `id`.
+fn word(id: &[u8; BLOCK_SECTOR_SIZE], word: usize) -> u16 {
+    u16::from_le_bytes([id[word * 2], id[word * 2 + 1]])
+}
+
+/// Read the string in `words`, which holds two characters per word with the first one in the high
+/// byte, and is padded with spaces.
+fn string(id: &[u8; BLOCK_SECTOR_SIZE], words: core::ops::Range<usize>) -> String {
+    let string: String = words
+        .flat_map(|w| word(id, w).to_be_bytes())
+        .map(char::from)
+        .collect();
+    String::from(string.trim_matches(|c: char| c == ' ' || c == '\0'))
+}
+
+/// Parse the 256 words returned by IDENTIFY DEVICE.
+pub fn parse_identify(id: &[u8; BLOCK_SECTOR_SIZE]) -> DiskIdentity {
+    let lba48 = word(id, ID_COMMAND_SETS) & ID_COMMAND_SETS_LBA48 != 0;
+    let sectors = if lba48 {
+        (0..4).fold(0, |sectors, i| {
+            sectors | u64::from(word(id, ID_LBA48_SECTORS + i)) << (16 * i)
+        })
+    } else {
+        u64::from(word(id, ID_LBA28_SECTORS)) | u64::from(word(id, ID_LBA28_SECTORS + 1)) << 16
+    };
+
+    DiskIdentity {
+        model: string(id, ID_MODEL),
+        serial: string(id, ID_SERIAL),
+        sectors,
+        lba48,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Put `s` in `words` of `id`, the way the disk does.
+    fn put_string(id: &mut [u8; BLOCK_SECTOR_SIZE], words: core::ops::Range<usize>, s: &str) {
+        let mut padded = s.as_bytes().to_vec();
+        padded.resize(words.len() * 2, b' ');
+        for (w, pair) in words.zip(padded.chunks_exact(2)) {
+            id[w * 2..w * 2 + 2].copy_from_slice(&[pair[1], pair[0]]);
+        }
+    }
+
+    #[test]
+    fn parse_sample() {
+        let mut id = [0; BLOCK_SECTOR_SIZE];
+        put_string(&mut id, ID_SERIAL, "QM00001");
+        put_string(&mut id, ID_MODEL, "QEMU HARDDISK");
+        // 1 GiB through 28-bit commands
+        id[120..124].copy_from_slice(&0x20_0000u32.to_le_bytes());
+
+        let identity = parse_identify(&id);
+        assert_eq!(
+            identity,
+            DiskIdentity {
+                model: "QEMU HARDDISK".into(),
+                serial: "QM00001".into(),
+                sectors: 0x20_0000,
+                lba48: false,
+            }
+        );
+        // the characters in each word are swapped
+        assert_eq!(&id[54..58], b"EQUM");
+
+        // 3 TiB, which only 48-bit commands reach
+        id[166..168].copy_from_slice(&ID_COMMAND_SETS_LBA48.to_le_bytes());
+        id[120..124].copy_from_slice(&0x0FFF_FFFFu32.to_le_bytes());
+        id[200..208].copy_from_slice(&0x1_8000_0000u64.to_le_bytes());
+        let identity = parse_identify(&id);
+        assert!(identity.lba48);
+        assert_eq!(identity.sectors, 0x1_8000_0000);
+        assert_eq!(identity.model, "QEMU HARDDISK");
+    }
+}
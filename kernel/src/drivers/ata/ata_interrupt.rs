@@ -1,4 +1,5 @@
 use crate::drivers::ata::ata_core::CHANNELS;
+use crate::threading::work_queue::schedule_work;
 use alloc::string::String;
 use kidneyos_shared::eprintln;
 use kidneyos_shared::serial::inb;
@@ -18,12 +19,11 @@ pub fn on_ide_interrupt(vec_no: u8) {
                 // Wake up the waiting thread
                 channel.sem_up();
             } else {
-                // Spurious interrupt
-                eprintln!(
-                    "IDE: Spurious interrupt on channel {} ({})",
-                    i,
-                    String::from_iter(channel.get_name())
-                );
+                // Spurious interrupt, which is reported outside the handler
+                let name = String::from_iter(channel.get_name());
+                schedule_work(move || {
+                    eprintln!("IDE: Spurious interrupt on channel {} ({})", i, name)
+                });
             }
         }
     }
@@ -1,5 +1,7 @@
 mod ata_channel;
 pub mod ata_core;
 mod ata_device;
+mod ata_dma;
+mod ata_identify;
 pub mod ata_interrupt;
 mod ata_timer;
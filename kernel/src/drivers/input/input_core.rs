@@ -1,16 +1,15 @@
+use crate::sync::spsc::SpscRing;
 use alloc::vec::Vec;
-use core::fmt::Display;
 
 const BUFFER_SIZE: usize = 256;
 
 /// A circular buffer for storing input from the PS/2 controller.
+///
+/// The keyboard interrupt handler is the only one to add bytes, and the terminal's softirq handler
+/// is the only one to take them out, so it needs no lock.
 pub struct InputBuffer {
     /// The buffer itself.
-    buf: [u8; BUFFER_SIZE],
-    /// The index of the head of the buffer.
-    head: usize,
-    /// The index of the tail of the buffer.
-    tail: usize,
+    buf: SpscRing<u8, BUFFER_SIZE>,
 
     /// Callbacks when buffer receives a byte.
     pub on_receive: Vec<fn(u8)>,
@@ -21,24 +20,24 @@ impl InputBuffer {
     /// Create a new, empty input buffer.
     pub const fn new() -> InputBuffer {
         InputBuffer {
-            buf: [0; BUFFER_SIZE],
-            head: 0,
-            tail: 0,
+            buf: SpscRing::new(),
             on_receive: Vec::new(),
         }
     }
 
     /// Check if the buffer is empty.
     pub fn is_empty(&self) -> bool {
-        self.head == self.tail
+        self.buf.is_empty()
     }
 
-    /// Add a byte to the buffer.
-    pub fn putc(&mut self, c: u8) {
+    /// Add a byte to the buffer, dropping it if the buffer is full. Only called from the keyboard
+    /// interrupt handler.
+    pub fn putc(&self, c: u8) {
         // TODO: wait while buffer is full
 
-        self.buf[self.head] = c;
-        self.head = (self.head + 1) % BUFFER_SIZE;
+        // SAFETY: Only the keyboard interrupt handler adds to the buffer, and it can't interrupt
+        // itself.
+        let _ = unsafe { self.buf.push(c) };
 
         for callback in self.on_receive.iter() {
             callback(c);
@@ -46,22 +45,11 @@ impl InputBuffer {
     }
 
     /// Get a byte from the buffer.
-    pub fn getc(&mut self) -> Option<u8> {
-        if self.head == self.tail {
-            None
-        } else {
-            let c = self.buf[self.tail];
-            self.tail = (self.tail + 1) % BUFFER_SIZE;
-            Some(c)
-        }
-    }
-}
-
-impl Display for InputBuffer {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        for i in self.tail..self.head {
-            write!(f, "{}", self.buf[i % BUFFER_SIZE] as char)?;
-        }
-        Ok(())
+    ///
+    /// # Safety
+    ///
+    /// Only one thread may take bytes out of the buffer at a time.
+    pub unsafe fn getc(&self) -> Option<u8> {
+        unsafe { self.buf.pop() }
     }
 }
@@ -102,6 +102,16 @@ static UNSHIFTED_KEYMAP: &[Keymap] = &[
     },
 ];
 
+/// Extended keys, which send escape sequences like a VT100 terminal does.
+static EXTENDED_KEYS: &[(u16, &str)] = &[
+    (0xE048, "\x1B[A"), // Up
+    (0xE050, "\x1B[B"), // Down
+    (0xE04D, "\x1B[C"), // Right
+    (0xE04B, "\x1B[D"), // Left
+    (0xE01C, "\r"),     // Keypad Enter
+    (0xE035, "/"),      // Keypad /
+];
+
 /// Characters for keys pressed with Shift, for those keys where it matters.
 static SHIFTED_KEYMAP: &[Keymap] = &[
     Keymap {
@@ -133,8 +143,8 @@ static SHIFTED_KEYMAP: &[Keymap] = &[
 pub fn on_keyboard_interrupt() {
     // Modifier keys
     let shift: bool = L_SHIFT.load(Relaxed) || R_SHIFT.load(Relaxed);
-    // TODO: Handle ctrl and alt?
-    let _ctrl: bool = L_CTRL.load(Relaxed) || R_CTRL.load(Relaxed);
+    // TODO: Handle alt?
+    let ctrl: bool = L_CTRL.load(Relaxed) || R_CTRL.load(Relaxed);
     let _alt: bool = L_ALT.load(Relaxed) || R_ALT.load(Relaxed);
 
     // Read the scancode
@@ -146,7 +156,17 @@ pub fn on_keyboard_interrupt() {
 
     // > 0x80 means key release
     let release: bool = code & 0x80 != 0;
-    code &= 0x7F;
+    code &= !0x80;
+
+    if let Some((_, sequence)) = EXTENDED_KEYS.iter().find(|(key, _)| *key == code) {
+        if !release {
+            let input_buffer = &unwrap_system().input_buffer;
+            for &c in sequence.as_bytes() {
+                input_buffer.putc(c);
+            }
+        }
+        return;
+    }
 
     // Caps Lock
     if code == 0x3A {
@@ -186,8 +206,13 @@ pub fn on_keyboard_interrupt() {
             c = c.to_ascii_lowercase();
         }
 
+        // Ctrl+letter gives the corresponding control character, e.g. Ctrl-C is 0x03
+        if ctrl && c.is_ascii_alphabetic() {
+            c = c.to_ascii_lowercase() - b'a' + 1;
+        }
+
         // Add to buffer
-        unwrap_system().input_buffer.lock().putc(c);
+        unwrap_system().input_buffer.putc(c);
     } else {
         // Modifier keys
 
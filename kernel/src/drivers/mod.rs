@@ -1,3 +1,7 @@
 pub mod ata;
 pub mod dummy_device;
 pub mod input;
+pub mod pci;
+pub mod qemu_exit;
+#[cfg(any(feature = "ramdisk", test))]
+pub mod ramdisk;
@@ -0,0 +1,155 @@
+// https://wiki.osdev.org/PCI
+
+use kidneyos_shared::serial::{inl, outl};
+
+/// Port to write the address of a configuration space register to, before accessing it through
+/// [`CONFIG_DATA`].
+const CONFIG_ADDRESS: u16 = 0xCF8;
+const CONFIG_DATA: u16 = 0xCFC;
+
+/// Set in [`CONFIG_ADDRESS`] to access configuration space.
+const CONFIG_ENABLE: u32 = 1 << 31;
+
+// Configuration space registers, as offsets
+const VENDOR_ID: u8 = 0x00;
+const COMMAND: u8 = 0x04;
+const CLASS: u8 = 0x08;
+const HEADER_TYPE: u8 = 0x0C;
+const BAR0: u8 = 0x10;
+
+/// Command register bit letting the device do DMA.
+const COMMAND_BUS_MASTER: u32 = 1 << 2;
+/// Header type bit saying the device has functions other than 0.
+const HEADER_MULTI_FUNCTION: u32 = 1 << 23;
+/// Vendor ID read back when there's no device.
+const NO_VENDOR: u32 = 0xFFFF;
+
+/// A function of a device on a PCI bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PciFunction {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl PciFunction {
+    /// What to write to [`CONFIG_ADDRESS`] to access the 32-bit register at `offset`.
+    fn config_address(self, offset: u8) -> u32 {
+        CONFIG_ENABLE
+            | u32::from(self.bus) << 16
+            | u32::from(self.device) << 11
+            | u32::from(self.function) << 8
+            | u32::from(offset & !0b11)
+    }
+
+    /// Read the 32-bit configuration space register at `offset`.
+    ///
+    /// # Safety
+    ///
+    /// Nothing else may be accessing configuration space at the same time.
+    pub unsafe fn read(self, offset: u8) -> u32 {
+        outl(CONFIG_ADDRESS, self.config_address(offset));
+        inl(CONFIG_DATA)
+    }
+
+    /// Write the 32-bit configuration space register at `offset`.
+    ///
+    /// # Safety
+    ///
+    /// Nothing else may be accessing configuration space at the same time, and the write mustn't
+    /// break anything using the device.
+    pub unsafe fn write(self, offset: u8, value: u32) {
+        outl(CONFIG_ADDRESS, self.config_address(offset));
+        outl(CONFIG_DATA, value);
+    }
+
+    /// The class code, subclass and programming interface, which say what kind of device this is.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`Self::read`].
+    pub unsafe fn class(self) -> (u8, u8, u8) {
+        let [_revision, prog_if, subclass, class] = self.read(CLASS).to_le_bytes();
+        (class, subclass, prog_if)
+    }
+
+    /// Base address register `n`, from 0 to 5.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`Self::read`].
+    pub unsafe fn bar(self, n: u8) -> u32 {
+        assert!(n < 6, "there are only 6 BARs");
+        self.read(BAR0 + 4 * n)
+    }
+
+    /// Let the device do DMA.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`Self::write`].
+    pub unsafe fn enable_bus_mastering(self) {
+        // The upper half is the status register, whose bits are cleared by writing 1s to them.
+        let command = self.read(COMMAND) & 0xFFFF;
+        self.write(COMMAND, command | COMMAND_BUS_MASTER);
+    }
+}
+
+/// Find the first function with the class code `class` and subclass `subclass`, by checking every
+/// device on every bus.
+///
+/// # Safety
+///
+/// Nothing else may be accessing configuration space at the same time.
+pub unsafe fn find(class: u8, subclass: u8) -> Option<PciFunction> {
+    for bus in 0..=u8::MAX {
+        for device in 0..32 {
+            for function in 0..8 {
+                let pci_function = PciFunction {
+                    bus,
+                    device,
+                    function,
+                };
+                if pci_function.read(VENDOR_ID) & 0xFFFF == NO_VENDOR {
+                    // functions needn't be numbered consecutively, but function 0 is always there
+                    if function == 0 {
+                        break;
+                    }
+                    continue;
+                }
+                let (found_class, found_subclass, _) = pci_function.class();
+                if (found_class, found_subclass) == (class, subclass) {
+                    return Some(pci_function);
+                }
+                if function == 0 && pci_function.read(HEADER_TYPE) & HEADER_MULTI_FUNCTION == 0 {
+                    break;
+                }
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::PciFunction;
+
+    #[test]
+    fn config_address() {
+        let function = PciFunction {
+            bus: 0,
+            device: 1,
+            function: 1,
+        };
+        assert_eq!(function.config_address(0x20), 0x8000_0920);
+        // registers are read a dword at a time
+        assert_eq!(function.config_address(0x0E), 0x8000_090C);
+
+        let function = PciFunction {
+            bus: 0xFF,
+            device: 31,
+            function: 7,
+        };
+        assert_eq!(function.config_address(0xFC), 0x80FF_FFFC);
+    }
+}
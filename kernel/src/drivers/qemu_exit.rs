@@ -0,0 +1,58 @@
+//! QEMU's `isa-debug-exit` device, which lets the kernel stop the VM with an exit status of its
+//! choosing, to report whether tests passed. QEMU only has it when started with
+//! `-device isa-debug-exit,iobase=0xf4,iosize=0x04`.
+
+use kidneyos_shared::serial::{inl, outl};
+
+/// The I/O port the device is at, as given by `iobase`.
+const QEMU_EXIT_PORT: u16 = 0xf4;
+
+/// What a read from an I/O port nothing's attached to gives. Reads from the exit device give 0.
+const FLOATING_BUS: u32 = u32::MAX;
+
+/// Written to the device to exit. QEMU exits with `(code << 1) | 1`, so these give 33 and 35,
+/// which can't be confused with QEMU's own exit statuses of 0 and 1.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QemuExitCode {
+    Success = 0x10,
+    Failure = 0x11,
+}
+
+impl QemuExitCode {
+    /// The value written to the port for this code.
+    fn port_value(self) -> u32 {
+        self as u32
+    }
+}
+
+/// Whether QEMU has the exit device.
+fn present() -> bool {
+    // SAFETY: Reading the port has no side effects, whether or not the device is there.
+    unsafe { inl(QEMU_EXIT_PORT) != FLOATING_BUS }
+}
+
+/// Exit QEMU with `code`, if it has the exit device. Otherwise, including on real hardware, this
+/// does nothing and returns.
+pub fn qemu_exit(code: QemuExitCode) {
+    if present() {
+        // SAFETY: The port belongs to the exit device, and writing to it has no effect on the
+        // kernel; QEMU just stops.
+        unsafe { outl(QEMU_EXIT_PORT, code.port_value()) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::QemuExitCode;
+
+    #[test]
+    fn port_values() {
+        assert_eq!(QemuExitCode::Success.port_value(), 0x10);
+        assert_eq!(QemuExitCode::Failure.port_value(), 0x11);
+        // the exit statuses `make selftest` looks for
+        let status = |code: QemuExitCode| (code.port_value() << 1) | 1;
+        assert_eq!(status(QemuExitCode::Success), 33);
+        assert_eq!(status(QemuExitCode::Failure), 35);
+    }
+}
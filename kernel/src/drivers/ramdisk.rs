@@ -0,0 +1,60 @@
+use crate::block::block_core::{BlockOp, BlockSector, BLOCK_SECTOR_SIZE};
+use crate::block::block_error::BlockError;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// A block device held in memory, so a filesystem can be mounted without any disk hardware.
+///
+/// Writes only last until the ramdisk is dropped.
+pub struct RamDisk {
+    data: Vec<u8>,
+}
+
+impl RamDisk {
+    /// New ramdisk holding a copy of the disk image `image`, e.g. one embedded in the kernel. If
+    /// the image isn't a whole number of sectors, the last sector is padded with zeroes.
+    pub fn from_image(image: &[u8]) -> Self {
+        let mut data = image.to_vec();
+        data.resize(image.len().next_multiple_of(BLOCK_SECTOR_SIZE), 0);
+        Self { data }
+    }
+
+    /// Size of the ramdisk in sectors.
+    pub fn sectors(&self) -> BlockSector {
+        (self.data.len() / BLOCK_SECTOR_SIZE)
+            .try_into()
+            .expect("ramdisk too large")
+    }
+
+    /// Byte range of the `count` sectors starting at `start`.
+    fn range(&self, start: BlockSector, count: BlockSector) -> Result<Range<usize>, BlockError> {
+        let end = start
+            .checked_add(count)
+            .filter(|&end| end <= self.sectors())
+            .ok_or(BlockError::SectorOutOfBounds)?;
+        Ok(start as usize * BLOCK_SECTOR_SIZE..end as usize * BLOCK_SECTOR_SIZE)
+    }
+}
+
+impl BlockOp for RamDisk {
+    unsafe fn read(&mut self, sector: BlockSector, buf: &mut [u8]) -> Result<(), BlockError> {
+        self.read_sectors(sector, 1, buf)
+    }
+
+    unsafe fn read_sectors(
+        &mut self,
+        start: BlockSector,
+        count: BlockSector,
+        buf: &mut [u8],
+    ) -> Result<(), BlockError> {
+        let range = self.range(start, count)?;
+        buf.copy_from_slice(&self.data[range]);
+        Ok(())
+    }
+
+    unsafe fn write(&mut self, sector: BlockSector, buf: &[u8]) -> Result<(), BlockError> {
+        let range = self.range(sector, 1)?;
+        self.data[range].copy_from_slice(buf);
+        Ok(())
+    }
+}
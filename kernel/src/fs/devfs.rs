@@ -0,0 +1,57 @@
+use crate::vfs::{
+    DirEntries, Error, FileInfo, INodeNum, INodeType, Path, Result, SimpleFileSystem,
+};
+
+/// Inode number of the root directory of a [`DevFS`]
+pub const ROOT_INODE: INodeNum = 1;
+/// Inode number of `null`
+pub const NULL_INODE: INodeNum = 2;
+/// Inode number of `tty`
+pub const TTY_INODE: INodeNum = 3;
+
+/// File system containing device files, normally mounted at `/dev`.
+///
+/// This only gives the devices names and inode numbers. Reading and writing is done by the
+/// [`RootFileSystem`](crate::fs::fs_manager::RootFileSystem), which recognizes these inodes
+/// when they are opened.
+pub struct DevFS;
+
+impl SimpleFileSystem for DevFS {
+    fn root(&self) -> INodeNum {
+        ROOT_INODE
+    }
+    fn open(&mut self, inode: INodeNum) -> Result<()> {
+        match inode {
+            ROOT_INODE | NULL_INODE | TTY_INODE => Ok(()),
+            _ => Err(Error::NotFound),
+        }
+    }
+    fn create(&mut self, _parent: INodeNum, _name: &Path) -> Result<INodeNum> {
+        Err(Error::ReadOnlyFS)
+    }
+    fn mkdir(&mut self, _parent: INodeNum, _name: &Path) -> Result<INodeNum> {
+        Err(Error::ReadOnlyFS)
+    }
+    fn readdir(&mut self, dir: INodeNum) -> Result<DirEntries> {
+        if dir != ROOT_INODE {
+            return Err(Error::NotDirectory);
+        }
+        let mut entries = DirEntries::new();
+        entries.add(NULL_INODE, INodeType::File, "null");
+        entries.add(TTY_INODE, INodeType::File, "tty");
+        Ok(entries)
+    }
+    fn stat(&mut self, file: INodeNum) -> Result<FileInfo> {
+        let r#type = if file == ROOT_INODE {
+            INodeType::Directory
+        } else {
+            INodeType::File
+        };
+        Ok(FileInfo {
+            r#type,
+            inode: file,
+            size: 0,
+            nlink: 1,
+        })
+    }
+}
@@ -0,0 +1,57 @@
+use crate::fs::FileDescriptor;
+use crate::sync::mutex::Mutex;
+use crate::user_program::syscall::EpollEvent;
+use crate::vfs::{Error, Result};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::{Debug, Formatter};
+
+/// Change to the files an `epoll` instance is waiting for, as with `epoll_ctl`.
+pub enum EpollOp {
+    Add(EpollEvent),
+    Modify(EpollEvent),
+    Delete,
+}
+
+/// An `epoll` instance: the set of files a process is waiting for, and what it's waiting for on
+/// each of them. Duplicated file descriptors share the same set.
+#[derive(Clone, Default)]
+pub struct Epoll(Arc<Mutex<BTreeMap<FileDescriptor, EpollEvent>>>);
+
+impl Epoll {
+    /// Apply `op` to the entry for `fd`.
+    ///
+    /// Returns [`Error::Exists`] if adding a file which is already there, and [`Error::NotFound`]
+    /// if modifying or deleting one which isn't.
+    pub fn update(&self, fd: FileDescriptor, op: EpollOp) -> Result<()> {
+        let mut interest = self.0.lock();
+        match op {
+            EpollOp::Add(event) => {
+                if interest.contains_key(&fd) {
+                    return Err(Error::Exists);
+                }
+                interest.insert(fd, event);
+            }
+            EpollOp::Modify(event) => {
+                *interest.get_mut(&fd).ok_or(Error::NotFound)? = event;
+            }
+            EpollOp::Delete => {
+                interest.remove(&fd).ok_or(Error::NotFound)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The files being waited for, and what's being waited for on each of them.
+    pub fn interest(&self) -> Vec<(FileDescriptor, EpollEvent)> {
+        let interest = self.0.lock();
+        interest.iter().map(|(&fd, &event)| (fd, event)).collect()
+    }
+}
+
+impl Debug for Epoll {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Epoll")
+    }
+}
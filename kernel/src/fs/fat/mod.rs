@@ -157,6 +157,40 @@ struct Fat32Header {
     signature_word: [u8; 2],
 }
 
+#[repr(C)]
+#[allow(dead_code)]
+#[derive(FromZeroes, FromBytes, Unaligned)]
+struct FsInfo {
+    lead_signature: U32,
+    _reserved: [u8; 480],
+    struct_signature: U32,
+    free_count: U32,
+    next_free: U32,
+    _reserved1: [u8; 12],
+    trail_signature: U32,
+}
+
+impl FsInfo {
+    const LEAD_SIGNATURE: u32 = 0x41615252;
+    const STRUCT_SIGNATURE: u32 = 0x61417272;
+    const TRAIL_SIGNATURE: u32 = 0xAA550000;
+    fn verify_integrity(&self) -> Result<()> {
+        for (name, value, expected) in [
+            ("lead", self.lead_signature, Self::LEAD_SIGNATURE),
+            ("struct", self.struct_signature, Self::STRUCT_SIGNATURE),
+            ("trail", self.trail_signature, Self::TRAIL_SIGNATURE),
+        ] {
+            let value: u32 = value.into();
+            if value != expected {
+                return error!(
+                    "invalid FSInfo {name} signature: {value:#010x}, expected {expected:#010x}"
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
 impl Fat32Header {
     fn fat_size(&self) -> u32 {
         self.fat_size.into()
@@ -178,11 +212,44 @@ impl Fat32Header {
         }
         Ok(())
     }
+    /// Extra checks done by [`FatFS::new_strict`]: the backup boot sector (if any) must match
+    /// `first_sector`, and the FSInfo sector (if any) must have valid signatures.
+    fn verify_strict(&self, block: &Block, first_sector: &[u8; BLOCK_SECTOR_SIZE]) -> Result<()> {
+        let disk_sectors_per_fat_sector = self.base.bytes_per_sector() / BLOCK_SECTOR_SIZE as u32;
+        let mut sector = [0; BLOCK_SECTOR_SIZE];
+        let bk_boot_sector: u32 = u16::from(self.bk_boot_sector).into();
+        if bk_boot_sector != 0 {
+            block.read(bk_boot_sector * disk_sectors_per_fat_sector, &mut sector)?;
+            if sector != *first_sector {
+                return error!("backup boot sector doesn't match primary boot sector");
+            }
+        }
+        let fs_info: u16 = self.fs_info.into();
+        // 0 and 0xFFFF both mean there is no FSInfo sector.
+        if fs_info != 0 && fs_info != 0xFFFF {
+            block.read(
+                u32::from(fs_info) * disk_sectors_per_fat_sector,
+                &mut sector,
+            )?;
+            FsInfo::ref_from(&sector)
+                .expect("FsInfo type should be 512 bytes")
+                .verify_integrity()?;
+        }
+        Ok(())
+    }
 }
 
 impl FatFS {
     /// Create new FAT filesystem from block device
-    pub fn new(mut block: Block) -> Result<Self> {
+    pub fn new(block: Block) -> Result<Self> {
+        Self::with_strictness(block, false)
+    }
+    /// Like [`FatFS::new`], but for FAT-32 also verify that the backup boot sector matches the
+    /// primary one, and that the FSInfo signatures are valid, to catch corrupted images early.
+    pub fn new_strict(block: Block) -> Result<Self> {
+        Self::with_strictness(block, true)
+    }
+    fn with_strictness(mut block: Block, strict: bool) -> Result<Self> {
         let mut first_sector = [0; 512];
         block.read(0, &mut first_sector)?;
         let fat16_header: &Fat16Header =
@@ -222,6 +289,9 @@ impl FatFS {
             fat_type = FatType::Fat16;
         } else {
             fat32_header.verify_integrity()?;
+            if strict {
+                fat32_header.verify_strict(&block, &first_sector)?;
+            }
             fat_type = FatType::Fat32;
         }
         let disk_sectors_per_fat_sector = bytes_per_sector / BLOCK_SECTOR_SIZE as u32;
@@ -367,33 +437,31 @@ impl SimpleFileSystem for FatFS {
         let file_size = info.vfs.size as u32;
         let mut read_count = 0;
         while !buf.is_empty() && offset < file_size {
-            // read a single cluster from the file
+            // read (the rest of) a single cluster from the file
             let cluster_index = offset / self.cluster_size();
             let cluster_offset = offset % self.cluster_size();
-            let sector_within_cluster = cluster_offset % self.disk_sectors_per_cluster;
+            let sector_within_cluster = cluster_offset / BLOCK_SECTOR_SIZE as u32;
             let sector_offset = cluster_offset % BLOCK_SECTOR_SIZE as u32;
+            // Read # of bytes equal to the minimum of:
+            //   - the buffer size
+            //   - the amount of bytes left in the file
+            //   - the rest of the cluster (starting from cluster_offset)
+            let read_size = min(
+                buf.len() as u32,
+                min(file_size - offset, self.cluster_size() - cluster_offset),
+            );
+            let sector_count = (sector_offset + read_size).div_ceil(BLOCK_SECTOR_SIZE as u32);
             let cluster = info.clusters[cluster_index as usize];
-            let cluster_start = self.first_disk_sector_in_cluster(cluster);
-            for sector in
-                cluster_start + sector_within_cluster..cluster_start + self.disk_sectors_per_cluster
-            {
-                let mut sector_data = [0; BLOCK_SECTOR_SIZE];
-                self.block.read(sector, &mut sector_data)?;
-                // Read # of bytes equal to the minimum of:
-                //   - the buffer size
-                //   - the amount of bytes left in the file
-                //   - the entire sector (starting from sector_offset)
-                let read_size = min(
-                    buf.len() as u32,
-                    min(file_size - offset, BLOCK_SECTOR_SIZE as u32 - sector_offset),
-                );
-                buf[..read_size as usize].copy_from_slice(
-                    &sector_data[sector_offset as usize..(sector_offset + read_size) as usize],
-                );
-                buf = &mut buf[read_size as usize..];
-                offset += read_size;
-                read_count += read_size;
-            }
+            let first_sector = self.first_disk_sector_in_cluster(cluster) + sector_within_cluster;
+            let mut sector_data = vec![0; sector_count as usize * BLOCK_SECTOR_SIZE];
+            self.block
+                .read_sectors(first_sector, sector_count, &mut sector_data)?;
+            buf[..read_size as usize].copy_from_slice(
+                &sector_data[sector_offset as usize..(sector_offset + read_size) as usize],
+            );
+            buf = &mut buf[read_size as usize..];
+            offset += read_size;
+            read_count += read_size;
         }
         Ok(read_count as usize)
     }
@@ -428,18 +496,22 @@ impl SimpleFileSystem for FatFS {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::block::block_core::test::block_from_file;
+    use crate::block::block_core::test::GzBlockDevice;
     use crate::vfs::OwnedDirEntry;
-    use std::fs::File;
-    use std::io::{prelude::*, Cursor};
     /// Open a gzip-compressed raw disk image containing a FAT filesystem.
     /// Any changes made to the filesystem are kept in memory, but not written back to the file.
     fn open_img_gz(path: &str) -> FatFS {
-        let file = File::open(path).unwrap();
-        let mut gz_decoder = flate2::read::GzDecoder::new(file);
-        let mut buf = vec![];
-        gz_decoder.read_to_end(&mut buf).unwrap();
-        FatFS::new(block_from_file(Cursor::new(buf))).unwrap()
+        FatFS::new(GzBlockDevice::open(path).unwrap().into_block()).unwrap()
+    }
+    /// Decompress a FAT image into memory and set byte `offset` of disk sector `sector` to
+    /// `value`, e.g. to corrupt it.
+    fn patched_img_gz(path: &str, sector: u32, offset: usize, value: u8) -> Block {
+        let block = GzBlockDevice::open(path).unwrap().into_block();
+        let mut buf = [0; BLOCK_SECTOR_SIZE];
+        block.read(sector, &mut buf).unwrap();
+        buf[offset] = value;
+        block.write(sector, &buf).unwrap();
+        block
     }
     fn test_simple(mut fat: FatFS) {
         let root = fat.root();
@@ -485,6 +557,20 @@ mod test {
         let fat = open_img_gz("tests/fat/simple_fat32.img.gz");
         test_simple(fat);
     }
+    #[test]
+    fn strict_fat32() {
+        let path = "tests/fat/simple_fat32.img.gz";
+        test_simple(FatFS::new_strict(GzBlockDevice::open(path).unwrap().into_block()).unwrap());
+        // FSInfo is in sector 1 of simple_fat32.img.gz; break its lead signature.
+        let Err(Error::IO(message)) = FatFS::new_strict(patched_img_gz(path, 1, 0, 0)) else {
+            panic!("strict mode should reject a corrupted FSInfo signature");
+        };
+        assert!(message.contains("FSInfo"), "{message}");
+        test_simple(FatFS::new(patched_img_gz(path, 1, 0, 0)).unwrap());
+        // the backup boot sector is in sector 6; change its volume label
+        assert!(FatFS::new_strict(patched_img_gz(path, 6, 71, b'X')).is_err());
+        test_simple(FatFS::new(patched_img_gz(path, 6, 71, b'X')).unwrap());
+    }
     fn read_only_test_vs_host(name: &str, r#type: FatType) {
         let type_string = match r#type {
             FatType::Fat16 => "fat16",
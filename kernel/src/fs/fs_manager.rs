@@ -1,10 +1,29 @@
+use crate::block::block_core::Block;
+use crate::fs::devfs::{self, DevFS};
+use crate::fs::epoll::{Epoll, EpollOp};
+use crate::fs::fat::FatFS;
 use crate::fs::pipe::{PipeInner, PipeReadEnd, PipeWriteEnd};
+use crate::fs::signalfd::SignalFd;
+use crate::fs::tarfs::TarFS;
+use crate::fs::tty::Tty;
+use crate::fs::vsfs::VSFS;
 use crate::fs::{FileDescriptor, ProcessFileDescriptor};
+use crate::mem::page_cache::{PageCache, WeakPageCache};
+use crate::mem::shm::SharedMemory;
 use crate::mem::vma::{VMAInfo, VMA};
 use crate::sync::mutex::Mutex;
-use crate::system::{running_process, unwrap_system};
-use crate::threading::{process::Pid, thread_control_block::ProcessControlBlock};
-use crate::user_program::syscall::Dirent;
+#[cfg(not(test))]
+use crate::threading::thread_sleep::thread_wakeup;
+use crate::threading::{
+    process::{Pid, Tid},
+    thread_control_block::ProcessControlBlock,
+};
+use crate::user_program::signal::PendingSignals;
+use crate::user_program::syscall::{
+    Dirent, Dirent64, EpollEvent, SigSet, EPOLLERR, EPOLLHUP, EPOLLIN, EPOLLOUT,
+    POSIX_FADV_DONTNEED, POSIX_FADV_NORMAL, POSIX_FADV_RANDOM, POSIX_FADV_SEQUENTIAL,
+};
+use crate::vfs::tempfs::TempFS;
 use crate::vfs::{
     Error, FileHandle, FileInfo, FileSystem, INodeNum, INodeType, OwnedDirEntry, OwnedPath, Path,
     Result,
@@ -14,7 +33,6 @@ use alloc::sync::Arc;
 use alloc::{
     boxed::Box,
     collections::{btree_map::Entry as BTreeMapEntry, BTreeMap},
-    format,
     string::String,
     vec,
     vec::Vec,
@@ -23,9 +41,92 @@ use core::cmp::min;
 use core::fmt::Debug;
 use core::mem::{align_of, size_of};
 use core::num::NonZeroUsize;
+use core::ops::Range;
 use core::sync::atomic::Ordering;
 use kidneyos_shared::mem::PAGE_FRAME_SIZE;
 
+/// How far past what's read from a file to read ahead, unless it's been advised otherwise.
+const READ_AHEAD: usize = PAGE_FRAME_SIZE;
+/// How far to read ahead in files which have been advised to be read sequentially.
+const SEQUENTIAL_READ_AHEAD: usize = 8 * PAGE_FRAME_SIZE;
+
+/// How far to read ahead in a file, given the `posix_fadvise` advice for it.
+fn read_ahead_for(advice: i32) -> usize {
+    match advice {
+        POSIX_FADV_RANDOM => 0,
+        POSIX_FADV_SEQUENTIAL => SEQUENTIAL_READ_AHEAD,
+        _ => READ_AHEAD,
+    }
+}
+
+/// Layout of the directory entries written by getdents
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DirentFormat {
+    /// [`Dirent`], with a 32-bit inode number
+    Dirent,
+    /// [`Dirent64`], with a 64-bit inode number and offset
+    Dirent64,
+}
+
+impl DirentFormat {
+    fn header_size(self) -> usize {
+        match self {
+            Self::Dirent => size_of::<Dirent>(),
+            Self::Dirent64 => size_of::<Dirent64>(),
+        }
+    }
+    fn align(self) -> usize {
+        match self {
+            Self::Dirent => align_of::<Dirent>(),
+            Self::Dirent64 => align_of::<Dirent64>(),
+        }
+    }
+    fn name_offset(self) -> usize {
+        match self {
+            Self::Dirent => core::mem::offset_of!(Dirent, name),
+            Self::Dirent64 => core::mem::offset_of!(Dirent64, d_name),
+        }
+    }
+    /// Write the fixed-size part of a directory entry to `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be aligned to `self.align()`, and valid for writing `self.header_size()` bytes.
+    unsafe fn write_header(
+        self,
+        ptr: *mut u8,
+        offset: u64,
+        inode: INodeNum,
+        reclen: u16,
+        r#type: u8,
+    ) {
+        match self {
+            Self::Dirent => {
+                let dirent_ptr: *mut Dirent = ptr.cast();
+                assert!(dirent_ptr.is_aligned());
+                dirent_ptr.write(Dirent {
+                    offset: offset as i64,
+                    inode,
+                    reclen,
+                    r#type,
+                    name: [],
+                });
+            }
+            Self::Dirent64 => {
+                let dirent_ptr: *mut Dirent64 = ptr.cast();
+                assert!(dirent_ptr.is_aligned());
+                dirent_ptr.write(Dirent64 {
+                    d_ino: inode.into(),
+                    d_off: offset as i64,
+                    d_reclen: reclen,
+                    d_type: r#type,
+                    d_name: [],
+                });
+            }
+        }
+    }
+}
+
 /// Possible places to seek from
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum SeekFrom {
@@ -41,11 +142,26 @@ pub enum Mode {
     ReadWrite,
     /// Open or create file for read/write access
     CreateReadWrite,
+    /// Create file for read/write access, failing with [`Error::Exists`] if it already exists
+    CreateExclusiveReadWrite,
     // could add ReadOnly, WriteOnly, etc. here
     // - depends whether we want support for file permissions
     // (if not, we could just do that at the libc level)
 }
 
+/// Advisory lock on a file, taken with `flock`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FileLock {
+    /// Can be held by any number of file descriptors at once
+    Shared,
+    /// Can only be held by one file descriptor, while no others hold any lock
+    Exclusive,
+}
+
+/// Stands in for `thread_wakeup` in tests, where there are no threads to wake.
+#[cfg(test)]
+fn thread_wakeup(_tid: Tid) {}
+
 /// Maximum number of simultaneously open files for a process.
 ///
 /// 1024 is the default on Linux.
@@ -63,12 +179,15 @@ struct Directory {
     /// are never skipped over or repeated.
     ///
     /// We accomplish this by assigning an ID to each directory entry. These IDs are always increasing,
-    /// and the "offset" member of a directory fd is the next ID it will read.
+    /// and the "offset" member of a directory fd is the next ID it will read. So getdents returns
+    /// entries in the order they were added to the directory.
     ///
     /// If this is `None`, that means the directory entries haven't been scanned yet.
     /// This scanning is done in [`FileSystemManagerTrait`].
     entries: Option<BTreeMap<u64, OwnedDirEntry>>,
     /// map from paths to directory entry IDs
+    ///
+    /// This is also the name-ordered view of the directory used by sorted getdents.
     lookup: BTreeMap<OwnedPath, u64>,
     /// next directory entry ID to hand out
     id: u64,
@@ -140,22 +259,46 @@ impl Directory {
     unsafe fn getdents(
         &self,
         offset: &mut u64,
-        output: *mut Dirent,
+        output: *mut u8,
         mut size: usize,
+        format: DirentFormat,
+        sorted: bool,
     ) -> Result<usize> {
         let entries = self
             .entries
             .as_ref()
             .expect("Directory::getdents called before directory entries were scanned");
+        // Offsets are opaque cookies, so one that was never handed out (say, from an lseek to
+        // garbage) is treated as the end of the directory rather than a position in it.
+        let end = if sorted {
+            self.lookup.len() as u64
+        } else {
+            self.id
+        };
+        if *offset > end {
+            *offset = end;
+            return Ok(0);
+        }
+        // In sorted order, the offset is the position in the name-ordered view rather than an ID.
+        let iter: Box<dyn Iterator<Item = (u64, &OwnedDirEntry)>> = if sorted {
+            Box::new(
+                self.lookup
+                    .values()
+                    .enumerate()
+                    .skip(usize::try_from(*offset).unwrap_or(usize::MAX))
+                    .map(|(i, id)| (i as u64, &entries[id])),
+            )
+        } else {
+            Box::new(entries.range(*offset..).map(|(id, entry)| (*id, entry)))
+        };
         let mut bytes_read = 0;
-        let mut output: *mut u8 = output.cast();
-        for entry in entries.range(*offset..) {
-            let off = *entry.0;
-            let r#type = entry.1.r#type;
-            let inode = entry.1.inode;
-            let name = &entry.1.name;
-            let required_bytes = size_of::<Dirent>() + name.len() + 1;
-            let dirent_align = align_of::<Dirent>();
+        let mut output = output;
+        for (off, entry) in iter {
+            let r#type = entry.r#type;
+            let inode = entry.inode;
+            let name = &entry.name;
+            let required_bytes = format.header_size() + name.len() + 1;
+            let dirent_align = format.align();
             // round up to dirent alignment
             let required_bytes = required_bytes.div_ceil(dirent_align) * dirent_align;
             if size < required_bytes {
@@ -164,20 +307,9 @@ impl Directory {
             let Ok(reclen) = u16::try_from(required_bytes) else {
                 return Err(Error::IO("file name too long".into()));
             };
-            let dirent = Dirent {
-                offset: off as i64,
-                inode,
-                reclen,
-                r#type: r#type.to_u8(),
-                name: [],
-            };
             unsafe {
-                let dirent_ptr: *mut Dirent = output.cast();
-                assert!(dirent_ptr.is_aligned());
-                dirent_ptr.write(dirent);
-                let name_ptr: *mut u8 = dirent_ptr
-                    .cast::<u8>()
-                    .add(core::mem::offset_of!(Dirent, name));
+                format.write_header(output, off, inode, reclen, r#type.to_u8());
+                let name_ptr: *mut u8 = output.add(format.name_offset());
                 name_ptr.copy_from_nonoverlapping(name.as_ptr(), name.len());
                 name_ptr.add(name.len()).write(0); // null terminator
             }
@@ -190,6 +322,32 @@ impl Directory {
     }
 }
 
+/// Data read from a file ahead of where it's been read up to, so the next read doesn't need to
+/// go to the file system.
+struct ReadAhead {
+    offset: u64,
+    data: Vec<u8>,
+}
+
+impl ReadAhead {
+    /// Copy as much of `buf` as this holds, starting from `offset`, returning how much that is.
+    fn copy_to(&self, offset: u64, buf: &mut [u8]) -> usize {
+        let Some(start) = offset.checked_sub(self.offset) else {
+            return 0;
+        };
+        let Some(data) = self.data.get(start as usize..) else {
+            return 0;
+        };
+        let n = min(data.len(), buf.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        n
+    }
+
+    fn overlaps(&self, range: &Range<u64>) -> bool {
+        self.offset < range.end && range.start < self.offset + self.data.len() as u64
+    }
+}
+
 /// Manages a single file system
 struct FileSystemManager<F: FileSystem> {
     fs: F,
@@ -203,6 +361,35 @@ struct FileSystemManager<F: FileSystem> {
     directories: BTreeMap<INodeNum, Directory>,
     /// Number of mount points in this file system.
     mount_count: u32,
+    /// What's been read ahead in each file. It's forgotten whenever the file is changed.
+    read_ahead: BTreeMap<INodeNum, ReadAhead>,
+}
+
+#[cfg(debug_assertions)]
+impl<F: FileSystem> Drop for FileSystemManager<F> {
+    /// Check that every reference to an inode belongs to a file which is still open, to catch
+    /// missing calls to `dec_ref`.
+    fn drop(&mut self) {
+        #[cfg(test)]
+        if std::thread::panicking() {
+            // don't turn a failed test into an abort
+            return;
+        }
+        let mut leaked: BTreeMap<INodeNum, usize> = self
+            .open_file_count
+            .iter()
+            .map(|(&inode, count)| (inode, count.get()))
+            .collect();
+        for handle in self.open_files.values() {
+            if let Some(count) = leaked.get_mut(&handle.inode()) {
+                *count -= 1;
+            }
+        }
+        leaked.retain(|_, count| *count > 0);
+        if !leaked.is_empty() {
+            panic!("inodes still referenced after their files were closed (inode => references): {leaked:?}");
+        }
+    }
 }
 
 struct TempOpen<F: FileSystem> {
@@ -249,6 +436,7 @@ impl<F: FileSystem + 'static> FileSystemManager<F> {
             directories: BTreeMap::new(),
             mount_point,
             mount_count: 0,
+            read_ahead: BTreeMap::new(),
         };
         me.directories.insert(root_ino, Directory::new(root_ino));
         // ensure root directory entries are in cache
@@ -283,10 +471,28 @@ trait FileSystemManagerTrait: 'static + Send + Sync {
     fn mount_point(&self) -> Option<(FileSystemID, INodeNum)>;
     fn lookup(&mut self, dir: INodeNum, entry: &Path) -> Result<INodeNum>;
     fn open(&mut self, inode: INodeNum, fd: ProcessFileDescriptor) -> Result<()>;
-    fn create(&mut self, parent: INodeNum, name: &Path, fd: ProcessFileDescriptor) -> Result<()>;
+    /// Create a file called `name` in `parent`, or open it if it already exists. With `exclusive`,
+    /// it must not exist yet.
+    fn create(
+        &mut self,
+        parent: INodeNum,
+        name: &Path,
+        fd: ProcessFileDescriptor,
+        exclusive: bool,
+    ) -> Result<()>;
     fn close(&mut self, fd: ProcessFileDescriptor) -> Result<()>;
-    fn read(&mut self, fd: ProcessFileDescriptor, offset: u64, buf: &mut [u8]) -> Result<usize>;
+    /// Read from the file open as `fd` into `buf`, then read up to `read_ahead` bytes past that
+    /// for the next read to use.
+    fn read(
+        &mut self,
+        fd: ProcessFileDescriptor,
+        offset: u64,
+        buf: &mut [u8],
+        read_ahead: usize,
+    ) -> Result<usize>;
     fn write(&mut self, fd: ProcessFileDescriptor, offset: u64, buf: &[u8]) -> Result<usize>;
+    /// Forget what's been read ahead in `inode`, if any of it is in `range`.
+    fn forget_read_ahead(&mut self, inode: INodeNum, range: Range<u64>);
     fn sync(&mut self) -> Result<()>;
     fn mkdir(&mut self, parent: INodeNum, name: &Path) -> Result<()>;
     fn can_be_safely_unmounted(&self) -> bool;
@@ -312,23 +518,40 @@ trait FileSystemManagerTrait: 'static + Send + Sync {
     /// Returns the number of bytes read.
     /// Advances `offset` past the directory entries read.
     ///
+    /// `offset` is an opaque cookie: the only valid values are 0, and ones returned by previous
+    /// calls (either through `offset` or the offset of an entry). Any other offset is treated as
+    /// the end of the directory, so nothing is read.
+    ///
+    /// If `sorted` is set, entries are returned in order of name, and `offset` is the number of
+    /// entries that come before the next one in that order. Unlike the default order, entries can
+    /// then be skipped or repeated if the directory changes between calls.
+    ///
     /// # Safety
     ///
-    /// entries must be valid for writing up to `size` bytes.
+    /// entries must be valid for writing up to `size` bytes, and aligned to `format`'s alignment.
     unsafe fn getdents(
         &mut self,
         dir: ProcessFileDescriptor,
         offset: &mut u64,
-        entries: *mut Dirent,
+        entries: *mut u8,
         size: usize,
+        format: DirentFormat,
+        sorted: bool,
     ) -> Result<usize>;
+    /// Get all the entries in a directory.
+    fn readdir(&mut self, dir: ProcessFileDescriptor) -> Result<Vec<OwnedDirEntry>>;
     fn ftruncate(&mut self, file: ProcessFileDescriptor, size: u64) -> Result<()>;
+    /// Truncate or extend a file to `size` bytes, whether or not it's open
+    fn truncate_direct(&mut self, inode: INodeNum, size: u64) -> Result<()>;
+    fn fallocate(&mut self, file: ProcessFileDescriptor, offset: u64, len: u64) -> Result<()>;
     /// increase reference count of inode (pretend there is an extra open file to it)
     fn inc_ref(&mut self, inode: INodeNum);
     /// decrease reference count of inode (pretend there is one fewer open file to it)
     fn dec_ref(&mut self, inode: INodeNum);
     /// Read bytes directly from a file
     fn read_direct(&mut self, inode: INodeNum, offset: u64, buf: &mut [u8]) -> Result<usize>;
+    /// Write bytes directly to a file, without growing it: anything past the end is dropped.
+    fn write_direct(&mut self, inode: INodeNum, offset: u64, buf: &[u8]) -> Result<usize>;
 }
 
 /// get parent directory and name of absolute path
@@ -368,11 +591,24 @@ impl<F: 'static + FileSystem> FileSystemManagerTrait for FileSystemManager<F> {
         let handle = self.fs.open(inode)?;
         self.open_file_handle(fd, handle)
     }
-    fn create(&mut self, parent: INodeNum, name: &Path, fd: ProcessFileDescriptor) -> Result<()> {
+    fn create(
+        &mut self,
+        parent: INodeNum,
+        name: &Path,
+        fd: ProcessFileDescriptor,
+        exclusive: bool,
+    ) -> Result<()> {
         if name.is_empty() || name == "." || name == ".." {
             // e.g. create("foo/"), create("foo/."), create("foo/..")
             return Err(Error::IsDirectory);
         }
+        if exclusive {
+            match self.lookup(parent, name) {
+                Ok(_) => return Err(Error::Exists),
+                Err(Error::NotFound) => {}
+                Err(e) => return Err(e),
+            }
+        }
         let mut dir = self.temp_open(parent)?;
         let file = self.fs.create(&mut dir.handle, name);
         self.temp_close(dir);
@@ -410,14 +646,51 @@ impl<F: 'static + FileSystem> FileSystemManagerTrait for FileSystemManager<F> {
         self.directories.insert(inode, Directory::empty(parent));
         Ok(())
     }
-    fn read(&mut self, fd: ProcessFileDescriptor, offset: u64, buf: &mut [u8]) -> Result<usize> {
+    fn read(
+        &mut self,
+        fd: ProcessFileDescriptor,
+        offset: u64,
+        buf: &mut [u8],
+        read_ahead: usize,
+    ) -> Result<usize> {
         let handle = self.open_files.get_mut(&fd).ok_or(Error::BadFd)?;
-        self.fs.read(handle, offset, buf)
+        let inode = handle.inode();
+        let cached = self
+            .read_ahead
+            .get(&inode)
+            .map_or(0, |ahead| ahead.copy_to(offset, buf));
+        if cached == buf.len() {
+            return Ok(cached);
+        }
+        let offset = offset + cached as u64;
+        let n = self.fs.read(handle, offset, &mut buf[cached..])?;
+        self.read_ahead.remove(&inode);
+        // Nothing's read ahead at the end of the file. If it can't be read now, it's read when
+        // it's asked for instead.
+        if read_ahead > 0 && cached + n == buf.len() {
+            let mut data = vec![0; read_ahead];
+            let offset = offset + n as u64;
+            if let Ok(len @ 1..) = self.fs.read(handle, offset, &mut data) {
+                data.truncate(len);
+                self.read_ahead.insert(inode, ReadAhead { offset, data });
+            }
+        }
+        Ok(cached + n)
     }
     fn write(&mut self, fd: ProcessFileDescriptor, offset: u64, buf: &[u8]) -> Result<usize> {
         let handle = self.open_files.get_mut(&fd).ok_or(Error::BadFd)?;
+        self.read_ahead.remove(&handle.inode());
         self.fs.write(handle, offset, buf)
     }
+    fn forget_read_ahead(&mut self, inode: INodeNum, range: Range<u64>) {
+        if self
+            .read_ahead
+            .get(&inode)
+            .is_some_and(|ahead| ahead.overlaps(&range))
+        {
+            self.read_ahead.remove(&inode);
+        }
+    }
     fn fstat(&mut self, fd: ProcessFileDescriptor) -> Result<FileInfo> {
         let handle = self.open_files.get(&fd).ok_or(Error::BadFd)?;
         self.fs.stat(handle)
@@ -528,7 +801,9 @@ impl<F: 'static + FileSystem> FileSystemManagerTrait for FileSystemManager<F> {
         let mut handle = temp_open(&mut self.fs, parent)?;
         let result = self.fs.unlink(&mut handle.handle, name);
         temp_close(&mut self.fs, handle, &self.open_file_count);
-        dir.remove(name);
+        if result.is_ok() {
+            dir.remove(name);
+        }
         result
     }
     fn rmdir(&mut self, parent: INodeNum, name: &Path) -> Result<()> {
@@ -536,15 +811,19 @@ impl<F: 'static + FileSystem> FileSystemManagerTrait for FileSystemManager<F> {
         let mut handle = temp_open(&mut self.fs, parent)?;
         let result = self.fs.rmdir(&mut handle.handle, name);
         temp_close(&mut self.fs, handle, &self.open_file_count);
-        dir.remove(name);
+        if result.is_ok() {
+            dir.remove(name);
+        }
         result
     }
     unsafe fn getdents(
         &mut self,
         dir: ProcessFileDescriptor,
         offset: &mut u64,
-        entries: *mut Dirent,
+        entries: *mut u8,
         size: usize,
+        format: DirentFormat,
+        sorted: bool,
     ) -> Result<usize> {
         let inode = self.open_files.get(&dir).ok_or(Error::BadFd)?.inode();
         // ensure directory entries are loaded
@@ -553,7 +832,18 @@ impl<F: 'static + FileSystem> FileSystemManagerTrait for FileSystemManager<F> {
         if dir.entries.is_none() {
             return Err(Error::IO("failed to read directory entries".into()));
         }
-        dir.getdents(offset, entries, size)
+        dir.getdents(offset, entries, size, format, sorted)
+    }
+    fn readdir(&mut self, dir: ProcessFileDescriptor) -> Result<Vec<OwnedDirEntry>> {
+        let inode = self.open_files.get(&dir).ok_or(Error::BadFd)?.inode();
+        // ensure directory entries are loaded
+        let _ = self.lookup(inode, "x");
+        let dir = self.directories.get(&inode).ok_or(Error::NotDirectory)?;
+        let entries = dir
+            .entries
+            .as_ref()
+            .ok_or_else(|| Error::IO("failed to read directory entries".into()))?;
+        Ok(entries.values().cloned().collect())
     }
     fn link(&mut self, source: INodeNum, parent: INodeNum, name: &Path) -> Result<()> {
         if name.is_empty() || name == "." || name == ".." {
@@ -609,8 +899,21 @@ impl<F: 'static + FileSystem> FileSystemManagerTrait for FileSystemManager<F> {
     }
     fn ftruncate(&mut self, fd: ProcessFileDescriptor, size: u64) -> Result<()> {
         let handle = self.open_files.get_mut(&fd).ok_or(Error::BadFd)?;
+        self.read_ahead.remove(&handle.inode());
         self.fs.truncate(handle, size)
     }
+    fn truncate_direct(&mut self, inode: INodeNum, size: u64) -> Result<()> {
+        self.read_ahead.remove(&inode);
+        let mut handle = self.temp_open(inode)?;
+        let result = self.fs.truncate(&mut handle.handle, size);
+        self.temp_close(handle);
+        result
+    }
+    fn fallocate(&mut self, fd: ProcessFileDescriptor, offset: u64, len: u64) -> Result<()> {
+        let handle = self.open_files.get_mut(&fd).ok_or(Error::BadFd)?;
+        self.read_ahead.remove(&handle.inode());
+        self.fs.fallocate(handle, offset, len)
+    }
     fn inc_ref(&mut self, inode: INodeNum) {
         match self.open_file_count.entry(inode) {
             BTreeMapEntry::Occupied(mut o) => {
@@ -636,6 +939,7 @@ impl<F: 'static + FileSystem> FileSystemManagerTrait for FileSystemManager<F> {
             None => {
                 // all open files to this inode have been closed
                 self.open_file_count.remove(&inode);
+                self.read_ahead.remove(&inode);
                 self.fs.release(inode);
             }
         }
@@ -671,6 +975,28 @@ impl<F: 'static + FileSystem> FileSystemManagerTrait for FileSystemManager<F> {
             }
         }
     }
+    fn write_direct(&mut self, inode: INodeNum, offset: u64, buf: &[u8]) -> Result<usize> {
+        self.read_ahead.remove(&inode);
+        let mut handle = self.temp_open(inode)?;
+        let result = self.fs.stat(&handle.handle).and_then(|info| {
+            let len = min(info.size.saturating_sub(offset), buf.len() as u64) as usize;
+            let mut bytes_written = 0;
+            while bytes_written < len {
+                let n = self.fs.write(
+                    &mut handle.handle,
+                    offset + bytes_written as u64,
+                    &buf[bytes_written..len],
+                )?;
+                if n == 0 {
+                    break;
+                }
+                bytes_written += n;
+            }
+            Ok(bytes_written)
+        });
+        self.temp_close(handle);
+        result
+    }
 }
 
 pub type FileSystemID = u16;
@@ -684,10 +1010,13 @@ enum OpenFile {
         inode: INodeNum,
         offset: u64,
         is_dir: bool,
+        /// How the file will be read, as last given to `posix_fadvise`: `POSIX_FADV_NORMAL`,
+        /// `POSIX_FADV_RANDOM` or `POSIX_FADV_SEQUENTIAL`
+        advice: i32,
     },
 
-    /// standard output
-    StdOut,
+    /// the console (`/dev/tty`)
+    Tty(Arc<Tty>),
     /// `/dev/null` (discards reads/writes)
     Null,
 
@@ -695,6 +1024,13 @@ enum OpenFile {
     PipeRead(PipeReadEnd),
     // Write end of a pipe
     PipeWrite(PipeWriteEnd),
+
+    /// an `epoll` instance
+    Epoll(Epoll),
+    /// a `signalfd`, for reading the process' pending signals
+    SignalFd(SignalFd),
+    /// a shared memory object
+    Shm(SharedMemory),
 }
 
 // wrapper around an array of filesystems for convenience
@@ -758,6 +1094,17 @@ pub struct RootFileSystem {
     file_systems: FileSystemList,
     root_mount: Option<FileSystemID>,
     open_files: BTreeMap<ProcessFileDescriptor, OpenFile>,
+    /// Shared memory objects, by the name they were created with by `shm_open`
+    shm_objects: BTreeMap<String, SharedMemory>,
+    /// Page caches of the files which are memory mapped somewhere
+    page_caches: BTreeMap<(FileSystemID, INodeNum), WeakPageCache>,
+    /// [`DevFS`] mounted by [`RootFileSystem::mount_dev`], and the console its `tty` refers to
+    dev: Option<(FileSystemID, Arc<Tty>)>,
+    /// Locks taken with `flock`, by the file they're on and then by the file descriptor holding
+    /// them. Files nobody has locked aren't in here.
+    file_locks: BTreeMap<(FileSystemID, INodeNum), BTreeMap<ProcessFileDescriptor, FileLock>>,
+    /// Threads waiting for a lock on each file to be released, which are woken when one is.
+    lock_waiters: BTreeMap<(FileSystemID, INodeNum), Vec<Tid>>,
 }
 
 impl RootFileSystem {
@@ -766,10 +1113,18 @@ impl RootFileSystem {
             file_systems: FileSystemList::new(),
             root_mount: None,
             open_files: BTreeMap::new(),
+            shm_objects: BTreeMap::new(),
+            page_caches: BTreeMap::new(),
+            dev: None,
+            file_locks: BTreeMap::new(),
+            lock_waiters: BTreeMap::new(),
         }
     }
+    /// Resolve `path`, where a relative path is relative to `cwd`, and an absolute one is
+    /// relative to `root` (the process' root directory). `..` never goes above `root`.
     fn resolve_path_relative_to(
         &mut self,
+        root: (FileSystemID, INodeNum),
         cwd: (FileSystemID, INodeNum),
         path: &Path,
         level_of_links: usize,
@@ -777,20 +1132,17 @@ impl RootFileSystem {
         if level_of_links > MAX_LEVEL_OF_LINKS {
             return Err(Error::TooManyLevelsOfLinks);
         }
-        let mut fs_id = self.root_mount.ok_or(Error::NotFound)?;
+        let (mut fs_id, mut inode) = if path.starts_with('/') { root } else { cwd };
         let mut fs_root = self.file_systems.get(fs_id).root();
-        let mut inode;
-        if path.starts_with('/') {
-            inode = fs_root;
-        } else {
-            (fs_id, inode) = cwd;
-            fs_root = self.file_systems.get(fs_id).root();
-        }
         let mut link_buf = [0; 256];
         for component in path.split('/') {
             if component.is_empty() || component == "." {
                 continue;
             }
+            if component == ".." && (fs_id, inode) == root {
+                // .. from the process' root is the root itself, even if it's been chrooted
+                continue;
+            }
             if component == ".." && inode == fs_root {
                 // .. from root of filesystem
                 // escape to parent filesystem, or do nothing if at /
@@ -816,6 +1168,7 @@ impl RootFileSystem {
                 }
                 Ok(link_dest) => {
                     (fs_id, inode) = self.resolve_path_relative_to(
+                        root,
                         (fs_id, inode),
                         link_dest.as_ref(),
                         level_of_links + 1,
@@ -832,14 +1185,49 @@ impl RootFileSystem {
         process: &ProcessControlBlock,
         path: &Path,
     ) -> Result<(FileSystemID, INodeNum)> {
-        self.resolve_path_relative_to(process.cwd, path, 0)
+        let root = self.process_root(process)?;
+        self.resolve_path_relative_to(root, process.cwd, path, 0)
+    }
+    /// Like [`Self::resolve_path`], but a relative `path` is relative to the directory open as
+    /// `dir` rather than the working directory (if `dir` isn't `None`).
+    fn resolve_path_at(
+        &mut self,
+        process: &ProcessControlBlock,
+        dir: Option<ProcessFileDescriptor>,
+        path: &Path,
+    ) -> Result<(FileSystemID, INodeNum)> {
+        let start = match dir {
+            Some(dir) if !path.starts_with('/') => {
+                let open_file = self.open_files.get(&dir).ok_or(Error::BadFd)?;
+                if !matches!(open_file, OpenFile::Regular { is_dir: true, .. }) {
+                    return Err(Error::NotDirectory);
+                }
+                self.inode_of(dir)?
+            }
+            _ => process.cwd,
+        };
+        let root = self.process_root(process)?;
+        self.resolve_path_relative_to(root, start, path, 0)
     }
     pub fn get_root(&self) -> Result<(FileSystemID, INodeNum)> {
         let root_fs = self.root_mount.ok_or(Error::NotFound)?;
         Ok((root_fs, self.file_systems.get(root_fs).root()))
     }
-    fn new_fd(&mut self, pid: Pid, file_info: OpenFile) -> Result<ProcessFileDescriptor> {
-        for fd in 0..MAX_OPEN_FILES as FileDescriptor {
+    /// The directory `/` refers to for `process`: where it's been chrooted to, if anywhere.
+    fn process_root(&self, process: &ProcessControlBlock) -> Result<(FileSystemID, INodeNum)> {
+        match process.root {
+            Some(root) => Ok(root),
+            None => self.get_root(),
+        }
+    }
+    /// Open `file_info` as the lowest free file descriptor of `pid` below `fd_limit`.
+    fn new_fd(
+        &mut self,
+        pid: Pid,
+        fd_limit: u16,
+        file_info: OpenFile,
+    ) -> Result<ProcessFileDescriptor> {
+        for fd in 0..fd_limit.min(MAX_OPEN_FILES) as FileDescriptor {
             let fd = ProcessFileDescriptor { pid, fd };
             if let alloc::collections::btree_map::Entry::Vacant(entry) = self.open_files.entry(fd) {
                 entry.insert(file_info);
@@ -862,6 +1250,24 @@ impl RootFileSystem {
         }
         result
     }
+    /// Mount a new file system of type `file_system_type` (`"fat"`, `"vsfs"`, `"tar"` or
+    /// `"tmpfs"`) at `path`. FAT, VSFS and tar file systems are read from `device`, while tmpfs
+    /// doesn't have one.
+    pub fn mount_device(
+        &mut self,
+        process: &ProcessControlBlock,
+        path: &Path,
+        file_system_type: &str,
+        device: Option<Block>,
+    ) -> Result<()> {
+        match (file_system_type, device) {
+            ("fat", Some(block)) => self.mount(process, path, FatFS::new(block)?),
+            ("vsfs", Some(block)) => self.mount(process, path, VSFS::new(block)?),
+            ("tar", Some(block)) => self.mount(process, path, TarFS::new(block)?),
+            ("tmpfs", None) => self.mount(process, path, TempFS::new()),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
     pub fn unmount(&mut self, process: &ProcessControlBlock, path: &Path) -> Result<()> {
         let (child_fs_id, _) = self.resolve_path(process, path)?;
         let Some((parent_fs_id, inode)) = self.file_systems.get(child_fs_id).mount_point() else {
@@ -889,16 +1295,55 @@ impl RootFileSystem {
         self.root_mount = Some(new_fs);
         Ok(())
     }
-    pub fn pipe(&mut self, pid: Pid) -> Result<(FileDescriptor, FileDescriptor)> {
+    /// Mount a [`DevFS`] at `/dev` (creating the directory if needed), whose `tty` is `tty`.
+    pub fn mount_dev(&mut self, tty: Arc<Tty>) -> Result<()> {
+        let (root_fs, root) = self.get_root()?;
+        let fs = self.file_systems.get_mut(root_fs);
+        let dir = match fs.lookup(root, "dev") {
+            Err(Error::NotFound) => {
+                fs.mkdir(root, "dev")?;
+                fs.lookup(root, "dev")?
+            }
+            result => result?,
+        };
+        let dev_fs = self.file_systems.add(DevFS, Some((root_fs, dir)))?;
+        if let Err(e) = self.file_systems.get_mut(root_fs).mount(dir, dev_fs) {
+            self.file_systems.remove(dev_fs);
+            return Err(e);
+        }
+        self.dev = Some((dev_fs, tty));
+        Ok(())
+    }
+    /// If `inode` is one of the devices in `/dev`, get the open file info for it.
+    fn device(&self, fs: FileSystemID, inode: INodeNum) -> Option<OpenFile> {
+        let (dev_fs, tty) = self.dev.as_ref()?;
+        if fs != *dev_fs {
+            return None;
+        }
+        match inode {
+            devfs::NULL_INODE => Some(OpenFile::Null),
+            devfs::TTY_INODE => Some(OpenFile::Tty(tty.clone())),
+            _ => None,
+        }
+    }
+    pub fn pipe(
+        &mut self,
+        process: &ProcessControlBlock,
+    ) -> Result<(FileDescriptor, FileDescriptor)> {
         let pipe_inner = Arc::new(PipeInner::default());
 
         // Ignoring the case where read_end succeeds but write_end fails for elegance.
         let read_end = self.new_fd(
-            pid,
+            process.pid,
+            process.fd_limit,
             OpenFile::PipeRead(PipeInner::read_end(pipe_inner.clone())),
         )?;
 
-        let write_end = self.new_fd(pid, OpenFile::PipeWrite(PipeInner::write_end(pipe_inner)))?;
+        let write_end = self.new_fd(
+            process.pid,
+            process.fd_limit,
+            OpenFile::PipeWrite(PipeInner::write_end(pipe_inner)),
+        )?;
 
         Ok((read_end.fd, write_end.fd))
     }
@@ -907,17 +1352,38 @@ impl RootFileSystem {
             self.file_systems.get_mut(*fs).inc_ref(*inode);
         }
     }
-    pub fn dup(&mut self, pid: Pid, fd: ProcessFileDescriptor) -> Result<FileDescriptor> {
+    pub fn dup(
+        &mut self,
+        process: &ProcessControlBlock,
+        fd: ProcessFileDescriptor,
+    ) -> Result<FileDescriptor> {
         let open_file = self.open_files.get_mut(&fd).ok_or(Error::BadFd)?;
 
         let new_file = open_file.clone();
         self.dup_inc_ref(&new_file);
 
-        Ok(self.new_fd(pid, new_file)?.fd)
+        Ok(self.new_fd(process.pid, process.fd_limit, new_file)?.fd)
     }
-    pub fn dup2(&mut self, fd: ProcessFileDescriptor, into: ProcessFileDescriptor) -> Result<()> {
+    /// Make `into` refer to the same file as `fd`, closing whatever it was before. `into` must be
+    /// below `process`' limit on open files.
+    pub fn dup2(
+        &mut self,
+        process: &ProcessControlBlock,
+        fd: ProcessFileDescriptor,
+        into: ProcessFileDescriptor,
+    ) -> Result<()> {
+        if fd == into {
+            return if self.open_files.contains_key(&fd) {
+                Ok(())
+            } else {
+                Err(Error::BadFd)
+            };
+        }
+        if !(0..process.fd_limit as FileDescriptor).contains(&into.fd) {
+            return Err(Error::BadFd);
+        }
         if self.open_files.contains_key(&into) {
-            self.close(fd).ok(); // errors are discarded
+            self.close(into).ok(); // errors are discarded
         }
 
         let open_file = self.open_files.get_mut(&fd).ok_or(Error::BadFd)?;
@@ -935,18 +1401,38 @@ impl RootFileSystem {
         process: &ProcessControlBlock,
         path: &Path,
         mode: Mode,
+    ) -> Result<FileDescriptor> {
+        self.open_at(process, None, path, mode)
+    }
+    /// Open a file, where a relative `path` is relative to the directory open as `dir`, or the
+    /// working directory if `dir` is `None` (as with `openat`).
+    pub fn open_at(
+        &mut self,
+        process: &ProcessControlBlock,
+        dir: Option<ProcessFileDescriptor>,
+        path: &Path,
+        mode: Mode,
     ) -> Result<FileDescriptor> {
         let (fs, inode) = match mode {
-            Mode::ReadWrite => self.resolve_path(process, path)?,
-            Mode::CreateReadWrite => self.resolve_path(process, dirname_of(path))?,
+            Mode::ReadWrite => self.resolve_path_at(process, dir, path)?,
+            Mode::CreateReadWrite | Mode::CreateExclusiveReadWrite => {
+                self.resolve_path_at(process, dir, dirname_of(path))?
+            }
         };
+        if let Mode::ReadWrite = mode {
+            if let Some(device) = self.device(fs, inode) {
+                return Ok(self.new_fd(process.pid, process.fd_limit, device)?.fd);
+            }
+        }
         let fd = self.new_fd(
             process.pid,
+            process.fd_limit,
             OpenFile::Regular {
                 fs,
                 inode,
                 offset: 0,
                 is_dir: false,
+                advice: POSIX_FADV_NORMAL,
             },
         )?;
         let fs = self.file_systems.get_mut(fs);
@@ -965,7 +1451,8 @@ impl RootFileSystem {
                     Ok(())
                 })
             }
-            Mode::CreateReadWrite => fs.create(inode, filename_of(path), fd),
+            Mode::CreateReadWrite => fs.create(inode, filename_of(path), fd, false),
+            Mode::CreateExclusiveReadWrite => fs.create(inode, filename_of(path), fd, true),
         };
         if let Err(e) = result {
             self.open_files.remove(&fd);
@@ -973,12 +1460,15 @@ impl RootFileSystem {
         }
         Ok(fd.fd)
     }
-    pub fn open_stdout(&mut self, pid: Pid) -> Result<FileDescriptor> {
-        let fd = self.new_fd(pid, OpenFile::StdOut)?;
+    /// Open the console, as if by opening `/dev/tty`, for the new process `pid`, which hasn't had
+    /// its limit on open files lowered.
+    pub fn open_tty(&mut self, pid: Pid) -> Result<FileDescriptor> {
+        let (_, tty) = self.dev.as_ref().ok_or(Error::NotFound)?;
+        let fd = self.new_fd(pid, MAX_OPEN_FILES, OpenFile::Tty(tty.clone()))?;
         Ok(fd.fd)
     }
     pub fn open_null(&mut self, pid: Pid) -> Result<FileDescriptor> {
-        let fd = self.new_fd(pid, OpenFile::Null)?;
+        let fd = self.new_fd(pid, MAX_OPEN_FILES, OpenFile::Null)?;
         Ok(fd.fd)
     }
     /// Close an open file
@@ -988,17 +1478,93 @@ impl RootFileSystem {
     pub fn close(&mut self, fd: ProcessFileDescriptor) -> Result<()> {
         let mut result = Ok(());
         let file_info = self.open_files.get(&fd).ok_or(Error::BadFd)?;
-        if let OpenFile::Regular { fs, .. } = file_info {
-            let fs = self.file_systems.get_mut(*fs);
+        if let OpenFile::Regular { fs, inode, .. } = *file_info {
+            self.unlock(fd, (fs, inode));
+            let fs = self.file_systems.get_mut(fs);
             result = fs.close(fd);
         }
         // don't need to do anything for non-regular files
         self.open_files.remove(&fd);
         result
     }
+    /// Take the advisory lock `lock` on the file open as `fd`, in place of any lock `fd` already
+    /// holds, or release its lock if `lock` is `None`.
+    ///
+    /// Fails with [`Error::WouldBlock`] if another file descriptor holds a conflicting lock, in
+    /// which case `fd` keeps the lock it had. Each file descriptor has its own lock, even ones
+    /// made with `dup`, which is released when it's closed.
+    pub fn flock(&mut self, fd: ProcessFileDescriptor, lock: Option<FileLock>) -> Result<()> {
+        let file = match self.open_files.get(&fd).ok_or(Error::BadFd)? {
+            OpenFile::Regular { fs, inode, .. } => (*fs, *inode),
+            _ => return Err(Error::InvalidArgument),
+        };
+        let Some(lock) = lock else {
+            self.unlock(fd, file);
+            return Ok(());
+        };
+        let locks = self.file_locks.entry(file).or_default();
+        let conflict = locks.iter().any(|(&holder, &held)| {
+            holder != fd && (lock == FileLock::Exclusive || held == FileLock::Exclusive)
+        });
+        if conflict {
+            return Err(Error::WouldBlock);
+        }
+        // waiters for a shared lock can have one once an exclusive lock is downgraded
+        if locks.insert(fd, lock) == Some(FileLock::Exclusive) && lock == FileLock::Shared {
+            self.wake_lock_waiters(file);
+        }
+        Ok(())
+    }
+    /// Like [`Self::flock`], but if it fails with [`Error::WouldBlock`], the thread `tid` is
+    /// woken with `thread_wakeup` the next time a lock on the file is released, to try again.
+    pub fn flock_or_queue(
+        &mut self,
+        fd: ProcessFileDescriptor,
+        lock: FileLock,
+        tid: Tid,
+    ) -> Result<()> {
+        let result = self.flock(fd, Some(lock));
+        if let Err(Error::WouldBlock) = result {
+            let file = self.inode_of(fd)?;
+            let waiters = self.lock_waiters.entry(file).or_default();
+            if !waiters.contains(&tid) {
+                waiters.push(tid);
+            }
+        }
+        result
+    }
+    /// Release any lock `fd` holds on `file`, waking the threads waiting for one to be released.
+    fn unlock(&mut self, fd: ProcessFileDescriptor, file: (FileSystemID, INodeNum)) {
+        let Some(locks) = self.file_locks.get_mut(&file) else {
+            return;
+        };
+        if locks.remove(&fd).is_none() {
+            return;
+        }
+        if locks.is_empty() {
+            self.file_locks.remove(&file);
+        }
+        self.wake_lock_waiters(file);
+    }
+    /// Wake the threads waiting for a lock on `file` to be released, to try again.
+    fn wake_lock_waiters(&mut self, file: (FileSystemID, INodeNum)) {
+        for tid in self.lock_waiters.remove(&file).unwrap_or_default() {
+            thread_wakeup(tid);
+        }
+    }
     pub fn mkdir(&mut self, process: &ProcessControlBlock, path: &Path) -> Result<()> {
+        self.mkdir_at(process, None, path)
+    }
+    /// Like [`Self::mkdir`], where a relative `path` is relative to the directory open as `dir`
+    /// (as with `mkdirat`).
+    pub fn mkdir_at(
+        &mut self,
+        process: &ProcessControlBlock,
+        dir: Option<ProcessFileDescriptor>,
+        path: &Path,
+    ) -> Result<()> {
         let (parent, name) = dirname_and_filename(path);
-        let (fs, parent) = self.resolve_path(process, parent)?;
+        let (fs, parent) = self.resolve_path_at(process, dir, parent)?;
         let fs = self.file_systems.get_mut(fs);
         fs.mkdir(parent, name)
     }
@@ -1014,7 +1580,11 @@ impl RootFileSystem {
         let file_info = file_system.open_files.get_mut(&fd).ok_or(Error::BadFd)?;
         match file_info {
             OpenFile::Regular {
-                fs, offset, is_dir, ..
+                fs,
+                offset,
+                is_dir,
+                advice,
+                ..
             } => {
                 let fs = *fs;
 
@@ -1022,13 +1592,23 @@ impl RootFileSystem {
                     return Err(Error::IsDirectory);
                 }
                 let fs = file_system.file_systems.get_mut(fs);
-                let read_count = fs.read(fd, *offset, buf)?;
+                let read_count = fs.read(fd, *offset, buf, read_ahead_for(*advice))?;
                 *offset += read_count as u64;
                 Ok(read_count)
             }
-            OpenFile::StdOut => {
-                // shouldn't read from stdout
-                Err(Error::BadFd)
+            OpenFile::Tty(tty) => {
+                let tty = tty.clone();
+
+                drop(file_system_guard); // don't hold the mutex while waiting for input
+
+                tty.read(buf)
+            }
+            OpenFile::SignalFd(signalfd) => {
+                let signalfd = signalfd.clone();
+
+                drop(file_system_guard); // don't hold the mutex while waiting for a signal
+
+                signalfd.read(buf)
             }
             OpenFile::PipeRead(pipe) => {
                 let inner = pipe.0.clone();
@@ -1067,8 +1647,8 @@ impl RootFileSystem {
                     }
                 }
             }
-            OpenFile::PipeWrite(_) => {
-                // Not open for writing.
+            OpenFile::PipeWrite(_) | OpenFile::Epoll(_) | OpenFile::Shm(_) => {
+                // Not open for reading.
                 Err(Error::BadFd)
             }
             OpenFile::Null => Ok(0),
@@ -1091,20 +1671,17 @@ impl RootFileSystem {
                 *offset += write_count as u64;
                 Ok(write_count)
             }
-            OpenFile::StdOut => {
-                use core::fmt::Write;
-                let string = String::from_utf8_lossy(buf);
-                // SAFETY: no other mut references to VIDEO_MEMORY_WRITER here
-                let result = unsafe {
-                    kidneyos_shared::video_memory::VIDEO_MEMORY_WRITER.write_str(&string)
-                };
-                if let Err(e) = result {
-                    Err(Error::IO(format!("{e}")))
-                } else {
-                    Ok(buf.len())
-                }
+            OpenFile::Tty(tty) => {
+                let tty = tty.clone();
+
+                drop(file_system_guard);
+
+                tty.write(buf)
             }
-            OpenFile::PipeRead(_) => {
+            OpenFile::PipeRead(_)
+            | OpenFile::Epoll(_)
+            | OpenFile::SignalFd(_)
+            | OpenFile::Shm(_) => {
                 // Not open for writing
                 Err(Error::BadFd)
             }
@@ -1174,13 +1751,11 @@ impl RootFileSystem {
     ///
     /// Panics if the file descriptors 0, 1, 2 are already in use for pid.
     pub fn open_standard_fds(&mut self, pid: Pid) {
-        // for now, ignore stdin (we don't have keyboard input set up yet)
-        let stdin = self.open_null(pid).unwrap();
+        let stdin = self.open_tty(pid).unwrap();
         assert_eq!(stdin, 0);
-        let stdout = self.open_stdout(pid).unwrap();
+        let stdout = self.open_tty(pid).unwrap();
         assert_eq!(stdout, 1);
-        // stderr and stdout can just go to the same place for now
-        let stderr = self.open_stdout(pid).unwrap();
+        let stderr = self.open_tty(pid).unwrap();
         assert_eq!(stderr, 2);
     }
     pub fn chdir(&mut self, process: &mut ProcessControlBlock, path: &Path) -> Result<()> {
@@ -1225,6 +1800,29 @@ impl RootFileSystem {
         }
         Ok(())
     }
+    /// Change the directory `/` refers to for `process` to `path`, which `..` can't go above.
+    ///
+    /// The working directory moves to the new root too, so it can't be used to escape.
+    pub fn chroot(&mut self, process: &mut ProcessControlBlock, path: &Path) -> Result<()> {
+        let (fs_id, inode) = self.resolve_path(process, path)?;
+        let fs = self.file_systems.get_mut(fs_id);
+        if fs.inode_type(inode)? != INodeType::Directory {
+            return Err(Error::NotDirectory);
+        }
+        // hold a reference to the new root, like the cwd
+        fs.inc_ref(inode);
+        if let Some((prev_fs, prev_inode)) = process.root.replace((fs_id, inode)) {
+            self.file_systems.get_mut(prev_fs).dec_ref(prev_inode);
+        }
+        if process.cwd_path != "/" {
+            // decrement reference count to previous cwd
+            let (prev_fs, prev_inode) = process.cwd;
+            self.file_systems.get_mut(prev_fs).dec_ref(prev_inode);
+        }
+        process.cwd = (fs_id, inode);
+        process.cwd_path = "/".into();
+        Ok(())
+    }
     pub fn fstat(&mut self, fd: ProcessFileDescriptor) -> Result<FileInfo> {
         let file = self.open_files.get_mut(&fd).ok_or(Error::BadFd)?;
         if let OpenFile::Regular { fs, .. } = file {
@@ -1233,20 +1831,177 @@ impl RootFileSystem {
             Err(Error::NotFound)
         }
     }
-    pub fn unlink(&mut self, process: &ProcessControlBlock, path: &Path) -> Result<()> {
-        let (dirname, filename) = dirname_and_filename(path);
-        let (fs_id, inode) = self.resolve_path(process, dirname)?;
-        self.file_systems.get_mut(fs_id).unlink(inode, filename)
+    /// Open the shared memory object called `name`, creating it (with a size of 0) if `create` is
+    /// true and it doesn't exist yet.
+    pub fn shm_open(
+        &mut self,
+        process: &ProcessControlBlock,
+        name: &str,
+        create: bool,
+    ) -> Result<FileDescriptor> {
+        let shm = match self.shm_objects.get(name) {
+            Some(shm) => shm.clone(),
+            None if create => {
+                let shm = SharedMemory::default();
+                self.shm_objects.insert(name.into(), shm.clone());
+                shm
+            }
+            None => return Err(Error::NotFound),
+        };
+        let fd = self.new_fd(process.pid, process.fd_limit, OpenFile::Shm(shm))?;
+        Ok(fd.fd)
     }
-    pub fn rmdir(&mut self, process: &ProcessControlBlock, path: &Path) -> Result<()> {
-        let (dirname, filename) = dirname_and_filename(path);
-        let (fs_id, inode) = self.resolve_path(process, dirname)?;
-        self.file_systems.get_mut(fs_id).rmdir(inode, filename)
+    /// Remove the name of the shared memory object called `name`. The object itself stays around
+    /// while it's open or mapped anywhere.
+    pub fn shm_unlink(&mut self, name: &str) -> Result<()> {
+        self.shm_objects.remove(name).ok_or(Error::NotFound)?;
+        Ok(())
     }
-    pub fn link(
+    /// Create an `epoll` instance, which isn't waiting for any files yet.
+    pub fn epoll_create(&mut self, process: &ProcessControlBlock) -> Result<FileDescriptor> {
+        let fd = self.new_fd(
+            process.pid,
+            process.fd_limit,
+            OpenFile::Epoll(Epoll::default()),
+        )?;
+        Ok(fd.fd)
+    }
+    /// Create a `signalfd`, which can be read to take the signals in `mask` from `signals`, the
+    /// pending signals of `process`. Reads fail rather than wait if `nonblocking` is set.
+    pub fn signalfd_create(
         &mut self,
         process: &ProcessControlBlock,
-        source: &Path,
+        signals: PendingSignals,
+        mask: SigSet,
+        nonblocking: bool,
+    ) -> Result<FileDescriptor> {
+        let signalfd = SignalFd::new(signals, mask, nonblocking);
+        let fd = self.new_fd(process.pid, process.fd_limit, OpenFile::SignalFd(signalfd))?;
+        Ok(fd.fd)
+    }
+    /// Change the files the `epoll` instance open as `epfd` is waiting for, as with `epoll_ctl`.
+    ///
+    /// `fd` is a file descriptor of the same process. Waiting for another `epoll` instance isn't
+    /// supported.
+    pub fn epoll_ctl(
+        &mut self,
+        epfd: ProcessFileDescriptor,
+        op: EpollOp,
+        fd: FileDescriptor,
+    ) -> Result<()> {
+        let Some(OpenFile::Epoll(epoll)) = self.open_files.get(&epfd) else {
+            return Err(Error::BadFd);
+        };
+        let target = ProcessFileDescriptor { pid: epfd.pid, fd };
+        match self.open_files.get(&target).ok_or(Error::BadFd)? {
+            OpenFile::Epoll(_) => Err(Error::Unsupported),
+            _ => epoll.update(fd, op),
+        }
+    }
+    /// Get the events (`EPOLLIN`, `EPOLLOUT`, ...) which are ready for `fd`, that is, which
+    /// operations wouldn't wait.
+    fn readiness(&self, fd: ProcessFileDescriptor) -> Result<u32> {
+        Ok(match self.open_files.get(&fd).ok_or(Error::BadFd)? {
+            // files on disk never make anyone wait
+            OpenFile::Regular { .. } | OpenFile::Null | OpenFile::Shm(_) => EPOLLIN | EPOLLOUT,
+            OpenFile::Tty(tty) if tty.has_input() => EPOLLIN | EPOLLOUT,
+            OpenFile::Tty(_) => EPOLLOUT,
+            OpenFile::PipeRead(pipe) => {
+                let mut events = 0;
+                if pipe.0.is_readable() {
+                    events |= EPOLLIN;
+                }
+                if pipe.0.write_ends.load(Ordering::SeqCst) == 0 {
+                    events |= EPOLLHUP;
+                }
+                events
+            }
+            OpenFile::PipeWrite(pipe) => {
+                // pipes never fill up, so writes never wait
+                if pipe.0.read_ends.load(Ordering::SeqCst) == 0 {
+                    EPOLLOUT | EPOLLERR
+                } else {
+                    EPOLLOUT
+                }
+            }
+            OpenFile::Epoll(_) => 0,
+            OpenFile::SignalFd(signalfd) if signalfd.is_readable() => EPOLLIN,
+            OpenFile::SignalFd(_) => 0,
+        })
+    }
+    /// Fill `events` with the files the `epoll` instance open as `epfd` is waiting for which are
+    /// ready, without waiting for any. Returns how many there were.
+    ///
+    /// Files which have been closed since they were added are ignored.
+    pub fn epoll_ready(
+        &self,
+        epfd: ProcessFileDescriptor,
+        events: &mut [EpollEvent],
+    ) -> Result<usize> {
+        let Some(OpenFile::Epoll(epoll)) = self.open_files.get(&epfd) else {
+            return Err(Error::BadFd);
+        };
+        let mut count = 0;
+        for (fd, wanted) in epoll.interest() {
+            if count == events.len() {
+                break;
+            }
+            let Ok(ready) = self.readiness(ProcessFileDescriptor { pid: epfd.pid, fd }) else {
+                continue;
+            };
+            // errors and hangups are reported whether they were asked for or not
+            let ready = ready & (wanted.events | EPOLLERR | EPOLLHUP);
+            if ready != 0 {
+                events[count] = EpollEvent {
+                    events: ready,
+                    data: wanted.data,
+                };
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+    /// Get the terminal that `fd` refers to, or `None` if it isn't a terminal.
+    pub fn tty(&self, fd: ProcessFileDescriptor) -> Result<Option<Arc<Tty>>> {
+        match self.open_files.get(&fd).ok_or(Error::BadFd)? {
+            OpenFile::Tty(tty) => Ok(Some(tty.clone())),
+            _ => Ok(None),
+        }
+    }
+    pub fn unlink(&mut self, process: &ProcessControlBlock, path: &Path) -> Result<()> {
+        self.unlink_at(process, None, path)
+    }
+    /// Like [`Self::unlink`], where a relative `path` is relative to the directory open as `dir`
+    /// (as with `unlinkat`).
+    pub fn unlink_at(
+        &mut self,
+        process: &ProcessControlBlock,
+        dir: Option<ProcessFileDescriptor>,
+        path: &Path,
+    ) -> Result<()> {
+        let (dirname, filename) = dirname_and_filename(path);
+        let (fs_id, inode) = self.resolve_path_at(process, dir, dirname)?;
+        self.file_systems.get_mut(fs_id).unlink(inode, filename)
+    }
+    pub fn rmdir(&mut self, process: &ProcessControlBlock, path: &Path) -> Result<()> {
+        self.rmdir_at(process, None, path)
+    }
+    /// Like [`Self::rmdir`], where a relative `path` is relative to the directory open as `dir`
+    /// (as with `unlinkat(AT_REMOVEDIR)`).
+    pub fn rmdir_at(
+        &mut self,
+        process: &ProcessControlBlock,
+        dir: Option<ProcessFileDescriptor>,
+        path: &Path,
+    ) -> Result<()> {
+        let (dirname, filename) = dirname_and_filename(path);
+        let (fs_id, inode) = self.resolve_path_at(process, dir, dirname)?;
+        self.file_systems.get_mut(fs_id).rmdir(inode, filename)
+    }
+    pub fn link(
+        &mut self,
+        process: &ProcessControlBlock,
+        source: &Path,
         dest: &Path,
     ) -> Result<()> {
         let (source_fs, inode) = self.resolve_path(process, source)?;
@@ -1263,13 +2018,41 @@ impl RootFileSystem {
         process: &ProcessControlBlock,
         source: &Path,
         dest: &Path,
+    ) -> Result<()> {
+        self.symlink_at(process, source, None, dest)
+    }
+    /// Like [`Self::symlink`], where a relative `dest` is relative to the directory open as `dir`
+    /// (as with `symlinkat`). `source` is stored as is.
+    pub fn symlink_at(
+        &mut self,
+        process: &ProcessControlBlock,
+        source: &Path,
+        dir: Option<ProcessFileDescriptor>,
+        dest: &Path,
     ) -> Result<()> {
         let (dest_dirname, dest_filename) = dirname_and_filename(dest);
-        let (parent_fs, parent_inode) = self.resolve_path(process, dest_dirname)?;
+        let (parent_fs, parent_inode) = self.resolve_path_at(process, dir, dest_dirname)?;
         self.file_systems
             .get_mut(parent_fs)
             .symlink(source, parent_inode, dest_filename)
     }
+    /// Get the destination of the symlink at `path`, where a relative `path` is relative to the
+    /// directory open as `dir`, or the working directory if `dir` is `None` (as with
+    /// `readlinkat`). Fails with [`Error::NotLink`] if it isn't a symlink.
+    pub fn read_link_at(
+        &mut self,
+        process: &ProcessControlBlock,
+        dir: Option<ProcessFileDescriptor>,
+        path: &Path,
+    ) -> Result<OwnedPath> {
+        let (dirname, filename) = dirname_and_filename(path);
+        let (fs_id, parent) = self.resolve_path_at(process, dir, dirname)?;
+        let fs = self.file_systems.get_mut(fs_id);
+        // the link itself, so don't follow it like resolve_path would
+        let inode = fs.lookup(parent, filename)?;
+        let mut buf = [0; 256];
+        Ok(fs.read_link(inode, &mut buf)?.into_owned())
+    }
     pub fn rename(
         &mut self,
         process: &ProcessControlBlock,
@@ -1308,6 +2091,9 @@ impl RootFileSystem {
     ///
     /// Returns the number of bytes read.
     ///
+    /// The directory offset can only be `lseek`ed to 0 or to the offset of an entry returned by
+    /// an earlier call. After seeking anywhere else, nothing is read.
+    ///
     /// # Safety
     ///
     /// `output` must be valid for writing up to `size` bytes.
@@ -1316,6 +2102,49 @@ impl RootFileSystem {
         fd: ProcessFileDescriptor,
         output: *mut Dirent,
         size: usize,
+    ) -> Result<usize> {
+        self.getdents_with_format(fd, output.cast(), size, DirentFormat::Dirent, false)
+    }
+
+    /// Like [`Self::getdents`], but writes [`Dirent64`]s.
+    ///
+    /// # Safety
+    ///
+    /// `output` must be valid for writing up to `size` bytes.
+    pub unsafe fn getdents64(
+        &mut self,
+        fd: ProcessFileDescriptor,
+        output: *mut Dirent64,
+        size: usize,
+    ) -> Result<usize> {
+        self.getdents_with_format(fd, output.cast(), size, DirentFormat::Dirent64, false)
+    }
+
+    /// Like [`Self::getdents64`], but returns entries sorted by name. The `d_off` of each entry
+    /// is its position in that order, which can be passed to `lseek` to continue from it.
+    ///
+    /// # Safety
+    ///
+    /// `output` must be valid for writing up to `size` bytes.
+    pub unsafe fn getdents64_sorted(
+        &mut self,
+        fd: ProcessFileDescriptor,
+        output: *mut Dirent64,
+        size: usize,
+    ) -> Result<usize> {
+        self.getdents_with_format(fd, output.cast(), size, DirentFormat::Dirent64, true)
+    }
+
+    /// # Safety
+    ///
+    /// `output` must be valid for writing up to `size` bytes, and aligned to `format`'s alignment.
+    unsafe fn getdents_with_format(
+        &mut self,
+        fd: ProcessFileDescriptor,
+        output: *mut u8,
+        size: usize,
+        format: DirentFormat,
+        sorted: bool,
     ) -> Result<usize> {
         let file_info = self.open_files.get_mut(&fd).ok_or(Error::BadFd)?;
         match file_info {
@@ -1326,13 +2155,23 @@ impl RootFileSystem {
                 ..
             } => {
                 let fs = self.file_systems.get_mut(*fs);
-                let read_count = fs.getdents(fd, offset, output, size)?;
+                let read_count = fs.getdents(fd, offset, output, size, format, sorted)?;
                 Ok(read_count)
             }
             _ => Err(Error::NotDirectory),
         }
     }
 
+    /// Get all the entries in the open directory `fd`, for use within the kernel.
+    pub fn readdir(&mut self, fd: ProcessFileDescriptor) -> Result<Vec<OwnedDirEntry>> {
+        match self.open_files.get(&fd).ok_or(Error::BadFd)? {
+            OpenFile::Regular {
+                fs, is_dir: true, ..
+            } => self.file_systems.get_mut(*fs).readdir(fd),
+            _ => Err(Error::NotDirectory),
+        }
+    }
+
     pub fn ftruncate(&mut self, fd: ProcessFileDescriptor, size: u64) -> Result<()> {
         let file_info = self.open_files.get_mut(&fd).ok_or(Error::BadFd)?;
         match file_info {
@@ -1348,15 +2187,100 @@ impl RootFileSystem {
                 let fs = self.file_systems.get_mut(*fs);
                 fs.ftruncate(fd, size)
             }
+            OpenFile::Shm(shm) => {
+                shm.set_size(size);
+                Ok(())
+            }
             _ => Err(Error::IO("can't truncate special file".into())),
         }
     }
 
+    /// Truncate or extend the file at `path` to `size` bytes, like [`Self::ftruncate`] without
+    /// needing it to be open. Offsets of file descriptors it's open as are left alone.
+    pub fn truncate(
+        &mut self,
+        process: &ProcessControlBlock,
+        path: &Path,
+        size: u64,
+    ) -> Result<()> {
+        let (fs_id, inode) = self.resolve_path(process, path)?;
+        let fs = self.file_systems.get_mut(fs_id);
+        if fs.inode_type(inode)? == INodeType::Directory {
+            return Err(Error::IsDirectory);
+        }
+        fs.truncate_direct(inode, size)
+    }
+
+    /// Allocate space for `len` bytes of the file open as `fd`, starting at `offset`.
+    pub fn fallocate(&mut self, fd: ProcessFileDescriptor, offset: u64, len: u64) -> Result<()> {
+        match self.open_files.get(&fd).ok_or(Error::BadFd)? {
+            OpenFile::Regular { is_dir: true, .. } => Err(Error::IsDirectory),
+            OpenFile::Regular { fs, .. } => {
+                let fs = self.file_systems.get_mut(*fs);
+                fs.fallocate(fd, offset, len)
+            }
+            _ => Err(Error::IO("can't allocate space for special file".into())),
+        }
+    }
+    /// Act on `posix_fadvise` advice for the `len` bytes at `offset` in the file open as `fd`,
+    /// which has to be a regular file. A `len` of 0 means up to the end of the file.
+    ///
+    /// How the file will be read is remembered for the whole file, and decides how far reads of
+    /// it, and faults in mappings of it, read ahead: `POSIX_FADV_SEQUENTIAL` reads further ahead,
+    /// and `POSIX_FADV_RANDOM` doesn't read ahead at all. `POSIX_FADV_DONTNEED` forgets what's
+    /// been read ahead in the range, including pages cached for mappings which haven't been
+    /// mapped yet. Pages which are mapped are still in use, so they're kept. Other advice is
+    /// accepted, but there's nothing for it to do.
+    pub fn fadvise(
+        &mut self,
+        fd: ProcessFileDescriptor,
+        offset: u64,
+        len: u64,
+        advice: i32,
+    ) -> Result<()> {
+        let OpenFile::Regular {
+            fs,
+            inode,
+            advice: file_advice,
+            ..
+        } = self.open_files.get_mut(&fd).ok_or(Error::BadFd)?
+        else {
+            return Err(Error::IllegalSeek);
+        };
+        match advice {
+            POSIX_FADV_NORMAL | POSIX_FADV_RANDOM | POSIX_FADV_SEQUENTIAL => *file_advice = advice,
+            POSIX_FADV_DONTNEED => {
+                let key = (*fs, *inode);
+                let end = match len {
+                    0 => u64::MAX,
+                    len => offset.saturating_add(len),
+                };
+                self.file_systems
+                    .get_mut(key.0)
+                    .forget_read_ahead(key.1, offset..end);
+                match self.page_caches.get(&key).map(WeakPageCache::upgrade) {
+                    // only pages wholly in the range
+                    Some(Some(cache)) => cache.evict(
+                        offset.div_ceil(PAGE_FRAME_SIZE as u64) as usize
+                            ..(end / PAGE_FRAME_SIZE as u64) as usize,
+                    ),
+                    Some(None) => {
+                        self.page_caches.remove(&key);
+                    }
+                    None => {}
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
     /// Close all open files belonging to process
     ///
     /// This should be called when the process exits/is killed.
     /// All errors that occur while closing files are ignored.
-    pub fn close_all(&mut self, pid: Pid) {
+    pub fn close_all(&mut self, pcb: &ProcessControlBlock) {
+        let pid = pcb.pid;
         let fds: Vec<FileDescriptor> = self
             .open_files
             .keys()
@@ -1365,17 +2289,35 @@ impl RootFileSystem {
         for fd in fds {
             let _ = self.close(ProcessFileDescriptor { pid, fd });
         }
-        if let Some(pcb) = unwrap_system().process.table.get(pid) {
-            // decrement reference count to cwd
-            let pcb = pcb.lock();
+        self.release_cwd_and_root(pcb);
+        for (_addr, vma) in pcb.vmas.iter() {
+            if let VMAInfo::MMap { fs, inode, .. } = vma.info() {
+                // decrease reference count to inode to let it be released.
+                self.file_systems.get_mut(*fs).dec_ref(*inode);
+            }
+        }
+    }
+
+    /// Take references to `pcb`'s cwd and root, which it has inherited from another process.
+    pub fn hold_cwd_and_root(&mut self, pcb: &ProcessControlBlock) {
+        // as in chdir, the cwd only holds a reference when it isn't the root
+        if pcb.cwd_path != "/" {
+            let (cwd_fs, cwd_inode) = pcb.cwd;
+            self.file_systems.get_mut(cwd_fs).inc_ref(cwd_inode);
+        }
+        if let Some((root_fs, root_inode)) = pcb.root {
+            self.file_systems.get_mut(root_fs).inc_ref(root_inode);
+        }
+    }
+
+    /// Drop the references `pcb` holds to its cwd and root, as it exits.
+    fn release_cwd_and_root(&mut self, pcb: &ProcessControlBlock) {
+        if pcb.cwd_path != "/" {
             let (cwd_fs, cwd_inode) = pcb.cwd;
             self.file_systems.get_mut(cwd_fs).dec_ref(cwd_inode);
-            for (_addr, vma) in pcb.vmas.iter() {
-                if let VMAInfo::MMap { fs, inode, .. } = vma.info() {
-                    // decrease reference count to inode to let it be released.
-                    self.file_systems.get_mut(*fs).dec_ref(*inode);
-                }
-            }
+        }
+        if let Some((root_fs, root_inode)) = pcb.root {
+            self.file_systems.get_mut(root_fs).dec_ref(root_inode);
         }
     }
 
@@ -1397,6 +2339,24 @@ impl RootFileSystem {
         self.file_systems.get_mut(fs_id).dec_ref(inode);
     }
 
+    /// Read the whole of the regular file open as `fd`, without changing its offset.
+    pub fn read_to_end(&mut self, fd: ProcessFileDescriptor) -> Result<Vec<u8>> {
+        if let Some(OpenFile::Regular { is_dir: true, .. }) = self.open_files.get(&fd) {
+            return Err(Error::IsDirectory);
+        }
+        let (fs, inode) = self.inode_of(fd)?;
+        let mut data = vec![];
+        loop {
+            let bytes_read = data.len();
+            data.resize(bytes_read + 4096, 0);
+            let n = self.read_direct(fs, inode, bytes_read as u64, &mut data[bytes_read..])?;
+            data.truncate(bytes_read + n);
+            if n == 0 {
+                return Ok(data);
+            }
+        }
+    }
+
     /// Read bytes directly from a file using its filesystem ID and inode number.
     pub fn read_direct(
         &mut self,
@@ -1410,73 +2370,94 @@ impl RootFileSystem {
             .read_direct(inode, offset, buffer)
     }
 
-    /// Map file by inode into memory
-    ///
-    /// Returns `Ok(false)` if there is already something mapped in `addr..addr + length`
-    pub fn mmap_inode(
+    /// Write bytes directly to a file using its filesystem ID and inode number. The file isn't
+    /// grown, so only the part of `buffer` which lies within it is written.
+    pub fn write_direct(
         &mut self,
-        addr: usize,
         fs_id: FileSystemID,
         inode: INodeNum,
-        length: usize,
-        offset_in_pages: u32,
-        writeable: bool,
-    ) -> Result<bool> {
-        // increase reference count to ensure that file data is kept around even if file is unlinked and all descriptors are closed.
-        self.file_systems.get_mut(fs_id).inc_ref(inode);
-        let pcb = running_process();
-        let mut pcb = pcb.lock();
-        Ok(pcb.vmas.add_vma(
-            VMA::new(
-                VMAInfo::MMap {
-                    fs: fs_id,
-                    inode,
-                    offset: offset_in_pages,
-                },
-                length,
-                writeable,
-            ),
-            addr,
-        ))
+        offset: u64,
+        buffer: &[u8],
+    ) -> Result<usize> {
+        self.file_systems
+            .get_mut(fs_id)
+            .write_direct(inode, offset, buffer)
     }
 
-    /// Map file into memory
-    ///
-    /// Returns `Ok(false)` if the requested address range is unavailable.
+    /// Get the page cache of the file `inode`, which is shared by every mapping of it, creating it
+    /// if the file isn't mapped anywhere yet.
+    fn page_cache(&mut self, fs: FileSystemID, inode: INodeNum) -> PageCache {
+        if let Some(cache) = self
+            .page_caches
+            .get(&(fs, inode))
+            .and_then(WeakPageCache::upgrade)
+        {
+            return cache;
+        }
+        // forget the caches of files which aren't mapped any more
+        self.page_caches
+            .retain(|_, cache| cache.upgrade().is_some());
+        let cache = PageCache::new(fs, inode);
+        self.page_caches.insert((fs, inode), cache.downgrade());
+        cache
+    }
+
+    /// Make a mapping of the file open as `fd`, to be added to a process' VMAs. If `shared` is
+    /// set, changes are written back to the file.
     pub fn mmap_file(
         &mut self,
-        addr: usize,
         fd: ProcessFileDescriptor,
         length: usize,
         offset: i64,
         writeable: bool,
-    ) -> Result<bool> {
+        shared: bool,
+    ) -> Result<VMA> {
         let offset = u64::try_from(offset).map_err(|_| Error::BadOffset)?;
-        let (fs, inode) = self.inode_of(fd)?;
         let offset_in_pages: u32 = (offset / PAGE_FRAME_SIZE as u64)
             .try_into()
             .map_err(|_| Error::BadOffset)?;
-        self.mmap_inode(addr, fs, inode, length, offset_in_pages, writeable)
+        if let Some(OpenFile::Shm(shm)) = self.open_files.get(&fd) {
+            let info = VMAInfo::Shm {
+                shm: shm.clone(),
+                offset: offset_in_pages,
+            };
+            return Ok(VMA::new(info, length, writeable));
+        }
+        let (fs, inode) = self.inode_of(fd)?;
+        // Otherwise the mapping could reach any page number, however far past the end it is.
+        if offset > self.fstat(fd)?.size {
+            return Err(Error::BadOffset);
+        }
+        let read_ahead = match self.open_files.get(&fd) {
+            Some(OpenFile::Regular { advice, .. }) => read_ahead_for(*advice) / PAGE_FRAME_SIZE,
+            _ => 0,
+        };
+        // increase reference count to ensure that file data is kept around even if file is unlinked and all descriptors are closed.
+        self.file_systems.get_mut(fs).inc_ref(inode);
+        let info = VMAInfo::MMap {
+            fs,
+            inode,
+            cache: self.page_cache(fs, inode),
+            offset: offset_in_pages,
+            shared,
+            read_ahead,
+        };
+        Ok(VMA::new(info, length, writeable))
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::block::block_core::test::GzBlockDevice;
+    use crate::user_program::signal::sig_bit;
     use crate::user_program::syscall;
-    use crate::vfs::tempfs::TempFS;
+    use crate::user_program::syscall::{SignalfdSiginfo, SIGINT, SIGTERM};
+    use crate::vfs;
+    use core::sync::atomic::AtomicUsize;
     use std::ffi::CStr;
     fn test_pcb(root: &RootFileSystem) -> ProcessControlBlock {
-        ProcessControlBlock {
-            pid: 0,
-            ppid: 0,
-            child_tids: vec![],
-            waiting_thread: None,
-            exit_code: None,
-            vmas: Default::default(),
-            cwd: root.get_root().unwrap(),
-            cwd_path: "/".into(),
-        }
+        ProcessControlBlock::for_test(0, root.get_root().unwrap())
     }
     // open file for fake PID of 0 with cwd / for testing
     fn open(root: &mut RootFileSystem, path: &Path, mode: Mode) -> Result<ProcessFileDescriptor> {
@@ -1524,6 +2505,20 @@ mod test {
         root_mutex.lock().close(file).unwrap();
     }
     #[test]
+    fn create_exclusive() {
+        let mut root = RootFileSystem::new();
+        root.mount_root(TempFS::new()).unwrap();
+        let file = open(&mut root, "/lock", Mode::CreateExclusiveReadWrite).unwrap();
+        root.close(file).unwrap();
+        assert!(matches!(
+            open(&mut root, "/lock", Mode::CreateExclusiveReadWrite),
+            Err(Error::Exists)
+        ));
+        // but it can still be opened normally
+        let file = open(&mut root, "/lock", Mode::CreateReadWrite).unwrap();
+        root.close(file).unwrap();
+    }
+    #[test]
     fn test_multiple_filesystems_simple() {
         let mut root = RootFileSystem::new();
         let fs = TempFS::new();
@@ -1568,6 +2563,195 @@ mod test {
         root.unmount(&pcb, "/2").unwrap();
     }
     #[test]
+    fn mount_fat_device() {
+        let mut root = RootFileSystem::new();
+        root.mount_root(TempFS::new()).unwrap();
+        let pcb = test_pcb(&root);
+        root.mkdir(&pcb, "/mnt").unwrap();
+        let device = Arc::new(
+            GzBlockDevice::open("tests/fat/simple_fat16.img.gz")
+                .unwrap()
+                .into_block(),
+        );
+        assert!(matches!(
+            root.mount_device(&pcb, "/mnt", "ext4", Some(device.shared())),
+            Err(Error::InvalidArgument)
+        ));
+        root.mount_device(&pcb, "/mnt", "fat", Some(device.shared()))
+            .unwrap();
+        let root_mutex = Mutex::new(root);
+        for (path, contents) in [("/mnt/a", &b"file a\n"[..]), ("/mnt/d/f", b"inner file\n")] {
+            let file = open(&mut root_mutex.lock(), path, Mode::ReadWrite).unwrap();
+            let mut buf = [0; 20];
+            let n = RootFileSystem::read(&root_mutex, file, &mut buf).unwrap();
+            assert_eq!(&buf[..n], contents);
+            root_mutex.lock().close(file).unwrap();
+        }
+        root_mutex.lock().unmount(&pcb, "/mnt").unwrap();
+    }
+    #[test]
+    fn mount_fat_ramdisk() {
+        use crate::block::block_core::{BlockManager, BlockType, BLOCK_SECTOR_SIZE};
+        use crate::drivers::ramdisk::RamDisk;
+        use std::io::Read;
+
+        let mut image = vec![];
+        flate2::read::GzDecoder::new(std::fs::File::open("tests/fat/simple_fat16.img.gz").unwrap())
+            .read_to_end(&mut image)
+            .unwrap();
+        let ramdisk = RamDisk::from_image(&image);
+        let mut block_manager = BlockManager::default();
+        block_manager.register_block(
+            BlockType::FileSystem,
+            "ram0",
+            ramdisk.sectors(),
+            Box::new(ramdisk),
+        );
+        let device = block_manager.by_name("ram0").unwrap();
+        assert_eq!(device.get_size() as usize * BLOCK_SECTOR_SIZE, image.len());
+
+        let mut root = RootFileSystem::new();
+        root.mount_root(TempFS::new()).unwrap();
+        let pcb = test_pcb(&root);
+        root.mkdir(&pcb, "/mnt").unwrap();
+        root.mount_device(&pcb, "/mnt", "fat", Some(device.shared()))
+            .unwrap();
+        let root_mutex = Mutex::new(root);
+        let file = open(&mut root_mutex.lock(), "/mnt/d/f", Mode::ReadWrite).unwrap();
+        let mut buf = [0; 20];
+        let n = RootFileSystem::read(&root_mutex, file, &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"inner file\n");
+        root_mutex.lock().close(file).unwrap();
+        root_mutex.lock().unmount(&pcb, "/mnt").unwrap();
+    }
+    #[test]
+    fn concurrent_access() {
+        const THREADS: usize = 4;
+        const ITERATIONS: usize = 50;
+        let root_mutex = Arc::new(Mutex::new(RootFileSystem::new()));
+        root_mutex.lock().mount_root(TempFS::new()).unwrap();
+        let shared = create(&root_mutex, "/shared", b"shared").unwrap();
+        root_mutex.lock().close(shared).unwrap();
+        let threads: Vec<_> = (0..THREADS)
+            .map(|i| {
+                let root_mutex = root_mutex.clone();
+                std::thread::spawn(move || {
+                    let mut pcb = test_pcb(&root_mutex.lock());
+                    pcb.pid = i as Pid + 1;
+                    let open = |path: &str, mode| {
+                        let fd = root_mutex.lock().open(&pcb, path, mode).unwrap();
+                        ProcessFileDescriptor { fd, pid: pcb.pid }
+                    };
+                    let log_path = format!("/log{i}");
+                    for j in 0..ITERATIONS {
+                        // a file only this thread uses
+                        let path = format!("/file{i}_{j}");
+                        let contents = format!("thread {i} iteration {j}");
+                        let file = open(&path, Mode::CreateReadWrite);
+                        RootFileSystem::write(&root_mutex, file, contents.as_bytes()).unwrap();
+                        root_mutex.lock().close(file).unwrap();
+                        let file = open(&path, Mode::ReadWrite);
+                        let mut buf = [0; 64];
+                        let n = RootFileSystem::read(&root_mutex, file, &mut buf).unwrap();
+                        assert_eq!(&buf[..n], contents.as_bytes());
+                        root_mutex.lock().unlink(&pcb, &path).unwrap();
+                        root_mutex.lock().close(file).unwrap();
+
+                        // a file every thread has open at once
+                        let shared = open("/shared", Mode::ReadWrite);
+                        let n = RootFileSystem::read(&root_mutex, shared, &mut buf).unwrap();
+                        assert_eq!(&buf[..n], b"shared");
+                        root_mutex.lock().close(shared).unwrap();
+
+                        // appended to over the whole test, so lost writes show up
+                        let log = open(&log_path, Mode::CreateReadWrite);
+                        root_mutex.lock().lseek(log, SeekFrom::End, 0).unwrap();
+                        RootFileSystem::write(&root_mutex, log, &[j as u8]).unwrap();
+                        root_mutex.lock().close(log).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let mut root = root_mutex.lock();
+        let pcb = test_pcb(&root);
+        for i in 0..THREADS {
+            let log = open(&mut root, &format!("/log{i}"), Mode::ReadWrite).unwrap();
+            drop(root);
+            let mut buf = [0; ITERATIONS + 1];
+            let n = RootFileSystem::read(&root_mutex, log, &mut buf).unwrap();
+            assert_eq!(&buf[..n], (0..ITERATIONS as u8).collect::<Vec<_>>());
+            root = root_mutex.lock();
+            root.close(log).unwrap();
+            root.unlink(&pcb, &format!("/log{i}")).unwrap();
+        }
+        // every reference taken by an open file has been dropped again
+        assert!(root.open_files.is_empty());
+        let root_fs = root.root_mount.unwrap();
+        assert!(root.file_systems.get(root_fs).can_be_safely_unmounted());
+    }
+    #[test]
+    fn flock() {
+        let root_mutex = Mutex::new(RootFileSystem::new());
+        root_mutex.lock().mount_root(TempFS::new()).unwrap();
+        let first = create(&root_mutex, "/locked", b"").unwrap();
+        let second = open(&mut root_mutex.lock(), "/locked", Mode::ReadWrite).unwrap();
+        {
+            let mut root = root_mutex.lock();
+            root.flock(first, Some(FileLock::Shared)).unwrap();
+            root.flock(second, Some(FileLock::Shared)).unwrap();
+            // the other shared lock is in the way of upgrading
+            assert!(matches!(
+                root.flock(first, Some(FileLock::Exclusive)),
+                Err(Error::WouldBlock)
+            ));
+            root.flock(second, None).unwrap();
+            root.flock(first, Some(FileLock::Exclusive)).unwrap();
+            assert!(matches!(
+                root.flock(second, Some(FileLock::Shared)),
+                Err(Error::WouldBlock)
+            ));
+        }
+
+        let mut root = root_mutex.lock();
+        let file = root.inode_of(first).unwrap();
+        const WAITER: Tid = 7;
+        // a thread waiting for the lock is queued once, however many times it tries
+        for _ in 0..2 {
+            assert!(matches!(
+                root.flock_or_queue(second, FileLock::Exclusive, WAITER),
+                Err(Error::WouldBlock)
+            ));
+        }
+        assert_eq!(root.lock_waiters[&file], [WAITER]);
+        // downgrading the exclusive lock wakes it, though it still can't have the lock it wants
+        root.flock(first, Some(FileLock::Shared)).unwrap();
+        assert!(root.lock_waiters.is_empty());
+        assert!(matches!(
+            root.flock_or_queue(second, FileLock::Exclusive, WAITER),
+            Err(Error::WouldBlock)
+        ));
+        // closing the first file descriptor releases its lock, and wakes it again
+        root.close(first).unwrap();
+        assert!(root.lock_waiters.is_empty());
+        root.flock_or_queue(second, FileLock::Exclusive, WAITER)
+            .unwrap();
+
+        root.close(second).unwrap();
+        assert!(root.file_locks.is_empty());
+    }
+    #[test]
+    #[should_panic(expected = "inodes still referenced")]
+    fn leaked_inode_reference() {
+        let mut manager = FileSystemManager::new(TempFS::new(), None);
+        let root = manager.fs.root();
+        // a reference without a matching dec_ref
+        manager.inc_ref(root);
+        drop(manager);
+    }
+    #[test]
     fn unlink() {
         let mut root = RootFileSystem::new();
         let fs = TempFS::new();
@@ -1685,6 +2869,30 @@ mod test {
         let dirent: Dirent = unsafe { dirents.as_ptr().cast::<Dirent>().read() };
         assert_eq!(dirent.inode, entries[2].1.inode);
         assert_eq!(usize::from(dirent.reclen), n);
+        // an offset getdents never handed out reads nothing
+        for bogus in [entries[2].1.offset + 100, i64::MAX] {
+            root.lseek(dir, SeekFrom::Start, bogus).unwrap();
+            let n = unsafe {
+                root.getdents(
+                    dir,
+                    dirents.as_mut_ptr().cast(),
+                    dirents.len() * std::mem::size_of_val(&dirents[0]),
+                )
+            }
+            .unwrap();
+            assert_eq!(n, 0);
+        }
+        // but seeking back to the start still works
+        root.lseek(dir, SeekFrom::Start, 0).unwrap();
+        let n = unsafe {
+            root.getdents(
+                dir,
+                dirents.as_mut_ptr().cast(),
+                dirents.len() * std::mem::size_of_val(&dirents[0]),
+            )
+        }
+        .unwrap();
+        assert_eq!(n, entries.iter().map(|e| usize::from(e.1.reclen)).sum());
         // now sort the directory entries, and make sure they are correct
         entries.sort_by(|a, b| a.0.cmp(&b.0));
         assert_eq!(entries[0].0, "dir");
@@ -1695,18 +2903,753 @@ mod test {
         assert_eq!(entries[2].1.r#type, syscall::S_REGULAR_FILE);
     }
     #[test]
-    fn ftruncate() {
+    fn dirents64() {
         let root_mutex = Mutex::new(RootFileSystem::new());
         let fs = TempFS::new();
         root_mutex.lock().mount_root(fs).unwrap();
+        let pcb = test_pcb(&root_mutex.lock());
         let fd = create(&root_mutex, "/file", b"test").unwrap();
-        root_mutex.lock().ftruncate(fd, 10).unwrap();
-        let stat = root_mutex.lock().fstat(fd).unwrap();
-        assert_eq!(stat.size, 10);
-        root_mutex.lock().lseek(fd, SeekFrom::Start, 0).unwrap();
-        let mut buf = [0; 10];
-        RootFileSystem::read(&root_mutex, fd, &mut buf).unwrap();
-        assert_eq!(&buf, b"test\0\0\0\0\0\0");
         root_mutex.lock().close(fd).unwrap();
+        let fd = create(&root_mutex, "/file2", b"test").unwrap();
+        root_mutex.lock().close(fd).unwrap();
+        root_mutex.lock().mkdir(&pcb, "/dir").unwrap();
+        let mut root = root_mutex.lock();
+        assert!(std::mem::align_of::<u64>() >= std::mem::align_of::<Dirent64>());
+        let dirent64 = Dirent64 {
+            d_ino: 0,
+            d_off: 0,
+            d_reclen: 0,
+            d_type: 0,
+            d_name: [],
+        };
+        assert_eq!(std::mem::size_of_val(&dirent64.d_ino), 8);
+        assert_eq!(std::mem::size_of_val(&dirent64.d_off), 8);
+        let mut dirents = vec![0u64; 1024];
+        let dir = open(&mut root, "/", Mode::ReadWrite).unwrap();
+        let n = unsafe {
+            root.getdents64(
+                dir,
+                dirents.as_mut_ptr().cast(),
+                dirents.len() * std::mem::size_of_val(&dirents[0]),
+            )
+        }
+        .unwrap();
+        let mut offset = 0;
+        let mut entries = vec![];
+        let dirents_ptr: *const u8 = dirents.as_ptr().cast();
+        while offset < n {
+            let dirent_ptr: *const Dirent64 = unsafe { dirents_ptr.add(offset).cast() };
+            assert!(dirent_ptr.is_aligned());
+            let dirent: &Dirent64 = unsafe { &*dirent_ptr };
+            let name_offset = std::mem::offset_of!(Dirent64, d_name);
+            let name_ptr = unsafe { dirent_ptr.cast::<std::ffi::c_char>().add(name_offset) };
+            let name: &str = unsafe { CStr::from_ptr(name_ptr) }.to_str().unwrap();
+            entries.push((name.to_owned(), *dirent));
+            assert_eq!(
+                usize::from(dirent.d_reclen) % std::mem::align_of::<Dirent64>(),
+                0
+            );
+            offset += usize::from(dirent.d_reclen);
+        }
+        // seek back to entries[2] to test that lseek works correctly for directories
+        root.lseek(dir, SeekFrom::Start, entries[2].1.d_off)
+            .unwrap();
+        let n = unsafe {
+            root.getdents64(
+                dir,
+                dirents.as_mut_ptr().cast(),
+                dirents.len() * std::mem::size_of_val(&dirents[0]),
+            )
+        }
+        .unwrap();
+        let dirent: Dirent64 = unsafe { dirents.as_ptr().cast::<Dirent64>().read() };
+        assert_eq!(dirent.d_ino, entries[2].1.d_ino);
+        assert_eq!(usize::from(dirent.d_reclen), n);
+        // now sort the directory entries, and make sure they are correct
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(entries[0].0, "dir");
+        assert_eq!(entries[1].0, "file");
+        assert_eq!(entries[2].0, "file2");
+        assert_eq!(entries[0].1.d_type, syscall::S_DIRECTORY);
+        assert_eq!(entries[1].1.d_type, syscall::S_REGULAR_FILE);
+        assert_eq!(entries[2].1.d_type, syscall::S_REGULAR_FILE);
+    }
+    #[test]
+    fn dirents_sorted() {
+        let root_mutex = Mutex::new(RootFileSystem::new());
+        root_mutex.lock().mount_root(TempFS::new()).unwrap();
+        let names = ["c", "a", "e", "bb", "d", "b"];
+        for name in names {
+            let fd = create(&root_mutex, &format!("/{name}"), b"").unwrap();
+            root_mutex.lock().close(fd).unwrap();
+        }
+        let mut root = root_mutex.lock();
+        let dir = open(&mut root, "/", Mode::ReadWrite).unwrap();
+        // only room for two entries at a time, so this takes several calls
+        let mut dirents = [0u64; 8];
+        let mut entries = vec![];
+        loop {
+            let n = unsafe {
+                root.getdents64_sorted(
+                    dir,
+                    dirents.as_mut_ptr().cast(),
+                    std::mem::size_of_val(&dirents),
+                )
+            }
+            .unwrap();
+            if n == 0 {
+                break;
+            }
+            let dirents_ptr: *const u8 = dirents.as_ptr().cast();
+            let mut offset = 0;
+            while offset < n {
+                let dirent_ptr: *const Dirent64 = unsafe { dirents_ptr.add(offset).cast() };
+                let dirent: &Dirent64 = unsafe { &*dirent_ptr };
+                let name_offset = std::mem::offset_of!(Dirent64, d_name);
+                let name_ptr = unsafe { dirent_ptr.cast::<std::ffi::c_char>().add(name_offset) };
+                let name: &str = unsafe { CStr::from_ptr(name_ptr) }.to_str().unwrap();
+                entries.push((name.to_owned(), dirent.d_off));
+                offset += usize::from(dirent.d_reclen);
+            }
+        }
+        let mut expected = names.to_vec();
+        expected.sort();
+        let entry_names: Vec<&str> = entries.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(entry_names, expected);
+        // offsets are positions in name order
+        for (i, (_, off)) in entries.iter().enumerate() {
+            assert_eq!(*off, i as i64);
+        }
+        root.close(dir).unwrap();
+    }
+    #[test]
+    fn write_direct() {
+        let root_mutex = Mutex::new(RootFileSystem::new());
+        root_mutex.lock().mount_root(TempFS::new()).unwrap();
+        let fd = create(&root_mutex, "/mapped", b"hello world").unwrap();
+        let mut root = root_mutex.lock();
+        let (fs, inode) = root.inode_of(fd).unwrap();
+        // only the part inside the file is written
+        assert_eq!(root.write_direct(fs, inode, 6, b"there!!!").unwrap(), 5);
+        assert_eq!(
+            root.write_direct(fs, inode, 20, b"past the end").unwrap(),
+            0
+        );
+        let mut buf = [0; 16];
+        assert_eq!(root.read_direct(fs, inode, 0, &mut buf).unwrap(), 11);
+        assert_eq!(&buf[..11], b"hello there");
+        root.close(fd).unwrap();
+    }
+    #[test]
+    fn ftruncate() {
+        let root_mutex = Mutex::new(RootFileSystem::new());
+        let fs = TempFS::new();
+        root_mutex.lock().mount_root(fs).unwrap();
+        let fd = create(&root_mutex, "/file", b"test").unwrap();
+        root_mutex.lock().ftruncate(fd, 10).unwrap();
+        let stat = root_mutex.lock().fstat(fd).unwrap();
+        assert_eq!(stat.size, 10);
+        root_mutex.lock().lseek(fd, SeekFrom::Start, 0).unwrap();
+        let mut buf = [0; 10];
+        RootFileSystem::read(&root_mutex, fd, &mut buf).unwrap();
+        assert_eq!(&buf, b"test\0\0\0\0\0\0");
+        root_mutex.lock().close(fd).unwrap();
+
+        // by path, without the file being open
+        let mut root = root_mutex.lock();
+        let pcb = test_pcb(&root);
+        root.truncate(&pcb, "/file", 2).unwrap();
+        let fd = open(&mut root, "/file", Mode::ReadWrite).unwrap();
+        assert_eq!(root.read_to_end(fd).unwrap(), b"te");
+        root.close(fd).unwrap();
+        assert!(matches!(
+            root.truncate(&pcb, "/", 0),
+            Err(Error::IsDirectory)
+        ));
+        assert!(matches!(
+            root.truncate(&pcb, "/missing", 0),
+            Err(Error::NotFound)
+        ));
+    }
+    #[test]
+    fn fallocate() {
+        const SIZE: usize = 1 << 20;
+        let root_mutex = Mutex::new(RootFileSystem::new());
+        // only just enough room for the allocated space
+        root_mutex
+            .lock()
+            .mount_root(TempFS::with_capacity(SIZE))
+            .unwrap();
+        let fd = create(&root_mutex, "/file", b"test").unwrap();
+        root_mutex.lock().fallocate(fd, 0, SIZE as u64).unwrap();
+        assert_eq!(root_mutex.lock().fstat(fd).unwrap().size, SIZE as u64);
+        // doesn't shrink the file
+        root_mutex.lock().fallocate(fd, 0, 1).unwrap();
+        assert_eq!(root_mutex.lock().fstat(fd).unwrap().size, SIZE as u64);
+        // writes within the allocated space don't need any more
+        root_mutex.lock().lseek(fd, SeekFrom::Start, 0).unwrap();
+        let data = vec![0xAA; SIZE];
+        assert_eq!(RootFileSystem::write(&root_mutex, fd, &data).unwrap(), SIZE);
+        assert!(matches!(
+            RootFileSystem::write(&root_mutex, fd, b"x"),
+            Err(Error::NoSpace)
+        ));
+        root_mutex.lock().close(fd).unwrap();
+    }
+    #[test]
+    fn fadvise() {
+        let root_mutex = Mutex::new(RootFileSystem::new());
+        root_mutex.lock().mount_root(TempFS::new()).unwrap();
+        let fd = create(&root_mutex, "/file", b"test").unwrap();
+        let mut root = root_mutex.lock();
+        let advice = |root: &RootFileSystem| match root.open_files[&fd] {
+            OpenFile::Regular { advice, .. } => advice,
+            _ => unreachable!(),
+        };
+        assert_eq!(advice(&root), POSIX_FADV_NORMAL);
+        root.fadvise(fd, 0, 0, POSIX_FADV_SEQUENTIAL).unwrap();
+        assert_eq!(advice(&root), POSIX_FADV_SEQUENTIAL);
+        // advice about what to do now doesn't change how the file will be read
+        root.fadvise(fd, 0, 0, POSIX_FADV_DONTNEED).unwrap();
+        assert_eq!(advice(&root), POSIX_FADV_SEQUENTIAL);
+
+        // the page cache of a file which is still mapped is kept
+        let (fs, inode) = root.inode_of(fd).unwrap();
+        let cache = root.page_cache(fs, inode);
+        root.fadvise(fd, 0, 0, POSIX_FADV_DONTNEED).unwrap();
+        assert!(root.page_caches.contains_key(&(fs, inode)));
+        // but forgotten once it isn't
+        drop(cache);
+        assert!(root.page_caches.contains_key(&(fs, inode)));
+        root.fadvise(fd, 0, 0, POSIX_FADV_DONTNEED).unwrap();
+        assert!(!root.page_caches.contains_key(&(fs, inode)));
+
+        let pcb = test_pcb(&root);
+        let (read, _write) = root.pipe(&pcb).unwrap();
+        let read = ProcessFileDescriptor { pid: 0, fd: read };
+        assert!(matches!(
+            root.fadvise(read, 0, 0, POSIX_FADV_NORMAL),
+            Err(Error::IllegalSeek)
+        ));
+        root.close(fd).unwrap();
+        assert!(matches!(
+            root.fadvise(fd, 0, 0, POSIX_FADV_NORMAL),
+            Err(Error::BadFd)
+        ));
+    }
+    /// File system which counts how many times files are read from it.
+    struct CountingFS {
+        fs: TempFS,
+        reads: Arc<AtomicUsize>,
+    }
+    impl vfs::SimpleFileSystem for CountingFS {
+        fn root(&self) -> INodeNum {
+            vfs::SimpleFileSystem::root(&self.fs)
+        }
+        fn open(&mut self, inode: INodeNum) -> Result<()> {
+            vfs::SimpleFileSystem::open(&mut self.fs, inode)
+        }
+        fn create(&mut self, parent: INodeNum, name: &Path) -> Result<INodeNum> {
+            vfs::SimpleFileSystem::create(&mut self.fs, parent, name)
+        }
+        fn readdir(&mut self, dir: INodeNum) -> Result<vfs::DirEntries> {
+            vfs::SimpleFileSystem::readdir(&mut self.fs, dir)
+        }
+        fn release(&mut self, inode: INodeNum) {
+            vfs::SimpleFileSystem::release(&mut self.fs, inode)
+        }
+        fn read(&mut self, file: INodeNum, offset: u64, buf: &mut [u8]) -> Result<usize> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            vfs::SimpleFileSystem::read(&mut self.fs, file, offset, buf)
+        }
+        fn write(&mut self, file: INodeNum, offset: u64, buf: &[u8]) -> Result<usize> {
+            vfs::SimpleFileSystem::write(&mut self.fs, file, offset, buf)
+        }
+        fn stat(&mut self, file: INodeNum) -> Result<FileInfo> {
+            vfs::SimpleFileSystem::stat(&mut self.fs, file)
+        }
+        fn truncate(&mut self, file: INodeNum, size: u64) -> Result<()> {
+            vfs::SimpleFileSystem::truncate(&mut self.fs, file, size)
+        }
+    }
+    // how many times the file system is read from, reading 512 bytes at each of `offsets` in a
+    // 64 KiB file advised with `advice`
+    fn device_reads(advice: i32, offsets: impl Iterator<Item = usize>) -> usize {
+        let reads = Arc::new(AtomicUsize::new(0));
+        let root_mutex = Mutex::new(RootFileSystem::new());
+        let fs = CountingFS {
+            fs: TempFS::new(),
+            reads: reads.clone(),
+        };
+        root_mutex.lock().mount_root(fs).unwrap();
+        let contents: Vec<u8> = (0..0x10000).map(|i| (i * 7) as u8).collect();
+        let fd = create(&root_mutex, "/file", &contents).unwrap();
+        root_mutex.lock().fadvise(fd, 0, 0, advice).unwrap();
+        reads.store(0, Ordering::SeqCst);
+        let mut buf = [0; 512];
+        for offset in offsets {
+            root_mutex
+                .lock()
+                .lseek(fd, SeekFrom::Start, offset as i64)
+                .unwrap();
+            assert_eq!(
+                RootFileSystem::read(&root_mutex, fd, &mut buf).unwrap(),
+                512
+            );
+            assert_eq!(buf, contents[offset..offset + 512]);
+        }
+        root_mutex.lock().close(fd).unwrap();
+        reads.load(Ordering::SeqCst)
+    }
+    #[test]
+    fn read_ahead() {
+        let sequential = || (0..0x10000).step_by(512);
+        let normal = device_reads(POSIX_FADV_NORMAL, sequential());
+        assert!(device_reads(POSIX_FADV_SEQUENTIAL, sequential()) < normal);
+        assert!(normal < device_reads(POSIX_FADV_RANDOM, sequential()));
+
+        let random = || (0..64).map(|i| (i * 37 % 64) * 1000);
+        assert_eq!(device_reads(POSIX_FADV_RANDOM, random()), 64);
+        assert!(device_reads(POSIX_FADV_NORMAL, random()) > 64);
+    }
+    #[test]
+    fn dontneed_forgets_read_ahead() {
+        let reads = Arc::new(AtomicUsize::new(0));
+        let root_mutex = Mutex::new(RootFileSystem::new());
+        let fs = CountingFS {
+            fs: TempFS::new(),
+            reads: reads.clone(),
+        };
+        root_mutex.lock().mount_root(fs).unwrap();
+        let fd = create(&root_mutex, "/file", &[1; 0x4000]).unwrap();
+        root_mutex.lock().lseek(fd, SeekFrom::Start, 0).unwrap();
+        let mut buf = [0; 512];
+        RootFileSystem::read(&root_mutex, fd, &mut buf).unwrap();
+        let before = reads.load(Ordering::SeqCst);
+        RootFileSystem::read(&root_mutex, fd, &mut buf).unwrap();
+        assert_eq!(reads.load(Ordering::SeqCst), before);
+        // advice about a range which wasn't read ahead doesn't drop it
+        root_mutex
+            .lock()
+            .fadvise(fd, 0x3000, 0, POSIX_FADV_DONTNEED)
+            .unwrap();
+        RootFileSystem::read(&root_mutex, fd, &mut buf).unwrap();
+        assert_eq!(reads.load(Ordering::SeqCst), before);
+        root_mutex
+            .lock()
+            .fadvise(fd, 0, 0, POSIX_FADV_DONTNEED)
+            .unwrap();
+        RootFileSystem::read(&root_mutex, fd, &mut buf).unwrap();
+        assert!(reads.load(Ordering::SeqCst) > before);
+        root_mutex.lock().close(fd).unwrap();
+    }
+    #[test]
+    fn mmap_past_end() {
+        let root_mutex = Mutex::new(RootFileSystem::new());
+        root_mutex.lock().mount_root(TempFS::new()).unwrap();
+        let fd = create(&root_mutex, "/file", b"test").unwrap();
+        let mut root = root_mutex.lock();
+        let offset = 2 * PAGE_FRAME_SIZE as i64;
+        assert!(matches!(
+            root.mmap_file(fd, PAGE_FRAME_SIZE, offset, false, false),
+            Err(Error::BadOffset)
+        ));
+        assert!(matches!(
+            root.mmap_file(fd, PAGE_FRAME_SIZE, i64::MAX, false, false),
+            Err(Error::BadOffset)
+        ));
+    }
+    #[test]
+    fn dev_tty() {
+        use crate::fs::tty::test::BufferConsole;
+        let console = BufferConsole::default();
+        let tty = Arc::new(Tty::new(Box::new(console.clone())));
+        let root_mutex = Mutex::new(RootFileSystem::new());
+        root_mutex.lock().mount_root(TempFS::new()).unwrap();
+        root_mutex.lock().mount_dev(tty.clone()).unwrap();
+        let fd = open(&mut root_mutex.lock(), "/dev/tty", Mode::ReadWrite).unwrap();
+        // input only becomes readable once the line is finished
+        for &c in b"hello\rworld\r" {
+            tty.receive(c);
+        }
+        let mut buf = [0; 16];
+        let n = RootFileSystem::read(&root_mutex, fd, &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"hello\n");
+        let n = RootFileSystem::read(&root_mutex, fd, &mut buf[..3]).unwrap();
+        assert_eq!(&buf[..n], b"wor");
+        let n = RootFileSystem::read(&root_mutex, fd, &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"ld\n");
+        assert_eq!(
+            RootFileSystem::write(&root_mutex, fd, b"hi there\n").unwrap(),
+            9
+        );
+        // input is echoed to the console, along with anything written
+        assert_eq!(&console.0.lock()[..], b"hello\nworld\nhi there\n");
+        root_mutex.lock().close(fd).unwrap();
+        let null = open(&mut root_mutex.lock(), "/dev/null", Mode::ReadWrite).unwrap();
+        assert_eq!(
+            RootFileSystem::read(&root_mutex, null, &mut buf).unwrap(),
+            0
+        );
+        root_mutex.lock().close(null).unwrap();
+    }
+    #[test]
+    fn open_at_read_to_end() {
+        let root_mutex = Mutex::new(RootFileSystem::new());
+        root_mutex.lock().mount_root(TempFS::new()).unwrap();
+        let pcb = test_pcb(&root_mutex.lock());
+        root_mutex.lock().mkdir(&pcb, "/bin").unwrap();
+        let contents: Vec<u8> = (0..10000).map(|i| i as u8).collect();
+        let file = create(&root_mutex, "/bin/prog", &contents).unwrap();
+        root_mutex.lock().close(file).unwrap();
+
+        let dir = open(&mut root_mutex.lock(), "/bin", Mode::ReadWrite).unwrap();
+        let fd = root_mutex
+            .lock()
+            .open_at(&pcb, Some(dir), "prog", Mode::ReadWrite)
+            .unwrap();
+        let fd = ProcessFileDescriptor { fd, pid: pcb.pid };
+        // read_to_end returns the whole file, whatever the offset, and leaves the offset alone
+        let mut buf = [0; 3];
+        RootFileSystem::read(&root_mutex, fd, &mut buf).unwrap();
+        assert_eq!(root_mutex.lock().read_to_end(fd).unwrap(), contents);
+        RootFileSystem::read(&root_mutex, fd, &mut buf).unwrap();
+        assert_eq!(buf, [3, 4, 5]);
+
+        let mut root = root_mutex.lock();
+        assert!(matches!(root.read_to_end(dir), Err(Error::IsDirectory)));
+        // paths are only relative to directories
+        assert!(matches!(
+            root.open_at(&pcb, Some(fd), "prog", Mode::ReadWrite),
+            Err(Error::NotDirectory)
+        ));
+        // and absolute paths ignore the directory entirely
+        let fd2 = root
+            .open_at(&pcb, Some(fd), "/bin/prog", Mode::ReadWrite)
+            .unwrap();
+        root.close(ProcessFileDescriptor {
+            fd: fd2,
+            pid: pcb.pid,
+        })
+        .unwrap();
+        root.close(fd).unwrap();
+        root.close(dir).unwrap();
+    }
+    #[test]
+    fn at_paths() {
+        let root_mutex = Mutex::new(RootFileSystem::new());
+        root_mutex.lock().mount_root(TempFS::new()).unwrap();
+        let pcb = test_pcb(&root_mutex.lock());
+        let mut root = root_mutex.lock();
+        root.mkdir(&pcb, "/d").unwrap();
+        let dir = open(&mut root, "/d", Mode::ReadWrite).unwrap();
+        root.mkdir_at(&pcb, Some(dir), "sub").unwrap();
+        let fd = root
+            .open_at(&pcb, Some(dir), "sub/file", Mode::CreateReadWrite)
+            .unwrap();
+        let fd = ProcessFileDescriptor { fd, pid: pcb.pid };
+        let absolute = open(&mut root, "/d/sub/file", Mode::ReadWrite).unwrap();
+        assert_eq!(
+            root.fstat(fd).unwrap().inode,
+            root.fstat(absolute).unwrap().inode
+        );
+        root.close(fd).unwrap();
+        root.close(absolute).unwrap();
+
+        root.symlink_at(&pcb, "sub/file", Some(dir), "link")
+            .unwrap();
+        assert_eq!(
+            root.read_link_at(&pcb, Some(dir), "link").unwrap(),
+            "sub/file"
+        );
+        assert_eq!(
+            root.read_link_at(&pcb, None, "/d/link").unwrap(),
+            "sub/file"
+        );
+        assert!(matches!(
+            root.read_link_at(&pcb, Some(dir), "sub/file"),
+            Err(Error::NotLink)
+        ));
+
+        root.unlink_at(&pcb, Some(dir), "link").unwrap();
+        root.unlink_at(&pcb, Some(dir), "sub/file").unwrap();
+        root.rmdir_at(&pcb, Some(dir), "sub").unwrap();
+        let entries = root.readdir(dir).unwrap();
+        assert!(entries
+            .iter()
+            .all(|entry| entry.name == "." || entry.name == ".."));
+        root.close(dir).unwrap();
+    }
+    #[test]
+    fn chroot() {
+        let mut root = RootFileSystem::new();
+        root.mount_root(TempFS::new()).unwrap();
+        let mut pcb = test_pcb(&root);
+        root.mkdir(&pcb, "/jail").unwrap();
+        root.mkdir(&pcb, "/jail/sub").unwrap();
+        for path in ["/outside", "/jail/inside"] {
+            let fd = root.open(&pcb, path, Mode::CreateReadWrite).unwrap();
+            root.close(ProcessFileDescriptor { fd, pid: 0 }).unwrap();
+        }
+        root.symlink(&pcb, "/inside", "/jail/link").unwrap();
+        let inside = open(&mut root, "/jail/inside", Mode::ReadWrite).unwrap();
+        let inside = root.fstat(inside).unwrap().inode;
+
+        root.chroot(&mut pcb, "/jail").unwrap();
+        assert_eq!(pcb.cwd_path, "/");
+        let inode_of = |root: &mut RootFileSystem, pcb: &ProcessControlBlock, path| {
+            let fd = root.open(pcb, path, Mode::ReadWrite)?;
+            root.fstat(ProcessFileDescriptor { fd, pid: 0 })
+                .map(|info| info.inode)
+        };
+        assert_eq!(inode_of(&mut root, &pcb, "/inside").unwrap(), inside);
+        assert_eq!(inode_of(&mut root, &pcb, "inside").unwrap(), inside);
+        // absolute symlinks stay inside too
+        assert_eq!(inode_of(&mut root, &pcb, "/link").unwrap(), inside);
+        // .. can't escape
+        assert_eq!(inode_of(&mut root, &pcb, "/../inside").unwrap(), inside);
+        root.chdir(&mut pcb, "sub").unwrap();
+        assert_eq!(
+            inode_of(&mut root, &pcb, "../../../inside").unwrap(),
+            inside
+        );
+        for path in ["/outside", "/../outside", "../../outside", "/jail/inside"] {
+            assert!(matches!(
+                inode_of(&mut root, &pcb, path),
+                Err(Error::NotFound)
+            ));
+        }
+    }
+    #[test]
+    fn chroot_references() {
+        let mut root = RootFileSystem::new();
+        root.mount_root(TempFS::new()).unwrap();
+        let pcb = test_pcb(&root);
+        root.mkdir(&pcb, "/jail").unwrap();
+        root.mount(&pcb, "/jail", TempFS::new()).unwrap();
+
+        let mut jailed = test_pcb(&root);
+        root.chroot(&mut jailed, "/jail").unwrap();
+        let mut visitor = test_pcb(&root);
+        root.chdir(&mut visitor, "/jail").unwrap();
+        root.release_cwd_and_root(&jailed);
+        // the jailed process only dropped its own reference, not the visitor's too
+        assert!(matches!(
+            root.unmount(&pcb, "/jail"),
+            Err(Error::FileSystemInUse)
+        ));
+
+        root.chdir(&mut visitor, "/").unwrap();
+        root.unmount(&pcb, "/jail").unwrap();
+    }
+    #[test]
+    fn inherited_cwd_and_root() {
+        let mut root = RootFileSystem::new();
+        root.mount_root(TempFS::new()).unwrap();
+        let pcb = test_pcb(&root);
+        root.mkdir(&pcb, "/jail").unwrap();
+        root.mount(&pcb, "/jail", TempFS::new()).unwrap();
+        root.mkdir(&pcb, "/jail/sub").unwrap();
+
+        let mut parent = test_pcb(&root);
+        root.chroot(&mut parent, "/jail").unwrap();
+        root.chdir(&mut parent, "/sub").unwrap();
+        // as exec and vfork set up the new process
+        let mut child = test_pcb(&root);
+        child.cwd = parent.cwd;
+        child.cwd_path = parent.cwd_path.clone();
+        child.root = parent.root;
+        root.hold_cwd_and_root(&child);
+
+        root.release_cwd_and_root(&parent);
+        assert!(matches!(
+            root.unmount(&pcb, "/jail"),
+            Err(Error::FileSystemInUse)
+        ));
+        root.release_cwd_and_root(&child);
+        root.unmount(&pcb, "/jail").unwrap();
+    }
+    #[test]
+    fn fd_limit() {
+        let mut root = RootFileSystem::new();
+        root.mount_root(TempFS::new()).unwrap();
+        let pcb = ProcessControlBlock {
+            fd_limit: 3,
+            ..test_pcb(&root)
+        };
+        let pid = pcb.pid;
+        let open_limited = |root: &mut RootFileSystem, path| {
+            let fd = root.open(&pcb, path, Mode::CreateReadWrite)?;
+            Ok(ProcessFileDescriptor { pid, fd })
+        };
+        let fds: Vec<ProcessFileDescriptor> = ["/a", "/b", "/c"]
+            .into_iter()
+            .map(|path| open_limited(&mut root, path).unwrap())
+            .collect();
+        assert!(matches!(
+            open_limited(&mut root, "/d"),
+            Err(Error::TooManyOpenFiles)
+        ));
+        assert!(matches!(
+            root.dup(&pcb, fds[0]),
+            Err(Error::TooManyOpenFiles)
+        ));
+        let above_limit = ProcessFileDescriptor { pid, fd: 3 };
+        assert!(matches!(
+            root.dup2(&pcb, fds[0], above_limit),
+            Err(Error::BadFd)
+        ));
+        // the limit belongs to the process, so one without it can still open files
+        let unlimited = open(&mut root, "/e", Mode::CreateReadWrite).unwrap();
+        assert_eq!(unlimited.fd, 3);
+        root.close(unlimited).unwrap();
+        // closing a file makes room for another
+        root.close(fds[1]).unwrap();
+        let fd = open_limited(&mut root, "/d").unwrap();
+        assert_eq!(fd.fd, 1);
+        for fd in [fds[0], fd, fds[2]] {
+            root.close(fd).unwrap();
+        }
+    }
+    #[test]
+    fn epoll() {
+        let mut root = RootFileSystem::new();
+        root.mount_root(TempFS::new()).unwrap();
+        let pcb = test_pcb(&root);
+        let pid = pcb.pid;
+        let fd = |fd| ProcessFileDescriptor { pid, fd };
+        let (read1, write1) = root.pipe(&pcb).unwrap();
+        let (read2, write2) = root.pipe(&pcb).unwrap();
+        let epfd = fd(root.epoll_create(&pcb).unwrap());
+        for read_end in [read1, read2] {
+            let event = EpollEvent {
+                events: EPOLLIN,
+                data: read_end as u64,
+            };
+            root.epoll_ctl(epfd, EpollOp::Add(event), read_end).unwrap();
+        }
+        assert!(matches!(
+            root.epoll_ctl(epfd, EpollOp::Delete, write1),
+            Err(Error::NotFound)
+        ));
+        assert!(matches!(
+            root.epoll_ctl(epfd, EpollOp::Delete, epfd.fd),
+            Err(Error::Unsupported)
+        ));
+        let mut events = [EpollEvent { events: 0, data: 0 }; 4];
+        assert_eq!(root.epoll_ready(epfd, &mut events).unwrap(), 0);
+
+        let root_mutex = Mutex::new(root);
+        RootFileSystem::write(&root_mutex, fd(write2), b"hi").unwrap();
+        let mut root = root_mutex.lock();
+        assert_eq!(root.epoll_ready(epfd, &mut events).unwrap(), 1);
+        assert_eq!(events[0].events, EPOLLIN);
+        assert_eq!(events[0].data, read2 as u64);
+
+        // closing the write end is reported even though only EPOLLIN was asked for
+        root.close(fd(write1)).unwrap();
+        assert_eq!(root.epoll_ready(epfd, &mut events).unwrap(), 2);
+        assert_eq!(events[0].events, EPOLLIN | EPOLLHUP);
+        assert_eq!(events[0].data, read1 as u64);
+        // and closed files are left out
+        root.close(fd(read1)).unwrap();
+        assert_eq!(root.epoll_ready(epfd, &mut events).unwrap(), 1);
+        assert_eq!(events[0].data, read2 as u64);
+
+        for file in [read2, write2, epfd.fd] {
+            root.close(fd(file)).unwrap();
+        }
+    }
+    #[test]
+    fn signalfd() {
+        let mut root = RootFileSystem::new();
+        root.mount_root(TempFS::new()).unwrap();
+        let pcb = test_pcb(&root);
+        let pid = pcb.pid;
+        let fd = |fd| ProcessFileDescriptor { pid, fd };
+        let signals = pcb.signals.pending.clone();
+        let mask = sig_bit(SIGTERM);
+        let sfd = fd(root
+            .signalfd_create(&pcb, signals.clone(), mask, true)
+            .unwrap());
+        let epfd = fd(root.epoll_create(&pcb).unwrap());
+        let event = EpollEvent {
+            events: EPOLLIN,
+            data: 0,
+        };
+        root.epoll_ctl(epfd, EpollOp::Add(event), sfd.fd).unwrap();
+        let mut events = [EpollEvent { events: 0, data: 0 }; 1];
+        assert_eq!(root.epoll_ready(epfd, &mut events).unwrap(), 0);
+
+        // signals which aren't in the mask aren't readable
+        signals.raise(SIGINT);
+        assert_eq!(root.epoll_ready(epfd, &mut events).unwrap(), 0);
+        signals.raise(SIGTERM);
+        assert_eq!(root.epoll_ready(epfd, &mut events).unwrap(), 1);
+        assert_eq!(events[0].events, EPOLLIN);
+
+        let root_mutex = Mutex::new(root);
+        let mut info = [0; size_of::<SignalfdSiginfo>()];
+        assert!(matches!(
+            RootFileSystem::read(&root_mutex, sfd, &mut info[1..]),
+            Err(Error::InvalidArgument)
+        ));
+        assert_eq!(
+            RootFileSystem::read(&root_mutex, sfd, &mut info).unwrap(),
+            info.len()
+        );
+        assert_eq!(u32::from_ne_bytes(info), SIGTERM as u32);
+        assert!(matches!(
+            RootFileSystem::read(&root_mutex, sfd, &mut info),
+            Err(Error::WouldBlock)
+        ));
+        // reading it takes it, but leaves the other signal pending
+        assert_eq!(signals.pending(), sig_bit(SIGINT));
+        let mut root = root_mutex.lock();
+        assert_eq!(root.epoll_ready(epfd, &mut events).unwrap(), 0);
+
+        for file in [sfd, epfd] {
+            root.close(file).unwrap();
+        }
+    }
+    #[test]
+    fn shm() {
+        let mut root = RootFileSystem::new();
+        root.mount_root(TempFS::new()).unwrap();
+        let pcb = test_pcb(&root);
+        let pid = pcb.pid;
+        let fd = |fd| ProcessFileDescriptor { pid, fd };
+        assert!(matches!(
+            root.shm_open(&pcb, "/shm", false),
+            Err(Error::NotFound)
+        ));
+        let fd1 = fd(root.shm_open(&pcb, "/shm", true).unwrap());
+        let fd2 = fd(root.shm_open(&pcb, "/shm", false).unwrap());
+        root.ftruncate(fd1, 5000).unwrap();
+        // both descriptors refer to the same object
+        let Some(OpenFile::Shm(shm)) = root.open_files.get(&fd2) else {
+            panic!("not a shared memory object");
+        };
+        assert_eq!(shm.size(), 5000);
+        // pages past the end can't be used
+        assert_eq!(shm.frame(2), None);
+
+        root.shm_unlink("/shm").unwrap();
+        assert!(matches!(root.shm_unlink("/shm"), Err(Error::NotFound)));
+        assert!(matches!(
+            root.shm_open(&pcb, "/shm", false),
+            Err(Error::NotFound)
+        ));
+        // but it can still be used through the descriptors which are already open
+        root.ftruncate(fd2, 0).unwrap();
+        let fd3 = fd(root.shm_open(&pcb, "/shm", true).unwrap());
+        root.ftruncate(fd3, 100).unwrap();
+        let Some(OpenFile::Shm(shm)) = root.open_files.get(&fd1) else {
+            panic!("not a shared memory object");
+        };
+        assert_eq!(shm.size(), 0);
+        for file in [fd1, fd2, fd3] {
+            root.close(file).unwrap();
+        }
     }
 }
@@ -1,14 +1,21 @@
+pub mod devfs;
+pub mod epoll;
 pub mod fat;
 pub mod fs_manager;
 pub mod pipe;
+pub mod signalfd;
 pub mod syscalls;
+pub mod tar;
+pub mod tarfs;
+pub mod tty;
 pub mod vsfs;
 
 use crate::fs::fs_manager::{Mode, RootFileSystem};
 use crate::system::{root_filesystem, running_process, running_thread_pid};
 use crate::threading::process::Pid;
+use crate::threading::thread_control_block::ProcessControlBlock;
 use crate::vfs::{Path, Result};
-use alloc::{vec, vec::Vec};
+use alloc::vec::Vec;
 
 pub type FileDescriptor = i16;
 
@@ -18,24 +25,42 @@ pub struct ProcessFileDescriptor {
     pub fd: FileDescriptor,
 }
 
+/// Run `f` with the root file system and the running process locked.
+///
+/// The process is locked first. Page faults read files in while the process is locked, so anything
+/// locking both has to lock them in that order.
+pub fn with_running_process<T>(
+    f: impl FnOnce(&mut RootFileSystem, &mut ProcessControlBlock) -> T,
+) -> T {
+    let pcb = running_process();
+    let mut pcb = pcb.lock();
+    f(&mut root_filesystem().lock(), &mut pcb)
+}
+
 /// Read entire contents of file to kernel memory.
 pub fn read_file(path: &Path) -> Result<Vec<u8>> {
-    let fd = root_filesystem()
-        .lock()
-        .open(&running_process().lock(), path, Mode::ReadWrite)?;
+    read_file_at(None, path)
+}
+
+/// Read entire contents of file to kernel memory, where a relative `path` is relative to the
+/// directory open as `dir` (or the working directory if `dir` is `None`).
+pub fn read_file_at(dir: Option<FileDescriptor>, path: &Path) -> Result<Vec<u8>> {
+    let pid = running_thread_pid();
+    let dir = dir.map(|fd| ProcessFileDescriptor { pid, fd });
+    with_running_process(|root, pcb| {
+        let fd = root.open_at(pcb, dir, path, Mode::ReadWrite)?;
+        let fd = ProcessFileDescriptor { fd, pid };
+        let data = root.read_to_end(fd);
+        root.close(fd).ok();
+        data
+    })
+}
+
+/// Read entire contents of the file open as `fd` to kernel memory.
+pub fn read_open_file(fd: FileDescriptor) -> Result<Vec<u8>> {
     let fd = ProcessFileDescriptor {
-        fd,
         pid: running_thread_pid(),
+        fd,
     };
-    let mut data = vec![];
-    loop {
-        let bytes_read = data.len();
-        data.resize(bytes_read + 4096, 0);
-        let n = RootFileSystem::read(root_filesystem(), fd, &mut data[bytes_read..])?;
-        data.truncate(bytes_read + n);
-        if n == 0 {
-            break;
-        }
-    }
-    Ok(data)
+    root_filesystem().lock().read_to_end(fd)
 }
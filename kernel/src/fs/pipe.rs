@@ -1,4 +1,4 @@
-use crate::sync::mutex::sleep::SleepMutex;
+use crate::sync::mutex::Mutex;
 use crate::sync::semaphore::Semaphore;
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
@@ -10,7 +10,8 @@ pub struct PipeInner {
     pub write_ends: AtomicUsize,
 
     pub semaphore: Semaphore,
-    pub contents: SleepMutex<VecDeque<u8>>,
+    // Only ever held while copying bytes in or out, never while waiting.
+    pub contents: Mutex<VecDeque<u8>>,
 }
 
 pub struct PipeReadEnd(pub Arc<PipeInner>);
@@ -23,7 +24,7 @@ impl Default for PipeInner {
             write_ends: AtomicUsize::new(0),
 
             semaphore: Semaphore::new(0),
-            contents: SleepMutex::new(VecDeque::new()),
+            contents: Mutex::new(VecDeque::new()),
         }
     }
 }
@@ -40,6 +41,11 @@ impl PipeInner {
 
         PipeWriteEnd(inner)
     }
+
+    /// Whether a read would return without waiting, either with data or at the end of the file.
+    pub fn is_readable(&self) -> bool {
+        !self.contents.lock().is_empty() || self.write_ends.load(Ordering::SeqCst) == 0
+    }
 }
 
 impl Clone for PipeReadEnd {
@@ -66,7 +72,10 @@ impl Drop for PipeReadEnd {
 
 impl Drop for PipeWriteEnd {
     fn drop(&mut self) {
-        self.0.write_ends.fetch_sub(1, Ordering::SeqCst);
+        if self.0.write_ends.fetch_sub(1, Ordering::SeqCst) == 1 {
+            // wake up a reader waiting for data, so that it sees the end of the file
+            self.0.semaphore.post();
+        }
     }
 }
 
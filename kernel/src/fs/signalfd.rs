@@ -0,0 +1,100 @@
+use crate::interrupts::{intr_disable, intr_enable};
+use crate::system::{running_process, running_thread_tid};
+use crate::threading::thread_sleep::thread_sleep;
+use crate::user_program::job_control::deliver_interrupt;
+use crate::user_program::signal::PendingSignals;
+use crate::user_program::syscall::{SigSet, SignalfdSiginfo};
+use crate::vfs::{Error, Result};
+use core::mem::size_of;
+
+/// A `signalfd`: a file which can be read to receive a process' pending signals, rather than
+/// having them delivered.
+#[derive(Clone, Debug)]
+pub struct SignalFd {
+    signals: PendingSignals,
+    /// The signals which can be read.
+    mask: SigSet,
+    /// Whether reads fail with [`Error::WouldBlock`] rather than wait when nothing is pending.
+    nonblocking: bool,
+}
+
+impl SignalFd {
+    pub fn new(signals: PendingSignals, mask: SigSet, nonblocking: bool) -> Self {
+        Self {
+            signals,
+            mask,
+            nonblocking,
+        }
+    }
+
+    /// Whether reading wouldn't wait, since one of the signals is pending.
+    pub fn is_readable(&self) -> bool {
+        self.signals.pending() & self.mask != 0
+    }
+
+    /// Take as many pending signals as fit in `buf`, as [`SignalfdSiginfo`]s, waiting for one if
+    /// none are pending yet.
+    ///
+    /// Returns [`Error::Interrupted`] if Ctrl-C is typed or a signal which isn't being read here
+    /// is delivered while waiting, so that the reader can get back to the syscall handler.
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        const SIZE: usize = size_of::<SignalfdSiginfo>();
+        if buf.len() < SIZE {
+            return Err(Error::InvalidArgument);
+        }
+        loop {
+            let count = self.take_into(buf);
+            if count > 0 {
+                return Ok(count);
+            }
+            if self.nonblocking {
+                return Err(Error::WouldBlock);
+            }
+            deliver_interrupt();
+            // Interrupts are off from checking until blocking, so a signal sent in between wakes
+            // this thread once it's asleep, rather than before.
+            let tid = running_thread_tid();
+            intr_disable();
+            {
+                let pcb = running_process();
+                let mut pcb = pcb.lock();
+                let interrupted = pcb.interrupted || pcb.signals.has_deliverable();
+                if interrupted || self.is_readable() {
+                    pcb.signals.sleepers.retain(|&sleeper| sleeper != tid);
+                    intr_enable();
+                    if interrupted {
+                        return Err(Error::Interrupted);
+                    }
+                    continue;
+                }
+                if !pcb.signals.sleepers.contains(&tid) {
+                    pcb.signals.sleepers.push(tid);
+                }
+            }
+            thread_sleep();
+            intr_enable();
+        }
+    }
+
+    /// Take as many pending signals as fit in `buf`, returning the number of bytes written.
+    fn take_into(&self, buf: &mut [u8]) -> usize {
+        let mut count = 0;
+        for chunk in buf.chunks_exact_mut(size_of::<SignalfdSiginfo>()) {
+            let Some(signal) = self.signals.take(self.mask) else {
+                break;
+            };
+            let info = SignalfdSiginfo {
+                ssi_signo: signal as u32,
+            };
+            // SAFETY: SignalfdSiginfo is plain old data, and chunk is exactly its size.
+            unsafe {
+                chunk
+                    .as_mut_ptr()
+                    .cast::<SignalfdSiginfo>()
+                    .write_unaligned(info)
+            };
+            count += chunk.len();
+        }
+        count
+    }
+}
@@ -2,41 +2,86 @@
 // Here we should be fine since we are checking the validity of pointers.
 #![allow(clippy::not_unsafe_ptr_arg_deref)]
 
+use crate::fs::epoll::EpollOp;
 use crate::fs::fs_manager::RootFileSystem;
 use crate::fs::{
-    fs_manager::{Mode, SeekFrom},
-    FileDescriptor, ProcessFileDescriptor,
+    fs_manager::{DirentFormat, FileLock, Mode, SeekFrom, MAX_OPEN_FILES},
+    with_running_process, FileDescriptor, ProcessFileDescriptor,
 };
-use crate::mem::util::{
-    get_cstr_from_user_space, get_mut_from_user_space, get_mut_slice_from_user_space,
-    get_slice_from_user_space, CStrError,
+use crate::interrupts::timer::{sleep_until, time_since_boot, TIMER_INTERRUPT_INTERVAL};
+use crate::interrupts::{intr_disable, intr_enable};
+use crate::mem::vma::VMAInfo;
+use crate::system::{
+    root_filesystem, running_process, running_thread_pid, running_thread_tid, unwrap_system,
 };
-use crate::system::{root_filesystem, running_process, running_thread_pid};
+use crate::threading::process::Pid;
+use crate::threading::thread_sleep::thread_sleep;
+use crate::user_program::job_control::deliver_interrupt;
+use crate::user_program::rusage::set_cpu_limit;
+use crate::user_program::signal::sig_bit;
 use crate::user_program::syscall::{
-    Dirent, Stat, EBADF, EFAULT, EINVAL, ENODEV, ENOENT, ENOMEM, ERANGE, O_CREATE, PROT_EXEC,
-    PROT_READ, PROT_WRITE, SEEK_CUR, SEEK_END, SEEK_SET,
+    Dirent, Dirent64, EpollEvent, RLimit, SigSet, Stat, AT_FDCWD, AT_REMOVEDIR, EBADF, EFAULT,
+    EINTR, EINVAL, ENAMETOOLONG, ENODEV, ENOENT, ENOMEM, ENOTTY, EPERM, EPOLL_CTL_ADD,
+    EPOLL_CTL_DEL, EPOLL_CTL_MOD, ERANGE, LOCK_EX, LOCK_NB, LOCK_SH, LOCK_UN, MAP_SHARED, MS_ASYNC,
+    MS_INVALIDATE, MS_SYNC, O_CREATE, O_EXCL, PATH_MAX, POSIX_FADV_DONTNEED, POSIX_FADV_NOREUSE,
+    POSIX_FADV_NORMAL, POSIX_FADV_RANDOM, POSIX_FADV_SEQUENTIAL, POSIX_FADV_WILLNEED, PROT_EXEC,
+    PROT_READ, PROT_WRITE, RLIMIT_AS, RLIMIT_CPU, RLIMIT_NOFILE, RLIM_INFINITY, SEEK_CUR, SEEK_END,
+    SEEK_SET, SFD_NONBLOCK, SIGKILL, TIOCGPGRP, TIOCSPGRP,
 };
-use crate::vfs::tempfs::TempFS;
+use crate::user_program::user_copy::{
+    check_user_range, copy_cstr_from_user, copy_from_user, copy_to_user, CStrError,
+};
+use crate::vfs::Error;
+use alloc::string::String;
+use alloc::vec;
+use core::cmp::{min, Ordering};
+use core::mem::size_of;
+use core::slice::from_mut;
+use core::time::Duration;
 use kidneyos_shared::mem::PAGE_FRAME_SIZE;
 
+/// The directory a relative path passed to an `*at` syscall is relative to, given its `dirfd`
+/// argument, or `None` for the working directory (`AT_FDCWD`).
+fn at_dir(dirfd: i32) -> Result<Option<ProcessFileDescriptor>, isize> {
+    if dirfd == AT_FDCWD {
+        return Ok(None);
+    }
+    match FileDescriptor::try_from(dirfd) {
+        Ok(fd) if fd >= 0 => Ok(Some(ProcessFileDescriptor {
+            pid: running_thread_pid(),
+            fd,
+        })),
+        _ => Err(-EBADF),
+    }
+}
+
 pub fn open(path: *const u8, flags: usize) -> isize {
-    if (flags & !O_CREATE) != 0 {
+    openat(AT_FDCWD, path, flags)
+}
+
+pub fn openat(dirfd: i32, path: *const u8, flags: usize) -> isize {
+    let dir = match at_dir(dirfd) {
+        Ok(dir) => dir,
+        Err(e) => return e,
+    };
+    if (flags & !(O_CREATE | O_EXCL)) != 0 {
         return -EINVAL;
     }
-    let path = match unsafe { get_cstr_from_user_space(path) } {
+    let path = match copy_cstr_from_user(path, PATH_MAX) {
         Ok(s) => s,
         Err(CStrError::BadUtf8) => return -ENOENT,
         Err(CStrError::Fault) => return -EFAULT,
+        Err(CStrError::TooLong) => return -ENAMETOOLONG,
     };
-    let mode = if (flags & O_CREATE) != 0 {
-        Mode::CreateReadWrite
-    } else {
+    let mode = if (flags & O_CREATE) == 0 {
+        // as on Linux, O_EXCL doesn't do anything without O_CREATE
         Mode::ReadWrite
+    } else if (flags & O_EXCL) != 0 {
+        Mode::CreateExclusiveReadWrite
+    } else {
+        Mode::CreateReadWrite
     };
-    match root_filesystem()
-        .lock()
-        .open(&running_process().lock(), path, mode)
-    {
+    match with_running_process(|root, pcb| root.open_at(pcb, dir, &path, mode)) {
         Err(e) => -e.to_isize(),
         Ok(fd) => fd.into(),
     }
@@ -48,16 +93,21 @@ pub fn read(fd: usize, buf: *mut u8, count: usize) -> isize {
     };
     // do reads of at most 128KB to not starve other processes
     let count = core::cmp::min(count, 128 << 10);
-    let Some(buf) = (unsafe { get_mut_slice_from_user_space::<u8>(buf, count) }) else {
-        return -EFAULT;
-    };
+    // check the buffer up front so we don't consume any data if it's bad
+    if let Err(e) = check_user_range(buf as usize, count, true) {
+        return -e;
+    }
+    let mut kernel_buf = vec![0; count];
     let fd = ProcessFileDescriptor {
         pid: running_thread_pid(),
         fd,
     };
-    match RootFileSystem::read(root_filesystem(), fd, buf) {
+    match RootFileSystem::read(root_filesystem(), fd, &mut kernel_buf) {
         Err(e) => -e.to_isize(),
-        Ok(n) => n as isize,
+        Ok(n) => match copy_to_user(buf, &kernel_buf[..n]) {
+            Ok(()) => n as isize,
+            Err(e) => -e,
+        },
     }
 }
 
@@ -67,23 +117,25 @@ pub fn write(fd: usize, buf: *const u8, count: usize) -> isize {
     };
     // do writes of at most 128KB to not starve other processes
     let count = core::cmp::min(count, 128 << 10);
-    let Some(buf) = (unsafe { get_slice_from_user_space::<u8>(buf, count) }) else {
-        return -EFAULT;
-    };
+    let mut kernel_buf = vec![0; count];
+    if let Err(e) = copy_from_user(&mut kernel_buf, buf) {
+        return -e;
+    }
     let fd = ProcessFileDescriptor {
         pid: running_thread_pid(),
         fd,
     };
-    match RootFileSystem::write(root_filesystem(), fd, buf) {
+    match RootFileSystem::write(root_filesystem(), fd, &kernel_buf) {
         Err(e) => -e.to_isize(),
         Ok(n) => n as isize,
     }
 }
 
-pub fn lseek64(fd: usize, offset: *mut i64, whence: isize) -> isize {
-    let Some(offset) = (unsafe { get_mut_from_user_space(offset) }) else {
-        return -EFAULT;
-    };
+pub fn lseek64(fd: usize, offset_ptr: *mut i64, whence: isize) -> isize {
+    let mut offset = 0;
+    if let Err(e) = copy_from_user(from_mut(&mut offset), offset_ptr) {
+        return -e;
+    }
     let Ok(fd) = FileDescriptor::try_from(fd) else {
         return -EBADF;
     };
@@ -97,11 +149,66 @@ pub fn lseek64(fd: usize, offset: *mut i64, whence: isize) -> isize {
         pid: running_thread_pid(),
         fd,
     };
-    match root_filesystem().lock().lseek(fd, whence, *offset) {
+    let result = root_filesystem().lock().lseek(fd, whence, offset);
+    match result {
         Err(e) => -e.to_isize(),
-        Ok(n) => {
-            *offset = n;
-            0
+        Ok(n) => match copy_to_user(offset_ptr, &[n]) {
+            Ok(()) => 0,
+            Err(e) => -e,
+        },
+    }
+}
+
+pub fn flock(fd: usize, operation: i32) -> isize {
+    let Ok(fd) = FileDescriptor::try_from(fd) else {
+        return -EBADF;
+    };
+    let fd = ProcessFileDescriptor {
+        pid: running_thread_pid(),
+        fd,
+    };
+    let lock = match operation & !LOCK_NB {
+        LOCK_SH => Some(FileLock::Shared),
+        LOCK_EX => Some(FileLock::Exclusive),
+        LOCK_UN => None,
+        _ => return -EINVAL,
+    };
+    let result = match lock {
+        Some(lock) if operation & LOCK_NB == 0 => flock_wait(fd, lock),
+        _ => root_filesystem().lock().flock(fd, lock),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => -e.to_isize(),
+    }
+}
+
+/// Take `lock` on the file open as `fd`, sleeping until conflicting locks are released.
+fn flock_wait(fd: ProcessFileDescriptor, lock: FileLock) -> Result<(), Error> {
+    let pcb = running_process();
+    let tid = running_thread_tid();
+    loop {
+        // let Ctrl-C interrupt the wait
+        deliver_interrupt();
+        // Interrupts are off from finding the lock taken until blocking, so neither its release
+        // nor Ctrl-C can wake this thread before it's blocked.
+        intr_disable();
+        let result = if pcb.lock().interrupted {
+            Err(Error::Interrupted)
+        } else {
+            root_filesystem().lock().flock_or_queue(fd, lock, tid)
+        };
+        if let Err(Error::WouldBlock) = result {
+            pcb.lock().signals.sleepers.push(tid);
+            thread_sleep();
+            pcb.lock()
+                .signals
+                .sleepers
+                .retain(|&sleeper| sleeper != tid);
+        }
+        intr_enable();
+        if !matches!(result, Err(Error::WouldBlock)) {
+            return result;
         }
     }
 }
@@ -121,54 +228,67 @@ pub fn close(fd: usize) -> isize {
 }
 
 pub fn chdir(path: *const u8) -> isize {
-    let path = match unsafe { get_cstr_from_user_space(path) } {
+    let path = match copy_cstr_from_user(path, PATH_MAX) {
         Ok(path) => path,
         Err(CStrError::BadUtf8) => return -ENOENT,
         Err(CStrError::Fault) => return -EFAULT,
+        Err(CStrError::TooLong) => return -ENAMETOOLONG,
     };
-    match root_filesystem()
-        .lock()
-        .chdir(&mut running_process().lock(), path)
-    {
+    match with_running_process(|root, pcb| root.chdir(pcb, &path)) {
         Err(e) => -e.to_isize(),
         Ok(()) => 0,
     }
 }
 
-pub fn getcwd(buf: *mut u8, size: usize) -> isize {
-    let Some(buf) = (unsafe { get_mut_slice_from_user_space(buf, size) }) else {
-        return -EFAULT;
+/// Every process runs as root (uid 0) for now, since there are no user IDs yet, so anyone can
+/// chroot. Otherwise this would fail with `EPERM` for the rest.
+pub fn chroot(path: *const u8) -> isize {
+    let path = match copy_cstr_from_user(path, PATH_MAX) {
+        Ok(path) => path,
+        Err(CStrError::BadUtf8) => return -ENOENT,
+        Err(CStrError::Fault) => return -EFAULT,
+        Err(CStrError::TooLong) => return -ENAMETOOLONG,
     };
-    let pcb = running_process();
-    let pcb = pcb.lock();
-    let cwd = pcb.cwd_path.as_bytes();
-    if size < cwd.len() + 1 {
+    match with_running_process(|root, pcb| root.chroot(pcb, &path)) {
+        Err(e) => -e.to_isize(),
+        Ok(()) => 0,
+    }
+}
+
+pub fn getcwd(buf: *mut u8, size: usize) -> isize {
+    let mut cwd = running_process().lock().cwd_path.as_bytes().to_vec();
+    cwd.push(0); // null terminator
+    if size < cwd.len() {
         return -ERANGE;
     }
-    buf[..cwd.len()].copy_from_slice(cwd);
-    buf[cwd.len()] = 0;
-    0
+    match copy_to_user(buf, &cwd) {
+        Ok(()) => 0,
+        Err(e) => -e,
+    }
 }
 
 pub fn mkdir(path: *const u8) -> isize {
-    let path = match unsafe { get_cstr_from_user_space(path) } {
+    mkdirat(AT_FDCWD, path)
+}
+
+pub fn mkdirat(dirfd: i32, path: *const u8) -> isize {
+    let dir = match at_dir(dirfd) {
+        Ok(dir) => dir,
+        Err(e) => return e,
+    };
+    let path = match copy_cstr_from_user(path, PATH_MAX) {
         Ok(path) => path,
         Err(CStrError::BadUtf8) => return -EINVAL,
         Err(CStrError::Fault) => return -EFAULT,
+        Err(CStrError::TooLong) => return -ENAMETOOLONG,
     };
-    match root_filesystem()
-        .lock()
-        .mkdir(&running_process().lock(), path)
-    {
+    match with_running_process(|root, pcb| root.mkdir_at(pcb, dir, &path)) {
         Err(e) => -e.to_isize(),
         Ok(()) => 0,
     }
 }
 
 pub fn fstat(fd: usize, statbuf: *mut Stat) -> isize {
-    let Some(statbuf) = (unsafe { get_mut_from_user_space(statbuf) }) else {
-        return -EFAULT;
-    };
     let Ok(fd) = FileDescriptor::try_from(fd) else {
         return -EBADF;
     };
@@ -176,45 +296,95 @@ pub fn fstat(fd: usize, statbuf: *mut Stat) -> isize {
         pid: running_thread_pid(),
         fd,
     };
-    match root_filesystem().lock().fstat(fd) {
+    let result = root_filesystem().lock().fstat(fd);
+    match result {
         Err(e) => -e.to_isize(),
         Ok(info) => {
-            *statbuf = Stat {
+            let stat = Stat {
                 inode: info.inode,
                 size: info.size,
                 nlink: info.nlink,
                 r#type: info.r#type.to_u8(),
             };
-            0
+            match copy_to_user(statbuf, &[stat]) {
+                Ok(()) => 0,
+                Err(e) => -e,
+            }
         }
     }
 }
 
-pub fn unlink(path: *const u8) -> isize {
-    let path = match unsafe { get_cstr_from_user_space(path) } {
-        Ok(path) => path,
-        Err(CStrError::BadUtf8) => return -EINVAL,
-        Err(CStrError::Fault) => return -EFAULT,
+pub fn ioctl(fd: usize, request: usize, arg: *mut i32) -> isize {
+    let Ok(fd) = FileDescriptor::try_from(fd) else {
+        return -EBADF;
     };
-    match root_filesystem()
-        .lock()
-        .unlink(&running_process().lock(), path)
-    {
-        Err(e) => -e.to_isize(),
-        Ok(()) => 0,
+    let fd = ProcessFileDescriptor {
+        pid: running_thread_pid(),
+        fd,
+    };
+    let result = root_filesystem().lock().tty(fd);
+    let tty = match result {
+        Err(e) => return -e.to_isize(),
+        Ok(None) => return -ENOTTY,
+        Ok(Some(tty)) => tty,
+    };
+    match request {
+        TIOCGPGRP => {
+            let pgid = tty.foreground().map_or(0, i32::from);
+            match copy_to_user(arg, &[pgid]) {
+                Ok(()) => 0,
+                Err(e) => -e,
+            }
+        }
+        TIOCSPGRP => {
+            let mut pgid = 0;
+            if let Err(e) = copy_from_user(from_mut(&mut pgid), arg) {
+                return -e;
+            }
+            match Pid::try_from(pgid) {
+                Ok(pgid) if pgid != 0 => {
+                    tty.set_foreground(Some(pgid));
+                    0
+                }
+                _ => -EINVAL,
+            }
+        }
+        _ => -ENOTTY,
     }
 }
 
+pub fn unlink(path: *const u8) -> isize {
+    unlinkat(AT_FDCWD, path, 0)
+}
+
 pub fn rmdir(path: *const u8) -> isize {
-    let path = match unsafe { get_cstr_from_user_space(path) } {
+    unlinkat(AT_FDCWD, path, AT_REMOVEDIR)
+}
+
+/// `unlink`, or `rmdir` with `AT_REMOVEDIR` in `flags`.
+pub fn unlinkat(dirfd: i32, path: *const u8, flags: i32) -> isize {
+    let dir = match at_dir(dirfd) {
+        Ok(dir) => dir,
+        Err(e) => return e,
+    };
+    if flags & !AT_REMOVEDIR != 0 {
+        return -EINVAL;
+    }
+    let path = match copy_cstr_from_user(path, PATH_MAX) {
         Ok(path) => path,
         Err(CStrError::BadUtf8) => return -EINVAL,
         Err(CStrError::Fault) => return -EFAULT,
+        Err(CStrError::TooLong) => return -ENAMETOOLONG,
     };
-    match root_filesystem()
-        .lock()
-        .rmdir(&running_process().lock(), path)
-    {
+    let mut root = root_filesystem().lock();
+    let process = running_process();
+    let process = process.lock();
+    let result = if flags & AT_REMOVEDIR != 0 {
+        root.rmdir_at(&process, dir, &path)
+    } else {
+        root.unlink_at(&process, dir, &path)
+    };
+    match result {
         Err(e) => -e.to_isize(),
         Ok(()) => 0,
     }
@@ -228,78 +398,165 @@ pub fn sync() -> isize {
 }
 
 pub fn getdents(fd: usize, output: *mut Dirent, size: usize) -> isize {
+    getdents_with_format(fd, output.cast(), size, DirentFormat::Dirent, false)
+}
+
+pub fn getdents64(fd: usize, output: *mut Dirent64, size: usize) -> isize {
+    getdents_with_format(fd, output.cast(), size, DirentFormat::Dirent64, false)
+}
+
+/// Like `getdents64`, but with entries sorted by name.
+pub fn getdents64_sorted(fd: usize, output: *mut Dirent64, size: usize) -> isize {
+    getdents_with_format(fd, output.cast(), size, DirentFormat::Dirent64, true)
+}
+
+fn getdents_with_format(
+    fd: usize,
+    output: *mut u8,
+    size: usize,
+    format: DirentFormat,
+    sorted: bool,
+) -> isize {
     let Ok(fd) = FileDescriptor::try_from(fd) else {
         return -EBADF;
     };
-    if unsafe { get_mut_slice_from_user_space(output as *mut u8, size) }.is_none() {
-        return -EFAULT;
+    // do reads of at most 128KB to not starve other processes
+    let size = core::cmp::min(size, 128 << 10);
+    // check the buffer up front so we don't advance the directory offset if it's bad
+    if let Err(e) = check_user_range(output as usize, size, true) {
+        return -e;
     }
+    // u64s so that the buffer is suitably aligned for Dirent and Dirent64
+    let mut kernel_buf = vec![0u64; size.div_ceil(8)];
     let fd = ProcessFileDescriptor {
         pid: running_thread_pid(),
         fd,
     };
-    // SAFETY: just checked that output..output + size is valid above
-    match unsafe { root_filesystem().lock().getdents(fd, output, size) } {
-        Ok(n) => n as isize,
+    let kernel_ptr = kernel_buf.as_mut_ptr();
+    // SAFETY: kernel_buf is valid for writing at least size bytes
+    let result = unsafe {
+        let mut root = root_filesystem().lock();
+        match (format, sorted) {
+            (DirentFormat::Dirent, _) => root.getdents(fd, kernel_ptr.cast(), size),
+            (DirentFormat::Dirent64, false) => root.getdents64(fd, kernel_ptr.cast(), size),
+            (DirentFormat::Dirent64, true) => root.getdents64_sorted(fd, kernel_ptr.cast(), size),
+        }
+    };
+    match result {
+        Ok(n) => {
+            let bytes = unsafe { core::slice::from_raw_parts(kernel_buf.as_ptr().cast::<u8>(), n) };
+            match copy_to_user(output, bytes) {
+                Ok(()) => n as isize,
+                Err(e) => -e,
+            }
+        }
         Err(e) => -e.to_isize(),
     }
 }
 
 pub fn link(source: *const u8, dest: *const u8) -> isize {
-    let source = match unsafe { get_cstr_from_user_space(source) } {
+    let source = match copy_cstr_from_user(source, PATH_MAX) {
         Ok(path) => path,
         Err(CStrError::BadUtf8) => return -ENOENT,
         Err(CStrError::Fault) => return -EFAULT,
+        Err(CStrError::TooLong) => return -ENAMETOOLONG,
     };
-    let dest = match unsafe { get_cstr_from_user_space(dest) } {
+    let dest = match copy_cstr_from_user(dest, PATH_MAX) {
         Ok(path) => path,
         Err(CStrError::BadUtf8) => return -EINVAL,
         Err(CStrError::Fault) => return -EFAULT,
+        Err(CStrError::TooLong) => return -ENAMETOOLONG,
     };
-    match root_filesystem()
-        .lock()
-        .link(&running_process().lock(), source, dest)
-    {
+    match with_running_process(|root, pcb| root.link(pcb, &source, &dest)) {
         Ok(()) => 0,
         Err(e) => -e.to_isize(),
     }
 }
 
 pub fn symlink(source: *const u8, dest: *const u8) -> isize {
-    let source = match unsafe { get_cstr_from_user_space(source) } {
+    symlinkat(source, AT_FDCWD, dest)
+}
+
+pub fn symlinkat(source: *const u8, dirfd: i32, dest: *const u8) -> isize {
+    let dir = match at_dir(dirfd) {
+        Ok(dir) => dir,
+        Err(e) => return e,
+    };
+    let source = match copy_cstr_from_user(source, PATH_MAX) {
         Ok(path) => path,
         Err(CStrError::BadUtf8) => return -EINVAL,
         Err(CStrError::Fault) => return -EFAULT,
+        Err(CStrError::TooLong) => return -ENAMETOOLONG,
     };
-    let dest = match unsafe { get_cstr_from_user_space(dest) } {
+    let dest = match copy_cstr_from_user(dest, PATH_MAX) {
         Ok(path) => path,
         Err(CStrError::BadUtf8) => return -EINVAL,
         Err(CStrError::Fault) => return -EFAULT,
+        Err(CStrError::TooLong) => return -ENAMETOOLONG,
     };
-    match root_filesystem()
-        .lock()
-        .symlink(&running_process().lock(), source, dest)
-    {
+    match with_running_process(|root, pcb| root.symlink_at(pcb, &source, dir, &dest)) {
         Ok(()) => 0,
         Err(e) => -e.to_isize(),
     }
 }
 
+/// Write the destination of the symlink at `path` to `buf`, truncated to `size` bytes and without
+/// a null terminator, returning how many bytes were written.
+pub fn readlinkat(dirfd: i32, path: *const u8, buf: *mut u8, size: usize) -> isize {
+    let dir = match at_dir(dirfd) {
+        Ok(dir) => dir,
+        Err(e) => return e,
+    };
+    if size == 0 {
+        return -EINVAL;
+    }
+    let path = match copy_cstr_from_user(path, PATH_MAX) {
+        Ok(path) => path,
+        Err(CStrError::BadUtf8) => return -ENOENT,
+        Err(CStrError::Fault) => return -EFAULT,
+        Err(CStrError::TooLong) => return -ENAMETOOLONG,
+    };
+    let result = with_running_process(|root, pcb| root.read_link_at(pcb, dir, &path));
+    match result {
+        Err(e) => -e.to_isize(),
+        Ok(link) => {
+            let len = min(link.len(), size);
+            match copy_to_user(buf, &link.as_bytes()[..len]) {
+                Ok(()) => len as isize,
+                Err(e) => -e,
+            }
+        }
+    }
+}
+
 pub fn rename(source: *const u8, dest: *const u8) -> isize {
-    let source = match unsafe { get_cstr_from_user_space(source) } {
+    let source = match copy_cstr_from_user(source, PATH_MAX) {
         Ok(path) => path,
         Err(CStrError::BadUtf8) => return -ENOENT,
         Err(CStrError::Fault) => return -EFAULT,
+        Err(CStrError::TooLong) => return -ENAMETOOLONG,
     };
-    let dest = match unsafe { get_cstr_from_user_space(dest) } {
+    let dest = match copy_cstr_from_user(dest, PATH_MAX) {
         Ok(path) => path,
         Err(CStrError::BadUtf8) => return -EINVAL,
         Err(CStrError::Fault) => return -EFAULT,
+        Err(CStrError::TooLong) => return -ENAMETOOLONG,
     };
-    match root_filesystem()
-        .lock()
-        .rename(&running_process().lock(), source, dest)
-    {
+    match with_running_process(|root, pcb| root.rename(pcb, &source, &dest)) {
+        Ok(()) => 0,
+        Err(e) => -e.to_isize(),
+    }
+}
+
+pub fn truncate(path: *const u8, size_lo: usize, size_hi: usize) -> isize {
+    let path = match copy_cstr_from_user(path, PATH_MAX) {
+        Ok(path) => path,
+        Err(CStrError::BadUtf8) => return -ENOENT,
+        Err(CStrError::Fault) => return -EFAULT,
+        Err(CStrError::TooLong) => return -ENAMETOOLONG,
+    };
+    let size = size_lo as u64 | (size_hi as u64) << 32;
+    match with_running_process(|root, pcb| root.truncate(pcb, &path, size)) {
         Ok(()) => 0,
         Err(e) => -e.to_isize(),
     }
@@ -320,48 +577,109 @@ pub fn ftruncate(fd: usize, size_lo: usize, size_hi: usize) -> isize {
     }
 }
 
+pub fn fallocate(
+    fd: usize,
+    offset_lo: usize,
+    offset_hi: usize,
+    len_lo: usize,
+    len_hi: usize,
+) -> isize {
+    let Ok(fd) = FileDescriptor::try_from(fd) else {
+        return -EBADF;
+    };
+    let fd = ProcessFileDescriptor {
+        pid: running_thread_pid(),
+        fd,
+    };
+    let offset = offset_lo as u64 | (offset_hi as u64) << 32;
+    let len = len_lo as u64 | (len_hi as u64) << 32;
+    match root_filesystem().lock().fallocate(fd, offset, len) {
+        Ok(()) => 0,
+        Err(e) => -e.to_isize(),
+    }
+}
+
+/// `posix_fadvise`, which on x86 Linux is `fadvise64`, with a 32-bit `len`.
+///
+/// See [`RootFileSystem::fadvise`](crate::fs::fs_manager::RootFileSystem::fadvise) for what it
+/// does.
+pub fn fadvise64(fd: usize, offset_lo: usize, offset_hi: usize, len: usize, advice: i32) -> isize {
+    let Ok(fd) = FileDescriptor::try_from(fd) else {
+        return -EBADF;
+    };
+    let fd = ProcessFileDescriptor {
+        pid: running_thread_pid(),
+        fd,
+    };
+    let offset = offset_lo as u64 | (offset_hi as u64) << 32;
+    if (offset as i64) < 0 || (len as isize) < 0 {
+        return -EINVAL;
+    }
+    if !matches!(
+        advice,
+        POSIX_FADV_NORMAL
+            | POSIX_FADV_RANDOM
+            | POSIX_FADV_SEQUENTIAL
+            | POSIX_FADV_WILLNEED
+            | POSIX_FADV_DONTNEED
+            | POSIX_FADV_NOREUSE
+    ) {
+        return -EINVAL;
+    }
+    match root_filesystem()
+        .lock()
+        .fadvise(fd, offset, len as u64, advice)
+    {
+        Ok(()) => 0,
+        Err(e) => -e.to_isize(),
+    }
+}
+
 pub fn unmount(path: *const u8) -> isize {
-    let path = match unsafe { get_cstr_from_user_space(path) } {
+    let path = match copy_cstr_from_user(path, PATH_MAX) {
         Ok(path) => path,
         Err(CStrError::BadUtf8) => return -ENOENT,
         Err(CStrError::Fault) => return -EFAULT,
+        Err(CStrError::TooLong) => return -ENAMETOOLONG,
     };
-    match root_filesystem()
-        .lock()
-        .unmount(&running_process().lock(), path)
-    {
+    match with_running_process(|root, pcb| root.unmount(pcb, &path)) {
         Ok(()) => 0,
         Err(e) => -e.to_isize(),
     }
 }
 
 pub fn mount(device: *const u8, target: *const u8, file_system_type: *const u8) -> isize {
-    let device = match unsafe { get_cstr_from_user_space(device) } {
+    let device = match copy_cstr_from_user(device, PATH_MAX) {
         Ok(d) => d,
         Err(CStrError::BadUtf8) => return -ENOENT,
         Err(CStrError::Fault) => return -EFAULT,
+        Err(CStrError::TooLong) => return -ENAMETOOLONG,
     };
-    let target = match unsafe { get_cstr_from_user_space(target) } {
+    let target = match copy_cstr_from_user(target, PATH_MAX) {
         Ok(d) => d,
         Err(CStrError::BadUtf8) => return -EINVAL,
         Err(CStrError::Fault) => return -EFAULT,
+        Err(CStrError::TooLong) => return -ENAMETOOLONG,
     };
-    let file_system_type = match unsafe { get_cstr_from_user_space(file_system_type) } {
+    let file_system_type = match copy_cstr_from_user(file_system_type, PATH_MAX) {
         Ok(d) => d,
         Err(CStrError::BadUtf8) => return -ENODEV,
         Err(CStrError::Fault) => return -EFAULT,
+        Err(CStrError::TooLong) => return -ENAMETOOLONG,
     };
-    let mut root = root_filesystem().lock();
-    let result = match file_system_type {
-        "tmpfs" => {
-            if !device.is_empty() {
-                // should set device to empty string for tmpfs
-                return -EINVAL;
-            }
-            root.mount(&running_process().lock(), target, TempFS::new())
+    // tmpfs doesn't have a device, which is given as an empty string
+    let device = if device.is_empty() {
+        None
+    } else {
+        let block_manager = unwrap_system().block_manager.read();
+        match block_manager.by_name(&device) {
+            Some(block) => Some(block.shared()),
+            None => return -ENOENT,
         }
-        _ => return -ENODEV,
     };
+    let result = with_running_process(|root, pcb| {
+        root.mount_device(pcb, &target, &file_system_type, device)
+    });
     match result {
         Ok(()) => 0,
         Err(e) => -e.to_isize(),
@@ -377,9 +695,7 @@ pub fn dup(fd: isize) -> isize {
 
     let process_fd = ProcessFileDescriptor { pid, fd };
 
-    root_filesystem()
-        .lock()
-        .dup(pid, process_fd)
+    with_running_process(|root, pcb| root.dup(pcb, process_fd))
         .map(|i| i.into())
         .unwrap_or_else(|err| -err.to_isize())
 }
@@ -399,31 +715,239 @@ pub fn dup2(old: isize, new: isize) -> isize {
 
     let new_process_fd = ProcessFileDescriptor { pid, fd: new };
 
-    root_filesystem()
-        .lock()
-        .dup2(old_process_fd, new_process_fd)
+    with_running_process(|root, pcb| root.dup2(pcb, old_process_fd, new_process_fd))
         .map(|_| 0)
         .unwrap_or_else(|err| -err.to_isize())
 }
 
-pub fn pipe(fds: *mut isize) -> isize {
-    let Some(fds) = (unsafe { get_mut_slice_from_user_space(fds, 2) }) else {
-        return -EFAULT;
+pub fn getrlimit(resource: i32, rlim: *mut RLimit) -> isize {
+    let rlimit = match resource {
+        RLIMIT_NOFILE => {
+            let limit = running_process().lock().fd_limit;
+            RLimit {
+                rlim_cur: limit.into(),
+                rlim_max: MAX_OPEN_FILES.into(),
+            }
+        }
+        RLIMIT_CPU => running_process().lock().cpu_limit,
+        RLIMIT_AS => RLimit {
+            rlim_cur: running_process().lock().vmas.limit(),
+            rlim_max: RLIM_INFINITY,
+        },
+        _ => return -EINVAL,
     };
+    match copy_to_user(rlim, &[rlimit]) {
+        Ok(()) => 0,
+        Err(e) => -e,
+    }
+}
 
-    let pid = running_process().lock().pid;
+pub fn setrlimit(resource: i32, rlim: *const RLimit) -> isize {
+    if !matches!(resource, RLIMIT_NOFILE | RLIMIT_CPU | RLIMIT_AS) {
+        return -EINVAL;
+    }
+    let mut rlimit = RLimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if let Err(e) = copy_from_user(from_mut(&mut rlimit), rlim) {
+        return -e;
+    }
+    if rlimit.rlim_cur > rlimit.rlim_max {
+        return -EINVAL;
+    }
+    if resource == RLIMIT_CPU {
+        set_cpu_limit(rlimit);
+        return 0;
+    }
+    if resource == RLIMIT_AS {
+        // the hard limit is always RLIM_INFINITY
+        if rlimit.rlim_max != RLIM_INFINITY {
+            return -EINVAL;
+        }
+        running_process().lock().vmas.set_limit(rlimit.rlim_cur);
+        return 0;
+    }
+    // the hard limit is always MAX_OPEN_FILES
+    match rlimit.rlim_max.cmp(&MAX_OPEN_FILES.into()) {
+        Ordering::Greater => return -EPERM,
+        Ordering::Less => return -EINVAL,
+        Ordering::Equal => {}
+    }
+    // rlim_cur <= rlim_max, so this fits
+    running_process().lock().fd_limit = rlimit.rlim_cur as u16;
+    0
+}
 
-    match root_filesystem().lock().pipe(pid) {
+pub fn pipe(fds: *mut isize) -> isize {
+    if let Err(e) = check_user_range(fds as usize, 2 * core::mem::size_of::<isize>(), true) {
+        return -e;
+    }
+
+    let result = with_running_process(|root, pcb| root.pipe(pcb));
+    match result {
         Ok((read_end, write_end)) => {
-            fds[0] = read_end as isize;
-            fds[1] = write_end as isize;
+            match copy_to_user(fds, &[read_end as isize, write_end as isize]) {
+                Ok(()) => 0,
+                Err(e) => -e,
+            }
+        }
+        Err(e) => -e.to_isize(),
+    }
+}
 
-            0
+/// Copy the name of a shared memory object from userspace. Like on Linux, names are `/` followed
+/// by at least one character other than `/`.
+fn copy_shm_name_from_user(name: *const u8) -> Result<String, isize> {
+    let name = match copy_cstr_from_user(name, PATH_MAX) {
+        Ok(name) => name,
+        Err(CStrError::BadUtf8) => return Err(EINVAL),
+        Err(CStrError::Fault) => return Err(EFAULT),
+        Err(CStrError::TooLong) => return Err(ENAMETOOLONG),
+    };
+    match name.strip_prefix('/') {
+        Some(rest) if !rest.is_empty() && !rest.contains('/') => Ok(name),
+        _ => Err(EINVAL),
+    }
+}
+
+pub fn shm_open(name: *const u8, flags: usize) -> isize {
+    if (flags & !O_CREATE) != 0 {
+        return -EINVAL;
+    }
+    let name = match copy_shm_name_from_user(name) {
+        Ok(name) => name,
+        Err(e) => return -e,
+    };
+    let create = (flags & O_CREATE) != 0;
+    match with_running_process(|root, pcb| root.shm_open(pcb, &name, create)) {
+        Ok(fd) => fd.into(),
+        Err(e) => -e.to_isize(),
+    }
+}
+
+pub fn shm_unlink(name: *const u8) -> isize {
+    let name = match copy_shm_name_from_user(name) {
+        Ok(name) => name,
+        Err(e) => return -e,
+    };
+    match root_filesystem().lock().shm_unlink(&name) {
+        Ok(()) => 0,
+        Err(e) => -e.to_isize(),
+    }
+}
+
+pub fn epoll_create(size: i32) -> isize {
+    // size is just a hint, but must be positive
+    if size <= 0 {
+        return -EINVAL;
+    }
+    match with_running_process(|root, pcb| root.epoll_create(pcb)) {
+        Ok(fd) => fd.into(),
+        Err(e) => -e.to_isize(),
+    }
+}
+
+/// The `signalfd4` syscall: create a `signalfd`, which can be read to take the running process'
+/// pending signals in `mask`. Changing the mask of an existing one (an `fd` other than -1) isn't
+/// supported yet.
+pub fn signalfd(fd: i32, mask: *const SigSet, size: usize, flags: i32) -> isize {
+    if fd != -1 || size != size_of::<SigSet>() || flags & !SFD_NONBLOCK != 0 {
+        return -EINVAL;
+    }
+    let mut set = [0];
+    if let Err(e) = copy_from_user(&mut set, mask) {
+        return -e;
+    }
+    // as on Linux, SIGKILL can't be read, since it can't be blocked
+    let mask = set[0] & !sig_bit(SIGKILL);
+    let signals = running_process().lock().signals.pending.clone();
+    let nonblocking = flags & SFD_NONBLOCK != 0;
+    match with_running_process(|root, pcb| root.signalfd_create(pcb, signals, mask, nonblocking)) {
+        Ok(fd) => fd.into(),
+        Err(e) => -e.to_isize(),
+    }
+}
+
+pub fn epoll_ctl(epfd: isize, op: i32, fd: isize, event: *const EpollEvent) -> isize {
+    let (Ok(epfd), Ok(fd)) = (FileDescriptor::try_from(epfd), FileDescriptor::try_from(fd)) else {
+        return -EBADF;
+    };
+    let op = if op == EPOLL_CTL_DEL {
+        EpollOp::Delete
+    } else {
+        let mut event_buf = EpollEvent { events: 0, data: 0 };
+        if let Err(e) = copy_from_user(from_mut(&mut event_buf), event) {
+            return -e;
+        }
+        match op {
+            EPOLL_CTL_ADD => EpollOp::Add(event_buf),
+            EPOLL_CTL_MOD => EpollOp::Modify(event_buf),
+            _ => return -EINVAL,
         }
+    };
+    let epfd = ProcessFileDescriptor {
+        pid: running_thread_pid(),
+        fd: epfd,
+    };
+    match root_filesystem().lock().epoll_ctl(epfd, op, fd) {
+        Ok(()) => 0,
         Err(e) => -e.to_isize(),
     }
 }
 
+pub fn epoll_wait(epfd: isize, events: *mut EpollEvent, max_events: i32, timeout: i32) -> isize {
+    let Ok(epfd) = FileDescriptor::try_from(epfd) else {
+        return -EBADF;
+    };
+    if max_events <= 0 {
+        return -EINVAL;
+    }
+    // a negative timeout waits forever
+    let deadline = u64::try_from(timeout)
+        .ok()
+        .map(|ms| time_since_boot().saturating_add(Duration::from_millis(ms)));
+    // there can't be more ready files than open ones
+    let max_events = min(max_events as usize, MAX_OPEN_FILES.into());
+    if let Err(e) = check_user_range(
+        events as usize,
+        max_events * core::mem::size_of::<EpollEvent>(),
+        true,
+    ) {
+        return -e;
+    }
+    let epfd = ProcessFileDescriptor {
+        pid: running_thread_pid(),
+        fd: epfd,
+    };
+    let mut ready = vec![EpollEvent { events: 0, data: 0 }; max_events];
+    loop {
+        let count = match root_filesystem().lock().epoll_ready(epfd, &mut ready) {
+            Ok(count) => count,
+            Err(e) => return -e.to_isize(),
+        };
+        if count > 0 || timeout == 0 {
+            return match copy_to_user(events, &ready[..count]) {
+                Ok(()) => count as isize,
+                Err(e) => -e,
+            };
+        }
+        // let Ctrl-C interrupt the wait
+        deliver_interrupt();
+        if running_process().lock().interrupted {
+            return -EINTR;
+        }
+        let now = time_since_boot();
+        if deadline.is_some_and(|deadline| now >= deadline) {
+            return 0;
+        }
+        // Files don't wake anything when they become ready, so check them again on the next
+        // tick, or at the deadline if that's sooner, blocking in the meantime.
+        let next_tick = now.saturating_add(TIMER_INTERRUPT_INTERVAL);
+        sleep_until(deadline.map_or(next_tick, |deadline| min(deadline, next_tick)));
+    }
+}
+
 pub fn mmap(
     addr: *mut core::ffi::c_void,
     length: usize,
@@ -434,7 +958,7 @@ pub fn mmap(
 ) -> isize {
     crate::println!("mmap fd={fd} addr={addr:?} length={length} prot={prot:#x} flags={flags:#x} offset={offset}");
     let addr = addr as usize;
-    let _ = flags; // TODO: anonymous mapping
+    // TODO: anonymous mapping
     if (prot & PROT_READ) == 0 {
         // non-readable pages can't be created on x86
         return -EINVAL;
@@ -457,8 +981,13 @@ pub fn mmap(
     let addr = addr & !(PAGE_FRAME_SIZE - 1);
     // round length up to page frame size
     let length = length.div_ceil(PAGE_FRAME_SIZE) * PAGE_FRAME_SIZE;
-    let mut root = root_filesystem().lock();
-    match root.mmap_file(addr, fd, length, offset, (prot & PROT_WRITE) != 0) {
+    let writeable = (prot & PROT_WRITE) != 0;
+    let shared = (flags & MAP_SHARED) != 0;
+    let mapped = with_running_process(|root, pcb| {
+        let vma = root.mmap_file(fd, length, offset, writeable, shared)?;
+        Ok::<_, Error>(pcb.vmas.add_vma(vma, addr))
+    });
+    match mapped {
         Ok(true) => addr as isize,
         Ok(false) => {
             // TODO: figure out an address range that is free
@@ -468,4 +997,73 @@ pub fn mmap(
     }
 }
 
-// TODO: munmap
+/// The `munmap` syscall. Changes to shared file mappings are written back first.
+pub fn munmap(addr: usize, length: usize) -> isize {
+    if addr % PAGE_FRAME_SIZE != 0 || length == 0 || length > 0x8000_0000 {
+        return -EINVAL;
+    }
+    let Some(end) = addr.checked_add(length.next_multiple_of(PAGE_FRAME_SIZE)) else {
+        return -EINVAL;
+    };
+    let pcb = running_process();
+    let mut pcb = pcb.lock();
+    let mut mappings = vec![];
+    for (vma_addr, vma) in pcb.vmas.overlapping(addr..end) {
+        // TODO: split mappings, so that only part of one can be removed
+        if vma_addr < addr || vma_addr + vma.size() > end {
+            return -EINVAL;
+        }
+        // the stack and heap aren't mappings
+        if !matches!(vma.info(), VMAInfo::MMap { .. } | VMAInfo::Shm { .. }) {
+            return -EINVAL;
+        }
+        mappings.push(vma_addr);
+    }
+    for vma_addr in mappings {
+        let vma = pcb.vmas.remove_vma(vma_addr).expect("VMA disappeared");
+        // as with Linux, errors writing back changes aren't reported
+        let _ = vma.write_back(vma_addr, vma_addr..vma_addr + vma.size());
+        // SAFETY: the VMA has been removed.
+        unsafe { vma.unmap(vma_addr) };
+        if let VMAInfo::MMap { fs, inode, .. } = vma.info() {
+            root_filesystem()
+                .lock()
+                .decrement_inode_ref_count(*fs, *inode);
+        }
+    }
+    0
+}
+
+/// The `msync` syscall: write back changes to the shared file mappings in `addr..addr + length`.
+///
+/// Changes are always written back before returning, even with `MS_ASYNC`.
+pub fn msync(addr: usize, length: usize, flags: i32) -> isize {
+    if addr % PAGE_FRAME_SIZE != 0
+        || flags & !(MS_ASYNC | MS_INVALIDATE | MS_SYNC) != 0
+        || (flags & MS_ASYNC != 0 && flags & MS_SYNC != 0)
+        || length > 0x8000_0000
+    {
+        return -EINVAL;
+    }
+    let Some(end) = addr.checked_add(length.next_multiple_of(PAGE_FRAME_SIZE)) else {
+        return -ENOMEM;
+    };
+    let pcb = running_process();
+    let pcb = pcb.lock();
+    // as with Linux, the whole range has to be mapped
+    let mut mapped_until = addr;
+    for (vma_addr, vma) in pcb.vmas.overlapping(addr..end) {
+        if vma_addr > mapped_until {
+            return -ENOMEM;
+        }
+        mapped_until = vma_addr + vma.size();
+        let pages = vma_addr.max(addr)..mapped_until.min(end);
+        if let Err(e) = vma.write_back(vma_addr, pages) {
+            return -e.to_isize();
+        }
+    }
+    if mapped_until < end {
+        return -ENOMEM;
+    }
+    0
+}
@@ -0,0 +1,426 @@
+//! Reading tar archives, e.g. the initrd.
+//!
+//! An archive is a series of 512-byte blocks. Each entry is a header block followed by its data,
+//! padded to a whole number of blocks, and the archive ends with two blocks of zeroes. Only the
+//! POSIX ustar format (and the older v7 format, which is the same without the path prefix) is
+//! understood; GNU and pax extensions for long names and large files aren't.
+
+use crate::vfs::{Error, INodeNum, INodeType, Path, Result, SimpleFileSystem};
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Size of a header, and the unit data is padded to.
+pub const TAR_BLOCK_SIZE: usize = 512;
+
+// Header fields, as byte ranges -------------------------------------------------------------------
+
+const NAME: core::ops::Range<usize> = 0..100;
+const SIZE: core::ops::Range<usize> = 124..136;
+const CHECKSUM: core::ops::Range<usize> = 148..156;
+const TYPE_FLAG: usize = 156;
+const LINK_NAME: core::ops::Range<usize> = 157..257;
+const MAGIC: core::ops::Range<usize> = 257..262;
+const PREFIX: core::ops::Range<usize> = 345..500;
+
+// -------------------------------------------------------------------------------------------------
+
+/// What a tar entry is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TarEntryType {
+    File,
+    /// A hard link to the earlier entry named by [`TarHeader::link`]
+    HardLink,
+    Symlink,
+    Directory,
+    /// Devices, FIFOs and extension headers, which are skipped
+    Other,
+}
+
+/// A parsed tar header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TarHeader {
+    /// Path of the entry, without any leading `./` or `/`, or trailing `/`
+    pub path: String,
+    pub r#type: TarEntryType,
+    /// Size of the data following the header, in bytes
+    pub size: u64,
+    /// Target of a link
+    pub link: String,
+}
+
+impl TarHeader {
+    /// Number of blocks taken up by the entry's data.
+    pub fn data_blocks(&self) -> u64 {
+        self.size.div_ceil(TAR_BLOCK_SIZE as u64)
+    }
+}
+
+fn corrupt() -> Error {
+    Error::IO(String::from("corrupt tar header"))
+}
+
+/// Read the NUL-terminated (unless it fills the field) string in `field`.
+fn string_field(field: &[u8]) -> Result<&str> {
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    core::str::from_utf8(&field[..len]).map_err(|_| corrupt())
+}
+
+/// Read the octal number in `field`, which is padded with spaces or NULs.
+fn octal_field(field: &[u8]) -> Result<u64> {
+    let digits = string_field(field)?.trim_matches(' ');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| corrupt())
+}
+
+/// Remove the `./` and `/`s which archives often have around paths.
+fn normalize(path: &str) -> String {
+    let path = path.trim_start_matches("./").trim_matches('/');
+    if path == "." {
+        String::new()
+    } else {
+        String::from(path)
+    }
+}
+
+/// Parse the header in `block`, which must be [`TAR_BLOCK_SIZE`] bytes long.
+///
+/// Returns `None` for a block of zeroes, which marks the end of the archive.
+pub fn parse_header(block: &[u8]) -> Result<Option<TarHeader>> {
+    assert_eq!(block.len(), TAR_BLOCK_SIZE);
+    if block.iter().all(|&b| b == 0) {
+        return Ok(None);
+    }
+
+    // The checksum is the sum of the header's bytes, with the checksum itself taken to be spaces.
+    let checksum: u64 = block
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| u64::from(if CHECKSUM.contains(&i) { b' ' } else { b }))
+        .sum();
+    if octal_field(&block[CHECKSUM])? != checksum {
+        return Err(corrupt());
+    }
+
+    let name = string_field(&block[NAME])?;
+    let path = if &block[MAGIC] == b"ustar" && block[PREFIX.start] != 0 {
+        let mut path = String::from(string_field(&block[PREFIX])?);
+        path.push('/');
+        path.push_str(name);
+        path
+    } else {
+        String::from(name)
+    };
+    let r#type = match block[TYPE_FLAG] {
+        b'0' | b'\0' | b'7' if path.ends_with('/') => TarEntryType::Directory,
+        b'0' | b'\0' | b'7' => TarEntryType::File,
+        b'1' => TarEntryType::HardLink,
+        b'2' => TarEntryType::Symlink,
+        b'5' => TarEntryType::Directory,
+        _ => TarEntryType::Other,
+    };
+    let link = string_field(&block[LINK_NAME])?;
+    Ok(Some(TarHeader {
+        path: normalize(&path),
+        r#type,
+        size: octal_field(&block[SIZE])?,
+        link: if r#type == TarEntryType::HardLink {
+            normalize(link)
+        } else {
+            String::from(link)
+        },
+    }))
+}
+
+/// Iterator over the entries of an archive in memory, giving each header and its data.
+pub struct TarEntries<'a> {
+    archive: &'a [u8],
+    /// Offset of the next header, or `None` once the end has been reached
+    offset: Option<usize>,
+}
+
+/// Iterate over the entries in `archive`.
+pub fn entries(archive: &[u8]) -> TarEntries<'_> {
+    TarEntries {
+        archive,
+        offset: Some(0),
+    }
+}
+
+impl<'a> Iterator for TarEntries<'a> {
+    type Item = Result<(TarHeader, &'a [u8])>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let offset = self.offset?;
+        // A missing end marker is taken as the end too.
+        let block = self.archive.get(offset..offset + TAR_BLOCK_SIZE)?;
+        let header = match parse_header(block) {
+            Ok(Some(header)) => header,
+            Ok(None) => {
+                self.offset = None;
+                return None;
+            }
+            Err(e) => {
+                self.offset = None;
+                return Some(Err(e));
+            }
+        };
+        let start = offset + TAR_BLOCK_SIZE;
+        let data = usize::try_from(header.size)
+            .ok()
+            .and_then(|size| self.archive.get(start..start.checked_add(size)?));
+        let Some(data) = data else {
+            self.offset = None;
+            return Some(Err(corrupt()));
+        };
+        self.offset = Some(start + header.data_blocks() as usize * TAR_BLOCK_SIZE);
+        Some(Ok((header, data)))
+    }
+}
+
+/// Find `name` in the directory `dir`.
+fn lookup<F: SimpleFileSystem>(
+    fs: &mut F,
+    dir: INodeNum,
+    name: &Path,
+) -> Result<Option<(INodeNum, INodeType)>> {
+    Ok(fs
+        .readdir(dir)?
+        .into_iter()
+        .find(|entry| entry.name == name)
+        .map(|entry| (entry.inode, entry.r#type)))
+}
+
+/// Find the inode at `path`, relative to the root of `fs`, without following symbolic links.
+pub fn lookup_path<F: SimpleFileSystem>(fs: &mut F, path: &Path) -> Result<INodeNum> {
+    let mut inode = fs.root();
+    let mut names = path.split('/').filter(|name| !name.is_empty()).peekable();
+    while let Some(name) = names.next() {
+        let (child, r#type) = lookup(fs, inode, name)?.ok_or(Error::NotFound)?;
+        if r#type != INodeType::Directory && names.peek().is_some() {
+            return Err(Error::NotDirectory);
+        }
+        inode = child;
+    }
+    Ok(inode)
+}
+
+/// Find the directory at `path`, relative to the root of `fs`, creating it and its parents if
+/// they don't exist.
+fn make_dirs<F: SimpleFileSystem>(fs: &mut F, path: &Path) -> Result<INodeNum> {
+    let mut inode = fs.root();
+    for name in path.split('/').filter(|name| !name.is_empty()) {
+        if name == ".." {
+            return Err(Error::InvalidArgument);
+        }
+        inode = match lookup(fs, inode, name)? {
+            Some((child, INodeType::Directory)) => child,
+            Some(_) => return Err(Error::NotDirectory),
+            None => fs.mkdir(inode, name)?,
+        };
+    }
+    Ok(inode)
+}
+
+/// Write all of `data` to the start of `file`.
+fn write_all<F: SimpleFileSystem>(fs: &mut F, file: INodeNum, data: &[u8]) -> Result<()> {
+    let mut written = 0;
+    while written < data.len() {
+        match fs.write(file, written as u64, &data[written..])? {
+            0 => return Err(Error::NoSpace),
+            n => written += n,
+        }
+    }
+    Ok(())
+}
+
+/// Extract every entry of `archive` into `fs`, creating directories as needed. Files which
+/// already exist are overwritten.
+pub fn unpack<F: SimpleFileSystem>(fs: &mut F, archive: &[u8]) -> Result<()> {
+    for entry in entries(archive) {
+        let (header, data) = entry?;
+        if header.r#type == TarEntryType::Directory {
+            make_dirs(fs, &header.path)?;
+            continue;
+        }
+        let (parent, name) = header.path.rsplit_once('/').unwrap_or(("", &header.path));
+        if name.is_empty() || name == ".." {
+            return Err(Error::InvalidArgument);
+        }
+        let parent = make_dirs(fs, parent)?;
+        match header.r#type {
+            TarEntryType::File => {
+                let file = match lookup(fs, parent, name)? {
+                    Some((file, INodeType::File)) => file,
+                    Some(_) => return Err(Error::Exists),
+                    None => fs.create(parent, name)?,
+                };
+                fs.open(file)?;
+                let result = fs
+                    .truncate(file, 0)
+                    .and_then(|()| write_all(fs, file, data));
+                fs.release(file);
+                result?;
+            }
+            TarEntryType::HardLink => {
+                let source = lookup_path(fs, &header.link)?;
+                fs.link(source, parent, name)?;
+            }
+            TarEntryType::Symlink => {
+                fs.symlink(&header.link, parent, name)?;
+            }
+            TarEntryType::Directory | TarEntryType::Other => {}
+        }
+    }
+    Ok(())
+}
+
+/// Read the whole file at `path`, relative to the root of `fs`.
+pub fn read_file<F: SimpleFileSystem>(fs: &mut F, path: &Path) -> Result<Vec<u8>> {
+    let file = lookup_path(fs, path)?;
+    let info = fs.stat(file)?;
+    if info.r#type != INodeType::File {
+        return Err(Error::IsDirectory);
+    }
+    fs.open(file)?;
+    let mut data = vec![0; info.size as usize];
+    let mut read = 0;
+    let result = loop {
+        if read == data.len() {
+            break Ok(());
+        }
+        match fs.read(file, read as u64, &mut data[read..]) {
+            Ok(0) => break Ok(()),
+            Ok(n) => read += n,
+            Err(e) => break Err(e),
+        }
+    };
+    fs.release(file);
+    result?;
+    data.truncate(read);
+    Ok(data)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::vfs::tempfs::TempFS;
+
+    /// Build a ustar archive of `entries`, given as (path, type flag, link target, data).
+    pub fn archive(entries: &[(&str, u8, &str, &[u8])]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        for &(path, type_flag, link, data) in entries {
+            let mut header = [0u8; TAR_BLOCK_SIZE];
+            header[NAME][..path.len()].copy_from_slice(path.as_bytes());
+            header[100..108].copy_from_slice(b"0000644\0");
+            header[SIZE].copy_from_slice(format!("{:011o}\0", data.len()).as_bytes());
+            header[TYPE_FLAG] = type_flag;
+            header[LINK_NAME][..link.len()].copy_from_slice(link.as_bytes());
+            header[MAGIC].copy_from_slice(b"ustar");
+            header[262..265].copy_from_slice(b"\x0000");
+            header[CHECKSUM].fill(b' ');
+            let checksum: u32 = header.iter().map(|&b| b as u32).sum();
+            header[CHECKSUM].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+            archive.extend_from_slice(&header);
+            archive.extend_from_slice(data);
+            archive.resize(archive.len().next_multiple_of(TAR_BLOCK_SIZE), 0);
+        }
+        archive.resize(archive.len() + 2 * TAR_BLOCK_SIZE, 0);
+        archive
+    }
+
+    #[test]
+    fn headers() {
+        let archive = archive(&[
+            ("./", b'5', "", b""),
+            ("./bin/", b'5', "", b""),
+            ("./bin/hello", b'0', "", b"hello, world\n"),
+        ]);
+        assert_eq!(archive.len(), 6 * TAR_BLOCK_SIZE);
+        let entries: Vec<_> = entries(&archive).collect::<Result<_>>().unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].0.path, "");
+        assert_eq!(entries[1].0.path, "bin");
+        assert_eq!(entries[1].0.r#type, TarEntryType::Directory);
+        assert_eq!(
+            entries[2].0,
+            TarHeader {
+                path: "bin/hello".into(),
+                r#type: TarEntryType::File,
+                size: 13,
+                link: String::new(),
+            }
+        );
+        assert_eq!(entries[2].1, b"hello, world\n");
+
+        let mut corrupted = archive.clone();
+        corrupted[TAR_BLOCK_SIZE] ^= 1;
+        let results: Vec<_> = super::entries(&corrupted).collect();
+        assert_eq!(results.len(), 2);
+        assert!(results[1].is_err());
+    }
+
+    #[test]
+    fn unpack_into_tempfs() {
+        let archive = archive(&[
+            ("init", b'0', "", b"\x7fELF not really"),
+            ("etc/", b'5', "", b""),
+            ("etc/motd", b'0', "", b"welcome\n"),
+            // parents which aren't in the archive are created
+            ("usr/share/doc/README", b'0', "", &[b'x'; 1000]),
+            ("usr/share/doc/LINK", b'1', "usr/share/doc/README", b""),
+            ("bin", b'2', "usr/bin", b""),
+            ("dev/null", b'3', "", b""),
+        ]);
+        let mut fs = TempFS::new();
+        unpack(&mut fs, &archive).unwrap();
+
+        let root = fs.root();
+        let names: Vec<_> = fs
+            .readdir(root)
+            .unwrap()
+            .to_sorted_vec()
+            .into_iter()
+            .map(|entry| (entry.name.into_owned(), entry.r#type))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("bin".into(), INodeType::Link),
+                ("dev".into(), INodeType::Directory),
+                ("etc".into(), INodeType::Directory),
+                ("init".into(), INodeType::File),
+                ("usr".into(), INodeType::Directory),
+            ]
+        );
+        assert_eq!(read_file(&mut fs, "/init").unwrap(), b"\x7fELF not really");
+        assert_eq!(read_file(&mut fs, "/etc/motd").unwrap(), b"welcome\n");
+        assert_eq!(
+            read_file(&mut fs, "/usr/share/doc/README").unwrap(),
+            [b'x'; 1000]
+        );
+        let readme = lookup_path(&mut fs, "/usr/share/doc/README").unwrap();
+        assert_eq!(lookup_path(&mut fs, "/usr/share/doc/LINK").unwrap(), readme);
+        assert_eq!(fs.stat(readme).unwrap().nlink, 2);
+        let bin = lookup_path(&mut fs, "/bin").unwrap();
+        let mut buf = [0; 16];
+        assert_eq!(
+            fs.readlink_no_alloc(bin, &mut buf).unwrap(),
+            Some("usr/bin")
+        );
+        // device nodes are skipped
+        let dev = lookup_path(&mut fs, "/dev").unwrap();
+        assert!(fs.readdir(dev).unwrap().entries.is_empty());
+        assert!(matches!(
+            read_file(&mut fs, "/etc/missing"),
+            Err(Error::NotFound)
+        ));
+
+        // unpacking again overwrites the files
+        let update = super::test::archive(&[("etc/motd", b'0', "", b"hi\n")]);
+        unpack(&mut fs, &update).unwrap();
+        assert_eq!(read_file(&mut fs, "/etc/motd").unwrap(), b"hi\n");
+    }
+}
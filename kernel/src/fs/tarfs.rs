@@ -0,0 +1,354 @@
+use crate::block::block_core::{Block, BlockSector, BLOCK_SECTOR_SIZE};
+use crate::fs::tar::{parse_header, TarEntryType, TarHeader, TAR_BLOCK_SIZE};
+use crate::vfs::{
+    DirEntries, Error, FileInfo, INodeNum, INodeType, Path, Result, SimpleFileSystem,
+};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::{vec, vec::Vec};
+use core::cmp::min;
+
+const ROOT_INO: INodeNum = 1;
+
+enum TarNode {
+    /// `size` bytes of data, starting at sector `start`
+    File {
+        start: BlockSector,
+        size: u64,
+    },
+    Directory(BTreeMap<String, INodeNum>),
+    Symlink(String),
+}
+
+struct TarINode {
+    node: TarNode,
+    nlink: u32,
+}
+
+impl TarINode {
+    fn new(node: TarNode) -> Self {
+        Self { node, nlink: 1 }
+    }
+    fn r#type(&self) -> INodeType {
+        match self.node {
+            TarNode::File { .. } => INodeType::File,
+            TarNode::Directory(_) => INodeType::Directory,
+            TarNode::Symlink(_) => INodeType::Link,
+        }
+    }
+}
+
+/// Read-only file system over a tar archive, serving files straight out of the archive rather
+/// than unpacking it.
+///
+/// Tar has no index, so the headers are all read the first time anything is looked up, but file
+/// data is only read when it's asked for. Directories which only appear in the paths of other
+/// entries are filled in.
+pub struct TarFS {
+    block: Block,
+    /// Inode `n` is at index `n - 1`
+    inodes: Vec<TarINode>,
+    /// Whether the headers have been read yet
+    scanned: bool,
+}
+
+impl TarFS {
+    /// Use the tar archive on `block`. Only its first header is read, to check that it's an
+    /// archive at all.
+    pub fn new(block: Block) -> Result<Self> {
+        let mut first = [0; TAR_BLOCK_SIZE];
+        block.read(0, &mut first)?;
+        parse_header(&first).map_err(|_| Error::Unsupported)?;
+        Ok(Self {
+            block,
+            inodes: vec![TarINode::new(TarNode::Directory(BTreeMap::new()))],
+            scanned: false,
+        })
+    }
+
+    fn get(&self, inode: INodeNum) -> Result<&TarINode> {
+        let index = (inode as usize).checked_sub(1).ok_or(Error::NotFound)?;
+        self.inodes.get(index).ok_or(Error::NotFound)
+    }
+
+    fn add(&mut self, node: TarNode) -> INodeNum {
+        self.inodes.push(TarINode::new(node));
+        self.inodes.len() as INodeNum
+    }
+
+    /// Entries of the directory `dir`, found while scanning.
+    fn entries_mut(&mut self, dir: INodeNum) -> &mut BTreeMap<String, INodeNum> {
+        match &mut self.inodes[dir as usize - 1].node {
+            TarNode::Directory(entries) => entries,
+            _ => unreachable!("inode {dir} should be a directory"),
+        }
+    }
+
+    /// Find the directory at `path`, creating it and its parents if they haven't been seen yet.
+    fn make_dirs(&mut self, path: &Path) -> Result<INodeNum> {
+        let mut dir = ROOT_INO;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            let existing = self.entries_mut(dir).get(name).copied();
+            dir = match existing {
+                Some(child) if self.get(child)?.r#type() == INodeType::Directory => child,
+                Some(_) => return Err(Error::NotDirectory),
+                None => {
+                    let child = self.add(TarNode::Directory(BTreeMap::new()));
+                    self.entries_mut(dir).insert(String::from(name), child);
+                    child
+                }
+            };
+        }
+        Ok(dir)
+    }
+
+    /// Find the inode at `path`, which was added earlier in the scan.
+    fn lookup_path(&self, path: &Path) -> Result<INodeNum> {
+        let mut inode = ROOT_INO;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            let TarNode::Directory(entries) = &self.get(inode)?.node else {
+                return Err(Error::NotDirectory);
+            };
+            inode = *entries.get(name).ok_or(Error::NotFound)?;
+        }
+        Ok(inode)
+    }
+
+    /// Add the entry described by `header`, whose data starts at sector `data_start`.
+    fn add_entry(&mut self, header: TarHeader, data_start: BlockSector) -> Result<()> {
+        if header.r#type == TarEntryType::Directory {
+            self.make_dirs(&header.path)?;
+            return Ok(());
+        }
+        let (parent, name) = header.path.rsplit_once('/').unwrap_or(("", &header.path));
+        if name.is_empty() || name == ".." || parent.split('/').any(|name| name == "..") {
+            return Err(Error::IO(String::from("bad path in tar archive")));
+        }
+        let inode = match header.r#type {
+            TarEntryType::File => self.add(TarNode::File {
+                start: data_start,
+                size: header.size,
+            }),
+            TarEntryType::Symlink => self.add(TarNode::Symlink(header.link)),
+            TarEntryType::HardLink => {
+                let inode = self.lookup_path(&header.link)?;
+                self.inodes[inode as usize - 1].nlink += 1;
+                inode
+            }
+            TarEntryType::Directory | TarEntryType::Other => return Ok(()),
+        };
+        let parent = self.make_dirs(parent)?;
+        // later entries replace earlier ones with the same path, as when extracting
+        self.entries_mut(parent).insert(String::from(name), inode);
+        Ok(())
+    }
+
+    /// Read every header in the archive, if that hasn't been done yet.
+    fn scan(&mut self) -> Result<()> {
+        if self.scanned {
+            return Ok(());
+        }
+        let mut sector: BlockSector = 0;
+        let mut block = [0; TAR_BLOCK_SIZE];
+        // A missing end marker is taken as the end too.
+        while sector < self.block.get_size() {
+            self.block.read(sector, &mut block)?;
+            // The archive ends with two blocks of zeroes, but the first is enough to stop at.
+            let Some(header) = parse_header(&block)? else {
+                break;
+            };
+            let data_start = sector + 1;
+            sector = BlockSector::try_from(header.data_blocks())
+                .ok()
+                .and_then(|blocks| data_start.checked_add(blocks))
+                .filter(|&end| end <= self.block.get_size())
+                .ok_or_else(|| Error::IO(String::from("tar entry runs past the end")))?;
+            self.add_entry(header, data_start)?;
+        }
+        self.scanned = true;
+        Ok(())
+    }
+}
+
+impl SimpleFileSystem for TarFS {
+    fn root(&self) -> INodeNum {
+        ROOT_INO
+    }
+
+    fn open(&mut self, inode: INodeNum) -> Result<()> {
+        self.scan()?;
+        self.get(inode).map(|_| ())
+    }
+
+    fn readdir(&mut self, dir: INodeNum) -> Result<DirEntries> {
+        self.scan()?;
+        let TarNode::Directory(entries) = &self.get(dir)?.node else {
+            return Err(Error::NotDirectory);
+        };
+        let mut dir_entries = DirEntries::new();
+        for (name, &inode) in entries {
+            dir_entries.add(inode, self.get(inode)?.r#type(), name);
+        }
+        Ok(dir_entries)
+    }
+
+    fn read(&mut self, file: INodeNum, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        self.scan()?;
+        let TarNode::File { start, size } = self.get(file)?.node else {
+            return Err(Error::IsDirectory);
+        };
+        if offset >= size {
+            return Ok(0);
+        }
+        // fits in usize since it's at most buf.len()
+        let len = min(buf.len() as u64, size - offset) as usize;
+        let skip = (offset % BLOCK_SECTOR_SIZE as u64) as usize;
+        let first = start + (offset / BLOCK_SECTOR_SIZE as u64) as BlockSector;
+        let count = (skip + len).div_ceil(BLOCK_SECTOR_SIZE);
+        let mut data = vec![0; count * BLOCK_SECTOR_SIZE];
+        self.block
+            .read_sectors(first, count as BlockSector, &mut data)?;
+        buf[..len].copy_from_slice(&data[skip..skip + len]);
+        Ok(len)
+    }
+
+    fn stat(&mut self, file: INodeNum) -> Result<FileInfo> {
+        self.scan()?;
+        let inode = self.get(file)?;
+        Ok(FileInfo {
+            r#type: inode.r#type(),
+            inode: file,
+            size: match &inode.node {
+                TarNode::File { size, .. } => *size,
+                TarNode::Directory(_) => 0,
+                TarNode::Symlink(link) => link.len() as u64,
+            },
+            nlink: inode.nlink,
+        })
+    }
+
+    fn readlink(&mut self, link: INodeNum) -> Result<String> {
+        self.scan()?;
+        match &self.get(link)?.node {
+            TarNode::Symlink(link) => Ok(link.clone()),
+            _ => Err(Error::NotLink),
+        }
+    }
+
+    fn create(&mut self, _parent: INodeNum, _name: &Path) -> Result<INodeNum> {
+        Err(Error::ReadOnlyFS)
+    }
+
+    fn mkdir(&mut self, _parent: INodeNum, _name: &Path) -> Result<INodeNum> {
+        Err(Error::ReadOnlyFS)
+    }
+
+    fn unlink(&mut self, _parent: INodeNum, _name: &Path) -> Result<()> {
+        Err(Error::ReadOnlyFS)
+    }
+
+    fn rmdir(&mut self, _parent: INodeNum, _name: &Path) -> Result<()> {
+        Err(Error::ReadOnlyFS)
+    }
+
+    fn write(&mut self, _file: INodeNum, _offset: u64, _buf: &[u8]) -> Result<usize> {
+        Err(Error::ReadOnlyFS)
+    }
+
+    fn link(&mut self, _source: INodeNum, _parent: INodeNum, _name: &Path) -> Result<()> {
+        Err(Error::ReadOnlyFS)
+    }
+
+    fn symlink(&mut self, _link: &Path, _parent: INodeNum, _name: &Path) -> Result<INodeNum> {
+        Err(Error::ReadOnlyFS)
+    }
+
+    fn truncate(&mut self, _file: INodeNum, _size: u64) -> Result<()> {
+        Err(Error::ReadOnlyFS)
+    }
+
+    fn fallocate(&mut self, _file: INodeNum, _offset: u64, _len: u64) -> Result<()> {
+        Err(Error::ReadOnlyFS)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::block_core::test::block_from_file;
+    use crate::fs::tar::test::archive;
+    use crate::vfs::read_only_test::{expect_tree, ExpectedTree};
+    use std::io::Cursor;
+
+    #[test]
+    fn nested_directories() {
+        let big: Vec<u8> = (0..2000).map(|i| (i % 251) as u8).collect();
+        let archive = archive(&[
+            ("./", b'5', "", b""),
+            ("./top", b'0', "", b"top level\n"),
+            ("./a/", b'5', "", b""),
+            ("./a/b/", b'5', "", b""),
+            ("./a/b/c/nested", b'0', "", b"deep inside\n"),
+            ("./a/b/big", b'0', "", &big),
+            ("./a/b/hard", b'1', "./a/b/big", b""),
+            ("./a/up", b'2', "../top", b""),
+        ]);
+        let mut fs = TarFS::new(block_from_file(Cursor::new(archive))).unwrap();
+        // nothing but the first header is read until the file system is used
+        assert!(!fs.scanned);
+
+        let root = fs.root();
+        let nested = fs.readdir(root).unwrap();
+        assert!(fs.scanned);
+        let a = nested.into_iter().find(|e| e.name == "a").unwrap().inode;
+        let b = fs.lookup_path("a/b").unwrap();
+        assert!(fs.readdir(a).unwrap().into_iter().any(|e| e.inode == b));
+        let file = fs.lookup_path("a/b/c/nested").unwrap();
+        let mut buf = [0; 64];
+        let n = SimpleFileSystem::read(&mut fs, file, 0, &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"deep inside\n");
+        // reads which don't start or end on a sector boundary
+        let big_inode = fs.lookup_path("a/b/big").unwrap();
+        let n = SimpleFileSystem::read(&mut fs, big_inode, 500, &mut buf).unwrap();
+        assert_eq!(&buf[..n], &big[500..564]);
+        assert_eq!(SimpleFileSystem::stat(&mut fs, big_inode).unwrap().nlink, 2);
+        assert!(matches!(
+            SimpleFileSystem::create(&mut fs, b, "new"),
+            Err(Error::ReadOnlyFS)
+        ));
+
+        let file = |contents: &[u8]| ExpectedTree::File(contents.to_vec());
+        let dir = |entries: Vec<(&str, ExpectedTree)>| {
+            ExpectedTree::Directory(
+                entries
+                    .into_iter()
+                    .map(|(name, tree)| (String::from(name), tree))
+                    .collect(),
+            )
+        };
+        let expected = dir(vec![
+            ("top", file(b"top level\n")),
+            (
+                "a",
+                dir(vec![
+                    (
+                        "b",
+                        dir(vec![
+                            ("c", dir(vec![("nested", file(b"deep inside\n"))])),
+                            ("big", file(&big)),
+                            ("hard", file(&big)),
+                        ]),
+                    ),
+                    ("up", ExpectedTree::Link(String::from("../top"))),
+                ]),
+            ),
+        ]);
+        expect_tree(&mut fs, &expected);
+    }
+
+    #[test]
+    fn not_an_archive() {
+        let block = block_from_file(Cursor::new(vec![0xAB; 4 * BLOCK_SECTOR_SIZE]));
+        assert!(matches!(TarFS::new(block), Err(Error::Unsupported)));
+    }
+}
@@ -0,0 +1,394 @@
+use crate::interrupts::{intr_disable, intr_enable};
+use crate::sync::mutex::{Mutex, MutexGuard};
+use crate::system::{running_thread_tid, unwrap_system};
+use crate::threading::process::{Pid, Tid};
+use crate::threading::thread_sleep::{thread_sleep, thread_wakeup};
+use crate::threading::work_queue::schedule_work;
+use crate::vfs::{Error, Result};
+use alloc::{boxed::Box, collections::VecDeque, format, string::String, vec::Vec};
+use core::cmp::min;
+use core::fmt::{Debug, Formatter};
+use core::mem::take;
+use kidneyos_shared::video_memory::VIDEO_MEMORY_WRITER;
+
+/// Somewhere a [`Tty`] can display its output.
+pub trait Console: Send {
+    /// Display `bytes`.
+    fn write(&mut self, bytes: &[u8]) -> core::fmt::Result;
+    /// Erase the last character displayed.
+    fn backspace(&mut self);
+}
+
+/// The VGA text mode console.
+pub struct VideoConsole;
+
+impl Console for VideoConsole {
+    fn write(&mut self, bytes: &[u8]) -> core::fmt::Result {
+        use core::fmt::Write;
+        let string = String::from_utf8_lossy(bytes);
+        // SAFETY: no other mut references to VIDEO_MEMORY_WRITER here
+        unsafe { VIDEO_MEMORY_WRITER.write_str(&string) }
+    }
+    fn backspace(&mut self) {
+        // SAFETY: no other mut references to VIDEO_MEMORY_WRITER here
+        unsafe { VIDEO_MEMORY_WRITER.backspace() }
+    }
+}
+
+/// Ctrl-C
+const INTR: u8 = 0x03;
+
+struct TtyInner {
+    console: Box<dyn Console>,
+    /// Whether the terminal is in canonical mode (see [`Tty`])
+    canonical: bool,
+    /// The line currently being typed, which can't be read yet
+    line: Vec<u8>,
+    /// Input waiting to be read
+    input: VecDeque<u8>,
+    /// Process group which gets interrupted by Ctrl-C
+    foreground: Option<Pid>,
+    /// Process group which Ctrl-C has been typed at, but which hasn't been interrupted yet
+    interrupt: Option<Pid>,
+    /// Threads sleeping in [`Tty::read`] until there's input
+    readers: Vec<Tid>,
+}
+
+impl TtyInner {
+    /// Returns the process group which Ctrl-C interrupts, if `c` is Ctrl-C.
+    fn receive(&mut self, c: u8) -> Option<Pid> {
+        if !self.canonical {
+            self.input.push_back(c);
+            return None;
+        }
+        match c {
+            b'\r' | b'\n' => {
+                self.line.push(b'\n');
+                self.input.extend(self.line.drain(..));
+                let _ = self.console.write(b"\n");
+            }
+            // BS (Backspace) or DEL (Delete)
+            0x08 | 0x7F => {
+                if self.line.pop().is_some() {
+                    self.console.backspace();
+                }
+            }
+            INTR => {
+                self.line.clear();
+                let _ = self.console.write(b"^C\n");
+                if let Some(pgid) = self.foreground {
+                    self.interrupt = Some(pgid);
+                }
+                return self.foreground;
+            }
+            _ => {
+                self.line.push(c);
+                let _ = self.console.write(&[c]);
+            }
+        }
+        None
+    }
+}
+
+/// A terminal, which reads from the keyboard and writes to a [`Console`].
+///
+/// By default, it's in canonical mode: input is echoed back to the console and can be edited with
+/// backspace, and only becomes available to readers a line at a time, once Enter is pressed.
+/// Ctrl-C throws away the line being typed, and interrupts the foreground process group.
+///
+/// In non-canonical (raw) mode, every byte of input can be read as soon as it arrives, without
+/// being echoed or interpreted at all.
+pub struct Tty(Mutex<TtyInner>);
+
+impl Tty {
+    pub fn new(console: Box<dyn Console>) -> Self {
+        Self(Mutex::new(TtyInner {
+            console,
+            canonical: true,
+            line: Vec::new(),
+            input: VecDeque::new(),
+            foreground: None,
+            interrupt: None,
+            readers: Vec::new(),
+        }))
+    }
+
+    /// Switch between canonical and raw mode.
+    ///
+    /// Anything typed on the current line becomes readable when switching to raw mode.
+    pub fn set_canonical(&self, canonical: bool) {
+        let mut inner = self.0.lock();
+        let inner = &mut *inner;
+        if !canonical {
+            inner.input.extend(inner.line.drain(..));
+        }
+        inner.canonical = canonical;
+    }
+
+    /// The process group which Ctrl-C interrupts, if any.
+    pub fn foreground(&self) -> Option<Pid> {
+        self.0.lock().foreground
+    }
+
+    /// Make `pgid` the process group which Ctrl-C interrupts.
+    pub fn set_foreground(&self, pgid: Option<Pid>) {
+        self.0.lock().foreground = pgid;
+    }
+
+    /// Get the process group which Ctrl-C was typed at since the last call, if any.
+    ///
+    /// [`receive`](Self::receive) only records Ctrl-C, and it's up to the caller to actually
+    /// interrupt the processes in the group.
+    pub fn take_interrupt(&self) -> Option<Pid> {
+        self.0.lock().interrupt.take()
+    }
+
+    /// Whether there's input which [`Tty::read`] would return without waiting.
+    pub fn has_input(&self) -> bool {
+        !self.0.lock().input.is_empty()
+    }
+
+    /// Handle a byte of keyboard input.
+    pub fn receive(&self, c: u8) {
+        let mut inner = self.0.lock();
+        inner.receive(c);
+        wake_readers(inner);
+    }
+
+    /// Handle keyboard input from `input`. Returns `None`, without taking anything from `input`,
+    /// if the terminal is locked, and otherwise the process group Ctrl-C was typed at, if it was.
+    pub fn try_receive(&self, input: impl Iterator<Item = u8>) -> Option<Option<Pid>> {
+        let mut inner = self.0.try_lock()?;
+        let mut interrupt = None;
+        for c in input {
+            interrupt = inner.receive(c).or(interrupt);
+        }
+        wake_readers(inner);
+        Some(interrupt)
+    }
+
+    /// Read input into `buf`, waiting until there is some.
+    ///
+    /// In canonical mode, this waits for a line to be entered, and reads at most one line.
+    ///
+    /// Returns [`Error::Interrupted`] if Ctrl-C is typed while waiting, so that the reader can
+    /// get back to the syscall handler and be interrupted if it's in the foreground.
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if let Some(result) = self.read_or_queue(buf, None) {
+            return result;
+        }
+        let tid = running_thread_tid();
+        loop {
+            // Interrupts are off from finding no input until blocking, so input can't come in,
+            // and wake this thread, before it's blocked.
+            intr_disable();
+            let result = self.read_or_queue(buf, Some(tid));
+            if result.is_none() {
+                thread_sleep();
+            }
+            intr_enable();
+            if let Some(result) = result {
+                return result;
+            }
+        }
+    }
+
+    /// Read input into `buf` if there is some, or otherwise queue `reader` to be woken when
+    /// there is, and return `None`.
+    fn read_or_queue(&self, buf: &mut [u8], reader: Option<Tid>) -> Option<Result<usize>> {
+        let mut inner = self.0.lock();
+        if inner.interrupt.is_some() {
+            return Some(Err(Error::Interrupted));
+        }
+        if inner.input.is_empty() {
+            inner.readers.extend(reader);
+            return None;
+        }
+        let available = if inner.canonical {
+            inner
+                .input
+                .iter()
+                .position(|&c| c == b'\n')
+                .map_or(inner.input.len(), |i| i + 1)
+        } else {
+            inner.input.len()
+        };
+        let n = min(buf.len(), available);
+        for (dst, src) in buf.iter_mut().zip(inner.input.drain(..n)) {
+            *dst = src;
+        }
+        Some(Ok(n))
+    }
+
+    /// Write `buf` to the console.
+    pub fn write(&self, buf: &[u8]) -> Result<usize> {
+        match self.0.lock().console.write(buf) {
+            Ok(()) => Ok(buf.len()),
+            Err(e) => Err(Error::IO(format!("{e}"))),
+        }
+    }
+}
+
+/// Lets programs doing their own line editing in raw mode (e.g. the shell) erase characters.
+impl Console for &Tty {
+    fn write(&mut self, bytes: &[u8]) -> core::fmt::Result {
+        self.0.lock().console.write(bytes)
+    }
+    fn backspace(&mut self) {
+        self.0.lock().console.backspace();
+    }
+}
+
+/// Wake the threads waiting in [`Tty::read`], after unlocking `inner`.
+fn wake_readers(mut inner: MutexGuard<TtyInner>) {
+    let readers = take(&mut inner.readers);
+    drop(inner);
+    for tid in readers {
+        thread_wakeup(tid);
+    }
+}
+
+/// Softirq handler which passes keyboard input from the input buffer on to the terminal.
+///
+/// The keyboard interrupt handler only puts input in the buffer, since it could interrupt a thread
+/// holding the terminal's lock. This could run while a preempted thread holds it too, so if it's
+/// locked, the input is left in the buffer until the next tick.
+///
+/// Ctrl-C interrupts the foreground process group from the work queue, for the same reason,
+/// rather than when a process next makes a syscall, so processes sleeping in e.g. `pause` are woken
+/// even if nothing else is running. It stays pending here until then, so readers see it too.
+pub fn receive_keyboard_input() {
+    let system = unwrap_system();
+    let interrupt = system.tty.try_receive(core::iter::from_fn(|| {
+        // SAFETY: Softirqs run one at a time, and nothing else takes input out of the buffer.
+        unsafe { system.input_buffer.getc() }
+    }));
+    if let Some(Some(pgid)) = interrupt {
+        schedule_work(move || {
+            unwrap_system().process.table.interrupt_group(pgid);
+        });
+    }
+}
+
+impl Debug for Tty {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Tty")
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use alloc::sync::Arc;
+
+    /// Console which saves everything written to it, for testing.
+    #[derive(Clone, Default)]
+    pub struct BufferConsole(pub Arc<Mutex<Vec<u8>>>);
+
+    impl Console for BufferConsole {
+        fn write(&mut self, bytes: &[u8]) -> core::fmt::Result {
+            self.0.lock().extend_from_slice(bytes);
+            Ok(())
+        }
+        fn backspace(&mut self) {
+            self.0.lock().pop();
+        }
+    }
+
+    fn test_tty() -> (Tty, BufferConsole) {
+        let console = BufferConsole::default();
+        (Tty::new(Box::new(console.clone())), console)
+    }
+
+    fn receive_all(tty: &Tty, input: &[u8]) {
+        for &c in input {
+            tty.receive(c);
+        }
+    }
+
+    #[test]
+    fn canonical_backspace() {
+        let (tty, console) = test_tty();
+        receive_all(&tty, b"ab\x08c\n");
+        let mut buf = [0; 16];
+        let n = tty.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"ac\n");
+        assert_eq!(&console.0.lock()[..], b"ac\n");
+        // backspace at the start of a line does nothing
+        receive_all(&tty, b"\x7f\x7fd\x7fe\r");
+        let n = tty.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"e\n");
+    }
+
+    #[test]
+    fn canonical_ctrl_c() {
+        let (tty, console) = test_tty();
+        receive_all(&tty, b"rm -r /\x03ls\n");
+        let mut buf = [0; 16];
+        let n = tty.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"ls\n");
+        assert_eq!(&console.0.lock()[..], b"rm -r /^C\nls\n");
+    }
+
+    #[test]
+    fn raw_mode() {
+        let (tty, console) = test_tty();
+        receive_all(&tty, b"ab");
+        tty.set_canonical(false);
+        receive_all(&tty, b"\x08\x03\n");
+        let mut buf = [0; 16];
+        let n = tty.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"ab\x08\x03\n");
+        // only input typed in canonical mode is echoed
+        assert_eq!(&console.0.lock()[..], b"ab");
+        tty.set_canonical(true);
+        receive_all(&tty, b"x\n");
+        let n = tty.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"x\n");
+    }
+
+    #[test]
+    fn input_waits_while_locked() {
+        let (tty, console) = test_tty();
+        let mut input = b"ls\n".iter().copied();
+        {
+            let _inner = tty.0.lock();
+            assert_eq!(tty.try_receive(&mut input), None);
+        }
+        // it's left for next time, rather than lost
+        assert_eq!(tty.try_receive(&mut input), Some(None));
+        let mut buf = [0; 16];
+        let n = tty.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"ls\n");
+        assert_eq!(&console.0.lock()[..], b"ls\n");
+    }
+
+    #[test]
+    fn ctrl_c_targets_foreground_group() {
+        let (tty, _) = test_tty();
+        // nothing is interrupted without a foreground process group
+        receive_all(&tty, b"\x03");
+        assert_eq!(tty.take_interrupt(), None);
+
+        // the shell (group 1) puts its child in group 2 and makes that the foreground
+        tty.set_foreground(Some(2));
+        receive_all(&tty, b"sleep\x03");
+        let mut buf = [0; 16];
+        assert!(matches!(tty.read(&mut buf), Err(Error::Interrupted)));
+        assert_eq!(tty.take_interrupt(), Some(2));
+        assert_eq!(tty.take_interrupt(), None);
+
+        // once the interrupt is handled, reading works again
+        receive_all(&tty, b"ls\n");
+        let n = tty.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"ls\n");
+
+        // back to the shell
+        tty.set_foreground(Some(1));
+        receive_all(&tty, b"\x03");
+        assert_eq!(tty.take_interrupt(), Some(1));
+    }
+}
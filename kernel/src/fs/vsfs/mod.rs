@@ -477,13 +477,17 @@ impl SimpleFileSystem for VSFS {
     fn truncate(&mut self, _file: INodeNum, _size: u64) -> Result<()> {
         Err(Error::ReadOnlyFS)
     }
+
+    fn fallocate(&mut self, _file: INodeNum, _offset: u64, _len: u64) -> Result<()> {
+        Err(Error::ReadOnlyFS)
+    }
 }
 
 #[allow(dead_code, unused_variables)]
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::block::block_core::test::block_from_file;
+    use crate::block::block_core::test::{block_from_file, GzBlockDevice};
     use crate::vfs::Error::Unsupported;
     use crate::vfs::OwnedDirEntry;
     use core::mem::size_of;
@@ -584,6 +588,22 @@ mod test {
         Err(Unsupported)
     }
 
+    #[test]
+    fn superblock_from_gz_image() {
+        let block = GzBlockDevice::open("tests/vsfs/vsfs-1file.disk.gz")
+            .unwrap()
+            .into_block();
+        assert_eq!(block.get_size(), 2048);
+        let vsfs = VSFS::new(block).unwrap();
+        assert_eq!(vsfs.superblock.magic_number, VSFS_MAGIC);
+        assert_eq!(vsfs.superblock.fs_size, 1 << 20);
+        assert_eq!(vsfs.superblock.num_inodes, 64);
+        assert_eq!(vsfs.superblock.free_inodes, 62);
+        assert_eq!(vsfs.superblock.num_blocks, 256);
+        assert_eq!(vsfs.superblock.free_blocks, 250);
+        assert_eq!(vsfs.superblock.data_start, 4);
+    }
+
     // #[test]
     fn test_1file() {
         let image_path = "tests/vsfs/vsfs-1file.disk";
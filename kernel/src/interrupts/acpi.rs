@@ -0,0 +1,344 @@
+// https://wiki.osdev.org/RSDP
+// https://wiki.osdev.org/RSDT
+// https://wiki.osdev.org/MADT
+
+use zerocopy::little_endian::{U16, U32};
+use zerocopy::{FromBytes, FromZeroes, Unaligned};
+
+/// Where the BIOS can put the RSDP, which is found by searching for its signature.
+pub const RSDP_SEARCH_START: usize = 0xE0000;
+pub const RSDP_SEARCH_END: usize = 0x100000;
+
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+const MADT_SIGNATURE: &[u8; 4] = b"APIC";
+
+/// Number of ISA IRQs, which are routed to the IO APIC's inputs in order unless the MADT says
+/// otherwise.
+pub const ISA_IRQS: usize = 16;
+
+/// The part of the RSDP from ACPI 1.0, which is all we need, since we use the RSDT and not the
+/// XSDT.
+#[repr(C)]
+#[derive(FromZeroes, FromBytes, Unaligned)]
+struct Rsdp {
+    signature: [u8; 8],
+    _checksum: u8,
+    _oem_id: [u8; 6],
+    _revision: u8,
+    rsdt_address: U32,
+}
+
+/// Header at the start of every ACPI table.
+#[repr(C)]
+#[derive(FromZeroes, FromBytes, Unaligned)]
+struct SdtHeader {
+    signature: [u8; 4],
+    length: U32,
+    _revision: u8,
+    _checksum: u8,
+    _oem_id: [u8; 6],
+    _oem_table_id: [u8; 8],
+    _oem_revision: U32,
+    _creator_id: U32,
+    _creator_revision: U32,
+}
+
+#[repr(C)]
+#[derive(FromZeroes, FromBytes, Unaligned)]
+struct MadtHeader {
+    header: SdtHeader,
+    local_apic_address: U32,
+    flags: U32,
+}
+
+/// MADT flag saying there are also legacy 8259 PICs, which have to be masked.
+const MADT_PCAT_COMPAT: u32 = 1;
+
+const MADT_IO_APIC: u8 = 1;
+const MADT_INTERRUPT_SOURCE_OVERRIDE: u8 = 2;
+
+#[repr(C)]
+#[derive(FromZeroes, FromBytes, Unaligned)]
+struct MadtIoApic {
+    _type: u8,
+    _length: u8,
+    id: u8,
+    _reserved: u8,
+    address: U32,
+    gsi_base: U32,
+}
+
+#[repr(C)]
+#[derive(FromZeroes, FromBytes, Unaligned)]
+struct MadtInterruptSourceOverride {
+    _type: u8,
+    _length: u8,
+    _bus: u8,
+    source: u8,
+    gsi: U32,
+    flags: U16,
+}
+
+/// Bits of an interrupt source override's flags, saying whether the interrupt is active high or
+/// low, and edge or level triggered. 0 means the bus's default, which for ISA is active high and
+/// edge triggered.
+const POLARITY_MASK: u16 = 0b11;
+const POLARITY_ACTIVE_LOW: u16 = 0b11;
+const TRIGGER_MASK: u16 = 0b1100;
+const TRIGGER_LEVEL: u16 = 0b1100;
+
+fn checksum_ok(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) == 0
+}
+
+/// Find the RSDP in `region`, which should be the memory from `RSDP_SEARCH_START` to
+/// `RSDP_SEARCH_END`, returning the physical address of the RSDT.
+pub fn find_rsdt(region: &[u8]) -> Option<usize> {
+    region.chunks(16).find_map(|chunk| {
+        let rsdp = Rsdp::ref_from_prefix(chunk)?;
+        (&rsdp.signature == RSDP_SIGNATURE && checksum_ok(&chunk[..core::mem::size_of::<Rsdp>()]))
+            .then(|| rsdp.rsdt_address.get() as usize)
+    })
+}
+
+/// Size of the header at the start of every ACPI table, which is enough to find the rest of it.
+pub const SDT_HEADER_SIZE: usize = core::mem::size_of::<SdtHeader>();
+
+/// Get the length of an ACPI table, including the header, from the start of it.
+pub fn table_length(header: &[u8]) -> Option<usize> {
+    Some(SdtHeader::ref_from_prefix(header)?.length.get() as usize)
+}
+
+/// Check a whole ACPI table, and get the contents after its header if it has the signature
+/// `signature`.
+fn table_contents<'a>(table: &'a [u8], signature: &[u8; 4]) -> Option<&'a [u8]> {
+    let header = SdtHeader::ref_from_prefix(table)?;
+    let table = table.get(..header.length.get() as usize)?;
+    if &header.signature != signature || !checksum_ok(table) {
+        return None;
+    }
+    // The length might be shorter than the header, if the firmware's broken.
+    table.get(core::mem::size_of::<SdtHeader>()..)
+}
+
+/// Get the physical addresses of the tables listed in the RSDT, `rsdt`.
+pub fn rsdt_entries(rsdt: &[u8]) -> Option<impl Iterator<Item = usize> + '_> {
+    let entries = table_contents(rsdt, b"RSDT")?;
+    Some(
+        entries
+            .chunks_exact(4)
+            .map(|entry| u32::from_le_bytes(entry.try_into().unwrap()) as usize),
+    )
+}
+
+/// Whether `table`, an ACPI table's header or all of it, is the MADT.
+pub fn is_madt(table: &[u8]) -> bool {
+    SdtHeader::ref_from_prefix(table).is_some_and(|header| &header.signature == MADT_SIGNATURE)
+}
+
+/// Where an ISA IRQ goes to.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct IsaRoute {
+    /// Global system interrupt, i.e. the IO APIC input (counting up across IO APICs)
+    pub gsi: u32,
+    pub active_low: bool,
+    pub level_triggered: bool,
+}
+
+/// What we need to know from the MADT to set up the APICs.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Madt {
+    pub local_apic_address: usize,
+    /// Whether there are legacy 8259 PICs too.
+    pub pcat_compat: bool,
+    pub io_apic_id: u8,
+    pub io_apic_address: usize,
+    /// First global system interrupt handled by the IO APIC
+    pub io_apic_gsi_base: u32,
+    /// Where each ISA IRQ goes to
+    pub isa_routes: [IsaRoute; ISA_IRQS],
+}
+
+impl Madt {
+    /// Parse the MADT, `table`, including its header. Only the first IO APIC is used.
+    ///
+    /// Returns `None` if the table is invalid, or there's no IO APIC.
+    pub fn parse(table: &[u8]) -> Option<Self> {
+        table_contents(table, MADT_SIGNATURE)?;
+        let header = MadtHeader::ref_from_prefix(table)?;
+        let table = table.get(..header.header.length.get() as usize)?;
+        let mut isa_routes = [IsaRoute {
+            gsi: 0,
+            active_low: false,
+            level_triggered: false,
+        }; ISA_IRQS];
+        for (irq, route) in isa_routes.iter_mut().enumerate() {
+            route.gsi = irq as u32;
+        }
+        let mut io_apic = None;
+        let mut entries = table.get(core::mem::size_of::<MadtHeader>()..)?;
+        while entries.len() >= 2 {
+            let length = usize::from(entries[1]);
+            if length < 2 || length > entries.len() {
+                return None;
+            }
+            let entry = &entries[..length];
+            match entry[0] {
+                MADT_IO_APIC if io_apic.is_none() => {
+                    let entry = MadtIoApic::ref_from_prefix(entry)?;
+                    io_apic = Some((entry.id, entry.address.get() as usize, entry.gsi_base.get()));
+                }
+                MADT_INTERRUPT_SOURCE_OVERRIDE => {
+                    let entry = MadtInterruptSourceOverride::ref_from_prefix(entry)?;
+                    let flags = entry.flags.get();
+                    if let Some(route) = isa_routes.get_mut(usize::from(entry.source)) {
+                        *route = IsaRoute {
+                            gsi: entry.gsi.get(),
+                            active_low: flags & POLARITY_MASK == POLARITY_ACTIVE_LOW,
+                            level_triggered: flags & TRIGGER_MASK == TRIGGER_LEVEL,
+                        };
+                    }
+                }
+                _ => {}
+            }
+            entries = &entries[length..];
+        }
+        let (io_apic_id, io_apic_address, io_apic_gsi_base) = io_apic?;
+        Some(Self {
+            local_apic_address: header.local_apic_address.get() as usize,
+            pcat_compat: header.flags.get() & MADT_PCAT_COMPAT != 0,
+            io_apic_id,
+            io_apic_address,
+            io_apic_gsi_base,
+            isa_routes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{find_rsdt, rsdt_entries, IsaRoute, Madt};
+
+    /// Fill in the length and checksum of an ACPI table.
+    fn finish_table(mut table: Vec<u8>) -> Vec<u8> {
+        let length = table.len() as u32;
+        table[4..8].copy_from_slice(&length.to_le_bytes());
+        table[9] = 0;
+        let sum = table.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        table[9] = sum.wrapping_neg();
+        table
+    }
+
+    /// Claim `table` is only `length` bytes long, keeping the checksum right.
+    fn claim_length(mut table: Vec<u8>, length: u32) -> Vec<u8> {
+        table[4..8].copy_from_slice(&length.to_le_bytes());
+        table[9] = 0;
+        let sum = table[..length as usize]
+            .iter()
+            .fold(0u8, |sum, b| sum.wrapping_add(*b));
+        table[9] = sum.wrapping_neg();
+        table
+    }
+
+    fn header(signature: &[u8; 4]) -> Vec<u8> {
+        let mut header = signature.to_vec();
+        header.extend_from_slice(&[0; 4]); // length
+        header.push(1); // revision
+        header.push(0); // checksum
+        header.extend_from_slice(b"BOCHS BXPCAPIC  ");
+        header.extend_from_slice(&[0; 12]);
+        assert_eq!(header.len(), 36);
+        header
+    }
+
+    /// Like the MADT QEMU makes for a machine with one CPU.
+    fn sample_madt() -> Vec<u8> {
+        let mut madt = header(b"APIC");
+        madt.extend_from_slice(&0xFEE0_0000u32.to_le_bytes());
+        madt.extend_from_slice(&1u32.to_le_bytes()); // PC-AT compatible
+
+        // processor local APIC: processor 0, APIC ID 0, enabled
+        madt.extend_from_slice(&[0, 8, 0, 0, 1, 0, 0, 0]);
+        // IO APIC: ID 0, at 0xFEC00000, GSIs from 0
+        madt.extend_from_slice(&[1, 12, 0, 0]);
+        madt.extend_from_slice(&0xFEC0_0000u32.to_le_bytes());
+        madt.extend_from_slice(&0u32.to_le_bytes());
+        // IRQ0 (the PIT) goes to GSI 2
+        madt.extend_from_slice(&[2, 10, 0, 0]);
+        madt.extend_from_slice(&2u32.to_le_bytes());
+        madt.extend_from_slice(&0u16.to_le_bytes());
+        // IRQ9 (ACPI) is active high and level triggered
+        madt.extend_from_slice(&[2, 10, 0, 9]);
+        madt.extend_from_slice(&9u32.to_le_bytes());
+        madt.extend_from_slice(&0b1101u16.to_le_bytes());
+        // local APIC NMI
+        madt.extend_from_slice(&[4, 6, 0xFF, 0, 0, 1]);
+        finish_table(madt)
+    }
+
+    #[test]
+    fn parse_madt() {
+        let madt = Madt::parse(&sample_madt()).unwrap();
+        assert_eq!(madt.local_apic_address, 0xFEE0_0000);
+        assert!(madt.pcat_compat);
+        assert_eq!(madt.io_apic_id, 0);
+        assert_eq!(madt.io_apic_address, 0xFEC0_0000);
+        assert_eq!(madt.io_apic_gsi_base, 0);
+        assert_eq!(
+            madt.isa_routes[0],
+            IsaRoute {
+                gsi: 2,
+                active_low: false,
+                level_triggered: false
+            }
+        );
+        assert_eq!(madt.isa_routes[1].gsi, 1);
+        assert_eq!(
+            madt.isa_routes[9],
+            IsaRoute {
+                gsi: 9,
+                active_low: false,
+                level_triggered: true
+            }
+        );
+
+        // bad checksum
+        let mut bad = sample_madt();
+        bad[40] ^= 1;
+        assert_eq!(Madt::parse(&bad), None);
+        // no IO APIC
+        let mut no_io_apic = sample_madt();
+        no_io_apic[52] = 0x7F;
+        assert_eq!(Madt::parse(&finish_table(no_io_apic)), None);
+        // a length which covers the SDT header, but not the rest of the MADT's
+        assert_eq!(Madt::parse(&claim_length(sample_madt(), 40)), None);
+    }
+
+    #[test]
+    fn find_madt() {
+        let mut rsdt = header(b"RSDT");
+        rsdt.extend_from_slice(&0x1000u32.to_le_bytes());
+        rsdt.extend_from_slice(&0x2000u32.to_le_bytes());
+        let rsdt = finish_table(rsdt);
+        assert_eq!(
+            rsdt_entries(&rsdt).unwrap().collect::<Vec<_>>(),
+            [0x1000, 0x2000]
+        );
+        // a length which doesn't even cover the header
+        assert!(rsdt_entries(&claim_length(rsdt, 20)).is_none());
+
+        let mut region = vec![0u8; 0x100];
+        let mut rsdp = b"RSD PTR ".to_vec();
+        rsdp.push(0); // checksum
+        rsdp.extend_from_slice(b"BOCHS ");
+        rsdp.push(0); // revision
+        rsdp.extend_from_slice(&0x7FE1234u32.to_le_bytes());
+        let sum = rsdp.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        rsdp[8] = sum.wrapping_neg();
+        // the signature has to be 16-byte aligned
+        region[0x41..0x41 + rsdp.len()].copy_from_slice(&rsdp);
+        assert_eq!(find_rsdt(&region), None);
+        region[0x80..0x80 + rsdp.len()].copy_from_slice(&rsdp);
+        assert_eq!(find_rsdt(&region), Some(0x7FE1234));
+    }
+}
@@ -0,0 +1,183 @@
+// https://wiki.osdev.org/APIC
+// https://wiki.osdev.org/IOAPIC
+
+use crate::interrupts::acpi::{self, Madt, RSDP_SEARCH_END, RSDP_SEARCH_START, SDT_HEADER_SIZE};
+use crate::interrupts::pic::{self, PIC1_OFFSET};
+use crate::paging::{map_device_memory, PageManager};
+use core::arch::{asm, x86::__cpuid};
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+/// Vector the local APIC uses for spurious interrupts, which don't need an EOI.
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
+/// ISA IRQs which have handlers, and so get routed through the IO APIC, to the same vectors as
+/// they would get through the PIC.
+const ROUTED_IRQS: [u8; 4] = [
+    0,  // timer
+    1,  // keyboard
+    14, // IDE primary
+    15, // IDE secondary
+];
+
+const IA32_APIC_BASE_MSR: u32 = 0x1B;
+const IA32_APIC_BASE_ENABLE: u32 = 1 << 11;
+/// CPUID leaf 1 EDX bit saying there's a local APIC.
+const CPUID_APIC: u32 = 1 << 9;
+
+// Local APIC registers, as offsets from its base address
+const LAPIC_ID: usize = 0x20;
+const LAPIC_TASK_PRIORITY: usize = 0x80;
+const LAPIC_EOI: usize = 0xB0;
+const LAPIC_SPURIOUS: usize = 0xF0;
+const LAPIC_SPURIOUS_ENABLE: u32 = 1 << 8;
+const LAPIC_SIZE: usize = 0x400;
+
+// IO APIC registers. Only the first two are memory mapped, and they're used to access the rest.
+const IOAPIC_REGSEL: usize = 0x00;
+const IOAPIC_WINDOW: usize = 0x10;
+const IOAPIC_SIZE: usize = 0x20;
+const IOAPIC_REDIRECTION_TABLE: u32 = 0x10;
+const IOAPIC_ACTIVE_LOW: u32 = 1 << 13;
+const IOAPIC_LEVEL_TRIGGERED: u32 = 1 << 15;
+
+/// Where the local APIC's registers are mapped, or null if the APIC isn't being used.
+static LOCAL_APIC: AtomicPtr<u32> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Whether interrupts are going through the APIC rather than the 8259 PIC.
+pub fn is_enabled() -> bool {
+    !LOCAL_APIC.load(Ordering::Relaxed).is_null()
+}
+
+unsafe fn read_register(base: *mut u32, offset: usize) -> u32 {
+    read_volatile(base.byte_add(offset))
+}
+
+unsafe fn write_register(base: *mut u32, offset: usize, value: u32) {
+    write_volatile(base.byte_add(offset), value)
+}
+
+unsafe fn read_msr(msr: u32) -> u64 {
+    let (low, high): (u32, u32);
+    asm!("rdmsr", in("ecx") msr, out("eax") low, out("edx") high, options(nomem, nostack));
+    (u64::from(high) << 32) | u64::from(low)
+}
+
+unsafe fn write_msr(msr: u32, value: u64) {
+    asm!(
+        "wrmsr",
+        in("ecx") msr,
+        in("eax") value as u32,
+        in("edx") (value >> 32) as u32,
+        options(nomem, nostack)
+    );
+}
+
+/// Map `len` bytes of physical memory starting at `phys_addr`, to read them.
+unsafe fn map_physical(
+    page_manager: &mut PageManager,
+    phys_addr: usize,
+    len: usize,
+) -> Result<&'static [u8], &'static str> {
+    let ptr = map_device_memory(page_manager, phys_addr, len).ok_or("out of device memory")?;
+    Ok(core::slice::from_raw_parts(ptr, len))
+}
+
+/// Find and parse the MADT, by way of the RSDP and RSDT.
+unsafe fn find_madt(page_manager: &mut PageManager) -> Result<Madt, &'static str> {
+    let bios = map_physical(
+        page_manager,
+        RSDP_SEARCH_START,
+        RSDP_SEARCH_END - RSDP_SEARCH_START,
+    )?;
+    let rsdt_addr = acpi::find_rsdt(bios).ok_or("no RSDP")?;
+    let rsdt_header = map_physical(page_manager, rsdt_addr, SDT_HEADER_SIZE)?;
+    let rsdt_len = acpi::table_length(rsdt_header).ok_or("bad RSDT")?;
+    let rsdt = map_physical(page_manager, rsdt_addr, rsdt_len)?;
+    for table_addr in acpi::rsdt_entries(rsdt).ok_or("bad RSDT")? {
+        let header = map_physical(page_manager, table_addr, SDT_HEADER_SIZE)?;
+        if !acpi::is_madt(header) {
+            continue;
+        }
+        let len = acpi::table_length(header).ok_or("bad MADT")?;
+        let madt = map_physical(page_manager, table_addr, len)?;
+        return Madt::parse(madt).ok_or("bad MADT, or no IO APIC");
+    }
+    Err("no MADT")
+}
+
+/// Set the IO APIC's redirection table entry for input `input`.
+unsafe fn set_redirection(io_apic: *mut u32, input: u32, low: u32, high: u32) {
+    let register = IOAPIC_REDIRECTION_TABLE + 2 * input;
+    write_register(io_apic, IOAPIC_REGSEL, register);
+    write_register(io_apic, IOAPIC_WINDOW, low);
+    write_register(io_apic, IOAPIC_REGSEL, register + 1);
+    write_register(io_apic, IOAPIC_WINDOW, high);
+}
+
+/// Switch from the 8259 PIC to the local APIC and IO APIC, which are found through the ACPI
+/// tables. The timer, keyboard and IDE IRQs get routed to the same vectors as before.
+///
+/// If there's no APIC, or the tables can't be understood, this returns an error and the PIC is
+/// left as it was.
+///
+/// # Safety
+///
+/// `page_manager` must be loaded, and interrupts must be disabled.
+pub unsafe fn init(page_manager: &mut PageManager) -> Result<(), &'static str> {
+    if __cpuid(1).edx & CPUID_APIC == 0 {
+        return Err("CPU has no local APIC");
+    }
+    let madt = find_madt(page_manager)?;
+
+    let local_apic: *mut u32 = map_device_memory(page_manager, madt.local_apic_address, LAPIC_SIZE)
+        .ok_or("out of device memory")?
+        .cast();
+    let io_apic: *mut u32 = map_device_memory(page_manager, madt.io_apic_address, IOAPIC_SIZE)
+        .ok_or("out of device memory")?
+        .cast();
+
+    write_msr(
+        IA32_APIC_BASE_MSR,
+        read_msr(IA32_APIC_BASE_MSR) | u64::from(IA32_APIC_BASE_ENABLE),
+    );
+    // accept all interrupts
+    write_register(local_apic, LAPIC_TASK_PRIORITY, 0);
+    write_register(
+        local_apic,
+        LAPIC_SPURIOUS,
+        LAPIC_SPURIOUS_ENABLE | u32::from(SPURIOUS_VECTOR),
+    );
+    let apic_id = read_register(local_apic, LAPIC_ID) >> 24;
+
+    for irq in ROUTED_IRQS {
+        let route = madt.isa_routes[usize::from(irq)];
+        let Some(input) = route.gsi.checked_sub(madt.io_apic_gsi_base) else {
+            continue;
+        };
+        // fixed delivery to this CPU
+        let mut low = u32::from(PIC1_OFFSET + irq);
+        if route.active_low {
+            low |= IOAPIC_ACTIVE_LOW;
+        }
+        if route.level_triggered {
+            low |= IOAPIC_LEVEL_TRIGGERED;
+        }
+        set_redirection(io_apic, input, low, apic_id << 24);
+    }
+
+    if madt.pcat_compat {
+        pic::mask_all();
+    }
+    LOCAL_APIC.store(local_apic, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Tell the local APIC the interrupt being handled is done.
+///
+/// # Safety
+///
+/// The APIC must be enabled.
+pub unsafe fn send_eoi() {
+    write_register(LOCAL_APIC.load(Ordering::Relaxed), LAPIC_EOI, 0);
+}
@@ -0,0 +1,767 @@
+//! A GDB remote stub on the second serial port (COM2), for debugging the kernel where QEMU's own
+//! stub isn't available, e.g. in Bochs or on real hardware.
+//!
+//! Build with the `gdb_stub` feature, and give QEMU a second serial port GDB can connect to, e.g.
+//! `-serial stdio -serial tcp::1235,server,nowait`, then `target remote :1235` in GDB. The kernel
+//! stops for GDB early in boot, and again whenever it hits a breakpoint (`int3`) or finishes a
+//! single step (a debug exception). While it's stopped, GDB can read and write registers and
+//! memory, set and remove software breakpoints, single-step and continue.
+//!
+//! Traps in user programs stop the kernel for GDB too.
+
+use super::stats;
+use crate::sync::mutex::Mutex;
+use crate::system::try_unwrap_system;
+use alloc::vec::Vec;
+use core::arch::asm;
+use kidneyos_shared::paging::kernel_mapping_ranges;
+use kidneyos_shared::serial::{SerialPort, COM2_BASE};
+
+/// The signal GDB is told stopped the kernel, whether it was a breakpoint or a single step.
+const SIGTRAP: u8 = 5;
+
+/// The largest packet GDB is told it can send, and the most memory read in one `m` packet.
+const PACKET_SIZE: usize = 0x400;
+
+/// The `int3` instruction, written over an instruction to set a breakpoint on it.
+const INT3: u8 = 0xCC;
+
+/// The trap flag, which makes the CPU raise a debug exception after the next instruction.
+const EFLAGS_TF: u32 = 1 << 8;
+
+/// The sum of `data`'s bytes, as used to check packets.
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
+
+fn hex_digit(value: u8) -> u8 {
+    b"0123456789abcdef"[(value & 0xf) as usize]
+}
+
+fn parse_hex_digit(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|value| value as u8)
+}
+
+fn push_hex_byte(out: &mut Vec<u8>, byte: u8) {
+    out.push(hex_digit(byte >> 4));
+    out.push(hex_digit(byte));
+}
+
+/// Parse a number written in hex, as addresses and lengths are.
+fn parse_hex(digits: &[u8]) -> Option<usize> {
+    if digits.is_empty() || digits.len() > 2 * core::mem::size_of::<usize>() {
+        return None;
+    }
+    digits.iter().try_fold(0, |value, &digit| {
+        Some(value << 4 | parse_hex_digit(digit)? as usize)
+    })
+}
+
+/// Decode bytes written as pairs of hex digits.
+fn decode_hex_bytes(digits: &[u8]) -> Option<Vec<u8>> {
+    if digits.len() % 2 != 0 {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| Some(parse_hex_digit(pair[0])? << 4 | parse_hex_digit(pair[1])?))
+        .collect()
+}
+
+/// Frame `data` as a packet, `$data#checksum`, escaping the characters which mean something in
+/// the framing.
+fn encode_packet(data: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(data.len() + 4);
+    packet.push(b'$');
+    for &byte in data {
+        if matches!(byte, b'$' | b'#' | b'}' | b'*') {
+            packet.push(b'}');
+            packet.push(byte ^ 0x20);
+        } else {
+            packet.push(byte);
+        }
+    }
+    let sum = checksum(&packet[1..]);
+    packet.push(b'#');
+    push_hex_byte(&mut packet, sum);
+    packet
+}
+
+/// Something which came in from GDB.
+#[derive(Debug, PartialEq)]
+enum Received {
+    /// A packet, with the framing taken off.
+    Packet(Vec<u8>),
+    /// A packet whose checksum didn't match, which GDB should be asked to send again.
+    BadPacket,
+    /// GDB didn't get the last packet sent to it properly, so it should be sent again.
+    Nak,
+}
+
+enum ReaderState {
+    /// Waiting for a `$` to start a packet.
+    Idle,
+    Data,
+    /// Got the `#`, and now the checksum's first digit, if it's `Some`.
+    Checksum(Option<u8>),
+}
+
+/// Picks packets out of the bytes coming in from GDB, one byte at a time.
+struct PacketReader {
+    state: ReaderState,
+    data: Vec<u8>,
+}
+
+impl PacketReader {
+    const fn new() -> Self {
+        Self {
+            state: ReaderState::Idle,
+            data: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, byte: u8) -> Option<Received> {
+        match self.state {
+            ReaderState::Idle => match byte {
+                b'$' => {
+                    self.data.clear();
+                    self.state = ReaderState::Data;
+                }
+                b'-' => return Some(Received::Nak),
+                // acks, and anything else between packets
+                _ => {}
+            },
+            ReaderState::Data => match byte {
+                b'#' => self.state = ReaderState::Checksum(None),
+                // GDB gave up on the last packet, and has started again
+                b'$' => self.data.clear(),
+                _ => self.data.push(byte),
+            },
+            ReaderState::Checksum(None) => self.state = ReaderState::Checksum(Some(byte)),
+            ReaderState::Checksum(Some(high)) => {
+                self.state = ReaderState::Idle;
+                let sum = parse_hex(&[high, byte]);
+                if sum != Some(checksum(&self.data) as usize) {
+                    return Some(Received::BadPacket);
+                }
+                return Some(Received::Packet(core::mem::take(&mut self.data)));
+            }
+        }
+        None
+    }
+}
+
+/// The registers GDB knows about, in the order it numbers them for i386.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Registers([u32; 16]);
+
+impl Registers {
+    const EIP: usize = 8;
+}
+
+/// Memory the stub reads and writes for GDB.
+trait Memory {
+    /// Fill `buf` from `address`, returning false if any of it isn't there.
+    fn read(&mut self, address: usize, buf: &mut [u8]) -> bool;
+    /// Write `data` to `address`, returning false if any of it isn't there.
+    fn write(&mut self, address: usize, data: &[u8]) -> bool;
+}
+
+/// What to do after handling a packet.
+#[derive(Debug, PartialEq)]
+enum Action {
+    /// Send this back, and wait for the next packet.
+    Reply(Vec<u8>),
+    /// Carry on running, stopping again after one instruction if `step`.
+    Resume { step: bool },
+    /// Send `OK` back, then carry on without GDB.
+    Detach,
+}
+
+/// The stub's state between stops.
+struct Stub {
+    /// The breakpoints which are set, and the byte each `int3` replaced.
+    breakpoints: Vec<(usize, u8)>,
+}
+
+fn reply(data: &[u8]) -> Action {
+    Action::Reply(data.to_vec())
+}
+
+fn error() -> Action {
+    reply(b"E01")
+}
+
+impl Stub {
+    const fn new() -> Self {
+        Self {
+            breakpoints: Vec::new(),
+        }
+    }
+
+    /// Handle `packet` from GDB, while stopped with registers `registers`.
+    fn handle(
+        &mut self,
+        packet: &[u8],
+        registers: &mut Registers,
+        memory: &mut impl Memory,
+    ) -> Action {
+        let Some((&command, args)) = packet.split_first() else {
+            return reply(b"");
+        };
+        match command {
+            b'?' => reply(&[b'S', hex_digit(SIGTRAP >> 4), hex_digit(SIGTRAP)]),
+            b'g' => {
+                let mut out = Vec::with_capacity(16 * 8);
+                for byte in registers.0.iter().flat_map(|value| value.to_le_bytes()) {
+                    push_hex_byte(&mut out, byte);
+                }
+                Action::Reply(out)
+            }
+            // GDB may send more registers than it knows about for i386, which are ignored
+            b'G' => match args.get(..16 * 8).and_then(decode_hex_bytes) {
+                Some(bytes) => {
+                    for (value, bytes) in registers.0.iter_mut().zip(bytes.chunks(4)) {
+                        *value = u32::from_le_bytes(bytes.try_into().unwrap());
+                    }
+                    reply(b"OK")
+                }
+                None => error(),
+            },
+            b'p' => match parse_hex(args).and_then(|n| registers.0.get(n)) {
+                Some(value) => {
+                    let mut out = Vec::with_capacity(8);
+                    for byte in value.to_le_bytes() {
+                        push_hex_byte(&mut out, byte);
+                    }
+                    Action::Reply(out)
+                }
+                // registers which aren't kept, e.g. the FPU's
+                None => reply(b"xxxxxxxx"),
+            },
+            b'P' => {
+                let parsed = split(args, b'=').and_then(|(n, value)| {
+                    let value = decode_hex_bytes(value).filter(|bytes| bytes.len() == 4)?;
+                    Some((parse_hex(n)?, u32::from_le_bytes(value.try_into().unwrap())))
+                });
+                match parsed {
+                    Some((n, value)) if n < registers.0.len() => {
+                        registers.0[n] = value;
+                        reply(b"OK")
+                    }
+                    _ => error(),
+                }
+            }
+            b'm' => {
+                let Some((address, len)) = parse_address_len(args) else {
+                    return error();
+                };
+                let mut buf = alloc::vec![0; len.min(PACKET_SIZE / 2)];
+                if !memory.read(address, &mut buf) {
+                    return error();
+                }
+                let mut out = Vec::with_capacity(buf.len() * 2);
+                for byte in buf {
+                    push_hex_byte(&mut out, byte);
+                }
+                Action::Reply(out)
+            }
+            b'M' => {
+                let parsed = split(args, b':').and_then(|(range, data)| {
+                    let (address, len) = parse_address_len(range)?;
+                    Some((
+                        address,
+                        decode_hex_bytes(data).filter(|data| data.len() == len)?,
+                    ))
+                });
+                match parsed {
+                    Some((address, data)) if memory.write(address, &data) => reply(b"OK"),
+                    _ => error(),
+                }
+            }
+            b'Z' | b'z' => {
+                // only software breakpoints, `Z0,address,kind`
+                let Some(address) = args
+                    .strip_prefix(b"0,")
+                    .and_then(|args| split(args, b','))
+                    .and_then(|(address, _kind)| parse_hex(address))
+                else {
+                    return reply(b"");
+                };
+                let done = if command == b'Z' {
+                    self.insert_breakpoint(address, memory)
+                } else {
+                    self.remove_breakpoint(address, memory)
+                };
+                if done {
+                    reply(b"OK")
+                } else {
+                    error()
+                }
+            }
+            b'c' | b's' => {
+                if !args.is_empty() {
+                    match parse_hex(args) {
+                        Some(address) => registers.0[Registers::EIP] = address as u32,
+                        None => return error(),
+                    }
+                }
+                Action::Resume {
+                    step: command == b's',
+                }
+            }
+            b'D' | b'k' => {
+                for (address, original) in core::mem::take(&mut self.breakpoints) {
+                    memory.write(address, &[original]);
+                }
+                if command == b'D' {
+                    Action::Detach
+                } else {
+                    Action::Resume { step: false }
+                }
+            }
+            // there's only one thread, as far as GDB knows
+            b'H' => reply(b"OK"),
+            b'q' if args.starts_with(b"Supported") => {
+                let mut out = b"PacketSize=".to_vec();
+                for shift in [8, 4, 0] {
+                    out.push(hex_digit((PACKET_SIZE >> shift) as u8));
+                }
+                Action::Reply(out)
+            }
+            b'q' if args == b"Attached" => reply(b"1"),
+            // an empty reply means the packet isn't supported
+            _ => reply(b""),
+        }
+    }
+
+    fn insert_breakpoint(&mut self, address: usize, memory: &mut impl Memory) -> bool {
+        if self.breakpoints.iter().any(|&(set, _)| set == address) {
+            return true;
+        }
+        let mut original = [0];
+        if !memory.read(address, &mut original) || !memory.write(address, &[INT3]) {
+            return false;
+        }
+        self.breakpoints.push((address, original[0]));
+        true
+    }
+
+    fn remove_breakpoint(&mut self, address: usize, memory: &mut impl Memory) -> bool {
+        let Some(index) = self.breakpoints.iter().position(|&(set, _)| set == address) else {
+            return false;
+        };
+        let (_, original) = self.breakpoints.swap_remove(index);
+        memory.write(address, &[original])
+    }
+}
+
+/// Split `data` at the first `separator`.
+fn split(data: &[u8], separator: u8) -> Option<(&[u8], &[u8])> {
+    let index = data.iter().position(|&byte| byte == separator)?;
+    Some((&data[..index], &data[index + 1..]))
+}
+
+/// Parse `address,length`.
+fn parse_address_len(args: &[u8]) -> Option<(usize, usize)> {
+    let (address, len) = split(args, b',')?;
+    Some((parse_hex(address)?, parse_hex(len)?))
+}
+
+/// The memory the kernel can see: the running thread's address space, or before there is one, the
+/// kernel's own mappings.
+struct KernelMemory;
+
+impl KernelMemory {
+    fn is_mapped(address: usize, len: usize) -> bool {
+        let Some(end) = address.checked_add(len) else {
+            return false;
+        };
+        if let Some(system) = try_unwrap_system() {
+            // The thread which hit the trap might have been holding this.
+            if let Some(running) = system.threads.running_thread.try_lock() {
+                if let Some(running) = running.as_ref() {
+                    return running.page_manager.is_range_mapped(address, len);
+                }
+            }
+        }
+        kernel_mapping_ranges()
+            .iter()
+            .any(|range| range.virt_start <= address && end <= range.virt_start + range.len)
+    }
+}
+
+impl Memory for KernelMemory {
+    fn read(&mut self, address: usize, buf: &mut [u8]) -> bool {
+        if !Self::is_mapped(address, buf.len()) {
+            return false;
+        }
+        // SAFETY: The memory is mapped, and the kernel is stopped, so nothing else is using it.
+        unsafe { core::ptr::copy(address as *const u8, buf.as_mut_ptr(), buf.len()) };
+        true
+    }
+
+    fn write(&mut self, address: usize, data: &[u8]) -> bool {
+        if !Self::is_mapped(address, data.len()) {
+            return false;
+        }
+        // Breakpoints go in the kernel's code, which is mapped read-only, so write protection is
+        // turned off while writing.
+        const CR0_WP: u32 = 1 << 16;
+        // SAFETY: As for reading. Interrupts are off, so nothing else runs without write
+        // protection.
+        unsafe {
+            let cr0: u32;
+            asm!("mov {}, cr0", out(reg) cr0);
+            asm!("mov cr0, {}", in(reg) cr0 & !CR0_WP);
+            core::ptr::copy(data.as_ptr(), address as *mut u8, data.len());
+            asm!("mov cr0, {}", in(reg) cr0);
+        }
+        true
+    }
+}
+
+/// The registers saved by the trap handlers, below the ones the CPU pushed. If the trap came from
+/// user mode, the CPU pushed the user stack's esp and ss above these too.
+#[repr(C)]
+struct TrapFrame {
+    gs: u32,
+    fs: u32,
+    es: u32,
+    ds: u32,
+    // as pushed by pusha
+    edi: u32,
+    esi: u32,
+    ebp: u32,
+    _esp: u32,
+    ebx: u32,
+    edx: u32,
+    ecx: u32,
+    eax: u32,
+    // pushed by the CPU
+    eip: u32,
+    cs: u32,
+    eflags: u32,
+}
+
+static STUB: Mutex<Stub> = Mutex::new(Stub::new());
+
+static PORT: SerialPort = SerialPort::new(COM2_BASE);
+
+/// Send `data` to GDB as a packet.
+fn send_packet(data: &[u8]) {
+    for byte in encode_packet(data) {
+        // SAFETY: The port was set up in `init`.
+        unsafe { PORT.write_byte(byte) };
+    }
+}
+
+/// Called by the trap handlers, with interrupts off: tell GDB the kernel's stopped, and do what
+/// it says until it says to carry on.
+unsafe extern "C" fn on_trap(frame: *mut TrapFrame) {
+    let f = &mut *frame;
+    let from_user = f.cs & 0b11 == 3;
+    // Only traps from user mode switch stacks, so otherwise the stack carries on past the frame.
+    let user_stack = frame.add(1) as *mut u32;
+    let (esp, ss) = if from_user {
+        (*user_stack, *user_stack.add(1))
+    } else {
+        let ss: u32;
+        asm!("mov {:e}, ss", out(reg) ss);
+        (user_stack as u32, ss)
+    };
+    let segment = |selector: u32| selector & 0xffff;
+    let mut registers = Registers([
+        f.eax,
+        f.ecx,
+        f.edx,
+        f.ebx,
+        esp,
+        f.ebp,
+        f.esi,
+        f.edi,
+        f.eip,
+        f.eflags,
+        segment(f.cs),
+        segment(ss),
+        segment(f.ds),
+        segment(f.es),
+        segment(f.fs),
+        segment(f.gs),
+    ]);
+
+    let mut stub = STUB.lock();
+    let mut reader = PacketReader::new();
+    let mut last = [b'S', hex_digit(SIGTRAP >> 4), hex_digit(SIGTRAP)].to_vec();
+    send_packet(&last);
+    let step = loop {
+        let packet = match reader.push(PORT.read_byte()) {
+            None => continue,
+            Some(Received::Nak) => {
+                send_packet(&last);
+                continue;
+            }
+            Some(Received::BadPacket) => {
+                PORT.write_byte(b'-');
+                continue;
+            }
+            Some(Received::Packet(packet)) => {
+                PORT.write_byte(b'+');
+                packet
+            }
+        };
+        match stub.handle(&packet, &mut registers, &mut KernelMemory) {
+            Action::Reply(reply) => {
+                send_packet(&reply);
+                last = reply;
+            }
+            Action::Resume { step } => break step,
+            Action::Detach => {
+                send_packet(b"OK");
+                break false;
+            }
+        }
+    };
+
+    let [eax, ecx, edx, ebx, esp, ebp, esi, edi, eip, eflags, _cs, ss, ds, es, fs, gs] =
+        registers.0;
+    (f.eax, f.ecx, f.edx, f.ebx, f.ebp, f.esi, f.edi) = (eax, ecx, edx, ebx, ebp, esi, edi);
+    (f.ds, f.es, f.fs, f.gs) = (ds, es, fs, gs);
+    f.eip = eip;
+    f.eflags = if step {
+        eflags | EFLAGS_TF
+    } else {
+        eflags & !EFLAGS_TF
+    };
+    // The kernel's stack and code segment can't be moved out from under it.
+    if from_user {
+        (*user_stack, *user_stack.add(1)) = (esp, ss);
+    }
+}
+
+/// Set up the serial port for GDB, and stop for it to connect.
+///
+/// # Safety
+///
+/// The IDT must have been loaded, with the trap handlers installed.
+pub unsafe fn init() {
+    if let Err(byte) = PORT.init() {
+        panic!("GDB stub: faulty serial port, got {byte:#X} back");
+    }
+    breakpoint();
+}
+
+/// Stop for GDB, as if there were a breakpoint here.
+pub fn breakpoint() {
+    // SAFETY: The breakpoint handler returns to the next instruction.
+    unsafe { asm!("int3") };
+}
+
+/// Entered on a debug exception, e.g. after single-stepping an instruction.
+#[naked]
+pub unsafe extern "C" fn debug_handler() -> ! {
+    asm!(
+        "
+        pusha
+        push ds
+        push es
+        push fs
+        push gs
+        push 0x1
+        call {} // Count the interrupt
+        add esp, 4
+        push esp // Pass the frame to the stub.
+        call {}
+        add esp, 4
+        pop gs
+        pop fs
+        pop es
+        pop ds
+        popa
+        iretd
+        ",
+        sym stats::record_interrupt,
+        sym on_trap,
+        options(noreturn),
+    )
+}
+
+/// Entered on a breakpoint (`int3`).
+#[naked]
+pub unsafe extern "C" fn breakpoint_handler() -> ! {
+    asm!(
+        "
+        pusha
+        push ds
+        push es
+        push fs
+        push gs
+        push 0x3
+        call {} // Count the interrupt
+        add esp, 4
+        push esp // Pass the frame to the stub.
+        call {}
+        add esp, 4
+        pop gs
+        pop fs
+        pop es
+        pop ds
+        popa
+        iretd
+        ",
+        sym stats::record_interrupt,
+        sym on_trap,
+        options(noreturn),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        checksum, encode_packet, Action, Memory, PacketReader, Received, Registers, Stub, INT3,
+    };
+
+    /// Memory at addresses `base..base + bytes.len()`.
+    struct FakeMemory {
+        base: usize,
+        bytes: Vec<u8>,
+    }
+
+    impl FakeMemory {
+        fn range(&self, address: usize, len: usize) -> Option<core::ops::Range<usize>> {
+            let start = address.checked_sub(self.base)?;
+            (start + len <= self.bytes.len()).then_some(start..start + len)
+        }
+    }
+
+    impl Memory for FakeMemory {
+        fn read(&mut self, address: usize, buf: &mut [u8]) -> bool {
+            let Some(range) = self.range(address, buf.len()) else {
+                return false;
+            };
+            buf.copy_from_slice(&self.bytes[range]);
+            true
+        }
+
+        fn write(&mut self, address: usize, data: &[u8]) -> bool {
+            let Some(range) = self.range(address, data.len()) else {
+                return false;
+            };
+            self.bytes[range].copy_from_slice(data);
+            true
+        }
+    }
+
+    fn memory() -> FakeMemory {
+        FakeMemory {
+            base: 0xc0100000,
+            bytes: (0..=255).collect(),
+        }
+    }
+
+    fn reply(data: &[u8]) -> Action {
+        Action::Reply(data.to_vec())
+    }
+
+    fn read_all(reader: &mut PacketReader, bytes: &[u8]) -> Vec<Received> {
+        bytes.iter().filter_map(|&byte| reader.push(byte)).collect()
+    }
+
+    #[test]
+    fn packet_framing() {
+        assert_eq!(checksum(b"OK"), 0x9a);
+        assert_eq!(encode_packet(b"OK"), b"$OK#9a");
+        assert_eq!(encode_packet(b""), b"$#00");
+        // escaped, and the checksum covers the escaped bytes
+        assert_eq!(encode_packet(b"a#b"), b"$a}\x03b#43");
+
+        let mut reader = PacketReader::new();
+        assert_eq!(
+            read_all(&mut reader, b"+$g#67$m10,4#2e"),
+            [
+                Received::Packet(b"g".to_vec()),
+                Received::Packet(b"m10,4".to_vec())
+            ]
+        );
+        // a bad checksum, then a request to send the last packet again
+        assert_eq!(
+            read_all(&mut reader, b"$g#00-"),
+            [Received::BadPacket, Received::Nak]
+        );
+        // whatever's encoded comes back out, apart from escaping
+        assert_eq!(
+            read_all(&mut reader, &encode_packet(b"qSupported:swbreak+")),
+            [Received::Packet(b"qSupported:swbreak+".to_vec())]
+        );
+    }
+
+    #[test]
+    fn read_memory() {
+        let mut stub = Stub::new();
+        let mut registers = Registers::default();
+        let mut memory = memory();
+
+        assert_eq!(
+            stub.handle(b"mc0100010,4", &mut registers, &mut memory),
+            reply(b"10111213")
+        );
+        assert_eq!(
+            stub.handle(b"mc01000fe,2", &mut registers, &mut memory),
+            reply(b"feff")
+        );
+        // off the end
+        assert_eq!(
+            stub.handle(b"mc01000ff,2", &mut registers, &mut memory),
+            reply(b"E01")
+        );
+        assert_eq!(
+            stub.handle(b"mnonsense", &mut registers, &mut memory),
+            reply(b"E01")
+        );
+    }
+
+    #[test]
+    fn registers_and_breakpoints() {
+        let mut stub = Stub::new();
+        let mut registers = Registers::default();
+        let mut memory = memory();
+
+        registers.0[Registers::EIP] = 0xc0100020;
+        let Action::Reply(all) = stub.handle(b"g", &mut registers, &mut memory) else {
+            panic!("no reply to g");
+        };
+        assert_eq!(all.len(), 16 * 8);
+        assert_eq!(&all[8 * 8..9 * 8], b"200010c0");
+        assert_eq!(
+            stub.handle(b"P0=78563412", &mut registers, &mut memory),
+            reply(b"OK")
+        );
+        assert_eq!(registers.0[0], 0x12345678);
+
+        assert_eq!(
+            stub.handle(b"Z0,c0100020,1", &mut registers, &mut memory),
+            reply(b"OK")
+        );
+        assert_eq!(memory.bytes[0x20], INT3);
+        assert_eq!(
+            stub.handle(b"z0,c0100020,1", &mut registers, &mut memory),
+            reply(b"OK")
+        );
+        assert_eq!(memory.bytes[0x20], 0x20);
+
+        assert_eq!(
+            stub.handle(b"s", &mut registers, &mut memory),
+            Action::Resume { step: true }
+        );
+        assert_eq!(
+            stub.handle(b"cc0100040", &mut registers, &mut memory),
+            Action::Resume { step: false }
+        );
+        assert_eq!(registers.0[Registers::EIP], 0xc0100040);
+        // unsupported
+        assert_eq!(
+            stub.handle(b"vMustReplyEmpty", &mut registers, &mut memory),
+            reply(b"")
+        );
+    }
+}
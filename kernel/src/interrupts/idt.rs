@@ -2,14 +2,23 @@
 // https://wiki.osdev.org/Interrupts_tutorial
 // https://wiki.osdev.org/Exceptions
 
-use core::{arch::asm, mem::size_of};
-use kidneyos_shared::{bit_array::BitArray, bitfield};
+use core::{
+    arch::asm,
+    mem::size_of,
+    ptr::{addr_of, addr_of_mut},
+};
+use kidneyos_shared::{
+    bit_array::BitArray,
+    bitfield,
+    global_descriptor_table::DOUBLE_FAULT_TSS_SELECTOR,
+    task_state_segment::{TaskStateSegment, DOUBLE_FAULT_TASK_STATE_SEGMENT},
+};
 use paste::paste;
 
 use crate::interrupts::intr_handler::{
-    general_protection_fault_handler, ide_prim_interrupt_handler, ide_secd_interrupt_handler,
-    keyboard_handler, page_fault_handler, syscall_handler, timer_interrupt_handler,
-    unhandled_handler,
+    double_fault_handler, general_protection_fault_handler, ide_prim_interrupt_handler,
+    ide_secd_interrupt_handler, keyboard_handler, page_fault_handler, spurious_interrupt_handler,
+    syscall_handler, timer_interrupt_handler, unhandled_handler,
 };
 
 bitfield!(
@@ -72,6 +81,18 @@ pub unsafe fn load() {
             .with_descriptor_privilege_level(3u8)
             .with_present(true);
     }
+    // A task gate rather than an interrupt gate, so that double faults get a fresh stack. See
+    // init_double_fault_task.
+    IDT[0x8] = GateDescriptor::default()
+        .with_segment_selector(DOUBLE_FAULT_TSS_SELECTOR)
+        .with_gate_type(0x5u8)
+        .with_present(true);
+    #[cfg(feature = "gdb_stub")]
+    {
+        use crate::interrupts::gdb_stub::{breakpoint_handler, debug_handler};
+        IDT[0x1] = IDT[0x1].with_offset(debug_handler as usize as u32);
+        IDT[0x3] = IDT[0x3].with_offset(breakpoint_handler as usize as u32);
+    }
     IDT[0xd] = IDT[0xd].with_offset(general_protection_fault_handler as usize as u32);
     IDT[0xe] = IDT[0xe].with_offset(page_fault_handler as usize as u32);
     IDT[0x20] = IDT[0x20].with_offset(timer_interrupt_handler as usize as u32); // PIC1_OFFSET (IRQ0)
@@ -79,6 +100,35 @@ pub unsafe fn load() {
     IDT[0x2E] = IDT[0x2E].with_offset(ide_prim_interrupt_handler as usize as u32); // IDE Primary (IRQ14)
     IDT[0x2F] = IDT[0x2F].with_offset(ide_secd_interrupt_handler as usize as u32); // IDE Secondary (IRQ15)
     IDT[0x80] = IDT[0x80].with_offset(syscall_handler as usize as u32);
+    IDT[0xFF] = IDT[0xFF].with_offset(spurious_interrupt_handler as usize as u32); // APIC spurious interrupt
 
     asm!("lidt [{}]", sym IDT_DESCRIPTOR);
 }
+
+const DOUBLE_FAULT_STACK_SIZE: usize = 0x4000;
+
+#[repr(align(16))]
+struct DoubleFaultStack([u8; DOUBLE_FAULT_STACK_SIZE]);
+
+/// The stack double faults are handled on, which is only ever used once, since the handler
+/// doesn't return.
+static mut DOUBLE_FAULT_STACK: DoubleFaultStack = DoubleFaultStack([0; DOUBLE_FAULT_STACK_SIZE]);
+
+/// Set up the task which the double fault task gate switches to, so that it runs
+/// [`double_fault_handler`] on [`DOUBLE_FAULT_STACK`], with the page tables which are currently
+/// loaded.
+///
+/// 32-bit x86 doesn't have the interrupt stack table, so this is the only way to make sure a
+/// double fault caused by a bad kernel stack doesn't turn into a triple fault.
+///
+/// # Safety
+///
+/// The kernel's page tables must be loaded, and the GDT must have been loaded by the kernel, so
+/// its descriptor for the double fault TSS points at the right address.
+pub unsafe fn init_double_fault_task() {
+    let cr3: u32;
+    asm!("mov {}, cr3", out(reg) cr3);
+    let stack_top = addr_of!(DOUBLE_FAULT_STACK).add(1) as u32;
+    *addr_of_mut!(DOUBLE_FAULT_TASK_STATE_SEGMENT) =
+        TaskStateSegment::kernel_task(double_fault_handler as usize as u32, stack_top, cr3);
+}
@@ -1,16 +1,247 @@
+use alloc::string::String;
 use core::arch::asm;
+use core::fmt;
+use core::ptr::addr_of;
+use kidneyos_shared::eprintln;
+use kidneyos_shared::task_state_segment::{TaskStateSegment, TASK_STATE_SEGMENT};
 
 use crate::drivers::ata::ata_interrupt;
 use crate::drivers::input::keyboard;
-use crate::interrupts::{intr_enable, pic, timer};
-use crate::system::running_process;
+use crate::interrupts::{intr_enable, pic, stats, timer};
+use crate::system::{running_process, running_thread_tid, unwrap_system};
+use crate::threading::process_functions::kill_process;
 use crate::threading::scheduling;
-use crate::user_program::syscall;
+use crate::user_program::rusage::cpu_limit_exceeded;
+use crate::user_program::syscall::{self, SIGSEGV, SIGXCPU};
 
 /* This file contains all the interrupt handlers to be installed in the IDT when the kernel is initialized.
  * Each must be naked function with C linkage and the type fn() -> !
  */
 
+/// Requested privilege level of the code segment a fault came from, if it was in user mode.
+const USER_RPL: u32 = 3;
+
+/// The error code pushed for a page fault.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct PageFaultError(u32);
+
+impl PageFaultError {
+    const PROTECTION_VIOLATION: u32 = 1 << 0;
+    const WRITE: u32 = 1 << 1;
+    const USER: u32 = 1 << 2;
+    const INSTRUCTION_FETCH: u32 = 1 << 4;
+
+    fn user(self) -> bool {
+        self.0 & Self::USER != 0
+    }
+
+    fn write(self) -> bool {
+        self.0 & Self::WRITE != 0
+    }
+}
+
+impl fmt::Display for PageFaultError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cause = if self.0 & Self::PROTECTION_VIOLATION != 0 {
+            "protection violation"
+        } else {
+            "page not present"
+        };
+        let access = if self.0 & Self::INSTRUCTION_FETCH != 0 {
+            "instruction fetch"
+        } else if self.0 & Self::WRITE != 0 {
+            "write"
+        } else {
+            "read"
+        };
+        let mode = if self.user() { "user" } else { "kernel" };
+        write!(f, "{cause} on {access} in {mode} mode ({:#b})", self.0)
+    }
+}
+
+/// What the CPU tells a fault handler about a fault: the error code it pushed, if any, where the
+/// fault came from, and, for page faults, the address in cr2.
+///
+/// The handlers decide what to do from one of these, so tests can make one up to check what would
+/// be done about a fault, without faulting.
+#[derive(Clone, Copy)]
+struct FaultFrame {
+    error_code: u32,
+    eip: usize,
+    cs: u32,
+    cr2: usize,
+}
+
+/// Why a fault couldn't be fixed up. It's formatted as it's printed, rather than into a `String`,
+/// so that a fault in the allocator, or with the heap corrupted, can still be reported.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FaultMessage {
+    PageFault {
+        error: PageFaultError,
+        eip: usize,
+        cr2: usize,
+    },
+    GeneralProtectionFault {
+        error_code: u32,
+        eip: usize,
+        user: bool,
+    },
+}
+
+impl fmt::Display for FaultMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::PageFault { error, eip, cr2 } => write!(
+                f,
+                "page fault ({error}) when trying to access {cr2:#X} from instruction at {eip:#X}"
+            ),
+            Self::GeneralProtectionFault {
+                error_code,
+                eip,
+                user: true,
+            } => write!(
+                f,
+                "general protection fault with error code {error_code:#X} at instruction {eip:#X}"
+            ),
+            Self::GeneralProtectionFault {
+                error_code,
+                eip,
+                user: false,
+            } => write!(
+                f,
+                "general protection fault with error code {error_code:#X} occurred from instruction at {eip:#X}"
+            ),
+        }
+    }
+}
+
+/// What's done about a fault which couldn't be fixed up.
+#[derive(Debug, PartialEq)]
+enum FaultAction {
+    /// Kill the running process as if by the signal, saying why, for a fault in user mode.
+    Kill(i32, FaultMessage),
+    /// Panic with the message, for a fault in the kernel.
+    Panic(FaultMessage),
+}
+
+impl FaultAction {
+    /// # Safety
+    ///
+    /// Must be called from a fault handler.
+    unsafe fn take(self) -> ! {
+        match self {
+            FaultAction::Kill(signal, why) => kill_running_process(signal, format_args!("{why}")),
+            FaultAction::Panic(message) => panic!("{message}"),
+        }
+    }
+}
+
+/// What to do about a page fault for which there's no VMA to fix it up.
+fn page_fault_action(frame: FaultFrame) -> FaultAction {
+    let FaultFrame {
+        error_code,
+        eip,
+        cr2,
+        ..
+    } = frame;
+    let error = PageFaultError(error_code);
+    let message = FaultMessage::PageFault { error, eip, cr2 };
+    if !error.user() {
+        return FaultAction::Panic(message);
+    }
+    FaultAction::Kill(SIGSEGV, message)
+}
+
+/// What to do about a general protection fault.
+fn general_protection_fault_action(frame: FaultFrame) -> FaultAction {
+    let FaultFrame {
+        error_code,
+        eip,
+        cs,
+        ..
+    } = frame;
+    // A non-zero error code is the selector of the segment which caused the fault.
+    let user = cs & 0b11 == USER_RPL;
+    let message = FaultMessage::GeneralProtectionFault {
+        error_code,
+        eip,
+        user,
+    };
+    if !user {
+        return FaultAction::Panic(message);
+    }
+    FaultAction::Kill(SIGSEGV, message)
+}
+
+/// The panic message for a double fault, from the faulting task's registers as saved in the TSS,
+/// and cr2. It's formatted as it's printed, since the heap might not be usable.
+struct DoubleFaultMessage<'a> {
+    tss: &'a TaskStateSegment,
+    cr2: usize,
+}
+
+impl fmt::Display for DoubleFaultMessage<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { tss, cr2 } = self;
+        write!(
+            f,
+            "double fault from instruction at {:#X}\n\
+             eax={:#X} ebx={:#X} ecx={:#X} edx={:#X} esi={:#X} edi={:#X}\n\
+             esp={:#X} ebp={:#X} eflags={:#X} cs={:#X} ss={:#X} cr2={cr2:#X}",
+            { tss.eip },
+            { tss.eax },
+            { tss.ebx },
+            { tss.ecx },
+            { tss.edx },
+            { tss.esi },
+            { tss.edi },
+            { tss.esp },
+            { tss.ebp },
+            { tss.eflags },
+            { tss.cs },
+            { tss.ss },
+        )
+    }
+}
+
+/// Kill the running process, as if by `signal`, after saying why.
+///
+/// TODO: call the process' handler for `signal` instead, if it has one.
+///
+/// # Safety
+///
+/// Must be called from an interrupt handler, for an interrupt in user mode.
+unsafe fn kill_running_process(signal: i32, why: fmt::Arguments) -> ! {
+    // important: re-enable interrupts before acquiring lock to prevent deadlock
+    intr_enable();
+    let (pid, name) = {
+        let pcb = running_process();
+        let pcb = pcb.lock();
+        (pcb.pid, String::from_utf8_lossy(pcb.comm()).into_owned())
+    };
+    eprintln!(
+        "process {pid} ({name}, thread {}) killed: {why}",
+        running_thread_tid()
+    );
+    kill_process(signal);
+}
+
+/// Called at the end of the timer interrupt handler, killing the running process if it's used up
+/// its CPU time limit. Only processes interrupted in user mode are killed, so that nothing is
+/// left half done in the kernel. One in the middle of a syscall is caught on a later tick.
+unsafe extern "C" fn enforce_cpu_limit(return_cs: u32) {
+    if return_cs & 0b11 != USER_RPL {
+        return;
+    }
+    let exceeded = {
+        let running = unwrap_system().threads.running_thread.lock();
+        running.as_deref().is_some_and(cpu_limit_exceeded)
+    };
+    if exceeded {
+        kill_running_process(SIGXCPU, format_args!("CPU time limit exceeded"));
+    }
+}
+
 #[naked]
 pub unsafe extern "C" fn unhandled_handler() -> ! {
     fn inner() -> ! {
@@ -26,35 +257,52 @@ pub unsafe extern "C" fn unhandled_handler() -> ! {
 
 #[naked]
 pub unsafe extern "C" fn page_fault_handler() -> ! {
-    unsafe fn inner(error_code: u32, return_eip: usize) {
+    unsafe fn inner(error_code: u32, return_eip: usize, return_cs: u32) {
         let vaddr: usize;
         asm!("mov {}, cr2", out(reg) vaddr);
+        // Interrupts are still off, so the timer interrupt handler can't be holding this.
+        if let Some(running) = unwrap_system().threads.running_thread.lock().as_mut() {
+            running.usage.page_faults += 1;
+        }
         // important: re-enable interrupts before acquiring lock to prevent deadlock
         intr_enable();
+        let error = PageFaultError(error_code);
         let pcb = running_process();
         let pcb = pcb.lock();
         // try checking for a VMA matching this address
-        if !pcb.vmas.install_pte(vaddr) {
-            panic!("page fault with error code {error_code:#b} occurred when trying to access {vaddr:#X} from instruction at {return_eip:#X}");
+        if pcb.vmas.install_pte(vaddr, error.write()) {
+            return;
         }
+        drop(pcb);
+        page_fault_action(FaultFrame {
+            error_code,
+            eip: return_eip,
+            cs: return_cs,
+            cr2: vaddr,
+        })
+        .take();
     }
 
     asm!(
         "
         pusha
-        # pusha pushes 8 registers, so to get past them we need to add 8 * 4 = 32 bytes to the stack pointer
-        # first push return_eip, which is above error_code on the stack, so need to add 4 extra bytes
-        push [esp+36]
-        # now push error_code; due to previous push we need to add 4 extra bytes here as well
-        push [esp+36]
+        push 0xE
+        call {} // Count the interrupt
+        add esp, 4
+        # past the 32 bytes pushed by pusha are the error code, return_eip and return_cs, and each
+        # push moves the next one 4 bytes further away
+        push [esp+40]
+        push [esp+40]
+        push [esp+40]
         call {}
         # pop arguments
-        add esp, 8
+        add esp, 12
         popa
         # pop error code argument
         add esp, 4
         iretd
         ",
+        sym stats::record_interrupt,
         sym inner,
         options(noreturn),
     )
@@ -62,10 +310,49 @@ pub unsafe extern "C" fn page_fault_handler() -> ! {
 
 #[naked]
 pub unsafe extern "C" fn general_protection_fault_handler() -> ! {
-    unsafe fn inner(error_code: u32, return_eip: usize) -> ! {
-        panic!("general protection fault with error code {error_code:#b} occurred from instruction at {return_eip:#X}");
+    unsafe fn inner(error_code: u32, return_eip: usize, return_cs: u32) -> ! {
+        general_protection_fault_action(FaultFrame {
+            error_code,
+            eip: return_eip,
+            cs: return_cs,
+            cr2: 0,
+        })
+        .take()
     }
 
+    asm!(
+        "
+        pusha
+        push 0xD
+        call {} // Count the interrupt
+        add esp, 4
+        # past the 32 bytes pushed by pusha are the error code, return_eip and return_cs, and each
+        # push moves the next one 4 bytes further away
+        push [esp+40]
+        push [esp+40]
+        push [esp+40]
+        call {}
+        ",
+        sym stats::record_interrupt,
+        sym inner,
+        options(noreturn),
+    )
+}
+
+/// Entry point of the double fault task, which the CPU switches to through the task gate in the
+/// IDT. It runs on its own stack, so this works even if the fault came from overflowing the
+/// kernel stack. The faulting task's registers were saved to the main TSS by the task switch.
+#[naked]
+pub unsafe extern "C" fn double_fault_handler() -> ! {
+    unsafe fn inner() -> ! {
+        stats::record_interrupt(0x8);
+        let cr2: usize;
+        asm!("mov {}, cr2", out(reg) cr2);
+        let tss = addr_of!(TASK_STATE_SEGMENT).read();
+        panic!("{}", DoubleFaultMessage { tss: &tss, cr2 });
+    }
+
+    // The error code is always 0, so it's left on the stack.
     asm!(
         "
         call {}
@@ -79,25 +366,39 @@ pub unsafe extern "C" fn general_protection_fault_handler() -> ! {
 pub unsafe extern "C" fn syscall_handler() -> ! {
     asm!(
         "
-        // Push arguments to stack.
+        // Save the program's registers, below the ones the CPU pushed, as a SyscallFrame.
+        push ebp
+        push edi
+        push esi
         push edx
         push ecx
         push ebx
         push eax
 
-        // TODO: We need to define what our syscall ABI is allowed to clobber
-        // and what it must preserve, then actually do that. We should also
-        // investigate what actual OSs do to ensure that we're not leaking
-        // sensitive kernel data, even if we are respecting our ABI.
+        push 0x80
+        call {} // Count the interrupt
+        add esp, 4
+
+        // TODO: We should investigate what actual OSs do to ensure that we're
+        // not leaking sensitive kernel data.
 
+        push esp // Pass the frame to the handler.
         call {}
-        // eax will contain the handler's return value, which is where it should
-        // remain when we return to the program.
+        add esp, 4
 
-        add esp, 16 // Drop arguments from stack.
+        // Restore the registers, with eax replaced by the handler's return
+        // value, or all of them replaced if a signal handler is being called.
+        pop eax
+        pop ebx
+        pop ecx
+        pop edx
+        pop esi
+        pop edi
+        pop ebp
 
         iretd
         ",
+        sym stats::record_interrupt,
         sym syscall::handler,
         options(noreturn),
     )
@@ -108,19 +409,33 @@ pub unsafe extern "C" fn timer_interrupt_handler() -> ! {
     asm!(
         "
         pusha
+        push 0x20
+        call {} // Count the interrupt
+        add esp, 4
         // Push IRQ0 value onto the stack.
         push 0x0
-        call {} // Update system clock
+        call {} // Update system clock, wake sleeping threads, and check for a hung thread
+        mov esi, eax // Whether a whole tick passed; esi is saved by pusha and across calls
         call {} // Send EOI signal to PICs
-        call {} // Yield process
+        // Interrupts which come early for a wakeup don't count towards the thread's time
+        mov eax, esi
+        test al, al // Only the low byte of a bool return value is defined
+        jz 2f
+        call {} // Preempt process if its time is up
+    2:
+        // past the argument and the 32 bytes pushed by pusha are the return eip and cs
+        push [esp+40]
+        call {} // Kill process if it's used up its CPU time limit
 
-        add esp, 4 // Drop arguments from stack
+        add esp, 8 // Drop arguments from stack
         popa
         iretd
         ",
+        sym stats::record_interrupt,
         sym timer::step_sys_clock,
         sym pic::send_eoi,
-        sym scheduling::scheduler_yield_and_continue,
+        sym scheduling::scheduler_tick,
+        sym enforce_cpu_limit,
         options(noreturn),
     )
 }
@@ -130,19 +445,23 @@ pub unsafe extern "C" fn ide_prim_interrupt_handler() -> ! {
     asm!(
     "
     pusha
+    push 0x2E
+    call {} // Count the interrupt
+    add esp, 4
     // Push IRQ14 value onto the stack.
     push 0XE
     call {} // Send irq signal to ATA
     call {} // Send EOI signal to PICs
-    call {} // Yield process
+    call {} // Preempt process
 
     add esp, 4 // Drop arguments from stack
     popa
     iretd
     ",
+    sym stats::record_interrupt,
     sym ata_interrupt::on_ide_interrupt,
     sym pic::send_eoi,
-    sym scheduling::scheduler_yield_and_continue,
+    sym scheduling::scheduler_preempt,
     options(noreturn),
     )
 }
@@ -152,19 +471,41 @@ pub unsafe extern "C" fn ide_secd_interrupt_handler() -> ! {
     asm!(
     "
     pusha
+    push 0x2F
+    call {} // Count the interrupt
+    add esp, 4
     // Push IRQ15 value onto the stack.
     push 0XF
     call {} // Send irq signal to ATA
     call {} // Send EOI signal to PICs
-    call {} // Yield process
+    call {} // Preempt process
 
     add esp, 4 // Drop arguments from stack
     popa
     iretd
     ",
+    sym stats::record_interrupt,
     sym ata_interrupt::on_ide_interrupt,
     sym pic::send_eoi,
-    sym scheduling::scheduler_yield_and_continue,
+    sym scheduling::scheduler_preempt,
+    options(noreturn),
+    )
+}
+
+/// The local APIC sends these when an interrupt goes away before it's delivered. They don't get an
+/// EOI.
+#[naked]
+pub unsafe extern "C" fn spurious_interrupt_handler() -> ! {
+    asm!(
+    "
+    pusha
+    push 0xFF
+    call {} // Count the interrupt
+    add esp, 4
+    popa
+    iretd
+    ",
+    sym stats::record_interrupt,
     options(noreturn),
     )
 }
@@ -174,19 +515,137 @@ pub unsafe extern "C" fn keyboard_handler() -> ! {
     asm!(
     "
     pusha
+    push 0x21
+    call {} // Count the interrupt
+    add esp, 4
     // Push IRQ1 value onto the stack.
     push 0X1
     call {} // Handle keyboard interrupt
     call {} // Send EOI signal to PICs
-    call {} // Yield process
+    call {} // Preempt process
 
     add esp, 4 // Drop arguments from stack
     popa
     iretd
     ",
+    sym stats::record_interrupt,
     sym keyboard::atkbd::on_keyboard_interrupt,
     sym pic::send_eoi,
-    sym scheduling::scheduler_yield_and_continue,
+    sym scheduling::scheduler_preempt,
     options(noreturn),
     )
 }
+
+#[cfg(test)]
+mod test {
+    use super::{
+        general_protection_fault_action, page_fault_action, DoubleFaultMessage, FaultAction,
+        FaultFrame, PageFaultError,
+    };
+    use crate::user_program::syscall::SIGSEGV;
+    use kidneyos_shared::global_descriptor_table::{KERNEL_CODE_SELECTOR, USER_CODE_SELECTOR};
+    use kidneyos_shared::task_state_segment::TaskStateSegment;
+
+    fn frame(error_code: u32, cs: u16, cr2: usize) -> FaultFrame {
+        FaultFrame {
+            error_code,
+            eip: 0x8048123,
+            cs: cs.into(),
+            cr2,
+        }
+    }
+
+    #[test]
+    fn page_fault_error() {
+        assert_eq!(
+            PageFaultError(0b110).to_string(),
+            "page not present on write in user mode (0b110)"
+        );
+        assert_eq!(
+            PageFaultError(0b10101).to_string(),
+            "protection violation on instruction fetch in user mode (0b10101)"
+        );
+        assert_eq!(
+            PageFaultError(0b0).to_string(),
+            "page not present on read in kernel mode (0b0)"
+        );
+        assert!(!PageFaultError(0b11).user());
+    }
+
+    #[test]
+    fn user_page_fault_kills_process() {
+        // a write to an unmapped page, from user mode
+        let action = page_fault_action(frame(0b110, USER_CODE_SELECTOR, 0xdead0));
+        let FaultAction::Kill(SIGSEGV, why) = action else {
+            panic!("{action:?}");
+        };
+        assert_eq!(
+            why.to_string(),
+            "page fault (page not present on write in user mode (0b110)) when trying to access \
+             0xDEAD0 from instruction at 0x8048123"
+        );
+    }
+
+    #[test]
+    fn kernel_page_fault_panics() {
+        // a read of a null pointer in the kernel
+        let action = page_fault_action(frame(0b0, KERNEL_CODE_SELECTOR, 0));
+        let FaultAction::Panic(message) = action else {
+            panic!("{action:?}");
+        };
+        assert_eq!(
+            message.to_string(),
+            "page fault (page not present on read in kernel mode (0b0)) when trying to access 0x0 \
+             from instruction at 0x8048123"
+        );
+    }
+
+    #[test]
+    fn general_protection_fault() {
+        // e.g. loading a bad segment selector from user mode
+        let action = general_protection_fault_action(frame(0x28, USER_CODE_SELECTOR, 0));
+        let FaultAction::Kill(SIGSEGV, why) = action else {
+            panic!("{action:?}");
+        };
+        assert_eq!(
+            why.to_string(),
+            "general protection fault with error code 0x28 at instruction 0x8048123"
+        );
+
+        let action = general_protection_fault_action(frame(0, KERNEL_CODE_SELECTOR, 0));
+        let FaultAction::Panic(message) = action else {
+            panic!("{action:?}");
+        };
+        assert_eq!(
+            message.to_string(),
+            "general protection fault with error code 0x0 occurred from instruction at 0x8048123"
+        );
+    }
+
+    #[test]
+    fn double_fault_registers() {
+        let mut tss = TaskStateSegment::kernel_task(0, 0, 0);
+        tss.eip = 0xc0101234;
+        tss.eax = 1;
+        tss.ebx = 2;
+        tss.ecx = 3;
+        tss.edx = 4;
+        tss.esi = 5;
+        tss.edi = 6;
+        tss.esp = 0xc0200000;
+        tss.ebp = 0xc0200010;
+        tss.eflags = 0x46;
+        tss.cs = KERNEL_CODE_SELECTOR;
+        tss.ss = 0x10;
+        assert_eq!(
+            DoubleFaultMessage {
+                tss: &tss,
+                cr2: 0xc01ffffc
+            }
+            .to_string(),
+            "double fault from instruction at 0xC0101234\n\
+             eax=0x1 ebx=0x2 ecx=0x3 edx=0x4 esi=0x5 edi=0x6\n\
+             esp=0xC0200000 ebp=0xC0200010 eflags=0x46 cs=0x8 ss=0x10 cr2=0xC01FFFFC"
+        );
+    }
+}
@@ -1,6 +1,14 @@
+#[cfg_attr(not(feature = "apic"), allow(dead_code))]
+mod acpi;
+#[cfg_attr(not(feature = "apic"), allow(dead_code))]
+pub mod apic;
+#[cfg_attr(not(feature = "gdb_stub"), allow(dead_code))]
+pub mod gdb_stub;
 pub mod idt;
 pub mod mutex_irq;
 pub mod pic;
+pub mod softirq;
+pub mod stats;
 
 mod intr_handler;
 pub mod timer;
@@ -1,4 +1,4 @@
-use core::arch::asm;
+use super::timer;
 use kidneyos_shared::serial::{inb, outb};
 
 pub const PIC1_OFFSET: u8 = 0x20;
@@ -42,24 +42,20 @@ pub unsafe fn pic_remap(offset1: u8, offset2: u8) {
 }
 
 pub unsafe fn init_pit() {
-    // program the PIT
-    // channel 0 (bit 6-7), lo/hi-byte (bit 4-5), rate generator (bit 1-3)
-    outb(0x43, 0b00110100);
-
-    asm!(
-        "
-        mov ax, 0xffff // (reload value)
-        out 0x40, al // set low byte of PIT reload value
-        mov al, ah
-        out 0x40, al // set high byte of PIT reload value
-        ",
-    );
+    // The PIT is reprogrammed after each timer interrupt, for the next one.
+    timer::program_pit(timer::MAX_RELOAD);
 
     // unmask and activate all IRQs
     outb(PIC1_DATA, 0x0);
     outb(PIC2_DATA, 0x0);
 }
 
+/// Mask every IRQ, e.g. so that the APIC can be used instead.
+pub unsafe fn mask_all() {
+    outb(PIC1_DATA, 0xff);
+    outb(PIC2_DATA, 0xff);
+}
+
 #[allow(unused)]
 pub unsafe fn irq_mask(mut irq: u8) {
     let port = if irq < 8 { PIC1_DATA } else { PIC2_DATA };
@@ -83,6 +79,12 @@ pub unsafe fn irq_unmask(mut irq: u8) {
 }
 
 pub unsafe fn send_eoi(irq: u8) {
+    #[cfg(feature = "apic")]
+    if super::apic::is_enabled() {
+        super::apic::send_eoi();
+        return;
+    }
+
     if irq >= 8 {
         outb(PIC2_CMD, PIC_EOI);
     }
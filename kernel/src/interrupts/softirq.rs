@@ -0,0 +1,108 @@
+use crate::sync::mutex::Mutex;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Callbacks run after timer ticks, the next time a thread yields or the CPU is idle, rather than
+/// in the timer interrupt handler itself, for periodic work which doesn't need to happen on the
+/// tick exactly.
+struct Softirqs {
+    handlers: Mutex<Vec<fn()>>,
+    /// Whether there's been a tick since the handlers last ran.
+    pending: AtomicBool,
+}
+
+impl Softirqs {
+    const fn new() -> Self {
+        Self {
+            handlers: Mutex::new(Vec::new()),
+            pending: AtomicBool::new(false),
+        }
+    }
+
+    fn register(&self, handler: fn()) {
+        self.handlers.lock().push(handler);
+    }
+
+    /// Mark the handlers to be run. However many times this is called before they're run, they
+    /// only run once.
+    fn raise(&self) {
+        self.pending.store(true, Ordering::Release);
+    }
+
+    /// Run the handlers, if they've been raised since they last ran. Returns whether they were.
+    fn run_pending(&self) -> bool {
+        if !self.pending.swap(false, Ordering::AcqRel) {
+            return false;
+        }
+        // Copied out, so a handler can register another without deadlocking.
+        let handlers = self.handlers.lock().clone();
+        for handler in handlers {
+            handler();
+        }
+        true
+    }
+}
+
+static SOFTIRQS: Softirqs = Softirqs::new();
+
+/// Have `handler` called after every timer tick, the next time a thread yields or the CPU is idle.
+///
+/// It's called with interrupts disabled, so it mustn't block, and should be quick.
+pub fn register_softirq(handler: fn()) {
+    SOFTIRQS.register(handler);
+}
+
+/// Called from the timer interrupt handler on each tick.
+pub fn raise_softirqs() {
+    SOFTIRQS.raise();
+}
+
+/// The dispatch point: run the softirq handlers, if there's been a tick since they last ran.
+/// Interrupts must be disabled, so that this can't be reentered from an interrupt handler, but it
+/// mustn't be called from one.
+pub fn run_softirqs() {
+    SOFTIRQS.run_pending();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Softirqs;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn runs_once_per_tick() {
+        static SOFTIRQS: Softirqs = Softirqs::new();
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        SOFTIRQS.register(|| {
+            COUNT.fetch_add(1, Ordering::SeqCst);
+        });
+
+        // nothing runs until there's been a tick
+        assert!(!SOFTIRQS.run_pending());
+        assert_eq!(COUNT.load(Ordering::SeqCst), 0);
+
+        SOFTIRQS.raise();
+        assert_eq!(
+            COUNT.load(Ordering::SeqCst),
+            0,
+            "ran in the interrupt handler"
+        );
+        assert!(SOFTIRQS.run_pending());
+        assert_eq!(COUNT.load(Ordering::SeqCst), 1);
+
+        // scheduling again before the next tick doesn't run it again
+        assert!(!SOFTIRQS.run_pending());
+        assert_eq!(COUNT.load(Ordering::SeqCst), 1);
+
+        SOFTIRQS.raise();
+        assert!(SOFTIRQS.run_pending());
+        assert_eq!(COUNT.load(Ordering::SeqCst), 2);
+
+        // ticks which come before the scheduler gets to run it are run together
+        SOFTIRQS.raise();
+        SOFTIRQS.raise();
+        assert!(SOFTIRQS.run_pending());
+        assert!(!SOFTIRQS.run_pending());
+        assert_eq!(COUNT.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,59 @@
+use crate::user_program::syscall::INTERRUPT_VECTORS;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// How many times each interrupt vector has fired, reported by the `interrupt_counts` syscall.
+///
+/// Only vectors with a handler installed in the IDT are counted; anything else panics anyway.
+pub static INTERRUPT_COUNTS: InterruptCounts = InterruptCounts::new();
+
+pub struct InterruptCounts([AtomicUsize; INTERRUPT_VECTORS]);
+
+impl InterruptCounts {
+    pub const fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const ZERO: AtomicUsize = AtomicUsize::new(0);
+        Self([ZERO; INTERRUPT_VECTORS])
+    }
+
+    pub fn record(&self, vector: u8) {
+        self.0[usize::from(vector)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Copy the counts for the first `counts.len()` vectors into `counts`, returning how many
+    /// were copied.
+    pub fn snapshot(&self, counts: &mut [usize]) -> usize {
+        let len = counts.len().min(INTERRUPT_VECTORS);
+        for (count, counter) in counts.iter_mut().zip(&self.0) {
+            *count = counter.load(Ordering::Relaxed);
+        }
+        len
+    }
+}
+
+/// Called at the start of each interrupt handler with its vector.
+pub fn record_interrupt(vector: u8) {
+    INTERRUPT_COUNTS.record(vector);
+}
+
+#[cfg(test)]
+mod test {
+    use super::InterruptCounts;
+
+    #[test]
+    fn counters() {
+        let counts = InterruptCounts::new();
+        for _ in 0..5 {
+            counts.record(0x80);
+        }
+        counts.record(0x20);
+        let mut snapshot = [usize::MAX; 0x100];
+        assert_eq!(counts.snapshot(&mut snapshot), 0x100);
+        assert_eq!(snapshot[0x80], 5);
+        assert_eq!(snapshot[0x20], 1);
+        assert_eq!(snapshot[0x21], 0);
+        // only the vectors asked for
+        let mut snapshot = [usize::MAX; 0x21];
+        assert_eq!(counts.snapshot(&mut snapshot), 0x21);
+        assert_eq!(snapshot[0x20], 1);
+    }
+}
@@ -1,36 +1,533 @@
-use super::mutex_irq::MutexIrq;
-use crate::threading::scheduling::scheduler_yield_and_continue;
+use super::mutex_irq::{hold_interrupts, MutexIrq};
+use super::softirq::raise_softirqs;
+use super::{intr_disable, intr_enable, IntrLevel};
+use crate::system::{running_thread_tid, unwrap_system};
+use crate::threading::process::Tid;
+use crate::threading::thread_sleep::{thread_sleep, thread_wakeup};
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
 use core::time::Duration;
+use kidneyos_shared::eprintln;
+use kidneyos_shared::mem::OFFSET;
+use kidneyos_shared::serial::{inb, outb};
 
-// PIT generates 3579545 / 3 Hz input signal which we wait to receive 0xffff (65535) of before sending a timer interrupt.
-// This gives us an interval of 0xffff * 3 / 3579545 seconds between each timer interrupt
+// The PIT counts down from its reload value at 3579545 / 3 Hz, and sends a timer interrupt when it
+// gets to 0. It's used in one-shot mode, so it's reprogrammed after each interrupt.
 // https://wiki.osdev.org/Programmable_Interval_Timer
-pub const TIMER_INTERRUPT_INTERVAL: Duration =
-    Duration::from_micros((10u64).pow(6) * 0xffff * 3 / 3579545);
+const PIT_CHANNEL0: u16 = 0x40;
+const PIT_COMMAND: u16 = 0x43;
+/// channel 0 (bits 6-7), lo/hi byte (bits 4-5), interrupt on terminal count (bits 1-3)
+const PIT_ONE_SHOT: u8 = 0b00110000;
+/// read-back (bits 6-7), latch count (bit 5 clear) and status (bit 4 clear), channel 0 (bit 1)
+const PIT_READ_BACK: u8 = 0b11000010;
+/// The status bit giving the PIT's output, which goes high once it's counted down to 0.
+const PIT_STATUS_OUTPUT: u8 = 0b10000000;
 
-static SYS_CLOCK: MutexIrq<Duration> = MutexIrq::new(Duration::new(0, 0));
+/// The longest the PIT can wait.
+pub const MAX_RELOAD: u16 = 0xffff;
+/// The shortest the PIT is programmed to wait, so that a wakeup which is due (or overdue) doesn't
+/// turn into an interrupt storm.
+const MIN_RELOAD: u16 = 0x80;
 
-pub fn step_sys_clock() {
-    let mut clock = SYS_CLOCK.lock();
-    match clock.checked_add(TIMER_INTERRUPT_INTERVAL) {
-        Some(update) => {
-            *clock = update;
+/// How long the PIT takes to count down `counts` times.
+const fn pit_interval(counts: u32) -> Duration {
+    Duration::from_nanos(counts as u64 * 3_000_000_000 / 3579545)
+}
+
+/// The reload value for the PIT to go off after `time`, within the range it can wait for.
+fn reload_for(time: Duration) -> u16 {
+    let counts = (time.as_nanos() * 3579545).div_ceil(3_000_000_000);
+    counts.clamp(MIN_RELOAD.into(), MAX_RELOAD.into()) as u16
+}
+
+/// The interval between timer ticks, which is how often the timer goes off when nothing is
+/// sleeping. The scheduler's time slices and the watchdog are counted in these.
+pub const TIMER_INTERRUPT_INTERVAL: Duration = pit_interval(MAX_RELOAD as u32);
+
+/// The number of timer ticks in a second, rounded down.
+pub const TICKS_PER_SECOND: usize =
+    (Duration::from_secs(1).as_micros() / TIMER_INTERRUPT_INTERVAL.as_micros()) as usize;
+
+/// The time since boot at which timer tick number `tick` comes, or the furthest time that can be
+/// represented if it's too far away.
+pub fn tick_time(tick: u64) -> Duration {
+    Duration::from_nanos((TIMER_INTERRUPT_INTERVAL.as_nanos() as u64).saturating_mul(tick))
+}
+
+/// The number of whole timer ticks between boot and `time`.
+fn ticks_at(time: Duration) -> u64 {
+    (time.as_nanos() / TIMER_INTERRUPT_INTERVAL.as_nanos()) as u64
+}
+
+/// What the PIT has counted down to, as read by [`read_pit`].
+#[derive(Clone, Copy)]
+struct PitReading {
+    count: u16,
+    /// Whether it's got to 0 and gone off. It wraps around and keeps counting down after that.
+    fired: bool,
+}
+
+/// The system clock, along with the threads sleeping until some point on it.
+pub struct Timer {
+    /// Time since boot, as of when the PIT was last programmed
+    clock: Duration,
+    /// The latest time [`Timer::now`] has returned, which it never goes back before
+    latest: Duration,
+    /// What the PIT was last programmed with
+    reload: u16,
+    /// Time since the last whole `TIMER_INTERRUPT_INTERVAL`
+    since_tick: Duration,
+    /// Threads waiting in [`sleep`] or to be woken by [`wake_at`], by when they should wake up
+    sleepers: BTreeMap<Duration, Vec<Tid>>,
+}
+
+impl Timer {
+    pub const fn new() -> Self {
+        Self {
+            clock: Duration::ZERO,
+            latest: Duration::ZERO,
+            reload: MAX_RELOAD,
+            since_tick: Duration::ZERO,
+            sleepers: BTreeMap::new(),
+        }
+    }
+
+    /// The time since the PIT was last programmed, given what it's counted down to.
+    fn elapsed(&self, reading: PitReading) -> Duration {
+        let counts = if reading.fired {
+            // however far it's got since wrapping around, as long as it hasn't done so twice
+            self.reload as u32 + 0u16.wrapping_sub(reading.count) as u32
+        } else {
+            self.reload.saturating_sub(reading.count) as u32
+        };
+        pit_interval(counts)
+    }
+
+    /// The current time since boot, given what the PIT has counted down to.
+    fn now(&mut self, reading: PitReading) -> Duration {
+        self.latest = self.latest.max(self.clock + self.elapsed(reading));
+        self.latest
+    }
+
+    /// The reload value the PIT should have for the next interrupt, after `self.clock`.
+    fn next_reload(&self) -> u16 {
+        match self.sleepers.first_key_value() {
+            Some((&deadline, _)) => reload_for(deadline.saturating_sub(self.clock)),
+            None => MAX_RELOAD,
+        }
+    }
+
+    /// Called when the PIT goes off, with what it's counted down to since. Advances the clock by
+    /// the time that's actually passed, including however late the interrupt was handled, and
+    /// returns the threads to wake up, what to reprogram the PIT with, and whether a whole tick
+    /// has passed since the last one.
+    fn expire(&mut self, reading: PitReading) -> (Vec<Tid>, u16, bool) {
+        let now = self.now(reading);
+        self.since_tick += now - self.clock;
+        self.clock = now;
+        let ticked = self.since_tick >= TIMER_INTERRUPT_INTERVAL;
+        if ticked {
+            self.since_tick -= TIMER_INTERRUPT_INTERVAL;
+        }
+        let mut woken = vec![];
+        while let Some(entry) = self.sleepers.first_entry() {
+            if *entry.key() > self.clock {
+                break;
+            }
+            woken.extend(entry.remove());
+        }
+        self.reload = self.next_reload();
+        (woken, self.reload, ticked)
+    }
+
+    /// Sleep `tid` until `deadline`, given what the PIT has counted down to. If that's before the
+    /// PIT goes off, returns what to reprogram it with.
+    fn add(&mut self, deadline: Duration, tid: Tid, reading: PitReading) -> Option<u16> {
+        self.sleepers.entry(deadline).or_default().push(tid);
+        if deadline >= self.clock + pit_interval(self.reload as u32) {
+            return None;
+        }
+        let now = self.now(reading);
+        self.since_tick += now - self.clock;
+        self.clock = now;
+        self.reload = self.next_reload();
+        Some(self.reload)
+    }
+
+    /// Stop waking `tid` at `deadline`. The PIT is left alone, since going off early does no harm.
+    fn remove(&mut self, deadline: Duration, tid: Tid) {
+        if let Some(tids) = self.sleepers.get_mut(&deadline) {
+            tids.retain(|&sleeper| sleeper != tid);
+            if tids.is_empty() {
+                self.sleepers.remove(&deadline);
+            }
+        }
+    }
+}
+
+static TIMER: MutexIrq<Timer> = MutexIrq::new(Timer::new());
+
+/// Start the PIT counting down from `reload`.
+///
+/// # Safety
+///
+/// Interrupts must be disabled.
+pub unsafe fn program_pit(reload: u16) {
+    outb(PIT_COMMAND, PIT_ONE_SHOT);
+    outb(PIT_CHANNEL0, reload as u8);
+    outb(PIT_CHANNEL0, (reload >> 8) as u8);
+}
+
+/// What the PIT has counted down to.
+///
+/// # Safety
+///
+/// Interrupts must be disabled.
+unsafe fn read_pit() -> PitReading {
+    outb(PIT_COMMAND, PIT_READ_BACK);
+    // the status comes first, then the count
+    let status = inb(PIT_CHANNEL0);
+    let low = inb(PIT_CHANNEL0);
+    let high = inb(PIT_CHANNEL0);
+    PitReading {
+        count: u16::from_le_bytes([low, high]),
+        fired: status & PIT_STATUS_OUTPUT != 0,
+    }
+}
+
+/// How long a thread can run for without a context switch before the watchdog decides the kernel
+/// is hung. Threads are normally preempted every tick, so this is very generous.
+pub const WATCHDOG_TIMEOUT: Duration = Duration::from_secs(10);
+
+const WATCHDOG_TICKS: usize =
+    (WATCHDOG_TIMEOUT.as_micros() / TIMER_INTERRUPT_INTERVAL.as_micros()) as usize;
+
+/// Counts timer ticks since the last context switch, to catch a thread which never gives up the
+/// CPU, e.g. because preemption is broken.
+///
+/// It can't catch a thread spinning with interrupts disabled, since then the timer never fires.
+pub struct Watchdog {
+    ticks: AtomicUsize,
+}
+
+impl Watchdog {
+    pub const fn new() -> Self {
+        Self {
+            ticks: AtomicUsize::new(0),
+        }
+    }
+
+    /// Count a timer tick, returning the number of ticks the running thread has had if it's more
+    /// than `limit`.
+    pub fn tick(&self, limit: usize) -> Option<usize> {
+        let ticks = self.ticks.fetch_add(1, Ordering::Relaxed) + 1;
+        (ticks > limit).then_some(ticks)
+    }
+
+    /// Reset the count, since the CPU has moved on to another thread (or is idle).
+    pub fn pet(&self) {
+        self.ticks.store(0, Ordering::Relaxed);
+    }
+}
+
+pub static WATCHDOG: Watchdog = Watchdog::new();
+
+/// Called on each timer tick, panicking if the running thread has had the CPU for longer than
+/// `WATCHDOG_TIMEOUT`.
+fn watchdog_tick() {
+    let Some(ticks) = WATCHDOG.tick(WATCHDOG_TICKS) else {
+        return;
+    };
+    print_backtrace();
+    // The thread might have been stopped while holding the lock, so don't wait for it.
+    let tid = unwrap_system()
+        .threads
+        .running_thread
+        .try_lock()
+        .and_then(|running| running.as_ref().map(|tcb| tcb.tid));
+    panic!("watchdog: thread {tid:?} has run for {ticks} ticks without a context switch");
+}
+
+/// Print the return addresses on the stack, by following the frame pointers.
+///
+/// When called from an interrupt handler, this continues into the interrupted code, since the
+/// handlers leave `ebp` alone.
+fn print_backtrace() {
+    eprintln!("backtrace:");
+    #[cfg(target_arch = "x86")]
+    {
+        let mut ebp: usize;
+        // SAFETY: Only reads the frame pointer register.
+        unsafe { core::arch::asm!("mov {}, ebp", out(reg) ebp) };
+        // Only follow frames on kernel stacks, and not forever in case one's been corrupted.
+        for _ in 0..32 {
+            if ebp < OFFSET || ebp % core::mem::align_of::<usize>() != 0 {
+                break;
+            }
+            // SAFETY: With frame pointers, ebp points at the saved ebp of the caller, and the
+            // return address is just above it.
+            let (saved_ebp, return_address) =
+                unsafe { (*(ebp as *const usize), *(ebp as *const usize).add(1)) };
+            eprintln!("  {return_address:#010x}");
+            ebp = saved_ebp;
+        }
+    }
+}
+
+/// Called from the timer interrupt handler: advances the system clock, wakes up sleeping threads
+/// which are due, and programs the PIT for the next wakeup or tick, whichever is sooner. Returns
+/// whether a whole tick has passed, rather than the interrupt coming early for a wakeup.
+pub extern "C" fn step_sys_clock() -> bool {
+    let (woken, reload, ticked) = {
+        let mut timer = TIMER.lock();
+        // SAFETY: We're in an interrupt handler, so interrupts are disabled.
+        let reading = unsafe { read_pit() };
+        timer.expire(reading)
+    };
+    // SAFETY: We're in an interrupt handler, so interrupts are disabled.
+    unsafe { program_pit(reload) };
+    for tid in woken {
+        thread_wakeup(tid);
+    }
+    // Interrupts which come early for a wakeup don't count towards the watchdog's limit, or run
+    // softirqs.
+    if ticked {
+        raise_softirqs();
+        watchdog_tick();
+    }
+    ticked
+}
+
+/// The time since boot.
+pub fn time_since_boot() -> Duration {
+    let mut timer = TIMER.lock();
+    // SAFETY: Interrupts are disabled while the timer is locked.
+    let reading = unsafe { read_pit() };
+    timer.now(reading)
+}
+
+/// The current timer tick: the number of whole ticks since boot.
+pub fn current_tick() -> u64 {
+    ticks_at(time_since_boot())
+}
+
+/// Wake the thread `tid` with `thread_wakeup` at `deadline`, as a time since boot, unless
+/// `cancel_wakeup` is called first. Returns false, and does nothing, if it's already passed.
+pub fn wake_at(deadline: Duration, tid: Tid) -> bool {
+    let mut timer = TIMER.lock();
+    // SAFETY: Interrupts are disabled while the timer is locked.
+    let reading = unsafe { read_pit() };
+    if deadline <= timer.now(reading) {
+        return false;
+    }
+    if let Some(reload) = timer.add(deadline, tid, reading) {
+        // SAFETY: Interrupts are disabled.
+        unsafe { program_pit(reload) };
+    }
+    true
+}
+
+/// Cancel waking `tid` at `deadline`, as arranged with `wake_at`.
+pub fn cancel_wakeup(deadline: Duration, tid: Tid) {
+    TIMER.lock().remove(deadline, tid);
+}
+
+/// Block the running thread for at least `time`.
+///
+/// Interrupts are enabled while it's blocked, then put back how they were.
+pub fn sleep(time: Duration) {
+    block_until(|now| {
+        now.checked_add(time)
+            .expect("Wakeup time is too far into the future!")
+    });
+}
+
+/// Block the running thread until at least `deadline`, as a time since boot, returning straight
+/// away if it's already passed.
+///
+/// Interrupts are enabled while it's blocked, then put back how they were.
+pub fn sleep_until(deadline: Duration) {
+    block_until(|_| deadline);
+}
+
+/// Block the running thread until the time `deadline` gives, given the current time.
+fn block_until(deadline: impl FnOnce(Duration) -> Duration) {
+    let _guard = hold_interrupts(IntrLevel::IntrOff);
+    let deadline = deadline(time_since_boot());
+    if !wake_at(deadline, running_thread_tid()) {
+        return;
+    }
+    loop {
+        // Interrupts are off between checking the time and blocking, so the wakeup can't come in
+        // between.
+        let now = TIMER.lock().clock;
+        if now >= deadline {
+            break;
         }
-        None => panic!("System clock overflowed!"),
+        thread_sleep();
+        // let the timer go off, in case there was nothing else to run
+        intr_enable();
+        intr_disable();
     }
 }
 
-#[allow(unused)]
-#[allow(clippy::while_immutable_condition)]
-pub fn sleep(time: Duration) -> usize {
-    let clock = SYS_CLOCK.lock();
-    match clock.checked_add(time) {
-        Some(end) => {
-            while *clock < end {
-                scheduler_yield_and_continue();
+#[cfg(test)]
+mod test {
+    use super::{
+        pit_interval, reload_for, tick_time, ticks_at, PitReading, Timer, Watchdog, MAX_RELOAD,
+    };
+    use core::time::Duration;
+
+    /// The PIT part way through counting down to `count`.
+    fn counted_to(count: u16) -> PitReading {
+        PitReading {
+            count,
+            fired: false,
+        }
+    }
+
+    /// The PIT having gone off, and counted `late` more since.
+    fn went_off(late: u16) -> PitReading {
+        PitReading {
+            count: 0u16.wrapping_sub(late),
+            fired: true,
+        }
+    }
+
+    #[test]
+    fn watchdog_fires_on_runaway_thread() {
+        let watchdog = Watchdog::new();
+        // a thread which is preempted every tick never trips it
+        for _ in 0..100 {
+            assert_eq!(watchdog.tick(5), None);
+            watchdog.pet();
+        }
+        // but one which keeps running does, once it's over the limit
+        for _ in 0..5 {
+            assert_eq!(watchdog.tick(5), None);
+        }
+        assert_eq!(watchdog.tick(5), Some(6));
+        assert_eq!(watchdog.tick(5), Some(7));
+        watchdog.pet();
+        assert_eq!(watchdog.tick(5), None);
+    }
+
+    #[test]
+    fn one_shot_wakeups() {
+        let ms = Duration::from_millis;
+        let mut timer = Timer::new();
+        // with nothing sleeping, the PIT goes off every tick
+        assert_eq!(timer.expire(went_off(0)), (vec![], MAX_RELOAD, true));
+        let start = timer.clock;
+
+        // both of these are sooner than the next tick, so the PIT is reprogrammed for each, ending
+        // up with the earlier one
+        let reload = timer
+            .add(start + ms(30), 1, counted_to(MAX_RELOAD))
+            .unwrap();
+        assert_eq!(reload, reload_for(ms(30)));
+        let reload = timer.add(start + ms(10), 2, counted_to(reload)).unwrap();
+        assert_eq!(reload, reload_for(ms(10)));
+        // but not for one after the interrupt it's already set for
+        assert_eq!(timer.add(start + ms(20), 3, counted_to(reload)), None);
+
+        let (woken, reload, ticked) = timer.expire(went_off(0));
+        assert_eq!(woken, [2]);
+        assert!(timer.clock >= start + ms(10));
+        assert!(!ticked);
+        assert_eq!(reload, reload_for(start + ms(20) - timer.clock));
+        assert_eq!(timer.expire(went_off(0)).0, [3]);
+        let (woken, reload, _) = timer.expire(went_off(0));
+        assert_eq!(woken, [1]);
+        assert!(timer.clock >= start + ms(30));
+        assert_eq!(reload, MAX_RELOAD);
+    }
+
+    #[test]
+    fn cancelled_wakeups() {
+        let ms = Duration::from_millis;
+        let mut timer = Timer::new();
+        let start = timer.clock;
+        timer.add(start + ms(10), 1, counted_to(MAX_RELOAD));
+        timer.add(start + ms(10), 2, counted_to(MAX_RELOAD));
+        timer.add(start + ms(20), 3, counted_to(MAX_RELOAD));
+
+        // e.g. because they were woken some other way first
+        timer.remove(start + ms(10), 1);
+        timer.remove(start + ms(20), 3);
+        // removing one which isn't there does nothing
+        timer.remove(start + ms(20), 2);
+
+        assert_eq!(timer.expire(went_off(0)).0, [2]);
+        // nothing's left, so the PIT goes back to going off every tick
+        let (woken, reload, _) = timer.expire(went_off(0));
+        assert!(woken.is_empty());
+        assert_eq!(reload, MAX_RELOAD);
+    }
+
+    #[test]
+    fn sleeping_for_ticks() {
+        let mut timer = Timer::new();
+        timer.expire(went_off(0));
+        // partway through a tick, thanks to an early wakeup
+        let reload = timer.add(
+            timer.clock + Duration::from_millis(10),
+            1,
+            counted_to(MAX_RELOAD),
+        );
+        timer.expire(went_off(0));
+        assert!(reload.is_some() && timer.clock > tick_time(ticks_at(timer.clock)));
+
+        // as sleep_ticks(3) would
+        let wake_tick = ticks_at(timer.clock) + 3;
+        assert_eq!(
+            timer.add(tick_time(wake_tick), 2, counted_to(timer.reload)),
+            None
+        );
+        loop {
+            let (woken, _, _) = timer.expire(went_off(0));
+            if woken == [2] {
+                break;
             }
-            0
+            assert!(woken.is_empty());
+            assert!(ticks_at(timer.clock) < wake_tick);
         }
-        None => panic!("Wakeup time is too far into the future!"),
+        assert!(ticks_at(timer.clock) >= wake_tick);
+    }
+
+    #[test]
+    fn late_interrupts_count() {
+        let mut timer = Timer::new();
+        // handled 100 counts after it went off, which the next tick doesn't make up for
+        timer.expire(went_off(100));
+        assert_eq!(timer.clock, pit_interval(MAX_RELOAD as u32 + 100));
+        timer.expire(went_off(0));
+        assert_eq!(timer.clock, pit_interval(2 * MAX_RELOAD as u32 + 100));
+    }
+
+    #[test]
+    fn now_never_goes_backwards() {
+        let ms = Duration::from_millis;
+        let mut timer = Timer::new();
+        let reload = timer.add(ms(10), 1, counted_to(MAX_RELOAD)).unwrap();
+        let start = timer.clock;
+
+        let before = timer.now(counted_to(1));
+        // once it's wrapped around, the count is back up high, but the time keeps going
+        let after = timer.now(went_off(10));
+        assert!(before < after);
+        assert_eq!(after, start + pit_interval(reload as u32 + 10));
+        // even if the PIT has wrapped around a second time
+        assert_eq!(timer.now(went_off(1)), after);
+
+        // and the clock doesn't go back when the interrupt finally arrives
+        timer.expire(went_off(0));
+        assert!(timer.clock >= after);
+    }
+
+    #[test]
+    fn far_off_ticks_saturate() {
+        assert_eq!(tick_time(u64::MAX), Duration::from_nanos(u64::MAX));
+        assert!(tick_time(u64::MAX / 2) >= tick_time(1000));
     }
 }
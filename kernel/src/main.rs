@@ -13,12 +13,14 @@
 #![feature(inline_const)]
 
 mod block;
+mod cmdline;
 mod drivers;
 pub mod fs;
 mod interrupts;
 pub mod mem;
 mod paging;
 mod rush;
+mod selftest;
 pub mod sync;
 mod system;
 mod threading;
@@ -28,19 +30,26 @@ pub mod vfs;
 extern crate alloc;
 
 use crate::block::block_core::BlockManager;
+use crate::cmdline::BootParams;
 use crate::drivers::ata::ata_core::ide_init;
 use crate::drivers::input::input_core::InputBuffer;
 use crate::fs::fs_manager::RootFileSystem;
+use crate::fs::tty::{receive_keyboard_input, Tty, VideoConsole};
+use crate::selftest::selftest_thread;
 use crate::sync::mutex::Mutex;
 use crate::sync::rwlock::sleep::RwLock;
 use crate::system::SystemState;
 use crate::threading::process::create_process_state;
 use crate::threading::thread_control_block::ThreadControlBlock;
+use crate::threading::work_queue::work_queue_worker;
 use alloc::boxed::Box;
-use interrupts::{idt, pic};
+use alloc::sync::Arc;
+use interrupts::{idt, pic, softirq::register_softirq};
 use kidneyos_shared::{global_descriptor_table, println, video_memory::VIDEO_MEMORY_WRITER};
 use mem::KernelAllocator;
-use threading::{create_thread_state, thread_system_start};
+use threading::{
+    create_thread_state, idle_function, scheduling::MLFQPriority, thread_system_start,
+};
 use vfs::tempfs::TempFS;
 
 #[cfg_attr(not(test), global_allocator)]
@@ -50,18 +59,40 @@ pub static mut KERNEL_ALLOCATOR: KernelAllocator = KernelAllocator::new();
 #[panic_handler]
 fn panic(args: &core::panic::PanicInfo) -> ! {
     kidneyos_shared::eprintln!("{}", args);
+    // so a test run under QEMU fails straight away, rather than hanging until it times out
+    drivers::qemu_exit::qemu_exit(drivers::qemu_exit::QemuExitCode::Failure);
     loop {}
 }
 
+#[cfg(not(feature = "initrd"))]
 const INIT: &[u8] =
     include_bytes!("../../programs/pipes/target/i686-unknown-linux-gnu/release/pipes").as_slice();
 
+/// Tar archive unpacked into the root filesystem at boot, from the path in `KIDNEYOS_INITRD` at
+/// build time.
+#[cfg(feature = "initrd")]
+const INITRD: &[u8] = include_bytes!(env!("KIDNEYOS_INITRD")).as_slice();
+
+/// Disk image registered as the block device `ram0`, from the path in `KIDNEYOS_RAMDISK` at build
+/// time.
+#[cfg(feature = "ramdisk")]
+const RAMDISK_IMAGE: &[u8] = include_bytes!(env!("KIDNEYOS_RAMDISK")).as_slice();
+
 #[cfg_attr(not(test), no_mangle)]
-extern "C" fn main(mem_upper: usize, video_memory_skip_lines: usize) -> ! {
+extern "C" fn main(
+    mem_upper: usize,
+    video_memory_skip_lines: usize,
+    cmdline: *const u8,
+    cmdline_len: usize,
+) -> ! {
     unsafe {
         VIDEO_MEMORY_WRITER.skip_lines(video_memory_skip_lines);
     }
 
+    // SAFETY: The trampoline copied the command line into its stack frame, which is never popped.
+    let cmdline = unsafe { core::slice::from_raw_parts(cmdline, cmdline_len) };
+    let params = BootParams::parse(core::str::from_utf8(cmdline).unwrap_or_default());
+
     // SAFETY: Single core, interrupts disabled.
     unsafe {
         KERNEL_ALLOCATOR.init(mem_upper);
@@ -71,11 +102,13 @@ extern "C" fn main(mem_upper: usize, video_memory_skip_lines: usize) -> ! {
         println!("IDTR set up!");
 
         println!("Enabling paging");
-        let page_manager = paging::enable();
+        #[cfg_attr(not(feature = "apic"), allow(unused_mut))]
+        let mut page_manager = paging::enable();
         println!("Paging enabled!");
 
         println!("Setting up GDTR");
         global_descriptor_table::load();
+        idt::init_double_fault_task();
         println!("GDTR set up!");
 
         println!("Setting up PIT");
@@ -83,34 +116,89 @@ extern "C" fn main(mem_upper: usize, video_memory_skip_lines: usize) -> ! {
         pic::init_pit();
         println!("PIT set up!");
 
+        #[cfg(feature = "gdb_stub")]
+        {
+            println!("Waiting for GDB on COM2");
+            interrupts::gdb_stub::init();
+        }
+
+        #[cfg(feature = "apic")]
+        {
+            println!("Setting up APIC");
+            match interrupts::apic::init(&mut page_manager) {
+                Ok(()) => println!("APIC set up!"),
+                Err(e) => println!("Couldn't set up APIC, using PIC instead: {e}"),
+            }
+        }
+
         println!("Initializing Thread System...");
         let threads = create_thread_state();
-        let mut process = create_process_state();
+        let process = create_process_state();
         println!("Finished Thread System initialization. Ready to start threading.");
 
         println!("Mounting root filesystem...");
         let mut root = RootFileSystem::new();
         // for now, we just use TempFS for the root filesystem
-        root.mount_root(TempFS::new())
-            .expect("Couldn't mount root FS");
-
-        let ide_tcb =
-            ThreadControlBlock::new_with_setup(ide_init, true, 0, &mut root, &mut process);
-
-        let block_manager = BlockManager::default();
-        let input_buffer = Mutex::new(InputBuffer::new());
+        #[allow(unused_mut)]
+        let mut root_fs = TempFS::new();
+        #[cfg(feature = "initrd")]
+        let init_elf = {
+            println!("Unpacking initrd...");
+            fs::tar::unpack(&mut root_fs, INITRD).expect("Couldn't unpack initrd");
+            fs::tar::read_file(&mut root_fs, "/init").expect("Couldn't read /init from initrd")
+        };
+        #[cfg(feature = "initrd")]
+        let init: &[u8] = &init_elf;
+        #[cfg(not(feature = "initrd"))]
+        let init = INIT;
+        root.mount_root(root_fs).expect("Couldn't mount root FS");
+        let tty = Arc::new(Tty::new(Box::new(VideoConsole)));
+        root.mount_dev(tty.clone()).expect("Couldn't mount /dev");
+
+        let ide_tcb = ThreadControlBlock::new_with_setup(ide_init, true, 0, &mut root, &process);
+        let mut idle_tcb =
+            ThreadControlBlock::new_with_setup(idle_function, true, 0, &mut root, &process);
+        idle_tcb.mlfq = MLFQPriority::idle();
+        let work_queue_tcb =
+            ThreadControlBlock::new_with_setup(work_queue_worker, true, 0, &mut root, &process);
+
+        #[allow(unused_mut)]
+        let mut block_manager = BlockManager::default();
+        #[cfg(feature = "ramdisk")]
+        {
+            let ramdisk = drivers::ramdisk::RamDisk::from_image(RAMDISK_IMAGE);
+            block_manager.register_block(
+                block::block_core::BlockType::FileSystem,
+                "ram0",
+                ramdisk.sectors(),
+                Box::new(ramdisk),
+            );
+        }
+        let input_buffer = InputBuffer::new();
 
         threads.scheduler.lock().push(Box::new(ide_tcb));
+        threads.scheduler.lock().push(Box::new(idle_tcb));
+        threads.scheduler.lock().push(Box::new(work_queue_tcb));
+        if params.selftest {
+            println!("Running self-tests");
+            let selftest_tcb =
+                ThreadControlBlock::new_with_setup(selftest_thread, true, 0, &mut root, &process);
+            threads.scheduler.lock().push(Box::new(selftest_tcb));
+        }
 
         crate::system::init_system(SystemState {
             threads,
             process,
-            block_manager: RwLock::new(block_manager),
+            block_manager: RwLock::new_writer_preferring(block_manager),
             root_filesystem: Mutex::new(root),
             input_buffer,
+            tty,
         });
         println!("initialized system");
 
-        thread_system_start(page_manager, INIT);
+        // keyboard input goes to the console
+        register_softirq(receive_keyboard_input);
+
+        thread_system_start(page_manager, init);
     }
 }
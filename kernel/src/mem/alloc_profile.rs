@@ -0,0 +1,101 @@
+//! Allocation size profiling, for choosing slab sizes (enabled by the `alloc_profile` feature).
+//!
+//! Every request to the kernel heap is counted in a histogram of sizes, bucketed by power of two:
+//! bucket `i` counts requests for more than `2^(i-1)` and at most `2^i` bytes. Frees are counted
+//! the same way, so the difference between the two is what's live.
+//!
+//! The counters are atomics in a fixed-size table, so recording doesn't allocate or need the
+//! allocator to be borrowed mutably.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// One bucket for each power of two a `usize` can hold, and one for sizes above the largest.
+const BUCKETS: usize = usize::BITS as usize + 1;
+
+/// The bucket requests for `size` bytes are counted in.
+fn bucket(size: usize) -> usize {
+    size.checked_next_power_of_two()
+        .map_or(usize::BITS, usize::trailing_zeros) as usize
+}
+
+pub struct AllocProfile {
+    allocs: [AtomicUsize; BUCKETS],
+    frees: [AtomicUsize; BUCKETS],
+}
+
+impl AllocProfile {
+    pub const fn new() -> Self {
+        Self {
+            allocs: [const { AtomicUsize::new(0) }; BUCKETS],
+            frees: [const { AtomicUsize::new(0) }; BUCKETS],
+        }
+    }
+
+    /// Count a request for `size` bytes.
+    pub fn record_alloc(&self, size: usize) {
+        self.allocs[bucket(size)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count `size` bytes being freed.
+    pub fn record_free(&self, size: usize) {
+        self.frees[bucket(size)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Write a line for each bucket any requests have been counted in.
+    pub fn report(&self, out: &mut impl Write) -> fmt::Result {
+        for (i, (allocs, frees)) in self.allocs.iter().zip(&self.frees).enumerate() {
+            let (allocs, frees) = (
+                allocs.load(Ordering::Relaxed),
+                frees.load(Ordering::Relaxed),
+            );
+            if allocs == 0 && frees == 0 {
+                continue;
+            }
+            write!(out, "[KERNEL ALLOCATOR]: ")?;
+            match 1_usize.checked_shl(i as u32) {
+                Some(max) => write!(out, "<= {max} bytes")?,
+                None => write!(out, "> {} bytes", 1_usize << (usize::BITS - 1))?,
+            }
+            writeln!(out, ": {allocs} allocated, {frees} freed")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+    use alloc::string::String;
+
+    #[test]
+    fn test_buckets() {
+        assert_eq!(bucket(0), 0);
+        assert_eq!(bucket(1), 0);
+        assert_eq!(bucket(2), 1);
+        assert_eq!(bucket(3), 2);
+        assert_eq!(bucket(4), 2);
+        assert_eq!(bucket(4097), 13);
+        assert_eq!(bucket(usize::MAX), BUCKETS - 1);
+
+        let profile = AllocProfile::new();
+        for size in [3, 4, 4, 100] {
+            profile.record_alloc(size);
+        }
+        profile.record_free(4);
+        profile.record_alloc(usize::MAX);
+
+        let mut report = String::new();
+        profile.report(&mut report).unwrap();
+        assert_eq!(
+            report,
+            format!(
+                "[KERNEL ALLOCATOR]: <= 4 bytes: 3 allocated, 1 freed\n\
+                 [KERNEL ALLOCATOR]: <= 128 bytes: 1 allocated, 0 freed\n\
+                 [KERNEL ALLOCATOR]: > {} bytes: 1 allocated, 0 freed\n",
+                1_usize << (usize::BITS - 1)
+            )
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Allocation tagging, for tracking down leaks (enabled by the `alloc_tags` feature).
+//!
+//! Every live allocation from the kernel heap is recorded along with a tag saying where it was
+//! made: the return address of [`GlobalAlloc::alloc`](core::alloc::GlobalAlloc::alloc), which is
+//! somewhere in whatever allocated the memory (usually `alloc::alloc::alloc`, but the caller of
+//! that is on the next frame up). When [`KernelAllocator::deinit`](super::KernelAllocator::deinit)
+//! finds a leak, it prints the allocations which are still live, and their tags can be looked up
+//! with `addr2line -e <kernel> <tag>`.
+//!
+//! The tags live in a fixed-size table rather than the core map, since the core map only has a
+//! byte per frame and most allocations are smaller than a frame. The allocator can't allocate
+//! memory for its own bookkeeping, so the table doesn't grow; allocations made once it's full are
+//! counted but not tagged.
+//!
+//! Reading the return address relies on frame pointers: without them, ebp can hold anything, and
+//! following it can give a meaningless tag, or fault. Add `"-C", "force-frame-pointers=yes"` to the
+//! `rustflags` in `.cargo/config.toml` when enabling the feature. Setting `RUSTFLAGS` instead would
+//! replace the flags there, which the kernel needs to link.
+
+// Without the feature, this is only built for its tests, which don't tag real allocations.
+#![cfg_attr(not(feature = "alloc_tags"), allow(dead_code))]
+
+use core::fmt::{self, Write};
+
+/// How many live allocations can be tagged at once.
+const MAX_TAGGED: usize = 4096;
+
+#[derive(Clone, Copy)]
+struct Tagged {
+    ptr: usize,
+    size: usize,
+    tag: usize,
+}
+
+pub struct AllocTags {
+    tagged: [Option<Tagged>; MAX_TAGGED],
+    /// Allocations which weren't tagged because the table was full
+    untagged: usize,
+}
+
+impl AllocTags {
+    pub const fn new() -> Self {
+        Self {
+            tagged: [None; MAX_TAGGED],
+            untagged: 0,
+        }
+    }
+
+    /// Record that `size` bytes at `ptr` were allocated from `tag`.
+    pub fn record(&mut self, ptr: *const u8, size: usize, tag: usize) {
+        match self.tagged.iter_mut().find(|entry| entry.is_none()) {
+            Some(entry) => {
+                *entry = Some(Tagged {
+                    ptr: ptr as usize,
+                    size,
+                    tag,
+                })
+            }
+            None => self.untagged += 1,
+        }
+    }
+
+    /// Forget the allocation at `ptr`, which has been freed.
+    pub fn remove(&mut self, ptr: *const u8) {
+        let entry = self
+            .tagged
+            .iter_mut()
+            .find(|entry| entry.is_some_and(|tagged| tagged.ptr == ptr as usize));
+        match entry {
+            Some(entry) => *entry = None,
+            None => self.untagged = self.untagged.saturating_sub(1),
+        }
+    }
+
+    /// Write a line for each allocation which is still live.
+    pub fn report(&self, out: &mut impl Write) -> fmt::Result {
+        for tagged in self.tagged.iter().flatten() {
+            writeln!(
+                out,
+                "[KERNEL ALLOCATOR]: {} bytes at {:#x} leaked, allocated from {:#x}",
+                tagged.size, tagged.ptr, tagged.tag
+            )?;
+        }
+        if self.untagged > 0 {
+            writeln!(
+                out,
+                "[KERNEL ALLOCATOR]: {} more allocations leaked, but weren't tagged",
+                self.untagged
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The return address of the function this is inlined into, or 0 if it can't be found.
+#[inline(always)]
+pub fn caller_address() -> usize {
+    #[cfg(target_arch = "x86")]
+    {
+        let ebp: usize;
+        // SAFETY: Only reads the frame pointer register.
+        unsafe { core::arch::asm!("mov {}, ebp", out(reg) ebp) };
+        if ebp != 0 {
+            // SAFETY: With frame pointers, ebp points at the saved ebp of the caller, and the
+            // return address is just above it.
+            return unsafe { *(ebp as *const usize).add(1) };
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::boxed::Box;
+    use alloc::string::String;
+
+    #[test]
+    fn test_report_leaks() {
+        let mut tags = Box::new(AllocTags::new());
+        let a = 0x1000 as *const u8;
+        let b = 0x2000 as *const u8;
+        tags.record(a, 16, 0xc0101234);
+        tags.record(b, 64, 0xc0105678);
+        tags.remove(a);
+
+        let mut report = String::new();
+        tags.report(&mut report).unwrap();
+        assert_eq!(
+            report,
+            "[KERNEL ALLOCATOR]: 64 bytes at 0x2000 leaked, allocated from 0xc0105678\n"
+        );
+
+        tags.remove(b);
+        let mut report = String::new();
+        tags.report(&mut report).unwrap();
+        assert_eq!(report, "");
+    }
+
+    #[test]
+    fn test_table_full() {
+        let mut tags = Box::new(AllocTags::new());
+        for i in 0..MAX_TAGGED + 2 {
+            tags.record((i * 16) as *const u8, 16, 0xc0100000);
+        }
+        tags.remove(core::ptr::null());
+        tags.remove((MAX_TAGGED * 16 + 16) as *const u8);
+
+        let mut report = String::new();
+        tags.report(&mut report).unwrap();
+        assert_eq!(report.lines().count(), MAX_TAGGED);
+        assert!(report.ends_with("1 more allocations leaked, but weren't tagged\n"));
+    }
+}
@@ -0,0 +1,317 @@
+//! A stand-in for [`KernelAllocator`](super::KernelAllocator) in tests.
+//!
+//! The kernel allocator can't be driven from tests, since it's a global which has to be
+//! initialized over the machine's upper memory, and its first allocation has to be the core map.
+//! This puts the same subblock and frame allocators over a buffer given to it instead, so they can
+//! be tested together, and allocations land at the same offsets into the buffer every run.
+
+use super::frame_allocator::{placement_algorithms::NextFit, CoreMapEntry, FrameAllocatorSolution};
+use super::subblock_allocator::SubblockAllocatorSolution;
+use super::FrameAllocator;
+use alloc::vec;
+use core::alloc::{AllocError, Allocator, Layout};
+use core::cell::RefCell;
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+use kidneyos_shared::mem::PAGE_FRAME_SIZE;
+
+pub struct BufferAllocator<'a> {
+    state: RefCell<BufferAllocatorState>,
+    start: NonNull<u8>,
+    frames: usize,
+    _buffer: PhantomData<&'a mut [u8]>,
+}
+
+struct BufferAllocatorState {
+    subblock_allocator: SubblockAllocatorSolution<FrameAllocatorSolution<NextFit>>,
+    /// Allocations which haven't been freed yet, which the kernel allocator counts with
+    /// `TOTAL_NUM_ALLOCATIONS` and `TOTAL_NUM_DEALLOCATIONS`
+    live_allocations: usize,
+}
+
+impl<'a> BufferAllocator<'a> {
+    /// Allocate from the whole frames in `buffer`, which needn't be aligned.
+    pub fn new(buffer: &'a mut [u8]) -> Self {
+        let skip = buffer
+            .as_ptr()
+            .align_offset(PAGE_FRAME_SIZE)
+            .min(buffer.len());
+        let frames = (buffer.len() - skip) / PAGE_FRAME_SIZE;
+        let start = NonNull::new(buffer[skip..].as_mut_ptr()).expect("buffer was null");
+        let core_map = vec![CoreMapEntry::default(); frames].into_boxed_slice();
+        let frame_allocator = FrameAllocatorSolution::<NextFit>::new(
+            NonNull::slice_from_raw_parts(start, frames * PAGE_FRAME_SIZE),
+            core_map,
+        );
+        Self {
+            state: RefCell::new(BufferAllocatorState {
+                subblock_allocator: SubblockAllocatorSolution::with_red_zones(
+                    frame_allocator,
+                    false,
+                ),
+                live_allocations: 0,
+            }),
+            start,
+            frames,
+            _buffer: PhantomData,
+        }
+    }
+
+    /// Where the first frame starts.
+    pub fn start(&self) -> NonNull<u8> {
+        self.start
+    }
+
+    /// How many frames there are to allocate from.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// How many allocations haven't been freed yet, not counting whole frames from
+    /// [`Self::frame_alloc`].
+    pub fn live_allocations(&self) -> usize {
+        self.state.borrow().live_allocations
+    }
+
+    /// Like [`KernelAllocator::frame_alloc`](super::KernelAllocator::frame_alloc).
+    pub fn frame_alloc(&self, frames: usize) -> Result<NonNull<u8>, AllocError> {
+        self.state
+            .borrow_mut()
+            .subblock_allocator
+            .get_frame_allocator()
+            .alloc(frames)
+    }
+
+    /// Like [`KernelAllocator::frame_dealloc`](super::KernelAllocator::frame_dealloc).
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have come from [`Self::frame_alloc`], and not have been freed yet.
+    pub unsafe fn frame_dealloc(&self, ptr: NonNull<u8>) {
+        self.state
+            .borrow_mut()
+            .subblock_allocator
+            .get_frame_allocator()
+            .dealloc(ptr);
+    }
+}
+
+// SAFETY: Allocations are never handed out twice, since the subblock allocator only hands out
+// blocks or frames which are free, and they stay valid for as long as the buffer is borrowed.
+unsafe impl Allocator for BufferAllocator<'_> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let mut state = self.state.borrow_mut();
+        let ptr = state.subblock_allocator.allocate(layout)?;
+        state.live_allocations += 1;
+        let ptr = NonNull::new(ptr).ok_or(AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let mut state = self.state.borrow_mut();
+        state.subblock_allocator.deallocate(ptr.as_ptr(), layout);
+        state.live_allocations -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use kidneyos_shared::sizes::KB;
+    use std::error::Error;
+
+    const FRAMES: usize = 16;
+
+    /// A buffer with room for `FRAMES` frames, wherever the allocation happens to be aligned.
+    fn buffer() -> Vec<u8> {
+        vec![0; (FRAMES + 1) * PAGE_FRAME_SIZE]
+    }
+
+    fn offset(allocator: &BufferAllocator, ptr: NonNull<impl ?Sized>) -> usize {
+        ptr.cast::<u8>().as_ptr() as usize - allocator.start().as_ptr() as usize
+    }
+
+    #[test]
+    fn deterministic_addresses() -> Result<(), Box<dyn Error>> {
+        let layouts = [
+            Layout::from_size_align(5, 1)?,
+            Layout::from_size_align(16, 8)?,
+            Layout::from_size_align(100, 4)?,
+            Layout::from_size_align(5000, 8)?,
+            Layout::from_size_align(8, PAGE_FRAME_SIZE)?,
+            Layout::from_size_align(2048, 2048)?,
+        ];
+        // Each frame split into subblocks is pushed onto the free list in order, so blocks are
+        // handed out from the end of the frame backwards.
+        let expected = [
+            PAGE_FRAME_SIZE - 16,
+            PAGE_FRAME_SIZE - 32,
+            2 * PAGE_FRAME_SIZE - 128,
+            2 * PAGE_FRAME_SIZE,
+            4 * PAGE_FRAME_SIZE,
+            6 * PAGE_FRAME_SIZE - 2048,
+        ];
+
+        // the same offsets every time, even though the buffers are in different places
+        for _ in 0..2 {
+            let mut buffer = buffer();
+            let allocator = BufferAllocator::new(&mut buffer);
+            assert_eq!(allocator.frames(), FRAMES);
+            let offsets: Vec<usize> = layouts
+                .iter()
+                .map(|&layout| Ok(offset(&allocator, allocator.allocate(layout)?)))
+                .collect::<Result<_, AllocError>>()?;
+            assert_eq!(offsets, expected);
+            assert_eq!(allocator.live_allocations(), layouts.len());
+
+            // freed blocks are reused first
+            let ptr = unsafe { allocator.start().add(expected[0]) };
+            unsafe { allocator.deallocate(ptr, layouts[0]) };
+            assert_eq!(
+                offset(&allocator, allocator.allocate(layouts[1])?),
+                expected[0]
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn no_overlaps() -> Result<(), Box<dyn Error>> {
+        let mut buffer = buffer();
+        let allocator = BufferAllocator::new(&mut buffer);
+        let mut allocations: Vec<(NonNull<u8>, Layout, u8)> = Vec::new();
+
+        let check = |allocations: &[(NonNull<u8>, Layout, u8)]| {
+            let mut ranges: Vec<_> = allocations
+                .iter()
+                .map(|&(ptr, layout, _)| {
+                    let start = offset(&allocator, ptr);
+                    start..start + layout.size()
+                })
+                .collect();
+            ranges.sort_by_key(|range| range.start);
+            for pair in ranges.windows(2) {
+                assert!(pair[0].end <= pair[1].start, "{pair:?} overlap");
+            }
+            for &(ptr, layout, fill) in allocations {
+                assert_eq!(ptr.as_ptr() as usize % layout.align(), 0);
+                assert!(offset(&allocator, ptr) + layout.size() <= FRAMES * PAGE_FRAME_SIZE);
+                let data = unsafe { core::slice::from_raw_parts(ptr.as_ptr(), layout.size()) };
+                assert!(
+                    data.iter().all(|&b| b == fill),
+                    "allocation was overwritten"
+                );
+            }
+        };
+
+        let allocate = |allocations: &mut Vec<_>, i: usize| -> Result<(), Box<dyn Error>> {
+            let size = [1, 7, 16, 33, 64, 200, 513, 1024, 2000, 3000][i % 10];
+            let align = 1 << (i % 5);
+            let layout = Layout::from_size_align(size, align)?;
+            let ptr = allocator.allocate(layout)?.cast::<u8>();
+            let fill = i as u8;
+            unsafe { ptr.as_ptr().write_bytes(fill, size) };
+            allocations.push((ptr, layout, fill));
+            Ok(())
+        };
+
+        for i in 0..30 {
+            allocate(&mut allocations, i)?;
+        }
+        check(&allocations);
+
+        // free every other allocation, and fill the gaps back in
+        let mut i = 0;
+        allocations.retain(|&(ptr, layout, _)| {
+            i += 1;
+            if i % 2 == 0 {
+                unsafe { allocator.deallocate(ptr, layout) };
+            }
+            i % 2 != 0
+        });
+        for i in 30..45 {
+            allocate(&mut allocations, i)?;
+        }
+        check(&allocations);
+
+        // whole frames don't overlap the blocks either
+        let frame = allocator.frame_alloc(1)?;
+        allocations.push((
+            frame,
+            Layout::from_size_align(PAGE_FRAME_SIZE, PAGE_FRAME_SIZE)?,
+            0,
+        ));
+        unsafe { frame.as_ptr().write_bytes(0, PAGE_FRAME_SIZE) };
+        check(&allocations);
+
+        let (frame, ..) = allocations.pop().unwrap();
+        unsafe { allocator.frame_dealloc(frame) };
+        for (ptr, layout, _) in allocations {
+            unsafe { allocator.deallocate(ptr, layout) };
+        }
+        assert_eq!(allocator.live_allocations(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn over_aligned() -> Result<(), Box<dyn Error>> {
+        let mut buffer = buffer();
+        let allocator = BufferAllocator::new(&mut buffer);
+        // move the next frame to be allocated somewhere which probably isn't aligned
+        let first = allocator.frame_alloc(1)?;
+
+        for (size, align) in [(8, 16 * KB), (5000, 16 * KB), (100, 8 * KB)] {
+            let layout = Layout::from_size_align(size, align)?;
+            let ptr = allocator.allocate(layout)?.cast::<u8>();
+            assert_eq!(ptr.as_ptr() as usize % align, 0);
+            assert!(offset(&allocator, ptr) + size <= FRAMES * PAGE_FRAME_SIZE);
+            unsafe { ptr.as_ptr().write_bytes(0xAB, size) };
+            unsafe { allocator.deallocate(ptr, layout) };
+        }
+        assert_eq!(allocator.live_allocations(), 0);
+
+        // every frame was freed, including the ones before the aligned pointers
+        unsafe { allocator.frame_dealloc(first) };
+        let all = allocator.frame_alloc(FRAMES)?;
+        assert_eq!(all, allocator.start());
+
+        // more than there's room for, once aligned
+        let layout = Layout::from_size_align(FRAMES * PAGE_FRAME_SIZE, 2 * PAGE_FRAME_SIZE)?;
+        unsafe { allocator.frame_dealloc(all) };
+        assert_eq!(allocator.allocate(layout), Err(AllocError));
+
+        Ok(())
+    }
+
+    #[test]
+    fn collections() {
+        let mut buffer = buffer();
+        let allocator = BufferAllocator::new(&mut buffer);
+        let mut v = Vec::new_in(&allocator);
+        v.extend(0..1000u32);
+        assert_eq!(v.iter().sum::<u32>(), 499500);
+        assert_eq!(allocator.live_allocations(), 1);
+        drop(v);
+        assert_eq!(allocator.live_allocations(), 0);
+    }
+
+    #[test]
+    fn out_of_frames() -> Result<(), Box<dyn Error>> {
+        let mut buffer = vec![0; 3 * PAGE_FRAME_SIZE];
+        let allocator = BufferAllocator::new(&mut buffer);
+        // the buffer is rounded in to whole frames
+        assert!(allocator.frames() >= 2);
+        let frames = allocator.frames();
+        assert_eq!(
+            allocator.allocate(Layout::from_size_align((frames + 1) * PAGE_FRAME_SIZE, 1)?),
+            Err(AllocError)
+        );
+        assert_eq!(allocator.live_allocations(), 0);
+        allocator.frame_alloc(frames)?;
+        assert_eq!(allocator.frame_alloc(1), Err(AllocError));
+        Ok(())
+    }
+}
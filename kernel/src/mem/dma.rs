@@ -0,0 +1,125 @@
+use crate::KERNEL_ALLOCATOR;
+use core::alloc::AllocError;
+use core::ptr::NonNull;
+use kidneyos_shared::mem::{OFFSET, PAGE_FRAME_SIZE};
+
+/// A physically contiguous buffer, for a device to read or write with DMA.
+///
+/// The frames come straight from the frame allocator, which hands out runs of frames from the
+/// kernel's linear mapping of physical memory, so the physical address is just the virtual address
+/// minus `OFFSET`.
+#[derive(Debug)]
+pub struct DmaBuffer {
+    virt: NonNull<u8>,
+    frames: usize,
+}
+
+// SAFETY: The buffer owns its frames, which aren't tied to any thread.
+unsafe impl Send for DmaBuffer {}
+
+impl DmaBuffer {
+    /// Wrap the run of `frames` frames starting at `virt`, which must have come from the frame
+    /// allocator.
+    fn from_frames(virt: NonNull<u8>, frames: usize) -> Self {
+        Self { virt, frames }
+    }
+
+    /// Where the kernel can access the buffer.
+    pub fn virt(&self) -> NonNull<u8> {
+        self.virt
+    }
+
+    /// Where the device should access the buffer.
+    pub fn phys(&self) -> usize {
+        self.phys_at(0)
+    }
+
+    /// The physical address of the byte `offset` bytes into the buffer, e.g. for splitting the
+    /// buffer into several descriptors.
+    pub fn phys_at(&self, offset: usize) -> usize {
+        assert!(
+            offset <= self.size(),
+            "offset {offset:#X} was past the buffer"
+        );
+        // wrapping, since the buffer isn't in the kernel's mapping in tests
+        (self.virt.as_ptr() as usize + offset).wrapping_sub(OFFSET)
+    }
+
+    /// The size of the buffer in bytes, which is a whole number of frames.
+    pub fn size(&self) -> usize {
+        self.frames * PAGE_FRAME_SIZE
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        // SAFETY: the frames belong to this buffer until it's freed.
+        unsafe { core::slice::from_raw_parts(self.virt.as_ptr(), self.size()) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: the frames belong to this buffer until it's freed.
+        unsafe { core::slice::from_raw_parts_mut(self.virt.as_ptr(), self.size()) }
+    }
+}
+
+/// Allocate a zeroed, physically contiguous buffer of at least `size` bytes, for DMA.
+///
+/// Returns an error if there isn't a long enough run of free frames.
+pub fn dma_alloc(size: usize) -> Result<DmaBuffer, AllocError> {
+    let frames = frames_for(size);
+    // SAFETY: Single core, and the frame allocator isn't used by interrupt handlers.
+    let virt = unsafe { KERNEL_ALLOCATOR.frame_alloc_zeroed(frames) }?;
+    Ok(DmaBuffer::from_frames(virt, frames))
+}
+
+/// The number of frames a buffer of `size` bytes takes up. Even an empty one gets a frame, so that
+/// it has an address to give the device.
+fn frames_for(size: usize) -> usize {
+    size.div_ceil(PAGE_FRAME_SIZE).max(1)
+}
+
+/// Free a buffer allocated by [`dma_alloc`].
+///
+/// # Safety
+///
+/// No device may still be using the buffer.
+pub unsafe fn dma_free(buffer: DmaBuffer) {
+    KERNEL_ALLOCATOR.frame_dealloc(buffer.virt);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whole_frames() {
+        assert_eq!(frames_for(0), 1);
+        assert_eq!(frames_for(1), 1);
+        assert_eq!(frames_for(PAGE_FRAME_SIZE), 1);
+        assert_eq!(frames_for(PAGE_FRAME_SIZE + 1), 2);
+        assert_eq!(frames_for(10000), 3);
+    }
+
+    #[test]
+    fn phys_within_buffer() {
+        let virt = NonNull::new((OFFSET + 0x10_0000) as *mut u8).unwrap();
+        let buffer = DmaBuffer::from_frames(virt, 3);
+        assert_eq!(buffer.size(), 3 * PAGE_FRAME_SIZE);
+        assert_eq!(buffer.phys(), 0x10_0000);
+        assert_eq!(
+            buffer.phys_at(PAGE_FRAME_SIZE + 4),
+            0x10_0000 + PAGE_FRAME_SIZE + 4
+        );
+        // one past the end is fine, e.g. for the end of the last descriptor
+        assert_eq!(
+            buffer.phys_at(buffer.size()),
+            0x10_0000 + 3 * PAGE_FRAME_SIZE
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "was past the buffer")]
+    fn phys_past_buffer() {
+        let virt = NonNull::new((OFFSET + 0x10_0000) as *mut u8).unwrap();
+        DmaBuffer::from_frames(virt, 1).phys_at(PAGE_FRAME_SIZE + 1);
+    }
+}
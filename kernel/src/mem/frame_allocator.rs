@@ -10,8 +10,11 @@ use kidneyos_shared::{bit_array::BitArray, bitfield, mem::PAGE_FRAME_SIZE};
 use paste::paste;
 
 bitfield!(
-    CoreMapEntry, u8
-    {}
+    CoreMapEntry, u16
+    {
+        // How many user pages the frame is mapped into, saturating at u8::MAX
+        (u8, map_count, 8, 15),
+    }
     {
         (allocated, 0),
         (pinned, 1),
@@ -56,33 +59,73 @@ where
     }
 
     unsafe fn dealloc(&mut self, ptr_to_dealloc: NonNull<u8>) -> usize {
-        let mut start = (ptr_to_dealloc.as_ptr() as usize
-            - self.start.cast::<u8>().as_ptr() as usize)
-            / PAGE_FRAME_SIZE;
+        let mut start = self.frame_index(ptr_to_dealloc);
+        // The pointer may be into the middle of the frames, if they were allocated with
+        // alloc_aligned, in which case the frames before it are part of the same allocation.
+        while start > 0 && self.core_map[start - 1].next() {
+            start -= 1;
+        }
         let mut frames_freed = 1;
 
         while self.core_map[start].next() {
             assert!(self.core_map[start].allocated());
-            self.core_map[start] = self.core_map[start].with_next(false).with_allocated(false);
+            self.core_map[start] = self.core_map[start]
+                .with_next(false)
+                .with_allocated(false)
+                .with_map_count(0);
 
             frames_freed += 1;
             start += 1;
         }
 
-        self.core_map[start] = self.core_map[start].with_allocated(false);
+        self.core_map[start] = self.core_map[start].with_allocated(false).with_map_count(0);
 
         self.frames_allocated -= frames_freed;
         frames_freed
     }
+
+    fn frame_mapped(&mut self, ptr: NonNull<u8>) {
+        let entry = &mut self.core_map[self.frame_index(ptr)];
+        *entry = entry.with_map_count(entry.map_count().saturating_add(1));
+    }
+
+    fn frame_unmapped(&mut self, ptr: NonNull<u8>) {
+        let entry = &mut self.core_map[self.frame_index(ptr)];
+        *entry = entry.with_map_count(entry.map_count().saturating_sub(1));
+    }
+
+    fn map_count(&self, ptr: NonNull<u8>) -> u8 {
+        self.core_map[self.frame_index(ptr)].map_count()
+    }
+
+    #[cfg(any(test, feature = "alloc_checks"))]
+    fn is_allocated(&self, ptr: NonNull<u8>) -> bool {
+        let offset =
+            (ptr.as_ptr() as usize).wrapping_sub(self.start.cast::<u8>().as_ptr() as usize);
+        self.core_map
+            .get(offset / PAGE_FRAME_SIZE)
+            .is_some_and(|entry| entry.allocated())
+    }
 }
 
 impl<A: PlacementAlgorithm> FrameAllocatorSolution<A> {
     pub fn new(start: NonNull<[u8]>, core_map: Box<[CoreMapEntry]>) -> Self {
+        Self::with_placement_algorithm(start, core_map, Default::default())
+    }
+
+    /// Create a frame allocator which chooses where to put allocations using
+    /// `placement_algorithm`, e.g. [`BestFit`](placement_algorithms::BestFit) to keep large runs
+    /// of frames free for large contiguous allocations.
+    pub fn with_placement_algorithm(
+        start: NonNull<[u8]>,
+        core_map: Box<[CoreMapEntry]>,
+        placement_algorithm: A,
+    ) -> Self {
         FrameAllocatorSolution {
             start,
             core_map,
             frames_allocated: 0,
-            placement_algorithm: Default::default(),
+            placement_algorithm,
         }
     }
 }
@@ -95,6 +138,11 @@ where
     pub fn num_allocated(&self) -> usize {
         self.frames_allocated
     }
+
+    /// Index into the core map of the frame `ptr` points into
+    fn frame_index(&self, ptr: NonNull<u8>) -> usize {
+        (ptr.as_ptr() as usize - self.start.cast::<u8>().as_ptr() as usize) / PAGE_FRAME_SIZE
+    }
 }
 
 #[cfg(test)]
@@ -257,4 +305,142 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_best_fit_keeps_large_runs() -> Result<(), Box<dyn Error>> {
+        const NUM_FRAMES: usize = 8;
+
+        fn fragment<A: PlacementAlgorithm>(frame_allocator: &mut FrameAllocatorSolution<A>) {
+            // Leave free runs of 3 frames (0-2) and 2 frames (4-5)
+            let a = frame_allocator.alloc(3).unwrap();
+            frame_allocator.alloc(1).unwrap();
+            let c = frame_allocator.alloc(2).unwrap();
+            frame_allocator.alloc(2).unwrap();
+            unsafe {
+                frame_allocator.dealloc(a);
+                frame_allocator.dealloc(c);
+            }
+        }
+
+        let layout = Layout::from_size_align(PAGE_FRAME_SIZE * NUM_FRAMES, PAGE_FRAME_SIZE)?;
+        let region = Global.allocate(layout)?;
+
+        // First fit splits up the 3 frame run, so there's no room for 3 frames afterwards
+        let core_map = [CoreMapEntry::default(); NUM_FRAMES];
+        let mut frame_allocator =
+            FrameAllocatorSolution::with_placement_algorithm(region, Box::new(core_map), FirstFit);
+        fragment(&mut frame_allocator);
+        assert_eq!(frame_allocator.alloc(2)?, region.cast::<u8>());
+        assert_eq!(frame_allocator.alloc(3), Err(AllocError));
+
+        // Best fit uses the 2 frame run instead
+        let core_map = [CoreMapEntry::default(); NUM_FRAMES];
+        let mut frame_allocator =
+            FrameAllocatorSolution::with_placement_algorithm(region, Box::new(core_map), BestFit);
+        fragment(&mut frame_allocator);
+        assert_eq!(frame_allocator.alloc(2)?, unsafe {
+            region.cast::<u8>().byte_add(PAGE_FRAME_SIZE * 4)
+        });
+        assert_eq!(frame_allocator.alloc(3)?, region.cast::<u8>());
+        check_coremap(&frame_allocator.core_map, 0..3, true);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_alloc_aligned() -> Result<(), Box<dyn Error>> {
+        const NUM_FRAMES: usize = 8;
+
+        let core_map = [CoreMapEntry::default(); NUM_FRAMES];
+        // aligned to 4 frames, so frame 1 isn't
+        let layout = Layout::from_size_align(PAGE_FRAME_SIZE * NUM_FRAMES, 4 * PAGE_FRAME_SIZE)?;
+        let region = Global.allocate(layout)?;
+
+        let mut frame_allocator =
+            FrameAllocatorSolution::with_placement_algorithm(region, Box::new(core_map), FirstFit);
+        let first = frame_allocator.alloc(1)?;
+
+        // frames 1 to 4 are allocated, and the pointer is to frame 4
+        let aligned = frame_allocator.alloc_aligned(1, 4 * PAGE_FRAME_SIZE)?;
+        assert_eq!(aligned, unsafe {
+            region.cast::<u8>().byte_add(PAGE_FRAME_SIZE * 4)
+        });
+        assert_eq!(frame_allocator.frames_allocated, 5);
+        check_coremap(&frame_allocator.core_map, 1..5, true);
+
+        // the frames before the pointer are freed too
+        assert_eq!(unsafe { frame_allocator.dealloc(aligned) }, 4);
+        assert_eq!(frame_allocator.frames_allocated, 1);
+        check_coremap(&frame_allocator.core_map, 1..5, false);
+        check_coremap(&frame_allocator.core_map, 0..1, true);
+        assert_eq!(unsafe { frame_allocator.dealloc(first) }, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_alloc_zeroed() -> Result<(), Box<dyn Error>> {
+        const NUM_FRAMES: usize = 4;
+
+        let core_map = [CoreMapEntry::default(); NUM_FRAMES];
+        let layout = Layout::from_size_align(PAGE_FRAME_SIZE * NUM_FRAMES, PAGE_FRAME_SIZE)?;
+        let region = Global.allocate(layout)?;
+
+        let mut frame_allocator =
+            FrameAllocatorSolution::with_placement_algorithm(region, Box::new(core_map), FirstFit);
+
+        // Leave a pattern behind in some frames, as the kernel might
+        let frames = frame_allocator.alloc(2)?;
+        unsafe {
+            core::ptr::write_bytes(frames.as_ptr(), 0xAB, 2 * PAGE_FRAME_SIZE);
+            frame_allocator.dealloc(frames);
+        }
+
+        let zeroed = frame_allocator.alloc_zeroed(2)?;
+        assert_eq!(zeroed, frames);
+        let data = unsafe { core::slice::from_raw_parts(zeroed.as_ptr(), 2 * PAGE_FRAME_SIZE) };
+        assert!(data.iter().all(|&b| b == 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_map_count() -> Result<(), Box<dyn Error>> {
+        const NUM_FRAMES: usize = 4;
+
+        let core_map = [CoreMapEntry::default(); NUM_FRAMES];
+        let layout = Layout::from_size_align(PAGE_FRAME_SIZE * NUM_FRAMES, PAGE_FRAME_SIZE)?;
+        let region = Global.allocate(layout)?;
+
+        let mut frame_allocator =
+            FrameAllocatorSolution::with_placement_algorithm(region, Box::new(core_map), FirstFit);
+
+        let frames = frame_allocator.alloc(2)?;
+        let second = unsafe { frames.byte_add(PAGE_FRAME_SIZE + 16) };
+        assert_eq!(frame_allocator.map_count(frames), 0);
+
+        // e.g. a page of a shared memory object, mapped by two processes
+        frame_allocator.frame_mapped(second);
+        frame_allocator.frame_mapped(second);
+        assert_eq!(frame_allocator.map_count(frames), 0);
+        assert_eq!(frame_allocator.map_count(second), 2);
+        frame_allocator.frame_unmapped(second);
+        assert_eq!(frame_allocator.map_count(second), 1);
+
+        // the count doesn't wrap around either way
+        frame_allocator.frame_unmapped(frames);
+        assert_eq!(frame_allocator.map_count(frames), 0);
+        for _ in 0..300 {
+            frame_allocator.frame_mapped(frames);
+        }
+        assert_eq!(frame_allocator.map_count(frames), u8::MAX);
+
+        // and it's forgotten once the frames are freed
+        unsafe { frame_allocator.dealloc(frames) };
+        let frames = frame_allocator.alloc(2)?;
+        assert_eq!(frame_allocator.map_count(frames), 0);
+        assert_eq!(frame_allocator.map_count(second), 0);
+
+        Ok(())
+    }
 }
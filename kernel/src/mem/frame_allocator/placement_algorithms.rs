@@ -19,6 +19,8 @@ pub trait PlacementAlgorithm: Default {
     ) -> Result<Range<usize>, AllocError>;
 }
 
+/// Picks the first run of free frames which is large enough, searching from just after the last
+/// allocation and wrapping around to the start. This spreads allocations across memory.
 #[derive(Default)]
 pub struct NextFit {
     /// The next frame number to start searching for free frames.
@@ -26,8 +28,12 @@ pub struct NextFit {
 }
 
 // There is no internal data for these two algorithms. Declare them as zero-sized types.
+/// Picks the first run of free frames which is large enough, searching from the start of memory.
 #[derive(Default)]
 pub struct FirstFit;
+/// Picks the smallest run of free frames which is large enough (the first one, if there's a tie).
+/// This leaves the large runs alone, so there's more chance of large contiguous allocations
+/// succeeding later.
 #[derive(Default)]
 pub struct BestFit;
 
@@ -236,6 +242,39 @@ mod tests {
         assert_eq!(algorithm.place(&core_map, 4), Err(AllocError));
     }
 
+    #[test]
+    fn test_fragmented() {
+        let mut core_map = [CoreMapEntry::default(); 16];
+        fill_coremap_range(&mut core_map, &(0..2));
+        fill_coremap_range(&mut core_map, &(7..8));
+        fill_coremap_range(&mut core_map, &(10..11));
+        fill_coremap_range(&mut core_map, &(14..16));
+
+        // Frames left are 2-6, 8-9, 11-13 (inclusive)
+
+        // First fit takes the start of the first run, however big it is
+        assert_eq!(FirstFit.place(&core_map, 2), Ok(2..4));
+        assert_eq!(FirstFit.place(&core_map, 3), Ok(2..5));
+
+        // Best fit takes the smallest run that's big enough
+        assert_eq!(BestFit.place(&core_map, 2), Ok(8..10));
+        assert_eq!(BestFit.place(&core_map, 3), Ok(11..14));
+        assert_eq!(BestFit.place(&core_map, 1), Ok(8..9));
+
+        // Next fit takes the first run that's big enough after where it left off
+        assert_eq!(NextFit { position: 9 }.place(&core_map, 2), Ok(11..13));
+        assert_eq!(NextFit { position: 12 }.place(&core_map, 2), Ok(12..14));
+        assert_eq!(NextFit { position: 12 }.place(&core_map, 3), Ok(2..5));
+
+        // Only one run is big enough for 5 frames, and none for 6
+        assert_eq!(FirstFit.place(&core_map, 5), Ok(2..7));
+        assert_eq!(BestFit.place(&core_map, 5), Ok(2..7));
+        assert_eq!(NextFit { position: 8 }.place(&core_map, 5), Ok(2..7));
+        assert_eq!(FirstFit.place(&core_map, 6), Err(AllocError));
+        assert_eq!(BestFit.place(&core_map, 6), Err(AllocError));
+        assert_eq!(NextFit { position: 8 }.place(&core_map, 6), Err(AllocError));
+    }
+
     #[test]
     fn test_best_fit_second() {
         let mut core_map = [CoreMapEntry::default(); 16];
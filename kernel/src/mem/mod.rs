@@ -1,9 +1,36 @@
+// halt is used for cases where we would panic in KernelAllocator, but can't
+// because doing so causes undefined behaviour as per the GlobalAlloc safety
+// contract. Tests aren't using it as the global allocator, so they can panic.
+macro_rules! halt {
+    () => {{
+        super::eprintln!();
+        loop {}
+    }};
+    ($($arg:tt)*) => {{
+        #[cfg(test)]
+        panic!($($arg)*);
+        #[cfg(not(test))]
+        {
+            kidneyos_shared::eprintln!($($arg)*);
+            loop {}
+        }
+    }};
+}
+
+#[cfg(any(test, feature = "alloc_profile"))]
+mod alloc_profile;
+#[cfg(any(test, feature = "alloc_tags"))]
+mod alloc_tags;
 mod buddy_allocator;
+#[cfg(test)]
+mod buffer_allocator;
+pub mod dma;
 mod dummy_allocator;
 mod frame_allocator;
+pub mod page_cache;
+pub mod shm;
 mod subblock_allocator;
 pub mod user;
-pub mod util;
 pub mod vma;
 
 use alloc::{boxed::Box, vec};
@@ -28,9 +55,17 @@ static FIRST_ALLOCATION: AtomicBool = AtomicBool::new(true);
 static TOTAL_NUM_ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
 static TOTAL_NUM_DEALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
 
+/// Largest alignment the dummy allocator supports. Once the kernel allocator is initialized, any
+/// alignment works, since larger ones are handled by [`FrameAllocator::alloc_aligned`].
 const MAX_SUPPORTED_ALIGN: usize = 4096;
 /// "Upper memory" (as opposed to "lower memory") starts at 1MB.
 const UPPER_MEMORY_START: usize = MB + OFFSET;
+/// Byte newly allocated heap memory is filled with, with the `alloc_poison` feature
+#[cfg(any(test, feature = "alloc_poison"))]
+const ALLOC_POISON: u8 = 0xAA;
+/// Byte freed heap memory is filled with, with the `alloc_poison` feature
+#[cfg(any(test, feature = "alloc_poison"))]
+const FREE_POISON: u8 = 0xDE;
 
 trait FrameAllocator {
     /// Allocates "frames_requested" number of contiguous frames
@@ -39,27 +74,64 @@ trait FrameAllocator {
     /// AllocError on failure
     fn alloc(&mut self, frames_requested: usize) -> Result<NonNull<u8>, AllocError>;
 
-    /// Deallocates the frame or frames pointed to by "ptr_to_dealloc" according to layout
+    /// Like [`Self::alloc`], but fills the frames with zeroes first
+    ///
+    /// Frames handed out to user programs must come from here, so that they can't read whatever
+    /// the kernel or another process left in them.
+    fn alloc_zeroed(&mut self, frames_requested: usize) -> Result<NonNull<u8>, AllocError> {
+        let frames = self.alloc(frames_requested)?;
+        // SAFETY: the allocator just gave us these frames, so nothing else is using them.
+        unsafe { ptr::write_bytes(frames.as_ptr(), 0, frames_requested * PAGE_FRAME_SIZE) };
+        Ok(frames)
+    }
+
+    /// Like [`Self::alloc`], but the frames start at a multiple of `align`, which must be a power
+    /// of 2 greater than or equal to the page size
+    ///
+    /// Extra frames are allocated so that some run of them is aligned, and a pointer to that run
+    /// is returned. [`Self::dealloc`] frees every frame allocated, given that pointer.
+    fn alloc_aligned(
+        &mut self,
+        frames_requested: usize,
+        align: usize,
+    ) -> Result<NonNull<u8>, AllocError> {
+        assert!(align.is_power_of_two() && align >= PAGE_FRAME_SIZE);
+        let extra_frames = align / PAGE_FRAME_SIZE - 1;
+        let frames = self.alloc(
+            frames_requested
+                .checked_add(extra_frames)
+                .ok_or(AllocError)?,
+        )?;
+        let offset = frames.as_ptr().align_offset(align);
+        // SAFETY: the frames are aligned to PAGE_FRAME_SIZE, so the offset is at most
+        // extra_frames * PAGE_FRAME_SIZE, which is still within the frames allocated.
+        Ok(unsafe { frames.add(offset) })
+    }
+
+    /// Deallocates the frame or frames pointed to by "ptr_to_dealloc" according to layout,
+    /// which may point into the frames, as returned by [`Self::alloc_aligned`]
     ///
     /// This function should return the number of frames deallocated on success
     ///
     /// This function is unsafe because "ptr_to_dealloc" the caller must ensure that
     /// ptr_to_dealloc must be owned by the allocator
     unsafe fn dealloc(&mut self, ptr_to_dealloc: NonNull<u8>) -> usize;
-}
 
-// halt is used for cases where we would panic in KernelAllocator, but can't
-// because doing so causes undefined behaviour as per the GlobalAlloc safety
-// contract.
-macro_rules! halt {
-    () => {{
-        super::eprintln!();
-        loop {}
-    }};
-    ($($arg:tt)*) => {{
-        kidneyos_shared::eprintln!($($arg)*);
-        loop {}
-    }};
+    /// Records that the frame `ptr` points into has been mapped into one more user page, so that
+    /// memory shared between processes can be accounted for
+    fn frame_mapped(&mut self, ptr: NonNull<u8>);
+
+    /// Records that the frame `ptr` points into has been unmapped from a user page
+    fn frame_unmapped(&mut self, ptr: NonNull<u8>);
+
+    /// How many user pages the frame `ptr` points into is mapped into, saturating at `u8::MAX`
+    ///
+    /// This is reset when the frame is deallocated.
+    fn map_count(&self, ptr: NonNull<u8>) -> u8;
+
+    /// Whether `ptr` points into a frame which is currently allocated
+    #[cfg(any(test, feature = "alloc_checks"))]
+    fn is_allocated(&self, ptr: NonNull<u8>) -> bool;
 }
 
 enum KernelAllocatorState {
@@ -74,6 +146,12 @@ enum KernelAllocatorState {
 
 pub struct KernelAllocator {
     state: UnsafeCell<KernelAllocatorState>,
+    /// Where each live allocation was made, to report leaks in [`Self::deinit`]
+    #[cfg(feature = "alloc_tags")]
+    tags: UnsafeCell<alloc_tags::AllocTags>,
+    /// How many allocations of each size have been requested, for [`Self::report_alloc_profile`]
+    #[cfg(any(test, feature = "alloc_profile"))]
+    profile: alloc_profile::AllocProfile,
 }
 
 impl KernelAllocator {
@@ -82,6 +160,10 @@ impl KernelAllocator {
             state: UnsafeCell::new(KernelAllocatorState::SetupState {
                 dummy_allocator: DummyAllocatorSolution::new_in(0, 0),
             }),
+            #[cfg(feature = "alloc_tags")]
+            tags: UnsafeCell::new(alloc_tags::AllocTags::new()),
+            #[cfg(any(test, feature = "alloc_profile"))]
+            profile: alloc_profile::AllocProfile::new(),
         }
     }
 
@@ -138,7 +220,10 @@ impl KernelAllocator {
         );
 
         *self.state.get_mut() = KernelAllocatorState::Initialized {
-            subblock_allocator: SubblockAllocatorSolution::new(frame_allocator),
+            subblock_allocator: SubblockAllocatorSolution::with_red_zones(
+                frame_allocator,
+                cfg!(feature = "alloc_redzones"),
+            ),
         };
     }
 
@@ -150,6 +235,17 @@ impl KernelAllocator {
         subblock_allocator.get_frame_allocator().alloc(frames)
     }
 
+    /// Allocate zeroed frames, for mapping into user programs.
+    pub fn frame_alloc_zeroed(&mut self, frames: usize) -> Result<NonNull<u8>, AllocError> {
+        let KernelAllocatorState::Initialized { subblock_allocator } = self.state.get_mut() else {
+            return Err(AllocError);
+        };
+
+        subblock_allocator
+            .get_frame_allocator()
+            .alloc_zeroed(frames)
+    }
+
     pub fn frame_dealloc(&mut self, ptr: NonNull<u8>) {
         let KernelAllocatorState::Initialized { subblock_allocator } = self.state.get_mut() else {
             halt!("[KERNEL ALLOCATOR]: Dealloc called on DeInitialized or SetupState kernel");
@@ -158,6 +254,42 @@ impl KernelAllocator {
         unsafe { subblock_allocator.get_frame_allocator().dealloc(ptr) };
     }
 
+    /// Record that the frame at `ptr` has been mapped into a user page.
+    pub fn frame_mapped(&mut self, ptr: NonNull<u8>) {
+        let KernelAllocatorState::Initialized { subblock_allocator } = self.state.get_mut() else {
+            halt!("[KERNEL ALLOCATOR]: frame_mapped called on DeInitialized or SetupState kernel");
+        };
+
+        subblock_allocator.get_frame_allocator().frame_mapped(ptr);
+    }
+
+    /// Record that the frame at `ptr` has been unmapped from a user page.
+    pub fn frame_unmapped(&mut self, ptr: NonNull<u8>) {
+        let KernelAllocatorState::Initialized { subblock_allocator } = self.state.get_mut() else {
+            halt!(
+                "[KERNEL ALLOCATOR]: frame_unmapped called on DeInitialized or SetupState kernel"
+            );
+        };
+
+        subblock_allocator.get_frame_allocator().frame_unmapped(ptr);
+    }
+
+    /// How many user pages the frame at `ptr` is mapped into.
+    pub fn frame_map_count(&mut self, ptr: NonNull<u8>) -> u8 {
+        let KernelAllocatorState::Initialized { subblock_allocator } = self.state.get_mut() else {
+            return 0;
+        };
+
+        subblock_allocator.get_frame_allocator().map_count(ptr)
+    }
+
+    /// Write a histogram of the sizes of the allocations requested so far, bucketed by power of
+    /// two, with how many of each have been freed.
+    #[cfg(any(test, feature = "alloc_profile"))]
+    pub fn report_alloc_profile(&self, out: &mut impl core::fmt::Write) -> core::fmt::Result {
+        self.profile.report(out)
+    }
+
     pub fn deinit(&mut self) {
         let KernelAllocatorState::Initialized {
             subblock_allocator, ..
@@ -176,7 +308,12 @@ impl KernelAllocator {
 
         subblock_allocator.deinit();
 
+        #[cfg(feature = "alloc_profile")]
+        let _ = self.report_alloc_profile(&mut ErrorWriter);
+
         if incorrect_num_allocs {
+            #[cfg(feature = "alloc_tags")]
+            let _ = self.tags.get_mut().report(&mut ErrorWriter);
             halt!("[KERNEL ALLOCATOR]: Leaks detected");
         }
 
@@ -184,6 +321,18 @@ impl KernelAllocator {
     }
 }
 
+/// Writes to the screen and serial port as errors, without allocating, for the allocator's reports.
+#[cfg(any(feature = "alloc_tags", feature = "alloc_profile"))]
+struct ErrorWriter;
+
+#[cfg(any(feature = "alloc_tags", feature = "alloc_profile"))]
+impl core::fmt::Write for ErrorWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        kidneyos_shared::eprint!("{}", s);
+        Ok(())
+    }
+}
+
 // SAFETY:
 //
 // - We don't panic.
@@ -191,6 +340,9 @@ impl KernelAllocator {
 // - We never rely on allocations happening.
 unsafe impl GlobalAlloc for KernelAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        #[cfg(any(test, feature = "alloc_profile"))]
+        self.profile.record_alloc(layout.size());
+
         if FIRST_ALLOCATION.load(Ordering::Relaxed) {
             // If we are here, it should be the dummy allocator doing the allocation
             let KernelAllocatorState::SetupState { dummy_allocator } = &mut *self.state.get()
@@ -225,11 +377,6 @@ unsafe impl GlobalAlloc for KernelAllocator {
                 halt!("[KERNEL ALLOCATOR]: Allocation requested before kernel is Initialized");
             };
 
-            // The alignment of the layout should never be larger than the size of a page
-            if layout.align() > MAX_SUPPORTED_ALIGN {
-                return ptr::null_mut();
-            }
-
             // Allocate using subblock allocator
             let ret_ptr = match subblock_allocator.allocate(layout) {
                 Ok(t) => t,
@@ -238,6 +385,12 @@ unsafe impl GlobalAlloc for KernelAllocator {
 
             TOTAL_NUM_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
 
+            #[cfg(any(test, feature = "alloc_poison"))]
+            ptr::write_bytes(ret_ptr, ALLOC_POISON, layout.size());
+
+            #[cfg(feature = "alloc_tags")]
+            (*self.tags.get()).record(ret_ptr, layout.size(), alloc_tags::caller_address());
+
             ret_ptr
         }
     }
@@ -250,8 +403,101 @@ unsafe impl GlobalAlloc for KernelAllocator {
             halt!("[KERNEL ALLOCATOR]: dealloc called before initialization of kernel allocator");
         };
 
+        // The start is overwritten again if the memory goes on a free list.
+        #[cfg(any(test, feature = "alloc_poison"))]
+        ptr::write_bytes(ptr, FREE_POISON, layout.size());
+
         subblock_allocator.deallocate(ptr, layout);
 
         TOTAL_NUM_DEALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+
+        #[cfg(any(test, feature = "alloc_profile"))]
+        self.profile.record_free(layout.size());
+
+        #[cfg(feature = "alloc_tags")]
+        (*self.tags.get()).remove(ptr);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::string::String;
+    use core::slice;
+    use std::alloc::{Allocator, Global};
+
+    /// An initialized kernel allocator, managing frames from the test's own heap.
+    fn kernel_allocator() -> KernelAllocator {
+        const NUM_FRAMES: usize = 4;
+
+        let core_map = [CoreMapEntry::default(); NUM_FRAMES];
+        let layout =
+            Layout::from_size_align(PAGE_FRAME_SIZE * NUM_FRAMES, PAGE_FRAME_SIZE).unwrap();
+        let region = Global.allocate(layout).unwrap();
+        let frame_allocator = FrameAllocatorSolution::<NextFit>::new(region, Box::new(core_map));
+        KernelAllocator {
+            state: UnsafeCell::new(KernelAllocatorState::Initialized {
+                subblock_allocator: SubblockAllocatorSolution::with_red_zones(
+                    frame_allocator,
+                    false,
+                ),
+            }),
+            #[cfg(feature = "alloc_tags")]
+            tags: UnsafeCell::new(alloc_tags::AllocTags::new()),
+            profile: alloc_profile::AllocProfile::new(),
+        }
+    }
+
+    #[test]
+    fn poison() {
+        // Nothing else in the tests uses the kernel allocator, so this only skips the dummy
+        // allocator here.
+        FIRST_ALLOCATION.store(false, Ordering::Relaxed);
+        let allocator = kernel_allocator();
+        for size in [48, 5000] {
+            let layout = Layout::from_size_align(size, 8).unwrap();
+            unsafe {
+                let ptr = allocator.alloc(layout);
+                let memory = slice::from_raw_parts_mut(ptr, size);
+                assert!(memory.iter().all(|&byte| byte == ALLOC_POISON));
+                memory.fill(0x11);
+
+                allocator.dealloc(ptr, layout);
+                // The frames are still there to read, since they came from the test's heap. The
+                // free list's link is at the start of freed subblocks.
+                let freed =
+                    slice::from_raw_parts(ptr.add(size_of::<usize>()), size - size_of::<usize>());
+                assert!(freed.iter().all(|&byte| byte == FREE_POISON));
+            }
+        }
+    }
+
+    #[test]
+    fn alloc_profile() {
+        FIRST_ALLOCATION.store(false, Ordering::Relaxed);
+        let allocator = kernel_allocator();
+        let sizes = [8, 8, 24, 32, 33, 5000];
+        let layouts = sizes.map(|size| Layout::from_size_align(size, 8).unwrap());
+        unsafe {
+            let ptrs = layouts.map(|layout| allocator.alloc(layout));
+            allocator.dealloc(ptrs[0], layouts[0]);
+            allocator.dealloc(ptrs[4], layouts[4]);
+
+            let mut report = String::new();
+            allocator.report_alloc_profile(&mut report).unwrap();
+            assert_eq!(
+                report,
+                "[KERNEL ALLOCATOR]: <= 8 bytes: 2 allocated, 1 freed\n\
+                 [KERNEL ALLOCATOR]: <= 32 bytes: 2 allocated, 0 freed\n\
+                 [KERNEL ALLOCATOR]: <= 64 bytes: 1 allocated, 1 freed\n\
+                 [KERNEL ALLOCATOR]: <= 8192 bytes: 1 allocated, 0 freed\n"
+            );
+
+            for (i, (ptr, layout)) in ptrs.into_iter().zip(layouts).enumerate() {
+                if i != 0 && i != 4 {
+                    allocator.dealloc(ptr, layout);
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,177 @@
+use crate::fs::fs_manager::FileSystemID;
+use crate::sync::mutex::Mutex;
+use crate::system::unwrap_system;
+use crate::vfs::INodeNum;
+use crate::KERNEL_ALLOCATOR;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::fmt::{Debug, Formatter};
+use core::ops::Range;
+use core::ptr::NonNull;
+use kidneyos_shared::mem::{OFFSET, PAGE_FRAME_SIZE};
+
+/// The frames holding the pages of a memory-mapped file, shared by every mapping of it.
+///
+/// Shared mappings use these frames directly, so processes see each other's writes, while private
+/// mappings use them read-only until they write to a page, which then gets copied. Pages are read
+/// from the file the first time they're faulted in, and the frames are freed once the file is
+/// unmapped everywhere.
+///
+/// Pages can also be read ahead of being faulted in. Until they're mapped, they can be evicted
+/// again.
+///
+/// Writes made to the file with `write` aren't seen by pages which have already been read.
+#[derive(Clone)]
+pub struct PageCache(Arc<Mutex<PageCacheInner>>);
+
+/// A reference to a [`PageCache`] which doesn't keep it alive.
+pub struct WeakPageCache(Weak<Mutex<PageCacheInner>>);
+
+struct PageCacheInner {
+    fs: FileSystemID,
+    inode: INodeNum,
+    /// Physical address of each page which has been faulted in, by page number. Mappings can reach
+    /// far past the end of the file, so only the pages which are used are stored.
+    frames: BTreeMap<usize, usize>,
+    /// Pages which have been read ahead, and haven't been mapped yet.
+    read_ahead: BTreeSet<usize>,
+}
+
+impl PageCache {
+    /// New cache for the file `inode`, with no pages read yet.
+    pub fn new(fs: FileSystemID, inode: INodeNum) -> Self {
+        Self(Arc::new(Mutex::new(PageCacheInner {
+            fs,
+            inode,
+            frames: BTreeMap::new(),
+            read_ahead: BTreeSet::new(),
+        })))
+    }
+
+    pub fn downgrade(&self) -> WeakPageCache {
+        WeakPageCache(Arc::downgrade(&self.0))
+    }
+
+    /// Get the physical address of page number `page` of the file, to be mapped, reading it in if
+    /// this is the first time it's been used. Past the end of the file, the page is zeroes.
+    ///
+    /// Returns `None` if the file couldn't be read, or there's no memory left.
+    pub fn frame(&self, page: usize) -> Option<usize> {
+        self.load(page, false)
+    }
+
+    /// Read page number `page` of the file in, ahead of it being mapped, if it hasn't been yet.
+    pub fn read_ahead(&self, page: usize) {
+        let _ = self.load(page, true);
+    }
+
+    /// Get the physical address of page number `page`, reading it in if it hasn't been yet. Unless
+    /// it's being read `ahead`, it's marked as mapped.
+    fn load(&self, page: usize, ahead: bool) -> Option<usize> {
+        let (fs, inode) = {
+            let mut inner = self.0.lock();
+            if let Some(&phys_addr) = inner.frames.get(&page) {
+                if !ahead {
+                    inner.read_ahead.remove(&page);
+                }
+                return Some(phys_addr);
+            }
+            (inner.fs, inner.inode)
+        };
+        // the cache isn't locked while reading, since that locks the file system, which is locked
+        // before the cache by fadvise
+        // the frame is zeroed, so data past the end of the file isn't leaked between processes.
+        let frame = unsafe { KERNEL_ALLOCATOR.frame_alloc_zeroed(1) }.ok()?;
+        // SAFETY: the frame was just allocated, and nothing else refers to it.
+        let data = unsafe { core::slice::from_raw_parts_mut(frame.as_ptr(), PAGE_FRAME_SIZE) };
+        if !read_page(fs, inode, page, data) {
+            // SAFETY: the frame was never mapped.
+            unsafe { KERNEL_ALLOCATOR.frame_dealloc(frame) };
+            return None;
+        }
+        let mut inner = self.0.lock();
+        if !ahead {
+            inner.read_ahead.remove(&page);
+        }
+        if let Some(&phys_addr) = inner.frames.get(&page) {
+            // read in by someone else in the meantime
+            // SAFETY: the frame was never mapped.
+            unsafe { KERNEL_ALLOCATOR.frame_dealloc(frame) };
+            return Some(phys_addr);
+        }
+        let phys_addr = frame.as_ptr() as usize - OFFSET;
+        inner.frames.insert(page, phys_addr);
+        if ahead {
+            inner.read_ahead.insert(page);
+        }
+        Some(phys_addr)
+    }
+
+    /// Free the frames of the pages in `pages` which were read ahead, and haven't been mapped.
+    pub fn evict(&self, pages: Range<usize>) {
+        if pages.is_empty() {
+            return;
+        }
+        let mut inner = self.0.lock();
+        let evicted: Vec<usize> = inner.read_ahead.range(pages).copied().collect();
+        for page in evicted {
+            inner.read_ahead.remove(&page);
+            let phys_addr = inner
+                .frames
+                .remove(&page)
+                .expect("read ahead page wasn't cached");
+            let frame = NonNull::new((phys_addr + OFFSET) as *mut u8).expect("frame was null");
+            // SAFETY: the page was never mapped.
+            unsafe { KERNEL_ALLOCATOR.frame_dealloc(frame) };
+        }
+    }
+
+    /// Get the physical address of page number `page` of the file, if it's been read in.
+    pub fn cached_frame(&self, page: usize) -> Option<usize> {
+        self.0.lock().frames.get(&page).copied()
+    }
+}
+
+/// Read page number `page` of the file `inode` into `data`, returning `false` if that fails.
+fn read_page(fs: FileSystemID, inode: INodeNum, page: usize, data: &mut [u8]) -> bool {
+    let offset = page as u64 * PAGE_FRAME_SIZE as u64;
+    let mut root = unwrap_system().root_filesystem.lock();
+    let mut bytes_read = 0;
+    while bytes_read < PAGE_FRAME_SIZE {
+        match root.read_direct(
+            fs,
+            inode,
+            offset + bytes_read as u64,
+            &mut data[bytes_read..],
+        ) {
+            Ok(0) => break,
+            Ok(n) => bytes_read += n,
+            Err(_) => return false,
+        }
+    }
+    true
+}
+
+impl WeakPageCache {
+    /// Get the cache back, if there are still mappings using it.
+    pub fn upgrade(&self) -> Option<PageCache> {
+        self.0.upgrade().map(PageCache)
+    }
+}
+
+impl Drop for PageCacheInner {
+    fn drop(&mut self) {
+        for phys_addr in self.frames.values() {
+            let frame = NonNull::new((phys_addr + OFFSET) as *mut u8).expect("frame was null");
+            // SAFETY: nothing refers to the cache any more, so nothing has the frame mapped.
+            unsafe { KERNEL_ALLOCATOR.frame_dealloc(frame) };
+        }
+    }
+}
+
+impl Debug for PageCache {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "PageCache")
+    }
+}
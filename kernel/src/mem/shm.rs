@@ -0,0 +1,73 @@
+use crate::sync::mutex::Mutex;
+use crate::KERNEL_ALLOCATOR;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::{Debug, Formatter};
+use core::ptr::NonNull;
+use kidneyos_shared::mem::{OFFSET, PAGE_FRAME_SIZE};
+
+/// A shared memory object, as created by `shm_open`.
+///
+/// Every mapping of it uses the same physical frames, so processes see each other's writes. The
+/// frames are freed once the object is unlinked, closed and unmapped everywhere.
+#[derive(Clone, Default)]
+pub struct SharedMemory(Arc<Mutex<SharedMemoryInner>>);
+
+#[derive(Default)]
+struct SharedMemoryInner {
+    size: u64,
+    /// Physical address of each page, allocated the first time it's faulted in
+    frames: Vec<Option<usize>>,
+}
+
+impl SharedMemory {
+    pub fn size(&self) -> u64 {
+        self.0.lock().size
+    }
+
+    /// Change the size of the object, as with `ftruncate`.
+    ///
+    /// Pages past the new end can't be faulted in any more, but any already mapped stay mapped
+    /// (and allocated) until the object is freed.
+    pub fn set_size(&self, size: u64) {
+        self.0.lock().size = size;
+    }
+
+    /// Get the physical address of page number `page` of the object, allocating a zeroed frame
+    /// for it if this is the first time it's been used.
+    ///
+    /// Returns `None` if the page is past the end of the object, or there's no memory left.
+    pub fn frame(&self, page: usize) -> Option<usize> {
+        let mut inner = self.0.lock();
+        if page as u64 >= inner.size.div_ceil(PAGE_FRAME_SIZE as u64) {
+            return None;
+        }
+        if inner.frames.len() <= page {
+            inner.frames.resize(page + 1, None);
+        }
+        if let Some(phys_addr) = inner.frames[page] {
+            return Some(phys_addr);
+        }
+        // zeroed, to prevent data from being leaked between processes.
+        let frame = unsafe { KERNEL_ALLOCATOR.frame_alloc_zeroed(1) }.ok()?;
+        let phys_addr = frame.as_ptr() as usize - OFFSET;
+        inner.frames[page] = Some(phys_addr);
+        Some(phys_addr)
+    }
+}
+
+impl Drop for SharedMemoryInner {
+    fn drop(&mut self) {
+        for phys_addr in self.frames.iter().flatten() {
+            let frame = NonNull::new((phys_addr + OFFSET) as *mut u8).expect("frame was null");
+            // SAFETY: nothing refers to the object any more, so nothing has the frame mapped.
+            unsafe { KERNEL_ALLOCATOR.frame_dealloc(frame) };
+        }
+    }
+}
+
+impl Debug for SharedMemory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "SharedMemory")
+    }
+}
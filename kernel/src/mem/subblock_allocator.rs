@@ -1,8 +1,9 @@
 use super::FrameAllocator;
-use core::ptr::NonNull;
+use core::ptr::{self, NonNull};
 use core::{
     alloc::{AllocError, Layout},
     mem::size_of,
+    slice,
 };
 use kidneyos_shared::mem::PAGE_FRAME_SIZE;
 
@@ -24,6 +25,46 @@ fn get_best_subblock_size_idx(layout: Layout) -> usize {
     SUBBLOCK_TYPE_COUNT
 }
 
+/// Size of the red zone after each allocation, and the least before it, if red zones are on
+const RED_ZONE_SIZE: usize = 16;
+/// What red zones are filled with
+const RED_ZONE_BYTE: u8 = 0xFD;
+
+/// Size of the red zone before an allocation of `layout`, which keeps the allocation aligned.
+fn red_zone_before(layout: Layout) -> usize {
+    RED_ZONE_SIZE.next_multiple_of(layout.align())
+}
+
+/// What's actually allocated for `layout` if red zones are on: the allocation with its red zones.
+fn with_red_zones(layout: Layout) -> Result<Layout, AllocError> {
+    let size = red_zone_before(layout)
+        .checked_add(layout.size())
+        .and_then(|size| size.checked_add(RED_ZONE_SIZE))
+        .ok_or(AllocError)?;
+    Layout::from_size_align(size, layout.align()).map_err(|_| AllocError)
+}
+
+/// Halt if anything has written to the red zones around the allocation of `layout` at `ptr`.
+///
+/// # Safety
+///
+/// `ptr` must have been allocated with red zones, for `layout`.
+unsafe fn check_red_zones(ptr: *mut u8, layout: Layout) {
+    let before = red_zone_before(layout);
+    if slice::from_raw_parts(ptr.sub(before), before)
+        .iter()
+        .any(|&byte| byte != RED_ZONE_BYTE)
+    {
+        halt!("[SUBBLOCK ALLOCATOR]: Red zone before {ptr:p} overwritten (buffer underrun)");
+    }
+    if slice::from_raw_parts(ptr.add(layout.size()), RED_ZONE_SIZE)
+        .iter()
+        .any(|&byte| byte != RED_ZONE_BYTE)
+    {
+        halt!("[SUBBLOCK ALLOCATOR]: Red zone after {ptr:p} overwritten (buffer overrun)");
+    }
+}
+
 struct ListNode {
     next: Option<&'static mut ListNode>,
 }
@@ -31,16 +72,66 @@ struct ListNode {
 pub struct SubblockAllocatorSolution<F: FrameAllocator> {
     list_heads: [Option<&'static mut ListNode>; SUBBLOCK_TYPE_COUNT],
     frame_allocator: F,
+    /// Whether each allocation has red zones before and after it, which are checked when it's
+    /// freed
+    red_zones: bool,
 }
 
 impl<F: FrameAllocator> SubblockAllocatorSolution<F> {
-    pub fn new(frame_allocator: F) -> Self {
+    /// Create a subblock allocator which, if `red_zones` is set, surrounds each allocation with
+    /// [`RED_ZONE_SIZE`] or more bytes of [`RED_ZONE_BYTE`], and halts if they've changed when
+    /// it's freed.
+    pub fn with_red_zones(frame_allocator: F, red_zones: bool) -> Self {
         const EMPTY: Option<&'static mut ListNode> = None;
 
         SubblockAllocatorSolution {
             list_heads: [EMPTY; SUBBLOCK_TYPE_COUNT],
             frame_allocator,
+            red_zones,
+        }
+    }
+
+    /// Allocate memory for `layout`, with red zones around it if they're on.
+    pub fn allocate(&mut self, layout: Layout) -> Result<*mut u8, AllocError> {
+        if !self.red_zones {
+            return self.allocate_block(layout);
+        }
+        let before = red_zone_before(layout);
+        let block = self.allocate_block(with_red_zones(layout)?)?;
+        // SAFETY: the block was just allocated, with room for both red zones.
+        unsafe {
+            ptr::write_bytes(block, RED_ZONE_BYTE, before);
+            ptr::write_bytes(
+                block.add(before + layout.size()),
+                RED_ZONE_BYTE,
+                RED_ZONE_SIZE,
+            );
+            Ok(block.add(before))
+        }
+    }
+
+    /// Free the memory at `ptr`, allocated by [`Self::allocate`] for `layout`, halting if its red
+    /// zones have been overwritten.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure the pointer belongs to the allocator.
+    pub unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        let (block, block_layout) = if self.red_zones {
+            // Can't fail, since it didn't when this was allocated.
+            let block_layout = with_red_zones(layout).unwrap();
+            (ptr.wrapping_sub(red_zone_before(layout)), block_layout)
+        } else {
+            (ptr, layout)
+        };
+
+        #[cfg(any(test, feature = "alloc_checks"))]
+        self.check_deallocate(block, get_best_subblock_size_idx(block_layout));
+
+        if self.red_zones {
+            check_red_zones(ptr, layout);
         }
+        self.deallocate_block(block, block_layout);
     }
 
     /// Allocate a fixed size block for the size requested by 'layout'
@@ -52,13 +143,20 @@ impl<F: FrameAllocator> SubblockAllocatorSolution<F> {
     /// the size of the subblock size corresponding to that LinkedList
     ///
     /// If the allocation size is larger than the largest subblock size (2048 bytes), a frame(s)
-    /// is allocated instead of dividing into subblocks
-    pub fn allocate(&mut self, layout: Layout) -> Result<*mut u8, AllocError> {
+    /// is allocated instead of dividing into subblocks. If the alignment is larger than a frame,
+    /// extra frames are allocated so that the allocation can be aligned within them.
+    fn allocate_block(&mut self, layout: Layout) -> Result<*mut u8, AllocError> {
         let subblock_size_index = get_best_subblock_size_idx(layout);
 
         if subblock_size_index == SUBBLOCK_TYPE_COUNT {
             let num_frames = layout.size().max(layout.align()).div_ceil(PAGE_FRAME_SIZE);
-            let new_frame = self.frame_allocator.alloc(num_frames)?;
+            let new_frame = if layout.align() > PAGE_FRAME_SIZE {
+                let num_frames = layout.size().div_ceil(PAGE_FRAME_SIZE).max(1);
+                self.frame_allocator
+                    .alloc_aligned(num_frames, layout.align())?
+            } else {
+                self.frame_allocator.alloc(num_frames)?
+            };
 
             return Ok(new_frame.as_ptr());
         };
@@ -119,7 +217,7 @@ impl<F: FrameAllocator> SubblockAllocatorSolution<F> {
     /// allocator
     ///
     /// TODO: Reclaim fully freed frames from free subblocks
-    pub unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+    unsafe fn deallocate_block(&mut self, ptr: *mut u8, layout: Layout) {
         let subblock_size_index = get_best_subblock_size_idx(layout);
 
         if subblock_size_index == SUBBLOCK_TYPE_COUNT {
@@ -137,6 +235,33 @@ impl<F: FrameAllocator> SubblockAllocatorSolution<F> {
         }
     }
 
+    /// Halt if `ptr`, of the subblock size at `subblock_size_index`, isn't currently allocated:
+    /// if it's already been freed, or the allocator never handed it out.
+    ///
+    /// Freed subblocks are found by walking the free list, so this is slow.
+    #[cfg(any(test, feature = "alloc_checks"))]
+    fn check_deallocate(&self, ptr: *mut u8, subblock_size_index: usize) {
+        let owned = NonNull::new(ptr).is_some_and(|ptr| self.frame_allocator.is_allocated(ptr));
+        if subblock_size_index == SUBBLOCK_TYPE_COUNT {
+            // the frame allocator knows whether the frames were already freed
+            if !owned {
+                halt!("[SUBBLOCK ALLOCATOR]: Freed {ptr:p}, which isn't allocated");
+            }
+            return;
+        }
+        // Subblocks are aligned to their size, since frames are.
+        if !owned || ptr as usize % SUBBLOCK_SIZES[subblock_size_index] != 0 {
+            halt!("[SUBBLOCK ALLOCATOR]: Freed {ptr:p}, which isn't owned by the allocator");
+        }
+        let mut node = self.list_heads[subblock_size_index].as_deref();
+        while let Some(free) = node {
+            if free as *const ListNode as *mut u8 == ptr {
+                halt!("[SUBBLOCK ALLOCATOR]: Double free of {ptr:p}");
+            }
+            node = free.next.as_deref();
+        }
+    }
+
     /// Return a mutable reference to underlying frame allocator
     ///
     /// This function should be used for memory allocations that do not go through the kernel
@@ -220,7 +345,8 @@ mod tests {
 
         let frame_allocator = FrameAllocatorSolution::<NextFit>::new(region, Box::new(core_map));
 
-        let mut subblock_allocator = SubblockAllocatorSolution::new(frame_allocator);
+        let mut subblock_allocator =
+            SubblockAllocatorSolution::with_red_zones(frame_allocator, false);
         assert!(subblock_allocator.is_empty());
 
         // A request for 5 bytes should use a 16 byte subblock
@@ -256,4 +382,98 @@ mod tests {
 
         Ok(())
     }
+
+    fn subblock_allocator_with_red_zones(
+        red_zones: bool,
+    ) -> SubblockAllocatorSolution<FrameAllocatorSolution<NextFit>> {
+        const NUM_FRAMES: usize = 4;
+
+        let core_map = [CoreMapEntry::default(); NUM_FRAMES];
+        let layout =
+            Layout::from_size_align(PAGE_FRAME_SIZE * NUM_FRAMES, PAGE_FRAME_SIZE).unwrap();
+        let region = Global.allocate(layout).unwrap();
+        let frame_allocator = FrameAllocatorSolution::new(region, Box::new(core_map));
+        SubblockAllocatorSolution::with_red_zones(frame_allocator, red_zones)
+    }
+
+    fn subblock_allocator() -> SubblockAllocatorSolution<FrameAllocatorSolution<NextFit>> {
+        subblock_allocator_with_red_zones(false)
+    }
+
+    #[test]
+    #[should_panic(expected = "Double free")]
+    fn double_free() {
+        let mut subblock_allocator = subblock_allocator();
+        let layout = Layout::from_size_align(24, 8).unwrap();
+        let ptr = subblock_allocator.allocate(layout).unwrap();
+        let other = subblock_allocator.allocate(layout).unwrap();
+        unsafe {
+            subblock_allocator.deallocate(ptr, layout);
+            subblock_allocator.deallocate(other, layout);
+            subblock_allocator.deallocate(ptr, layout);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "isn't allocated")]
+    fn double_free_of_frames() {
+        let mut subblock_allocator = subblock_allocator();
+        let layout = Layout::from_size_align(5000, 8).unwrap();
+        let ptr = subblock_allocator.allocate(layout).unwrap();
+        unsafe {
+            subblock_allocator.deallocate(ptr, layout);
+            subblock_allocator.deallocate(ptr, layout);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "isn't owned by the allocator")]
+    fn free_of_unowned_memory() {
+        let mut subblock_allocator = subblock_allocator();
+        let layout = Layout::from_size_align(16, 8).unwrap();
+        let ptr = subblock_allocator.allocate(layout).unwrap();
+        // in the right frame, but not the start of a subblock
+        unsafe { subblock_allocator.deallocate(ptr.add(8), layout) };
+    }
+
+    #[test]
+    fn red_zones() {
+        let mut subblock_allocator = subblock_allocator_with_red_zones(true);
+        for (size, align) in [(5, 2), (24, 32), (5000, 8)] {
+            let layout = Layout::from_size_align(size, align).unwrap();
+            let ptr = subblock_allocator.allocate(layout).unwrap();
+            assert_eq!(ptr as usize % align, 0);
+            unsafe {
+                ptr.write_bytes(0x11, size);
+                subblock_allocator.deallocate(ptr, layout);
+            }
+        }
+        // 5 bytes and two red zones need a 64 byte subblock
+        assert_eq!(subblock_allocator.length_of_lst(64), 64);
+    }
+
+    #[test]
+    #[should_panic(expected = "buffer overrun")]
+    fn red_zone_overrun() {
+        let mut subblock_allocator = subblock_allocator_with_red_zones(true);
+        let layout = Layout::from_size_align(10, 2).unwrap();
+        let ptr = subblock_allocator.allocate(layout).unwrap();
+        unsafe {
+            // one byte past the end
+            ptr.add(10).write(0);
+            subblock_allocator.deallocate(ptr, layout);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "buffer underrun")]
+    fn red_zone_underrun() {
+        let mut subblock_allocator = subblock_allocator_with_red_zones(true);
+        let layout = Layout::from_size_align(10, 2).unwrap();
+        let ptr = subblock_allocator.allocate(layout).unwrap();
+        unsafe {
+            ptr.sub(1).write(0);
+            subblock_allocator.deallocate(ptr, layout);
+        }
+    }
 }
@@ -1,13 +1,39 @@
 use crate::fs::fs_manager::FileSystemID;
+use crate::mem::page_cache::PageCache;
+use crate::mem::shm::SharedMemory;
 use crate::system::unwrap_system;
-use crate::vfs::INodeNum;
+use crate::user_program::syscall::MemUsage;
+use crate::vfs::{self, INodeNum};
 use crate::KERNEL_ALLOCATOR;
 use alloc::collections::BTreeMap;
+use core::ops::Range;
+use core::ptr::NonNull;
 use kidneyos_shared::mem::{OFFSET, PAGE_FRAME_SIZE};
 
 /// A list of virtual memory areas for a process
-#[derive(Debug, Default, Clone)]
-pub struct VMAList(BTreeMap<usize, VMA>);
+#[derive(Debug, Clone)]
+pub struct VMAList {
+    vmas: BTreeMap<usize, VMA>,
+    /// Total size of the VMAs in bytes
+    size: usize,
+    /// Most bytes the VMAs can add up to, as set with `setrlimit(RLIMIT_AS)`
+    limit: usize,
+}
+
+impl Default for VMAList {
+    fn default() -> Self {
+        Self {
+            vmas: BTreeMap::new(),
+            size: 0,
+            limit: usize::MAX,
+        }
+    }
+}
+
+/// The frame at physical address `phys_addr`, as the kernel sees it.
+fn frame_at(phys_addr: usize) -> NonNull<u8> {
+    NonNull::new((phys_addr + OFFSET) as *mut u8).expect("mapped frame was null")
+}
 
 /// A virtual memory area
 #[derive(Debug, Clone)]
@@ -27,12 +53,22 @@ pub enum VMAInfo {
     Heap,
     /// This VMA contains a memory-mapped file
     ///
-    /// `offset` is in units of pages
+    /// `offset` is in units of pages. If `shared` is set, the pages of `cache` are mapped as they
+    /// are, and changes are written back to the file. Otherwise, each page is copied the first time
+    /// it's written to, so changes are only seen by this process. A fault reads in the `read_ahead`
+    /// pages after the one faulted in too, which depends on the advice given for the file.
     MMap {
         fs: FileSystemID,
         inode: INodeNum,
+        cache: PageCache,
         offset: u32,
+        shared: bool,
+        read_ahead: usize,
     },
+    /// This VMA contains a shared memory object
+    ///
+    /// `offset` is in units of pages
+    Shm { shm: SharedMemory, offset: u32 },
 }
 
 impl Clone for VMAInfo {
@@ -41,15 +77,32 @@ impl Clone for VMAInfo {
         match self {
             Self::Stack => Self::Stack,
             Self::Heap => Self::Heap,
-            Self::MMap { fs, inode, offset } => {
+            Self::MMap {
+                fs,
+                inode,
+                cache,
+                offset,
+                shared,
+                read_ahead,
+            } => {
                 let fs = *fs;
                 let inode = *inode;
-                let offset = *offset;
                 // increment reference count to inode to allow mmapped closed file to still be read.
                 let mut root = unwrap_system().root_filesystem.lock();
                 root.increment_inode_ref_count(fs, inode);
-                Self::MMap { fs, inode, offset }
+                Self::MMap {
+                    fs,
+                    inode,
+                    cache: cache.clone(),
+                    offset: *offset,
+                    shared: *shared,
+                    read_ahead: *read_ahead,
+                }
             }
+            Self::Shm { shm, offset } => Self::Shm {
+                shm: shm.clone(),
+                offset: *offset,
+            },
         }
     }
 }
@@ -71,59 +124,178 @@ impl VMA {
     pub fn writeable(&self) -> bool {
         self.writeable
     }
+    /// Map the frame at `phys_addr` into the running thread's page table at `virt_addr`, replacing
+    /// whatever was mapped there before (whose frame isn't freed).
+    unsafe fn map(&self, phys_addr: usize, virt_addr: usize, writeable: bool) {
+        let mut tcb_guard = unwrap_system().threads.running_thread.lock();
+        let tcb = tcb_guard.as_mut().expect("no running thread");
+        if let Some(old_phys_addr) = tcb.page_manager.unmap(virt_addr) {
+            KERNEL_ALLOCATOR.frame_unmapped(frame_at(old_phys_addr));
+        }
+        tcb.page_manager.map(phys_addr, virt_addr, writeable, true);
+        KERNEL_ALLOCATOR.frame_mapped(frame_at(phys_addr));
+    }
+    /// Whether `virt_addr` is mapped in the running thread's page table.
+    fn is_mapped(virt_addr: usize) -> bool {
+        let tcb_guard = unwrap_system().threads.running_thread.lock();
+        let tcb = tcb_guard.as_ref().expect("no running thread");
+        tcb.page_manager.is_mapped(virt_addr)
+    }
     #[must_use]
-    unsafe fn install_in_page_table(&self, virt_addr: usize, offset: usize) -> bool {
+    unsafe fn install_in_page_table(&self, virt_addr: usize, offset: usize, write: bool) -> bool {
         debug_assert_eq!(virt_addr % PAGE_FRAME_SIZE, 0);
         debug_assert_eq!(offset % PAGE_FRAME_SIZE, 0);
-        let Ok(frame_ptr) = (unsafe { KERNEL_ALLOCATOR.frame_alloc(1) }) else {
-            return false;
-        };
-        let frame_ptr = frame_ptr.as_ptr();
-        let phys_addr = frame_ptr as usize - OFFSET;
-        let mut tcb_guard = unwrap_system().threads.running_thread.lock();
-        let tcb = tcb_guard.as_mut().expect("no running thread");
-        tcb.page_manager
-            .map(phys_addr, virt_addr, self.writeable(), true);
-        drop(tcb_guard);
-        // important we don't use the virtual address here since it may be read-only!
-        let data = core::slice::from_raw_parts_mut(frame_ptr, PAGE_FRAME_SIZE);
+        let mapped = Self::is_mapped(virt_addr);
         match &self.info {
             VMAInfo::Stack | VMAInfo::Heap => {
-                // zero memory, to prevent data from being leaked between processes.
-                data.fill(0);
+                if mapped {
+                    return false;
+                }
+                // the frame is zeroed, to prevent data from being leaked between processes.
+                let Ok(frame_ptr) = (unsafe { KERNEL_ALLOCATOR.frame_alloc_zeroed(1) }) else {
+                    return false;
+                };
+                self.map(
+                    frame_ptr.as_ptr() as usize - OFFSET,
+                    virt_addr,
+                    self.writeable,
+                );
                 true
             }
-            VMAInfo::MMap { fs, inode, offset } => {
-                let fs = *fs;
-                let inode = *inode;
-                let offset = u64::from(*offset) * PAGE_FRAME_SIZE as u64;
-                let mut root = unwrap_system().root_filesystem.lock();
-                let mut bytes_read = 0;
-                while bytes_read < PAGE_FRAME_SIZE {
-                    let n = match root.read_direct(
-                        fs,
-                        inode,
-                        offset + bytes_read as u64,
-                        &mut data[bytes_read..],
-                    ) {
-                        Ok(0) => {
-                            break;
-                        }
-                        Ok(n) => n,
-                        // Generate page fault if reading data from mmapped file fails.
-                        // This seems to be consistent with other OSes (some do a bus error instead)
-                        Err(_) => {
-                            return false;
-                        }
-                    };
-                    bytes_read += n;
+            VMAInfo::Shm {
+                shm,
+                offset: first_page,
+            } => {
+                if mapped {
+                    return false;
+                }
+                // every process mapping the object shares its frames, rather than getting its own
+                let Some(phys_addr) = shm.frame(*first_page as usize + offset / PAGE_FRAME_SIZE)
+                else {
+                    return false;
+                };
+                self.map(phys_addr, virt_addr, self.writeable);
+                true
+            }
+            VMAInfo::MMap {
+                cache,
+                offset: first_page,
+                shared,
+                read_ahead,
+                ..
+            } => {
+                let page = *first_page as usize + offset / PAGE_FRAME_SIZE;
+                // Generate page fault if reading data from mmapped file fails.
+                // This seems to be consistent with other OSes (some do a bus error instead)
+                let Some(phys_addr) = cache.frame(page) else {
+                    return false;
+                };
+                // If it can't be read now, it's read when it's faulted in instead.
+                for ahead in 1..=*read_ahead {
+                    if offset + ahead * PAGE_FRAME_SIZE >= self.size {
+                        break;
+                    }
+                    cache.read_ahead(page + ahead);
+                }
+                if *shared || !(write && self.writeable) {
+                    if mapped {
+                        return false;
+                    }
+                    // a private mapping's pages stay read-only until they're copied
+                    self.map(phys_addr, virt_addr, *shared && self.writeable);
+                    return true;
                 }
-                // if we reached the end of the file, fill the rest of the page with zeros.
-                data[bytes_read..].fill(0);
+                // Copy on write: this is either the first access to the page of a private
+                // mapping, and it's a write, or a write to the read-only page mapped before.
+                let Ok(frame_ptr) = (unsafe { KERNEL_ALLOCATOR.frame_alloc(1) }) else {
+                    return false;
+                };
+                core::ptr::copy_nonoverlapping(
+                    (phys_addr + OFFSET) as *const u8,
+                    frame_ptr.as_ptr(),
+                    PAGE_FRAME_SIZE,
+                );
+                self.map(frame_ptr.as_ptr() as usize - OFFSET, virt_addr, true);
                 true
             }
         }
     }
+    /// Write the pages in `pages` of this VMA, which starts at `vma_addr`, back to its file, if
+    /// it's a shared file mapping and they've been written to since they were last written back.
+    pub fn write_back(&self, vma_addr: usize, pages: Range<usize>) -> vfs::Result<()> {
+        let VMAInfo::MMap {
+            fs,
+            inode,
+            offset,
+            shared: true,
+            ..
+        } = self.info
+        else {
+            return Ok(());
+        };
+        if !self.writeable {
+            return Ok(());
+        }
+        for page in pages.step_by(PAGE_FRAME_SIZE) {
+            let dirty = {
+                let mut tcb_guard = unwrap_system().threads.running_thread.lock();
+                let tcb = tcb_guard.as_mut().expect("no running thread");
+                tcb.page_manager.take_dirty(page)
+            };
+            let Some(phys_addr) = dirty else {
+                continue;
+            };
+            // SAFETY: the frame is mapped into the kernel at phys_addr + OFFSET, and belongs to the
+            // page cache, which this VMA keeps alive.
+            let data = unsafe {
+                core::slice::from_raw_parts((phys_addr + OFFSET) as *const u8, PAGE_FRAME_SIZE)
+            };
+            let file_offset = u64::from(offset) * PAGE_FRAME_SIZE as u64 + (page - vma_addr) as u64;
+            let written =
+                unwrap_system()
+                    .root_filesystem
+                    .lock()
+                    .write_direct(fs, inode, file_offset, data);
+            if let Err(e) = written {
+                // still not written back, so try again next time
+                let mut tcb_guard = unwrap_system().threads.running_thread.lock();
+                let tcb = tcb_guard.as_mut().expect("no running thread");
+                tcb.page_manager.set_dirty(page);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+    /// Unmap the pages of this VMA, which starts at `vma_addr`, from the running thread's page
+    /// table, freeing their frames (other than those belonging to a shared memory object or page
+    /// cache).
+    ///
+    /// Changes to a shared file mapping should be written back first.
+    ///
+    /// # Safety
+    ///
+    /// The VMA must have been removed, so userspace has given up any pointers into it.
+    pub unsafe fn unmap(&self, vma_addr: usize) {
+        let mut tcb_guard = unwrap_system().threads.running_thread.lock();
+        let tcb = tcb_guard.as_mut().expect("no running thread");
+        for page in (vma_addr..vma_addr + self.size).step_by(PAGE_FRAME_SIZE) {
+            let Some(phys_addr) = tcb.page_manager.unmap(page) else {
+                continue;
+            };
+            KERNEL_ALLOCATOR.frame_unmapped(frame_at(phys_addr));
+            let owned = match &self.info {
+                VMAInfo::Stack | VMAInfo::Heap => true,
+                VMAInfo::MMap { cache, offset, .. } => {
+                    let page = *offset as usize + (page - vma_addr) / PAGE_FRAME_SIZE;
+                    cache.cached_frame(page) != Some(phys_addr)
+                }
+                VMAInfo::Shm { .. } => false,
+            };
+            if owned {
+                KERNEL_ALLOCATOR.frame_dealloc(frame_at(phys_addr));
+            }
+        }
+    }
 }
 
 impl VMAList {
@@ -133,7 +305,7 @@ impl VMAList {
     }
     fn vma_at(&self, addr: usize) -> Option<(usize, &VMA)> {
         // find VMA whose address is closest to addr without going over
-        let (vma_addr, vma) = self.0.range(..=addr).next_back()?;
+        let (vma_addr, vma) = self.vmas.range(..=addr).next_back()?;
         let vma_addr = *vma_addr;
         // check if addr actually lies in the VMA
         if addr >= vma_addr && addr < vma_addr + vma.size {
@@ -148,39 +320,187 @@ impl VMAList {
         if self.vma_at(range.start).is_some() {
             return false;
         }
-        self.0.range(range.start..range.end).next().is_none()
+        self.vmas.range(range.start..range.end).next().is_none()
     }
-    /// Install PTE for virtual address `addr`, if possible.
+    /// Install PTE for virtual address `addr`, if possible, after a fault which was a write if
+    /// `write` is set. If `addr` is already mapped, this only succeeds for a write to a page of a
+    /// private file mapping which hasn't been copied yet.
     ///
     /// Returns `false` on failure, e.g. couldn't allocate physical memory, there is no VMA covering `addr`,
     /// couldn't read mmapped file.
     ///
     /// # Safety
     ///
-    /// `addr` must be currently unmapped.
+    /// There must be no pointers into the page at `addr` if it's mapped.
     #[must_use]
-    pub unsafe fn install_pte(&self, addr: usize) -> bool {
+    pub unsafe fn install_pte(&self, addr: usize, write: bool) -> bool {
         // round down to page
         let addr = addr & !(PAGE_FRAME_SIZE - 1);
         let Some((vma_addr, vma)) = self.vma_at(addr) else {
             return false;
         };
-        vma.install_in_page_table(addr, addr - vma_addr)
+        vma.install_in_page_table(addr, addr - vma_addr, write)
+    }
+    /// Whether `bytes` more bytes of VMAs would fit under the limit.
+    fn can_grow(&self, bytes: usize) -> bool {
+        bytes <= self.limit.saturating_sub(self.size)
+    }
+    /// Total size of the VMAs in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+    /// Most bytes the VMAs can add up to.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+    /// Set the most bytes the VMAs can add up to. VMAs which are already there are kept, even if
+    /// they add up to more than `limit`, but no more can be added until they're under it.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
     }
     /// Add a VMA to the list.
     ///
-    /// `addr` must be a multiple of `PAGE_FRAME_SIZE`. If there is already a VMA anywhere in the address range, returns `false`.
+    /// `addr` must be a multiple of `PAGE_FRAME_SIZE`. If there is already a VMA anywhere in the address range, or
+    /// the VMAs would add up to more than the limit, returns `false`.
     #[must_use]
     pub fn add_vma(&mut self, vma: VMA, addr: usize) -> bool {
         assert_eq!(addr % PAGE_FRAME_SIZE, 0);
-        if !self.is_address_range_free(addr..addr + vma.size) {
+        if !self.can_grow(vma.size) || !self.is_address_range_free(addr..addr + vma.size) {
+            return false;
+        }
+        self.size += vma.size;
+        self.vmas.insert(addr, vma);
+        true
+    }
+    /// Change the size of the VMA starting at `addr` to `size`, which must be a multiple of `PAGE_FRAME_SIZE`.
+    ///
+    /// Returns `false` if there is no VMA at `addr`, or if growing it would overlap another VMA or take the VMAs
+    /// over the limit.
+    #[must_use]
+    pub fn resize_vma(&mut self, addr: usize, size: usize) -> bool {
+        assert_eq!(size % PAGE_FRAME_SIZE, 0);
+        let Some(old_size) = self.vmas.get(&addr).map(VMA::size) else {
+            return false;
+        };
+        if size > old_size
+            && (!self.can_grow(size - old_size)
+                || !self.is_address_range_free(addr + old_size..addr + size))
+        {
             return false;
         }
-        self.0.insert(addr, vma);
+        self.size = self.size - old_size + size;
+        self.vmas.get_mut(&addr).expect("VMA disappeared").size = size;
         true
     }
+    /// Remove the VMA starting at `addr`, if there is one.
+    pub fn remove_vma(&mut self, addr: usize) -> Option<VMA> {
+        let vma = self.vmas.remove(&addr)?;
+        self.size -= vma.size;
+        Some(vma)
+    }
     pub fn iter(&self) -> impl '_ + Iterator<Item = (usize, &VMA)> {
-        self.0.iter().map(|(&k, v)| (k, v))
+        self.vmas.iter().map(|(&k, v)| (k, v))
+    }
+    /// The VMAs which overlap `range`, in order.
+    pub fn overlapping(&self, range: Range<usize>) -> impl '_ + Iterator<Item = (usize, &VMA)> {
+        let start = self
+            .vma_at(range.start)
+            .map_or(range.start, |(addr, _)| addr);
+        self.vmas.range(start..range.end).map(|(&k, v)| (k, v))
+    }
+    /// Write back the changes to every shared file mapping, e.g. before the process exits.
+    pub fn write_back_all(&self) -> vfs::Result<()> {
+        for (addr, vma) in self.iter() {
+            vma.write_back(addr, addr..addr + vma.size)?;
+        }
+        Ok(())
+    }
+    /// Unmap every VMA from the running thread's page table, freeing the frames they own, e.g.
+    /// when the process exits. The VMAs themselves are kept.
+    ///
+    /// Changes to shared file mappings should be written back first.
+    ///
+    /// # Safety
+    ///
+    /// Userspace must never run in this address space again.
+    pub unsafe fn unmap_all(&self) {
+        for (addr, vma) in self.iter() {
+            vma.unmap(addr);
+        }
+    }
+    /// How much memory the VMAs are using in the running thread's page table.
+    pub fn memory_usage(&self) -> MemUsage {
+        let tcb_guard = unwrap_system().threads.running_thread.lock();
+        let tcb = tcb_guard.as_ref().expect("no running thread");
+        self.memory_usage_with(
+            |page| tcb.page_manager.translate(page),
+            |phys_addr| unsafe { KERNEL_ALLOCATOR.frame_map_count(frame_at(phys_addr)) },
+        )
+    }
+    /// How much memory the VMAs are using, given the physical address each page is mapped to, if
+    /// it's mapped, and how many pages each frame is mapped into.
+    fn memory_usage_with(
+        &self,
+        translate: impl Fn(usize) -> Option<usize>,
+        map_count: impl Fn(usize) -> u8,
+    ) -> MemUsage {
+        let mut usage = MemUsage::default();
+        for (addr, vma) in self.iter() {
+            for page in (addr..addr + vma.size).step_by(PAGE_FRAME_SIZE) {
+                let Some(phys_addr) = translate(page) else {
+                    continue;
+                };
+                usage.rss += PAGE_FRAME_SIZE;
+                // frames mapped before the counts were kept, or too many times to count, still
+                // count for something
+                usage.pss += PAGE_FRAME_SIZE / usize::from(map_count(phys_addr).max(1));
+            }
+        }
+        usage
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn address_space_limit() {
+        let mut vmas = VMAList::new();
+        vmas.set_limit(4 * PAGE_FRAME_SIZE);
+        let heap = || VMA::new(VMAInfo::Heap, 2 * PAGE_FRAME_SIZE, true);
+        assert!(vmas.add_vma(heap(), 0x10000));
+        assert!(vmas.add_vma(heap(), 0x20000));
+        assert_eq!(vmas.size(), 4 * PAGE_FRAME_SIZE);
+        // nothing more fits, whether it's a new VMA or growing an old one
+        assert!(!vmas.add_vma(heap(), 0x30000));
+        assert!(!vmas.resize_vma(0x10000, 3 * PAGE_FRAME_SIZE));
+        // but shrinking makes room again
+        assert!(vmas.resize_vma(0x10000, PAGE_FRAME_SIZE));
+        assert!(vmas.remove_vma(0x20000).is_some());
+        assert_eq!(vmas.size(), PAGE_FRAME_SIZE);
+        assert!(vmas.add_vma(heap(), 0x30000));
+        assert!(vmas.resize_vma(0x10000, 2 * PAGE_FRAME_SIZE));
+        assert_eq!(vmas.size(), 4 * PAGE_FRAME_SIZE);
+    }
+
+    #[test]
+    fn proportional_set_size() {
+        let mut vmas = VMAList::new();
+        let shared = || VMA::new(VMAInfo::Heap, 2 * PAGE_FRAME_SIZE, true);
+        assert!(vmas.add_vma(shared(), 0x10000));
+        assert!(vmas.add_vma(shared(), 0x20000));
+        assert!(vmas.add_vma(VMA::new(VMAInfo::Stack, 4 * PAGE_FRAME_SIZE, true), 0x30000));
+        // both of the first two VMAs map the same two frames, and only one page of the stack has
+        // been touched
+        let translate = |page: usize| match page {
+            0x10000..=0x2ffff => Some(0x100000 + page % 0x10000),
+            0x30000 => Some(0x200000),
+            _ => None,
+        };
+        let map_count = |phys_addr: usize| if phys_addr < 0x200000 { 2 } else { 1 };
+        let usage = vmas.memory_usage_with(translate, map_count);
+        assert_eq!(usage.rss, 5 * PAGE_FRAME_SIZE);
+        assert_eq!(usage.pss, 3 * PAGE_FRAME_SIZE);
     }
-    // TODO: free physical memory allocated by VMAs on process exit
 }
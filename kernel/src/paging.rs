@@ -1,18 +1,36 @@
-use alloc::alloc::Global;
+use crate::interrupts::mutex_irq::MutexIrq;
+use alloc::{alloc::Global, vec::Vec};
 use kidneyos_shared::{
-    mem::OFFSET,
+    mem::{DEVICE_MEMORY_START, OFFSET, PAGE_FRAME_SIZE},
     paging::{self, kernel_mapping_ranges},
 };
 
 pub type PageManager<A = Global> = paging::PageManager<A>;
 
+/// Physical addresses of the pages mapped with [`map_device_memory`]. The `i`th page is mapped at
+/// `DEVICE_MEMORY_START + i * PAGE_FRAME_SIZE`.
+static DEVICE_PAGES: MutexIrq<Vec<usize>> = MutexIrq::new(Vec::new());
+
 pub trait PageManagerDefault {
     fn default() -> Self;
 }
 
 impl PageManagerDefault for PageManager<Global> {
     fn default() -> Self {
-        PageManager::from_mapping_ranges_in(kernel_mapping_ranges(), Global, OFFSET)
+        let mut page_manager =
+            PageManager::from_mapping_ranges_in(kernel_mapping_ranges(), Global, OFFSET);
+        for (i, &phys_addr) in DEVICE_PAGES.lock().iter().enumerate() {
+            // SAFETY: page_manager has not yet been loaded.
+            unsafe {
+                page_manager.map(
+                    phys_addr,
+                    DEVICE_MEMORY_START + i * PAGE_FRAME_SIZE,
+                    true,
+                    false,
+                )
+            };
+        }
+        page_manager
     }
 }
 
@@ -21,3 +39,35 @@ pub unsafe fn enable() -> PageManager {
     page_manager.load();
     page_manager
 }
+
+/// Map the physical addresses `phys_addr..phys_addr + len`, e.g. a device's registers, into
+/// `page_manager` and every page manager created after it, returning where they were mapped to.
+///
+/// Returns `None` if there isn't enough room left after `DEVICE_MEMORY_START`.
+///
+/// # Safety
+///
+/// `page_manager` must be loaded, and nothing else may be using the physical memory.
+#[cfg_attr(not(feature = "apic"), allow(dead_code))]
+pub unsafe fn map_device_memory(
+    page_manager: &mut PageManager,
+    phys_addr: usize,
+    len: usize,
+) -> Option<*mut u8> {
+    let first_page = phys_addr - phys_addr % PAGE_FRAME_SIZE;
+    let pages = (phys_addr + len - first_page).div_ceil(PAGE_FRAME_SIZE);
+    let mut device_pages = DEVICE_PAGES.lock();
+    if device_pages.len() + pages > (usize::MAX - DEVICE_MEMORY_START) / PAGE_FRAME_SIZE + 1 {
+        return None;
+    }
+    let start = DEVICE_MEMORY_START + device_pages.len() * PAGE_FRAME_SIZE;
+    for i in 0..pages {
+        let page = first_page + i * PAGE_FRAME_SIZE;
+        // TODO: Device registers should be mapped with caching disabled.
+        page_manager.map(page, start + i * PAGE_FRAME_SIZE, true, false);
+        device_pages.push(page);
+    }
+    // make sure the new mappings take effect
+    page_manager.load();
+    Some((start + phys_addr % PAGE_FRAME_SIZE) as *mut u8)
+}
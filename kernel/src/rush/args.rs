@@ -0,0 +1,36 @@
+use crate::rush::context::Context;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+/// Split a builtin's arguments into the single-letter flags at the start (e.g. `-p`, `-rf`), and
+/// the operands after them. `--` ends the flags.
+///
+/// Prints an error and returns `None` if there's a flag which isn't in `allowed`.
+pub fn split_flags<'a>(
+    ctx: &Context,
+    command: &str,
+    args: &[&'a str],
+    allowed: &str,
+) -> Option<(String, Vec<&'a str>)> {
+    let mut flags = String::new();
+    let mut rest = args;
+    while let Some((arg, after)) = rest.split_first() {
+        if *arg == "--" {
+            rest = after;
+            break;
+        }
+        let Some(letters) = arg.strip_prefix('-').filter(|letters| !letters.is_empty()) else {
+            break;
+        };
+        for c in letters.chars() {
+            if !allowed.contains(c) {
+                let _ = writeln!(ctx.stderr(), "rush: {command}: invalid option -- '{c}'");
+                return None;
+            }
+            flags.push(c);
+        }
+        rest = after;
+    }
+    Some((flags, rest.to_vec()))
+}
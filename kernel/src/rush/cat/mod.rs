@@ -0,0 +1,42 @@
+use crate::fs::fs_manager::Mode;
+use crate::fs::FileDescriptor;
+use crate::rush::context::{Context, STDIN, STDOUT};
+use crate::vfs::{Error, Result};
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+/// Print the contents of each file in `args`, or of standard input if there aren't any.
+pub fn cat(ctx: &Context, args: Vec<&str>) {
+    if args.is_empty() {
+        match copy_to_stdout(ctx, STDIN) {
+            // Ctrl-C is how you stop reading from the terminal
+            Ok(()) | Err(Error::Interrupted) => {}
+            Err(e) => {
+                let _ = writeln!(ctx.stderr(), "rush: cat: {}", e);
+            }
+        }
+        return;
+    }
+
+    for path in args {
+        let result = ctx.open(path, Mode::ReadWrite).and_then(|fd| {
+            let result = copy_to_stdout(ctx, fd);
+            ctx.close(fd);
+            result
+        });
+        if let Err(e) = result {
+            let _ = writeln!(ctx.stderr(), "rush: cat: {}: {}", path, e);
+        }
+    }
+}
+
+fn copy_to_stdout(ctx: &Context, fd: FileDescriptor) -> Result<()> {
+    let mut buf = [0; 512];
+    loop {
+        let n = ctx.read(fd, &mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        ctx.write_all(STDOUT, &buf[..n])?;
+    }
+}
@@ -1,9 +1,9 @@
-use crate::system::{root_filesystem, running_process};
+use crate::rush::context::Context;
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
-use kidneyos_shared::eprintln;
+use core::fmt::Write;
 
-pub fn cd(args: Vec<&str>) {
+pub fn cd(ctx: &Context, args: Vec<&str>) {
     let path: String;
 
     // TODO: change to home directory
@@ -13,7 +13,7 @@ pub fn cd(args: Vec<&str>) {
         path = home_dir.to_string();
     } else if args.len() > 1 {
         // Too many arguments
-        eprintln!("rush: cd: too many arguments");
+        let _ = writeln!(ctx.stderr(), "rush: cd: too many arguments");
         return;
     } else {
         let new_path = args[0].to_string();
@@ -27,7 +27,7 @@ pub fn cd(args: Vec<&str>) {
             cd_path = home_dir.to_string() + stripped;
         } else {
             // Relative path
-            let curr_path = running_process().lock().cwd_path.clone();
+            let curr_path = ctx.process.lock().cwd_path.clone();
             // let curr_path = "/".to_string();
             // curr_path.push('/'); // Pad with a slash to avoid edge cases
             cd_path = curr_path + &new_path;
@@ -36,7 +36,11 @@ pub fn cd(args: Vec<&str>) {
         // Resolve the new path
         let ret = resolve_path(cd_path.clone());
         if ret.is_err() {
-            eprintln!("rush: cd: {}: No such file or directory", new_path);
+            let _ = writeln!(
+                ctx.stderr(),
+                "rush: cd: {}: No such file or directory",
+                new_path
+            );
             return;
         }
         path = ret.unwrap();
@@ -44,15 +48,10 @@ pub fn cd(args: Vec<&str>) {
 
     // Change the directory to the new path
     // eprintln!("DEBUG | cd | {}", path);
-    let running = running_process();
-    let mut pcb = running.lock();
-
-    root_filesystem()
-        .lock()
-        .chdir(&mut pcb, &path)
-        .unwrap_or_else(|_| {
-            eprintln!("rush: cd: No such file or directory");
-        });
+    let result = ctx.fs.lock().chdir(&mut ctx.process.lock(), &path);
+    if result.is_err() {
+        let _ = writeln!(ctx.stderr(), "rush: cd: No such file or directory");
+    }
 }
 
 fn resolve_path(path: String) -> Result<String, String> {
@@ -0,0 +1,97 @@
+use crate::fs::fs_manager::Mode;
+use crate::rush::context::Context;
+use crate::vfs::INodeType;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// The names in the directory `word` refers to which could complete it, in order.
+///
+/// Only the part of `word` after the last `/` is completed, so `/tmp/fo` looks in `/tmp` for
+/// names starting with `fo`, and `fo` looks in the working directory. This goes for command names
+/// as well as their arguments. Directories have a `/` on the end.
+pub fn complete(ctx: &Context, word: &str) -> Vec<String> {
+    let (dir, prefix) = split_word(word);
+    let dir = if dir.is_empty() { "." } else { dir };
+    let Ok(fd) = ctx.open(dir, Mode::ReadWrite) else {
+        return Vec::new();
+    };
+    let entries = ctx.readdir(fd);
+    ctx.close(fd);
+    let Ok(entries) = entries else {
+        return Vec::new();
+    };
+
+    let mut names: Vec<String> = entries
+        .into_iter()
+        .filter(|entry| entry.name != "." && entry.name != "..")
+        .filter(|entry| entry.name.starts_with(prefix))
+        .map(|entry| match entry.r#type {
+            INodeType::Directory => format!("{}/", entry.name),
+            _ => entry.name.into_owned(),
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+/// Split `word` into the directory part, up to and including the last `/`, and the name after it.
+pub fn split_word(word: &str) -> (&str, &str) {
+    match word.rfind('/') {
+        Some(i) => word.split_at(i + 1),
+        None => ("", word),
+    }
+}
+
+/// The longest prefix shared by all of `names`.
+pub fn common_prefix(names: &[String]) -> &str {
+    let Some((first, rest)) = names.split_first() else {
+        return "";
+    };
+    let mut len = first.len();
+    for name in rest {
+        len = first
+            .char_indices()
+            .zip(name.chars())
+            .take_while(|((_, a), b)| a == b)
+            .last()
+            .map_or(0, |((i, a), _)| i + a.len_utf8())
+            .min(len);
+    }
+    &first[..len]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rush::parser::execute;
+    use crate::rush::parser::test::{test_context, test_fs};
+
+    #[test]
+    fn complete_common_prefix() {
+        let fs = test_fs();
+        let ctx = test_context(&fs);
+        execute(&ctx, "echo > /tmp/foo");
+        execute(&ctx, "mkdir /tmp/foobar");
+        execute(&ctx, "echo > /tmp/bar");
+
+        let names = complete(&ctx, "/tmp/fo");
+        assert_eq!(names, ["foo", "foobar/"]);
+        assert_eq!(common_prefix(&names), "foo");
+        // relative to the working directory
+        assert_eq!(complete(&ctx, "tmp/b"), ["bar"]);
+        assert_eq!(complete(&ctx, "tm"), ["tmp/"]);
+        assert!(complete(&ctx, "/tmp/x").is_empty());
+        assert!(complete(&ctx, "/nowhere/").is_empty());
+    }
+
+    #[test]
+    fn common_prefixes() {
+        let names = |names: &[&str]| names.iter().map(|&n| n.into()).collect::<Vec<String>>();
+        assert_eq!(common_prefix(&names(&[])), "");
+        assert_eq!(common_prefix(&names(&["ls"])), "ls");
+        assert_eq!(common_prefix(&names(&["cat", "cd", "clear"])), "c");
+        assert_eq!(common_prefix(&names(&["rm", "rmdir"])), "rm");
+        assert_eq!(common_prefix(&names(&["a", "b"])), "");
+    }
+}
@@ -0,0 +1,220 @@
+use crate::fs::fs_manager::{Mode, RootFileSystem, SeekFrom};
+use crate::fs::{FileDescriptor, ProcessFileDescriptor};
+use crate::rush::parser::{Redirect, RedirectKind};
+use crate::sync::mutex::Mutex;
+use crate::system::{root_filesystem, running_process};
+use crate::threading::{process::Pid, thread_control_block::ProcessControlBlock};
+use crate::vfs::{Error, OwnedDirEntry, Result};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+pub const STDIN: FileDescriptor = 0;
+pub const STDOUT: FileDescriptor = 1;
+pub const STDERR: FileDescriptor = 2;
+
+/// What a rush command runs in: the file system, and the process whose file descriptors and
+/// working directory it uses.
+pub struct Context<'a> {
+    pub fs: &'a Mutex<RootFileSystem>,
+    pub process: Arc<Mutex<ProcessControlBlock>>,
+    pid: Pid,
+}
+
+impl Context<'static> {
+    /// The context of the running shell.
+    pub fn current() -> Self {
+        Self::new(root_filesystem(), running_process())
+    }
+}
+
+impl<'a> Context<'a> {
+    pub fn new(fs: &'a Mutex<RootFileSystem>, process: Arc<Mutex<ProcessControlBlock>>) -> Self {
+        let pid = process.lock().pid;
+        Self { fs, process, pid }
+    }
+
+    fn fd(&self, fd: FileDescriptor) -> ProcessFileDescriptor {
+        ProcessFileDescriptor { pid: self.pid, fd }
+    }
+
+    /// Run `f` with our process and then the file system locked, in the same order as
+    /// [`crate::fs::with_running_process`].
+    fn with_process<T>(&self, f: impl FnOnce(&mut RootFileSystem, &ProcessControlBlock) -> T) -> T {
+        let process = self.process.lock();
+        f(&mut self.fs.lock(), &process)
+    }
+
+    /// Standard output, for use with `write!`.
+    pub fn stdout(&self) -> FdWriter<'_, 'a> {
+        FdWriter {
+            context: self,
+            fd: STDOUT,
+        }
+    }
+
+    /// Standard error, for use with `write!`.
+    pub fn stderr(&self) -> FdWriter<'_, 'a> {
+        FdWriter {
+            context: self,
+            fd: STDERR,
+        }
+    }
+
+    /// Open the file at `path` for `redirect`, and put it in place of the standard input or
+    /// output.
+    ///
+    /// Returns the file that was there before, as with [`Self::replace`].
+    pub fn redirect(&self, redirect: &Redirect) -> Result<SavedFd> {
+        let target = match redirect.kind {
+            RedirectKind::Input => STDIN,
+            RedirectKind::Truncate | RedirectKind::Append => STDOUT,
+        };
+        let mode = match redirect.kind {
+            RedirectKind::Input => Mode::ReadWrite,
+            RedirectKind::Truncate | RedirectKind::Append => Mode::CreateReadWrite,
+        };
+        let file = self.fd(self.open(redirect.path, mode)?);
+        let mut fs = self.fs.lock();
+        let prepared = match redirect.kind {
+            RedirectKind::Input => Ok(()),
+            RedirectKind::Truncate => fs.ftruncate(file, 0),
+            RedirectKind::Append => fs.lseek(file, SeekFrom::End, 0).map(|_| ()),
+        };
+        if let Err(e) = prepared {
+            fs.close(file).ok();
+            return Err(e);
+        }
+        drop(fs);
+        self.replace(target, file.fd)
+    }
+
+    /// Put the open file `file` in place of `target`, closing `file`.
+    ///
+    /// Returns the file that was there before, which should be given to [`Self::restore`] once the
+    /// command is done.
+    pub fn replace(&self, target: FileDescriptor, file: FileDescriptor) -> Result<SavedFd> {
+        if file == target {
+            // target wasn't open, or file couldn't have been given its number
+            return Ok(SavedFd {
+                fd: target,
+                saved: None,
+            });
+        }
+        let (saved, result) = self.with_process(|fs, process| {
+            let saved = SavedFd {
+                fd: target,
+                saved: fs.dup(process, self.fd(target)).ok(),
+            };
+            let result = fs.dup2(process, self.fd(file), self.fd(target));
+            fs.close(self.fd(file)).ok();
+            (saved, result)
+        });
+        match result {
+            Ok(()) => Ok(saved),
+            Err(e) => {
+                self.restore(saved);
+                Err(e)
+            }
+        }
+    }
+
+    /// Open the file at `path`, relative to the working directory.
+    pub fn open(&self, path: &str, mode: Mode) -> Result<FileDescriptor> {
+        self.with_process(|fs, process| fs.open(process, path, mode))
+    }
+
+    /// Read all of the file at `path`, relative to the working directory.
+    pub fn read_file(&self, path: &str) -> Result<Vec<u8>> {
+        let file = self.fd(self.open(path, Mode::ReadWrite)?);
+        let mut fs = self.fs.lock();
+        let data = fs.read_to_end(file);
+        fs.close(file).ok();
+        data
+    }
+
+    /// Get the entries of the open directory `fd`.
+    pub fn readdir(&self, fd: FileDescriptor) -> Result<Vec<OwnedDirEntry>> {
+        self.fs.lock().readdir(self.fd(fd))
+    }
+
+    /// Create a directory at `path`, relative to the working directory.
+    pub fn mkdir(&self, path: &str) -> Result<()> {
+        self.with_process(|fs, process| fs.mkdir(process, path))
+    }
+
+    /// Remove the empty directory at `path`, relative to the working directory.
+    pub fn rmdir(&self, path: &str) -> Result<()> {
+        self.with_process(|fs, process| fs.rmdir(process, path))
+    }
+
+    /// Remove the file at `path`, relative to the working directory.
+    pub fn unlink(&self, path: &str) -> Result<()> {
+        self.with_process(|fs, process| fs.unlink(process, path))
+    }
+
+    /// Create a pipe, returning its read and write ends.
+    pub fn pipe(&self) -> Result<(FileDescriptor, FileDescriptor)> {
+        self.with_process(|fs, process| fs.pipe(process))
+    }
+
+    pub fn close(&self, fd: FileDescriptor) {
+        self.fs.lock().close(self.fd(fd)).ok();
+    }
+
+    /// Read from one of our file descriptors.
+    pub fn read(&self, fd: FileDescriptor, buf: &mut [u8]) -> Result<usize> {
+        RootFileSystem::read(self.fs, self.fd(fd), buf)
+    }
+
+    /// Write all of `buf` to one of our file descriptors.
+    pub fn write_all(&self, fd: FileDescriptor, mut buf: &[u8]) -> Result<()> {
+        while !buf.is_empty() {
+            match RootFileSystem::write(self.fs, self.fd(fd), buf)? {
+                0 => return Err(Error::IO("wrote 0 bytes".into())),
+                n => buf = &buf[n..],
+            }
+        }
+        Ok(())
+    }
+
+    /// Undo a [`redirect`](Self::redirect).
+    pub fn restore(&self, saved: SavedFd) {
+        self.with_process(|fs, process| match saved.saved {
+            Some(fd) => {
+                fs.dup2(process, self.fd(fd), self.fd(saved.fd)).ok();
+                fs.close(self.fd(fd)).ok();
+            }
+            None => {
+                fs.close(self.fd(saved.fd)).ok();
+            }
+        })
+    }
+
+    /// Undo several redirects, in the opposite order to how they were made.
+    pub fn restore_all(&self, saved: Vec<SavedFd>) {
+        for saved in saved.into_iter().rev() {
+            self.restore(saved);
+        }
+    }
+}
+
+/// A standard file descriptor which has been redirected, and what it used to be.
+pub struct SavedFd {
+    fd: FileDescriptor,
+    /// A duplicate of the original file, if `fd` was open
+    saved: Option<FileDescriptor>,
+}
+
+/// Writes to one of a [`Context`]'s file descriptors.
+pub struct FdWriter<'c, 'a> {
+    context: &'c Context<'a>,
+    fd: FileDescriptor,
+}
+
+impl core::fmt::Write for FdWriter<'_, '_> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.context
+            .write_all(self.fd, s.as_bytes())
+            .map_err(|_| core::fmt::Error)
+    }
+}
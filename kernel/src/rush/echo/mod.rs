@@ -0,0 +1,87 @@
+use crate::rush::context::Context;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+/// Print the arguments, separated by spaces and followed by a newline.
+///
+/// Leading arguments made up of the flags `n` and `e` (e.g. `-n`, `-ne`) are options:
+/// `-n` leaves out the newline, and `-e` interprets the escapes `\n`, `\t` and `\\`.
+pub fn echo(ctx: &Context, args: Vec<&str>) {
+    let mut newline = true;
+    let mut escapes = false;
+
+    let mut words = args.as_slice();
+    while let Some((arg, rest)) = words.split_first() {
+        let Some(flags) = arg.strip_prefix('-') else {
+            break;
+        };
+        if flags.is_empty() || !flags.chars().all(|c| c == 'n' || c == 'e') {
+            break;
+        }
+        newline &= !flags.contains('n');
+        escapes |= flags.contains('e');
+        words = rest;
+    }
+
+    let mut output = String::new();
+    for (i, word) in words.iter().enumerate() {
+        if i > 0 {
+            output.push(' ');
+        }
+        if escapes {
+            push_unescaped(&mut output, word);
+        } else {
+            output.push_str(word);
+        }
+    }
+    if newline {
+        output.push('\n');
+    }
+    let _ = ctx.stdout().write_str(&output);
+}
+
+fn push_unescaped(output: &mut String, word: &str) {
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            output.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => output.push('\n'),
+            Some('t') => output.push('\t'),
+            Some('\\') => output.push('\\'),
+            // anything else is left alone
+            Some(other) => {
+                output.push('\\');
+                output.push(other);
+            }
+            None => output.push('\\'),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::rush::parser::execute;
+    use crate::rush::parser::test::{read_to_string, test_context, test_fs};
+
+    #[test]
+    fn echo() {
+        let fs = test_fs();
+        let ctx = test_context(&fs);
+        let echo = |line: &str| {
+            execute(&ctx, &alloc::format!("{line} > /tmp/out"));
+            read_to_string(&ctx, "/tmp/out")
+        };
+        assert_eq!(echo("echo"), "\n");
+        assert_eq!(echo("echo hello   world"), "hello world\n");
+        assert_eq!(echo("echo -n hello world"), "hello world");
+        assert_eq!(echo(r"echo a\tb\n"), "a\\tb\\n\n");
+        assert_eq!(echo(r"echo -e a\tb\nc\\d\q"), "a\tb\nc\\d\\q\n");
+        assert_eq!(echo(r"echo -ne x\n"), "x\n");
+        assert_eq!(echo("echo -n -e -x -n"), "-x -n");
+        assert_eq!(echo("echo - -"), "- -\n");
+    }
+}
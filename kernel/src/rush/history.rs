@@ -0,0 +1,42 @@
+use alloc::collections::VecDeque;
+use alloc::string::String;
+
+/// How many lines the shell remembers.
+pub const HISTORY_SIZE: usize = 64;
+
+/// A ring buffer of the most recently entered lines.
+///
+/// Once it's full, recording a new line forgets the oldest one.
+pub struct History {
+    entries: VecDeque<String>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Record `line`, unless it's blank or the same as the last line recorded.
+    pub fn push(&mut self, line: &str) {
+        if self.capacity == 0
+            || line.trim().is_empty()
+            || self.entries.back().map(String::as_str) == Some(line)
+        {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(line.into());
+    }
+
+    /// The line entered `age` lines ago, where 0 is the most recent.
+    pub fn get(&self, age: usize) -> Option<&str> {
+        let index = self.entries.len().checked_sub(age + 1)?;
+        Some(&self.entries[index])
+    }
+}
@@ -0,0 +1,338 @@
+use crate::fs::tty::Console;
+use crate::rush::completion::{common_prefix, split_word};
+use crate::rush::history::{History, HISTORY_SIZE};
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::mem;
+
+/// Ctrl-C
+const INTR: u8 = 0x03;
+/// Escape, which starts the sequences sent by the arrow keys (e.g. `ESC [ A` for up)
+const ESC: u8 = 0x1B;
+
+/// What happened to the line being typed.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Edit {
+    /// Enter was pressed.
+    Line(String),
+    /// Ctrl-C threw the line away.
+    Cancel,
+    /// Pressing Tab twice listed the possible completions below the line, so the prompt and the
+    /// line need to be displayed again, with [`LineEditor::redraw`].
+    Listed,
+}
+
+/// Finds the names which could complete the last part of a word, e.g. `foo` and `foobar/` for
+/// `/tmp/fo` (see [`complete`](crate::rush::completion::complete)).
+pub type Completer = Box<dyn Fn(&str) -> Vec<String>>;
+
+/// How far through an escape sequence we are.
+enum Escape {
+    None,
+    /// After `ESC`
+    Started,
+    /// After `ESC [`, waiting for the final byte
+    Csi,
+}
+
+/// The shell's line editor, used with the terminal in raw mode.
+///
+/// Typed characters are echoed, and backspace works as it does in canonical mode. The up and down
+/// arrows go back and forth through the [`History`], replacing the line being typed so that it
+/// can be edited before pressing Enter. Tab completes the word being typed as far as possible, and
+/// pressing it again lists the candidates.
+pub struct LineEditor {
+    line: Vec<u8>,
+    history: History,
+    /// How many lines back in the history the current line came from, while using the arrows
+    browsing: Option<usize>,
+    /// The line being typed before going back through the history
+    draft: Vec<u8>,
+    escape: Escape,
+    completer: Completer,
+    /// Whether the last key pressed was Tab
+    tabbed: bool,
+}
+
+impl Default for LineEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LineEditor {
+    /// A line editor without Tab completion.
+    pub fn new() -> Self {
+        Self::with_completer(Box::new(|_| Vec::new()))
+    }
+
+    pub fn with_completer(completer: Completer) -> Self {
+        Self {
+            line: Vec::new(),
+            history: History::new(HISTORY_SIZE),
+            browsing: None,
+            draft: Vec::new(),
+            escape: Escape::None,
+            completer,
+            tabbed: false,
+        }
+    }
+
+    /// Handle a byte of input, echoing to `console`.
+    ///
+    /// Returns the line once it's finished.
+    pub fn feed(&mut self, c: u8, console: &mut dyn Console) -> Option<Edit> {
+        let tabbed = mem::replace(&mut self.tabbed, c == b'\t');
+        match self.escape {
+            Escape::Started => {
+                self.escape = if c == b'[' { Escape::Csi } else { Escape::None };
+                return None;
+            }
+            Escape::Csi => {
+                // parameters come before the final byte, but we don't use any
+                if (0x40..=0x7E).contains(&c) {
+                    self.escape = Escape::None;
+                    match c {
+                        b'A' => self.older(console),
+                        b'B' => self.newer(console),
+                        _ => {}
+                    }
+                }
+                return None;
+            }
+            Escape::None => {}
+        }
+
+        match c {
+            b'\r' | b'\n' => {
+                let _ = console.write(b"\n");
+                let line = String::from_utf8_lossy(&mem::take(&mut self.line)).into_owned();
+                self.history.push(&line);
+                self.browsing = None;
+                return Some(Edit::Line(line));
+            }
+            // BS (Backspace) or DEL (Delete)
+            0x08 | 0x7F => {
+                if self.line.pop().is_some() {
+                    console.backspace();
+                }
+            }
+            INTR => {
+                let _ = console.write(b"^C\n");
+                self.line.clear();
+                self.browsing = None;
+                return Some(Edit::Cancel);
+            }
+            ESC => self.escape = Escape::Started,
+            b'\t' => return self.complete(tabbed, console),
+            // ignore any other control characters
+            c if c < 0x20 => {}
+            _ => {
+                self.line.push(c);
+                let _ = console.write(&[c]);
+            }
+        }
+        None
+    }
+
+    /// Display the line again, after the prompt.
+    pub fn redraw(&self, console: &mut dyn Console) {
+        let _ = console.write(&self.line);
+    }
+
+    /// Tab: complete the last word on the line as far as all the candidates agree, or list them
+    /// if there's nothing to add and Tab was pressed twice.
+    fn complete(&mut self, tabbed: bool, console: &mut dyn Console) -> Option<Edit> {
+        let start = self
+            .line
+            .iter()
+            .rposition(|&c| c == b' ')
+            .map_or(0, |i| i + 1);
+        let word = String::from_utf8_lossy(&self.line[start..]).into_owned();
+        let names = (self.completer)(&word);
+        let (_, typed) = split_word(&word);
+        let common = common_prefix(&names);
+
+        let mut rest = Vec::from(common.strip_prefix(typed)?);
+        // a complete file name is followed by the next argument, but a directory could be
+        // followed by a name in it
+        if names.len() == 1 && !common.ends_with('/') {
+            rest.push(b' ');
+        }
+        if !rest.is_empty() {
+            let _ = console.write(&rest);
+            self.line.extend(rest);
+        } else if tabbed && names.len() > 1 {
+            let _ = console.write(b"\n");
+            let _ = console.write(names.join("  ").as_bytes());
+            let _ = console.write(b"\n");
+            return Some(Edit::Listed);
+        }
+        None
+    }
+
+    /// Up arrow: go back to the previous line in the history.
+    fn older(&mut self, console: &mut dyn Console) {
+        let age = self.browsing.map_or(0, |age| age + 1);
+        let Some(entry) = self.history.get(age) else {
+            return;
+        };
+        let entry = entry.as_bytes().to_vec();
+        if self.browsing.is_none() {
+            self.draft = mem::take(&mut self.line);
+        }
+        self.browsing = Some(age);
+        self.replace_line(entry, console);
+    }
+
+    /// Down arrow: go forward to the next line in the history, and eventually back to the line
+    /// that was being typed.
+    fn newer(&mut self, console: &mut dyn Console) {
+        let line = match self.browsing {
+            None => return,
+            Some(0) => {
+                self.browsing = None;
+                mem::take(&mut self.draft)
+            }
+            Some(age) => {
+                self.browsing = Some(age - 1);
+                self.history
+                    .get(age - 1)
+                    .unwrap_or_default()
+                    .as_bytes()
+                    .to_vec()
+            }
+        };
+        self.replace_line(line, console);
+    }
+
+    fn replace_line(&mut self, line: Vec<u8>, console: &mut dyn Console) {
+        for _ in self.line.drain(..) {
+            console.backspace();
+        }
+        let _ = console.write(&line);
+        self.line = line;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fs::tty::test::BufferConsole;
+
+    fn type_keys(editor: &mut LineEditor, console: &mut BufferConsole, keys: &[u8]) -> Vec<Edit> {
+        keys.iter()
+            .filter_map(|&c| editor.feed(c, console))
+            .collect()
+    }
+
+    const UP: &[u8] = b"\x1b[A";
+    const DOWN: &[u8] = b"\x1b[B";
+
+    fn line(s: &str) -> Edit {
+        Edit::Line(s.into())
+    }
+
+    #[test]
+    fn up_arrow_recalls_history() {
+        let mut editor = LineEditor::new();
+        let mut console = BufferConsole::default();
+        let entered = type_keys(&mut editor, &mut console, b"ls\rpwd\rpwd\r\recho hi\r");
+        assert_eq!(
+            entered,
+            [
+                line("ls"),
+                line("pwd"),
+                line("pwd"),
+                line(""),
+                line("echo hi")
+            ]
+        );
+
+        // each press goes back one line, skipping the repeated `pwd` and the blank line
+        console.0.lock().clear();
+        type_keys(&mut editor, &mut console, UP);
+        assert_eq!(&console.0.lock()[..], b"echo hi");
+        type_keys(&mut editor, &mut console, UP);
+        assert_eq!(&console.0.lock()[..], b"pwd");
+        type_keys(&mut editor, &mut console, UP);
+        assert_eq!(&console.0.lock()[..], b"ls");
+        // there's nothing older
+        type_keys(&mut editor, &mut console, UP);
+        assert_eq!(&console.0.lock()[..], b"ls");
+
+        // the recalled line can be edited before entering it
+        let entered = type_keys(&mut editor, &mut console, b" /tmp\r");
+        assert_eq!(entered, [line("ls /tmp")]);
+        type_keys(&mut editor, &mut console, UP);
+        type_keys(&mut editor, &mut console, UP);
+        let entered = type_keys(&mut editor, &mut console, b"\r");
+        assert_eq!(entered, [line("echo hi")]);
+    }
+
+    #[test]
+    fn down_arrow_returns_to_draft() {
+        let mut editor = LineEditor::new();
+        let mut console = BufferConsole::default();
+        type_keys(&mut editor, &mut console, b"one\rtwo\r");
+        console.0.lock().clear();
+
+        type_keys(&mut editor, &mut console, b"thr");
+        type_keys(&mut editor, &mut console, UP);
+        type_keys(&mut editor, &mut console, UP);
+        assert_eq!(&console.0.lock()[..], b"one");
+        type_keys(&mut editor, &mut console, DOWN);
+        assert_eq!(&console.0.lock()[..], b"two");
+        type_keys(&mut editor, &mut console, DOWN);
+        assert_eq!(&console.0.lock()[..], b"thr");
+        // down does nothing once we're back
+        type_keys(&mut editor, &mut console, DOWN);
+        let entered = type_keys(&mut editor, &mut console, b"ee\r");
+        assert_eq!(entered, [line("three")]);
+    }
+
+    #[test]
+    fn tab_completion() {
+        let mut editor = LineEditor::with_completer(Box::new(|word| {
+            let (_, name) = split_word(word);
+            ["bar", "foo", "foobar/"]
+                .into_iter()
+                .filter(|candidate| candidate.starts_with(name))
+                .map(String::from)
+                .collect()
+        }));
+        let mut console = BufferConsole::default();
+
+        // `fo` could be `foo` or `foobar/`, so it only completes as far as `foo`
+        assert!(type_keys(&mut editor, &mut console, b"cat /tmp/fo\t").is_empty());
+        assert_eq!(&console.0.lock()[..], b"cat /tmp/foo");
+        // a second Tab lists them
+        let listed = type_keys(&mut editor, &mut console, b"\t");
+        assert_eq!(listed, [Edit::Listed]);
+        assert_eq!(&console.0.lock()[..], b"cat /tmp/foo\nfoo  foobar/\n");
+        console.0.lock().clear();
+        editor.redraw(&mut console);
+        assert_eq!(&console.0.lock()[..], b"cat /tmp/foo");
+
+        // once there's only one candidate, it's completed in full
+        let entered = type_keys(&mut editor, &mut console, b"b\tbaz\r");
+        assert_eq!(entered, [line("cat /tmp/foobar/baz")]);
+        let entered = type_keys(&mut editor, &mut console, b"b\tx\r");
+        assert_eq!(entered, [line("bar x")]);
+        // nothing matches
+        let entered = type_keys(&mut editor, &mut console, b"ls q\t\t\r");
+        assert_eq!(entered, [line("ls q")]);
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let mut history = History::new(2);
+        history.push("a");
+        history.push("b");
+        history.push("c");
+        assert_eq!(history.get(0), Some("c"));
+        assert_eq!(history.get(1), Some("b"));
+        assert_eq!(history.get(2), None);
+    }
+}
@@ -1,7 +1,8 @@
+use crate::rush::context::Context;
 use crate::rush::ls::ls_config::LsConfig;
-use kidneyos_shared::println;
+use core::fmt::Write;
 
-pub fn list(dir: &str, config: LsConfig) {
-    println!("Listing directory: {}", dir);
-    println!("Config: {}", config);
+pub fn list(ctx: &Context, dir: &str, config: LsConfig) {
+    let _ = writeln!(ctx.stdout(), "Listing directory: {}", dir);
+    let _ = writeln!(ctx.stdout(), "Config: {}", config);
 }
@@ -0,0 +1,71 @@
+use crate::rush::args::split_flags;
+use crate::rush::context::Context;
+use crate::vfs::{Error, Result};
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+/// Create each directory in `args`.
+///
+/// With `-p`, missing parent directories are created too, and it's not an error for a directory
+/// to exist already.
+pub fn mkdir(ctx: &Context, args: Vec<&str>) {
+    let Some((flags, paths)) = split_flags(ctx, "mkdir", &args, "p") else {
+        return;
+    };
+    if paths.is_empty() {
+        let _ = writeln!(ctx.stderr(), "rush: mkdir: missing operand");
+        return;
+    }
+    let parents = flags.contains('p');
+    for path in paths {
+        let result = if parents {
+            mkdir_parents(ctx, path)
+        } else {
+            ctx.mkdir(path.trim_end_matches('/'))
+        };
+        if let Err(e) = result {
+            let _ = writeln!(
+                ctx.stderr(),
+                "rush: mkdir: cannot create directory '{}': {}",
+                path,
+                e
+            );
+        }
+    }
+}
+
+fn mkdir_parents(ctx: &Context, path: &str) -> Result<()> {
+    // create each prefix of the path in turn, e.g. `a`, then `a/b`, then `a/b/c`
+    for (i, c) in path.char_indices().chain([(path.len(), '/')]) {
+        let prefix = &path[..i];
+        if c != '/' || prefix.is_empty() || prefix.ends_with('/') {
+            continue;
+        }
+        match ctx.mkdir(prefix) {
+            Ok(()) | Err(Error::Exists) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::rush::parser::execute;
+    use crate::rush::parser::test::{exists, test_context, test_fs};
+
+    #[test]
+    fn mkdir_parents() {
+        let fs = test_fs();
+        let ctx = test_context(&fs);
+        execute(&ctx, "mkdir /tmp/a/b");
+        assert!(!exists(&ctx, "/tmp/a"));
+        execute(&ctx, "mkdir -p /tmp/a/b/c/");
+        assert!(exists(&ctx, "/tmp/a/b/c"));
+        // relative to the working directory, and fine if it already exists
+        execute(&ctx, "mkdir -p tmp/a/b/c tmp/a/d");
+        assert!(exists(&ctx, "/tmp/a/d"));
+        execute(&ctx, "mkdir /tmp/a/b/c/e");
+        assert!(exists(&ctx, "/tmp/a/b/c/e"));
+    }
+}
@@ -1,7 +1,17 @@
+mod args;
+mod cat;
 mod cd;
 mod clear;
+mod completion;
+mod context;
+mod echo;
 mod env;
+mod history;
+mod line_editor;
 mod ls;
+mod mkdir;
 mod parser;
 mod pwd;
+mod rm;
+mod rmdir;
 pub mod rush_core;
@@ -1,26 +1,241 @@
+use crate::fs::FileDescriptor;
+use crate::rush::cat::cat;
 use crate::rush::cd::cd;
 use crate::rush::clear::clear;
+use crate::rush::context::{Context, STDIN, STDOUT};
+use crate::rush::echo::echo;
 use crate::rush::env::CURR_DIR;
 use crate::rush::ls::ls_config::LsConfig;
 use crate::rush::ls::ls_core::list;
+use crate::rush::mkdir::mkdir;
 use crate::rush::pwd::pwd;
-use alloc::string::ToString;
+use crate::rush::rm::rm;
+use crate::rush::rmdir::rmdir;
+use crate::rush::rush_core::run_program;
+use alloc::string::{String, ToString};
 use alloc::vec::Vec;
-use kidneyos_shared::eprintln;
+use alloc::{format, vec};
+use core::fmt::{Display, Write};
 use kidneyos_syscalls::exit;
 
+/// What a redirection does with its file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RedirectKind {
+    /// `< file`: read standard input from the file
+    Input,
+    /// `> file`: write standard output to the file, replacing its contents
+    Truncate,
+    /// `>> file`: write standard output to the end of the file
+    Append,
+}
+
+impl Display for RedirectKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            RedirectKind::Input => write!(f, "<"),
+            RedirectKind::Truncate => write!(f, ">"),
+            RedirectKind::Append => write!(f, ">>"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Redirect<'a> {
+    pub kind: RedirectKind,
+    pub path: &'a str,
+}
+
+/// A parsed command line.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Command<'a> {
+    /// The command name, followed by its arguments
+    pub args: Vec<&'a str>,
+    /// Redirections, in the order they appear
+    pub redirects: Vec<Redirect<'a>>,
+}
+
+enum Token<'a> {
+    Word(&'a str),
+    Redirect(RedirectKind),
+    Pipe,
+}
+
+impl Display for Token<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{word}"),
+            Token::Redirect(kind) => write!(f, "{kind}"),
+            Token::Pipe => write!(f, "|"),
+        }
+    }
+}
+
+fn unexpected(token: Option<Token>) -> String {
+    match token {
+        Some(token) => format!("syntax error near unexpected token `{token}'"),
+        None => "syntax error near unexpected token `newline'".to_string(),
+    }
+}
+
+fn tokenize(input: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut rest = input;
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return tokens;
+        }
+        // operators don't need to be separated from words by spaces (e.g. `ls >out`)
+        if let Some(after) = rest.strip_prefix('|') {
+            tokens.push(Token::Pipe);
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix(">>") {
+            tokens.push(Token::Redirect(RedirectKind::Append));
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('>') {
+            tokens.push(Token::Redirect(RedirectKind::Truncate));
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('<') {
+            tokens.push(Token::Redirect(RedirectKind::Input));
+            rest = after;
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '|'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Word(&rest[..end]));
+            rest = &rest[end..];
+        }
+    }
+}
+
+/// Split a command line into a pipeline of commands, each with its arguments and redirections.
+///
+/// Returns an error message if a redirection isn't followed by a file name, or if one of the
+/// commands in a pipeline is empty.
+pub fn parse(input: &str) -> Result<Vec<Command>, String> {
+    let mut pipeline = vec![Command::default()];
+    let mut tokens = tokenize(input).into_iter();
+    while let Some(token) = tokens.next() {
+        let command = pipeline.last_mut().unwrap();
+        match token {
+            Token::Word(word) => command.args.push(word),
+            Token::Redirect(kind) => match tokens.next() {
+                Some(Token::Word(path)) => command.redirects.push(Redirect { kind, path }),
+                next => return Err(unexpected(next)),
+            },
+            Token::Pipe => {
+                if *command == Command::default() {
+                    return Err(unexpected(Some(Token::Pipe)));
+                }
+                pipeline.push(Command::default());
+            }
+        }
+    }
+    if pipeline.len() > 1 && *pipeline.last().unwrap() == Command::default() {
+        return Err(unexpected(None));
+    }
+    Ok(pipeline)
+}
+
 pub(crate) fn parse_input(input: &str) {
-    let mut tokens = input.split_whitespace();
-    let command = tokens.next().unwrap_or("");
-    let args = tokens.collect::<Vec<&str>>();
+    execute(&Context::current(), input);
+}
+
+/// Parse and run a command line.
+pub(crate) fn execute(ctx: &Context, input: &str) {
+    if let Err(message) = parse(input).and_then(|pipeline| run_pipeline(ctx, &pipeline)) {
+        let _ = writeln!(ctx.stderr(), "rush: {message}");
+    }
+}
+
+/// Run each command in `pipeline`, with a pipe from each one's standard output to the next one's
+/// standard input.
+///
+/// Builtins run in the shell itself, which can't fork, so the commands run one after another:
+/// each one writes all of its output into the pipe before the next one starts reading.
+fn run_pipeline(ctx: &Context, pipeline: &[Command]) -> Result<(), String> {
+    let mut read_end = None;
+    for (i, command) in pipeline.iter().enumerate() {
+        let stdin = read_end.take();
+        let mut stdout = None;
+        if i + 1 < pipeline.len() {
+            match ctx.pipe() {
+                Ok((read, write)) => {
+                    read_end = Some(read);
+                    stdout = Some(write);
+                }
+                Err(e) => {
+                    if let Some(fd) = stdin {
+                        ctx.close(fd);
+                    }
+                    return Err(format!("pipe: {e}"));
+                }
+            }
+        }
+        if let Err(message) = run_stage(ctx, command, stdin, stdout) {
+            if let Some(fd) = read_end {
+                ctx.close(fd);
+            }
+            return Err(message);
+        }
+    }
+    Ok(())
+}
+
+/// Run `command` with its standard input and output replaced by `stdin` and `stdout` (if given),
+/// and then by its own redirections. `stdin` and `stdout` are closed afterwards.
+fn run_stage(
+    ctx: &Context,
+    command: &Command,
+    stdin: Option<FileDescriptor>,
+    stdout: Option<FileDescriptor>,
+) -> Result<(), String> {
+    let mut saved = Vec::new();
+    let mut result = Ok(());
+    for (target, file) in [(STDIN, stdin), (STDOUT, stdout)] {
+        let Some(file) = file else { continue };
+        if result.is_err() {
+            ctx.close(file);
+            continue;
+        }
+        match ctx.replace(target, file) {
+            Ok(fd) => saved.push(fd),
+            Err(e) => result = Err(format!("{e}")),
+        }
+    }
+    if result.is_ok() {
+        for redirect in &command.redirects {
+            match ctx.redirect(redirect) {
+                Ok(fd) => saved.push(fd),
+                Err(e) => {
+                    result = Err(format!("{}: {e}", redirect.path));
+                    break;
+                }
+            }
+        }
+    }
+    if result.is_ok() {
+        run(ctx, &command.args);
+    }
+    ctx.restore_all(saved);
+    result
+}
+
+fn run(ctx: &Context, args: &[&str]) {
+    let Some((&command, args)) = args.split_first() else {
+        // nothing to run (but any redirections have still been done, like `> file`)
+        return;
+    };
+    let args = args.to_vec();
 
     match command {
         "cat" => {
-            // print the contents of a file
+            // print the contents of files, or standard input
+            cat(ctx, args);
         }
         "cd" => {
             // change directory
-            cd(args);
+            cd(ctx, args);
         }
         "clear" => {
             // clear the screen
@@ -28,6 +243,7 @@ pub(crate) fn parse_input(input: &str) {
         }
         "echo" => {
             // print the arguments
+            echo(ctx, args);
         }
         "exit" => {
             exit(0);
@@ -35,15 +251,168 @@ pub(crate) fn parse_input(input: &str) {
         "ls" => {
             let config = LsConfig::from_args(args);
             let curr_dir = CURR_DIR.read().to_string();
-            list(curr_dir.as_ref(), config);
+            list(ctx, curr_dir.as_ref(), config);
+        }
+        "mkdir" => {
+            // create directories
+            mkdir(ctx, args);
         }
         "pwd" => {
             // print working directory
-            pwd();
+            pwd(ctx);
+        }
+        "rm" => {
+            // remove files
+            rm(ctx, args);
+        }
+        "rmdir" => {
+            // remove empty directories
+            rmdir(ctx, args);
         }
         _ => {
-            // command not found
-            eprintln!("rush: {}: command not found", command);
+            // run the program at that path, if there is one
+            if let Err(message) = run_program(ctx, command) {
+                let _ = writeln!(ctx.stderr(), "rush: {message}");
+            }
         }
     }
 }
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::fs::fs_manager::{Mode, RootFileSystem};
+    use crate::fs::ProcessFileDescriptor;
+    use crate::sync::mutex::Mutex;
+    use crate::threading::thread_control_block::ProcessControlBlock;
+    use crate::vfs::tempfs::TempFS;
+    use alloc::sync::Arc;
+    use alloc::vec;
+
+    /// A file system with an empty `/tmp`, for running commands in.
+    pub fn test_fs() -> Mutex<RootFileSystem> {
+        let fs = Mutex::new(RootFileSystem::new());
+        fs.lock().mount_root(TempFS::new()).unwrap();
+        fs
+    }
+
+    /// A context for running commands as a process whose standard input and output are
+    /// `/dev/null`.
+    pub fn test_context(fs: &Mutex<RootFileSystem>) -> Context {
+        let pcb = ProcessControlBlock::for_test(1, fs.lock().get_root().unwrap());
+        let ctx = Context::new(fs, Arc::new(Mutex::new(pcb)));
+        let mut root = fs.lock();
+        for _ in 0..3 {
+            root.open_null(1).unwrap();
+        }
+        root.mkdir(&ctx.process.lock(), "/tmp").unwrap();
+        drop(root);
+        ctx
+    }
+
+    pub fn exists(ctx: &Context, path: &str) -> bool {
+        ctx.open(path, Mode::ReadWrite)
+            .map(|fd| ctx.close(fd))
+            .is_ok()
+    }
+
+    pub fn read_to_string(ctx: &Context, path: &str) -> String {
+        let pid = ctx.process.lock().pid;
+        let fd = ctx
+            .fs
+            .lock()
+            .open(&ctx.process.lock(), path, Mode::ReadWrite)
+            .unwrap();
+        let fd = ProcessFileDescriptor { pid, fd };
+        let mut contents = Vec::new();
+        let mut buf = [0; 64];
+        loop {
+            let n = RootFileSystem::read(ctx.fs, fd, &mut buf).unwrap();
+            if n == 0 {
+                break;
+            }
+            contents.extend_from_slice(&buf[..n]);
+        }
+        ctx.fs.lock().close(fd).unwrap();
+        String::from_utf8(contents).unwrap()
+    }
+
+    #[test]
+    fn parse_redirects() {
+        let pipeline = parse("ls -l>out  >> log <in").unwrap();
+        assert_eq!(pipeline.len(), 1);
+        let command = &pipeline[0];
+        assert_eq!(command.args, ["ls", "-l"]);
+        assert_eq!(
+            command.redirects,
+            [
+                Redirect {
+                    kind: RedirectKind::Truncate,
+                    path: "out"
+                },
+                Redirect {
+                    kind: RedirectKind::Append,
+                    path: "log"
+                },
+                Redirect {
+                    kind: RedirectKind::Input,
+                    path: "in"
+                },
+            ]
+        );
+        assert_eq!(
+            parse("pwd >").unwrap_err(),
+            "syntax error near unexpected token `newline'"
+        );
+        assert_eq!(
+            parse("pwd > >> out").unwrap_err(),
+            "syntax error near unexpected token `>>'"
+        );
+    }
+
+    #[test]
+    fn redirect_builtin_output() {
+        let fs = test_fs();
+        let ctx = test_context(&fs);
+        execute(&ctx, "pwd > /tmp/out");
+        assert_eq!(read_to_string(&ctx, "/tmp/out"), "/\n");
+        execute(&ctx, "pwd >>/tmp/out");
+        assert_eq!(read_to_string(&ctx, "/tmp/out"), "/\n/\n");
+        execute(&ctx, "pwd > /tmp/out");
+        // the shell's own output goes back where it was afterwards
+        execute(&ctx, "pwd");
+        assert_eq!(read_to_string(&ctx, "/tmp/out"), "/\n");
+        // reading from a file which doesn't exist fails before the command runs
+        execute(&ctx, "pwd < /tmp/missing > /tmp/out");
+        assert_eq!(read_to_string(&ctx, "/tmp/out"), "/\n");
+    }
+
+    #[test]
+    fn parse_pipeline() {
+        let pipeline = parse("ls|cat > out | cat").unwrap();
+        let args: Vec<_> = pipeline
+            .iter()
+            .map(|command| command.args.clone())
+            .collect();
+        assert_eq!(args, [vec!["ls"], vec!["cat"], vec!["cat"]]);
+        assert_eq!(pipeline[1].redirects.len(), 1);
+        assert_eq!(
+            parse("| cat").unwrap_err(),
+            "syntax error near unexpected token `|'"
+        );
+        assert_eq!(
+            parse("pwd |").unwrap_err(),
+            "syntax error near unexpected token `newline'"
+        );
+    }
+
+    #[test]
+    fn pipeline_of_builtins() {
+        let fs = test_fs();
+        let ctx = test_context(&fs);
+        execute(&ctx, "pwd | cat > /tmp/out");
+        assert_eq!(read_to_string(&ctx, "/tmp/out"), "/\n");
+        execute(&ctx, "cat < /tmp/out | cat | cat >> /tmp/out");
+        assert_eq!(read_to_string(&ctx, "/tmp/out"), "/\n/\n");
+    }
+}
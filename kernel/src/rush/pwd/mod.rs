@@ -1,7 +1,7 @@
-use crate::system::running_process;
-use kidneyos_shared::println;
+use crate::rush::context::Context;
+use core::fmt::Write;
 
-pub fn pwd() {
-    let curr_path = running_process().lock().cwd_path.clone();
-    println!("{}", curr_path);
+pub fn pwd(ctx: &Context) {
+    let curr_path = ctx.process.lock().cwd_path.clone();
+    let _ = writeln!(ctx.stdout(), "{}", curr_path);
 }
@@ -0,0 +1,88 @@
+use crate::fs::fs_manager::Mode;
+use crate::rush::args::split_flags;
+use crate::rush::context::Context;
+use crate::vfs::{Error, INodeType, Result};
+use alloc::format;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+/// Remove each file in `args`.
+///
+/// With `-r` (or `-R`), directories are removed along with everything in them.
+pub fn rm(ctx: &Context, args: Vec<&str>) {
+    let Some((flags, paths)) = split_flags(ctx, "rm", &args, "rR") else {
+        return;
+    };
+    if paths.is_empty() {
+        let _ = writeln!(ctx.stderr(), "rush: rm: missing operand");
+        return;
+    }
+    let recursive = flags.contains('r') || flags.contains('R');
+    for path in paths {
+        let path = path.trim_end_matches('/');
+        let result = match ctx.unlink(path) {
+            Err(Error::IsDirectory) if recursive => remove_tree(ctx, path),
+            result => result,
+        };
+        if let Err(e) = result {
+            let _ = writeln!(ctx.stderr(), "rush: rm: cannot remove '{}': {}", path, e);
+        }
+    }
+}
+
+/// Remove the directory `dir` and everything in it.
+fn remove_tree(ctx: &Context, dir: &str) -> Result<()> {
+    let fd = ctx.open(dir, Mode::ReadWrite)?;
+    let entries = ctx.readdir(fd);
+    ctx.close(fd);
+    for entry in entries? {
+        if entry.name == "." || entry.name == ".." {
+            continue;
+        }
+        let path = format!("{}/{}", dir, entry.name);
+        // symbolic links are removed rather than followed, so we never leave the tree
+        if entry.r#type == INodeType::Directory {
+            remove_tree(ctx, &path)?;
+        } else {
+            ctx.unlink(&path)?;
+        }
+    }
+    ctx.rmdir(dir)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::rush::parser::execute;
+    use crate::rush::parser::test::{exists, test_context, test_fs};
+
+    #[test]
+    fn rm_file() {
+        let fs = test_fs();
+        let ctx = test_context(&fs);
+        execute(&ctx, "echo hi > /tmp/file");
+        assert!(exists(&ctx, "/tmp/file"));
+        execute(&ctx, "rm /tmp/file");
+        assert!(!exists(&ctx, "/tmp/file"));
+        assert!(exists(&ctx, "/tmp"));
+    }
+
+    #[test]
+    fn rm_recursive() {
+        let fs = test_fs();
+        let ctx = test_context(&fs);
+        execute(&ctx, "mkdir -p /tmp/tree/a/b /tmp/tree/c");
+        execute(&ctx, "echo 1 > /tmp/tree/a/b/file");
+        execute(&ctx, "echo 2 > /tmp/tree/file");
+        // directories aren't removed without -r
+        execute(&ctx, "rm /tmp/tree");
+        execute(&ctx, "rmdir /tmp/tree");
+        assert!(exists(&ctx, "/tmp/tree/a/b/file"));
+        execute(&ctx, "rm -r /tmp/tree");
+        assert!(!exists(&ctx, "/tmp/tree"));
+        assert!(exists(&ctx, "/tmp"));
+        // rmdir only removes empty directories
+        execute(&ctx, "mkdir /tmp/empty");
+        execute(&ctx, "rmdir /tmp/empty");
+        assert!(!exists(&ctx, "/tmp/empty"));
+    }
+}
@@ -0,0 +1,21 @@
+use crate::rush::context::Context;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+/// Remove each of the empty directories in `args`.
+pub fn rmdir(ctx: &Context, args: Vec<&str>) {
+    if args.is_empty() {
+        let _ = writeln!(ctx.stderr(), "rush: rmdir: missing operand");
+        return;
+    }
+    for path in args {
+        if let Err(e) = ctx.rmdir(path.trim_end_matches('/')) {
+            let _ = writeln!(
+                ctx.stderr(),
+                "rush: rmdir: failed to remove '{}': {}",
+                path,
+                e
+            );
+        }
+    }
+}
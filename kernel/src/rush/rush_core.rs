@@ -1,65 +1,107 @@
+use crate::fs::tty::Tty;
+use crate::rush::completion::complete;
+use crate::rush::context::Context;
 use crate::rush::env::{CURR_DIR, HOST_NAME};
+use crate::rush::line_editor::{Edit, LineEditor};
 use crate::rush::parser::parse_input;
-use crate::sync::mutex::Mutex;
-use crate::system::unwrap_system;
+use crate::system::{running_process, unwrap_system};
+use crate::threading::process::Pid;
+use crate::threading::process_wait::wait_for_exit;
 use crate::threading::scheduling::scheduler_yield_and_continue;
+use crate::threading::thread_control_block::ThreadControlBlock;
+use crate::user_program::elf::Elf;
+use crate::user_program::job_control::deliver_interrupt;
+use crate::vfs::Error;
+use alloc::boxed::Box;
+use alloc::format;
 use alloc::string::String;
 use core::sync::atomic::AtomicBool;
 use core::sync::atomic::Ordering::SeqCst;
 use kidneyos_shared::print;
-use kidneyos_shared::video_memory::VIDEO_MEMORY_WRITER;
 
 pub static IS_SYSTEM_FULLY_INITIALIZED: AtomicBool = AtomicBool::new(false);
 
-static BUFFER: Mutex<String> = Mutex::new(String::new());
-static JUST_READ_LINE: AtomicBool = AtomicBool::new(false);
-
 pub extern "C" fn rush_loop() -> ! {
-    // initialize RUSH ----------------------------------------------------------------------------
-    unwrap_system()
-        .input_buffer
-        .lock()
-        .on_receive
-        .insert(0, |input| {
-            BUFFER.lock().push(input as char);
-
-            if input == 0x08 || input == 0x7F {
-                // BS (Backspace) or DEL (Delete)
-                let mut buffer = BUFFER.lock();
-                buffer.pop(); // BS or DEL
-
-                // Remove the previous character
-                if !buffer.is_empty() {
-                    buffer.pop();
-                    unsafe { VIDEO_MEMORY_WRITER.backspace() };
-                }
-            } else if input != b'\r' {
-                print!("{}", input as char);
-            } else {
-                print!("\n");
-                JUST_READ_LINE.store(true, SeqCst);
-            }
-        });
-
     // Wait until the system is fully initialized to avoid weird display issues
     while !IS_SYSTEM_FULLY_INITIALIZED.load(SeqCst) {
         scheduler_yield_and_continue();
     }
 
+    let tty = &unwrap_system().tty;
+    // Builtins run in the shell itself, so Ctrl-C should stop them (e.g. `cat` reading the terminal)
+    let shell = running_process();
+    tty.set_foreground(Some(shell.lock().pgid));
+
     print_prompt(false);
+    let mut editor =
+        LineEditor::with_completer(Box::new(|word| complete(&Context::current(), word)));
     loop {
-        if JUST_READ_LINE.load(SeqCst) {
-            let mut buffer = BUFFER.lock();
-            buffer.pop(); // remove the newline character
-            parse_input(&buffer); // parse and execute the command
-            buffer.clear(); // clear the buffer
-            JUST_READ_LINE.store(false, SeqCst);
-
-            print_prompt(false);
+        // the line editor needs to see the arrow keys, so read the terminal in raw mode
+        tty.set_canonical(false);
+        let mut chunk = [0; 256];
+        let n = match tty.read(&mut chunk) {
+            Ok(n) => n,
+            Err(_) => {
+                // Ctrl-C was typed while a command was running in canonical mode. The shell
+                // itself doesn't exit when interrupted, so just start again.
+                deliver_interrupt();
+                shell.lock().interrupted = false;
+                continue;
+            }
+        };
+        for &c in &chunk[..n] {
+            match editor.feed(c, &mut &**tty) {
+                Some(Edit::Line(line)) => {
+                    // commands reading the terminal expect the usual line editing and Ctrl-C
+                    tty.set_canonical(true);
+                    parse_input(&line); // parse and execute the command
+                    print_prompt(false);
+                }
+                Some(Edit::Cancel) => print_prompt(false),
+                Some(Edit::Listed) => {
+                    print_prompt(false);
+                    editor.redraw(&mut &**tty);
+                }
+                None => {}
+            }
         }
+    }
+}
+
+/// Run the program at `path` as a job in a process group of its own, and wait for it to exit.
+///
+/// The job is the terminal's foreground process group until then, so Ctrl-C interrupts it rather
+/// than the shell.
+pub(crate) fn run_program(ctx: &Context, path: &str) -> Result<(), String> {
+    let data = match ctx.read_file(path) {
+        Ok(data) => data,
+        Err(Error::NotFound) => return Err(format!("{path}: command not found")),
+        Err(e) => return Err(format!("{path}: {e}")),
+    };
+    let elf = Elf::parse_bytes(&data).map_err(|e| format!("{path}: {e}"))?;
+    let system = unwrap_system();
+    let job = ThreadControlBlock::new_from_elf(elf, path, &system.process)
+        .map_err(|e| format!("{path}: {e:?}"))?;
+    let pid = job.pid;
+    if let Some(pcb) = system.process.table.get(pid) {
+        pcb.lock().pgid = pid;
+    }
+    system.threads.scheduler.lock().push(Box::new(job));
 
-        scheduler_yield_and_continue(); // Until we can read input
+    if in_foreground(&system.tty, pid, || wait_for_exit(pid)).is_some() {
+        system.process.table.remove(pid);
     }
+    Ok(())
+}
+
+/// Make `job` the terminal's foreground process group while `wait` waits for it, then give the
+/// terminal back to the group which had it before.
+fn in_foreground<T>(tty: &Tty, job: Pid, wait: impl FnOnce() -> T) -> T {
+    let shell = tty.foreground();
+    tty.set_foreground(Some(job));
+    let result = wait();
+    tty.set_foreground(shell);
+    result
 }
 
 fn print_prompt(is_root: bool) {
@@ -74,3 +116,38 @@ fn print_prompt(is_root: bool) {
         print!("$ ");
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fs::tty::test::BufferConsole;
+    use crate::threading::process::ProcessTable;
+    use crate::threading::thread_control_block::ProcessControlBlock;
+
+    #[test]
+    fn ctrl_c_interrupts_job_not_shell() {
+        let table = ProcessTable::default();
+        let group_leader = |pid| ProcessControlBlock {
+            pgid: pid,
+            ..ProcessControlBlock::for_test(pid, (0, 0))
+        };
+        let shell = table.add(group_leader(1));
+        let job = table.add(group_leader(2));
+        let tty = Tty::new(Box::<BufferConsole>::default());
+        tty.set_foreground(Some(1));
+
+        in_foreground(&tty, 2, || {
+            // Ctrl-C typed while the shell waits for the job
+            let pgid = tty
+                .try_receive([0x03].into_iter())
+                .flatten()
+                .expect("Ctrl-C should target the foreground group");
+            table.interrupt_group(pgid);
+        });
+
+        assert!(job.lock().interrupted);
+        assert!(!shell.lock().interrupted);
+        // the shell gets the terminal back
+        assert_eq!(tty.foreground(), Some(1));
+    }
+}
@@ -0,0 +1,282 @@
+//! Smoke tests run in the kernel at boot, for CI under QEMU.
+//!
+//! Booting with `selftest` on the kernel command line (see [`crate::cmdline`]) starts a kernel
+//! thread which runs each check, prints whether it passed, and then exits QEMU with the result
+//! through its `isa-debug-exit` device (see [`crate::drivers::qemu_exit`]). `make selftest` boots
+//! a self-test ISO this way.
+
+use crate::drivers::qemu_exit::{qemu_exit, QemuExitCode};
+use crate::fs::fs_manager::{Mode, RootFileSystem};
+use crate::fs::ProcessFileDescriptor;
+use crate::interrupts::{mutex_irq::hold_interrupts, IntrLevel};
+use crate::sync::mutex::Mutex;
+use crate::system::{running_process, running_thread_pid, unwrap_system};
+use crate::threading::process::Pid;
+use crate::threading::process_functions::exit_process;
+use crate::threading::process_wait::wait_for_exit;
+use crate::threading::scheduling::scheduler_yield_and_continue;
+use crate::threading::thread_control_block::{ProcessControlBlock, ThreadControlBlock};
+use crate::threading::thread_functions::ThreadFunction;
+use crate::vfs::tempfs::TempFS;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use kidneyos_shared::mem::PAGE_FRAME_SIZE;
+use kidneyos_shared::print;
+
+type Check = fn() -> Result<(), String>;
+
+const CHECKS: &[(&str, Check)] = &[
+    ("alloc", check_alloc),
+    ("tempfs", || {
+        check_tempfs(&unwrap_system().root_filesystem, &running_process())
+    }),
+    ("process", check_process),
+    ("threads", check_threads),
+];
+
+/// Run each of `checks`, writing a line saying whether it passed to `out`. Returns whether they
+/// all did.
+fn run_checks(checks: &[(&str, Check)], out: &mut impl Write) -> bool {
+    let mut passed = true;
+    for (name, check) in checks {
+        let _ = match check() {
+            Ok(()) => writeln!(out, "selftest: {name} ... ok"),
+            Err(message) => {
+                passed = false;
+                writeln!(out, "selftest: {name} ... FAILED: {message}")
+            }
+        };
+    }
+    let _ = writeln!(
+        out,
+        "selftest: {}",
+        if passed { "all passed" } else { "FAILED" }
+    );
+    passed
+}
+
+/// Writes to the screen and serial port.
+struct Console;
+
+impl Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        print!("{}", s);
+        Ok(())
+    }
+}
+
+/// The function run by the self-test thread: runs the checks, then exits QEMU. Without QEMU's exit
+/// device, the thread just exits, and the kernel carries on.
+pub extern "C" fn selftest_thread() -> i32 {
+    let passed = run_checks(CHECKS, &mut Console);
+    qemu_exit(if passed {
+        QemuExitCode::Success
+    } else {
+        QemuExitCode::Failure
+    });
+    i32::from(!passed)
+}
+
+/// Allocate memory of a range of sizes, from a few bytes to several pages, all live at once, and
+/// check none of it was overwritten by the others.
+fn check_alloc() -> Result<(), String> {
+    const SIZES: [usize; 5] = [1, 24, 256, 4000, 3 * PAGE_FRAME_SIZE];
+    let buffers: Vec<Vec<u8>> = (0..).zip(SIZES).map(|(i, size)| vec![i; size]).collect();
+    for (i, buffer) in (0..).zip(&buffers) {
+        if buffer.iter().any(|&byte| byte != i) {
+            return Err(format!("{} byte allocation was overwritten", buffer.len()));
+        }
+    }
+    let boxed = Box::new([0x5a_u8; 64]);
+    if boxed.iter().any(|&byte| byte != 0x5a) {
+        return Err("boxed array was overwritten".into());
+    }
+    Ok(())
+}
+
+/// Mount a TempFS in `root`, write a file to it and read it back, then unmount it again.
+fn check_tempfs(
+    root: &Mutex<RootFileSystem>,
+    process: &Mutex<ProcessControlBlock>,
+) -> Result<(), String> {
+    const DIR: &str = "/selftest";
+    const FILE: &str = "/selftest/file";
+    const CONTENTS: &[u8] = b"self-test data";
+
+    let pid = process.lock().pid;
+    let open = |mode| {
+        let fd = root.lock().open(&process.lock(), FILE, mode);
+        fd.map(|fd| ProcessFileDescriptor { pid, fd })
+            .map_err(|e| format!("open {FILE}: {e}"))
+    };
+    let close = |fd| {
+        root.lock()
+            .close(fd)
+            .map_err(|e| format!("close {FILE}: {e}"))
+    };
+
+    root.lock()
+        .mkdir(&process.lock(), DIR)
+        .map_err(|e| format!("mkdir {DIR}: {e}"))?;
+    root.lock()
+        .mount(&process.lock(), DIR, TempFS::new())
+        .map_err(|e| format!("mount {DIR}: {e}"))?;
+
+    let fd = open(Mode::CreateReadWrite)?;
+    let written =
+        RootFileSystem::write(root, fd, CONTENTS).map_err(|e| format!("write {FILE}: {e}"))?;
+    close(fd)?;
+    if written != CONTENTS.len() {
+        return Err(format!("only wrote {written} bytes to {FILE}"));
+    }
+
+    let fd = open(Mode::ReadWrite)?;
+    let mut buf = [0; CONTENTS.len() + 1];
+    let read = RootFileSystem::read(root, fd, &mut buf).map_err(|e| format!("read {FILE}: {e}"))?;
+    close(fd)?;
+    if buf[..read] != *CONTENTS {
+        return Err(format!("read {:?} back from {FILE}", &buf[..read]));
+    }
+
+    root.lock()
+        .unmount(&process.lock(), DIR)
+        .map_err(|e| format!("unmount {DIR}: {e}"))?;
+    root.lock()
+        .rmdir(&process.lock(), DIR)
+        .map_err(|e| format!("rmdir {DIR}: {e}"))
+}
+
+/// Start a new kernel process, running `function`, which should exit it with [`exit_process`].
+fn spawn(function: ThreadFunction) -> Pid {
+    let system = unwrap_system();
+    let thread = ThreadControlBlock::new_with_setup(
+        function,
+        true,
+        running_thread_pid(),
+        &mut system.root_filesystem.lock(),
+        &system.process,
+    );
+    let pid = thread.pid;
+    let _guard = hold_interrupts(IntrLevel::IntrOff);
+    system.threads.scheduler.lock().push(Box::new(thread));
+    pid
+}
+
+/// Wait for the process `pid` to exit, and reap it, returning its exit code.
+fn reap(pid: Pid) -> Result<i32, String> {
+    let pcb = wait_for_exit(pid).ok_or_else(|| format!("couldn't wait for process {pid}"))?;
+    let exit_code = pcb.lock().exit_code;
+    unwrap_system().process.table.remove(pid);
+    exit_code.ok_or_else(|| format!("process {pid} has no exit code"))
+}
+
+const CHILD_EXIT_CODE: i32 = 42;
+
+extern "C" fn exit_child() -> i32 {
+    exit_process(CHILD_EXIT_CODE)
+}
+
+/// Start a process which exits straight away, and check its exit code is seen by its parent.
+fn check_process() -> Result<(), String> {
+    match reap(spawn(exit_child))? {
+        CHILD_EXIT_CODE => Ok(()),
+        exit_code => Err(format!(
+            "child exited with {exit_code}, not {CHILD_EXIT_CODE}"
+        )),
+    }
+}
+
+/// How many times each thread in [`check_threads`] logs its name and yields.
+const STEPS: usize = 3;
+
+/// The names of the threads started by [`check_threads`], in the order they ran.
+static SCHEDULE_LOG: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+fn log_steps(name: u8) -> ! {
+    for _ in 0..STEPS {
+        SCHEDULE_LOG.lock().push(name);
+        scheduler_yield_and_continue();
+    }
+    exit_process(0)
+}
+
+extern "C" fn thread_a() -> i32 {
+    log_steps(b'a')
+}
+
+extern "C" fn thread_b() -> i32 {
+    log_steps(b'b')
+}
+
+/// Start two threads which take turns to run, and check they both got to run all the way through.
+fn check_threads() -> Result<(), String> {
+    SCHEDULE_LOG.lock().clear();
+    let pids = [spawn(thread_a), spawn(thread_b)];
+    for pid in pids {
+        reap(pid)?;
+    }
+    check_schedule_log(&SCHEDULE_LOG.lock())
+}
+
+/// Check that each of the threads in [`check_threads`] logged every step, and that they took
+/// turns, rather than one running to completion before the other started.
+fn check_schedule_log(log: &[u8]) -> Result<(), String> {
+    let log = String::from_utf8_lossy(log);
+    for name in ['a', 'b'] {
+        if log.matches(name).count() != STEPS {
+            return Err(format!("thread {name} didn't run {STEPS} times: {log:?}"));
+        }
+    }
+    let switches = log.as_bytes().windows(2).filter(|w| w[0] != w[1]).count();
+    if switches < 2 {
+        return Err(format!("threads didn't take turns: {log:?}"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_report() {
+        let mut out = String::new();
+        let passed = run_checks(
+            &[("good", || Ok(())), ("bad", || Err("broken".into()))],
+            &mut out,
+        );
+        assert!(!passed);
+        assert_eq!(
+            out,
+            "selftest: good ... ok\nselftest: bad ... FAILED: broken\nselftest: FAILED\n"
+        );
+
+        let mut out = String::new();
+        assert!(run_checks(&[("alloc", check_alloc)], &mut out));
+        assert_eq!(out, "selftest: alloc ... ok\nselftest: all passed\n");
+    }
+
+    #[test]
+    fn tempfs() {
+        let mut root = RootFileSystem::new();
+        root.mount_root(TempFS::new()).unwrap();
+        let process = Mutex::new(ProcessControlBlock::for_test(0, root.get_root().unwrap()));
+        let root = Mutex::new(root);
+        assert_eq!(check_tempfs(&root, &process), Ok(()));
+        // it cleans up after itself, so it can run again
+        assert_eq!(check_tempfs(&root, &process), Ok(()));
+    }
+
+    #[test]
+    fn schedule_log() {
+        assert_eq!(check_schedule_log(b"ababab"), Ok(()));
+        assert_eq!(check_schedule_log(b"aabbab"), Ok(()));
+        assert!(check_schedule_log(b"aaabbb").is_err());
+        assert!(check_schedule_log(b"ababa").is_err());
+        assert!(check_schedule_log(b"abababab").is_err());
+    }
+}
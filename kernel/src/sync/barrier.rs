@@ -0,0 +1,117 @@
+use crate::sync::condvar::Condvar;
+use crate::sync::mutex::sleep::SleepMutex;
+
+/// How many threads have arrived at a [`Barrier`] in the current round.
+struct BarrierState {
+    /// The number of threads which have to arrive to end a round.
+    n: usize,
+    arrived: usize,
+    /// Counts rounds, so a waiting thread can tell its round is over even if others have already
+    /// arrived for the next one.
+    generation: u64,
+}
+
+impl BarrierState {
+    const fn new(n: usize) -> Self {
+        Self {
+            n,
+            arrived: 0,
+            generation: 0,
+        }
+    }
+
+    /// Count a thread arriving. Returns the round it arrived in, and whether it was the last to
+    /// arrive, which ends the round.
+    fn arrive(&mut self) -> (u64, bool) {
+        let generation = self.generation;
+        self.arrived += 1;
+        if self.arrived < self.n {
+            return (generation, false);
+        }
+        self.arrived = 0;
+        self.generation += 1;
+        (generation, true)
+    }
+
+    /// Whether round `generation` is over.
+    fn released(&self, generation: u64) -> bool {
+        self.generation != generation
+    }
+}
+
+/// Blocks threads until a set number of them have arrived, then lets them all carry on. It can be
+/// used again straight away for the next round.
+pub struct Barrier {
+    state: SleepMutex<BarrierState>,
+    condvar: Condvar,
+}
+
+impl Barrier {
+    /// A barrier which releases threads `n` at a time. If `n` is 0, it doesn't block at all.
+    pub const fn new(n: usize) -> Self {
+        Self {
+            state: SleepMutex::new(BarrierState::new(n)),
+            condvar: Condvar::new(),
+        }
+    }
+
+    /// Block until `n` threads, including this one, have called `wait` in this round. Returns true
+    /// in the last of them to arrive, and false in the others.
+    pub fn wait(&self) -> bool {
+        let mut state = self.state.lock();
+        let (generation, last) = state.arrive();
+        if last {
+            self.condvar.notify_all();
+            return true;
+        }
+        while !state.released(generation) {
+            state = self.condvar.wait(state);
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BarrierState;
+
+    #[test]
+    fn rounds_wait_for_all_threads() {
+        let mut state = BarrierState::new(3);
+        for round in 0..2 {
+            // nobody's released until everyone's arrived
+            let (first, last) = state.arrive();
+            assert_eq!((first, last), (round, false));
+            let (second, last) = state.arrive();
+            assert_eq!((second, last), (round, false));
+            assert!(!state.released(round));
+
+            // and then only the last to arrive ends the round, releasing everyone in it
+            assert_eq!(state.arrive(), (round, true));
+            assert!(state.released(round));
+            assert!(!state.released(round + 1));
+        }
+    }
+
+    #[test]
+    fn waiters_released_after_next_round_starts() {
+        let mut state = BarrierState::new(2);
+        let (first, last) = state.arrive();
+        assert!(!last && !state.released(first));
+        assert!(state.arrive().1);
+
+        // the last thread arrives again before the first has woken up, which still sees its round
+        // as over rather than waiting for this one
+        let (second, last) = state.arrive();
+        assert!(!last);
+        assert!(state.released(first));
+        assert!(!state.released(second));
+    }
+
+    #[test]
+    fn zero_threads_never_block() {
+        let mut state = BarrierState::new(0);
+        assert_eq!(state.arrive(), (0, true));
+        assert_eq!(state.arrive(), (1, true));
+    }
+}
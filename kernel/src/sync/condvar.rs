@@ -0,0 +1,192 @@
+use crate::interrupts::mutex_irq::{hold_interrupts, MutexIrq};
+use crate::interrupts::timer::{cancel_wakeup, current_tick, tick_time, time_since_boot, wake_at};
+use crate::interrupts::{intr_disable, intr_enable, IntrLevel};
+use crate::sync::mutex::sleep::SleepMutexGuard;
+use crate::system::running_thread_tid;
+use crate::threading::process::Tid;
+use crate::threading::thread_sleep::{thread_sleep, thread_wakeup};
+use alloc::collections::VecDeque;
+use core::mem::take;
+use core::time::Duration;
+
+/// The threads waiting on a [`Condvar`], in the order they started waiting.
+struct Waiters(VecDeque<Tid>);
+
+impl Waiters {
+    const fn new() -> Self {
+        Self(VecDeque::new())
+    }
+
+    fn push(&mut self, tid: Tid) {
+        self.0.push_back(tid);
+    }
+
+    /// Whether `tid` is still waiting, i.e. hasn't been notified.
+    fn contains(&self, tid: Tid) -> bool {
+        self.0.contains(&tid)
+    }
+
+    /// Stop `tid` waiting, because it's given up before being notified.
+    fn remove(&mut self, tid: Tid) {
+        self.0.retain(|&waiter| waiter != tid);
+    }
+
+    fn notify_one(&mut self) -> Option<Tid> {
+        self.0.pop_front()
+    }
+
+    fn notify_all(&mut self) -> VecDeque<Tid> {
+        take(&mut self.0)
+    }
+
+    /// Whether `tid` has finished waiting, given its `deadline` and the time `now` gives: returns
+    /// whether it timed out if so, or `None` if it should keep waiting. If it timed out, it stops
+    /// waiting, so a later notify goes to someone else.
+    fn finished(
+        &mut self,
+        tid: Tid,
+        deadline: Option<Duration>,
+        now: impl FnOnce() -> Duration,
+    ) -> Option<bool> {
+        if !self.contains(tid) {
+            return Some(false);
+        }
+        if deadline.is_some_and(|deadline| now() >= deadline) {
+            self.remove(tid);
+            return Some(true);
+        }
+        None
+    }
+}
+
+/// A condition variable: lets a thread holding a [`SleepMutex`] unlock it and sleep until another
+/// thread notifies it that whatever the mutex protects has changed.
+///
+/// [`SleepMutex`]: crate::sync::mutex::sleep::SleepMutex
+pub struct Condvar {
+    waiters: MutexIrq<Waiters>,
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            waiters: MutexIrq::new(Waiters::new()),
+        }
+    }
+
+    /// Unlock `guard`'s mutex and block until another thread calls `notify_one` or `notify_all`,
+    /// then lock it again.
+    ///
+    /// Another thread can get the mutex first after the notify, so check what was waited for again
+    /// once this returns.
+    pub fn wait<'a, T>(&self, guard: SleepMutexGuard<'a, T>) -> SleepMutexGuard<'a, T> {
+        self.wait_until(guard, None).0
+    }
+
+    /// Like `wait`, but give up waiting `ticks` timer ticks after the current one. Also returns
+    /// whether it timed out, rather than being notified.
+    pub fn wait_timeout<'a, T>(
+        &self,
+        guard: SleepMutexGuard<'a, T>,
+        ticks: u64,
+    ) -> (SleepMutexGuard<'a, T>, bool) {
+        let deadline = tick_time(current_tick().saturating_add(ticks));
+        self.wait_until(guard, Some(deadline))
+    }
+
+    /// Wake the thread which has been waiting longest, if any.
+    pub fn notify_one(&self) {
+        if let Some(tid) = self.waiters.lock().notify_one() {
+            thread_wakeup(tid);
+        }
+    }
+
+    /// Wake all the waiting threads.
+    pub fn notify_all(&self) {
+        for tid in self.waiters.lock().notify_all() {
+            thread_wakeup(tid);
+        }
+    }
+
+    fn wait_until<'a, T>(
+        &self,
+        mut guard: SleepMutexGuard<'a, T>,
+        deadline: Option<Duration>,
+    ) -> (SleepMutexGuard<'a, T>, bool) {
+        let tid = running_thread_tid();
+        let mutex = guard.mutex();
+        let timed_out = {
+            // Interrupts are off from joining the queue until blocking, so neither a notify nor the
+            // timer can wake this thread before it's blocked.
+            let _guard = hold_interrupts(IntrLevel::IntrOff);
+            self.waiters.lock().push(tid);
+            guard.unlock();
+            if let Some(deadline) = deadline {
+                // If it's already passed, this times out below without blocking.
+                wake_at(deadline, tid);
+            }
+            loop {
+                let finished = self.waiters.lock().finished(tid, deadline, time_since_boot);
+                if let Some(timed_out) = finished {
+                    // The timer might not have gone off yet, even if the deadline's passed.
+                    if let Some(deadline) = deadline {
+                        cancel_wakeup(deadline, tid);
+                    }
+                    break timed_out;
+                }
+                thread_sleep();
+                // let the timer go off, in case there was nothing else to run
+                intr_enable();
+                intr_disable();
+            }
+        };
+        (mutex.lock(), timed_out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Waiters;
+    use core::time::Duration;
+
+    #[test]
+    fn timed_out_without_notify() {
+        let ms = Duration::from_millis;
+        let mut waiters = Waiters::new();
+        waiters.push(1);
+        waiters.push(2);
+
+        // 1 keeps waiting until its deadline passes with nobody having notified it
+        assert_eq!(waiters.finished(1, Some(ms(10)), || ms(5)), None);
+        assert_eq!(waiters.finished(1, Some(ms(10)), || ms(10)), Some(true));
+
+        // so it's not the one a notify goes to afterwards
+        assert_eq!(waiters.notify_one(), Some(2));
+        assert_eq!(waiters.notify_one(), None);
+    }
+
+    #[test]
+    fn notified_before_timing_out() {
+        let ms = Duration::from_millis;
+        let mut waiters = Waiters::new();
+        waiters.push(1);
+        waiters.push(2);
+        waiters.push(3);
+
+        assert_eq!(waiters.notify_one(), Some(1));
+        // so when 1 wakes up, it doesn't count as having timed out, whatever the time
+        assert_eq!(waiters.finished(1, Some(ms(10)), || ms(20)), Some(false));
+        // without a deadline, 2 waits until it's notified, and the time isn't looked at
+        assert_eq!(waiters.finished(2, None, || unreachable!()), None);
+
+        assert_eq!(waiters.notify_all(), [2, 3]);
+        assert_eq!(waiters.finished(2, None, || unreachable!()), Some(false));
+        assert_eq!(waiters.finished(3, Some(ms(10)), || ms(20)), Some(false));
+    }
+}
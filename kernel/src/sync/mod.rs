@@ -1,4 +1,7 @@
+pub mod barrier;
+pub mod condvar;
 #[allow(dead_code)]
 pub mod mutex;
 pub mod rwlock;
 pub mod semaphore;
+pub mod spsc;
@@ -34,6 +34,11 @@ impl<'a, T> SleepMutexGuard<'a, T> {
             mutex.unlock();
         }
     }
+
+    /// The mutex this guard holds.
+    pub fn mutex(&self) -> &'a SleepMutex<T> {
+        self.mutex.expect("guard has already been unlocked")
+    }
 }
 
 // Ensure mutex is released if dropped (such as in the event of a panic)
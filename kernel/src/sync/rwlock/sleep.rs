@@ -13,13 +13,61 @@ struct Waiter {
 struct RwLockState {
     reader_count: usize,
     any_writer: bool,
+    /// The number of threads waiting to write.
+    writers_waiting: usize,
+    /// Whether new readers wait while a writer is, so that a steady stream of readers can't keep
+    /// it waiting forever.
+    prefer_writers: bool,
     wait_queue: VecDeque<Waiter>,
 }
 
+impl RwLockState {
+    const fn new(prefer_writers: bool) -> Self {
+        Self {
+            reader_count: 0,
+            any_writer: false,
+            writers_waiting: 0,
+            prefer_writers,
+            wait_queue: VecDeque::new(),
+        }
+    }
+
+    /// Count another reader, if one can have the lock now.
+    fn try_read(&mut self) -> bool {
+        let writer_first = self.prefer_writers && self.writers_waiting > 0;
+        if self.reader_count == usize::MAX || self.any_writer || writer_first {
+            return false;
+        }
+        self.reader_count += 1;
+        true
+    }
+
+    /// Take the lock for a writer, if it's free. `waiting` says whether the writer has been
+    /// counted in `writers_waiting`, and is updated to say whether it is now.
+    fn try_write(&mut self, waiting: &mut bool) -> bool {
+        if self.reader_count == 0 && !self.any_writer {
+            self.any_writer = true;
+            if *waiting {
+                self.writers_waiting -= 1;
+                *waiting = false;
+            }
+            return true;
+        }
+        if !*waiting {
+            self.writers_waiting += 1;
+            *waiting = true;
+        }
+        false
+    }
+}
+
 /// A read-write lock, like `std::sync::RwLock`.
 ///
 /// This lock can be acquired for either reading (`&T`) or writing (`&mut T`).
 /// It allows any number of concurrent readers, but only one writer at a time.
+///
+/// By default, readers can keep getting the lock while a writer waits for them all to finish. Use
+/// [`RwLock::new_writer_preferring`] for a lock which makes new readers wait behind a writer.
 pub struct RwLock<T> {
     state: Mutex<RwLockState>,
     data: UnsafeCell<T>,
@@ -88,11 +136,15 @@ impl<T> RwLock<T> {
     /// Create new RwLock with data
     pub const fn new(data: T) -> Self {
         Self {
-            state: Mutex::new(RwLockState {
-                any_writer: false,
-                reader_count: 0,
-                wait_queue: VecDeque::new(),
-            }),
+            state: Mutex::new(RwLockState::new(false)),
+            data: UnsafeCell::new(data),
+        }
+    }
+    /// Create a new RwLock with data, which makes new readers wait while any writer is waiting,
+    /// so writers can't be starved by readers.
+    pub const fn new_writer_preferring(data: T) -> Self {
+        Self {
+            state: Mutex::new(RwLockState::new(true)),
             data: UnsafeCell::new(data),
         }
     }
@@ -100,8 +152,7 @@ impl<T> RwLock<T> {
     pub fn read(&self) -> RwLockReadGuard<'_, T> {
         loop {
             let mut state = self.state.lock();
-            if state.reader_count < usize::MAX && !state.any_writer {
-                state.reader_count += 1;
+            if state.try_read() {
                 // SAFETY: there are no writers, since any_writers is false
                 return RwLockReadGuard { lock: self };
             }
@@ -115,10 +166,10 @@ impl<T> RwLock<T> {
     }
     /// Acquire the lock for writing.
     pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        let mut waiting = false;
         loop {
             let mut state = self.state.lock();
-            if state.reader_count == 0 && !state.any_writer {
-                state.any_writer = true;
+            if state.try_write(&mut waiting) {
                 // SAFETY: there are no readers or writers, according to state.
                 return RwLockWriteGuard { lock: self };
             }
@@ -156,3 +207,48 @@ impl<T: Default> Default for RwLock<T> {
         T::default().into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::RwLockState;
+
+    /// Keep the lock read-locked by overlapping readers, each taking it before the last lets it go,
+    /// while a writer waits. Returns how many readers came along before the writer got the lock,
+    /// if it did within `limit`.
+    fn readers_before_writer(state: &mut RwLockState, limit: usize) -> Option<usize> {
+        assert!(state.try_read());
+        let mut waiting = false;
+        assert!(!state.try_write(&mut waiting));
+        assert_eq!(state.writers_waiting, 1);
+
+        for readers in 0..limit {
+            // a new reader turns up before the one holding the lock is done
+            let got_read = state.try_read();
+            state.reader_count -= 1;
+            if state.try_write(&mut waiting) {
+                assert_eq!(state.writers_waiting, 0);
+                assert!(!got_read);
+                return Some(readers);
+            }
+            assert!(got_read);
+        }
+        None
+    }
+
+    #[test]
+    fn readers_starve_writer_by_default() {
+        let mut state = RwLockState::new(false);
+        assert_eq!(readers_before_writer(&mut state, 1000), None);
+    }
+
+    #[test]
+    fn writer_preferring_lets_writer_in() {
+        let mut state = RwLockState::new(true);
+        // the next reader waits, so the writer gets in as soon as the current one is done
+        assert_eq!(readers_before_writer(&mut state, 1000), Some(0));
+
+        // and once the writer's done, readers can have it again
+        state.any_writer = false;
+        assert!(state.try_read());
+    }
+}
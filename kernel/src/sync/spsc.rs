@@ -0,0 +1,168 @@
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// A fixed-size, lock-free queue with one producer and one consumer, e.g. an interrupt handler
+/// pushing and a thread popping. Holds up to `N - 1` items.
+///
+/// Neither side ever waits for the other, so the producer can push from an interrupt handler that
+/// came in halfway through a pop, and the consumer doesn't need interrupts disabled.
+pub struct SpscRing<T, const N: usize> {
+    slots: [UnsafeCell<MaybeUninit<T>>; N],
+    /// The index of the next slot to push into. Only written by the producer.
+    head: AtomicUsize,
+    /// The index of the next slot to pop from. Only written by the consumer.
+    tail: AtomicUsize,
+}
+
+// SAFETY: Items are moved from the producer to the consumer, and each slot is only touched by one
+// of them at a time, as handed over by `head` and `tail`.
+unsafe impl<T: Send, const N: usize> Sync for SpscRing<T, N> {}
+unsafe impl<T: Send, const N: usize> Send for SpscRing<T, N> {}
+
+impl<T, const N: usize> Default for SpscRing<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> SpscRing<T, N> {
+    pub const fn new() -> Self {
+        assert!(N > 1, "ring needs a spare slot to tell full from empty");
+        Self {
+            slots: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire) == self.tail.load(Ordering::Acquire)
+    }
+
+    /// Add `value` to the back of the queue, or give it back if the queue is full.
+    ///
+    /// # Safety
+    ///
+    /// Only one producer may call this, i.e. calls to it mustn't overlap.
+    pub unsafe fn push(&self, value: T) -> Result<(), T> {
+        let head = self.head.load(Ordering::Relaxed);
+        let next = (head + 1) % N;
+        if next == self.tail.load(Ordering::Acquire) {
+            return Err(value);
+        }
+        // SAFETY: The slot at head is empty, and the consumer won't look at it until head moves
+        // past it below.
+        unsafe { (*self.slots[head].get()).write(value) };
+        self.head.store(next, Ordering::Release);
+        Ok(())
+    }
+
+    /// Take the item at the front of the queue, if there is one.
+    ///
+    /// # Safety
+    ///
+    /// Only one consumer may call this, or `peek_each`, i.e. calls to them mustn't overlap.
+    pub unsafe fn pop(&self) -> Option<T> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        if tail == self.head.load(Ordering::Acquire) {
+            return None;
+        }
+        // SAFETY: The producer filled the slot at tail before moving head past it, and won't
+        // reuse it until tail moves past it below.
+        let value = unsafe { (*self.slots[tail].get()).assume_init_read() };
+        self.tail.store((tail + 1) % N, Ordering::Release);
+        Some(value)
+    }
+
+    /// Call `f` on each item in the queue, front first, without taking them out.
+    ///
+    /// # Safety
+    ///
+    /// As for `pop`, this may only be called by the consumer.
+    pub unsafe fn peek_each(&self, mut f: impl FnMut(&T)) {
+        let mut index = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+        while index != head {
+            // SAFETY: Slots between tail and head are filled, and the producer leaves them alone.
+            f(unsafe { (*self.slots[index].get()).assume_init_ref() });
+            index = (index + 1) % N;
+        }
+    }
+}
+
+impl<T, const N: usize> Drop for SpscRing<T, N> {
+    fn drop(&mut self) {
+        // SAFETY: Having `&mut self` means nothing else is pushing or popping.
+        while unsafe { self.pop() }.is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SpscRing;
+
+    /// Interleave pushes from a simulated interrupt handler with pops, with bursts of interrupts
+    /// which fill the ring up, and check everything pushed comes out once, in order.
+    #[test]
+    fn interleaved_push_and_pop() {
+        let ring = SpscRing::<u32, 8>::new();
+        let mut pushed = Vec::new();
+        let mut popped = Vec::new();
+        let mut next = 0;
+        // a simple LCG, so the interleaving is varied but the same every run
+        let mut seed = 12345_u32;
+        let mut random = || {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            seed >> 16
+        };
+
+        for _ in 0..10_000 {
+            // the "interrupt" fires a few times between each pop
+            for _ in 0..random() % 4 {
+                // SAFETY: There's only one producer and one consumer.
+                match unsafe { ring.push(next) } {
+                    Ok(()) => pushed.push(next),
+                    // full, so the item is handed back rather than overwriting one
+                    Err(item) => assert_eq!(item, next),
+                }
+                next += 1;
+            }
+            // SAFETY: As above.
+            if let Some(item) = unsafe { ring.pop() } {
+                popped.push(item);
+            }
+        }
+        // SAFETY: As above.
+        while let Some(item) = unsafe { ring.pop() } {
+            popped.push(item);
+        }
+
+        assert!(ring.is_empty());
+        assert!(pushed.len() < next as usize, "ring never filled up");
+        assert_eq!(popped, pushed);
+    }
+
+    #[test]
+    fn holds_one_less_than_its_size() {
+        let ring = SpscRing::<u8, 4>::new();
+        // SAFETY: There's only one producer and one consumer.
+        unsafe {
+            assert_eq!(ring.push(1), Ok(()));
+            assert_eq!(ring.push(2), Ok(()));
+            assert_eq!(ring.push(3), Ok(()));
+            assert_eq!(ring.push(4), Err(4));
+
+            let mut peeked = Vec::new();
+            ring.peek_each(|&item| peeked.push(item));
+            assert_eq!(peeked, [1, 2, 3]);
+
+            assert_eq!(ring.pop(), Some(1));
+            assert_eq!(ring.push(4), Ok(()));
+            assert_eq!(ring.pop(), Some(2));
+            assert_eq!(ring.pop(), Some(3));
+            assert_eq!(ring.pop(), Some(4));
+            assert_eq!(ring.pop(), None);
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use crate::block::block_core::BlockManager;
 use crate::drivers::input::input_core::InputBuffer;
 use crate::fs::fs_manager::RootFileSystem;
+use crate::fs::tty::Tty;
 use crate::sync::mutex::Mutex;
 use crate::sync::rwlock::sleep::RwLock;
 use crate::threading::process::{Pid, ProcessState, Tid};
@@ -14,7 +15,8 @@ pub struct SystemState {
 
     pub block_manager: RwLock<BlockManager>,
     pub root_filesystem: Mutex<RootFileSystem>,
-    pub input_buffer: Mutex<InputBuffer>,
+    pub input_buffer: InputBuffer,
+    pub tty: Arc<Tty>,
 }
 
 impl core::fmt::Debug for SystemState {
@@ -49,11 +51,16 @@ pub fn init_system(state: SystemState) {
 }
 
 pub fn unwrap_system() -> &'static SystemState {
+    try_unwrap_system().expect("System not initialized.")
+}
+
+/// Get the system, if it's been initialized yet.
+pub fn try_unwrap_system() -> Option<&'static SystemState> {
     if SYSTEM_STATE.load(core::sync::atomic::Ordering::Acquire) == INITIALIZED {
         // SAFETY: since SYSTEM_STATE = INITIALIZED, the SYSTEM has been initialized.
-        unsafe { SYSTEM.assume_init_ref() }
+        Some(unsafe { SYSTEM.assume_init_ref() })
     } else {
-        panic!("System not initialized.");
+        None
     }
 }
 
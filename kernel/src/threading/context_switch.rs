@@ -1,5 +1,5 @@
 use crate::{
-    interrupts::{intr_get_level, IntrLevel},
+    interrupts::{intr_get_level, timer::WATCHDOG, IntrLevel},
     threading::thread_functions::clean_up_thread,
 };
 use core::mem::offset_of;
@@ -45,6 +45,9 @@ pub unsafe fn switch_threads(
     // Update the status of the current thread.
     (*switch_from).status = status_for_current_thread;
 
+    threads.stats.record_context_switch();
+    WATCHDOG.pet();
+
     let page_manager = &(*switch_to).page_manager;
     page_manager.load();
 
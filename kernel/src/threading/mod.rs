@@ -1,27 +1,36 @@
 mod context_switch;
 pub mod process;
 pub mod process_functions;
+pub mod process_vfork;
+pub mod process_wait;
 pub mod scheduling;
 pub mod thread_control_block;
 pub mod thread_functions;
+pub mod thread_join;
 pub mod thread_sleep;
+pub mod work_queue;
 
 use crate::rush::rush_core::rush_loop;
 use crate::sync::mutex::Mutex;
 use crate::system::unwrap_system;
-use crate::threading::scheduling::Scheduler;
+use crate::threading::scheduling::{scheduler_yield_and_continue, Scheduler, SchedulerStats};
 use crate::user_program::elf::Elf;
 use crate::{
-    interrupts::{intr_enable, intr_get_level, IntrLevel},
+    interrupts::{
+        intr_disable, intr_enable, intr_get_level, softirq::run_softirqs, timer::WATCHDOG,
+        IntrLevel,
+    },
     paging::PageManager,
     threading::scheduling::create_scheduler,
 };
 use alloc::boxed::Box;
+use core::arch::asm;
 use thread_control_block::ThreadControlBlock;
 
 pub struct ThreadState {
     pub running_thread: Mutex<Option<Box<ThreadControlBlock>>>,
     pub scheduler: Mutex<Box<dyn Send + Scheduler>>,
+    pub stats: SchedulerStats,
 }
 
 pub fn create_thread_state() -> ThreadState {
@@ -35,6 +44,7 @@ pub fn create_thread_state() -> ThreadState {
     ThreadState {
         running_thread: Mutex::new(None), // Drop Option<> and set this to the IDLE thread?
         scheduler,
+        stats: SchedulerStats::new(),
     }
 }
 
@@ -55,10 +65,11 @@ pub fn thread_system_start(kernel_page_manager: PageManager, init_elf: &[u8]) ->
     );
 
     // Create the initial user program thread.
-    let elf = Elf::parse_bytes(init_elf).expect("failed to parse provided elf file");
+    let elf = Elf::parse_bytes(init_elf)
+        .unwrap_or_else(|e| panic!("failed to parse provided elf file: {e}"));
 
     // Create the initial user program thread.
-    let user_tcb = ThreadControlBlock::new_from_elf(elf, &system.process)
+    let user_tcb = ThreadControlBlock::new_from_elf(elf, "init", &system.process)
         .expect("Failed to parse Elf for initial program.");
 
     // SAFETY: Interrupts must be disabled.
@@ -71,17 +82,30 @@ pub fn thread_system_start(kernel_page_manager: PageManager, init_elf: &[u8]) ->
 
     // Eventually, the scheduler may run the kernel thread again.
     // We may later replace this with code to clean up the kernel resources.
-    // For now, we will act as the idle thread.
-    // idle_function();
     rush_loop();
 
     // This function never returns.
 }
 
-// /// The function run by the idle thread.
-// /// Continually yields and should never die.
-// extern "C" fn idle_function() -> ! {
-//     loop {
-//         scheduler_yield_and_continue();
-//     }
-// }
+/// The function run by the idle thread.
+/// Yields whenever another thread is ready, and otherwise halts until the next interrupt.
+/// Should never die or block.
+pub extern "C" fn idle_function() -> i32 {
+    let threads = &unwrap_system().threads;
+    loop {
+        intr_disable();
+        // The scheduler runs these when it switches threads, but that doesn't happen while idle.
+        run_softirqs();
+        if threads.scheduler.lock().has_ready() {
+            intr_enable();
+            scheduler_yield_and_continue();
+        } else {
+            threads.stats.record_idle_halt();
+            // Being idle isn't being hung.
+            WATCHDOG.pet();
+            // SAFETY: Interrupts are only enabled after the instruction following `sti`, so
+            // one can't come in between checking the scheduler and halting, and be missed.
+            unsafe { asm!("sti", "hlt") };
+        }
+    }
+}
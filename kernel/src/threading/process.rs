@@ -6,36 +6,76 @@ use core::sync::atomic::{AtomicU16, Ordering};
 
 pub type Pid = u16;
 pub type Tid = u16;
-pub type AtomicPid = AtomicU16;
 pub type AtomicTid = AtomicU16;
 
 #[derive(Default)]
 pub struct ProcessTable {
     content: RwLock<BTreeMap<Pid, Arc<Mutex<ProcessControlBlock>>>>,
+    pids: Mutex<PidAllocator>,
+}
+
+/// Hands out pids from 1 up to some maximum, in order, going back to the start once it runs out.
+///
+/// A pid is free once its process has been removed from the process table, i.e. reaped with
+/// `waitpid`, but isn't handed out again until the allocator has wrapped around, so that a pid
+/// which was just waited on doesn't immediately refer to some other process. 0 is never handed
+/// out, since it's used for the kernel (e.g. as the parent of the first process).
+pub struct PidAllocator {
+    /// Where to start looking for a free pid
+    next: Pid,
+    max: Pid,
+}
+
+impl Default for PidAllocator {
+    fn default() -> Self {
+        Self::new(Pid::MAX)
+    }
+}
+
+impl PidAllocator {
+    /// An allocator handing out pids from 1 to `max`, inclusive.
+    pub const fn new(max: Pid) -> Self {
+        assert!(max > 0);
+        Self { next: 1, max }
+    }
+
+    /// Allocate the lowest pid at or after the last one allocated, wrapping around to 1 after
+    /// `max`, for which `in_use` returns `false`.
+    ///
+    /// Returns `None` if every pid is in use.
+    pub fn allocate(&mut self, in_use: impl Fn(Pid) -> bool) -> Option<Pid> {
+        let start = self.next;
+        let mut pid = start;
+        loop {
+            let following = if pid == self.max { 1 } else { pid + 1 };
+            if !in_use(pid) {
+                self.next = following;
+                return Some(pid);
+            }
+            pid = following;
+            if pid == start {
+                return None;
+            }
+        }
+    }
 }
 
 pub struct ProcessState {
     pub table: ProcessTable,
     next_tid: AtomicTid,
-    next_pid: AtomicPid,
 }
 
 pub fn create_process_state() -> ProcessState {
     ProcessState {
         table: Default::default(),
         next_tid: AtomicTid::new(1),
-        next_pid: AtomicPid::new(1),
     }
 }
 
 impl ProcessState {
+    /// Allocate a pid for a new process, which should then be added to the table.
     pub fn allocate_pid(&self) -> Pid {
-        // SAFETY: Atomically accesses a shared variable.
-        let pid = self.next_pid.fetch_add(1, Ordering::SeqCst);
-        if pid == 0 {
-            panic!("PID overflow"); // TODO: handle overflow properly
-        }
-        pid
+        self.table.allocate_pid().expect("Out of pids")
     }
     pub fn allocate_tid(&self) -> Tid {
         // SAFETY: Atomically accesses a shared variable.
@@ -48,6 +88,13 @@ impl ProcessState {
 }
 
 impl ProcessTable {
+    /// Allocate a pid which isn't used by any process in the table, including ones which have
+    /// exited but haven't been waited on yet.
+    pub fn allocate_pid(&self) -> Option<Pid> {
+        let content = self.content.read();
+        self.pids.lock().allocate(|pid| content.contains_key(&pid))
+    }
+
     pub fn add(&self, pcb: ProcessControlBlock) -> Arc<Mutex<ProcessControlBlock>> {
         let pid = pcb.pid;
         let mut content = self.content.write();
@@ -69,4 +116,119 @@ impl ProcessTable {
     pub fn get(&self, pid: Pid) -> Option<Arc<Mutex<ProcessControlBlock>>> {
         self.content.read().get(&pid).cloned()
     }
+
+    /// Mark every process in the process group `pgid` as interrupted.
+    ///
+    /// Returns the number of processes in the group.
+    pub fn interrupt_group(&self, pgid: Pid) -> usize {
+        let mut count = 0;
+        for pcb in self.content.read().values() {
+            let mut pcb = pcb.lock();
+            if pcb.pgid == pgid {
+                pcb.interrupted = true;
+                pcb.signals.wake_sleepers();
+                count += 1;
+            }
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fs::tty::{test::BufferConsole, Tty};
+    use alloc::boxed::Box;
+    use alloc::collections::VecDeque;
+
+    fn test_pcb(pid: Pid, ppid: Pid, pgid: Pid) -> ProcessControlBlock {
+        ProcessControlBlock {
+            ppid,
+            pgid,
+            ..ProcessControlBlock::for_test(pid, (0, 0))
+        }
+    }
+
+    #[test]
+    fn ctrl_c_interrupts_foreground_child() {
+        let table = ProcessTable::default();
+        let shell = table.add(test_pcb(1, 0, 1));
+        // the shell puts its child in a new group, which the child's own child inherits
+        let child = table.add(test_pcb(2, 1, 2));
+        let grandchild = table.add(test_pcb(3, 2, 2));
+
+        let tty = Tty::new(Box::<BufferConsole>::default());
+        tty.set_foreground(Some(child.lock().pgid));
+        tty.receive(0x03);
+        let pgid = tty
+            .take_interrupt()
+            .expect("Ctrl-C should target the foreground group");
+        assert_eq!(table.interrupt_group(pgid), 2);
+
+        assert!(child.lock().interrupted);
+        assert!(grandchild.lock().interrupted);
+        assert!(!shell.lock().interrupted);
+    }
+
+    #[test]
+    fn ctrl_c_wakes_paused_process() {
+        let table = ProcessTable::default();
+        let paused = table.add(test_pcb(2, 1, 2));
+        // as pause leaves it, until a signal comes
+        paused.lock().signals.sleepers.push(7);
+
+        let tty = Tty::new(Box::<BufferConsole>::default());
+        tty.set_foreground(Some(2));
+        // what the keyboard softirq hands to the work queue, without waiting for a syscall
+        let pgid = tty
+            .try_receive([0x03].into_iter())
+            .flatten()
+            .expect("Ctrl-C should target the foreground group");
+        table.interrupt_group(pgid);
+
+        let paused = paused.lock();
+        assert!(paused.interrupted);
+        assert!(paused.signals.sleepers.is_empty());
+    }
+
+    #[test]
+    fn pids_are_reused_only_after_wrapping() {
+        let mut pids = PidAllocator::new(5);
+        let in_use = |live: &'static [Pid]| move |pid: Pid| live.contains(&pid);
+        assert_eq!(pids.allocate(in_use(&[])), Some(1));
+        assert_eq!(pids.allocate(in_use(&[1])), Some(2));
+        // 1 is free again, but isn't handed out until after 5
+        assert_eq!(pids.allocate(in_use(&[2])), Some(3));
+        assert_eq!(pids.allocate(in_use(&[2, 4])), Some(5));
+        // then the lowest free pid is handed out, skipping any still in use
+        assert_eq!(pids.allocate(in_use(&[1, 2, 5])), Some(3));
+        assert_eq!(pids.allocate(in_use(&[1, 2, 3, 4, 5])), None);
+        assert_eq!(pids.allocate(in_use(&[1, 2, 3, 5])), Some(4));
+    }
+
+    #[test]
+    fn many_processes() {
+        let table = ProcessTable::default();
+        // the first process lives for the whole test, and a few others for a while each
+        let init = table.allocate_pid().unwrap();
+        table.add(test_pcb(init, 0, init));
+        let mut live = VecDeque::new();
+        let mut last = init;
+        let mut wrapped = false;
+        for _ in 0..2 * usize::from(Pid::MAX) {
+            let pid = table.allocate_pid().unwrap();
+            assert_ne!(pid, 0);
+            assert!(table.get(pid).is_none(), "pid {pid} is still in use");
+            wrapped |= pid < last;
+            last = pid;
+            table.add(test_pcb(pid, init, pid));
+            live.push_back(pid);
+            if live.len() > 10 {
+                // exited and waited on
+                table.remove(live.pop_front().unwrap());
+            }
+        }
+        assert!(wrapped);
+        assert!(table.get(init).is_some());
+    }
 }
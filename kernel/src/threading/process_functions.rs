@@ -1,6 +1,7 @@
-use crate::system::{running_process, running_thread_tid};
+use crate::system::{running_process, running_thread_tid, unwrap_system};
 
 use super::{
+    process_vfork::release_vfork_parent,
     thread_functions::{self, stop_thread},
     thread_sleep::thread_wakeup,
 };
@@ -9,6 +10,10 @@ pub fn exit_process(exit_code: i32) -> ! {
     let pcb = running_process();
     let mut pcb = pcb.lock();
     pcb.exit_code = Some(exit_code);
+    release_vfork_parent(&mut pcb);
+
+    // Changes to shared file mappings would be lost otherwise. There's nobody to report errors to.
+    let _ = pcb.vmas.write_back_all();
 
     if let Some(wait_tid) = pcb.waiting_thread {
         thread_wakeup(wait_tid);
@@ -22,7 +27,28 @@ pub fn exit_process(exit_code: i32) -> ! {
             stop_thread(*tid)
         }
     });
+
+    // SAFETY: none of the process' threads will run in userspace again.
+    unsafe { pcb.vmas.unmap_all() };
+    let (replaces, killed_by) = (pcb.replaces, pcb.killed_by);
     drop(pcb);
 
+    // Whatever's waiting on the process this one replaced with execve sees it exit.
+    if let Some(replaced) = replaces.and_then(|pid| unwrap_system().process.table.get(pid)) {
+        let mut replaced = replaced.lock();
+        replaced.exit_code = Some(exit_code);
+        replaced.killed_by = killed_by;
+        if let Some(wait_tid) = replaced.waiting_thread {
+            thread_wakeup(wait_tid);
+        }
+    }
+
     thread_functions::exit_thread(-1);
 }
+
+/// Kill the running process, as if by `signal`. It exits with 128 plus the signal, which is what
+/// shells report for a process killed by it, and `waitid` reports the signal itself.
+pub fn kill_process(signal: i32) -> ! {
+    running_process().lock().killed_by = Some(signal);
+    exit_process(128 + signal);
+}
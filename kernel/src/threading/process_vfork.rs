@@ -0,0 +1,99 @@
+use super::thread_control_block::{ProcessControlBlock, ThreadControlBlock};
+use super::thread_sleep::{thread_sleep, thread_wakeup};
+use crate::interrupts::{intr_disable, intr_enable, mutex_irq::hold_interrupts, IntrLevel};
+use crate::system::{running_process, running_thread_tid, unwrap_system};
+use crate::user_program::rusage::inherit_cpu_limit;
+use crate::user_program::syscall::SyscallFrame;
+use alloc::boxed::Box;
+use core::mem::take;
+use core::ptr::NonNull;
+
+/// The `vfork` syscall, made with the registers in `frame`: start a new process which returns from
+/// the syscall with 0, using the running process' memory rather than a copy of it, and suspend the
+/// running thread until the new process execs or exits. Returns the new process' pid.
+///
+/// As with processes started by `execve`, the new process gets its own standard file descriptors
+/// rather than inheriting them, but it keeps the running process' cwd and root.
+pub fn vfork(frame: &SyscallFrame) -> isize {
+    let system = unwrap_system();
+    let parent = running_process();
+    let tid = running_thread_tid();
+    let parent_pid = parent.lock().pid;
+
+    let child = ProcessControlBlock::create(
+        &system.process,
+        &mut system.root_filesystem.lock(),
+        parent_pid,
+    );
+    let child_pid = {
+        let mut parent = parent.lock();
+        let mut child = child.lock();
+        // the child borrows the parent's memory until it execs or exits
+        child.vmas = take(&mut parent.vmas);
+        child.heap_start = parent.heap_start;
+        child.program_break = parent.program_break;
+        child.pgid = parent.pgid;
+        child.comm = parent.comm;
+        child.cwd = parent.cwd;
+        child.cwd_path = parent.cwd_path.clone();
+        child.root = parent.root;
+        system.root_filesystem.lock().hold_cwd_and_root(&child);
+        child.signals.blocked = parent.signals.blocked;
+        child.signals.actions = parent.signals.actions;
+        child.fd_limit = parent.fd_limit;
+        child.vfork_parent = Some((parent_pid, tid));
+        child.pid
+    };
+
+    let page_manager = {
+        let _guard = hold_interrupts(IntrLevel::IntrOff);
+        let running = system.threads.running_thread.lock();
+        let running = running.as_ref().expect("Why is nothing running!?");
+        // SAFETY: this thread is suspended below until the child execs or exits, and its thread
+        // is cleaned up on the next context switch after that, so it's done with the page tables
+        // before this thread can drop them.
+        unsafe { running.page_manager.borrow() }
+    };
+    let mut thread = ThreadControlBlock::new_with_page_manager(
+        NonNull::new(frame.eip as *mut u8).expect("syscall made from address 0"),
+        child_pid,
+        page_manager,
+        &system.process,
+    );
+    // the child carries on from the syscall, but with 0 returned
+    thread.user_frame = Some(SyscallFrame { eax: 0, ..*frame });
+    inherit_cpu_limit(&mut thread, &mut child.lock());
+    system.threads.scheduler.lock().push(Box::new(thread));
+
+    loop {
+        // Interrupts are off between checking and blocking, so the child can't exec or exit, and
+        // wake this thread, before it's blocked.
+        intr_disable();
+        let released = child.lock().vfork_parent.is_none();
+        if !released {
+            thread_sleep();
+        }
+        intr_enable();
+        if released {
+            break;
+        }
+    }
+
+    child_pid as isize
+}
+
+/// If the process `pcb` was made with `vfork`, give the memory it borrowed back to its parent, and
+/// let the parent carry on. This is called as it execs or exits.
+pub fn release_vfork_parent(pcb: &mut ProcessControlBlock) {
+    let Some((parent_pid, tid)) = pcb.vfork_parent else {
+        return;
+    };
+    if let Some(parent) = unwrap_system().process.table.get(parent_pid) {
+        let mut parent = parent.lock();
+        parent.vmas = take(&mut pcb.vmas);
+        parent.heap_start = pcb.heap_start;
+        parent.program_break = pcb.program_break;
+    }
+    pcb.vfork_parent = None;
+    thread_wakeup(tid);
+}
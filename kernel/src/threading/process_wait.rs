@@ -0,0 +1,164 @@
+use super::process::Pid;
+use super::thread_control_block::ProcessControlBlock;
+use super::thread_sleep::thread_sleep;
+use crate::interrupts::{intr_disable, intr_enable};
+use crate::sync::mutex::Mutex;
+use crate::system::{running_thread_pid, running_thread_tid, unwrap_system};
+use crate::user_program::syscall::{
+    SigInfo, CLD_EXITED, CLD_KILLED, ECHILD, EINVAL, P_PID, SIGCHLD, WEXITED, WNOWAIT,
+};
+use crate::user_program::user_copy::{check_user_range, copy_to_user};
+use alloc::sync::Arc;
+use core::mem::size_of;
+
+/// Wait for the process `pid` to exit, and return it, without reaping it.
+///
+/// Returns `None` if there's no such process, it's the running process, or another thread is
+/// already waiting on it.
+pub fn wait_for_exit(pid: Pid) -> Option<Arc<Mutex<ProcessControlBlock>>> {
+    if pid == running_thread_pid() {
+        return None;
+    }
+
+    let pcb_ref = unwrap_system().process.table.get(pid)?;
+    let mut pcb = pcb_ref.lock();
+
+    // Can't wait on a process that already has a thread waiting
+    if pcb.waiting_thread.is_some() {
+        return None;
+    }
+
+    pcb.waiting_thread = Some(running_thread_tid());
+    drop(pcb);
+
+    loop {
+        // Interrupts are off from finding it still running until blocking, so it can't exit, and
+        // wake this thread, before it's blocked.
+        intr_disable();
+        let exited = pcb_ref.lock().exit_code.is_some();
+        if !exited {
+            thread_sleep();
+        }
+        intr_enable();
+        if exited {
+            break;
+        }
+    }
+
+    // It might not be reaped yet, so it can be waited on again.
+    pcb_ref.lock().waiting_thread = None;
+    Some(pcb_ref)
+}
+
+/// The `waitpid` syscall: wait for the process `pid` to exit, store its status in `status`, then
+/// reap it. `options` aren't supported yet.
+pub fn waitpid(pid: Pid, status: *mut i32) -> isize {
+    if check_user_range(status as usize, size_of::<i32>(), true).is_err() {
+        return -1;
+    }
+
+    let Some(pcb) = wait_for_exit(pid) else {
+        return -1;
+    };
+    let wait_status = {
+        let pcb = pcb.lock();
+        wait_status(pcb.exit_code.expect("process hasn't exited"), pcb.killed_by)
+    };
+
+    if copy_to_user(status, &[wait_status]).is_err() {
+        return -1;
+    }
+
+    unwrap_system().process.table.remove(pid);
+
+    pid as isize
+}
+
+/// The `waitid` syscall: wait for the process `id` to exit, as long as `idtype` is `P_PID`, and
+/// fill in `infop` with how it exited. It's reaped, unless `options` includes `WNOWAIT`.
+pub fn waitid(idtype: i32, id: Pid, infop: *mut SigInfo, options: i32) -> isize {
+    if idtype != P_PID || options & WEXITED == 0 || options & !(WEXITED | WNOWAIT) != 0 {
+        return -EINVAL;
+    }
+
+    if let Err(e) = check_user_range(infop as usize, size_of::<SigInfo>(), true) {
+        return -e;
+    }
+
+    let Some(pcb) = wait_for_exit(id) else {
+        return -ECHILD;
+    };
+    let info = {
+        let pcb = pcb.lock();
+        child_info(
+            pcb.pid,
+            pcb.exit_code.expect("process hasn't exited"),
+            pcb.killed_by,
+        )
+    };
+
+    if let Err(e) = copy_to_user(infop, &[info]) {
+        return -e;
+    }
+
+    if options & WNOWAIT == 0 {
+        unwrap_system().process.table.remove(id);
+    }
+
+    0
+}
+
+/// The status `waitpid` reports for a process which exited with `exit_code`, having been killed by
+/// the signal `killed_by` if it didn't exit by itself: the exit code in the second byte, or just
+/// the signal, as on Linux.
+fn wait_status(exit_code: i32, killed_by: Option<i32>) -> i32 {
+    match killed_by {
+        Some(signal) => signal,
+        None => (exit_code & 0xff) << 8,
+    }
+}
+
+/// How the process `pid` exited, with `exit_code`, having been killed by the signal `killed_by`
+/// if it didn't exit by itself.
+fn child_info(pid: Pid, exit_code: i32, killed_by: Option<i32>) -> SigInfo {
+    let (si_code, si_status) = match killed_by {
+        Some(signal) => (CLD_KILLED, signal),
+        None => (CLD_EXITED, exit_code),
+    };
+    SigInfo {
+        si_signo: SIGCHLD,
+        si_code,
+        si_pid: pid,
+        si_status,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::user_program::syscall::SIGSEGV;
+
+    #[test]
+    fn exited_or_killed() {
+        let exited = child_info(2, 3, None);
+        assert_eq!(exited.si_signo, SIGCHLD);
+        assert_eq!(exited.si_code, CLD_EXITED);
+        assert_eq!(exited.si_pid, 2);
+        assert_eq!(exited.si_status, 3);
+
+        // the exit code of a killed process is 128 plus the signal, but the signal is reported
+        let killed = child_info(4, 128 + SIGSEGV, Some(SIGSEGV));
+        assert_eq!(killed.si_signo, SIGCHLD);
+        assert_eq!(killed.si_code, CLD_KILLED);
+        assert_eq!(killed.si_pid, 4);
+        assert_eq!(killed.si_status, SIGSEGV);
+    }
+
+    #[test]
+    fn waitpid_status() {
+        assert_eq!(wait_status(3, None), 0x300);
+        // only the low byte of the exit code is kept
+        assert_eq!(wait_status(0x1ff, None), 0xff00);
+        assert_eq!(wait_status(128 + SIGSEGV, Some(SIGSEGV)), SIGSEGV);
+    }
+}
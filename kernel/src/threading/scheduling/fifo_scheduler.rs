@@ -1,8 +1,13 @@
+use super::super::thread_control_block::ThreadStatus;
 use super::super::ThreadControlBlock;
 use super::scheduler::Scheduler;
 use crate::threading::process::Tid;
 use alloc::{boxed::Box, collections::VecDeque};
 
+#[cfg_attr(
+    any(feature = "mlfq_scheduler", feature = "stride_scheduler"),
+    allow(dead_code)
+)]
 pub struct FIFOScheduler {
     ready_queue: VecDeque<Box<ThreadControlBlock>>,
 }
@@ -35,4 +40,14 @@ impl Scheduler for FIFOScheduler {
         let pos = self.ready_queue.iter().position(|tcb| tcb.tid == _tid);
         pos.and_then(|index| self.ready_queue.get_mut(index).map(|tcb| &mut **tcb))
     }
+
+    fn len(&self) -> usize {
+        self.ready_queue.len()
+    }
+
+    fn has_ready(&self) -> bool {
+        self.ready_queue
+            .iter()
+            .any(|tcb| tcb.status == ThreadStatus::Ready)
+    }
 }
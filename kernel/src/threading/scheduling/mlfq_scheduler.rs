@@ -0,0 +1,214 @@
+use super::super::thread_control_block::ThreadStatus;
+use super::super::ThreadControlBlock;
+use super::scheduler::Scheduler;
+use crate::threading::process::Tid;
+use alloc::{boxed::Box, collections::VecDeque};
+
+/// The number of priority levels. Level 0 is the highest.
+pub const MLFQ_LEVELS: usize = 3;
+
+/// The number of timer ticks a thread can run for at each level before it's moved down a level.
+pub const MLFQ_QUANTUM_TICKS: [usize; MLFQ_LEVELS] = [1, 2, 4];
+
+/// The number of timer ticks between moving every thread back to the top level, so threads at the
+/// bottom aren't starved by ones above.
+pub const MLFQ_BOOST_TICKS: usize = 50;
+
+/// Where a thread is in the `MLFQScheduler`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MLFQPriority {
+    level: usize,
+    /// Ticks used of the quantum at this level. This isn't reset when the thread yields, so it
+    /// can't stay at a high level by yielding just before the timer goes off.
+    ticks_used: usize,
+    /// Whether this is the idle thread, which is kept out of the levels and only run when no other
+    /// thread is ready.
+    idle: bool,
+}
+
+impl MLFQPriority {
+    /// The priority of the idle thread.
+    pub fn idle() -> Self {
+        Self {
+            idle: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    /// Count a timer tick the thread was running for. If that used up its quantum, it's moved
+    /// down a level, and this returns true so it's preempted.
+    pub fn tick(&mut self) -> bool {
+        self.ticks_used += 1;
+        if self.ticks_used < MLFQ_QUANTUM_TICKS[self.level] {
+            return false;
+        }
+        self.level = (self.level + 1).min(MLFQ_LEVELS - 1);
+        self.ticks_used = 0;
+        true
+    }
+
+    /// Move the thread back to the top level.
+    pub fn boost(&mut self) {
+        *self = Self {
+            idle: self.idle,
+            ..Self::default()
+        };
+    }
+}
+
+/// A multi-level feedback queue: threads run in FIFO order within each level, and higher levels
+/// always go first. Threads start at the top and move down as they use up their quanta, so ones
+/// which mostly wait (e.g. on I/O) stay above ones which use the CPU constantly.
+#[cfg_attr(not(feature = "mlfq_scheduler"), allow(dead_code))]
+pub struct MLFQScheduler {
+    queues: [VecDeque<Box<ThreadControlBlock>>; MLFQ_LEVELS],
+    /// The idle thread, while it isn't running
+    idle: Option<Box<ThreadControlBlock>>,
+    ticks_since_boost: usize,
+}
+
+// TODO: Will be removed, requires a change to stack type.
+// SAFETY: Schedulers should be run with interrupts disabled.
+unsafe impl Sync for MLFQScheduler {}
+
+impl Scheduler for MLFQScheduler {
+    fn new() -> MLFQScheduler {
+        MLFQScheduler {
+            queues: Default::default(),
+            idle: None,
+            ticks_since_boost: 0,
+        }
+    }
+
+    fn push(&mut self, thread: Box<ThreadControlBlock>) {
+        if thread.mlfq.is_idle() {
+            self.idle = Some(thread);
+        } else {
+            self.queues[thread.mlfq.level()].push_back(thread);
+        }
+    }
+
+    fn pop(&mut self) -> Option<Box<ThreadControlBlock>> {
+        // Blocked threads are left where they are, so they keep their place once they're woken.
+        self.queues
+            .iter_mut()
+            .find_map(|queue| {
+                let pos = queue
+                    .iter()
+                    .position(|tcb| tcb.status == ThreadStatus::Ready)?;
+                queue.remove(pos)
+            })
+            .or_else(|| self.idle.take())
+    }
+
+    fn remove(&mut self, tid: Tid) -> Option<Box<ThreadControlBlock>> {
+        if self.idle.as_ref().is_some_and(|tcb| tcb.tid == tid) {
+            return self.idle.take();
+        }
+        self.queues.iter_mut().find_map(|queue| {
+            let pos = queue.iter().position(|tcb| tcb.tid == tid)?;
+            queue.remove(pos)
+        })
+    }
+
+    fn get_mut(&mut self, tid: Tid) -> Option<&mut ThreadControlBlock> {
+        self.queues
+            .iter_mut()
+            .flatten()
+            .chain(&mut self.idle)
+            .find(|tcb| tcb.tid == tid)
+            .map(|tcb| &mut **tcb)
+    }
+
+    fn len(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum::<usize>() + usize::from(self.idle.is_some())
+    }
+
+    fn has_ready(&self) -> bool {
+        self.queues
+            .iter()
+            .flatten()
+            .chain(&self.idle)
+            .any(|tcb| tcb.status == ThreadStatus::Ready)
+    }
+
+    fn tick(&mut self, running: &mut ThreadControlBlock) -> bool {
+        let expired = running.mlfq.tick();
+
+        self.ticks_since_boost += 1;
+        if self.ticks_since_boost >= MLFQ_BOOST_TICKS {
+            self.ticks_since_boost = 0;
+            running.mlfq.boost();
+            let (top, rest) = self.queues.split_at_mut(1);
+            for queue in rest {
+                top[0].extend(queue.drain(..));
+            }
+            for tcb in &mut top[0] {
+                tcb.mlfq.boost();
+            }
+        }
+
+        expired
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MLFQPriority, MLFQ_BOOST_TICKS, MLFQ_LEVELS};
+
+    #[test]
+    fn io_bound_stays_above_cpu_bound() {
+        let mut cpu_bound = MLFQPriority::default();
+        let mut io_bound = MLFQPriority::default();
+
+        // Over one boost interval, the timer almost always finds the CPU-bound thread running, and
+        // only occasionally the I/O-bound one, since it spends most of its time blocked.
+        for tick in 0..MLFQ_BOOST_TICKS {
+            if tick % 25 == 0 {
+                io_bound.tick();
+            } else {
+                cpu_bound.tick();
+            }
+        }
+
+        assert_eq!(cpu_bound.level(), MLFQ_LEVELS - 1);
+        assert!(io_bound.level() < cpu_bound.level());
+
+        cpu_bound.boost();
+        assert_eq!(cpu_bound.level(), 0);
+    }
+
+    #[test]
+    fn demoted_after_quantum() {
+        let mut priority = MLFQPriority::default();
+        // the top level's quantum is a single tick
+        assert!(priority.tick());
+        assert_eq!(priority.level(), 1);
+        assert!(!priority.tick());
+        assert!(priority.tick());
+        assert_eq!(priority.level(), 2);
+        // and it can't go any lower
+        for _ in 0..10 {
+            priority.tick();
+        }
+        assert_eq!(priority.level(), MLFQ_LEVELS - 1);
+    }
+
+    #[test]
+    fn idle_stays_idle() {
+        let mut idle = MLFQPriority::idle();
+        assert!(idle.is_idle());
+        idle.tick();
+        idle.boost();
+        assert!(idle.is_idle());
+        assert!(!MLFQPriority::default().is_idle());
+    }
+}
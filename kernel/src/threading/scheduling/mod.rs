@@ -1,28 +1,59 @@
 mod fifo_scheduler;
+mod mlfq_scheduler;
 mod scheduler;
+mod stats;
+mod stride_scheduler;
 
-pub use fifo_scheduler::FIFOScheduler;
+pub use mlfq_scheduler::MLFQPriority;
 pub use scheduler::Scheduler;
+pub use stats::SchedulerStats;
+pub use stride_scheduler::DEFAULT_TICKETS;
 
 use alloc::boxed::Box;
 
 use super::{context_switch::switch_threads, thread_control_block::ThreadStatus};
-use crate::interrupts::{intr_get_level, mutex_irq::hold_interrupts, IntrLevel};
+use crate::interrupts::{
+    intr_get_level, mutex_irq::hold_interrupts, softirq::run_softirqs, IntrLevel,
+};
 use crate::system::unwrap_system;
 
+#[cfg(all(feature = "mlfq_scheduler", feature = "stride_scheduler"))]
+compile_error!("only one of the mlfq_scheduler and stride_scheduler features can be enabled");
+
+#[cfg(not(any(feature = "mlfq_scheduler", feature = "stride_scheduler")))]
+type DefaultScheduler = fifo_scheduler::FIFOScheduler;
+
+#[cfg(feature = "mlfq_scheduler")]
+type DefaultScheduler = mlfq_scheduler::MLFQScheduler;
+
+#[cfg(feature = "stride_scheduler")]
+type DefaultScheduler = stride_scheduler::StrideScheduler;
+
 pub fn create_scheduler() -> Box<dyn Scheduler + Send> {
     assert_eq!(intr_get_level(), IntrLevel::IntrOff);
 
     // SAFETY: Interrupts should be off.
-    Box::new(FIFOScheduler::new())
+    Box::new(DefaultScheduler::new())
 }
 
-/// Voluntarily relinquishes control of the CPU to another processor in the scheduler.
-fn scheduler_yield(status_for_current_thread: ThreadStatus) {
+/// Relinquishes control of the CPU to another processor in the scheduler.
+fn scheduler_yield(status_for_current_thread: ThreadStatus, voluntary: bool) {
     let _guard = hold_interrupts(IntrLevel::IntrOff);
 
+    // Involuntary yields come from interrupt handlers, which softirqs are meant to run outside of.
+    if voluntary {
+        run_softirqs();
+    }
+
+    unwrap_system().threads.stats.record_yield(voluntary);
+
     let mut scheduler = unwrap_system().threads.scheduler.lock();
 
+    // Otherwise we'd go round the blocked threads forever, since interrupts are off.
+    if !scheduler.has_ready() {
+        return;
+    }
+
     while let Some(switch_to) = scheduler.pop() {
         // Check if the thread is not blocked.
         match switch_to.as_ref().status {
@@ -32,6 +63,14 @@ fn scheduler_yield(status_for_current_thread: ThreadStatus) {
             }
             _ => {
                 drop(scheduler);
+                let mut running = unwrap_system().threads.running_thread.lock();
+                let usage = &mut running.as_mut().expect("Why is nothing running!?").usage;
+                if voluntary {
+                    usage.voluntary_switches += 1;
+                } else {
+                    usage.involuntary_switches += 1;
+                }
+                drop(running);
                 // SAFETY: Threads and Scheduler must be initialized and active.
                 // Interrupts must be disabled.
                 unsafe {
@@ -48,12 +87,33 @@ fn scheduler_yield(status_for_current_thread: ThreadStatus) {
 
 // Voluntarily relinquishes control of the CPU and marks current thread as ready.
 pub fn scheduler_yield_and_continue() {
-    scheduler_yield(ThreadStatus::Ready);
+    scheduler_yield(ThreadStatus::Ready, true);
+}
+
+/// Takes the CPU away from the current thread (e.g. when the timer goes off), leaving it ready to
+/// run again.
+pub fn scheduler_preempt() {
+    scheduler_yield(ThreadStatus::Ready, false);
+}
+
+/// Called on each timer tick, preempting the current thread if the scheduler says it's had the
+/// CPU for long enough.
+pub fn scheduler_tick() {
+    let threads = &unwrap_system().threads;
+    let preempt = {
+        let mut running = threads.running_thread.lock();
+        let running = running.as_mut().expect("Why is nothing running!?");
+        running.usage.cpu_ticks += 1;
+        threads.scheduler.lock().tick(running)
+    };
+    if preempt {
+        scheduler_preempt();
+    }
 }
 
 /// Voluntarily relinquishes control of the CPU and marks the current thread to die.
 pub fn scheduler_yield_and_die() -> ! {
-    scheduler_yield(ThreadStatus::Dying);
+    scheduler_yield(ThreadStatus::Dying, true);
 
     panic!("A thread was rescheduled after dying.");
 }
@@ -61,5 +121,5 @@ pub fn scheduler_yield_and_die() -> ! {
 /// Voluntarily relinquishes control of the CPU and marks the current thread as blocked.
 #[allow(unused)]
 pub fn scheduler_yield_and_block() {
-    scheduler_yield(ThreadStatus::Blocked);
+    scheduler_yield(ThreadStatus::Blocked, true);
 }
@@ -12,4 +12,19 @@ pub trait Scheduler {
     fn pop(&mut self) -> Option<Box<ThreadControlBlock>>;
     fn remove(&mut self, tid: Tid) -> Option<Box<ThreadControlBlock>>;
     fn get_mut(&mut self, tid: Tid) -> Option<&mut ThreadControlBlock>;
+    /// The number of threads waiting in the scheduler.
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Whether any of the threads waiting in the scheduler are ready to run, rather than blocked.
+    fn has_ready(&self) -> bool;
+    /// Called on each timer tick with the running thread, returning whether it should be
+    /// preempted. By default, threads are preempted on every tick.
+    fn tick(&mut self, _running: &mut ThreadControlBlock) -> bool {
+        true
+    }
+    /// Called when `thread.priority` has been changed, so the scheduler can take it into account.
+    /// By default, priorities are ignored.
+    fn set_priority(&mut self, _thread: &mut ThreadControlBlock) {}
 }
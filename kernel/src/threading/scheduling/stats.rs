@@ -0,0 +1,74 @@
+use crate::user_program::syscall::SchedStats;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counters for tuning the scheduler, reported by the `sched_stats` syscall.
+#[derive(Default)]
+pub struct SchedulerStats {
+    context_switches: AtomicUsize,
+    voluntary_yields: AtomicUsize,
+    preemptions: AtomicUsize,
+    idle_halts: AtomicUsize,
+}
+
+impl SchedulerStats {
+    pub const fn new() -> Self {
+        Self {
+            context_switches: AtomicUsize::new(0),
+            voluntary_yields: AtomicUsize::new(0),
+            preemptions: AtomicUsize::new(0),
+            idle_halts: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn record_context_switch(&self) {
+        self.context_switches.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a thread giving up the CPU, either because it chose to (`voluntary`), or because an
+    /// interrupt made it.
+    pub fn record_yield(&self, voluntary: bool) {
+        let counter = if voluntary {
+            &self.voluntary_yields
+        } else {
+            &self.preemptions
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_idle_halt(&self) {
+        self.idle_halts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Get the counters, along with the length of the run queue, which the scheduler knows.
+    pub fn snapshot(&self, run_queue_length: usize) -> SchedStats {
+        SchedStats {
+            context_switches: self.context_switches.load(Ordering::Relaxed),
+            run_queue_length,
+            voluntary_yields: self.voluntary_yields.load(Ordering::Relaxed),
+            preemptions: self.preemptions.load(Ordering::Relaxed),
+            idle_halts: self.idle_halts.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SchedulerStats;
+
+    #[test]
+    fn counters() {
+        let stats = SchedulerStats::new();
+        for _ in 0..3 {
+            stats.record_yield(true);
+            stats.record_context_switch();
+        }
+        stats.record_yield(false);
+        stats.record_idle_halt();
+        let snapshot = stats.snapshot(2);
+        assert_eq!(snapshot.context_switches, 3);
+        assert_eq!(snapshot.voluntary_yields, 3);
+        assert_eq!(snapshot.preemptions, 1);
+        assert_eq!(snapshot.idle_halts, 1);
+        assert_eq!(snapshot.run_queue_length, 2);
+    }
+}
@@ -0,0 +1,145 @@
+use super::super::thread_control_block::ThreadStatus;
+use super::super::ThreadControlBlock;
+use super::scheduler::Scheduler;
+use crate::threading::process::Tid;
+use crate::user_program::syscall::SCHED_PRIORITY_MAX;
+use alloc::{boxed::Box, collections::VecDeque};
+
+/// The number of tickets a thread starts with.
+pub const DEFAULT_TICKETS: usize = 100;
+
+/// The stride of a thread with a single ticket. Every thread's stride is this divided by its
+/// ticket count, so this should be much bigger than any ticket count.
+const STRIDE1: u64 = 1 << 20;
+
+/// How far a thread's pass moves each time it's run, given its ticket count.
+pub fn stride(tickets: usize) -> u64 {
+    STRIDE1 / tickets.max(1) as u64
+}
+
+/// The number of tickets a thread gets for its priority, from twice `DEFAULT_TICKETS` for the
+/// highest priority, down to a twentieth of it for the lowest.
+fn tickets_for_priority(priority: i32) -> usize {
+    (SCHED_PRIORITY_MAX + 1 - priority) as usize * DEFAULT_TICKETS / 20
+}
+
+/// Pick the ready thread with the lowest pass, out of `(pass, ready)` for each thread, returning
+/// its index. Ties go to the thread which comes first.
+fn next_index(threads: impl Iterator<Item = (u64, bool)>) -> Option<usize> {
+    threads
+        .enumerate()
+        .filter(|(_, (_, ready))| *ready)
+        .min_by_key(|(_, (pass, _))| *pass)
+        .map(|(index, _)| index)
+}
+
+/// A stride scheduler: each thread gets a share of the CPU in proportion to its `tickets`.
+///
+/// Each thread has a pass, which moves forward by its stride (inversely proportional to its
+/// tickets) each time it runs, and the thread with the lowest pass runs next.
+#[cfg_attr(not(feature = "stride_scheduler"), allow(dead_code))]
+pub struct StrideScheduler {
+    ready_queue: VecDeque<Box<ThreadControlBlock>>,
+    /// The pass of the last thread picked to run
+    global_pass: u64,
+}
+
+// TODO: Will be removed, requires a change to stack type.
+// SAFETY: Schedulers should be run with interrupts disabled.
+unsafe impl Sync for StrideScheduler {}
+
+impl Scheduler for StrideScheduler {
+    fn new() -> StrideScheduler {
+        StrideScheduler {
+            ready_queue: VecDeque::new(),
+            global_pass: 0,
+        }
+    }
+
+    fn push(&mut self, mut thread: Box<ThreadControlBlock>) {
+        // A thread which is new, or has been blocked for a while, would otherwise be far behind
+        // and get the CPU to itself until it caught up.
+        thread.pass = thread.pass.max(self.global_pass);
+        self.ready_queue.push_back(thread);
+    }
+
+    fn pop(&mut self) -> Option<Box<ThreadControlBlock>> {
+        let index = next_index(
+            self.ready_queue
+                .iter()
+                .map(|tcb| (tcb.pass, tcb.status == ThreadStatus::Ready)),
+        )?;
+        let mut thread = self.ready_queue.remove(index)?;
+        self.global_pass = thread.pass;
+        thread.pass += stride(thread.tickets);
+        Some(thread)
+    }
+
+    fn remove(&mut self, tid: Tid) -> Option<Box<ThreadControlBlock>> {
+        let pos = self.ready_queue.iter().position(|tcb| tcb.tid == tid);
+        pos.and_then(|index| self.ready_queue.remove(index))
+    }
+
+    fn get_mut(&mut self, tid: Tid) -> Option<&mut ThreadControlBlock> {
+        let pos = self.ready_queue.iter().position(|tcb| tcb.tid == tid);
+        pos.and_then(|index| self.ready_queue.get_mut(index).map(|tcb| &mut **tcb))
+    }
+
+    fn len(&self) -> usize {
+        self.ready_queue.len()
+    }
+
+    fn has_ready(&self) -> bool {
+        self.ready_queue
+            .iter()
+            .any(|tcb| tcb.status == ThreadStatus::Ready)
+    }
+
+    fn set_priority(&mut self, thread: &mut ThreadControlBlock) {
+        thread.tickets = tickets_for_priority(thread.priority);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{next_index, stride, tickets_for_priority, DEFAULT_TICKETS};
+    use crate::user_program::syscall::{SCHED_PRIORITY_MAX, SCHED_PRIORITY_MIN};
+
+    #[test]
+    fn proportional_share() {
+        let tickets = [300, 100];
+        let mut passes = [0, 0];
+        let mut runs = [0usize, 0];
+
+        for _ in 0..400 {
+            let index = next_index(passes.iter().map(|&pass| (pass, true))).unwrap();
+            passes[index] += stride(tickets[index]);
+            runs[index] += 1;
+        }
+
+        // 3:1, give or take a run for rounding
+        assert!(runs[0].abs_diff(300) <= 1, "{runs:?}");
+        assert!(runs[1].abs_diff(100) <= 1, "{runs:?}");
+    }
+
+    #[test]
+    fn priority_tickets() {
+        assert_eq!(tickets_for_priority(0), DEFAULT_TICKETS);
+        assert_eq!(
+            tickets_for_priority(SCHED_PRIORITY_MIN),
+            2 * DEFAULT_TICKETS
+        );
+        assert_eq!(
+            tickets_for_priority(SCHED_PRIORITY_MAX),
+            DEFAULT_TICKETS / 20
+        );
+    }
+
+    #[test]
+    fn skips_blocked_threads() {
+        assert_eq!(next_index([(5, false), (10, true)].into_iter()), Some(1));
+        assert_eq!(next_index([(5, false)].into_iter()), None);
+        // ties go to whichever is first
+        assert_eq!(next_index([(10, true), (10, true)].into_iter()), Some(0));
+    }
+}
@@ -1,8 +1,13 @@
 use super::thread_functions::{PrepareThreadContext, SwitchThreadsContext, ThreadFunction};
-use crate::fs::fs_manager::RootFileSystem;
+use crate::fs::fs_manager::{RootFileSystem, MAX_OPEN_FILES};
 use crate::system::{running_thread_ppid, unwrap_system};
 use crate::threading::process::{Pid, ProcessState, Tid};
+use crate::threading::scheduling::{MLFQPriority, DEFAULT_TICKETS};
+use crate::threading::thread_join::JoinTable;
 use crate::user_program::elf::{ElfArchitecture, ElfProgramType, ElfUsage};
+use crate::user_program::rusage::NO_CPU_LIMIT;
+use crate::user_program::signal::Signals;
+use crate::user_program::syscall::{RLimit, RUsage, SyscallFrame, TASK_COMM_LEN};
 use crate::{
     fs::fs_manager::FileSystemID,
     mem::vma::{VMAInfo, VMAList, VMA},
@@ -15,7 +20,8 @@ use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::{
     mem::size_of,
-    ptr::{copy_nonoverlapping, write_bytes, NonNull},
+    ptr::{write_bytes, NonNull},
+    slice,
 };
 use kidneyos_shared::mem::{OFFSET, PAGE_FRAME_SIZE};
 
@@ -42,17 +48,52 @@ pub struct ProcessControlBlock {
     pub pid: Pid,
     // The Pid of the process' parent
     pub ppid: Pid,
+    /// The process group this process is in, for job control
+    pub pgid: Pid,
+    /// Set when Ctrl-C is typed while this process is in the terminal's foreground process group.
+    /// It's sent `SIGINT` the next time it makes a syscall.
+    pub interrupted: bool,
     // The TIDs of this process' children threads
     pub child_tids: Vec<Tid>,
     // The TIDs of the threads waiting on this process to end
     pub waiting_thread: Option<Tid>,
+    /// Exit codes of this process' threads which haven't been joined yet
+    pub joins: JoinTable,
 
     pub exit_code: Option<i32>,
+    /// The signal which killed the process, if it didn't exit by itself. For processes killed by
+    /// the kernel, e.g. for a segfault, this is the one Linux would have killed it with.
+    pub killed_by: Option<i32>,
+    /// The process this one took the place of with `execve`, if any. Its parent only knows its
+    /// pid, so it exits along with this one.
+    pub replaces: Option<Pid>,
+    /// The process and thread which made this process with `vfork`. They're suspended, and this
+    /// process borrows their memory, until it execs or exits.
+    pub vfork_parent: Option<(Pid, Tid)>,
+    /// Signals sent to the process, and which of them it's blocked
+    pub signals: Signals,
+    /// The process' name, for debugging: the base name of the program it's running, unless it's
+    /// changed it with `prctl(PR_SET_NAME)`. Null-terminated, unless it's the full length.
+    pub comm: [u8; TASK_COMM_LEN - 1],
     /// filesystem and inode of current working directory
     pub cwd: (FileSystemID, INodeNum),
     /// path to cwd (needed for getcwd syscall)
     pub cwd_path: OwnedPath,
+    /// filesystem and inode absolute paths are resolved from, if the process has been
+    /// chrooted (otherwise it's the root of the root filesystem)
+    pub root: Option<(FileSystemID, INodeNum)>,
     pub vmas: VMAList,
+    /// start of the program heap, just past the end of the loaded ELF segments
+    pub heap_start: usize,
+    /// current program break (end of the heap), as set by the brk syscall
+    pub program_break: usize,
+    /// Resources used by the process' threads which have exited
+    pub usage: RUsage,
+    /// Limit on CPU time in seconds, as set with `setrlimit(RLIMIT_CPU)`
+    pub cpu_limit: RLimit,
+    /// Limit on open files, as set with `setrlimit(RLIMIT_NOFILE)`: new file descriptors must be
+    /// less than this. It's never more than [`MAX_OPEN_FILES`].
+    pub fd_limit: u16,
 }
 
 impl ProcessControlBlock {
@@ -64,7 +105,7 @@ impl ProcessControlBlock {
         let pid = state.allocate_pid();
         // open stdin, stdout, stderr
         root.open_standard_fds(pid);
-        // TODO: inherit cwd from parent
+        // exec and vfork replace this with the parent's cwd
         let cwd = root.get_root().unwrap();
         let mut vmas = VMAList::new();
         // set up stack
@@ -78,16 +119,74 @@ impl ProcessControlBlock {
         let pcb = Self {
             pid,
             ppid: parent_pid,
+            // every process starts off as the leader of its own group
+            pgid: pid,
+            interrupted: false,
             child_tids: Vec::new(),
             waiting_thread: None,
+            joins: JoinTable::default(),
             exit_code: None,
+            killed_by: None,
+            replaces: None,
+            vfork_parent: None,
+            signals: Signals::default(),
+            comm: [0; TASK_COMM_LEN - 1],
             vmas,
+            heap_start: 0,
+            program_break: 0,
             cwd,
             cwd_path: "/".into(),
+            root: None,
+            usage: RUsage::default(),
+            cpu_limit: NO_CPU_LIMIT,
+            fd_limit: MAX_OPEN_FILES,
         };
 
         state.table.add(pcb)
     }
+
+    /// A process `pid` for tests, with its cwd at `cwd`, which isn't added to the process table
+    /// and has nothing mapped.
+    #[cfg(test)]
+    pub fn for_test(pid: Pid, cwd: (FileSystemID, INodeNum)) -> Self {
+        Self {
+            pid,
+            ppid: 0,
+            pgid: pid,
+            interrupted: false,
+            child_tids: Vec::new(),
+            waiting_thread: None,
+            joins: JoinTable::default(),
+            exit_code: None,
+            killed_by: None,
+            replaces: None,
+            vfork_parent: None,
+            signals: Signals::default(),
+            comm: [0; TASK_COMM_LEN - 1],
+            vmas: VMAList::new(),
+            heap_start: 0,
+            program_break: 0,
+            cwd,
+            cwd_path: "/".into(),
+            root: None,
+            usage: RUsage::default(),
+            cpu_limit: NO_CPU_LIMIT,
+            fd_limit: MAX_OPEN_FILES,
+        }
+    }
+
+    /// Set the process' name to `name`, truncated to `TASK_COMM_LEN - 1` bytes.
+    pub fn set_comm(&mut self, name: &[u8]) {
+        let len = name.len().min(self.comm.len());
+        self.comm = [0; TASK_COMM_LEN - 1];
+        self.comm[..len].copy_from_slice(&name[..len]);
+    }
+
+    /// The process' name, without the null terminator.
+    pub fn comm(&self) -> &[u8] {
+        let len = self.comm.iter().position(|&b| b == 0);
+        &self.comm[..len.unwrap_or(self.comm.len())]
+    }
 }
 
 // TODO: Use enums so that we never have garbage data (i.e. stacks that don't
@@ -114,6 +213,26 @@ pub struct ThreadControlBlock {
     pub status: ThreadStatus,
     pub exit_code: Option<i32>,
     pub page_manager: PageManager,
+    /// The priority set with `sched_setparam` or `nice`, from `SCHED_PRIORITY_MIN` (highest) to
+    /// `SCHED_PRIORITY_MAX` (lowest). What it does depends on the scheduler.
+    pub priority: i32,
+    /// The thread's priority, if the `MLFQScheduler` is in use
+    pub mlfq: MLFQPriority,
+    /// The thread's share of the CPU, relative to other threads, if the `StrideScheduler` is in
+    /// use
+    pub tickets: usize,
+    /// How far the thread has got, if the `StrideScheduler` is in use. The thread with the lowest
+    /// pass runs next.
+    pub pass: u64,
+    /// Resources the thread has used, for `getrusage`
+    pub usage: RUsage,
+    /// Timer ticks the thread can use before its process is killed for going over its
+    /// `RLIMIT_CPU` limit, if it has one. It's kept here, rather than with the limit in the
+    /// process, so that the timer interrupt handler can check it.
+    pub cpu_limit: Option<usize>,
+    /// The registers the thread starts off with in userspace, if it starts off returning from a
+    /// syscall, like the child of `vfork`, rather than at `eip`.
+    pub user_frame: Option<SyscallFrame>,
 }
 
 #[derive(Debug)]
@@ -124,14 +243,14 @@ pub enum ThreadElfCreateError {
 }
 
 impl ThreadControlBlock {
+    /// Create a new process running the program `elf`, which is named after the base name of
+    /// `path`.
     pub fn new_from_elf(
         elf: Elf,
+        path: &str,
         state: &ProcessState,
     ) -> Result<ThreadControlBlock, ThreadElfCreateError> {
-        // Shared ELFs can count as a "Relocatable Executable" if the entry point is set.
-        let executable = matches!(elf.header.usage, ElfUsage::Executable | ElfUsage::Shared);
-
-        if !executable {
+        if elf.header.usage != ElfUsage::Executable {
             return Err(ThreadElfCreateError::NotExecutable);
         }
 
@@ -147,24 +266,24 @@ impl ThreadControlBlock {
         };
         let pcb =
             ProcessControlBlock::create(state, &mut unwrap_system().root_filesystem.lock(), ppid);
-        let pcb = pcb.lock();
+        let mut pcb = pcb.lock();
         let pid = pcb.pid;
+        pcb.set_comm(path.rsplit('/').next().unwrap_or(path).as_bytes());
         let mut page_manager = PageManager::default();
+        let mut segments_end = 0;
 
         for program_header in elf.program_headers {
             if program_header.program_type != ElfProgramType::Load {
                 continue;
             }
 
-            // Some ELF files have off-alignment segments (off 4KB).
-            // We need to pad this space with zeroes.
-            let segment_virtual_frame_start =
-                program_header.virtual_address as usize / PAGE_FRAME_SIZE;
-            let segment_virtual_start = segment_virtual_frame_start * PAGE_FRAME_SIZE;
-            let segment_padding = program_header.virtual_address as usize % PAGE_FRAME_SIZE;
-            let segment_padded_size = segment_padding + program_header.data.len();
-
-            let frames = segment_padded_size.div_ceil(PAGE_FRAME_SIZE);
+            // Segments don't have to be page-aligned, so map every page they touch.
+            let pages = program_header.page_range();
+            if pages.is_empty() {
+                continue;
+            }
+            let frames = pages.len() / PAGE_FRAME_SIZE;
+            segments_end = segments_end.max(pages.end);
 
             unsafe {
                 // TODO: Save this physical address somewhere so we can deallocate
@@ -176,39 +295,28 @@ impl ThreadControlBlock {
                     .as_ptr();
                 let phys_addr = kernel_virt_addr.sub(OFFSET);
 
-                // TODO: Throw an error if this range overlaps any previously mapped
-                // ranges, since `map_range` requires that the input range has not
-                // already been mapped.
+                // `Elf::parse_bytes` has already checked that segments don't share any pages,
+                // since `map_range` requires that the input range has not already been mapped.
 
                 // Map the physical address obtained by the allocation above to the
                 // virtual address assigned by the ELF header.
                 page_manager.map_range(
                     phys_addr as usize,
-                    segment_virtual_start,
-                    frames * PAGE_FRAME_SIZE,
+                    pages.start,
+                    pages.len(),
                     program_header.writable,
                     true,
                 );
 
-                write_bytes(kernel_virt_addr, 0, segment_padded_size);
-
                 // Load so we can write to the virtual addresses mapped above.
-                copy_nonoverlapping(
-                    program_header.data.as_ptr(),
-                    kernel_virt_addr.add(segment_padding),
-                    program_header.data.len(),
-                );
-
-                // Zero the sliver of addresses between the end of the region, and
-                // the end of the region we had to map due to page
-                write_bytes(
-                    kernel_virt_addr.add(segment_padded_size),
-                    0,
-                    frames * PAGE_FRAME_SIZE - segment_padded_size,
-                );
+                program_header.load(slice::from_raw_parts_mut(kernel_virt_addr, pages.len()));
             }
         }
 
+        // The heap starts out empty, right after the last segment.
+        pcb.heap_start = segments_end;
+        pcb.program_break = segments_end;
+
         Ok(ThreadControlBlock::new_with_page_manager(
             NonNull::new(elf.header.program_entry as *mut u8)
                 .ok_or(ThreadElfCreateError::InvalidEntryPoint)?,
@@ -249,7 +357,7 @@ impl ThreadControlBlock {
         is_kernel: bool,
         parent_pid: Pid,
         file_system: &mut RootFileSystem,
-        state: &mut ProcessState,
+        state: &ProcessState,
     ) -> Self {
         let entry = NonNull::new(eip as *mut u8).unwrap();
 
@@ -317,6 +425,13 @@ impl ThreadControlBlock {
             status: ThreadStatus::Invalid,
             exit_code: None,
             page_manager,
+            priority: 0,
+            mlfq: MLFQPriority::default(),
+            tickets: DEFAULT_TICKETS,
+            pass: 0,
+            usage: RUsage::default(),
+            cpu_limit: None,
+            user_frame: None,
         }
     }
 
@@ -357,6 +472,13 @@ impl ThreadControlBlock {
             status: ThreadStatus::Running,
             exit_code: None,
             page_manager,
+            priority: 0,
+            mlfq: MLFQPriority::default(),
+            tickets: DEFAULT_TICKETS,
+            pass: 0,
+            usage: RUsage::default(),
+            cpu_limit: None,
+            user_frame: None,
         }
     }
 
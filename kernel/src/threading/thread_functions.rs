@@ -1,6 +1,8 @@
 use super::process::Tid;
 use super::thread_control_block::{ThreadControlBlock, ThreadStatus};
+use super::thread_sleep::thread_wakeup;
 use crate::system::unwrap_system;
+use crate::user_program::rusage::add_usage;
 use crate::{
     interrupts::{intr_disable, intr_enable},
     threading::scheduling::scheduler_yield_and_die,
@@ -31,7 +33,19 @@ pub fn exit_thread(exit_code: i32) -> ! {
     let mut guard = threads.running_thread.lock();
     let mut current_thread = guard.as_mut().expect("Why is nothing running!?");
     current_thread.set_exit_code(exit_code);
+    let (tid, pid, usage) = (current_thread.tid, current_thread.pid, current_thread.usage);
     drop(guard);
+
+    if let Some(pcb) = unwrap_system().process.table.get(pid) {
+        let mut pcb = pcb.lock();
+        // The process' resource usage still includes this thread's once it's gone.
+        add_usage(&mut pcb.usage, &usage);
+        // Keep the exit code around for whichever thread joins this one.
+        if let Some(joiner) = pcb.joins.exit(tid, exit_code) {
+            thread_wakeup(joiner);
+        }
+    }
+
     // Yield.
     scheduler_yield_and_die();
 }
@@ -78,6 +92,7 @@ unsafe extern "C" fn run_thread(
         eip,
         esp,
         is_kernel,
+        user_frame,
         ..
     } = *switched_to;
 
@@ -103,6 +118,29 @@ unsafe extern "C" fn run_thread(
 
         // Safely exit the thread.
         exit_thread(exit_code);
+    } else if let Some(frame) = user_frame {
+        // Return from a syscall the way the syscall handler does, restoring every register.
+        asm!(
+            "
+            mov ds, {data_sel:x}
+            mov es, {data_sel:x}
+            mov fs, {data_sel:x}
+            mov gs, {data_sel:x}
+
+            mov esp, {frame}
+            pop eax
+            pop ebx
+            pop ecx
+            pop edx
+            pop esi
+            pop edi
+            pop ebp
+            iretd
+            ",
+            data_sel = in(reg) USER_DATA_SELECTOR,
+            frame = in(reg) &frame,
+            options(noreturn),
+        )
     } else {
         // https://wiki.osdev.org/Getting_to_Ring_3#iret_method
         // https://web.archive.org/web/20160326062442/http://jamesmolloy.co.uk/tutorial_html/10.-User%20Mode.html
@@ -0,0 +1,194 @@
+use super::process::{Pid, Tid};
+use super::thread_sleep::thread_sleep;
+use crate::interrupts::{intr_disable, intr_enable};
+use crate::system::{running_process, running_thread_pid, running_thread_tid, unwrap_system};
+use crate::user_program::syscall::{EDEADLK, EINVAL, ESRCH};
+use crate::user_program::user_copy::copy_to_user;
+use alloc::collections::{BTreeMap, BTreeSet};
+
+/// Exit statuses of a process' threads, kept until another thread in the process joins them.
+///
+/// Threads are joinable unless they've been detached, in which case nothing is kept when they
+/// exit.
+#[derive(Debug, Default)]
+pub struct JoinTable {
+    /// Exit codes of threads which have exited but haven't been joined yet
+    exited: BTreeMap<Tid, i32>,
+    /// The thread waiting to join each thread, by the thread being joined
+    joiners: BTreeMap<Tid, Tid>,
+    detached: BTreeSet<Tid>,
+}
+
+impl JoinTable {
+    /// Record that `tid` exited with `exit_code`, returning the thread waiting to join it, if any,
+    /// which should be woken.
+    pub fn exit(&mut self, tid: Tid, exit_code: i32) -> Option<Tid> {
+        if self.detached.remove(&tid) {
+            return None;
+        }
+        self.exited.insert(tid, exit_code);
+        self.joiners.remove(&tid)
+    }
+
+    /// Join `tid` from `joiner`, which must be a live thread in the same process if it hasn't
+    /// exited yet.
+    ///
+    /// Returns its exit code if it has exited, which can only be taken once. Otherwise, `joiner`
+    /// is recorded as waiting on it, and should sleep until it's woken, then try again.
+    pub fn join(&mut self, tid: Tid, joiner: Tid) -> Result<Option<i32>, isize> {
+        if tid == joiner {
+            return Err(EDEADLK);
+        }
+        if self.detached.contains(&tid) {
+            return Err(EINVAL);
+        }
+        if let Some(exit_code) = self.exited.remove(&tid) {
+            return Ok(Some(exit_code));
+        }
+        match self.joiners.get(&tid) {
+            Some(&waiting) if waiting != joiner => Err(EINVAL),
+            _ => {
+                self.joiners.insert(tid, joiner);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Detach `tid`, so its exit code isn't kept. If it has already exited, its exit code is
+    /// dropped now.
+    pub fn detach(&mut self, tid: Tid) -> Result<(), isize> {
+        if self.exited.remove(&tid).is_some() {
+            return Ok(());
+        }
+        if self.detached.contains(&tid) || self.joiners.contains_key(&tid) {
+            return Err(EINVAL);
+        }
+        self.detached.insert(tid);
+        Ok(())
+    }
+
+    /// Whether `tid` has exited and is waiting to be joined.
+    pub fn has_exited(&self, tid: Tid) -> bool {
+        self.exited.contains_key(&tid)
+    }
+}
+
+/// Whether `tid` is a thread of the process `pid` which hasn't exited yet.
+///
+/// Interrupts must be disabled, so the thread can't exit while the answer is being used.
+fn is_live_thread(tid: Tid, pid: Pid) -> bool {
+    if tid == running_thread_tid() {
+        return true;
+    }
+    unwrap_system()
+        .threads
+        .scheduler
+        .lock()
+        .get_mut(tid)
+        .is_some_and(|tcb| tcb.pid == pid)
+}
+
+/// The `thread_join` syscall: wait for the thread `tid` in this process to exit, and store its
+/// exit code in `retval` unless it's null.
+pub fn thread_join(tid: Tid, retval: *mut i32) -> isize {
+    let pid = running_thread_pid();
+    let joiner = running_thread_tid();
+    let pcb = running_process();
+
+    let exit_code = loop {
+        intr_disable();
+        let result = {
+            let mut pcb = pcb.lock();
+            if !pcb.joins.has_exited(tid) && !is_live_thread(tid, pid) {
+                Err(ESRCH)
+            } else {
+                pcb.joins.join(tid, joiner)
+            }
+        };
+        // Interrupts are still off, so the thread can't exit, and wake this one, before it's
+        // blocked.
+        if let Ok(None) = result {
+            thread_sleep();
+        }
+        intr_enable();
+        match result {
+            Ok(Some(exit_code)) => break exit_code,
+            Ok(None) => {}
+            Err(e) => return -e,
+        }
+    };
+
+    if !retval.is_null() {
+        if let Err(e) = copy_to_user(retval, &[exit_code]) {
+            return -e;
+        }
+    }
+    0
+}
+
+/// The `thread_detach` syscall: have the thread `tid` in this process be cleaned up entirely once
+/// it exits, without being joined.
+pub fn thread_detach(tid: Tid) -> isize {
+    let pid = running_thread_pid();
+    let pcb = running_process();
+
+    intr_disable();
+    let result = {
+        let mut pcb = pcb.lock();
+        if !pcb.joins.has_exited(tid) && !is_live_thread(tid, pid) {
+            Err(ESRCH)
+        } else {
+            pcb.joins.detach(tid)
+        }
+    };
+    intr_enable();
+
+    match result {
+        Ok(()) => 0,
+        Err(e) => -e,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::JoinTable;
+    use crate::user_program::syscall::{EDEADLK, EINVAL};
+
+    #[test]
+    fn join_after_exit() {
+        let mut joins = JoinTable::default();
+        assert_eq!(joins.exit(2, 42), None);
+        assert_eq!(joins.join(2, 1), Ok(Some(42)));
+        // the exit code can only be taken once
+        assert!(!joins.has_exited(2));
+    }
+
+    #[test]
+    fn join_before_exit() {
+        let mut joins = JoinTable::default();
+        assert_eq!(joins.join(2, 1), Ok(None));
+        // trying again before it's exited is fine, but another thread can't join it too
+        assert_eq!(joins.join(2, 1), Ok(None));
+        assert_eq!(joins.join(2, 3), Err(EINVAL));
+        assert_eq!(joins.join(1, 1), Err(EDEADLK));
+        // the joiner is woken when it exits
+        assert_eq!(joins.exit(2, 7), Some(1));
+        assert_eq!(joins.join(2, 1), Ok(Some(7)));
+    }
+
+    #[test]
+    fn detach() {
+        let mut joins = JoinTable::default();
+        assert_eq!(joins.detach(2), Ok(()));
+        assert_eq!(joins.detach(2), Err(EINVAL));
+        assert_eq!(joins.join(2, 1), Err(EINVAL));
+        // nothing is kept once it exits
+        assert_eq!(joins.exit(2, 0), None);
+        assert!(!joins.has_exited(2));
+
+        // detaching a thread which has already exited drops its exit code
+        joins.exit(3, 0);
+        assert_eq!(joins.detach(3), Ok(()));
+        assert!(!joins.has_exited(3));
+    }
+}
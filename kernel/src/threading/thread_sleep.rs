@@ -1,14 +1,38 @@
 use super::{scheduling::scheduler_yield_and_block, thread_control_block::ThreadStatus};
+use crate::interrupts::timer::{current_tick, sleep_until, tick_time};
 use crate::system::unwrap_system;
 use crate::threading::process::Tid;
 
+/// Block the running thread until another calls `thread_wakeup` for it.
+///
+/// To wait for a condition, check it with interrupts disabled, so that the wakeup can't come in
+/// between checking and blocking, and check it again once this returns.
 pub fn thread_sleep() {
     scheduler_yield_and_block();
 }
 
+/// Make the thread `tid` runnable again, if it's blocked in `thread_sleep`.
 pub fn thread_wakeup(tid: Tid) {
     let threads = &unwrap_system().threads;
     if let Some(tcb) = threads.scheduler.lock().get_mut(tid) {
         tcb.status = ThreadStatus::Ready;
     }
 }
+
+/// Block the running thread until timer tick number `tick`, as counted by `current_tick`,
+/// returning straight away if it's already come.
+///
+/// Interrupts are enabled while it's blocked, then put back how they were.
+// Only called from code that's stubbed out in tests.
+#[cfg_attr(test, allow(dead_code))]
+pub fn sleep_until_tick(tick: u64) {
+    sleep_until(tick_time(tick));
+}
+
+/// Block the running thread until `ticks` timer ticks after the current one.
+///
+/// Interrupts are enabled while it's blocked, then put back how they were.
+#[cfg_attr(test, allow(dead_code))]
+pub fn sleep_ticks(ticks: u64) {
+    sleep_until_tick(current_tick().saturating_add(ticks));
+}
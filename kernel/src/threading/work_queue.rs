@@ -0,0 +1,112 @@
+use super::process::{AtomicTid, Tid};
+use super::thread_sleep::{thread_sleep, thread_wakeup};
+use crate::interrupts::{intr_disable, intr_enable, mutex_irq::hold_interrupts, IntrLevel};
+use crate::sync::mutex::Mutex;
+use crate::system::running_thread_tid;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use core::sync::atomic::Ordering;
+
+/// A piece of deferred work.
+pub type Work = Box<dyn FnOnce() + Send>;
+
+/// Work queued by interrupt handlers (or anything else) to be run later by a kernel thread, so
+/// the handlers themselves can be kept short.
+pub struct WorkQueue {
+    queue: Mutex<VecDeque<Work>>,
+    /// The thread which runs the work, or 0 if it hasn't started yet.
+    worker: AtomicTid,
+}
+
+impl WorkQueue {
+    pub const fn new() -> Self {
+        Self {
+            queue: Mutex::new(VecDeque::new()),
+            worker: AtomicTid::new(0),
+        }
+    }
+
+    /// Add `work` to the queue, returning the worker thread to wake up for it, if it's started.
+    ///
+    /// Interrupts must be disabled, so that an interrupt handler can't find the queue locked by
+    /// the thread it interrupted.
+    fn push(&self, work: Work) -> Option<Tid> {
+        self.queue.lock().push_back(work);
+        Some(self.worker.load(Ordering::Acquire)).filter(|&tid| tid != 0)
+    }
+
+    /// Take the work which was queued first, if there is any. Interrupts must be disabled, as for
+    /// `push`.
+    fn pop(&self) -> Option<Work> {
+        self.queue.lock().pop_front()
+    }
+}
+
+static WORK_QUEUE: WorkQueue = WorkQueue::new();
+
+/// Run `work` later on the work queue's kernel thread, rather than now. Can be called from
+/// interrupt handlers.
+pub fn schedule_work(work: impl FnOnce() + Send + 'static) {
+    let worker = {
+        let _guard = hold_interrupts(IntrLevel::IntrOff);
+        WORK_QUEUE.push(Box::new(work))
+    };
+    if let Some(tid) = worker {
+        thread_wakeup(tid);
+    }
+}
+
+/// The function run by the work queue's kernel thread: runs queued work, in the order it was
+/// queued, and sleeps while there's none.
+pub extern "C" fn work_queue_worker() -> i32 {
+    WORK_QUEUE
+        .worker
+        .store(running_thread_tid(), Ordering::Release);
+    loop {
+        // Interrupts are off between finding the queue empty and blocking, so work scheduled in
+        // between can't be missed.
+        intr_disable();
+        let work = WORK_QUEUE.pop();
+        if work.is_none() {
+            thread_sleep();
+        }
+        intr_enable();
+        if let Some(work) = work {
+            work();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WorkQueue;
+    use core::sync::atomic::Ordering;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn irq_work_runs_in_order() {
+        let queue = WorkQueue::new();
+        let ran: Arc<Mutex<Vec<u32>>> = Arc::default();
+        let work = |n| {
+            let ran = ran.clone();
+            Box::new(move || ran.lock().unwrap().push(n))
+        };
+
+        // Queued from an IRQ before the worker's started: nothing runs yet, and there's nobody to
+        // wake.
+        assert_eq!(queue.push(work(1)), None);
+        assert_eq!(queue.push(work(2)), None);
+        assert!(ran.lock().unwrap().is_empty());
+
+        // Once it has, pushing says which thread to wake.
+        queue.worker.store(5, Ordering::Release);
+        assert_eq!(queue.push(work(3)), Some(5));
+
+        // The worker runs it all in the order it was queued, then finds the queue empty.
+        while let Some(work) = queue.pop() {
+            work();
+        }
+        assert_eq!(*ran.lock().unwrap(), [1, 2, 3]);
+        assert!(queue.pop().is_none());
+    }
+}
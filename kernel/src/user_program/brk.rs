@@ -0,0 +1,58 @@
+use crate::mem::vma::{VMAInfo, VMA};
+use crate::system::{running_process, unwrap_system};
+use crate::KERNEL_ALLOCATOR;
+use core::ptr::NonNull;
+use kidneyos_shared::mem::{OFFSET, PAGE_FRAME_SIZE};
+
+/// Sets the program break of the running process to `addr`, growing or
+/// shrinking its heap VMA as needed. Pages in the heap are faulted in as zero
+/// pages on first access.
+///
+/// Returns the new program break on success. As with Linux, if `addr` is 0 or
+/// the break can't be moved there (e.g. it would run into another mapping),
+/// the current program break is returned unchanged instead.
+pub fn brk(addr: usize) -> isize {
+    let pcb = running_process();
+    let mut pcb = pcb.lock();
+    let heap_start = pcb.heap_start;
+    let current = pcb.program_break;
+
+    if addr < heap_start || addr >= OFFSET {
+        return current as isize;
+    }
+
+    let old_size = (current - heap_start).next_multiple_of(PAGE_FRAME_SIZE);
+    let new_size = (addr - heap_start).next_multiple_of(PAGE_FRAME_SIZE);
+
+    let resized = if old_size == new_size {
+        true
+    } else if old_size == 0 {
+        pcb.vmas
+            .add_vma(VMA::new(VMAInfo::Heap, new_size, true), heap_start)
+    } else if new_size == 0 {
+        pcb.vmas.remove_vma(heap_start).is_some()
+    } else {
+        pcb.vmas.resize_vma(heap_start, new_size)
+    };
+    if !resized {
+        return current as isize;
+    }
+
+    if new_size < old_size {
+        // Free any pages that were faulted in above the new break.
+        let mut tcb_guard = unwrap_system().threads.running_thread.lock();
+        let tcb = tcb_guard.as_mut().expect("no running thread");
+        for page in (heap_start + new_size..heap_start + old_size).step_by(PAGE_FRAME_SIZE) {
+            // SAFETY: the page is no longer covered by a VMA, so userspace has
+            // given up any pointers into it.
+            if let Some(phys_addr) = unsafe { tcb.page_manager.unmap(page) } {
+                let frame =
+                    NonNull::new((phys_addr + OFFSET) as *mut u8).expect("mapped frame was null");
+                unsafe { KERNEL_ALLOCATOR.frame_dealloc(frame) };
+            }
+        }
+    }
+
+    pcb.program_break = addr;
+    addr as isize
+}
@@ -6,6 +6,64 @@ use nom::number::Endianness;
 use nom::IResult;
 
 use alloc::vec::Vec;
+use core::fmt::{self, Display, Formatter};
+use core::ops::Range;
+use kidneyos_shared::mem::{OFFSET, PAGE_FRAME_SIZE};
+
+/// `EI_CLASS` for 32-bit ELF files.
+const ELFCLASS32: u8 = 1;
+/// `EI_DATA` for little-endian ELF files.
+const ELFDATA2LSB: u8 = 1;
+
+/// Why an ELF file can't be run.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ElfError {
+    /// The file isn't an ELF file, or is cut off or corrupt.
+    Malformed,
+    /// The file is for 64-bit machines.
+    Not32Bit,
+    /// The file is big-endian.
+    NotLittleEndian,
+    /// The file is for a machine other than x86.
+    UnsupportedArchitecture(ElfArchitecture),
+    /// The file is a library, object file or core dump rather than an executable.
+    NotExecutable(ElfUsage),
+    /// The file needs a dynamic linker (it has a `PT_INTERP` segment), which we don't have.
+    DynamicallyLinked,
+    /// The segment loaded at this virtual address overlaps kernel memory.
+    KernelAddress(u32),
+    /// The segments loaded at these virtual addresses share a page, so loading one would
+    /// overwrite the other.
+    OverlappingSegments(u32, u32),
+    /// The entry point isn't in any of the loaded segments.
+    EntryPointOutsideSegments(u32),
+}
+
+impl Display for ElfError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ElfError::Malformed => write!(f, "malformed ELF file"),
+            ElfError::Not32Bit => write!(f, "not a 32-bit ELF file"),
+            ElfError::NotLittleEndian => write!(f, "not a little-endian ELF file"),
+            ElfError::UnsupportedArchitecture(architecture) => {
+                write!(f, "unsupported architecture {architecture:?}")
+            }
+            ElfError::NotExecutable(usage) => write!(f, "not an executable ({usage:?})"),
+            ElfError::DynamicallyLinked => {
+                write!(f, "dynamically linked executables aren't supported")
+            }
+            ElfError::KernelAddress(address) => {
+                write!(f, "segment at {address:#x} overlaps kernel memory")
+            }
+            ElfError::OverlappingSegments(first, second) => {
+                write!(f, "segments at {first:#x} and {second:#x} overlap")
+            }
+            ElfError::EntryPointOutsideSegments(entry) => {
+                write!(f, "entry point {entry:#x} isn't in a loaded segment")
+            }
+        }
+    }
+}
 
 // Endianness of the fields within the Elf file.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -252,6 +310,32 @@ impl<'a> ElfProgramHeader<'a> {
     }
 }
 
+impl ElfProgramHeader<'_> {
+    /// The virtual addresses of the whole pages the segment occupies.
+    ///
+    /// Segments don't have to start or end on a page boundary, e.g. `.text` often starts partway
+    /// into a page, at the same offset into the page as it is in the file.
+    pub fn page_range(&self) -> Range<usize> {
+        let start = self.virtual_address as usize;
+        let end = start + self.memory_size as usize;
+        (start - start % PAGE_FRAME_SIZE)..end.next_multiple_of(PAGE_FRAME_SIZE)
+    }
+
+    /// Fill `pages`, which will be mapped at the start of [`Self::page_range`], with the segment.
+    ///
+    /// The data from the file goes at the segment's offset into its first page. Everything else
+    /// is zeroed: the gap before that, the rest of the segment after the file data (e.g. `.bss`),
+    /// and the rest of the last page.
+    pub fn load(&self, pages: &mut [u8]) {
+        debug_assert_eq!(pages.len(), self.page_range().len());
+        let start = self.virtual_address as usize % PAGE_FRAME_SIZE;
+        let end = start + self.data.len();
+        pages[..start].fill(0);
+        pages[start..end].copy_from_slice(self.data);
+        pages[end..].fill(0);
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Elf<'a> {
     // Contains elf metadata.
@@ -293,7 +377,339 @@ impl<'a> Elf<'a> {
         ))
     }
 
-    pub fn parse_bytes(bytes: &'a [u8]) -> Result<Elf<'a>, nom::Err<Error<&'a [u8]>>> {
-        Ok(Self::parse(bytes)?.1)
+    /// Parse an executable, checking that it's one we can run.
+    pub fn parse_bytes(bytes: &'a [u8]) -> Result<Elf<'a>, ElfError> {
+        // Check the class and endianness before parsing, since the parser just fails on them
+        let Some(&[0x7F, b'E', b'L', b'F', class, data]) = bytes.get(..6) else {
+            return Err(ElfError::Malformed);
+        };
+        if class != ELFCLASS32 {
+            return Err(ElfError::Not32Bit);
+        }
+        if data != ELFDATA2LSB {
+            return Err(ElfError::NotLittleEndian);
+        }
+
+        let elf = Self::parse(bytes)
+            .map_err(|_: nom::Err<Error<&[u8]>>| ElfError::Malformed)?
+            .1;
+        elf.validate()?;
+        Ok(elf)
+    }
+
+    fn validate(&self) -> Result<(), ElfError> {
+        if self.header.architecture != ElfArchitecture::X86 {
+            return Err(ElfError::UnsupportedArchitecture(self.header.architecture));
+        }
+        // Shared objects (including position-independent executables) would need relocating
+        if self.header.usage != ElfUsage::Executable {
+            return Err(ElfError::NotExecutable(self.header.usage));
+        }
+
+        let mut loaded: Vec<&ElfProgramHeader> = Vec::new();
+        for program_header in &self.program_headers {
+            match program_header.program_type {
+                ElfProgramType::Interpret => return Err(ElfError::DynamicallyLinked),
+                ElfProgramType::Load => {
+                    let start = program_header.virtual_address as usize;
+                    let end = start.checked_add(program_header.memory_size as usize);
+                    if end.map_or(true, |end| end > OFFSET) {
+                        return Err(ElfError::KernelAddress(program_header.virtual_address));
+                    }
+
+                    // Each segment gets its own pages, so segments can't even share a page
+                    let pages = program_header.page_range();
+                    let overlapping = loaded.iter().find(|other| {
+                        let other_pages = other.page_range();
+                        !pages.is_empty()
+                            && !other_pages.is_empty()
+                            && pages.start < other_pages.end
+                            && other_pages.start < pages.end
+                    });
+                    if let Some(other) = overlapping {
+                        return Err(ElfError::OverlappingSegments(
+                            other.virtual_address,
+                            program_header.virtual_address,
+                        ));
+                    }
+                    loaded.push(program_header);
+                }
+                _ => {}
+            }
+        }
+
+        let entry = self.header.program_entry;
+        let entry_loaded = loaded.iter().any(|program_header| {
+            let start = program_header.virtual_address;
+            (start..start + program_header.memory_size).contains(&entry)
+        });
+        if !entry_loaded {
+            return Err(ElfError::EntryPointOutsideSegments(entry));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    const PT_LOAD: u32 = 1;
+    const PT_INTERP: u32 = 3;
+
+    /// Build an ELF file for `machine` with a program header for each of `segments`, given as
+    /// (type, virtual address, size). Segments have no data in the file.
+    fn build_elf(machine: u16, segments: &[(u32, u32, u32)]) -> Vec<u8> {
+        let segments: Vec<_> = segments
+            .iter()
+            .map(|&(program_type, virtual_address, size)| {
+                (program_type, virtual_address, size, &[][..])
+            })
+            .collect();
+        build_elf_with_data(machine, &segments)
+    }
+
+    /// Like [`build_elf`], but each segment also has some data, which is put at the same offset
+    /// into a page of the file as the segment's virtual address.
+    fn build_elf_with_data(machine: u16, segments: &[(u32, u32, u32, &[u8])]) -> Vec<u8> {
+        const HEADER_SIZE: u16 = 52;
+        const PROGRAM_HEADER_SIZE: u16 = 32;
+
+        // Lay out the data first, so the program headers can point at it
+        let mut data_end = usize::from(HEADER_SIZE + PROGRAM_HEADER_SIZE * segments.len() as u16);
+        let mut file_offsets = Vec::new();
+        for &(_, virtual_address, _, data) in segments {
+            if data.is_empty() {
+                file_offsets.push(0);
+                continue;
+            }
+            let page_offset = virtual_address as usize % PAGE_FRAME_SIZE;
+            let mut file_offset = data_end - data_end % PAGE_FRAME_SIZE + page_offset;
+            if file_offset < data_end {
+                file_offset += PAGE_FRAME_SIZE;
+            }
+            file_offsets.push(file_offset);
+            data_end = file_offset + data.len();
+        }
+
+        let mut elf = vec![0x7F, b'E', b'L', b'F', ELFCLASS32, ELFDATA2LSB, 1, 0];
+        elf.extend([0; 8]);
+        elf.extend(2u16.to_le_bytes()); // ET_EXEC
+        elf.extend(machine.to_le_bytes());
+        elf.extend(1u32.to_le_bytes()); // version
+
+        // the entry point is at the start of the first segment
+        let entry = segments
+            .iter()
+            .find(|&&(program_type, ..)| program_type == PT_LOAD)
+            .map_or(0x1000, |&(_, virtual_address, ..)| virtual_address);
+        elf.extend(entry.to_le_bytes());
+        elf.extend(u32::from(HEADER_SIZE).to_le_bytes()); // program headers offset
+        elf.extend(0u32.to_le_bytes()); // section headers offset
+        elf.extend(0u32.to_le_bytes()); // flags
+        elf.extend(HEADER_SIZE.to_le_bytes());
+        elf.extend(PROGRAM_HEADER_SIZE.to_le_bytes());
+        elf.extend((segments.len() as u16).to_le_bytes());
+        elf.extend([0; 6]); // no sections
+        assert_eq!(elf.len(), usize::from(HEADER_SIZE));
+
+        for (&(program_type, virtual_address, size, data), &file_offset) in
+            segments.iter().zip(&file_offsets)
+        {
+            for field in [
+                program_type,
+                file_offset as u32,
+                virtual_address,
+                virtual_address,
+                data.len() as u32,
+                size,
+                5, // readable and executable
+                0x1000,
+            ] {
+                elf.extend(field.to_le_bytes());
+            }
+        }
+        for (&(.., data), &file_offset) in segments.iter().zip(&file_offsets) {
+            if !data.is_empty() {
+                elf.resize(file_offset, 0);
+                elf.extend_from_slice(data);
+            }
+        }
+        elf
+    }
+
+    const EM_386: u16 = 0x03;
+
+    #[test]
+    fn accepts_static_executable() {
+        let elf = build_elf(
+            EM_386,
+            &[(PT_LOAD, 0x1000, 0x2000), (PT_LOAD, 0x4000, 0x10)],
+        );
+        let elf = Elf::parse_bytes(&elf).unwrap();
+        assert_eq!(elf.program_headers.len(), 2);
+        assert_eq!(elf.header.program_entry, 0x1000);
+        // right up to the start of kernel memory is fine
+        let elf = build_elf(EM_386, &[(PT_LOAD, OFFSET as u32 - 0x1000, 0x1000)]);
+        assert!(Elf::parse_bytes(&elf).is_ok());
+    }
+
+    #[test]
+    fn rejects_64_bit() {
+        let mut elf = build_elf(EM_386, &[(PT_LOAD, 0x1000, 0x1000)]);
+        elf[4] = 2; // ELFCLASS64
+        assert_eq!(Elf::parse_bytes(&elf).unwrap_err(), ElfError::Not32Bit);
+    }
+
+    #[test]
+    fn rejects_wrong_architecture() {
+        let elf = build_elf(0x3E, &[(PT_LOAD, 0x1000, 0x1000)]);
+        assert_eq!(
+            Elf::parse_bytes(&elf).unwrap_err(),
+            ElfError::UnsupportedArchitecture(ElfArchitecture::X8664)
+        );
+        let mut elf = build_elf(EM_386, &[(PT_LOAD, 0x1000, 0x1000)]);
+        elf[5] = 2; // ELFDATA2MSB
+        assert_eq!(
+            Elf::parse_bytes(&elf).unwrap_err(),
+            ElfError::NotLittleEndian
+        );
+    }
+
+    #[test]
+    fn rejects_shared_object() {
+        let mut elf = build_elf(EM_386, &[(PT_LOAD, 0x1000, 0x1000)]);
+        elf[16] = 3; // ET_DYN
+        assert_eq!(
+            Elf::parse_bytes(&elf).unwrap_err(),
+            ElfError::NotExecutable(ElfUsage::Shared)
+        );
+    }
+
+    #[test]
+    fn rejects_dynamic() {
+        let elf = build_elf(
+            EM_386,
+            &[(PT_INTERP, 0x1000, 0x13), (PT_LOAD, 0x1000, 0x1000)],
+        );
+        assert_eq!(
+            Elf::parse_bytes(&elf).unwrap_err(),
+            ElfError::DynamicallyLinked
+        );
+    }
+
+    #[test]
+    fn rejects_kernel_address() {
+        let kernel = OFFSET as u32 + 0x1000;
+        let elf = build_elf(
+            EM_386,
+            &[(PT_LOAD, 0x1000, 0x1000), (PT_LOAD, kernel, 0x1000)],
+        );
+        assert_eq!(
+            Elf::parse_bytes(&elf).unwrap_err(),
+            ElfError::KernelAddress(kernel)
+        );
+        // a segment can't start in user memory and run into kernel memory either
+        let straddling = OFFSET as u32 - 0x1000;
+        let elf = build_elf(EM_386, &[(PT_LOAD, straddling, 0x2000)]);
+        assert_eq!(
+            Elf::parse_bytes(&elf).unwrap_err(),
+            ElfError::KernelAddress(straddling)
+        );
+    }
+
+    #[test]
+    fn rejects_truncated() {
+        let elf = build_elf(EM_386, &[(PT_LOAD, 0x1000, 0x1000)]);
+        assert_eq!(
+            Elf::parse_bytes(&elf[..60]).unwrap_err(),
+            ElfError::Malformed
+        );
+        assert_eq!(
+            Elf::parse_bytes(b"\x7fEL").unwrap_err(),
+            ElfError::Malformed
+        );
+    }
+
+    #[test]
+    fn load_unaligned_segment() {
+        let text = [0x55, 0x89, 0xE5, 0x90, 0xC3];
+        let data = [1, 2, 3, 4];
+        let elf = build_elf_with_data(
+            EM_386,
+            &[
+                // .text starts partway into a page
+                (PT_LOAD, 0x1234, text.len() as u32, &text),
+                // .data is followed by .bss, and crosses into another page
+                (PT_LOAD, 0x2FFE, 0x10, &data),
+            ],
+        );
+        let elf = Elf::parse_bytes(&elf).unwrap();
+        let [text_header, data_header] = &elf.program_headers[..] else {
+            panic!("expected two segments");
+        };
+        assert_eq!(text_header.file_offset % 0x1000, 0x234);
+        assert_eq!(text_header.data, text);
+        assert_eq!(data_header.data, data);
+
+        assert_eq!(text_header.page_range(), 0x1000..0x2000);
+        let mut pages = vec![0xFF; 0x1000];
+        text_header.load(&mut pages);
+        assert!(pages[..0x234].iter().all(|&b| b == 0));
+        assert_eq!(pages[0x234..0x239], text);
+        assert!(pages[0x239..].iter().all(|&b| b == 0));
+
+        assert_eq!(data_header.page_range(), 0x2000..0x4000);
+        let mut pages = vec![0xFF; 0x2000];
+        data_header.load(&mut pages);
+        assert!(pages[..0xFFE].iter().all(|&b| b == 0));
+        assert_eq!(pages[0xFFE..0x1002], data);
+        assert!(pages[0x1002..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn rejects_overlapping_segments() {
+        let elf = build_elf(
+            EM_386,
+            &[(PT_LOAD, 0x1000, 0x3000), (PT_LOAD, 0x2000, 0x1000)],
+        );
+        assert_eq!(
+            Elf::parse_bytes(&elf).unwrap_err(),
+            ElfError::OverlappingSegments(0x1000, 0x2000)
+        );
+        // sharing a page counts as overlapping, since we'd map it twice
+        let elf = build_elf(
+            EM_386,
+            &[
+                (PT_LOAD, 0x1000, 0x10),
+                (PT_LOAD, 0x1800, 0x10),
+                (PT_LOAD, 0x800, 0x10),
+            ],
+        );
+        assert_eq!(
+            Elf::parse_bytes(&elf).unwrap_err(),
+            ElfError::OverlappingSegments(0x1000, 0x1800)
+        );
+        // but segments which are next to each other are fine
+        let elf = build_elf(
+            EM_386,
+            &[(PT_LOAD, 0x1000, 0x1000), (PT_LOAD, 0x2000, 0x1000)],
+        );
+        assert!(Elf::parse_bytes(&elf).is_ok());
+    }
+
+    #[test]
+    fn rejects_entry_outside_segments() {
+        let mut elf = build_elf(EM_386, &[(PT_LOAD, 0x1000, 0x1000)]);
+        // e_entry
+        elf[24..28].copy_from_slice(&0x2000u32.to_le_bytes());
+        assert_eq!(
+            Elf::parse_bytes(&elf).unwrap_err(),
+            ElfError::EntryPointOutsideSegments(0x2000)
+        );
+        elf[24..28].copy_from_slice(&0x1FFFu32.to_le_bytes());
+        assert!(Elf::parse_bytes(&elf).is_ok());
     }
 }
@@ -0,0 +1,121 @@
+use crate::interrupts::{intr_disable, intr_enable};
+use crate::sync::mutex::Mutex;
+use crate::system::running_thread_tid;
+use crate::threading::process::Tid;
+use crate::threading::thread_sleep::{thread_sleep, thread_wakeup};
+use crate::user_program::syscall::{
+    EAGAIN, EINVAL, ENOSYS, FUTEX_PRIVATE_FLAG, FUTEX_WAIT, FUTEX_WAKE,
+};
+use crate::user_program::user_copy::user_phys_addr;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+
+/// Threads waiting in `FUTEX_WAIT`, by the physical address of the word they're waiting on.
+///
+/// Using the physical address means threads which share memory wait on the same word, even if
+/// it's at a different virtual address for each of them.
+pub struct FutexQueues(BTreeMap<usize, VecDeque<Tid>>);
+
+impl FutexQueues {
+    pub const fn new() -> Self {
+        Self(BTreeMap::new())
+    }
+
+    /// Add `tid` to the end of the queue for the word at `addr`.
+    pub fn wait(&mut self, addr: usize, tid: Tid) {
+        self.0.entry(addr).or_default().push_back(tid);
+    }
+
+    /// Whether `tid` is still waiting on the word at `addr`, i.e. hasn't been woken yet.
+    pub fn is_waiting(&self, addr: usize, tid: Tid) -> bool {
+        self.0.get(&addr).is_some_and(|queue| queue.contains(&tid))
+    }
+
+    /// Remove up to `count` threads from the front of the queue for the word at `addr`, and
+    /// return them so they can be woken.
+    pub fn wake(&mut self, addr: usize, count: usize) -> Vec<Tid> {
+        let Some(queue) = self.0.get_mut(&addr) else {
+            return Vec::new();
+        };
+        let woken = queue.drain(..count.min(queue.len())).collect();
+        if queue.is_empty() {
+            self.0.remove(&addr);
+        }
+        woken
+    }
+}
+
+static FUTEX_QUEUES: Mutex<FutexQueues> = Mutex::new(FutexQueues::new());
+
+/// The `futex` syscall: `FUTEX_WAIT` blocks until woken if `*uaddr == val`, and `FUTEX_WAKE`
+/// wakes up to `val` threads waiting on `uaddr`, returning how many it woke.
+pub fn futex(uaddr: *const u32, op: i32, val: u32) -> isize {
+    if uaddr as usize % core::mem::align_of::<u32>() != 0 {
+        return -EINVAL;
+    }
+    // the word can't cross a page boundary since it's aligned, so this covers all of it
+    let addr = match user_phys_addr(uaddr as usize) {
+        Ok(addr) => addr,
+        Err(e) => return -e,
+    };
+    match op & !FUTEX_PRIVATE_FLAG {
+        FUTEX_WAIT => {
+            let tid = running_thread_tid();
+            {
+                // hold the lock while checking the word, so a thread which changes it and then
+                // wakes us can't do so in between
+                let mut queues = FUTEX_QUEUES.lock();
+                // SAFETY: user_phys_addr checked that the word is readable.
+                if unsafe { uaddr.read_volatile() } != val {
+                    return -EAGAIN;
+                }
+                queues.wait(addr, tid);
+            }
+            loop {
+                // Interrupts are off between checking and blocking, so a FUTEX_WAKE can't come
+                // in between and be missed.
+                intr_disable();
+                let waiting = FUTEX_QUEUES.lock().is_waiting(addr, tid);
+                if waiting {
+                    thread_sleep();
+                }
+                intr_enable();
+                if !waiting {
+                    return 0;
+                }
+            }
+        }
+        FUTEX_WAKE => {
+            let woken = FUTEX_QUEUES.lock().wake(addr, val as usize);
+            for &tid in &woken {
+                thread_wakeup(tid);
+            }
+            woken.len() as isize
+        }
+        _ => -ENOSYS,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::FutexQueues;
+
+    #[test]
+    fn wake_in_order() {
+        let mut queues = FutexQueues::new();
+        queues.wait(0x1000, 1);
+        queues.wait(0x1000, 2);
+        queues.wait(0x2004, 3);
+        queues.wait(0x1000, 4);
+        assert_eq!(queues.wake(0x1000, 2), [1, 2]);
+        assert!(!queues.is_waiting(0x1000, 1));
+        assert!(queues.is_waiting(0x1000, 4));
+        // only the threads waiting on that word are woken
+        assert!(queues.is_waiting(0x2004, 3));
+        assert_eq!(queues.wake(0x1000, 10), [4]);
+        assert_eq!(queues.wake(0x1000, 10), []);
+        assert_eq!(queues.wake(0x3000, 1), []);
+        assert_eq!(queues.wake(0x2004, 0), []);
+        assert!(queues.is_waiting(0x2004, 3));
+    }
+}
@@ -0,0 +1,60 @@
+use crate::system::{running_process, running_thread_pid, unwrap_system};
+use crate::threading::process::Pid;
+use crate::user_program::syscall::{ESRCH, SIGINT};
+
+/// Moves the process `pid` into the process group `pgid`.
+///
+/// As with Linux, a `pid` of 0 means the running process, and a `pgid` of 0 means the group whose
+/// id is `pid`. A process can only change its own group or the groups of its children.
+pub fn setpgid(pid: Pid, pgid: Pid) -> isize {
+    let running_pid = running_thread_pid();
+    let pid = if pid == 0 { running_pid } else { pid };
+    let pgid = if pgid == 0 { pid } else { pgid };
+    let Some(pcb) = unwrap_system().process.table.get(pid) else {
+        return -ESRCH;
+    };
+    let mut pcb = pcb.lock();
+    if pcb.pid != running_pid && pcb.ppid != running_pid {
+        return -ESRCH;
+    }
+    pcb.pgid = pgid;
+    0
+}
+
+/// Returns the process group of `pid`, or of the running process if `pid` is 0.
+pub fn getpgid(pid: Pid) -> isize {
+    let pcb = if pid == 0 {
+        running_process()
+    } else {
+        match unwrap_system().process.table.get(pid) {
+            Some(pcb) => pcb,
+            None => return -ESRCH,
+        }
+    };
+    let pgid = pcb.lock().pgid;
+    pgid as isize
+}
+
+/// Marks every process in the terminal's foreground process group as interrupted, if Ctrl-C has
+/// been typed since the last call.
+pub fn deliver_interrupt() {
+    let system = unwrap_system();
+    if let Some(pgid) = system.tty.take_interrupt() {
+        system.process.table.interrupt_group(pgid);
+    }
+}
+
+/// Delivers any pending Ctrl-C, then sends `SIGINT` to the running process if it's been
+/// interrupted.
+///
+/// This is called on the way into and out of every syscall, so a process waiting on the terminal
+/// gets `SIGINT` as soon as Ctrl-C makes its read fail.
+pub fn handle_interrupt() {
+    deliver_interrupt();
+    let pcb = running_process();
+    let mut pcb = pcb.lock();
+    if pcb.interrupted {
+        pcb.interrupted = false;
+        pcb.signals.pending.raise(SIGINT);
+    }
+}
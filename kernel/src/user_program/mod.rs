@@ -1,4 +1,15 @@
+pub mod brk;
 pub mod elf;
+pub mod futex;
+pub mod job_control;
+pub mod prctl;
 pub mod random;
+pub mod rusage;
+pub mod sched;
+pub mod signal;
 pub mod syscall;
+pub mod syslog;
 pub mod time;
+#[cfg_attr(not(feature = "syscall_trace"), allow(dead_code))]
+pub mod trace;
+pub mod user_copy;
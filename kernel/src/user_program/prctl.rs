@@ -0,0 +1,47 @@
+use crate::system::running_process;
+use crate::user_program::syscall::{EINVAL, PR_GET_NAME, PR_SET_NAME, TASK_COMM_LEN};
+use crate::user_program::user_copy::{copy_from_user, copy_to_user};
+use core::slice::from_mut;
+
+/// Set the running process' name from the null-terminated string at `name`, of which at most
+/// `TASK_COMM_LEN - 1` bytes are used.
+fn set_name(name: *const u8) -> isize {
+    let mut comm = [0; TASK_COMM_LEN - 1];
+    let mut len = 0;
+    // One byte at a time, so nothing past the terminator is read.
+    while len < comm.len() {
+        if let Err(e) = copy_from_user(from_mut(&mut comm[len]), name.wrapping_add(len)) {
+            return -e;
+        }
+        if comm[len] == 0 {
+            break;
+        }
+        len += 1;
+    }
+    running_process().lock().set_comm(&comm[..len]);
+    0
+}
+
+/// Copy the running process' name, null-terminated, into the `TASK_COMM_LEN` bytes at `name`.
+fn get_name(name: *mut u8) -> isize {
+    let mut comm = [0; TASK_COMM_LEN];
+    {
+        let pcb = running_process();
+        let pcb = pcb.lock();
+        let len = pcb.comm().len();
+        comm[..len].copy_from_slice(pcb.comm());
+    }
+    match copy_to_user(name, &comm) {
+        Ok(()) => 0,
+        Err(e) => -e,
+    }
+}
+
+/// The `prctl` syscall. Only `PR_SET_NAME` and `PR_GET_NAME` are supported.
+pub fn prctl(option: i32, arg2: usize) -> isize {
+    match option {
+        PR_SET_NAME => set_name(arg2 as *const u8),
+        PR_GET_NAME => get_name(arg2 as *mut u8),
+        _ => -EINVAL,
+    }
+}
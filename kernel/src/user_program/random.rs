@@ -1,3 +1,4 @@
+use crate::user_program::user_copy::{check_user_range, copy_to_user};
 use core::arch::asm;
 
 /// Generates a random int using the CPU's RDRAND instruction.
@@ -18,35 +19,82 @@ fn generate_random_i32() -> Option<i32> {
     (success == 1).then_some(random_int)
 }
 
-/// Fills a buffer with random bytes from the CPU's RDRAND instruction.
-/// Returns the number of bytes written, or -1 if an error occurs.
-/// Currently no flags are implemented, if there is no random data available,
-/// the random data generated so far is returned.
-pub fn getrandom(buffer: &mut [u8], length: usize, _flags: usize) -> isize {
-    let mut bytes_written: usize = 0;
-    let chunks = length / 4;
-    let remainder = length % 4;
-
-    for i in 0..chunks {
-        match generate_random_i32() {
-            Some(random_int) => {
-                let random_bytes = random_int.to_le_bytes();
-                buffer[i * 4..i * 4 + 4].copy_from_slice(&random_bytes);
-            }
-            None => return bytes_written.try_into().unwrap(),
+/// Fill `buffer` with random bytes, four at a time from `next`, and return how many were written.
+/// If `next` runs out of random data, only the bytes generated so far are written.
+fn fill_random(buffer: &mut [u8], mut next: impl FnMut() -> Option<i32>) -> usize {
+    let mut bytes_written = 0;
+    for chunk in buffer.chunks_mut(4) {
+        let Some(random_int) = next() else {
+            break;
+        };
+        chunk.copy_from_slice(&random_int.to_le_bytes()[..chunk.len()]);
+        bytes_written += chunk.len();
+    }
+    bytes_written
+}
+
+/// The `getrandom` syscall: fill the `length` bytes at `buf` with random bytes from the CPU's
+/// RDRAND instruction. Returns the number of bytes written, or -1 if `buf` isn't writable.
+///
+/// Currently no flags are implemented. If there is no random data available, the random data
+/// generated so far is returned.
+pub fn getrandom(buf: usize, length: usize, _flags: usize) -> isize {
+    if check_user_range(buf, length, true).is_err() {
+        return -1;
+    }
+
+    // Generated a bit at a time, so a large request doesn't need as much kernel memory.
+    let mut chunk = [0; 256];
+    let mut bytes_written = 0;
+    while bytes_written < length {
+        let len = chunk.len().min(length - bytes_written);
+        let n = fill_random(&mut chunk[..len], generate_random_i32);
+        if copy_to_user((buf + bytes_written) as *mut u8, &chunk[..n]).is_err() {
+            return -1;
+        }
+        bytes_written += n;
+        if n < len {
+            break;
         }
     }
 
-    // Handle any remaining bytes if length is not a multiple of 4
-    if remainder > 0 {
-        if let Some(random_int) = generate_random_i32() {
-            let random_bytes = random_int.to_le_bytes();
-            for (i, &byte) in random_bytes.iter().enumerate().take(remainder) {
-                buffer[chunks * 4 + i] = byte;
+    bytes_written as isize
+}
+
+#[cfg(test)]
+mod test {
+    use super::fill_random;
+
+    #[test]
+    fn whole_and_partial_chunks() {
+        let counter = || {
+            let mut n = 0;
+            move || {
+                n += 1;
+                Some(n)
             }
-            bytes_written += remainder;
-        }
+        };
+        let mut buffer = [0xFF; 17];
+        assert_eq!(fill_random(&mut buffer[..16], counter()), 16);
+        assert_eq!(
+            buffer[..16],
+            [1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 4, 0, 0, 0]
+        );
+        assert_eq!(buffer[16], 0xFF);
+
+        assert_eq!(fill_random(&mut buffer, counter()), 17);
+        assert_eq!(buffer[12..], [4, 0, 0, 0, 5]);
     }
 
-    bytes_written.try_into().unwrap()
+    #[test]
+    fn runs_out_of_random_data() {
+        let mut left = 2;
+        let source = || {
+            left -= 1;
+            (left >= 0).then_some(7)
+        };
+        let mut buffer = [0; 17];
+        assert_eq!(fill_random(&mut buffer, source), 8);
+        assert_eq!(buffer[..9], [7, 0, 0, 0, 7, 0, 0, 0, 0]);
+    }
 }
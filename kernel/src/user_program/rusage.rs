@@ -0,0 +1,98 @@
+use crate::interrupts::{mutex_irq::hold_interrupts, timer::TICKS_PER_SECOND, IntrLevel};
+use crate::system::{running_process, unwrap_system};
+use crate::threading::thread_control_block::{ProcessControlBlock, ThreadControlBlock};
+use crate::user_program::syscall::{
+    MemUsage, RLimit, RUsage, EINVAL, RLIM_INFINITY, RUSAGE_SELF, RUSAGE_THREAD,
+};
+use crate::user_program::user_copy::copy_to_user;
+
+/// The `RLIMIT_CPU` limit processes start off with.
+pub const NO_CPU_LIMIT: RLimit = RLimit {
+    rlim_cur: RLIM_INFINITY,
+    rlim_max: RLIM_INFINITY,
+};
+
+/// Add the counters in `usage` to `total`.
+pub fn add_usage(total: &mut RUsage, usage: &RUsage) {
+    total.cpu_ticks += usage.cpu_ticks;
+    total.page_faults += usage.page_faults;
+    total.voluntary_switches += usage.voluntary_switches;
+    total.involuntary_switches += usage.involuntary_switches;
+}
+
+/// The `getrusage` syscall. `who` is either `RUSAGE_SELF`, for the whole process (the running
+/// thread along with any of its threads which have exited), or `RUSAGE_THREAD`, for just the
+/// running thread.
+pub fn getrusage(who: i32, usage: *mut RUsage) -> isize {
+    let thread = {
+        // The timer interrupt handler updates the counters.
+        let _guard = hold_interrupts(IntrLevel::IntrOff);
+        let running = unwrap_system().threads.running_thread.lock();
+        running.as_ref().expect("Why is nothing running!?").usage
+    };
+    let total = match who {
+        RUSAGE_THREAD => thread,
+        RUSAGE_SELF => {
+            let mut total = running_process().lock().usage;
+            add_usage(&mut total, &thread);
+            total
+        }
+        _ => return -EINVAL,
+    };
+    match copy_to_user(usage, &[total]) {
+        Ok(()) => 0,
+        Err(e) => -e,
+    }
+}
+
+/// The `mem_usage` syscall, reporting how much memory the running process' VMAs are using. Pages
+/// it shares, e.g. of a shared memory object, count in full towards its RSS, but only in part
+/// towards its PSS.
+pub fn mem_usage(usage: *mut MemUsage) -> isize {
+    let total = running_process().lock().vmas.memory_usage();
+    match copy_to_user(usage, &[total]) {
+        Ok(()) => 0,
+        Err(e) => -e,
+    }
+}
+
+/// Set the running process' `RLIMIT_CPU` limit. Once it's used more than `limit.rlim_cur`
+/// seconds of CPU time, the timer interrupt handler kills it.
+pub fn set_cpu_limit(limit: RLimit) {
+    let pcb = running_process();
+    let mut pcb = pcb.lock();
+    pcb.cpu_limit = limit;
+    // The running thread gets whatever the process' exited threads haven't used up.
+    let ticks = (limit.rlim_cur != RLIM_INFINITY).then(|| {
+        limit
+            .rlim_cur
+            .saturating_mul(TICKS_PER_SECOND)
+            .saturating_sub(pcb.usage.cpu_ticks)
+    });
+    // The timer interrupt handler checks the limit.
+    let _guard = hold_interrupts(IntrLevel::IntrOff);
+    let mut running = unwrap_system().threads.running_thread.lock();
+    running
+        .as_mut()
+        .expect("Why is nothing running!?")
+        .cpu_limit = ticks;
+}
+
+/// Pass the running process' `RLIMIT_CPU` limit on to `thread` in the process `pcb`, which is
+/// replacing it with `execve`. As on Linux, the CPU time used so far still counts towards it.
+pub fn inherit_cpu_limit(thread: &mut ThreadControlBlock, pcb: &mut ProcessControlBlock) {
+    pcb.cpu_limit = running_process().lock().cpu_limit;
+    let _guard = hold_interrupts(IntrLevel::IntrOff);
+    let running = unwrap_system().threads.running_thread.lock();
+    let running = running.as_ref().expect("Why is nothing running!?");
+    thread.cpu_limit = running
+        .cpu_limit
+        .map(|limit| limit.saturating_sub(running.usage.cpu_ticks));
+}
+
+/// Whether `thread` has used more CPU time than its process' `RLIMIT_CPU` limit allows.
+pub fn cpu_limit_exceeded(thread: &ThreadControlBlock) -> bool {
+    thread
+        .cpu_limit
+        .is_some_and(|limit| thread.usage.cpu_ticks > limit)
+}
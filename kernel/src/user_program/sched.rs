@@ -0,0 +1,67 @@
+use crate::interrupts::{mutex_irq::hold_interrupts, IntrLevel};
+use crate::system::{running_thread_pid, unwrap_system};
+use crate::threading::process::Pid;
+use crate::user_program::syscall::{SchedParam, ESRCH, SCHED_PRIORITY_MAX, SCHED_PRIORITY_MIN};
+use crate::user_program::user_copy::{copy_from_user, copy_to_user};
+use core::slice::from_mut;
+
+/// Only the calling thread's parameters can be read or changed, which `pid` can refer to as 0 or
+/// the calling process' id.
+fn check_pid(pid: Pid) -> Result<(), isize> {
+    if pid == 0 || pid == running_thread_pid() {
+        Ok(())
+    } else {
+        Err(ESRCH)
+    }
+}
+
+/// Set the running thread's priority to `priority`, clamped to the valid range, and let the
+/// scheduler know.
+fn set_priority(priority: i32) {
+    let threads = &unwrap_system().threads;
+    // The timer interrupt handler locks both of these too.
+    let _guard = hold_interrupts(IntrLevel::IntrOff);
+    let mut running = threads.running_thread.lock();
+    let running = running.as_mut().expect("Why is nothing running!?");
+    running.priority = priority.clamp(SCHED_PRIORITY_MIN, SCHED_PRIORITY_MAX);
+    threads.scheduler.lock().set_priority(running);
+}
+
+fn priority() -> i32 {
+    let running = unwrap_system().threads.running_thread.lock();
+    running.as_ref().expect("Why is nothing running!?").priority
+}
+
+/// The `sched_setparam` syscall. The priority is clamped to the valid range, rather than being
+/// rejected if it's outside it.
+pub fn sched_setparam(pid: Pid, param: *const SchedParam) -> isize {
+    if let Err(e) = check_pid(pid) {
+        return -e;
+    }
+    let mut new = SchedParam { sched_priority: 0 };
+    if let Err(e) = copy_from_user(from_mut(&mut new), param) {
+        return -e;
+    }
+    set_priority(new.sched_priority);
+    0
+}
+
+/// The `sched_getparam` syscall.
+pub fn sched_getparam(pid: Pid, param: *mut SchedParam) -> isize {
+    if let Err(e) = check_pid(pid) {
+        return -e;
+    }
+    let current = SchedParam {
+        sched_priority: priority(),
+    };
+    match copy_to_user(param, &[current]) {
+        Ok(()) => 0,
+        Err(e) => -e,
+    }
+}
+
+/// The `nice` syscall: add `increment` to the running thread's priority.
+pub fn nice(increment: i32) -> isize {
+    set_priority(priority().saturating_add(increment));
+    0
+}
@@ -0,0 +1,478 @@
+use crate::interrupts::{intr_disable, intr_enable};
+use crate::system::{running_process, running_thread_tid, unwrap_system};
+use crate::threading::process::{Pid, Tid};
+use crate::threading::process_functions::kill_process;
+use crate::threading::thread_sleep::thread_sleep;
+#[cfg(not(test))]
+use crate::threading::thread_sleep::thread_wakeup;
+use crate::user_program::job_control::deliver_interrupt;
+use crate::user_program::syscall::{
+    SigAction, SigSet, SyscallFrame, EINTR, EINVAL, ESRCH, NSIG, SA_NODEFER, SA_RESTORER, SIGCHLD,
+    SIGKILL, SIGSEGV, SIG_BLOCK, SIG_DFL, SIG_IGN, SIG_SETMASK, SIG_UNBLOCK,
+};
+use crate::user_program::user_copy::{copy_from_user, copy_to_user};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::mem::{replace, size_of};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// The `eflags` bits a program can change itself, so can be restored by `sigreturn`: the
+/// arithmetic flags, along with the trap and direction flags.
+const USER_EFLAGS: usize = 0xdd5;
+/// The direction flag, which the ABI says is clear when a function is called.
+const EFLAGS_DF: usize = 0x400;
+
+/// The set containing just `signal`.
+pub fn sig_bit(signal: i32) -> SigSet {
+    1 << (signal - 1)
+}
+
+/// The signals which have been sent to a process, but not delivered or read from a `signalfd` yet.
+/// They're shared with the process' `signalfd`s, so those can be read without locking its PCB.
+#[derive(Clone, Debug, Default)]
+pub struct PendingSignals(Arc<AtomicU32>);
+
+impl PendingSignals {
+    /// Make `signal` pending. Sending a signal which is already pending does nothing.
+    pub fn raise(&self, signal: i32) {
+        self.0.fetch_or(sig_bit(signal), Ordering::SeqCst);
+    }
+
+    /// The signals which are pending.
+    pub fn pending(&self) -> SigSet {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Remove the lowest pending signal in `mask`, and return it.
+    pub fn take(&self, mask: SigSet) -> Option<i32> {
+        self.0
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |pending| {
+                let ready = pending & mask;
+                (ready != 0).then(|| pending & !(ready & ready.wrapping_neg()))
+            })
+            .ok()
+            .map(|pending| ((pending & mask).trailing_zeros() + 1) as i32)
+    }
+}
+
+/// Whether `action` calls a handler, rather than ignoring the signal or taking the default action.
+fn has_handler(action: &SigAction) -> bool {
+    !matches!(action.sa_handler, SIG_DFL | SIG_IGN)
+}
+
+/// A process' signals.
+#[derive(Default)]
+pub struct Signals {
+    pub pending: PendingSignals,
+    /// Signals which stay pending rather than being delivered, as set with `sigprocmask`.
+    pub blocked: SigSet,
+    /// What to do with each signal when it's delivered, as set with `sigaction`, indexed by signal
+    /// number.
+    pub actions: [SigAction; NSIG as usize],
+    /// The signals to block again once a signal interrupts `sigsuspend`, which blocks others while
+    /// it waits.
+    pub restore_blocked: Option<SigSet>,
+    /// Threads sleeping in `pause`, `sigsuspend`, a blocking `flock` or reading a `signalfd`, to be
+    /// woken when a signal is sent.
+    pub sleepers: Vec<Tid>,
+}
+
+impl Signals {
+    /// The signals whose actions are `matching`.
+    fn matching(&self, matching: impl Fn(i32, &SigAction) -> bool) -> SigSet {
+        (1..NSIG)
+            .filter(|&signal| matching(signal, &self.actions[signal as usize]))
+            .fold(0, |set, signal| set | sig_bit(signal))
+    }
+
+    /// The signals which have handlers, rather than being ignored or having their default action.
+    pub fn handled(&self) -> SigSet {
+        self.matching(|_, action| has_handler(action))
+    }
+
+    /// The signals which are thrown away when they're delivered.
+    pub fn ignored(&self) -> SigSet {
+        self.matching(|signal, action| match action.sa_handler {
+            SIG_IGN => true,
+            SIG_DFL => signal == SIGCHLD,
+            _ => false,
+        })
+    }
+
+    /// Whether any pending signal isn't blocked or ignored, so would do something when delivered.
+    pub fn has_deliverable(&self) -> bool {
+        self.pending.pending() & !self.blocked & !self.ignored() != 0
+    }
+
+    /// Wake the threads sleeping until a signal is sent.
+    pub fn wake_sleepers(&mut self) {
+        for tid in self.sleepers.drain(..) {
+            thread_wakeup(tid);
+        }
+    }
+}
+
+/// Stands in for `thread_wakeup` in tests, where there are no threads to wake.
+#[cfg(test)]
+fn thread_wakeup(_tid: Tid) {}
+
+/// The registers a signal handler interrupted, saved on the user stack to be restored by
+/// `sigreturn`, along with the signals which were blocked.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct SignalContext {
+    eax: usize,
+    ebx: usize,
+    ecx: usize,
+    edx: usize,
+    esi: usize,
+    edi: usize,
+    ebp: usize,
+    eip: usize,
+    eflags: usize,
+    esp: usize,
+    blocked: SigSet,
+}
+
+/// What's pushed onto the user stack to call a signal handler: the address it returns to, its
+/// argument, then what it interrupted.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SignalFrame {
+    restorer: usize,
+    signal: i32,
+    context: SignalContext,
+}
+
+/// Delivers the running process' pending signals which aren't blocked.
+///
+/// This is called on the way into and out of every syscall. Signals with handlers are only
+/// delivered on the way out, once `frame`, the registers the program will go back to, are given,
+/// so that they can be changed to call the handler. Other signals kill the process, apart from
+/// ignored ones and `SIGCHLD`, which are thrown away.
+pub fn handle_signals(mut frame: Option<&mut SyscallFrame>) {
+    loop {
+        let (signal, action, blocked) = {
+            let pcb = running_process();
+            let mut pcb = pcb.lock();
+            let signals = &mut pcb.signals;
+            let mut deliverable = !signals.blocked;
+            if frame.is_none() {
+                deliverable &= !signals.handled();
+            }
+            let Some(signal) = signals.pending.take(deliverable) else {
+                if frame.is_some() {
+                    // no handler ran, so sigsuspend's mask can go straight back
+                    if let Some(blocked) = signals.restore_blocked.take() {
+                        signals.blocked = blocked;
+                    }
+                }
+                return;
+            };
+            let action = signals.actions[signal as usize];
+            if !has_handler(&action) {
+                drop(pcb);
+                match action.sa_handler {
+                    SIG_DFL if signal != SIGCHLD => kill_process(signal),
+                    _ => continue,
+                }
+            }
+            // sigsuspend's mask stays while the handler runs, and goes back once it returns
+            let blocked = signals.restore_blocked.take().unwrap_or(signals.blocked);
+            {
+                // block the signal while its handler runs, unless it's asked not to be
+                let mut block = action.sa_mask;
+                if action.sa_flags & SA_NODEFER == 0 {
+                    block |= sig_bit(signal);
+                }
+                signals.blocked |= block & !sig_bit(SIGKILL);
+            }
+            (signal, action, blocked)
+        };
+        let frame = frame
+            .as_deref_mut()
+            .expect("signals with handlers aren't taken without a frame");
+        // as on Linux, a process whose stack can't hold the frame is killed
+        if call_handler(frame, signal, &action, blocked).is_err() {
+            kill_process(SIGSEGV);
+        }
+        return;
+    }
+}
+
+/// Change `frame` to call the handler in `action` for `signal`, and return to what it would have
+/// gone back to, with `blocked` blocked again, once the handler calls `sigreturn`.
+fn call_handler(
+    frame: &mut SyscallFrame,
+    signal: i32,
+    action: &SigAction,
+    blocked: SigSet,
+) -> Result<(), isize> {
+    let signal_frame = SignalFrame {
+        restorer: action.sa_restorer,
+        signal,
+        context: SignalContext {
+            eax: frame.eax,
+            ebx: frame.ebx,
+            ecx: frame.ecx,
+            edx: frame.edx,
+            esi: frame.esi,
+            edi: frame.edi,
+            ebp: frame.ebp,
+            eip: frame.eip,
+            eflags: frame.eflags,
+            esp: frame.esp,
+            blocked,
+        },
+    };
+    // The ABI wants the handler's arguments to be 16 byte aligned, just past the return address.
+    let args = frame
+        .esp
+        .wrapping_sub(size_of::<SignalFrame>() - size_of::<usize>())
+        & !0xf;
+    let esp = args.wrapping_sub(size_of::<usize>());
+    copy_to_user(esp as *mut SignalFrame, &[signal_frame])?;
+    frame.esp = esp;
+    frame.eip = action.sa_handler;
+    frame.eflags &= !EFLAGS_DF;
+    Ok(())
+}
+
+/// The `sigreturn` syscall, which a signal handler's restorer makes once it returns: go back to
+/// what the handler interrupted, with the signals which were blocked then. The result is `eax` as
+/// it was, so that it's left alone too.
+pub fn sigreturn(frame: &mut SyscallFrame) -> isize {
+    // Returning from the handler popped its return address, leaving the stack pointer at its
+    // argument, just before the context.
+    let context = frame.esp.wrapping_add(size_of::<i32>()) as *const SignalContext;
+    let mut saved = [SignalContext::default()];
+    if copy_from_user(&mut saved, context).is_err() {
+        kill_process(SIGSEGV);
+    }
+    let [saved] = saved;
+    frame.ebx = saved.ebx;
+    frame.ecx = saved.ecx;
+    frame.edx = saved.edx;
+    frame.esi = saved.esi;
+    frame.edi = saved.edi;
+    frame.ebp = saved.ebp;
+    frame.eip = saved.eip;
+    frame.esp = saved.esp;
+    // privileged flags, like the interrupt flag, can't be changed
+    frame.eflags = (frame.eflags & !USER_EFLAGS) | (saved.eflags & USER_EFLAGS);
+    running_process().lock().signals.blocked = saved.blocked & !sig_bit(SIGKILL);
+    saved.eax as isize
+}
+
+/// The `sigaction` syscall: change what happens when `signal` is delivered to `act`, unless it's
+/// null, then write what used to happen to `oldact`, unless it's null.
+///
+/// A handler needs a restorer to return to, since there's no default one like Linux's vDSO.
+pub fn sigaction(signal: i32, act: *const SigAction, oldact: *mut SigAction) -> isize {
+    if !(1..NSIG).contains(&signal) {
+        return -EINVAL;
+    }
+    let mut new = [SigAction::default()];
+    if !act.is_null() {
+        if let Err(e) = copy_from_user(&mut new, act) {
+            return -e;
+        }
+        if signal == SIGKILL
+            || new[0].sa_flags & !(SA_RESTORER | SA_NODEFER) != 0
+            || (has_handler(&new[0]) && new[0].sa_flags & SA_RESTORER == 0)
+        {
+            return -EINVAL;
+        }
+    }
+    let old = {
+        let pcb = running_process();
+        let mut pcb = pcb.lock();
+        let old = pcb.signals.actions[signal as usize];
+        if !act.is_null() {
+            pcb.signals.actions[signal as usize] = new[0];
+        }
+        old
+    };
+    if !oldact.is_null() {
+        if let Err(e) = copy_to_user(oldact, &[old]) {
+            return -e;
+        }
+    }
+    0
+}
+
+/// The `kill` syscall: send `signal` to the process `pid`. A `signal` of 0 just checks that the
+/// process exists. Sending to process groups (a `pid` of 0 or less) isn't supported yet.
+pub fn kill(pid: i32, signal: i32) -> isize {
+    if !(0..NSIG).contains(&signal) || pid <= 0 {
+        return -EINVAL;
+    }
+    let Some(pcb) = Pid::try_from(pid)
+        .ok()
+        .and_then(|pid| unwrap_system().process.table.get(pid))
+    else {
+        return -ESRCH;
+    };
+    if signal != 0 {
+        let mut pcb = pcb.lock();
+        pcb.signals.pending.raise(signal);
+        pcb.signals.wake_sleepers();
+    }
+    0
+}
+
+/// The `sigprocmask` syscall: change the running process' blocked signals as given by `how` and
+/// `set`, unless `set` is null, then write the ones which were blocked before to `oldset`, unless
+/// it's null.
+pub fn sigprocmask(how: i32, set: *const SigSet, oldset: *mut SigSet) -> isize {
+    // Don't copy to or from userspace while holding the PCB lock, since that can page fault.
+    let mut change = [0];
+    if !set.is_null() {
+        if let Err(e) = copy_from_user(&mut change, set) {
+            return -e;
+        }
+    }
+    let old = {
+        let pcb = running_process();
+        let mut pcb = pcb.lock();
+        let old = pcb.signals.blocked;
+        if !set.is_null() {
+            let blocked = match how {
+                SIG_BLOCK => old | change[0],
+                SIG_UNBLOCK => old & !change[0],
+                SIG_SETMASK => change[0],
+                _ => return -EINVAL,
+            };
+            pcb.signals.blocked = blocked & !sig_bit(SIGKILL);
+        }
+        old
+    };
+    if !oldset.is_null() {
+        if let Err(e) = copy_to_user(oldset, &[old]) {
+            return -e;
+        }
+    }
+    0
+}
+
+/// Sleep until a signal which isn't blocked or ignored is pending, blocking just `mask`, if it's
+/// given, in the meantime. It's delivered on the way out of the syscall, which always fails with
+/// `EINTR`.
+fn suspend(mask: Option<SigSet>) -> isize {
+    let pcb = running_process();
+    let tid = running_thread_tid();
+    if let Some(mask) = mask {
+        let mut pcb = pcb.lock();
+        let old = replace(&mut pcb.signals.blocked, mask & !sig_bit(SIGKILL));
+        pcb.signals.restore_blocked = Some(old);
+    }
+    loop {
+        // Ctrl-C only interrupts processes once a syscall delivers it
+        deliver_interrupt();
+        intr_disable();
+        {
+            let mut pcb = pcb.lock();
+            if pcb.interrupted || pcb.signals.has_deliverable() {
+                pcb.signals.sleepers.retain(|&sleeper| sleeper != tid);
+                intr_enable();
+                return -EINTR;
+            }
+            if !pcb.signals.sleepers.contains(&tid) {
+                pcb.signals.sleepers.push(tid);
+            }
+        }
+        // Interrupts are still off, so a signal sent after checking wakes this thread once it's
+        // blocked, rather than before.
+        thread_sleep();
+        intr_enable();
+    }
+}
+
+/// The `pause` syscall: sleep until a signal is delivered.
+pub fn pause() -> isize {
+    suspend(None)
+}
+
+/// The `rt_sigsuspend` syscall: sleep until a signal is delivered, with just the signals in `mask`
+/// blocked until then. `size` is the size of `mask`, which has to be a `SigSet`.
+pub fn sigsuspend(mask: *const SigSet, size: usize) -> isize {
+    if size != size_of::<SigSet>() {
+        return -EINVAL;
+    }
+    let mut mask_buf = [0];
+    if let Err(e) = copy_from_user(&mut mask_buf, mask) {
+        return -e;
+    }
+    suspend(Some(mask_buf[0]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::user_program::syscall::{SIGINT, SIGTERM};
+
+    #[test]
+    fn lowest_pending_first() {
+        let pending = PendingSignals::default();
+        assert_eq!(pending.take(!0), None);
+
+        pending.raise(SIGTERM);
+        pending.raise(SIGINT);
+        pending.raise(SIGTERM);
+        assert_eq!(pending.pending(), sig_bit(SIGINT) | sig_bit(SIGTERM));
+
+        // only signals in the mask are taken
+        assert_eq!(pending.take(sig_bit(SIGTERM)), Some(SIGTERM));
+        assert_eq!(pending.take(sig_bit(SIGTERM)), None);
+
+        pending.raise(SIGTERM);
+        assert_eq!(pending.take(!0), Some(SIGINT));
+        assert_eq!(pending.take(!0), Some(SIGTERM));
+        assert_eq!(pending.pending(), 0);
+    }
+
+    #[test]
+    fn blocked_signals_stay_pending() {
+        let mut signals = Signals {
+            blocked: sig_bit(SIGTERM),
+            ..Default::default()
+        };
+        signals.pending.raise(SIGTERM);
+        assert!(!signals.has_deliverable());
+
+        // a signalfd shares the pending signals
+        let signalfd = signals.pending.clone();
+        assert_eq!(signalfd.take(sig_bit(SIGTERM)), Some(SIGTERM));
+
+        signals.pending.raise(SIGTERM);
+        signals.blocked = 0;
+        assert!(signals.has_deliverable());
+    }
+
+    #[test]
+    fn handled_signals() {
+        let mut signals = Signals::default();
+        assert_eq!(signals.handled(), 0);
+
+        signals.actions[SIGINT as usize].sa_handler = 0x1000;
+        signals.actions[SIGTERM as usize].sa_handler = SIG_IGN;
+        assert_eq!(signals.handled(), sig_bit(SIGINT));
+    }
+
+    #[test]
+    fn ignored_signals_arent_deliverable() {
+        let mut signals = Signals::default();
+        assert_eq!(signals.ignored(), sig_bit(SIGCHLD));
+
+        // pause doesn't wake up for a signal which would just be thrown away
+        signals.pending.raise(SIGCHLD);
+        assert!(!signals.has_deliverable());
+
+        signals.actions[SIGTERM as usize].sa_handler = SIG_IGN;
+        signals.pending.raise(SIGTERM);
+        assert!(!signals.has_deliverable());
+
+        signals.actions[SIGCHLD as usize].sa_handler = 0x1000;
+        assert!(signals.has_deliverable());
+    }
+}
@@ -1,33 +1,105 @@
 // https://docs.google.com/document/d/1qMMU73HW541wME00Ngl79ou-kQ23zzTlGXJYo9FNh5M
 
-use crate::fs::read_file;
 use crate::fs::syscalls::{
-    chdir, close, dup, dup2, fstat, ftruncate, getcwd, getdents, link, lseek64, mkdir, mmap, mount,
-    open, pipe, read, rename, rmdir, symlink, sync, unlink, unmount, write,
+    chdir, chroot, close, dup, dup2, epoll_create, epoll_ctl, epoll_wait, fadvise64, fallocate,
+    flock, fstat, ftruncate, getcwd, getdents, getdents64, getdents64_sorted, getrlimit, ioctl,
+    link, lseek64, mkdir, mkdirat, mmap, mount, msync, munmap, open, openat, pipe, read,
+    readlinkat, rename, rmdir, setrlimit, shm_open, shm_unlink, signalfd, symlink, symlinkat, sync,
+    truncate, unlink, unlinkat, unmount, write,
 };
-use crate::interrupts::{intr_disable, intr_enable};
-use crate::mem::util::{
-    get_cstr_from_user_space, get_mut_from_user_space, get_ref_from_user_space, CStrError,
-};
-use crate::system::{running_thread_pid, running_thread_ppid, running_thread_tid, unwrap_system};
-use crate::threading::process::Pid;
+use crate::fs::{read_file, read_file_at, read_open_file, FileDescriptor};
+use crate::interrupts::stats::INTERRUPT_COUNTS;
+use crate::system::{running_process, running_thread_pid, running_thread_ppid, unwrap_system};
 use crate::threading::process_functions;
+use crate::threading::process_vfork::{release_vfork_parent, vfork};
+use crate::threading::process_wait::{waitid, waitpid};
 use crate::threading::scheduling::{scheduler_yield_and_continue, scheduler_yield_and_die};
 use crate::threading::thread_control_block::ThreadControlBlock;
-use crate::threading::thread_sleep::thread_sleep;
+use crate::threading::thread_join::{thread_detach, thread_join};
+use crate::user_program::brk::brk;
 use crate::user_program::elf::Elf;
+use crate::user_program::futex::futex;
+use crate::user_program::job_control::{getpgid, handle_interrupt, setpgid};
+use crate::user_program::prctl::prctl;
 use crate::user_program::random::getrandom;
-use crate::user_program::time::{get_rtc, get_tsc, Timespec, CLOCK_MONOTONIC, CLOCK_REALTIME};
+use crate::user_program::rusage::{getrusage, inherit_cpu_limit, mem_usage};
+use crate::user_program::sched::{nice, sched_getparam, sched_setparam};
+use crate::user_program::signal::{
+    handle_signals, kill, pause, sigaction, sigprocmask, sigreturn, sigsuspend,
+};
+use crate::user_program::syslog::syslog;
+use crate::user_program::time::{
+    get_rtc, get_tsc, nanosleep, Timespec, CLOCK_MONOTONIC, CLOCK_REALTIME,
+};
+#[cfg(feature = "syscall_trace")]
+use crate::user_program::trace;
+use crate::user_program::user_copy::{
+    copy_cstr_from_user, copy_from_user, copy_to_user, CStrError,
+};
 use alloc::boxed::Box;
-use core::slice::from_raw_parts_mut;
-use kidneyos_shared::println;
+use alloc::format;
+use core::mem::zeroed;
+use core::slice::from_mut;
 pub use kidneyos_syscalls::defs::*;
 
+/// The registers of a program making a syscall, as saved by the syscall interrupt handler. They're
+/// restored when the syscall returns, so changing them changes where the program carries on.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SyscallFrame {
+    pub eax: usize,
+    pub ebx: usize,
+    pub ecx: usize,
+    pub edx: usize,
+    pub esi: usize,
+    pub edi: usize,
+    pub ebp: usize,
+    // pushed by the CPU
+    pub eip: usize,
+    pub cs: usize,
+    pub eflags: usize,
+    pub esp: usize,
+    pub ss: usize,
+}
+
 /// This function is responsible for processing syscalls made by user programs.
-/// Its return value is the syscall return value, whose meaning depends on the syscall.
-/// It might not actually return sometimes, such as when the syscall is exit.
-pub extern "C" fn handler(syscall_number: usize, arg0: usize, arg1: usize, arg2: usize) -> isize {
-    println!("syscall number {syscall_number:#X} with arguments: {arg0:#X} {arg1:#X} {arg2:#X}");
+/// The syscall number is in `eax`, which gets the syscall return value, whose meaning depends on
+/// the syscall. It might not actually return sometimes, such as when the syscall is exit,
+/// or when the process is killed by a signal.
+///
+/// Arguments are passed in `ebx`, `ecx`, `edx`, `esi` and `edi`, in that order, as on Linux.
+pub extern "C" fn handler(frame: &mut SyscallFrame) {
+    let syscall_number = frame.eax;
+    let [arg0, arg1, arg2, arg3, arg4] = [frame.ebx, frame.ecx, frame.edx, frame.esi, frame.edi];
+    #[cfg(feature = "syscall_trace")]
+    let args = [arg0, arg1, arg2, arg3, arg4];
+    #[cfg(feature = "syscall_trace")]
+    if trace::never_returns(syscall_number) {
+        trace::trace(running_thread_pid(), syscall_number, args, None);
+    }
+    handle_interrupt();
+    handle_signals(None);
+    let result = match syscall_number {
+        // sigreturn changes the registers the program goes back to
+        SYS_SIGRETURN => sigreturn(frame),
+        SYS_VFORK => vfork(frame),
+        _ => dispatch(syscall_number, arg0, arg1, arg2, arg3, arg4),
+    };
+    frame.eax = result as usize;
+    #[cfg(feature = "syscall_trace")]
+    trace::trace(running_thread_pid(), syscall_number, args, Some(result));
+    handle_interrupt();
+    handle_signals(Some(frame));
+}
+
+fn dispatch(
+    syscall_number: usize,
+    arg0: usize,
+    arg1: usize,
+    arg2: usize,
+    arg3: usize,
+    arg4: usize,
+) -> isize {
     // TODO: Start implementing this by branching on syscall_number.
     // Add todo!()'s for any syscalls that aren't implemented.
     // Return an error if an invalid syscall number is provided.
@@ -36,114 +108,154 @@ pub extern "C" fn handler(syscall_number: usize, arg0: usize, arg1: usize, arg2:
         SYS_EXIT => {
             process_functions::exit_process(arg0 as i32);
         }
-        SYS_FORK => {
-            todo!("fork syscall")
-        }
+        // TODO: fork
+        SYS_FORK => -ENOSYS,
         SYS_OPEN => open(arg0 as _, arg1),
         SYS_READ => read(arg0, arg1 as _, arg2 as _),
         SYS_WRITE => write(arg0, arg1 as _, arg2 as _),
         SYS_LSEEK64 => lseek64(arg0, arg1 as _, arg2 as _),
         SYS_CLOSE => close(arg0),
+        SYS_FLOCK => flock(arg0, arg1 as _),
         SYS_CHDIR => chdir(arg0 as _),
+        SYS_CHROOT => chroot(arg0 as _),
         SYS_GETCWD => getcwd(arg0 as _, arg1 as _),
         SYS_MKDIR => mkdir(arg0 as _),
         SYS_RMDIR => rmdir(arg0 as _),
         SYS_FSTAT => fstat(arg0 as _, arg1 as _),
+        SYS_SYSLOG => syslog(arg0 as _, arg1 as _, arg2 as _),
         SYS_UNLINK => unlink(arg0 as _),
         SYS_GETDENTS => getdents(arg0, arg1 as _, arg2 as _),
+        SYS_GETDENTS64 => getdents64(arg0, arg1 as _, arg2 as _),
+        SYS_GETDENTS64_SORTED => getdents64_sorted(arg0, arg1 as _, arg2 as _),
         SYS_LINK => link(arg0 as _, arg1 as _),
         SYS_SYMLINK => symlink(arg0 as _, arg1 as _),
         SYS_RENAME => rename(arg0 as _, arg1 as _),
+        SYS_OPENAT => openat(arg0 as _, arg1 as _, arg2),
+        SYS_MKDIRAT => mkdirat(arg0 as _, arg1 as _),
+        SYS_UNLINKAT => unlinkat(arg0 as _, arg1 as _, arg2 as _),
+        SYS_SYMLINKAT => symlinkat(arg0 as _, arg1 as _, arg2 as _),
+        SYS_READLINKAT => readlinkat(arg0 as _, arg1 as _, arg2 as _, arg3),
+        SYS_MUNMAP => munmap(arg0, arg1),
+        SYS_MSYNC => msync(arg0, arg1, arg2 as _),
+        SYS_TRUNCATE => truncate(arg0 as _, arg1 as _, arg2 as _),
         SYS_FTRUNCATE => ftruncate(arg0 as _, arg1 as _, arg2 as _),
+        SYS_FADVISE64 => fadvise64(arg0, arg1, arg2, arg3, arg4 as _),
+        SYS_FALLOCATE => fallocate(arg0 as _, arg1 as _, arg2 as _, arg3 as _, arg4 as _),
         SYS_UNMOUNT => unmount(arg0 as _),
         SYS_MOUNT => mount(arg0 as _, arg1 as _, arg2 as _),
         SYS_SYNC => sync(),
-        SYS_WAITPID => {
-            let wait_pid = arg0 as Pid;
-
-            if wait_pid == running_thread_pid() {
-                return -1;
-            }
-
-            let status_ptr = match unsafe { get_mut_from_user_space(arg1 as *mut i32) } {
-                Some(ptr) => ptr,
-                None => return -1,
-            };
-
-            let system = unwrap_system();
-            let pcb_ref = match system.process.table.get(wait_pid) {
-                Some(pcb) => pcb,
-                None => return -1, // Process with wait_pid doesnt exist
-            };
-            let mut parent_pcb = pcb_ref.lock();
-
-            // Can't wait on a thread that alreay has a child waiting
-            if parent_pcb.waiting_thread.is_some() {
-                return -1;
-            }
-
-            parent_pcb.waiting_thread = Some(running_thread_tid());
-            drop(parent_pcb);
-
-            loop {
-                intr_disable();
-                {
-                    let parent_pcb = pcb_ref.lock();
-                    if parent_pcb.exit_code.is_some() {
-                        intr_enable();
-                        break;
-                    }
-                }
-                intr_enable();
-                thread_sleep();
-            }
-
-            let parent_pcb = pcb_ref.lock();
-            let exit_code = parent_pcb.exit_code.unwrap();
-            *status_ptr = (exit_code & 0xff) << 8;
-
-            let parent_pid = parent_pcb.pid;
-            system.process.table.remove(parent_pid);
-
-            parent_pid as isize
-        }
+        SYS_WAITPID => waitpid(arg0 as _, arg1 as _),
+        SYS_WAITID => waitid(arg0 as _, arg1 as _, arg2 as _, arg3 as _),
         SYS_DUP => dup(arg0 as _),
         SYS_PIPE => pipe(arg0 as _),
         SYS_DUP2 => dup2(arg0 as _, arg1 as _),
+        SYS_GETRLIMIT => getrlimit(arg0 as _, arg1 as _),
+        SYS_GETRUSAGE => getrusage(arg0 as _, arg1 as _),
+        SYS_THREAD_JOIN => thread_join(arg0 as _, arg1 as _),
+        SYS_THREAD_DETACH => thread_detach(arg0 as _),
+        SYS_FUTEX => futex(arg0 as _, arg1 as _, arg2 as _),
+        SYS_SHM_OPEN => shm_open(arg0 as _, arg1),
+        SYS_SHM_UNLINK => shm_unlink(arg0 as _),
+        SYS_EPOLL_CREATE => epoll_create(arg0 as _),
+        SYS_EPOLL_CTL => epoll_ctl(arg0 as _, arg1 as _, arg2 as _, arg3 as _),
+        SYS_EPOLL_WAIT => epoll_wait(arg0 as _, arg1 as _, arg2 as _, arg3 as _),
+        SYS_SIGNALFD4 => signalfd(arg0 as _, arg1 as _, arg2, arg3 as _),
+        SYS_KILL => kill(arg0 as _, arg1 as _),
+        SYS_SIGPROCMASK => sigprocmask(arg0 as _, arg1 as _, arg2 as _),
+        SYS_SIGACTION => sigaction(arg0 as _, arg1 as _, arg2 as _),
+        SYS_PAUSE => pause(),
+        SYS_RT_SIGSUSPEND => sigsuspend(arg0 as _, arg1 as _),
+        SYS_SETRLIMIT => setrlimit(arg0 as _, arg1 as _),
+        SYS_IOCTL => ioctl(arg0, arg1, arg2 as _),
         SYS_EXECVE => {
-            let cstr = match unsafe { get_cstr_from_user_space(arg0 as *const u8) } {
+            let cstr = match copy_cstr_from_user(arg0 as *const u8, PATH_MAX) {
                 Ok(cstr) => cstr,
                 Err(CStrError::Fault) => return -EFAULT,
+                Err(CStrError::TooLong) => return -ENAMETOOLONG,
                 Err(CStrError::BadUtf8) => return -ENOENT, // ?
             };
 
-            let Ok(data) = read_file(cstr) else {
+            let Ok(data) = read_file(&cstr) else {
                 return -EIO;
             };
 
-            let system = unwrap_system();
-
-            let elf = Elf::parse_bytes(&data).ok();
-
-            let Some(elf) = elf else { return -ENOEXEC };
-
-            let Ok(control) = ThreadControlBlock::new_from_elf(elf, &system.process) else {
-                return -ENOEXEC;
+            exec(&data, &cstr)
+        }
+        SYS_EXECVEAT => {
+            let path = match copy_cstr_from_user(arg1 as *const u8, PATH_MAX) {
+                Ok(path) => path,
+                Err(CStrError::Fault) => return -EFAULT,
+                Err(CStrError::TooLong) => return -ENAMETOOLONG,
+                Err(CStrError::BadUtf8) => return -ENOENT,
             };
+            let flags = arg4 as i32;
+            if flags & !AT_EMPTY_PATH != 0 {
+                return -EINVAL;
+            }
 
-            system.threads.scheduler.lock().push(Box::new(control));
-
-            scheduler_yield_and_die();
+            let data = if path.is_empty() {
+                // fexecve: run the file open as the fd itself
+                if flags & AT_EMPTY_PATH == 0 {
+                    return -ENOENT;
+                }
+                let Ok(fd) = FileDescriptor::try_from(arg0) else {
+                    return -EBADF;
+                };
+                read_open_file(fd)
+            } else {
+                let dir = if arg0 as i32 == AT_FDCWD {
+                    None
+                } else {
+                    let Ok(fd) = FileDescriptor::try_from(arg0) else {
+                        return -EBADF;
+                    };
+                    Some(fd)
+                };
+                read_file_at(dir, &path)
+            };
+            // fexecve'd programs are named after the file descriptor, since their path would be
+            // /dev/fd/<fd> on Linux
+            let path = if path.is_empty() {
+                format!("{arg0}")
+            } else {
+                path
+            };
+            match data {
+                Ok(data) => exec(&data, &path),
+                Err(e) => -e.to_isize(),
+            }
         }
         SYS_GETPID => running_thread_pid() as isize,
-        SYS_NANOSLEEP => {
-            todo!("nanosleep syscall")
-        }
+        SYS_NANOSLEEP => nanosleep(arg0 as _, arg1 as _),
         SYS_GETPPID => running_thread_ppid() as isize,
+        SYS_SETPGID => setpgid(arg0 as _, arg1 as _),
+        SYS_GETPGID => getpgid(arg0 as _),
         SYS_SCHED_YIELD => {
             scheduler_yield_and_continue();
             0
         }
+        SYS_SCHED_SETPARAM => sched_setparam(arg0 as _, arg1 as _),
+        SYS_SCHED_GETPARAM => sched_getparam(arg0 as _, arg1 as _),
+        SYS_NICE => nice(arg0 as _),
+        SYS_PRCTL => prctl(arg0 as _, arg1),
+        SYS_SCHED_STATS => {
+            let threads = &unwrap_system().threads;
+            let run_queue_length = threads.scheduler.lock().len();
+            let stats = threads.stats.snapshot(run_queue_length);
+            match copy_to_user(arg0 as *mut SchedStats, &[stats]) {
+                Ok(()) => 0,
+                Err(e) => -e,
+            }
+        }
+        SYS_INTERRUPT_COUNTS => {
+            let mut counts = [0; INTERRUPT_VECTORS];
+            let len = INTERRUPT_COUNTS.snapshot(&mut counts[..arg1.min(INTERRUPT_VECTORS)]);
+            match copy_to_user(arg0 as *mut usize, &counts[..len]) {
+                Ok(()) => len as isize,
+                Err(e) => -e,
+            }
+        }
+        SYS_MEM_USAGE => mem_usage(arg0 as _),
         SYS_CLOCK_GETTIME => {
             let timespec = match arg0 {
                 CLOCK_REALTIME => get_rtc(),
@@ -151,27 +263,19 @@ pub extern "C" fn handler(syscall_number: usize, arg0: usize, arg1: usize, arg2:
                 _ => return -1, // Only supporting realtime and monotonic for now
             };
 
-            let Some(timespec_ptr) = (unsafe { get_mut_from_user_space(arg1 as *mut Timespec) })
-            else {
-                return -1;
-            };
-
-            *timespec_ptr = timespec;
-            0
-        }
-        SYS_GETRANDOM => {
-            let Some(buffer_ptr) = (unsafe { get_mut_from_user_space(arg0 as *mut u8) }) else {
-                return -1;
-            };
-
-            let buffer = unsafe { from_raw_parts_mut(buffer_ptr, arg1) };
-            getrandom(buffer, arg1, arg2)
+            match copy_to_user(arg1 as *mut Timespec, &[timespec]) {
+                Ok(()) => 0,
+                Err(_) => -1,
+            }
         }
+        SYS_GETRANDOM => getrandom(arg0, arg1, arg2),
+        SYS_BRK => brk(arg0),
         SYS_MMAP => {
-            let Some(options) = (unsafe { get_ref_from_user_space(arg0 as *const MMapOptions) })
-            else {
-                return -EFAULT;
-            };
+            // SAFETY: all zeroes is a valid MMapOptions
+            let mut options: MMapOptions = unsafe { zeroed() };
+            if let Err(e) = copy_from_user(from_mut(&mut options), arg0 as *const MMapOptions) {
+                return -e;
+            }
             mmap(
                 options.addr,
                 options.length,
@@ -184,3 +288,58 @@ pub extern "C" fn handler(syscall_number: usize, arg0: usize, arg1: usize, arg2:
         _ => -ENOSYS,
     }
 }
+
+/// Replace the running process with the executable `data`, read from `path`, as with `execve`.
+///
+/// Only returns if `data` can't be executed.
+fn exec(data: &[u8], path: &str) -> isize {
+    let system = unwrap_system();
+
+    let Ok(elf) = Elf::parse_bytes(data) else {
+        return -ENOEXEC;
+    };
+
+    let Ok(mut control) = ThreadControlBlock::new_from_elf(elf, path, &system.process) else {
+        return -ENOEXEC;
+    };
+
+    // the new process takes the place of this one, so it stays in the same process group and
+    // directories, keeps its resource limits, and exits in its place
+    let (pgid, cwd, cwd_path, root, address_space_limit, fd_limit, replaces) = {
+        let pcb = running_process();
+        let mut pcb = pcb.lock();
+        let inherited = (
+            pcb.pgid,
+            pcb.cwd,
+            pcb.cwd_path.clone(),
+            pcb.root,
+            pcb.vmas.limit(),
+            pcb.fd_limit,
+            pcb.replaces.unwrap_or(pcb.pid),
+        );
+        // a vfork child gives its parent's memory back, rather than unmapping it
+        release_vfork_parent(&mut pcb);
+        // as on exit, nothing would see changes to shared file mappings otherwise
+        let _ = pcb.vmas.write_back_all();
+        // SAFETY: this thread dies below, without going back to userspace.
+        unsafe { pcb.vmas.unmap_all() };
+        inherited
+    };
+    if let Some(pcb) = system.process.table.get(control.pid) {
+        let mut pcb = pcb.lock();
+        pcb.pgid = pgid;
+        pcb.cwd = cwd;
+        pcb.cwd_path = cwd_path;
+        pcb.root = root;
+        // this process drops its own references as it exits
+        system.root_filesystem.lock().hold_cwd_and_root(&pcb);
+        pcb.replaces = Some(replaces);
+        pcb.vmas.set_limit(address_space_limit);
+        pcb.fd_limit = fd_limit;
+        inherit_cpu_limit(&mut control, &mut pcb);
+    }
+
+    system.threads.scheduler.lock().push(Box::new(control));
+
+    scheduler_yield_and_die();
+}
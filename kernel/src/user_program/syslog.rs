@@ -0,0 +1,50 @@
+use crate::interrupts::{mutex_irq::hold_interrupts, IntrLevel};
+use crate::user_program::syscall::{
+    EINVAL, SYSLOG_ACTION_CLEAR, SYSLOG_ACTION_READ_ALL, SYSLOG_ACTION_READ_CLEAR,
+    SYSLOG_ACTION_SIZE_BUFFER,
+};
+use crate::user_program::user_copy::copy_to_user;
+use alloc::vec;
+use kidneyos_shared::klog::{KERNEL_LOG, KERNEL_LOG_SIZE};
+
+/// Copy the most recent `len` bytes of the kernel log into `buf`, clearing the log afterwards if
+/// `clear` is set. Returns how many bytes were copied.
+fn read_all(buf: *mut u8, len: usize, clear: bool) -> isize {
+    let mut log = vec![0; len.min(KERNEL_LOG_SIZE)];
+    let len = {
+        // The log is written to by interrupt handlers too.
+        let _guard = hold_interrupts(IntrLevel::IntrOff);
+        // SAFETY: Single core, and interrupts are off.
+        unsafe {
+            let len = KERNEL_LOG.read_recent(&mut log);
+            if clear {
+                KERNEL_LOG.clear();
+            }
+            len
+        }
+    };
+    match copy_to_user(buf, &log[..len]) {
+        Ok(()) => len as isize,
+        Err(e) => -e,
+    }
+}
+
+/// The `syslog` syscall (`klogctl` in userspace), for reading the kernel log like `dmesg`. Only
+/// `SYSLOG_ACTION_READ_ALL`, `SYSLOG_ACTION_READ_CLEAR`, `SYSLOG_ACTION_CLEAR` and
+/// `SYSLOG_ACTION_SIZE_BUFFER` are supported.
+pub fn syslog(action: i32, buf: *mut u8, len: i32) -> isize {
+    match action {
+        SYSLOG_ACTION_READ_ALL | SYSLOG_ACTION_READ_CLEAR => match usize::try_from(len) {
+            Ok(len) => read_all(buf, len, action == SYSLOG_ACTION_READ_CLEAR),
+            Err(_) => -EINVAL,
+        },
+        SYSLOG_ACTION_CLEAR => {
+            let _guard = hold_interrupts(IntrLevel::IntrOff);
+            // SAFETY: Single core, and interrupts are off.
+            unsafe { KERNEL_LOG.clear() };
+            0
+        }
+        SYSLOG_ACTION_SIZE_BUFFER => KERNEL_LOG_SIZE as isize,
+        _ => -EINVAL,
+    }
+}
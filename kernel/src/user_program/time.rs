@@ -1,4 +1,8 @@
+use crate::interrupts::timer::sleep;
+use crate::user_program::syscall::EINVAL;
+use crate::user_program::user_copy::copy_from_user;
 use core::arch::asm;
+use core::time::Duration;
 
 // QEMU default is 100 ticks per second
 // This will need to be changed when compiling for a real system
@@ -7,7 +11,7 @@ pub const TICKS_PER_SECOND: u64 = 100;
 pub const CLOCK_REALTIME: usize = 0;
 pub const CLOCK_MONOTONIC: usize = 1;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 #[repr(C)]
 pub struct Timespec {
     pub tv_sec: i64,
@@ -147,3 +151,24 @@ pub fn get_rtc() -> Timespec {
         tv_nsec: 0,
     }
 }
+
+/// The `nanosleep` syscall: sleep for `duration`. Nothing interrupts the sleep, so `remainder` is
+/// never written to.
+pub fn nanosleep(duration: *const Timespec, _remainder: *mut Timespec) -> isize {
+    let mut timespec = [Timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    }];
+    if let Err(e) = copy_from_user(&mut timespec, duration) {
+        return -e;
+    }
+    let Timespec { tv_sec, tv_nsec } = timespec[0];
+    let (Ok(secs), Ok(nanos)) = (u64::try_from(tv_sec), u32::try_from(tv_nsec)) else {
+        return -EINVAL;
+    };
+    if nanos >= 1_000_000_000 {
+        return -EINVAL;
+    }
+    sleep(Duration::new(secs, nanos));
+    0
+}
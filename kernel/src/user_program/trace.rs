@@ -0,0 +1,181 @@
+//! Syscall tracing, like `strace` (enabled by the `syscall_trace` feature).
+//!
+//! Each syscall is logged to the serial port as it returns, as its name and arguments, followed by
+//! its return value, e.g. `[pid 3] write(fd=1, buf=0x8048f00, count=12) = 12`. Syscalls which aren't
+//! named below are logged by number, with all five argument registers. Syscalls which never return,
+//! like `exit`, are logged before they run, with a return value of `?`.
+//!
+//! Nothing is allocated, so this can be used while debugging the allocator too.
+
+use crate::threading::process::Pid;
+use core::fmt::{self, Write};
+use kidneyos_shared::serial::SERIAL_WRITER;
+use kidneyos_syscalls::defs::*;
+
+/// How an argument is printed.
+#[derive(Clone, Copy)]
+enum Arg {
+    Int(&'static str),
+    Ptr(&'static str),
+}
+
+use Arg::{Int, Ptr};
+
+/// The name and arguments of the syscall `number`, if it's one which gets decoded.
+fn signature(number: usize) -> Option<(&'static str, &'static [Arg])> {
+    Some(match number {
+        SYS_EXIT => ("exit", &[Int("status")]),
+        SYS_READ => ("read", &[Int("fd"), Ptr("buf"), Int("count")]),
+        SYS_WRITE => ("write", &[Int("fd"), Ptr("buf"), Int("count")]),
+        SYS_OPEN => ("open", &[Ptr("path"), Int("flags")]),
+        SYS_CLOSE => ("close", &[Int("fd")]),
+        SYS_WAITPID => ("waitpid", &[Int("pid"), Ptr("status"), Int("options")]),
+        SYS_LINK => ("link", &[Ptr("source"), Ptr("dest")]),
+        SYS_UNLINK => ("unlink", &[Ptr("path")]),
+        SYS_EXECVE => ("execve", &[Ptr("path"), Ptr("argv"), Ptr("envp")]),
+        SYS_CHDIR => ("chdir", &[Ptr("path")]),
+        SYS_GETPID => ("getpid", &[]),
+        SYS_PAUSE => ("pause", &[]),
+        SYS_KILL => ("kill", &[Int("pid"), Int("sig")]),
+        SYS_RENAME => ("rename", &[Ptr("source"), Ptr("dest")]),
+        SYS_MKDIR => ("mkdir", &[Ptr("path")]),
+        SYS_RMDIR => ("rmdir", &[Ptr("path")]),
+        SYS_DUP => ("dup", &[Int("fd")]),
+        SYS_PIPE => ("pipe", &[Ptr("fds")]),
+        SYS_BRK => ("brk", &[Ptr("addr")]),
+        SYS_MUNMAP => ("munmap", &[Ptr("addr"), Int("length")]),
+        SYS_TRUNCATE => ("truncate", &[Ptr("path"), Int("size_lo"), Int("size_hi")]),
+        SYS_CHROOT => ("chroot", &[Ptr("path")]),
+        SYS_DUP2 => ("dup2", &[Int("old_fd"), Int("new_fd")]),
+        SYS_GETPPID => ("getppid", &[]),
+        SYS_SIGACTION => ("sigaction", &[Int("sig"), Ptr("act"), Ptr("oldact")]),
+        SYS_GETRUSAGE => ("getrusage", &[Int("who"), Ptr("usage")]),
+        SYS_SYSLOG => ("syslog", &[Int("type"), Ptr("buf"), Int("len")]),
+        SYS_FSTAT => ("fstat", &[Int("fd"), Ptr("statbuf")]),
+        SYS_SIGRETURN => ("sigreturn", &[]),
+        SYS_SIGPROCMASK => ("sigprocmask", &[Int("how"), Ptr("set"), Ptr("oldset")]),
+        SYS_LSEEK64 => ("lseek64", &[Int("fd"), Ptr("offset"), Int("whence")]),
+        SYS_GETDENTS => ("getdents", &[Int("fd"), Ptr("dirp"), Int("count")]),
+        SYS_FLOCK => ("flock", &[Int("fd"), Int("operation")]),
+        SYS_MSYNC => ("msync", &[Ptr("addr"), Int("length"), Int("flags")]),
+        SYS_SCHED_YIELD => ("sched_yield", &[]),
+        SYS_NANOSLEEP => ("nanosleep", &[Ptr("duration"), Ptr("remainder")]),
+        SYS_PRCTL => ("prctl", &[Int("option"), Ptr("arg2")]),
+        SYS_RT_SIGSUSPEND => ("rt_sigsuspend", &[Ptr("mask"), Int("sigsetsize")]),
+        SYS_GETCWD => ("getcwd", &[Ptr("buf"), Int("size")]),
+        SYS_VFORK => ("vfork", &[]),
+        SYS_GETDENTS64 => ("getdents64", &[Int("fd"), Ptr("dirp"), Int("count")]),
+        SYS_FUTEX => ("futex", &[Ptr("uaddr"), Int("op"), Int("val")]),
+        SYS_WAITID => (
+            "waitid",
+            &[Int("idtype"), Int("id"), Ptr("infop"), Int("options")],
+        ),
+        SYS_OPENAT => ("openat", &[Int("dirfd"), Ptr("path"), Int("flags")]),
+        SYS_MKDIRAT => ("mkdirat", &[Int("dirfd"), Ptr("path")]),
+        SYS_UNLINKAT => ("unlinkat", &[Int("dirfd"), Ptr("path"), Int("flags")]),
+        SYS_SYMLINKAT => ("symlinkat", &[Ptr("source"), Int("dirfd"), Ptr("dest")]),
+        SYS_READLINKAT => (
+            "readlinkat",
+            &[Int("dirfd"), Ptr("path"), Ptr("buf"), Int("size")],
+        ),
+        SYS_SIGNALFD4 => (
+            "signalfd4",
+            &[Int("fd"), Ptr("mask"), Int("sizemask"), Int("flags")],
+        ),
+        SYS_FADVISE64 => (
+            "fadvise64",
+            &[
+                Int("fd"),
+                Int("offset_lo"),
+                Int("offset_hi"),
+                Int("len"),
+                Int("advice"),
+            ],
+        ),
+        _ => return None,
+    })
+}
+
+/// Write the trace line for the syscall `number` made by `pid`, including the newline.
+fn write_trace(
+    out: &mut impl Write,
+    pid: Pid,
+    number: usize,
+    args: [usize; 5],
+    result: Option<isize>,
+) -> fmt::Result {
+    write!(out, "[pid {pid}] ")?;
+    match signature(number) {
+        Some((name, arg_kinds)) => {
+            write!(out, "{name}(")?;
+            for (i, (kind, value)) in arg_kinds.iter().zip(args).enumerate() {
+                if i > 0 {
+                    write!(out, ", ")?;
+                }
+                match kind {
+                    Int(name) => write!(out, "{name}={}", value as isize)?,
+                    Ptr(name) => write!(out, "{name}={value:#x}")?,
+                }
+            }
+        }
+        None => {
+            write!(out, "syscall_{number:#x}(")?;
+            for (i, value) in args.iter().enumerate() {
+                if i > 0 {
+                    write!(out, ", ")?;
+                }
+                write!(out, "{value:#x}")?;
+            }
+        }
+    }
+    match result {
+        Some(result) => writeln!(out, ") = {result}"),
+        None => writeln!(out, ") = ?"),
+    }
+}
+
+/// Log the syscall `number` made by `pid` to the serial port. `result` is `None` if the syscall
+/// doesn't return.
+pub fn trace(pid: Pid, number: usize, args: [usize; 5], result: Option<isize>) {
+    // SAFETY: Single core, and only written to by the print macros otherwise, which don't hold a
+    // reference to it.
+    unsafe {
+        let _ = write_trace(&mut SERIAL_WRITER, pid, number, args, result);
+    }
+}
+
+/// Whether the syscall `number` never returns, so has to be logged before it runs.
+pub fn never_returns(number: usize) -> bool {
+    number == SYS_EXIT
+}
+
+#[cfg(test)]
+mod test {
+    use super::write_trace;
+    use kidneyos_syscalls::defs::{SYS_EXIT, SYS_WRITE};
+
+    #[test]
+    fn write_is_decoded() {
+        let mut line = String::new();
+        write_trace(&mut line, 3, SYS_WRITE, [1, 0x8048f00, 12, 0, 0], Some(12)).unwrap();
+        assert_eq!(line, "[pid 3] write(fd=1, buf=0x8048f00, count=12) = 12\n");
+    }
+
+    #[test]
+    fn errors_and_unknown_syscalls() {
+        let mut line = String::new();
+        write_trace(&mut line, 1, SYS_WRITE, [usize::MAX, 0, 0, 0, 0], Some(-9)).unwrap();
+        assert_eq!(line, "[pid 1] write(fd=-1, buf=0x0, count=0) = -9\n");
+
+        let mut line = String::new();
+        write_trace(&mut line, 2, SYS_EXIT, [0, 0, 0, 0, 0], None).unwrap();
+        assert_eq!(line, "[pid 2] exit(status=0) = ?\n");
+
+        let mut line = String::new();
+        write_trace(&mut line, 2, 0x2000, [1, 2, 3, 4, 5], Some(0)).unwrap();
+        assert_eq!(
+            line,
+            "[pid 2] syscall_0x2000(0x1, 0x2, 0x3, 0x4, 0x5) = 0\n"
+        );
+    }
+}
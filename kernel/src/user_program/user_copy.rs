@@ -0,0 +1,126 @@
+use crate::system::{running_process, unwrap_system};
+use crate::user_program::syscall::EFAULT;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::mem::size_of_val;
+use core::ptr::copy_nonoverlapping;
+use kidneyos_shared::mem::{OFFSET, PAGE_FRAME_SIZE};
+
+pub enum CStrError {
+    Fault,
+    BadUtf8,
+    TooLong,
+}
+
+fn can_access(page: usize, write: bool) -> bool {
+    unwrap_system()
+        .threads
+        .running_thread
+        .lock()
+        .as_ref()
+        .expect("A syscall was called without a running thread.")
+        .page_manager
+        .can_access(page, write)
+}
+
+/// Checks that `start..start + len` lies entirely below `OFFSET` and is mapped in the running
+/// thread's page directory (and writeable, if `write` is set).
+///
+/// Pages that are covered by one of the running process' VMAs but haven't been touched yet
+/// (e.g. fresh heap pages) are faulted in first, just as if userspace had accessed them.
+///
+/// Returns `Err(EFAULT)` if any part of the range can't be accessed.
+pub fn check_user_range(start: usize, len: usize, write: bool) -> Result<(), isize> {
+    if len == 0 {
+        return Ok(());
+    }
+    let end = start.checked_add(len).ok_or(EFAULT)?;
+    if end > OFFSET {
+        return Err(EFAULT);
+    }
+    let first_page = start & !(PAGE_FRAME_SIZE - 1);
+    for page in (first_page..end).step_by(PAGE_FRAME_SIZE) {
+        if !can_access(page, write) {
+            let pcb = running_process();
+            // SAFETY: we don't have any pointers into the page yet.
+            if !unsafe { pcb.lock().vmas.install_pte(page, write) } {
+                return Err(EFAULT);
+            }
+        }
+        if !can_access(page, write) {
+            return Err(EFAULT);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the physical address that the userspace address `addr` refers to, faulting its page in
+/// first if necessary (as with [`check_user_range`]).
+///
+/// Returns `Err(EFAULT)` if `addr` isn't readable.
+pub fn user_phys_addr(addr: usize) -> Result<usize, isize> {
+    check_user_range(addr, 1, false)?;
+    unwrap_system()
+        .threads
+        .running_thread
+        .lock()
+        .as_ref()
+        .expect("A syscall was called without a running thread.")
+        .page_manager
+        .translate(addr)
+        .ok_or(EFAULT)
+}
+
+/// Copies `dst.len()` values from the userspace pointer `src` into `dst`.
+///
+/// `src` doesn't need to be aligned, but every bit pattern must be a valid `T`, since userspace
+/// can put anything there.
+///
+/// Returns `Err(EFAULT)` (without copying anything) if `src` isn't readable.
+pub fn copy_from_user<T: Copy>(dst: &mut [T], src: *const T) -> Result<(), isize> {
+    let len = size_of_val(dst);
+    check_user_range(src as usize, len, false)?;
+    // SAFETY: just checked that src..src + len is readable user memory, which can't overlap dst.
+    unsafe { copy_nonoverlapping(src.cast::<u8>(), dst.as_mut_ptr().cast::<u8>(), len) };
+    Ok(())
+}
+
+/// Copies `src` to the userspace pointer `dst`, which doesn't need to be aligned.
+///
+/// Returns `Err(EFAULT)` (without copying anything) if `dst` isn't writeable.
+pub fn copy_to_user<T: Copy>(dst: *mut T, src: &[T]) -> Result<(), isize> {
+    let len = size_of_val(src);
+    check_user_range(dst as usize, len, true)?;
+    // SAFETY: just checked that dst..dst + len is writeable user memory, which can't overlap src.
+    unsafe { copy_nonoverlapping(src.as_ptr().cast::<u8>(), dst.cast::<u8>(), len) };
+    Ok(())
+}
+
+/// Copies a null-terminated string from the userspace pointer `src`.
+///
+/// At most `max_len` bytes (including the null terminator) are read, one page at a time, so we
+/// never look past the terminator into a page that might not be mapped.
+///
+/// Returns `CStrError::Fault` if we run into memory that isn't readable before finding the
+/// terminator, and `CStrError::TooLong` if there's no terminator in the first `max_len` bytes.
+pub fn copy_cstr_from_user(src: *const u8, max_len: usize) -> Result<String, CStrError> {
+    let mut bytes = Vec::new();
+    let mut addr = src as usize;
+    loop {
+        if bytes.len() == max_len {
+            return Err(CStrError::TooLong);
+        }
+        check_user_range(addr, 1, false).map_err(|_| CStrError::Fault)?;
+        let page_end = (addr & !(PAGE_FRAME_SIZE - 1)) + PAGE_FRAME_SIZE;
+        let chunk_len = core::cmp::min(page_end - addr, max_len - bytes.len());
+        // SAFETY: addr..page_end lies in a single readable user page.
+        let chunk = unsafe { core::slice::from_raw_parts(addr as *const u8, chunk_len) };
+        if let Some(nul) = chunk.iter().position(|&b| b == 0) {
+            bytes.extend_from_slice(&chunk[..nul]);
+            break;
+        }
+        bytes.extend_from_slice(chunk);
+        addr += chunk_len;
+    }
+    String::from_utf8(bytes).map_err(|_| CStrError::BadUtf8)
+}
@@ -0,0 +1,449 @@
+use crate::block::block_core::{Block, BlockSector, BLOCK_SECTOR_SIZE};
+use crate::vfs::{DirEntries, Error, FileInfo, INodeNum, Path, Result, SimpleFileSystem};
+use alloc::{string::String, vec, vec::Vec};
+
+/// Marks sector 0 of a journal, which records how much of it has been applied.
+const SUPERBLOCK_MAGIC: u32 = u32::from_le_bytes(*b"KJNL");
+/// Marks the start of each record.
+const RECORD_MAGIC: u32 = u32::from_le_bytes(*b"KJRC");
+/// Records start here, after the superblock.
+const FIRST_RECORD: BlockSector = 1;
+
+/// Size of a record header, which is followed by the record's payload. Records always start at
+/// the beginning of a sector.
+///
+/// ```text
+/// 0..4    magic
+/// 4..8    sequence number
+/// 8       op
+/// 12..16  inode (the parent directory for create and unlink)
+/// 16..24  offset (for write)
+/// 24..28  payload length
+/// 28..32  CRC-32 of the rest of the header and the payload
+/// ```
+const HEADER_SIZE: usize = 32;
+
+/// What a journal record does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    /// Create the file named by the payload
+    Create = 1,
+    /// Write the payload to the file
+    Write = 2,
+    /// Unlink the file named by the payload
+    Unlink = 3,
+}
+
+impl Op {
+    fn from_u8(op: u8) -> Option<Self> {
+        match op {
+            1 => Some(Self::Create),
+            2 => Some(Self::Write),
+            3 => Some(Self::Unlink),
+            _ => None,
+        }
+    }
+}
+
+struct Record {
+    seq: u32,
+    op: Op,
+    inode: INodeNum,
+    offset: u64,
+    payload: Vec<u8>,
+}
+
+/// CRC-32 (as used by zlib and Ethernet) of `parts`, one after the other.
+fn crc32(parts: &[&[u8]]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in parts.iter().flat_map(|part| part.iter()) {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+impl Record {
+    /// The record as it's written to the journal, padded to a whole number of sectors.
+    fn encode(&self) -> Vec<u8> {
+        let len = (HEADER_SIZE + self.payload.len()).next_multiple_of(BLOCK_SECTOR_SIZE);
+        let mut data = vec![0; len];
+        data[0..4].copy_from_slice(&RECORD_MAGIC.to_le_bytes());
+        data[4..8].copy_from_slice(&self.seq.to_le_bytes());
+        data[8] = self.op as u8;
+        data[12..16].copy_from_slice(&self.inode.to_le_bytes());
+        data[16..24].copy_from_slice(&self.offset.to_le_bytes());
+        data[24..28].copy_from_slice(&(self.payload.len() as u32).to_le_bytes());
+        let checksum = crc32(&[&data[..28], &self.payload]);
+        data[28..32].copy_from_slice(&checksum.to_le_bytes());
+        data[HEADER_SIZE..HEADER_SIZE + self.payload.len()].copy_from_slice(&self.payload);
+        data
+    }
+
+    /// The name in the payload of a create or unlink.
+    fn name(&self) -> Result<&Path> {
+        core::str::from_utf8(&self.payload).map_err(|_| Error::InvalidArgument)
+    }
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+/// Write-ahead journal in front of a file system, for demonstrating crash consistency.
+///
+/// Each create, write and unlink is written to the journal on a block device before it's applied
+/// to the wrapped file system, and the journal's superblock is updated once it has been. When the
+/// journal is mounted again, records written after the last update are replayed, so an operation
+/// interrupted by a crash is either finished or was never started. Other changes go straight to
+/// the wrapped file system.
+///
+/// Records refer to inodes by number, so the wrapped file system must keep its inode numbers
+/// between mounts. Once the journal fills up, it starts again from the beginning, since
+/// everything in it has been applied by then.
+pub struct JournalFS<F> {
+    inner: F,
+    journal: Block,
+    /// Sequence number of the last record which has been applied
+    applied: u32,
+    /// Sector where the next record goes
+    end: BlockSector,
+    /// Stop each operation once its record is written, as if the machine had crashed
+    #[cfg(test)]
+    crash_before_apply: bool,
+}
+
+impl<F: SimpleFileSystem> JournalFS<F> {
+    /// Put the journal on `journal` in front of `inner`, replaying anything in it which hadn't
+    /// been applied yet. If `journal` doesn't hold a journal, a new one is started.
+    pub fn mount(inner: F, journal: Block) -> Result<Self> {
+        if journal.get_size() <= FIRST_RECORD {
+            return Err(Error::NoSpace);
+        }
+        let mut fs = Self {
+            inner,
+            journal,
+            applied: 0,
+            end: FIRST_RECORD,
+            #[cfg(test)]
+            crash_before_apply: false,
+        };
+        let mut superblock = [0; BLOCK_SECTOR_SIZE];
+        fs.journal.read(0, &mut superblock)?;
+        if u32_at(&superblock, 0) == SUPERBLOCK_MAGIC {
+            fs.applied = u32_at(&superblock, 4);
+            fs.end = u32_at(&superblock, 8).clamp(FIRST_RECORD, fs.journal.get_size());
+            fs.replay()?;
+        }
+        fs.checkpoint()?;
+        Ok(fs)
+    }
+
+    /// Take the wrapped file system and the journal's device back.
+    pub fn into_parts(self) -> (F, Block) {
+        (self.inner, self.journal)
+    }
+
+    /// Record in the superblock that everything up to `self.end` has been applied.
+    fn checkpoint(&mut self) -> Result<()> {
+        let mut superblock = [0; BLOCK_SECTOR_SIZE];
+        superblock[0..4].copy_from_slice(&SUPERBLOCK_MAGIC.to_le_bytes());
+        superblock[4..8].copy_from_slice(&self.applied.to_le_bytes());
+        superblock[8..12].copy_from_slice(&self.end.to_le_bytes());
+        self.journal.write(0, &superblock)?;
+        Ok(())
+    }
+
+    /// Read the record at `sector`, and how many sectors it takes up, or `None` if there isn't a
+    /// whole, uncorrupted one there.
+    fn read_record(&self, sector: BlockSector) -> Result<Option<(Record, BlockSector)>> {
+        let size = self.journal.get_size();
+        let mut data = vec![0; BLOCK_SECTOR_SIZE];
+        self.journal.read(sector, &mut data)?;
+        let (Some(op), RECORD_MAGIC) = (Op::from_u8(data[8]), u32_at(&data, 0)) else {
+            return Ok(None);
+        };
+        // The length hasn't been checked against the CRC yet, so it could be anything.
+        let len = u32_at(&data, 24) as usize;
+        let room = (size.saturating_sub(sector) as usize).saturating_mul(BLOCK_SECTOR_SIZE);
+        let Some(record_size) = HEADER_SIZE.checked_add(len).filter(|&n| n <= room) else {
+            return Ok(None);
+        };
+        let sectors = record_size.div_ceil(BLOCK_SECTOR_SIZE);
+        data.resize(sectors * BLOCK_SECTOR_SIZE, 0);
+        for i in 1..sectors {
+            let range = i * BLOCK_SECTOR_SIZE..(i + 1) * BLOCK_SECTOR_SIZE;
+            self.journal
+                .read(sector + i as BlockSector, &mut data[range])?;
+        }
+        let payload = &data[HEADER_SIZE..HEADER_SIZE + len];
+        if crc32(&[&data[..28], payload]) != u32_at(&data, 28) {
+            return Ok(None);
+        }
+        let record = Record {
+            seq: u32_at(&data, 4),
+            op,
+            inode: u32_at(&data, 12),
+            offset: u64::from_le_bytes(data[16..24].try_into().unwrap()),
+            payload: payload.to_vec(),
+        };
+        Ok(Some((record, sectors as BlockSector)))
+    }
+
+    /// Apply the records after the last one which was applied.
+    fn replay(&mut self) -> Result<()> {
+        while self.end < self.journal.get_size() {
+            let Some((record, sectors)) = self.read_record(self.end)? else {
+                break;
+            };
+            // anything else was left over from before the journal wrapped around
+            if record.seq != self.applied.wrapping_add(1) {
+                break;
+            }
+            // Operations which failed the first time fail again, and ones which were applied
+            // before the crash (but not checkpointed) are harmless to apply again.
+            let _ = self.apply(&record);
+            self.applied = record.seq;
+            self.end += sectors;
+        }
+        Ok(())
+    }
+
+    /// Do what `record` says to the wrapped file system, returning the created inode or the
+    /// number of bytes written.
+    fn apply(&mut self, record: &Record) -> Result<usize> {
+        match record.op {
+            Op::Create => Ok(self.inner.create(record.inode, record.name()?)? as usize),
+            Op::Write => self
+                .inner
+                .write(record.inode, record.offset, &record.payload),
+            Op::Unlink => self.inner.unlink(record.inode, record.name()?).map(|()| 0),
+        }
+    }
+
+    /// Write a record to the journal, then apply it, then checkpoint.
+    fn commit(&mut self, op: Op, inode: INodeNum, offset: u64, payload: &[u8]) -> Result<usize> {
+        let record = Record {
+            seq: self.applied.wrapping_add(1),
+            op,
+            inode,
+            offset,
+            payload: payload.to_vec(),
+        };
+        let data = record.encode();
+        let sectors = (data.len() / BLOCK_SECTOR_SIZE) as BlockSector;
+        let size = self.journal.get_size();
+        if sectors > size - FIRST_RECORD {
+            return Err(Error::NoSpace);
+        }
+        if self.end + sectors > size {
+            // Everything has been applied, so start again, making sure a crash doesn't send
+            // replay to the old end.
+            self.end = FIRST_RECORD;
+            self.checkpoint()?;
+        }
+        for (i, sector) in data.chunks_exact(BLOCK_SECTOR_SIZE).enumerate() {
+            self.journal.write(self.end + i as BlockSector, sector)?;
+        }
+        // The record must be on disk before any of the changes it describes.
+        self.journal.barrier()?;
+
+        #[cfg(test)]
+        if self.crash_before_apply {
+            return Err(Error::IO(String::from("simulated crash")));
+        }
+
+        let result = self.apply(&record);
+        self.applied = record.seq;
+        self.end += sectors;
+        self.checkpoint()?;
+        result
+    }
+}
+
+impl<F: SimpleFileSystem> SimpleFileSystem for JournalFS<F> {
+    fn root(&self) -> INodeNum {
+        self.inner.root()
+    }
+    fn open(&mut self, inode: INodeNum) -> Result<()> {
+        self.inner.open(inode)
+    }
+    fn create(&mut self, parent: INodeNum, name: &Path) -> Result<INodeNum> {
+        self.commit(Op::Create, parent, 0, name.as_bytes())
+            .map(|inode| inode as INodeNum)
+    }
+    fn mkdir(&mut self, parent: INodeNum, name: &Path) -> Result<INodeNum> {
+        self.inner.mkdir(parent, name)
+    }
+    fn unlink(&mut self, parent: INodeNum, name: &Path) -> Result<()> {
+        self.commit(Op::Unlink, parent, 0, name.as_bytes())
+            .map(|_| ())
+    }
+    fn rmdir(&mut self, parent: INodeNum, name: &Path) -> Result<()> {
+        self.inner.rmdir(parent, name)
+    }
+    fn readdir(&mut self, dir: INodeNum) -> Result<DirEntries> {
+        self.inner.readdir(dir)
+    }
+    fn release(&mut self, inode: INodeNum) {
+        self.inner.release(inode)
+    }
+    fn read(&mut self, file: INodeNum, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        self.inner.read(file, offset, buf)
+    }
+    fn write(&mut self, file: INodeNum, offset: u64, buf: &[u8]) -> Result<usize> {
+        self.commit(Op::Write, file, offset, buf)
+    }
+    fn stat(&mut self, file: INodeNum) -> Result<FileInfo> {
+        self.inner.stat(file)
+    }
+    fn link(&mut self, source: INodeNum, parent: INodeNum, name: &Path) -> Result<()> {
+        self.inner.link(source, parent, name)
+    }
+    fn symlink(&mut self, link: &Path, parent: INodeNum, name: &Path) -> Result<INodeNum> {
+        self.inner.symlink(link, parent, name)
+    }
+    fn readlink(&mut self, link: INodeNum) -> Result<String> {
+        self.inner.readlink(link)
+    }
+    fn readlink_no_alloc<'a>(
+        &mut self,
+        link: INodeNum,
+        buf: &'a mut [u8],
+    ) -> Result<Option<&'a str>> {
+        self.inner.readlink_no_alloc(link, buf)
+    }
+    fn truncate(&mut self, file: INodeNum, size: u64) -> Result<()> {
+        self.inner.truncate(file, size)
+    }
+    fn fallocate(&mut self, file: INodeNum, offset: u64, len: u64) -> Result<()> {
+        self.inner.fallocate(file, offset, len)
+    }
+    fn sync(&mut self) -> Result<()> {
+        self.inner.sync()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::block_core::test::block_from_file;
+    use crate::vfs::tempfs::TempFS;
+    use std::io::Cursor;
+
+    fn read_all(fs: &mut impl SimpleFileSystem, file: INodeNum) -> Vec<u8> {
+        let mut buf = [0; 64];
+        let n = fs.read(file, 0, &mut buf).unwrap();
+        buf[..n].to_vec()
+    }
+
+    fn names(fs: &mut impl SimpleFileSystem) -> Vec<String> {
+        let root = fs.root();
+        fs.readdir(root)
+            .unwrap()
+            .to_sorted_vec()
+            .into_iter()
+            .map(|entry| entry.name.into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn replay_after_crash() {
+        let journal = block_from_file(Cursor::new(vec![0; 16 * BLOCK_SECTOR_SIZE]));
+        let mut fs = JournalFS::mount(TempFS::new(), journal).unwrap();
+        let root = fs.root();
+        let a = fs.create(root, "a").unwrap();
+        assert_eq!(fs.write(a, 0, b"hello").unwrap(), 5);
+        let b = fs.create(root, "b").unwrap();
+        // big enough to take up more than one sector
+        let big = vec![b'b'; 700];
+        assert_eq!(fs.write(b, 0, &big).unwrap(), 700);
+
+        // crash once the record is in the journal, but before it's applied
+        fs.crash_before_apply = true;
+        assert!(fs.write(a, 5, b", world").is_err());
+        let (mut inner, journal) = fs.into_parts();
+        assert_eq!(read_all(&mut inner, a), b"hello");
+
+        let mut fs = JournalFS::mount(inner, journal).unwrap();
+        assert_eq!(read_all(&mut fs, a), b"hello, world");
+        assert_eq!(fs.stat(b).unwrap().size, 700);
+
+        fs.crash_before_apply = true;
+        assert!(fs.unlink(root, "b").is_err());
+        let (mut inner, journal) = fs.into_parts();
+        assert_eq!(names(&mut inner), ["a", "b"]);
+        let mut fs = JournalFS::mount(inner, journal).unwrap();
+        assert_eq!(names(&mut fs), ["a"]);
+
+        // Records which have been replayed aren't replayed again.
+        let (mut inner, journal) = fs.into_parts();
+        inner.write(a, 0, b"HELLO").unwrap();
+        let mut fs = JournalFS::mount(inner, journal).unwrap();
+        assert_eq!(read_all(&mut fs, a), b"HELLO, world");
+
+        // The journal wraps around once it's full.
+        for i in 0..20u8 {
+            fs.write(a, 0, &[i; 600]).unwrap();
+        }
+        fs.crash_before_apply = true;
+        assert!(fs.write(a, 0, b"last").is_err());
+        let (inner, journal) = fs.into_parts();
+        let mut fs = JournalFS::mount(inner, journal).unwrap();
+        let mut buf = [0; 5];
+        fs.read(a, 0, &mut buf).unwrap();
+        assert_eq!(&buf, b"last\x13");
+    }
+
+    #[test]
+    fn torn_record_is_ignored() {
+        let journal = block_from_file(Cursor::new(vec![0; 8 * BLOCK_SECTOR_SIZE]));
+        let mut fs = JournalFS::mount(TempFS::new(), journal).unwrap();
+        let root = fs.root();
+        let a = fs.create(root, "a").unwrap();
+        fs.crash_before_apply = true;
+        assert!(fs.write(a, 0, b"half written").is_err());
+        let (inner, journal) = fs.into_parts();
+
+        // corrupt the record, as if the crash came while it was being written
+        let end = FIRST_RECORD + 1;
+        let mut sector = [0; BLOCK_SECTOR_SIZE];
+        journal.read(end, &mut sector).unwrap();
+        sector[HEADER_SIZE] ^= 0xFF;
+        journal.write(end, &sector).unwrap();
+
+        let mut fs = JournalFS::mount(inner, journal).unwrap();
+        assert_eq!(read_all(&mut fs, a), b"");
+        fs.write(a, 0, b"ok").unwrap();
+        assert_eq!(read_all(&mut fs, a), b"ok");
+    }
+
+    #[test]
+    fn corrupt_length_is_ignored() {
+        let journal = block_from_file(Cursor::new(vec![0; 8 * BLOCK_SECTOR_SIZE]));
+        let mut fs = JournalFS::mount(TempFS::new(), journal).unwrap();
+        let root = fs.root();
+        let a = fs.create(root, "a").unwrap();
+        fs.crash_before_apply = true;
+        assert!(fs.write(a, 0, b"half written").is_err());
+        let (mut inner, mut journal) = fs.into_parts();
+
+        // a length which runs past the end of the journal, or overflows
+        let end = FIRST_RECORD + 1;
+        let mut sector = [0; BLOCK_SECTOR_SIZE];
+        journal.read(end, &mut sector).unwrap();
+        for len in [8 * BLOCK_SECTOR_SIZE as u32, u32::MAX] {
+            sector[24..28].copy_from_slice(&len.to_le_bytes());
+            journal.write(end, &sector).unwrap();
+            let mut fs = JournalFS::mount(inner, journal).unwrap();
+            assert_eq!(read_all(&mut fs, a), b"");
+            (inner, journal) = fs.into_parts();
+        }
+    }
+}
@@ -1,3 +1,5 @@
+pub mod journal;
+pub mod overlayfs;
 #[cfg(test)]
 pub mod read_only_test;
 pub mod tempfs;
@@ -18,7 +20,7 @@ pub trait FileHandle: core::fmt::Debug + Send + Sync {
     fn inode(&self) -> INodeNum;
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Error {
     /// directory entry not found
     NotFound,
@@ -34,7 +36,7 @@ pub enum Error {
     NotEmpty,
     /// Target destination already exists
     Exists,
-    /// Unsupported operation (e.g. file system does not support symlinks)
+    /// Unsupported operation (e.g. file system does not support symlinks) (EOPNOTSUPP)
     Unsupported,
     /// Write operation to a read-only file system
     ReadOnlyFS,
@@ -58,6 +60,12 @@ pub enum Error {
     HardLinkBetweenFileSystems,
     /// All read handles are closed, a write cannot be performed (EPIPE).
     PipeClosed,
+    /// Waiting was interrupted by Ctrl-C or a signal (EINTR)
+    Interrupted,
+    /// The operation would have to wait, but was asked not to (EWOULDBLOCK)
+    WouldBlock,
+    /// Invalid argument, e.g. an unknown file system type (EINVAL)
+    InvalidArgument,
     /// Error accessing underlying storage device
     IO(String),
 }
@@ -92,6 +100,9 @@ impl core::fmt::Display for Error {
                 write!(f, "hard link between different file systems")
             }
             Self::PipeClosed => write!(f, "write to closed pipe"),
+            Self::Interrupted => write!(f, "interrupted"),
+            Self::WouldBlock => write!(f, "operation would block"),
+            Self::InvalidArgument => write!(f, "invalid argument"),
             Self::IO(s) => write!(f, "I/O error: {s}"),
         }
     }
@@ -100,6 +111,8 @@ impl core::fmt::Display for Error {
 impl core::error::Error for Error {}
 
 impl Error {
+    /// The errno syscalls return (negated) for this error. Each error has its own errno, except
+    /// for a few which are reported as `EINVAL`, as Linux does for them.
     pub fn to_isize(&self) -> isize {
         match self {
             Error::NotFound => syscall::ENOENT,
@@ -109,7 +122,7 @@ impl Error {
             Error::TooManyLinks => syscall::EMLINK,
             Error::NotEmpty => syscall::ENOTEMPTY,
             Error::Exists => syscall::EEXIST,
-            Error::Unsupported => syscall::EIO,
+            Error::Unsupported => syscall::EOPNOTSUPP,
             Error::ReadOnlyFS => syscall::EROFS,
             Error::TooManyOpenFiles => syscall::EMFILE,
             Error::BadFd => syscall::EBADF,
@@ -121,6 +134,9 @@ impl Error {
             Error::TooManyLevelsOfLinks => syscall::ELOOP,
             Error::HardLinkBetweenFileSystems => syscall::EXDEV,
             Error::PipeClosed => syscall::EPIPE,
+            Error::Interrupted => syscall::EINTR,
+            Error::WouldBlock => syscall::EWOULDBLOCK,
+            Error::InvalidArgument => syscall::EINVAL,
             Error::IO(_) => syscall::EIO,
         }
     }
@@ -358,6 +374,12 @@ pub trait FileSystem: Sized + Sync + Send {
     ///
     /// The kernel must ensure that `file` is a regular file before calling this.
     fn truncate(&mut self, file: &mut Self::FileHandle, size: u64) -> Result<()>;
+    /// Make sure space is allocated for the `len` bytes of `file` starting at `offset`, so that
+    /// writing to them can't run out of space. If that goes past the end of the file, its size is
+    /// increased as with [`Self::truncate`], but it's never decreased.
+    ///
+    /// The kernel must ensure that `file` is a regular file before calling this.
+    fn fallocate(&mut self, file: &mut Self::FileHandle, offset: u64, len: u64) -> Result<()>;
     /// Sync changes to disk.
     ///
     /// Blocks until all previous operations have been committed to disk.
@@ -464,6 +486,16 @@ pub trait SimpleFileSystem: Sized + Send + Sync {
     fn truncate(&mut self, file: INodeNum, size: u64) -> Result<()> {
         Err(Error::Unsupported)
     }
+    /// Allocate space for `len` bytes of `file` starting at `offset`, without shrinking it.
+    ///
+    /// By default, this grows the file with [`SimpleFileSystem::truncate`] if it's too small.
+    fn fallocate(&mut self, file: INodeNum, offset: u64, len: u64) -> Result<()> {
+        let end = offset.checked_add(len).ok_or(Error::NoSpace)?;
+        if end > self.stat(file)?.size {
+            self.truncate(file, end)?;
+        }
+        Ok(())
+    }
     /// Sync changes to disk.
     fn sync(&mut self) -> Result<()> {
         Ok(())
@@ -547,7 +579,94 @@ impl<F: SimpleFileSystem> FileSystem for F {
     fn truncate(&mut self, file: &mut Self::FileHandle, size: u64) -> Result<()> {
         SimpleFileSystem::truncate(self, file.0, size)
     }
+    fn fallocate(&mut self, file: &mut Self::FileHandle, offset: u64, len: u64) -> Result<()> {
+        SimpleFileSystem::fallocate(self, file.0, offset, len)
+    }
     fn sync(&mut self) -> Result<()> {
         SimpleFileSystem::sync(self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    /// Every variant of [`Error`], with the name of the errno it should be reported as.
+    fn all_errors() -> Vec<(Error, &'static str)> {
+        // This stops compiling when a variant is added, as a reminder to add it to the list too.
+        let _ = |error: Error| match error {
+            Error::NotFound
+            | Error::NotDirectory
+            | Error::IsDirectory
+            | Error::NoSpace
+            | Error::TooManyLinks
+            | Error::NotEmpty
+            | Error::Exists
+            | Error::Unsupported
+            | Error::ReadOnlyFS
+            | Error::TooManyOpenFiles
+            | Error::BadFd
+            | Error::FileSystemInUse
+            | Error::BadOffset
+            | Error::IllegalSeek
+            | Error::NotMounted
+            | Error::NotLink
+            | Error::TooManyLevelsOfLinks
+            | Error::HardLinkBetweenFileSystems
+            | Error::PipeClosed
+            | Error::Interrupted
+            | Error::WouldBlock
+            | Error::InvalidArgument
+            | Error::IO(_) => {}
+        };
+        vec![
+            (Error::NotFound, "ENOENT"),
+            (Error::NotDirectory, "ENOTDIR"),
+            (Error::IsDirectory, "EISDIR"),
+            (Error::NoSpace, "ENOSPC"),
+            (Error::TooManyLinks, "EMLINK"),
+            (Error::NotEmpty, "ENOTEMPTY"),
+            (Error::Exists, "EEXIST"),
+            (Error::Unsupported, "EOPNOTSUPP"),
+            (Error::ReadOnlyFS, "EROFS"),
+            (Error::TooManyOpenFiles, "EMFILE"),
+            (Error::BadFd, "EBADF"),
+            (Error::FileSystemInUse, "EBUSY"),
+            (Error::BadOffset, "EINVAL"),
+            (Error::IllegalSeek, "ESPIPE"),
+            (Error::NotMounted, "EINVAL"),
+            (Error::NotLink, "EINVAL"),
+            (Error::TooManyLevelsOfLinks, "ELOOP"),
+            (Error::HardLinkBetweenFileSystems, "EXDEV"),
+            (Error::PipeClosed, "EPIPE"),
+            (Error::Interrupted, "EINTR"),
+            (Error::WouldBlock, "EAGAIN"),
+            (Error::InvalidArgument, "EINVAL"),
+            (Error::IO("disk on fire".into()), "EIO"),
+        ]
+    }
+
+    #[test]
+    fn errno_mapping() {
+        // reported as EINVAL, like Linux does, rather than having errnos of their own
+        let einval = [Error::BadOffset, Error::NotMounted, Error::NotLink];
+
+        let mut errnos = Vec::new();
+        for (error, name) in all_errors() {
+            let errno = error.to_isize();
+            assert_eq!(
+                syscall::errno_name(errno),
+                Some(name),
+                "wrong errno for {error:?}"
+            );
+            if !einval.contains(&error) {
+                assert!(!errnos.contains(&errno), "{error:?} shares errno {errno}");
+                errnos.push(errno);
+            }
+        }
+
+        assert_eq!(syscall::errno_name(syscall::EWOULDBLOCK), Some("EAGAIN"));
+        assert_eq!(syscall::errno_name(0), None);
+    }
+}
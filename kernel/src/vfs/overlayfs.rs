@@ -0,0 +1,427 @@
+use crate::vfs::{
+    DirEntries, Error, FileInfo, INodeNum, INodeType, OwnedPath, Path, Result, SimpleFileSystem,
+};
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    string::String,
+    vec,
+    vec::Vec,
+};
+
+const ROOT_INO: INodeNum = 1;
+
+/// Number of bytes copied at a time when a file is copied up to the upper layer.
+const COPY_UP_CHUNK: usize = 4096;
+
+const NO_INODE: &str = "Couldn't find inode — either kernel is using filesystem incorrectly or we forgot about an inode when we shouldn't have.";
+
+/// A file/directory/symlink in an [`OverlayFS`], and where it is in each layer.
+struct OverlayINode {
+    r#type: INodeType,
+    /// The directory containing this inode and its name in it, so it can be copied up.
+    /// Only the root doesn't have one.
+    parent: Option<(INodeNum, OwnedPath)>,
+    /// inode number in the lower file system
+    lower: Option<INodeNum>,
+    /// inode number in the upper file system. A directory which has both this and `lower`
+    /// shows the entries of both.
+    upper: Option<INodeNum>,
+    /// number of directory entries pointing to this inode
+    links: usize,
+}
+
+/// File system showing the contents of a read-only `lower` file system, with changes going to a
+/// writable `upper` one.
+///
+/// The first time a file from the lower file system is written to, it's copied up to the upper
+/// one, along with the directories containing it, and the upper copy is used from then on.
+/// Deleting something from the lower file system leaves a whiteout, which hides it. Whiteouts
+/// are only kept in memory, so they're lost when the overlay is unmounted.
+pub struct OverlayFS<L, U> {
+    lower: L,
+    upper: U,
+    inodes: BTreeMap<INodeNum, OverlayINode>,
+    inode_counter: INodeNum,
+    /// inode number of each (directory, name) entry which has been seen
+    names: BTreeMap<(INodeNum, OwnedPath), INodeNum>,
+    /// (directory, name) of entries in the lower file system which have been deleted
+    whiteouts: BTreeSet<(INodeNum, OwnedPath)>,
+}
+
+impl<L: SimpleFileSystem, U: SimpleFileSystem> OverlayFS<L, U> {
+    pub fn new(lower: L, upper: U) -> Self {
+        let root = OverlayINode {
+            r#type: INodeType::Directory,
+            parent: None,
+            lower: Some(lower.root()),
+            upper: Some(upper.root()),
+            links: 1,
+        };
+        OverlayFS {
+            lower,
+            upper,
+            inodes: BTreeMap::from([(ROOT_INO, root)]),
+            inode_counter: ROOT_INO,
+            names: BTreeMap::new(),
+            whiteouts: BTreeSet::new(),
+        }
+    }
+    fn get_inode(&self, inode: INodeNum) -> Result<&OverlayINode> {
+        self.inodes.get(&inode).ok_or(Error::NotFound)
+    }
+    fn add_inode(&mut self, inode: OverlayINode) -> INodeNum {
+        loop {
+            self.inode_counter = self.inode_counter.wrapping_add(1);
+            if !self.inodes.contains_key(&self.inode_counter) {
+                break;
+            }
+        }
+        self.inodes.insert(self.inode_counter, inode);
+        self.inode_counter
+    }
+    /// Read the entries of the directory `dir` from both layers, giving an inode number to any we
+    /// haven't seen before.
+    fn entries(&mut self, dir: INodeNum) -> Result<Vec<(OwnedPath, INodeNum)>> {
+        let inode = self.get_inode(dir)?;
+        let (lower_dir, upper_dir) = (inode.lower, inode.upper);
+        // name => (type, lower inode, upper inode)
+        let mut found: BTreeMap<OwnedPath, (INodeType, Option<INodeNum>, Option<INodeNum>)> =
+            BTreeMap::new();
+        if let Some(upper_dir) = upper_dir {
+            for entry in &self.upper.readdir(upper_dir)? {
+                found.insert(
+                    entry.name.into_owned(),
+                    (entry.r#type, None, Some(entry.inode)),
+                );
+            }
+        }
+        if let Some(lower_dir) = lower_dir {
+            for entry in &self.lower.readdir(lower_dir)? {
+                let name = entry.name.into_owned();
+                if self.whiteouts.contains(&(dir, name.clone())) {
+                    continue;
+                }
+                match found.get_mut(&name) {
+                    // directories in both layers are merged
+                    Some((INodeType::Directory, lower, _))
+                        if entry.r#type == INodeType::Directory =>
+                    {
+                        *lower = Some(entry.inode);
+                    }
+                    // anything else in the upper layer hides the lower one
+                    Some(_) => {}
+                    None => {
+                        found.insert(name, (entry.r#type, Some(entry.inode), None));
+                    }
+                }
+            }
+        }
+        let mut entries = Vec::with_capacity(found.len());
+        for (name, (r#type, lower, upper)) in found {
+            let key = (dir, name);
+            let inode = match self.names.get(&key) {
+                Some(&inode) => inode,
+                None => {
+                    let inode = self.add_inode(OverlayINode {
+                        r#type,
+                        parent: Some(key.clone()),
+                        lower,
+                        upper,
+                        links: 1,
+                    });
+                    self.names.insert(key.clone(), inode);
+                    inode
+                }
+            };
+            entries.push((key.1, inode));
+        }
+        Ok(entries)
+    }
+    fn lookup(&mut self, dir: INodeNum, name: &Path) -> Result<INodeNum> {
+        self.entries(dir)?
+            .into_iter()
+            .find_map(|(entry_name, inode)| (entry_name == name).then_some(inode))
+            .ok_or(Error::NotFound)
+    }
+    /// Return [`Error::Exists`] if there's already something called `name` in `dir`.
+    fn check_not_exists(&mut self, dir: INodeNum, name: &Path) -> Result<()> {
+        match self.lookup(dir, name) {
+            Ok(_) => Err(Error::Exists),
+            Err(Error::NotFound) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+    /// Make sure `inode` is in the upper file system, copying it (and its parent directories) up
+    /// if it isn't yet, and return its inode number there.
+    fn copy_up(&mut self, inode: INodeNum) -> Result<INodeNum> {
+        let overlay_inode = self.get_inode(inode)?;
+        if let Some(upper) = overlay_inode.upper {
+            return Ok(upper);
+        }
+        let lower = overlay_inode
+            .lower
+            .expect("inode should be in at least one layer");
+        let r#type = overlay_inode.r#type;
+        let (parent, name) = overlay_inode
+            .parent
+            .clone()
+            .expect("root should be in both layers");
+        let upper_parent = self.copy_up(parent)?;
+        let upper = match r#type {
+            INodeType::Directory => self.upper.mkdir(upper_parent, &name)?,
+            INodeType::Link => {
+                let link = self.lower.readlink(lower)?;
+                self.upper.symlink(&link, upper_parent, &name)?
+            }
+            INodeType::File => {
+                let upper = self.upper.create(upper_parent, &name)?;
+                let mut buf = vec![0; COPY_UP_CHUNK];
+                let mut offset = 0;
+                loop {
+                    let n = self.lower.read(lower, offset, &mut buf)?;
+                    if n == 0 {
+                        break;
+                    }
+                    let mut written = 0;
+                    while written < n {
+                        written +=
+                            self.upper
+                                .write(upper, offset + written as u64, &buf[written..n])?;
+                    }
+                    offset += n as u64;
+                }
+                upper
+            }
+        };
+        self.inodes.get_mut(&inode).expect(NO_INODE).upper = Some(upper);
+        Ok(upper)
+    }
+    /// Add an entry for something which was just made in the upper file system.
+    fn add_upper_entry(
+        &mut self,
+        parent: INodeNum,
+        name: &Path,
+        r#type: INodeType,
+        upper: INodeNum,
+    ) -> INodeNum {
+        let key = (parent, String::from(name));
+        // a new directory doesn't show anything from a lower one which was deleted
+        self.whiteouts.remove(&key);
+        let inode = self.add_inode(OverlayINode {
+            r#type,
+            parent: Some(key.clone()),
+            lower: None,
+            upper: Some(upper),
+            links: 1,
+        });
+        self.names.insert(key, inode);
+        inode
+    }
+    // performs either unlink or rmdir.
+    fn unlink_or_rmdir(&mut self, parent: INodeNum, name: &Path, is_rmdir: bool) -> Result<()> {
+        let inode = self.lookup(parent, name)?;
+        let overlay_inode = self.get_inode(inode)?;
+        match (overlay_inode.r#type, is_rmdir) {
+            (INodeType::Directory, false) => return Err(Error::IsDirectory),
+            (INodeType::File | INodeType::Link, true) => return Err(Error::NotDirectory),
+            _ => {}
+        }
+        let (lower, upper) = (overlay_inode.lower, overlay_inode.upper);
+        if is_rmdir && !self.entries(inode)?.is_empty() {
+            return Err(Error::NotEmpty);
+        }
+        if upper.is_some() {
+            // the parent of anything in the upper file system is too
+            let upper_parent = self.copy_up(parent)?;
+            if is_rmdir {
+                self.upper.rmdir(upper_parent, name)?;
+            } else {
+                self.upper.unlink(upper_parent, name)?;
+            }
+        }
+        let key = (parent, String::from(name));
+        if lower.is_some() {
+            self.whiteouts.insert(key.clone());
+        }
+        if is_rmdir {
+            self.whiteouts.retain(|(dir, _)| *dir != inode);
+        }
+        self.names.remove(&key);
+        self.inodes.get_mut(&inode).expect(NO_INODE).links -= 1;
+        // Note that we don't forget about the inode here; we do that in `release`, so that
+        // existing file handles can still access the file until then.
+        Ok(())
+    }
+}
+
+impl<L: SimpleFileSystem, U: SimpleFileSystem> SimpleFileSystem for OverlayFS<L, U> {
+    fn root(&self) -> INodeNum {
+        ROOT_INO
+    }
+    fn open(&mut self, inode: INodeNum) -> Result<()> {
+        let overlay_inode = self.get_inode(inode)?;
+        match (overlay_inode.upper, overlay_inode.lower) {
+            (Some(upper), _) => self.upper.open(upper),
+            (None, Some(lower)) => self.lower.open(lower),
+            (None, None) => panic!("inode should be in at least one layer"),
+        }
+    }
+    fn create(&mut self, parent: INodeNum, name: &Path) -> Result<INodeNum> {
+        match self.lookup(parent, name) {
+            Ok(inode) => return Ok(inode),
+            Err(Error::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+        let upper_parent = self.copy_up(parent)?;
+        let upper = self.upper.create(upper_parent, name)?;
+        Ok(self.add_upper_entry(parent, name, INodeType::File, upper))
+    }
+    fn mkdir(&mut self, parent: INodeNum, name: &Path) -> Result<INodeNum> {
+        self.check_not_exists(parent, name)?;
+        let upper_parent = self.copy_up(parent)?;
+        let upper = self.upper.mkdir(upper_parent, name)?;
+        Ok(self.add_upper_entry(parent, name, INodeType::Directory, upper))
+    }
+    fn unlink(&mut self, parent: INodeNum, name: &Path) -> Result<()> {
+        self.unlink_or_rmdir(parent, name, false)
+    }
+    fn rmdir(&mut self, parent: INodeNum, name: &Path) -> Result<()> {
+        self.unlink_or_rmdir(parent, name, true)
+    }
+    fn readdir(&mut self, dir: INodeNum) -> Result<DirEntries> {
+        let mut entries = DirEntries::new();
+        for (name, inode) in self.entries(dir)? {
+            entries.add(inode, self.get_inode(inode)?.r#type, &name);
+        }
+        Ok(entries)
+    }
+    fn release(&mut self, inode: INodeNum) {
+        let Some(overlay_inode) = self.inodes.get(&inode) else {
+            return;
+        };
+        match (overlay_inode.upper, overlay_inode.lower) {
+            (Some(upper), _) => self.upper.release(upper),
+            (None, Some(lower)) => self.lower.release(lower),
+            (None, None) => {}
+        }
+        if overlay_inode.links == 0 {
+            self.inodes.remove(&inode);
+        }
+    }
+    fn read(&mut self, file: INodeNum, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        let overlay_inode = self.get_inode(file)?;
+        match (overlay_inode.upper, overlay_inode.lower) {
+            (Some(upper), _) => self.upper.read(upper, offset, buf),
+            (None, Some(lower)) => self.lower.read(lower, offset, buf),
+            (None, None) => panic!("inode should be in at least one layer"),
+        }
+    }
+    fn write(&mut self, file: INodeNum, offset: u64, buf: &[u8]) -> Result<usize> {
+        let upper = self.copy_up(file)?;
+        self.upper.write(upper, offset, buf)
+    }
+    fn stat(&mut self, file: INodeNum) -> Result<FileInfo> {
+        let overlay_inode = self.get_inode(file)?;
+        let mut info = match (overlay_inode.upper, overlay_inode.lower) {
+            (Some(upper), _) => self.upper.stat(upper)?,
+            (None, Some(lower)) => self.lower.stat(lower)?,
+            (None, None) => panic!("inode should be in at least one layer"),
+        };
+        info.inode = file;
+        Ok(info)
+    }
+    fn link(&mut self, source: INodeNum, parent: INodeNum, name: &Path) -> Result<()> {
+        self.check_not_exists(parent, name)?;
+        let upper_source = self.copy_up(source)?;
+        let upper_parent = self.copy_up(parent)?;
+        self.upper.link(upper_source, upper_parent, name)?;
+        let key = (parent, String::from(name));
+        self.whiteouts.remove(&key);
+        self.names.insert(key, source);
+        self.inodes.get_mut(&source).expect(NO_INODE).links += 1;
+        Ok(())
+    }
+    fn symlink(&mut self, link: &Path, parent: INodeNum, name: &Path) -> Result<INodeNum> {
+        self.check_not_exists(parent, name)?;
+        let upper_parent = self.copy_up(parent)?;
+        let upper = self.upper.symlink(link, upper_parent, name)?;
+        Ok(self.add_upper_entry(parent, name, INodeType::Link, upper))
+    }
+    fn readlink(&mut self, link: INodeNum) -> Result<String> {
+        let overlay_inode = self.get_inode(link)?;
+        match (overlay_inode.upper, overlay_inode.lower) {
+            (Some(upper), _) => self.upper.readlink(upper),
+            (None, Some(lower)) => self.lower.readlink(lower),
+            (None, None) => panic!("inode should be in at least one layer"),
+        }
+    }
+    fn truncate(&mut self, file: INodeNum, size: u64) -> Result<()> {
+        let upper = self.copy_up(file)?;
+        self.upper.truncate(upper, size)
+    }
+    fn sync(&mut self) -> Result<()> {
+        self.upper.sync()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::OverlayFS;
+    use crate::block::block_core::test::GzBlockDevice;
+    use crate::fs::fat::FatFS;
+    use crate::vfs::{tempfs::TempFS, Error, INodeNum, SimpleFileSystem};
+    use alloc::sync::Arc;
+
+    fn read_all<F: SimpleFileSystem>(fs: &mut F, file: INodeNum) -> Vec<u8> {
+        let mut buf = [0; 64];
+        let n = fs.read(file, 0, &mut buf).unwrap();
+        buf[..n].to_vec()
+    }
+
+    fn names<F: SimpleFileSystem>(fs: &mut F, dir: INodeNum) -> Vec<String> {
+        let entries = fs.readdir(dir).unwrap().to_sorted_vec();
+        entries.into_iter().map(|e| e.name.into_owned()).collect()
+    }
+
+    #[test]
+    fn copy_up_and_whiteout() {
+        let device = Arc::new(
+            GzBlockDevice::open("tests/fat/simple_fat16.img.gz")
+                .unwrap()
+                .into_block(),
+        );
+        let mut fs = OverlayFS::new(FatFS::new(device.shared()).unwrap(), TempFS::new());
+        let root = fs.root();
+
+        let a = fs.lookup(root, "a").unwrap();
+        fs.open(a).unwrap();
+        assert_eq!(read_all(&mut fs, a), b"file a\n");
+        // first write copies the file up
+        assert_eq!(fs.write(a, 5, b"A").unwrap(), 1);
+        assert_eq!(read_all(&mut fs, a), b"file A\n");
+        fs.release(a);
+
+        // deleting a lower file leaves a whiteout
+        fs.unlink(root, "b").unwrap();
+        assert_eq!(names(&mut fs, root), ["a", "c", "d"]);
+        assert!(matches!(fs.lookup(root, "b"), Err(Error::NotFound)));
+        let b = fs.create(root, "b").unwrap();
+        assert_eq!(read_all(&mut fs, b), b"");
+
+        // a directory made where a lower one was deleted starts out empty
+        assert!(matches!(fs.rmdir(root, "d"), Err(Error::NotEmpty)));
+        let d = fs.lookup(root, "d").unwrap();
+        fs.unlink(d, "f").unwrap();
+        fs.rmdir(root, "d").unwrap();
+        let d = fs.mkdir(root, "d").unwrap();
+        assert!(names(&mut fs, d).is_empty());
+
+        // none of that made it to the lower file system
+        let mut lower = FatFS::new(device.shared()).unwrap();
+        let lower_root = lower.root();
+        assert_eq!(names(&mut lower, lower_root), ["a", "b", "c", "d"]);
+        let entries = lower.readdir(lower_root).unwrap().to_sorted_vec();
+        assert_eq!(read_all(&mut lower, entries[0].inode), b"file a\n");
+        assert_eq!(names(&mut lower, entries[3].inode), ["f"]);
+    }
+}
@@ -1,86 +1,177 @@
-use crate::vfs::{FileSystem, INodeNum, INodeType, OwnedDirEntry};
+use crate::vfs::{FileSystem, INodeNum, INodeType};
+use std::collections::BTreeMap;
 use std::io::prelude::*;
 
 type StdPath = std::path::Path;
-type StdPathBuf = std::path::PathBuf;
 
-fn read_only_test_on<F: FileSystem>(fs: &mut F, fs_dir_inode: INodeNum, host_directory: &StdPath) {
-    // compare directory entries
-    let mut dir_handle = fs.open(fs_dir_inode).unwrap();
-    let fs_dir_ents = fs.readdir(&mut dir_handle).unwrap().to_sorted_vec();
-    let mut host_dir_ents: Vec<OwnedDirEntry> = std::fs::read_dir(host_directory)
-        .unwrap()
-        .filter_map(|std_dirent| {
-            let std_dirent = std_dirent.unwrap();
-            let name = std_dirent
-                .file_name()
-                .to_str()
-                .expect("bad UTF-8 in host filename")
-                .to_owned();
-            if name == "." || name == ".." {
-                return None;
-            }
-            let file_type = std_dirent.file_type().unwrap();
-            let r#type = if file_type.is_symlink() {
-                INodeType::Link
-            } else if file_type.is_dir() {
-                INodeType::Directory
-            } else if file_type.is_file() {
-                INodeType::File
-            } else {
-                panic!("Weird file type in host directory: {file_type:?}");
-            };
-            Some(OwnedDirEntry {
-                inode: 0,
-                name: name.into(),
-                r#type,
-            })
-        })
-        .collect();
-    host_dir_ents.sort_by(|a, b| a.name.cmp(&b.name));
-    assert_eq!(host_dir_ents.len(), fs_dir_ents.len());
-    for (host_ent, fs_ent) in host_dir_ents.iter().zip(fs_dir_ents.iter()) {
-        let mut host_subpath = StdPathBuf::from(host_directory);
-        host_subpath.push(host_ent.name.as_ref());
-        assert_eq!(host_ent.name, fs_ent.name);
-        assert_eq!(host_ent.r#type, fs_ent.r#type);
-        match fs_ent.r#type {
-            INodeType::Directory => {
-                read_only_test_on(fs, fs_ent.inode, &host_subpath);
+/// What a file system is expected to contain, e.g. the contents of a directory on the host which
+/// a disk image was built from.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExpectedTree {
+    /// regular file with these contents
+    File(Vec<u8>),
+    /// symbolic link to this path
+    Link(String),
+    /// directory with these entries, by name
+    Directory(BTreeMap<String, ExpectedTree>),
+}
+
+impl ExpectedTree {
+    /// Read the expected tree from `path` on the host. Symbolic links aren't followed.
+    pub fn from_host(path: impl AsRef<StdPath>) -> Self {
+        let path = path.as_ref();
+        let file_type = std::fs::symlink_metadata(path).unwrap().file_type();
+        if file_type.is_symlink() {
+            let link = std::fs::read_link(path).unwrap();
+            Self::Link(
+                link.to_str()
+                    .expect("bad UTF-8 in host symlink target")
+                    .to_owned(),
+            )
+        } else if file_type.is_dir() {
+            let entries = std::fs::read_dir(path)
+                .unwrap()
+                .map(|std_dirent| {
+                    let std_dirent = std_dirent.unwrap();
+                    let name = std_dirent
+                        .file_name()
+                        .to_str()
+                        .expect("bad UTF-8 in host filename")
+                        .to_owned();
+                    (name, Self::from_host(std_dirent.path()))
+                })
+                .collect();
+            Self::Directory(entries)
+        } else if file_type.is_file() {
+            let mut contents = vec![];
+            std::fs::File::open(path)
+                .unwrap()
+                .read_to_end(&mut contents)
+                .unwrap();
+            Self::File(contents)
+        } else {
+            panic!("Weird file type in host directory: {file_type:?}");
+        }
+    }
+    fn r#type(&self) -> INodeType {
+        match self {
+            Self::File(_) => INodeType::File,
+            Self::Link(_) => INodeType::Link,
+            Self::Directory(_) => INodeType::Directory,
+        }
+    }
+    /// Check that `inode` in `fs` matches this tree. `path` is only used in assertion messages.
+    fn check<F: FileSystem>(&self, fs: &mut F, inode: INodeNum, path: &str) {
+        let mut handle = fs.open(inode).unwrap();
+        let info = fs.stat(&handle).unwrap();
+        assert_eq!(info.r#type, self.r#type(), "wrong type at {path}");
+        match self {
+            Self::Directory(expected_entries) => {
+                let fs_dir_ents = fs.readdir(&mut handle).unwrap().to_sorted_vec();
+                let fs_names: Vec<&str> = fs_dir_ents.iter().map(|e| e.name.as_ref()).collect();
+                let expected_names: Vec<&str> =
+                    expected_entries.keys().map(String::as_str).collect();
+                assert_eq!(fs_names, expected_names, "mismatch in directory {path}");
+                for (fs_ent, expected) in fs_dir_ents.iter().zip(expected_entries.values()) {
+                    expected.check(fs, fs_ent.inode, &format!("{path}/{}", fs_ent.name));
+                }
             }
-            INodeType::File => {
-                let mut file = fs.open(fs_ent.inode).unwrap();
+            Self::File(expected_contents) => {
                 // weird buffer size to try to catch edge cases (e.g. read crossing sector)
                 let mut buffer = [0u8; 37];
                 let mut fs_contents = vec![];
                 loop {
                     let n = fs
-                        .read(&mut file, fs_contents.len() as u64, &mut buffer)
+                        .read(&mut handle, fs_contents.len() as u64, &mut buffer)
                         .unwrap();
                     if n == 0 {
                         break;
                     }
                     fs_contents.extend_from_slice(&buffer[..n]);
                 }
-                let mut host_contents = vec![];
-                std::fs::File::open(&host_subpath)
-                    .unwrap()
-                    .read_to_end(&mut host_contents)
-                    .unwrap();
+                assert_eq!(expected_contents, &fs_contents, "mismatch at file {path}");
+            }
+            Self::Link(expected_link) => {
+                let mut buffer = vec![0u8; info.size as usize];
+                let link = fs.readlink(&mut handle, &mut buffer).unwrap();
                 assert_eq!(
-                    host_contents,
-                    fs_contents,
-                    "mismatch at file {}",
-                    host_subpath.to_string_lossy()
+                    Some(expected_link.as_str()),
+                    link,
+                    "mismatch at link {path}"
                 );
             }
-            INodeType::Link => todo!(),
         }
+        fs.release(inode);
     }
 }
 
+/// ensure the filesystem matches `expected`
+pub fn expect_tree<F: FileSystem>(fs: &mut F, expected: &ExpectedTree) {
+    let root = fs.root();
+    expected.check(fs, root, "");
+}
+
 /// ensure the filesystem matches the given directory on disk
 pub fn read_only_test<F: FileSystem>(fs: &mut F, host_directory: impl AsRef<StdPath>) {
-    let root = fs.root();
-    read_only_test_on(fs, root, host_directory.as_ref());
+    expect_tree(fs, &ExpectedTree::from_host(host_directory));
+}
+
+#[cfg(test)]
+mod test {
+    use super::{read_only_test, ExpectedTree};
+    use crate::vfs::{tempfs::TempFS, INodeNum, SimpleFileSystem};
+    use std::collections::BTreeMap;
+
+    /// Make a fresh directory on the host for a test called `name`.
+    fn host_temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("kidneyos-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir(&dir).unwrap();
+        dir
+    }
+
+    /// Fill in the directory `dir` in `fs` to match `entries`.
+    fn populate(fs: &mut TempFS, dir: INodeNum, entries: &BTreeMap<String, ExpectedTree>) {
+        for (name, entry) in entries {
+            match entry {
+                ExpectedTree::File(contents) => {
+                    let file = fs.create(dir, name).unwrap();
+                    assert_eq!(fs.write(file, 0, contents).unwrap(), contents.len());
+                }
+                ExpectedTree::Link(link) => {
+                    fs.symlink(link, dir, name).unwrap();
+                }
+                ExpectedTree::Directory(entries) => {
+                    let subdir = fs.mkdir(dir, name).unwrap();
+                    populate(fs, subdir, entries);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tempfs_vs_host() {
+        let host = host_temp_dir("tempfs_vs_host");
+        std::fs::write(host.join("a"), b"file a\n").unwrap();
+        std::fs::write(host.join("empty"), b"").unwrap();
+        std::fs::create_dir(host.join("d")).unwrap();
+        std::fs::write(host.join("d").join("big"), vec![b'x'; 1000]).unwrap();
+        std::os::unix::fs::symlink("d/big", host.join("link")).unwrap();
+
+        let expected = ExpectedTree::from_host(&host);
+        let ExpectedTree::Directory(entries) = &expected else {
+            panic!("host directory should be a directory");
+        };
+        assert_eq!(
+            entries.get("link"),
+            Some(&ExpectedTree::Link("d/big".into()))
+        );
+
+        let mut fs = TempFS::new();
+        let root = fs.root();
+        populate(&mut fs, root, entries);
+        read_only_test(&mut fs, &host);
+
+        std::fs::remove_dir_all(&host).unwrap();
+    }
 }
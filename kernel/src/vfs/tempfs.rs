@@ -83,6 +83,10 @@ impl TempINode {
 pub struct TempFS {
     inodes: BTreeMap<INodeNum, TempINode>,
     inode_counter: INodeNum,
+    /// maximum number of bytes of file data, or `None` if unlimited
+    capacity: Option<usize>,
+    /// number of bytes of file data currently stored
+    bytes_used: usize,
 }
 
 const ROOT_INO: INodeNum = 1;
@@ -102,8 +106,35 @@ impl TempFS {
         TempFS {
             inodes,
             inode_counter: 1,
+            capacity: None,
+            bytes_used: 0,
         }
     }
+    /// Create a TempFS that can hold at most `capacity` bytes of file data.
+    ///
+    /// Once that is used up, `create`, `write` and `truncate` fail with `Error::NoSpace`.
+    pub fn with_capacity(capacity: usize) -> TempFS {
+        TempFS {
+            capacity: Some(capacity),
+            ..TempFS::new()
+        }
+    }
+    fn is_full(&self) -> bool {
+        self.capacity
+            .is_some_and(|capacity| self.bytes_used >= capacity)
+    }
+    /// Account for `bytes` more bytes of file data, or return `Error::NoSpace` if that would exceed the capacity.
+    fn reserve_bytes(&mut self, bytes: usize) -> Result<()> {
+        let new_used = self.bytes_used.checked_add(bytes).ok_or(Error::NoSpace)?;
+        if self.capacity.is_some_and(|capacity| new_used > capacity) {
+            return Err(Error::NoSpace);
+        }
+        self.bytes_used = new_used;
+        Ok(())
+    }
+    fn free_bytes(&mut self, bytes: usize) {
+        self.bytes_used -= bytes;
+    }
     fn get_inode(&self, inode: INodeNum) -> &TempINode {
         self.inodes.get(&inode).expect(NO_INODE)
     }
@@ -143,7 +174,7 @@ impl TempFS {
         match &inode.data {
             TempINodeData::Directory(d) => {
                 if !is_rmdir {
-                    return Err(Error::NotDirectory);
+                    return Err(Error::IsDirectory);
                 }
                 if !d.is_empty() {
                     return Err(Error::NotEmpty);
@@ -212,16 +243,19 @@ impl SimpleFileSystem for TempFS {
         let TempINodeData::Directory(parent_dir) = &mut parent_inode.data else {
             panic!("Kernel should call stat to make sure this is a directory before creating a file in it.");
         };
-        let inode_num = parent_dir.inode_by_name(name).unwrap_or_else(|| {
-            // create new file
-            let inode_num = self.add_inode(TempINode::empty_file());
-            let parent_inode = self.get_inode_mut(parent);
-            let TempINodeData::Directory(parent_dir) = &mut parent_inode.data else {
-                panic!("should never happen due to check above");
-            };
-            parent_dir.add_entry(name.into(), inode_num);
-            inode_num
-        });
+        if let Some(inode_num) = parent_dir.inode_by_name(name) {
+            return Ok(inode_num);
+        }
+        if self.is_full() {
+            return Err(Error::NoSpace);
+        }
+        // create new file
+        let inode_num = self.add_inode(TempINode::empty_file());
+        let parent_inode = self.get_inode_mut(parent);
+        let TempINodeData::Directory(parent_dir) = &mut parent_inode.data else {
+            panic!("should never happen due to check above");
+        };
+        parent_dir.add_entry(name.into(), inode_num);
         Ok(inode_num)
     }
     fn unlink(&mut self, parent: INodeNum, name: &Path) -> Result<()> {
@@ -264,7 +298,13 @@ impl SimpleFileSystem for TempFS {
             .expect("kernel should only call release on inodes it knows to exist");
         if inode.nlink == 0 {
             // we can safely remove the inode.
-            self.inodes.remove(&inode_num);
+            if let Some(TempINode {
+                data: TempINodeData::File(f),
+                ..
+            }) = self.inodes.remove(&inode_num)
+            {
+                self.free_bytes(f.data.len());
+            }
         }
     }
     fn read(&mut self, file: INodeNum, offset: u64, buf: &mut [u8]) -> Result<usize> {
@@ -294,8 +334,8 @@ impl SimpleFileSystem for TempFS {
                 buf.len()
             );
         }
-        let inode = self.get_inode_mut(file);
-        let TempINodeData::File(f) = &mut inode.data else {
+        let inode = self.get_inode(file);
+        let TempINodeData::File(f) = &inode.data else {
             panic!("Kernel should make sure this is a regular file before writing to it.");
         };
         if offset > (isize::MAX as u64).saturating_sub(buf.len() as u64) {
@@ -305,14 +345,17 @@ impl SimpleFileSystem for TempFS {
         let offset = offset as usize;
         // amount we need to grow the file by
         let grow_amount = (offset + buf.len()).saturating_sub(f.data.len());
+        self.reserve_bytes(grow_amount)?;
+        let TempINodeData::File(f) = &mut self.get_inode_mut(file).data else {
+            panic!("checked above");
+        };
         // return no space error if allocation failed
-        f.data
-            .try_reserve(grow_amount)
-            .map_err(|_| Error::NoSpace)?;
-        for _ in 0..grow_amount {
-            // NOTE: files with holes will not perform well.
-            f.data.push(0);
+        if f.data.try_reserve(grow_amount).is_err() {
+            self.free_bytes(grow_amount);
+            return Err(Error::NoSpace);
         }
+        // fill in any hole between the old end of the file and `offset` with zeroes
+        f.data.resize(f.data.len() + grow_amount, 0);
         f.data[offset..offset + buf.len()].copy_from_slice(buf);
         Ok(buf.len())
     }
@@ -431,22 +474,29 @@ impl SimpleFileSystem for TempFS {
             println!("tempfs: truncate {file:?} to {size} bytes");
         }
         let inode = self.get_inode_mut(file);
-        let TempINodeData::File(file) = &mut inode.data else {
+        let TempINodeData::File(f) = &mut inode.data else {
             panic!(
                 "Kernel should use stat to make sure this is a file before calling truncate on it."
             );
         };
-        if size <= file.data.len() as u64 {
+        let old_len = f.data.len();
+        if size <= old_len as u64 {
             // shrink file
-            file.data.truncate(size as usize);
+            f.data.truncate(size as usize);
+            self.free_bytes(old_len - size as usize);
         } else {
             // grow file
             let size: usize = size.try_into().map_err(|_| Error::NoSpace)?;
-            let grow_by = size - file.data.len();
-            file.data.try_reserve(grow_by).map_err(|_| Error::NoSpace)?;
-            for _ in 0..grow_by {
-                file.data.push(0);
+            let grow_by = size - old_len;
+            self.reserve_bytes(grow_by)?;
+            let TempINodeData::File(f) = &mut self.get_inode_mut(file).data else {
+                panic!("checked above");
+            };
+            if f.data.try_reserve(grow_by).is_err() {
+                self.free_bytes(grow_by);
+                return Err(Error::NoSpace);
             }
+            f.data.resize(size, 0);
         }
         Ok(())
     }
@@ -827,4 +877,47 @@ mod tests {
             b"hello\0\0\0\0\0"
         );
     }
+
+    #[test]
+    fn hole() {
+        const OFFSET: u64 = 10 << 20;
+        let mut fs = TempFS::new();
+        let mut test_file = create_path(&mut fs, "/test").unwrap();
+        assert_eq!(fs.write(&mut test_file, OFFSET, b"x").unwrap(), 1);
+        assert_eq!(fs.stat(&test_file).unwrap().size, OFFSET + 1);
+        let mut buf = [0xFF; 4096];
+        assert_eq!(fs.read(&mut test_file, OFFSET / 2, &mut buf).unwrap(), 4096);
+        assert!(buf.iter().all(|&b| b == 0));
+        let mut buf = [0; 2];
+        assert_eq!(fs.read(&mut test_file, OFFSET - 1, &mut buf).unwrap(), 2);
+        assert_eq!(&buf, b"\0x");
+    }
+
+    #[test]
+    fn capacity() {
+        let mut fs = TempFS::with_capacity(16);
+        let mut a = create_path(&mut fs, "/a").unwrap();
+        assert_eq!(fs.write(&mut a, 0, b"0123456789").unwrap(), 10);
+        // overwriting existing data doesn't use any more space
+        assert_eq!(fs.write(&mut a, 0, b"abcde").unwrap(), 5);
+        let mut b = create_path(&mut fs, "/b").unwrap();
+        assert_eq!(fs.write(&mut b, 0, b"01234").unwrap(), 5);
+        // 10 + 5 + 2 > 16
+        assert_matches!(fs.write(&mut b, 5, b"56"), Err(Error::NoSpace));
+        assert_matches!(fs.truncate(&mut b, 7), Err(Error::NoSpace));
+        // the failed write shouldn't have changed anything
+        assert_eq!(read_file(&mut fs, &mut b).unwrap(), b"01234");
+        // exactly filling up the file system is fine
+        assert_eq!(fs.write(&mut b, 5, b"5").unwrap(), 1);
+        assert_matches!(create_path(&mut fs, "/c"), Err(Error::NoSpace));
+        // shrinking a file gives back space
+        fs.truncate(&mut b, 3).unwrap();
+        assert_eq!(fs.write(&mut a, 10, b"xyz").unwrap(), 3);
+        // removing a file gives back its space once it's released
+        unlink_path(&mut fs, "/a").unwrap();
+        assert_matches!(fs.write(&mut b, 3, b"3"), Err(Error::NoSpace));
+        fs.release(a.inode());
+        let mut c = create_path(&mut fs, "/c").unwrap();
+        assert_eq!(fs.write(&mut c, 0, b"0123456789abc").unwrap(), 13);
+    }
 }
@@ -1,4 +1,4 @@
-PROGRAMS := exit example_c example_rust fs execve pipes
+PROGRAMS := exit example_c example_rust fs execve pipes brk epoll shm_reader shm sched_stats sched_param interrupt_counts segfault prctl_exec prctl msync mmap_private dmesg rusage cpu_limit as_limit mem_usage waitid signalfd sigaction sigsuspend exit_code_child exit_code vfork chroot_exec chroot
 
 .PHONY: programs
 programs: $(PROGRAMS)
@@ -26,6 +26,110 @@ pipes:
 	# We don't want to export CARGO_TARGET_DIR to our destination make.
 	unset CARGO_TARGET_DIR && cd programs/pipes && make
 
+brk:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/brk && make
+
+epoll:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/epoll && make
+
+shm_reader:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/shm_reader && make
+
+shm: shm_reader
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/shm && make
+
+sched_stats:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/sched_stats && make
+
+sched_param:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/sched_param && make
+
+interrupt_counts:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/interrupt_counts && make
+
+segfault:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/segfault && make
+
+prctl_exec:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/prctl_exec && make
+
+prctl:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/prctl && make
+
+msync:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/msync && make
+
+mmap_private:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/mmap_private && make
+
+dmesg:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/dmesg && make
+
+rusage:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/rusage && make
+
+cpu_limit:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/cpu_limit && make
+
+as_limit:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/as_limit && make
+
+mem_usage:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/mem_usage && make
+
+waitid:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/waitid && make
+
+signalfd:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/signalfd && make
+
+sigaction:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/sigaction && make
+
+sigsuspend:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/sigsuspend && make
+
+exit_code_child:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/exit_code_child && make
+
+exit_code:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/exit_code && make
+
+vfork:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/vfork && make
+
+chroot_exec:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/chroot_exec && make
+
+chroot:
+	# We don't want to export CARGO_TARGET_DIR to our destination make.
+	unset CARGO_TARGET_DIR && cd programs/chroot && make
+
 .PHONY: clean
 clean::
 	cd programs/exit && make clean
@@ -34,3 +138,29 @@ clean::
 	unset CARGO_TARGET_DIR && cd programs/example_rust && make clean
 	unset CARGO_TARGET_DIR && cd programs/execve && make clean
 	unset CARGO_TARGET_DIR && cd programs/pipes && make clean
+	unset CARGO_TARGET_DIR && cd programs/brk && make clean
+	unset CARGO_TARGET_DIR && cd programs/epoll && make clean
+	unset CARGO_TARGET_DIR && cd programs/shm_reader && make clean
+	unset CARGO_TARGET_DIR && cd programs/shm && make clean
+	unset CARGO_TARGET_DIR && cd programs/sched_stats && make clean
+	unset CARGO_TARGET_DIR && cd programs/sched_param && make clean
+	unset CARGO_TARGET_DIR && cd programs/interrupt_counts && make clean
+	unset CARGO_TARGET_DIR && cd programs/segfault && make clean
+	unset CARGO_TARGET_DIR && cd programs/prctl_exec && make clean
+	unset CARGO_TARGET_DIR && cd programs/prctl && make clean
+	unset CARGO_TARGET_DIR && cd programs/msync && make clean
+	unset CARGO_TARGET_DIR && cd programs/mmap_private && make clean
+	unset CARGO_TARGET_DIR && cd programs/dmesg && make clean
+	unset CARGO_TARGET_DIR && cd programs/rusage && make clean
+	unset CARGO_TARGET_DIR && cd programs/cpu_limit && make clean
+	unset CARGO_TARGET_DIR && cd programs/as_limit && make clean
+	unset CARGO_TARGET_DIR && cd programs/mem_usage && make clean
+	unset CARGO_TARGET_DIR && cd programs/waitid && make clean
+	unset CARGO_TARGET_DIR && cd programs/signalfd && make clean
+	unset CARGO_TARGET_DIR && cd programs/sigaction && make clean
+	unset CARGO_TARGET_DIR && cd programs/sigsuspend && make clean
+	unset CARGO_TARGET_DIR && cd programs/exit_code_child && make clean
+	unset CARGO_TARGET_DIR && cd programs/exit_code && make clean
+	unset CARGO_TARGET_DIR && cd programs/vfork && make clean
+	unset CARGO_TARGET_DIR && cd programs/chroot_exec && make clean
+	unset CARGO_TARGET_DIR && cd programs/chroot && make clean
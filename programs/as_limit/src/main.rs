@@ -0,0 +1,108 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+use core::ffi::{c_char, c_void};
+use kidneyos_syscalls::{
+    RLimit, EINVAL, ENOMEM, MAP_PRIVATE, O_CREATE, PROT_READ, RLIMIT_AS, RLIM_INFINITY,
+};
+
+const PATH: *const c_char = c"/as_limit".as_ptr();
+
+const PAGE_SIZE: usize = 4096;
+const MAP_ADDR: usize = 0x40000000;
+
+/// The kernel gives every process a 16 MiB stack, and this program has no heap, so this leaves
+/// room for 1 MiB of mappings.
+const LIMIT: usize = 17 * 1024 * 1024;
+const TOO_BIG: usize = 2 * 1024 * 1024;
+
+fn map(fd: i32, length: usize) -> isize {
+    kidneyos_syscalls::mmap(
+        MAP_ADDR as *mut c_void,
+        length,
+        PROT_READ,
+        MAP_PRIVATE,
+        fd,
+        0,
+    ) as isize
+}
+
+fn set_limit(limit: usize) -> i32 {
+    let limit = RLimit {
+        rlim_cur: limit,
+        rlim_max: RLIM_INFINITY,
+    };
+
+    kidneyos_syscalls::setrlimit(RLIMIT_AS, &limit)
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    let mut limit = RLimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+
+    if kidneyos_syscalls::getrlimit(RLIMIT_AS, &mut limit) != 0
+        || limit.rlim_cur != RLIM_INFINITY
+        || limit.rlim_max != RLIM_INFINITY
+    {
+        kidneyos_syscalls::exit(0x100);
+    }
+
+    // The hard limit can't be changed.
+    let hard = RLimit {
+        rlim_cur: LIMIT,
+        rlim_max: LIMIT,
+    };
+
+    if kidneyos_syscalls::setrlimit(RLIMIT_AS, &hard) != -EINVAL as i32 {
+        kidneyos_syscalls::exit(0x200);
+    }
+
+    if set_limit(LIMIT) != 0 {
+        kidneyos_syscalls::exit(0x300);
+    }
+
+    let fd = kidneyos_syscalls::open(PATH, O_CREATE);
+
+    if fd < 0 {
+        kidneyos_syscalls::exit(0x400);
+    }
+
+    if map(fd, TOO_BIG) != -ENOMEM {
+        kidneyos_syscalls::exit(0x500);
+    }
+
+    // Growing the heap past the limit fails too, leaving the break where it was.
+    let start = kidneyos_syscalls::brk(core::ptr::null_mut());
+    let end = kidneyos_syscalls::brk(start.wrapping_byte_add(TOO_BIG));
+
+    if end != start {
+        kidneyos_syscalls::exit(0x600);
+    }
+
+    // Anything under the limit is fine.
+    if map(fd, PAGE_SIZE) != MAP_ADDR as isize {
+        kidneyos_syscalls::exit(0x700);
+    }
+
+    if kidneyos_syscalls::munmap(MAP_ADDR as *mut c_void, PAGE_SIZE) != 0 {
+        kidneyos_syscalls::exit(0x800);
+    }
+
+    // And with no limit, so is the mapping which was too big.
+    if set_limit(RLIM_INFINITY) != 0 || map(fd, TOO_BIG) != MAP_ADDR as isize {
+        kidneyos_syscalls::exit(0x900);
+    }
+
+    kidneyos_syscalls::exit(0);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
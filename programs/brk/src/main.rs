@@ -0,0 +1,57 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+const HEAP_SIZE: usize = 8 * 1024;
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    let start = kidneyos_syscalls::sbrk(0);
+
+    let heap = kidneyos_syscalls::sbrk(HEAP_SIZE as isize);
+    if heap as usize == usize::MAX || heap != start {
+        kidneyos_syscalls::exit(0x100);
+    }
+
+    let heap = heap.cast::<u8>();
+
+    // Write a pattern across both new pages, then check it reads back.
+    for i in 0..HEAP_SIZE {
+        unsafe { heap.add(i).write(i as u8 ^ 0x5a) };
+    }
+    for i in 0..HEAP_SIZE {
+        if unsafe { heap.add(i).read() } != i as u8 ^ 0x5a {
+            kidneyos_syscalls::exit(0x200);
+        }
+    }
+
+    if kidneyos_syscalls::sbrk(0) != start.wrapping_byte_add(HEAP_SIZE) {
+        kidneyos_syscalls::exit(0x300);
+    }
+
+    // Shrink the heap back down.
+    if kidneyos_syscalls::sbrk(-(HEAP_SIZE as isize)) as usize == usize::MAX {
+        kidneyos_syscalls::exit(0x400);
+    }
+
+    if kidneyos_syscalls::sbrk(0) != start {
+        kidneyos_syscalls::exit(0x500);
+    }
+
+    // Growing into the stack should fail.
+    let stack_addr = &start as *const _ as usize;
+    if stack_addr > start as usize
+        && kidneyos_syscalls::brk(stack_addr as *mut _) as usize == stack_addr
+    {
+        kidneyos_syscalls::exit(0x600);
+    }
+
+    kidneyos_syscalls::exit(0);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
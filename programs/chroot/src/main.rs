@@ -0,0 +1,91 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+use core::ffi::c_char;
+use kidneyos_syscalls::{Pid, O_CREATE};
+
+// Checks that its root is still the jail.
+const TARGET_PROGRAM: &[u8] =
+    include_bytes!("../../chroot_exec/target/i686-unknown-linux-gnu/release/chroot_exec");
+
+/// Where the target program is inside the jail, and so after chroot'ing.
+const TARGET_PATH: *const c_char = c"/chroot_exec".as_ptr();
+
+fn create(path: *const c_char, contents: &[u8]) -> bool {
+    let fd = kidneyos_syscalls::open(path, O_CREATE);
+
+    if fd < 0 || kidneyos_syscalls::write(fd, contents.as_ptr(), contents.len()) < 0 {
+        return false;
+    }
+
+    kidneyos_syscalls::close(fd);
+    true
+}
+
+fn in_jail() -> bool {
+    let fd = kidneyos_syscalls::open(c"/marker".as_ptr(), 0);
+
+    if fd < 0 {
+        return false;
+    }
+
+    kidneyos_syscalls::close(fd);
+    kidneyos_syscalls::open(c"/jail/marker".as_ptr(), 0) < 0
+}
+
+fn wait_for_exit_code(pid: Pid) -> Option<i32> {
+    let mut status = 0;
+
+    if kidneyos_syscalls::waitpid(pid, &mut status, 0) != pid
+        || !kidneyos_syscalls::wifexited(status)
+    {
+        return None;
+    }
+
+    Some(kidneyos_syscalls::wifexitstatus(status))
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    if kidneyos_syscalls::mkdir(c"/jail".as_ptr()) != 0 {
+        kidneyos_syscalls::exit(0x100);
+    }
+
+    if !create(c"/jail/marker".as_ptr(), b"")
+        || !create(c"/jail/chroot_exec".as_ptr(), TARGET_PROGRAM)
+    {
+        kidneyos_syscalls::exit(0x200);
+    }
+
+    if kidneyos_syscalls::chroot(c"/jail".as_ptr()) != 0 || !in_jail() {
+        kidneyos_syscalls::exit(0x300);
+    }
+
+    // SAFETY: the child only exits.
+    let pid = unsafe { kidneyos_syscalls::vfork() };
+
+    if pid == 0 {
+        kidneyos_syscalls::exit(if in_jail() { 0 } else { 1 });
+    }
+
+    if wait_for_exit_code(pid) != Some(0) {
+        kidneyos_syscalls::exit(0x400);
+    }
+
+    let argv = [TARGET_PATH, core::ptr::null()];
+
+    let envp = [core::ptr::null()];
+
+    // Only returns if it fails.
+    kidneyos_syscalls::execve(TARGET_PATH, argv.as_ptr(), envp.as_ptr());
+
+    kidneyos_syscalls::exit(0x500);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
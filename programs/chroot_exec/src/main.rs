@@ -0,0 +1,30 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+// Run by the chroot program, from inside its jail.
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    // Only exists inside the jail, which this process' root should still be.
+    let fd = kidneyos_syscalls::open(c"/marker".as_ptr(), 0);
+
+    if fd < 0 {
+        kidneyos_syscalls::exit(0x100);
+    }
+
+    kidneyos_syscalls::close(fd);
+
+    if kidneyos_syscalls::open(c"/jail/marker".as_ptr(), 0) >= 0 {
+        kidneyos_syscalls::exit(0x200);
+    }
+
+    kidneyos_syscalls::exit(0);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
@@ -0,0 +1,94 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+// The kernel should print why this process was killed, then carry on with it exiting with
+// 128 + 24, as if it had been killed by SIGXCPU. There's no fork yet, so rather than running a
+// busy child, this process limits itself and then keeps the CPU busy.
+
+use kidneyos_syscalls::{RLimit, RUsage, EINVAL, RLIMIT_CPU, RLIM_INFINITY, RUSAGE_SELF};
+
+/// Timer ticks well past the one second limit (there are about 18 a second), after which the
+/// process clearly wasn't killed.
+const GIVE_UP_TICKS: usize = 200;
+
+fn get_limit() -> RLimit {
+    let mut limit = RLimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+
+    let result = kidneyos_syscalls::getrlimit(RLIMIT_CPU, &mut limit);
+
+    if result != 0 {
+        kidneyos_syscalls::exit(result);
+    }
+
+    limit
+}
+
+fn cpu_ticks() -> usize {
+    let mut usage = RUsage::default();
+
+    let result = kidneyos_syscalls::getrusage(RUSAGE_SELF, &mut usage);
+
+    if result != 0 {
+        kidneyos_syscalls::exit(result);
+    }
+
+    usage.cpu_ticks
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    let limit = get_limit();
+
+    if limit.rlim_cur != RLIM_INFINITY || limit.rlim_max != RLIM_INFINITY {
+        kidneyos_syscalls::exit(0x100);
+    }
+
+    // The soft limit can't be above the hard one.
+    let bad = RLimit {
+        rlim_cur: 2,
+        rlim_max: 1,
+    };
+
+    if kidneyos_syscalls::setrlimit(RLIMIT_CPU, &bad) != -EINVAL as i32 {
+        kidneyos_syscalls::exit(0x200);
+    }
+
+    let one_second = RLimit {
+        rlim_cur: 1,
+        rlim_max: RLIM_INFINITY,
+    };
+
+    let result = kidneyos_syscalls::setrlimit(RLIMIT_CPU, &one_second);
+
+    if result != 0 {
+        kidneyos_syscalls::exit(result);
+    }
+
+    let limit = get_limit();
+
+    if limit.rlim_cur != 1 || limit.rlim_max != RLIM_INFINITY {
+        kidneyos_syscalls::exit(0x300);
+    }
+
+    let mut x = core::hint::black_box(1u32);
+
+    while cpu_ticks() < GIVE_UP_TICKS {
+        for i in 0..100_000 {
+            x = core::hint::black_box(x.wrapping_mul(31).wrapping_add(i));
+        }
+    }
+
+    // Still running, so the limit wasn't enforced.
+    kidneyos_syscalls::exit(0x400);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
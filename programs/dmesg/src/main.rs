@@ -0,0 +1,89 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+use kidneyos_syscalls::{
+    SYSLOG_ACTION_CLEAR, SYSLOG_ACTION_READ_ALL, SYSLOG_ACTION_READ_CLEAR,
+    SYSLOG_ACTION_SIZE_BUFFER,
+};
+
+const LOG_SIZE: usize = 16 * 1024;
+
+// Logged by the kernel while booting, in this order.
+const BOOT_LINES: [&[u8]; 3] = [
+    b"Initializing Thread System...\n",
+    b"Mounting root filesystem...\n",
+    b"initialized system\n",
+];
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    if kidneyos_syscalls::klogctl(SYSLOG_ACTION_SIZE_BUFFER, core::ptr::null_mut(), 0)
+        != LOG_SIZE as i32
+    {
+        kidneyos_syscalls::exit(0x100);
+    }
+
+    let mut log = [0u8; LOG_SIZE];
+    let len = kidneyos_syscalls::klogctl(
+        SYSLOG_ACTION_READ_ALL,
+        log.as_mut_ptr().cast(),
+        LOG_SIZE as i32,
+    );
+
+    if len <= 0 {
+        kidneyos_syscalls::exit(0x200);
+    }
+
+    let log = &log[..len as usize];
+
+    // Print it, like dmesg.
+    kidneyos_syscalls::write(1, log.as_ptr(), log.len());
+
+    let mut rest = log;
+
+    for line in BOOT_LINES {
+        match find(rest, line) {
+            Some(i) => rest = &rest[i + line.len()..],
+            None => kidneyos_syscalls::exit(0x300),
+        }
+    }
+
+    // Only the most recent output comes back if it doesn't all fit.
+    let mut tail = [0u8; 8];
+
+    if kidneyos_syscalls::klogctl(SYSLOG_ACTION_READ_ALL, tail.as_mut_ptr().cast(), 8) != 8 {
+        kidneyos_syscalls::exit(0x400);
+    }
+
+    if kidneyos_syscalls::klogctl(SYSLOG_ACTION_READ_ALL, tail.as_mut_ptr().cast(), -1) >= 0 {
+        kidneyos_syscalls::exit(0x500);
+    }
+
+    if kidneyos_syscalls::klogctl(SYSLOG_ACTION_READ_CLEAR, tail.as_mut_ptr().cast(), 8) != 8 {
+        kidneyos_syscalls::exit(0x600);
+    }
+
+    if kidneyos_syscalls::klogctl(SYSLOG_ACTION_READ_ALL, tail.as_mut_ptr().cast(), 8) != 0 {
+        kidneyos_syscalls::exit(0x700);
+    }
+
+    if kidneyos_syscalls::klogctl(SYSLOG_ACTION_CLEAR, core::ptr::null_mut(), 0) != 0 {
+        kidneyos_syscalls::exit(0x800);
+    }
+
+    kidneyos_syscalls::exit(0);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
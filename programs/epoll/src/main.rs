@@ -0,0 +1,85 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+use kidneyos_syscalls::{EpollEvent, EPOLLIN, EPOLL_CTL_ADD, EPOLL_CTL_DEL};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    let mut first = [0, 0];
+    let mut second = [0, 0];
+
+    kidneyos_syscalls::pipe(first.as_mut_ptr());
+    kidneyos_syscalls::pipe(second.as_mut_ptr());
+
+    let epfd = kidneyos_syscalls::epoll_create(1);
+
+    if epfd < 0 {
+        kidneyos_syscalls::exit(epfd);
+    }
+
+    for read in [first[0], second[0]] {
+        let event = EpollEvent {
+            events: EPOLLIN,
+            data: read as u64,
+        };
+
+        let error = kidneyos_syscalls::epoll_ctl(epfd, EPOLL_CTL_ADD, read, &event);
+
+        if error < 0 {
+            kidneyos_syscalls::exit(error);
+        }
+    }
+
+    let mut events = [EpollEvent { events: 0, data: 0 }; 4];
+
+    // Nothing has been written yet.
+    if kidneyos_syscalls::epoll_wait(epfd, events.as_mut_ptr(), events.len() as i32, 0) != 0 {
+        kidneyos_syscalls::exit(0x100);
+    }
+
+    let data: [u8; 5] = [1, 2, 3, 4, 5];
+
+    kidneyos_syscalls::write(second[1], data.as_ptr(), data.len());
+
+    // Only the second pipe should be ready.
+    let ready = kidneyos_syscalls::epoll_wait(epfd, events.as_mut_ptr(), events.len() as i32, -1);
+
+    if ready != 1 {
+        kidneyos_syscalls::exit(0x200);
+    }
+
+    if events[0].data != second[0] as u64 || events[0].events != EPOLLIN {
+        kidneyos_syscalls::exit(0x300);
+    }
+
+    let mut buf = [0; 5];
+
+    kidneyos_syscalls::read(second[0], buf.as_mut_ptr(), buf.len());
+
+    // Once it's been read, nothing is ready again.
+    if kidneyos_syscalls::epoll_wait(epfd, events.as_mut_ptr(), events.len() as i32, 0) != 0 {
+        kidneyos_syscalls::exit(0x400);
+    }
+
+    // A timeout runs out with nothing ready.
+    if kidneyos_syscalls::epoll_wait(epfd, events.as_mut_ptr(), events.len() as i32, 50) != 0 {
+        kidneyos_syscalls::exit(0x500);
+    }
+
+    // Removing it twice fails.
+    kidneyos_syscalls::epoll_ctl(epfd, EPOLL_CTL_DEL, second[0], core::ptr::null());
+
+    if kidneyos_syscalls::epoll_ctl(epfd, EPOLL_CTL_DEL, second[0], core::ptr::null()) >= 0 {
+        kidneyos_syscalls::exit(0x600);
+    }
+
+    kidneyos_syscalls::exit(0);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
@@ -2,13 +2,28 @@
 #![cfg_attr(not(test), no_main)]
 
 use core::ffi::c_char;
-use kidneyos_syscalls::O_CREATE;
+use kidneyos_syscalls::{Pid, O_CREATE};
 
 const TARGET_PROGRAM: &[u8] =
     include_bytes!("../../example_rust/target/i686-unknown-linux-gnu/release/example_rust");
 
 const TARGET_PATH: *const c_char = c"/example_rust".as_ptr();
 
+/// What the example_rust program exits with.
+const TARGET_EXIT_CODE: i32 = 1;
+
+fn wait_for_exit_code(pid: Pid) -> Option<i32> {
+    let mut status = 0;
+
+    if kidneyos_syscalls::waitpid(pid, &mut status, 0) != pid
+        || !kidneyos_syscalls::wifexited(status)
+    {
+        return None;
+    }
+
+    Some(kidneyos_syscalls::wifexitstatus(status))
+}
+
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
     // TempFS - We'll create the file that we want to execute on the fly.
@@ -31,6 +46,30 @@ pub extern "C" fn _start() -> ! {
 
     let envp = [core::ptr::null()];
 
+    // Run it from a file descriptor first, in a child so this process is still around to check
+    // it ran.
+    let fd = kidneyos_syscalls::open(TARGET_PATH, 0);
+
+    if fd < 0 {
+        kidneyos_syscalls::exit(fd);
+    }
+
+    // SAFETY: the child only execs or exits.
+    let pid = unsafe { kidneyos_syscalls::vfork() };
+
+    if pid == 0 {
+        // Only returns if it fails.
+        kidneyos_syscalls::fexecve(fd, argv.as_ptr(), envp.as_ptr());
+
+        kidneyos_syscalls::exit(0x100);
+    }
+
+    if wait_for_exit_code(pid) != Some(TARGET_EXIT_CODE) {
+        kidneyos_syscalls::exit(0x200);
+    }
+
+    kidneyos_syscalls::close(fd);
+
     let result = kidneyos_syscalls::execve(TARGET_PATH, argv.as_ptr(), envp.as_ptr());
 
     kidneyos_syscalls::exit(result);
@@ -0,0 +1,66 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+// Vforks, has the child exec a program which exits with EXIT_CODE, then checks that waitpid sees
+// that exit code.
+
+use core::ffi::c_char;
+use kidneyos_syscalls::O_CREATE;
+
+// Exits with EXIT_CODE.
+const TARGET_PROGRAM: &[u8] =
+    include_bytes!("../../exit_code_child/target/i686-unknown-linux-gnu/release/exit_code_child");
+
+const TARGET_PATH: *const c_char = c"/exit_code_child".as_ptr();
+
+/// Has to match `EXIT_CODE` in the exit_code_child program.
+const EXIT_CODE: i32 = 42;
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    let fd = kidneyos_syscalls::open(TARGET_PATH, O_CREATE);
+
+    if fd < 0 || kidneyos_syscalls::write(fd, TARGET_PROGRAM.as_ptr(), TARGET_PROGRAM.len()) < 0 {
+        kidneyos_syscalls::exit(0x100);
+    }
+
+    kidneyos_syscalls::close(fd);
+
+    // SAFETY: the child only execs or exits.
+    let pid = unsafe { kidneyos_syscalls::vfork() };
+
+    if pid == 0 {
+        let argv = [TARGET_PATH, core::ptr::null()];
+        let envp = [core::ptr::null()];
+
+        // Only returns if it fails.
+        kidneyos_syscalls::execve(TARGET_PATH, argv.as_ptr(), envp.as_ptr());
+
+        kidneyos_syscalls::exit(0x300);
+    }
+
+    let mut status = 0;
+
+    // The child has a new process once it's exec'd, but it's still waited on by its pid.
+    if kidneyos_syscalls::waitpid(pid, &mut status, 0) != pid {
+        kidneyos_syscalls::exit(0x200);
+    }
+
+    if !kidneyos_syscalls::wifexited(status) {
+        kidneyos_syscalls::exit(0x400);
+    }
+
+    if kidneyos_syscalls::wifexitstatus(status) != EXIT_CODE {
+        kidneyos_syscalls::exit(0x500);
+    }
+
+    kidneyos_syscalls::exit(0);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
@@ -0,0 +1,20 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+// Run by the exit_code and vfork programs, which check that they see this exit code.
+
+/// Has to match `EXIT_CODE` in the exit_code and vfork programs.
+const EXIT_CODE: i32 = 42;
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    kidneyos_syscalls::exit(EXIT_CODE);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
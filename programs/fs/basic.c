@@ -27,6 +27,16 @@ void _start() {
             exit(~(i << 8 | (uint8_t)buf[i]));
     }
     check(close(fd));
+    // truncate by path, without an open file
+    check(truncate("/foo", 4));
+    fd = check(open("/foo", 0));
+    if (check(read(fd, buf, 10)) != 4) exit(__LINE__);
+    for (int i = 0; i < 4; i++) {
+        if (buf[i] != test_data[i]) exit(__LINE__);
+    }
+    check(close(fd));
+    if (truncate("/", 0) != -EISDIR) exit(__LINE__);
+    if (truncate("/missing", 0) != -ENOENT) exit(__LINE__);
     check(mkdir("/d"));
     check(mount("", "/d", "tmpfs"));
     check(chdir("/d"));
@@ -37,6 +47,14 @@ void _start() {
     fd = check(open("file", O_CREATE));
     check(link("file", "hardlink"));
     check(symlink("file", "symlink"));
+    // hard links can't cross file systems, even between two of the same type
+    check(mkdir("/e"));
+    check(mount("", "/e", "tmpfs"));
+    if (link("file", "/e/hardlink") != -EXDEV) exit(__LINE__);
+    if (link("/foo", "hardlink2") != -EXDEV) exit(__LINE__);
+    if (open("/e/hardlink", 0) != -ENOENT) exit(__LINE__);
+    check(unmount("/e"));
+    check(rmdir("/e"));
     struct Stat file_info = {0};
     check(write(fd, "hello", 5));
     check(ftruncate(fd, 4));
@@ -57,6 +75,8 @@ void _start() {
     if (symlink_info.size != 4) exit(__LINE__);
     if (symlink_info.type != S_REGULAR_FILE) exit(__LINE__);
     if (symlink_info.inode != file_info.inode) exit(__LINE__);
+    if (check(readlinkat(AT_FDCWD, "symlink", buf, 10)) != 4) exit(__LINE__);
+    if (buf[0] != 'f' || buf[3] != 'e') exit(__LINE__);
 
 
     check(unlink("/d/hardlink"));
@@ -71,6 +91,21 @@ void _start() {
     check(mkdir("/e"));
     check(rmdir("/e"));
     if (open("/e/new", O_CREATE) != -ENOENT) exit(__LINE__);
+    // the same, relative to directory file descriptors
+    int root_fd = check(open("/", 0));
+    check(mkdirat(root_fd, "at"));
+    int dir_fd = check(open("/at", 0));
+    check(close(check(openat(dir_fd, "file", O_CREATE))));
+    check(symlinkat("file", dir_fd, "symlink"));
+    if (check(readlinkat(dir_fd, "symlink", buf, 2)) != 2) exit(__LINE__);
+    if (buf[0] != 'f' || buf[1] != 'i') exit(__LINE__);
+    if (unlinkat(root_fd, "at", 0) != -EISDIR) exit(__LINE__);
+    check(unlinkat(dir_fd, "symlink", 0));
+    check(unlinkat(dir_fd, "file", 0));
+    check(close(dir_fd));
+    check(unlinkat(root_fd, "at", AT_REMOVEDIR));
+    check(close(root_fd));
+    if (mkdirat(-1, "at") != -EBADF) exit(__LINE__);
     check(sync());
     print("success!\n");
     exit(0);
@@ -0,0 +1,22 @@
+#include <kidneyos.h>
+
+void _start() {
+    // fill the last bytes of a fresh heap page with non-null characters,
+    // so the string runs right up to the end of the mapped region
+    char *page = sbrk(4096);
+    if (page == (char *)-1) exit(__LINE__);
+    char *end = page + 4096;
+    for (int i = 1; i <= 16; i++) end[-i] = 'a';
+    if (open(end - 16, 0) != -EFAULT) exit(__LINE__);
+    if (mkdir(end - 16) != -EFAULT) exit(__LINE__);
+
+    // a terminated string that is too long
+    for (int i = 0; i < 4095; i++) page[i] = 'a';
+    sbrk(4096);
+    for (int i = 4095; i < PATH_MAX + 16; i++) page[i] = 'a';
+    page[PATH_MAX + 16] = 0;
+    if (open(page, 0) != -ENAMETOOLONG) exit(__LINE__);
+
+    write(1, "ok\n", 3);
+    exit(0);
+}
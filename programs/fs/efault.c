@@ -0,0 +1,24 @@
+#include <kidneyos.h>
+
+void _start() {
+    // kernel memory
+    if (write(1, (const uint8_t *)0xC0000000, 4) != -EFAULT) exit(__LINE__);
+    // unmapped user memory
+    if (write(1, (const uint8_t *)0x1000, 4) != -EFAULT) exit(__LINE__);
+    // range wrapping around the end of the address space
+    if (write(1, (const uint8_t *)0xFFFFFFF0, 32) != -EFAULT) exit(__LINE__);
+
+    int fd = open("/efault", O_CREATE);
+    if (fd < 0) exit(-fd);
+    if (write(fd, "data", 4) != 4) exit(__LINE__);
+    if (lseek64(fd, 0, SEEK_SET) != 0) exit(__LINE__);
+    if (read(fd, (uint8_t *)0xC0000000, 4) != -EFAULT) exit(__LINE__);
+    // a failed read shouldn't have consumed anything
+    char buf[4];
+    if (read(fd, (uint8_t *)buf, 4) != 4) exit(__LINE__);
+    if (fstat(fd, (struct Stat *)0xC0000000) != -EFAULT) exit(__LINE__);
+    close(fd);
+
+    write(1, "ok\n", 3);
+    exit(0);
+}
@@ -0,0 +1,59 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+use kidneyos_syscalls::INTERRUPT_VECTORS;
+
+const SYSCALL_VECTOR: usize = 0x80;
+const SYSCALLS: usize = 10;
+
+fn counts() -> [usize; INTERRUPT_VECTORS] {
+    let mut counts = [0; INTERRUPT_VECTORS];
+
+    let result = kidneyos_syscalls::interrupt_counts(counts.as_mut_ptr(), counts.len());
+
+    if result != INTERRUPT_VECTORS as i32 {
+        kidneyos_syscalls::exit(0x100);
+    }
+
+    counts
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    let before = counts();
+
+    // Each syscall is a software interrupt.
+    for _ in 0..SYSCALLS {
+        kidneyos_syscalls::getpid();
+    }
+
+    let after = counts();
+
+    // Nothing else is running to make syscalls. The second interrupt_counts is counted too,
+    // before it reads the counts.
+    if after[SYSCALL_VECTOR] != before[SYSCALL_VECTOR] + SYSCALLS + 1 {
+        kidneyos_syscalls::exit(0x200);
+    }
+
+    // Only part of the counts can be asked for. Nothing handles divide errors or debug
+    // exceptions, so those are never counted.
+    let mut first = [usize::MAX; 2];
+
+    if kidneyos_syscalls::interrupt_counts(first.as_mut_ptr(), first.len()) != 2 {
+        kidneyos_syscalls::exit(0x300);
+    }
+
+    if first != [0, 0] {
+        kidneyos_syscalls::exit(0x400);
+    }
+
+    kidneyos_syscalls::exit(0);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
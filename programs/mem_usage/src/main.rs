@@ -0,0 +1,110 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+// There's no fork yet, so rather than two processes sharing memory, this process maps the same
+// shared memory object twice. Each of its frames is then mapped into two pages, which count in
+// full towards the RSS but only half towards the PSS.
+
+use core::ffi::{c_char, c_void};
+use kidneyos_syscalls::{MemUsage, O_CREATE, PROT_READ, PROT_WRITE};
+
+const SHM_NAME: *const c_char = c"/kidneyos-mem-usage".as_ptr();
+
+const PAGE_SIZE: usize = 4096;
+const SHM_SIZE: usize = 4 * PAGE_SIZE;
+
+const FIRST: usize = 0x4000_0000;
+const SECOND: usize = 0x4001_0000;
+
+fn usage() -> MemUsage {
+    let mut usage = MemUsage::default();
+
+    let result = kidneyos_syscalls::mem_usage(&mut usage);
+
+    if result != 0 {
+        kidneyos_syscalls::exit(result);
+    }
+
+    usage
+}
+
+/// Map the shared memory object open as `fd` at `addr`.
+fn map(addr: usize, fd: i32) -> *mut u8 {
+    let result = kidneyos_syscalls::mmap(
+        addr as *mut c_void,
+        SHM_SIZE,
+        PROT_READ | PROT_WRITE,
+        0,
+        fd,
+        0,
+    );
+
+    if result as usize != addr {
+        kidneyos_syscalls::exit(result as i32);
+    }
+
+    result.cast()
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    // Nothing is shared yet.
+    let before = usage();
+
+    if before.rss == 0 || before.pss != before.rss {
+        kidneyos_syscalls::exit(0x100);
+    }
+
+    let fd = kidneyos_syscalls::shm_open(SHM_NAME, O_CREATE);
+
+    if fd < 0 {
+        kidneyos_syscalls::exit(fd);
+    }
+
+    kidneyos_syscalls::ftruncate(fd, SHM_SIZE as u64);
+
+    let first = map(FIRST, fd);
+    let second = map(SECOND, fd);
+
+    // Fault every page of both mappings in.
+    for page in (0..SHM_SIZE).step_by(PAGE_SIZE) {
+        unsafe { first.add(page).write(1) };
+        if unsafe { second.add(page).read() } != 1 {
+            kidneyos_syscalls::exit(0x200);
+        }
+    }
+
+    let shared = usage();
+
+    if shared.rss - before.rss != 2 * SHM_SIZE {
+        kidneyos_syscalls::exit(0x300);
+    }
+
+    if shared.pss - before.pss != SHM_SIZE {
+        kidneyos_syscalls::exit(0x400);
+    }
+
+    // Once only one mapping is left, the object's frames are no longer shared.
+    if kidneyos_syscalls::munmap(SECOND as *mut c_void, SHM_SIZE) != 0 {
+        kidneyos_syscalls::exit(0x500);
+    }
+
+    let unshared = usage();
+
+    if unshared.rss - before.rss != SHM_SIZE || unshared.pss - before.pss != SHM_SIZE {
+        kidneyos_syscalls::exit(0x600);
+    }
+
+    kidneyos_syscalls::close(fd);
+    kidneyos_syscalls::shm_unlink(SHM_NAME);
+
+    kidneyos_syscalls::exit(0);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
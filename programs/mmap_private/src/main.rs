@@ -0,0 +1,99 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+use core::ffi::{c_char, c_void};
+use kidneyos_syscalls::{MAP_PRIVATE, MAP_SHARED, O_CREATE, PROT_READ, PROT_WRITE, SEEK_SET};
+
+const PATH: *const c_char = c"/mmap_private".as_ptr();
+
+const PAGE_SIZE: usize = 4096;
+const SHARED_ADDR: usize = 0x12345000;
+const PRIVATE_ADDR: usize = 0x23456000;
+
+fn map(addr: usize, flags: i32, fd: i32) -> *mut u8 {
+    let result = kidneyos_syscalls::mmap(
+        addr as *mut c_void,
+        PAGE_SIZE,
+        PROT_READ | PROT_WRITE,
+        flags,
+        fd,
+        0,
+    );
+
+    if result as usize != addr {
+        kidneyos_syscalls::exit(0x100);
+    }
+
+    result.cast()
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    let fd = kidneyos_syscalls::open(PATH, O_CREATE);
+
+    if fd < 0 {
+        kidneyos_syscalls::exit(0x200);
+    }
+
+    let original = [b'a'; PAGE_SIZE];
+
+    if kidneyos_syscalls::write(fd, original.as_ptr(), original.len()) != PAGE_SIZE as i32 {
+        kidneyos_syscalls::exit(0x300);
+    }
+
+    let shared = map(SHARED_ADDR, MAP_SHARED, fd);
+    let private = map(PRIVATE_ADDR, MAP_PRIVATE, fd);
+
+    // Read first, so the page is mapped read-only before it's copied.
+    if unsafe { private.read_volatile() } != b'a' {
+        kidneyos_syscalls::exit(0x400);
+    }
+
+    unsafe { private.write_volatile(b'p') };
+
+    if unsafe { private.read_volatile() } != b'p' {
+        kidneyos_syscalls::exit(0x500);
+    }
+
+    if unsafe { shared.read_volatile() } != b'a' {
+        kidneyos_syscalls::exit(0x600);
+    }
+
+    // Writes to the shared mapping aren't seen by the private copy.
+    unsafe { shared.add(1).write_volatile(b's') };
+
+    if unsafe { private.add(1).read_volatile() } != b'a' {
+        kidneyos_syscalls::exit(0x700);
+    }
+
+    if kidneyos_syscalls::munmap(private.cast(), PAGE_SIZE) != 0
+        || kidneyos_syscalls::munmap(shared.cast(), PAGE_SIZE) != 0
+    {
+        kidneyos_syscalls::exit(0x800);
+    }
+
+    let mut contents = [0; PAGE_SIZE];
+
+    if kidneyos_syscalls::lseek64(fd, 0, SEEK_SET) != 0
+        || kidneyos_syscalls::read(fd, contents.as_mut_ptr(), PAGE_SIZE) != PAGE_SIZE as i32
+    {
+        kidneyos_syscalls::exit(0x900);
+    }
+
+    let mut expected = original;
+    expected[1] = b's';
+
+    if contents != expected {
+        kidneyos_syscalls::exit(0xA00);
+    }
+
+    kidneyos_syscalls::exit(0);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
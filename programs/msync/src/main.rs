@@ -0,0 +1,93 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+use core::ffi::{c_char, c_void};
+use kidneyos_syscalls::{MAP_SHARED, MS_SYNC, O_CREATE, PROT_READ, PROT_WRITE, SEEK_SET};
+
+const PATH: *const c_char = c"/msync".as_ptr();
+
+const PAGE_SIZE: usize = 4096;
+const FILE_SIZE: usize = 2 * PAGE_SIZE;
+const MAP_ADDR: usize = 0x12345000;
+
+/// Read the whole file open as `fd`.
+fn contents(fd: i32) -> [u8; FILE_SIZE] {
+    let mut buf = [0; FILE_SIZE];
+
+    if kidneyos_syscalls::lseek64(fd, 0, SEEK_SET) != 0 {
+        kidneyos_syscalls::exit(0x100);
+    }
+
+    if kidneyos_syscalls::read(fd, buf.as_mut_ptr(), buf.len()) != FILE_SIZE as i32 {
+        kidneyos_syscalls::exit(0x200);
+    }
+
+    buf
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    let fd = kidneyos_syscalls::open(PATH, O_CREATE);
+
+    if fd < 0 {
+        kidneyos_syscalls::exit(0x300);
+    }
+
+    let original = [b'a'; FILE_SIZE];
+
+    if kidneyos_syscalls::write(fd, original.as_ptr(), original.len()) != FILE_SIZE as i32 {
+        kidneyos_syscalls::exit(0x400);
+    }
+
+    let result = kidneyos_syscalls::mmap(
+        MAP_ADDR as *mut c_void,
+        FILE_SIZE,
+        PROT_READ | PROT_WRITE,
+        MAP_SHARED,
+        fd,
+        0,
+    );
+
+    if result as usize != MAP_ADDR {
+        kidneyos_syscalls::exit(0x500);
+    }
+
+    let mapping = result.cast::<u8>();
+
+    // Only the second page is changed.
+    unsafe { mapping.add(PAGE_SIZE + 1).write_volatile(b'b') };
+
+    if kidneyos_syscalls::msync(result, FILE_SIZE, MS_SYNC) != 0 {
+        kidneyos_syscalls::exit(0x600);
+    }
+
+    let mut expected = original;
+    expected[PAGE_SIZE + 1] = b'b';
+
+    if contents(fd) != expected {
+        kidneyos_syscalls::exit(0x700);
+    }
+
+    // munmap writes back changes too.
+    unsafe { mapping.write_volatile(b'c') };
+
+    if kidneyos_syscalls::munmap(result, FILE_SIZE) != 0 {
+        kidneyos_syscalls::exit(0x800);
+    }
+
+    expected[0] = b'c';
+
+    if contents(fd) != expected {
+        kidneyos_syscalls::exit(0x900);
+    }
+
+    kidneyos_syscalls::exit(0);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
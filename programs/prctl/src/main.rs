@@ -0,0 +1,72 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+use core::ffi::c_char;
+use kidneyos_syscalls::{O_CREATE, PR_GET_NAME, PR_SET_NAME, TASK_COMM_LEN};
+
+// Checks its own name when run, so that exec can be seen to reset it.
+const TARGET_PROGRAM: &[u8] =
+    include_bytes!("../../prctl_exec/target/i686-unknown-linux-gnu/release/prctl_exec");
+
+const TARGET_PATH: *const c_char = c"/prctl_exec".as_ptr();
+
+fn name() -> [u8; TASK_COMM_LEN] {
+    let mut name = [0xFF; TASK_COMM_LEN];
+
+    if kidneyos_syscalls::prctl(PR_GET_NAME, name.as_mut_ptr() as usize) != 0 {
+        kidneyos_syscalls::exit(0x100);
+    }
+
+    name
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    if kidneyos_syscalls::prctl(PR_SET_NAME, c"renamed".as_ptr() as usize) != 0 {
+        kidneyos_syscalls::exit(0x200);
+    }
+
+    if !name().starts_with(b"renamed\0") {
+        kidneyos_syscalls::exit(0x300);
+    }
+
+    // Names are cut short to fit, leaving room for the terminator.
+    let long_name = c"a-name-longer-than-fifteen-bytes";
+
+    if kidneyos_syscalls::prctl(PR_SET_NAME, long_name.as_ptr() as usize) != 0 {
+        kidneyos_syscalls::exit(0x400);
+    }
+
+    if name() != *b"a-name-longer-t\0" {
+        kidneyos_syscalls::exit(0x500);
+    }
+
+    let fd = kidneyos_syscalls::open(TARGET_PATH, O_CREATE);
+
+    if fd < 0 {
+        kidneyos_syscalls::exit(0x600);
+    }
+
+    if kidneyos_syscalls::write(fd, TARGET_PROGRAM.as_ptr(), TARGET_PROGRAM.len()) < 0 {
+        kidneyos_syscalls::exit(0x700);
+    }
+
+    kidneyos_syscalls::close(fd);
+
+    let argv = [TARGET_PATH, core::ptr::null()];
+
+    let envp = [core::ptr::null()];
+
+    // Only returns if it fails.
+    kidneyos_syscalls::execve(TARGET_PATH, argv.as_ptr(), envp.as_ptr());
+
+    kidneyos_syscalls::exit(0x800);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
@@ -0,0 +1,30 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+use kidneyos_syscalls::{PR_GET_NAME, TASK_COMM_LEN};
+
+// Run by the prctl program, after it's renamed itself.
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    let mut name = [0xFF; TASK_COMM_LEN];
+
+    if kidneyos_syscalls::prctl(PR_GET_NAME, name.as_mut_ptr() as usize) != 0 {
+        kidneyos_syscalls::exit(0x100);
+    }
+
+    // exec names the process after the program's base name.
+    if !name.starts_with(b"prctl_exec\0") {
+        kidneyos_syscalls::exit(0x200);
+    }
+
+    kidneyos_syscalls::exit(0);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
@@ -0,0 +1,76 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+use kidneyos_syscalls::{RUsage, EINVAL, RUSAGE_SELF, RUSAGE_THREAD};
+
+// Far more work than fits in one timer tick.
+const MAX_ROUNDS: usize = 100_000;
+const ROUND_ITERATIONS: u32 = 100_000;
+
+fn usage(who: i32) -> RUsage {
+    let mut usage = RUsage::default();
+
+    let result = kidneyos_syscalls::getrusage(who, &mut usage);
+
+    if result != 0 {
+        kidneyos_syscalls::exit(result);
+    }
+
+    usage
+}
+
+/// Some work the compiler can't optimize away.
+fn work() -> u32 {
+    let mut x = core::hint::black_box(1u32);
+    for i in 0..ROUND_ITERATIONS {
+        x = x.wrapping_mul(31).wrapping_add(i);
+    }
+    core::hint::black_box(x)
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    let mut rounds = 0;
+
+    while usage(RUSAGE_SELF).cpu_ticks == 0 {
+        if rounds == MAX_ROUNDS {
+            kidneyos_syscalls::exit(0x100);
+        }
+        work();
+        rounds += 1;
+    }
+
+    let before = usage(RUSAGE_SELF);
+    kidneyos_syscalls::scheduler_yield();
+    let after = usage(RUSAGE_SELF);
+
+    // The kernel's initial thread is always ready to run, so yielding switches to it.
+    if after.voluntary_switches <= before.voluntary_switches {
+        kidneyos_syscalls::exit(0x200);
+    }
+
+    if after.cpu_ticks < before.cpu_ticks || after.page_faults < before.page_faults {
+        kidneyos_syscalls::exit(0x300);
+    }
+
+    // This process only has one thread, so the two are the same.
+    let thread = usage(RUSAGE_THREAD);
+    if thread.cpu_ticks < after.cpu_ticks || thread.voluntary_switches != after.voluntary_switches {
+        kidneyos_syscalls::exit(0x400);
+    }
+
+    let mut unused = RUsage::default();
+    if kidneyos_syscalls::getrusage(-1, &mut unused) != -EINVAL as i32 {
+        kidneyos_syscalls::exit(0x500);
+    }
+
+    kidneyos_syscalls::exit(0);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
@@ -0,0 +1,86 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+use kidneyos_syscalls::{SchedParam, SCHED_PRIORITY_MAX, SCHED_PRIORITY_MIN};
+
+fn set_priority(priority: i32) {
+    let param = SchedParam {
+        sched_priority: priority,
+    };
+
+    let result = kidneyos_syscalls::sched_setparam(0, &param);
+
+    if result != 0 {
+        kidneyos_syscalls::exit(result);
+    }
+}
+
+fn priority() -> i32 {
+    let mut param = SchedParam { sched_priority: 0 };
+
+    let result = kidneyos_syscalls::sched_getparam(0, &mut param);
+
+    if result != 0 {
+        kidneyos_syscalls::exit(result);
+    }
+
+    param.sched_priority
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    if priority() != 0 {
+        kidneyos_syscalls::exit(0x100);
+    }
+
+    set_priority(5);
+
+    if priority() != 5 {
+        kidneyos_syscalls::exit(0x200);
+    }
+
+    // Out of range priorities are clamped.
+    set_priority(100);
+
+    if priority() != SCHED_PRIORITY_MAX {
+        kidneyos_syscalls::exit(0x300);
+    }
+
+    set_priority(-100);
+
+    if priority() != SCHED_PRIORITY_MIN {
+        kidneyos_syscalls::exit(0x400);
+    }
+
+    set_priority(0);
+    kidneyos_syscalls::nice(3);
+
+    if priority() != 3 {
+        kidneyos_syscalls::exit(0x500);
+    }
+
+    kidneyos_syscalls::nice(i32::MIN);
+
+    if priority() != SCHED_PRIORITY_MIN {
+        kidneyos_syscalls::exit(0x600);
+    }
+
+    // Only the calling process' own parameters can be used.
+    let mut param = SchedParam { sched_priority: 0 };
+
+    let result = kidneyos_syscalls::sched_getparam(kidneyos_syscalls::getpid() + 1000, &mut param);
+
+    if result >= 0 {
+        kidneyos_syscalls::exit(0x700);
+    }
+
+    kidneyos_syscalls::exit(0);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
@@ -0,0 +1,60 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+use kidneyos_syscalls::SchedStats;
+
+const YIELDS: usize = 10;
+
+fn stats() -> SchedStats {
+    let mut stats = SchedStats {
+        context_switches: 0,
+        run_queue_length: 0,
+        voluntary_yields: 0,
+        preemptions: 0,
+        idle_halts: 0,
+    };
+
+    let result = kidneyos_syscalls::sched_stats(&mut stats);
+
+    if result != 0 {
+        kidneyos_syscalls::exit(result);
+    }
+
+    stats
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    let before = stats();
+
+    // The kernel's initial thread is always ready to run, so each of these switches to it.
+    for _ in 0..YIELDS {
+        kidneyos_syscalls::scheduler_yield();
+    }
+
+    let after = stats();
+
+    if after.context_switches < before.context_switches + YIELDS {
+        kidneyos_syscalls::exit(0x100);
+    }
+
+    if after.voluntary_yields < before.voluntary_yields + YIELDS {
+        kidneyos_syscalls::exit(0x200);
+    }
+
+    // Preemptions only happen when interrupts do, so they can't be predicted, but they can't
+    // go backwards.
+    if after.preemptions < before.preemptions {
+        kidneyos_syscalls::exit(0x300);
+    }
+
+    kidneyos_syscalls::exit(0);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
@@ -0,0 +1,24 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+// The kernel should print why this process was killed, then carry on with it exiting with
+// 128 + 11, as if it had been killed by SIGSEGV.
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    // Hidden from the compiler, so it can't assume this never happens.
+    let null = core::hint::black_box(core::ptr::null::<u32>());
+    // SAFETY: It isn't, which is the point.
+    let value = unsafe { core::ptr::read_volatile(null) };
+
+    // Still running, so the fault wasn't caught.
+    kidneyos_syscalls::exit(0x100 + value as i32);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
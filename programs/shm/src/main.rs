@@ -0,0 +1,89 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+use core::ffi::{c_char, c_void};
+use kidneyos_syscalls::{O_CREATE, PROT_READ, PROT_WRITE};
+
+const READER_PROGRAM: &[u8] =
+    include_bytes!("../../shm_reader/target/i686-unknown-linux-gnu/release/shm_reader");
+
+const READER_PATH: *const c_char = c"/shm_reader".as_ptr();
+
+const SHM_NAME: *const c_char = c"/kidneyos-shm".as_ptr();
+
+const SHM_SIZE: usize = 8 * 1024;
+
+/// Map the shared memory object open as `fd` at `addr`.
+fn map(addr: usize, fd: i32) -> *mut u8 {
+    let result = kidneyos_syscalls::mmap(
+        addr as *mut c_void,
+        SHM_SIZE,
+        PROT_READ | PROT_WRITE,
+        0,
+        fd,
+        0,
+    );
+
+    if result as usize != addr {
+        kidneyos_syscalls::exit(result as i32);
+    }
+
+    result.cast()
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    let fd = kidneyos_syscalls::shm_open(SHM_NAME, O_CREATE);
+
+    if fd < 0 {
+        kidneyos_syscalls::exit(fd);
+    }
+
+    kidneyos_syscalls::ftruncate(fd, SHM_SIZE as u64);
+
+    // Two mappings of the same object share memory even within a process.
+    let first = map(0x4000_0000, fd);
+    let second = map(0x4001_0000, fd);
+
+    for i in 0..SHM_SIZE {
+        unsafe { first.add(i).write(i as u8 ^ 0x5a) };
+    }
+    for i in 0..SHM_SIZE {
+        if unsafe { second.add(i).read() } != i as u8 ^ 0x5a {
+            kidneyos_syscalls::exit(0x100);
+        }
+    }
+
+    kidneyos_syscalls::close(fd);
+
+    // Run the reader as another process, which checks it sees what we wrote.
+    let reader = kidneyos_syscalls::open(READER_PATH, O_CREATE);
+
+    if reader < 0 {
+        kidneyos_syscalls::exit(reader);
+    }
+
+    let result = kidneyos_syscalls::write(reader, READER_PROGRAM.as_ptr(), READER_PROGRAM.len());
+
+    if result < 0 {
+        kidneyos_syscalls::exit(result);
+    }
+
+    kidneyos_syscalls::close(reader);
+
+    let argv = [READER_PATH, core::ptr::null()];
+
+    let envp = [core::ptr::null()];
+
+    let result = kidneyos_syscalls::execve(READER_PATH, argv.as_ptr(), envp.as_ptr());
+
+    kidneyos_syscalls::exit(result);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
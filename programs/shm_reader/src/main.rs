@@ -0,0 +1,63 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+use core::ffi::{c_char, c_void};
+use kidneyos_syscalls::{PROT_READ, PROT_WRITE};
+
+const SHM_NAME: *const c_char = c"/kidneyos-shm".as_ptr();
+
+const SHM_SIZE: usize = 8 * 1024;
+
+// Run by the shm program, after it's filled the shared memory object with a pattern.
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    let fd = kidneyos_syscalls::shm_open(SHM_NAME, 0);
+
+    if fd < 0 {
+        kidneyos_syscalls::exit(fd);
+    }
+
+    let addr = 0x4000_0000;
+
+    let result = kidneyos_syscalls::mmap(
+        addr as *mut c_void,
+        SHM_SIZE,
+        PROT_READ | PROT_WRITE,
+        0,
+        fd,
+        0,
+    );
+
+    if result as usize != addr {
+        kidneyos_syscalls::exit(result as i32);
+    }
+
+    let shared = result.cast::<u8>();
+
+    for i in 0..SHM_SIZE {
+        if unsafe { shared.add(i).read() } != i as u8 ^ 0x5a {
+            kidneyos_syscalls::exit(0x100);
+        }
+    }
+
+    kidneyos_syscalls::close(fd);
+
+    if kidneyos_syscalls::shm_unlink(SHM_NAME) != 0 {
+        kidneyos_syscalls::exit(0x200);
+    }
+
+    // Now the name is gone, it can't be opened again.
+    if kidneyos_syscalls::shm_open(SHM_NAME, 0) >= 0 {
+        kidneyos_syscalls::exit(0x300);
+    }
+
+    kidneyos_syscalls::exit(0);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
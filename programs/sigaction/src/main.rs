@@ -0,0 +1,91 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+use core::ptr::null;
+use core::sync::atomic::{AtomicI32, AtomicU32, Ordering};
+use kidneyos_syscalls::{SigAction, SigSet, EINVAL, SIGINT, SIGKILL, SIG_BLOCK, SIG_DFL, SIG_IGN};
+
+/// The signal the handler was called with, or 0 if it hasn't been.
+static HANDLED: AtomicI32 = AtomicI32::new(0);
+/// The signals which were blocked while the handler ran.
+static BLOCKED_IN_HANDLER: AtomicU32 = AtomicU32::new(0);
+
+const SIGINT_SET: SigSet = 1 << (SIGINT - 1);
+
+fn blocked() -> SigSet {
+    let mut set = 0;
+    kidneyos_syscalls::sigprocmask(SIG_BLOCK, null(), &mut set);
+    set
+}
+
+extern "C" fn handler(signal: i32) {
+    BLOCKED_IN_HANDLER.store(blocked(), Ordering::SeqCst);
+    HANDLED.store(signal, Ordering::SeqCst);
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    let action = SigAction {
+        sa_handler: handler as usize,
+        ..SigAction::default()
+    };
+    let mut old = SigAction::default();
+
+    // SAFETY: the action is a valid SigAction.
+    if unsafe { kidneyos_syscalls::sigaction(SIGINT, &action, &mut old) } != 0
+        || old.sa_handler != SIG_DFL
+    {
+        kidneyos_syscalls::exit(0x100);
+    }
+
+    // SIGKILL always kills.
+    let ignore = SigAction {
+        sa_handler: SIG_IGN,
+        ..SigAction::default()
+    };
+
+    // SAFETY: the action is a valid SigAction.
+    if unsafe { kidneyos_syscalls::sigaction(SIGKILL, &ignore, core::ptr::null_mut()) }
+        != -EINVAL as i32
+    {
+        kidneyos_syscalls::exit(0x200);
+    }
+
+    let pid = kidneyos_syscalls::getpid();
+
+    // The handler runs as kill returns, then kill's result is returned as if nothing happened.
+    if kidneyos_syscalls::kill(pid.into(), SIGINT) != 0 {
+        kidneyos_syscalls::exit(0x300);
+    }
+
+    if HANDLED.load(Ordering::SeqCst) != SIGINT {
+        kidneyos_syscalls::exit(0x400);
+    }
+
+    // SIGINT was blocked while its handler ran, and is unblocked again now.
+    if BLOCKED_IN_HANDLER.load(Ordering::SeqCst) != SIGINT_SET || blocked() != 0 {
+        kidneyos_syscalls::exit(0x500);
+    }
+
+    // Once it's ignored, SIGINT doesn't kill the process or call the handler.
+    HANDLED.store(0, Ordering::SeqCst);
+
+    // SAFETY: the action is a valid SigAction.
+    if unsafe { kidneyos_syscalls::sigaction(SIGINT, &ignore, &mut old) } != 0
+        || old.sa_handler != handler as usize
+        || kidneyos_syscalls::kill(pid.into(), SIGINT) != 0
+        || HANDLED.load(Ordering::SeqCst) != 0
+    {
+        kidneyos_syscalls::exit(0x600);
+    }
+
+    kidneyos_syscalls::exit(0);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
@@ -0,0 +1,96 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+// There's no fork yet, so rather than having another process send the signal, this process sends
+// SIGTERM to itself. Since it's blocked, it stays pending until it's read from the signalfd,
+// rather than killing the process.
+
+use kidneyos_syscalls::{
+    EpollEvent, SigSet, SignalfdSiginfo, EAGAIN, EPOLLIN, EPOLL_CTL_ADD, SFD_NONBLOCK, SIGTERM,
+    SIG_BLOCK,
+};
+
+const SIGTERM_SET: SigSet = 1 << (SIGTERM - 1);
+
+fn read_signal(fd: i32, info: &mut SignalfdSiginfo) -> i32 {
+    kidneyos_syscalls::read(
+        fd,
+        (info as *mut SignalfdSiginfo).cast(),
+        core::mem::size_of::<SignalfdSiginfo>(),
+    )
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    let mut old: SigSet = 0;
+
+    if kidneyos_syscalls::sigprocmask(SIG_BLOCK, &SIGTERM_SET, &mut old) != 0 || old != 0 {
+        kidneyos_syscalls::exit(0x100);
+    }
+
+    let sfd = kidneyos_syscalls::signalfd(-1, &SIGTERM_SET, SFD_NONBLOCK);
+
+    if sfd < 0 {
+        kidneyos_syscalls::exit(sfd);
+    }
+
+    let epfd = kidneyos_syscalls::epoll_create(1);
+
+    let event = EpollEvent {
+        events: EPOLLIN,
+        data: sfd as u64,
+    };
+
+    if epfd < 0 || kidneyos_syscalls::epoll_ctl(epfd, EPOLL_CTL_ADD, sfd, &event) != 0 {
+        kidneyos_syscalls::exit(0x200);
+    }
+
+    let mut events = [EpollEvent { events: 0, data: 0 }; 1];
+    let mut info = SignalfdSiginfo::default();
+
+    // Nothing has been sent yet.
+    if kidneyos_syscalls::epoll_wait(epfd, events.as_mut_ptr(), 1, 0) != 0 {
+        kidneyos_syscalls::exit(0x300);
+    }
+
+    if read_signal(sfd, &mut info) != -EAGAIN as i32 {
+        kidneyos_syscalls::exit(0x400);
+    }
+
+    let pid = kidneyos_syscalls::getpid();
+
+    if kidneyos_syscalls::kill(pid.into(), SIGTERM) != 0 {
+        kidneyos_syscalls::exit(0x500);
+    }
+
+    // Now the signalfd is ready.
+    if kidneyos_syscalls::epoll_wait(epfd, events.as_mut_ptr(), 1, -1) != 1
+        || events[0].events != EPOLLIN
+        || events[0].data != sfd as u64
+    {
+        kidneyos_syscalls::exit(0x600);
+    }
+
+    let read = read_signal(sfd, &mut info);
+
+    if read != core::mem::size_of::<SignalfdSiginfo>() as i32 || info.ssi_signo != SIGTERM as u32 {
+        kidneyos_syscalls::exit(0x700);
+    }
+
+    // Reading it took it, so it isn't pending any more.
+    if kidneyos_syscalls::epoll_wait(epfd, events.as_mut_ptr(), 1, 0) != 0
+        || read_signal(sfd, &mut info) != -EAGAIN as i32
+    {
+        kidneyos_syscalls::exit(0x800);
+    }
+
+    kidneyos_syscalls::exit(0);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
@@ -0,0 +1,74 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+// There's no fork or way to start another thread yet, so rather than having another process send
+// the signal while this one sleeps, this process sends SIGTERM to itself while it's blocked.
+// sigsuspend unblocks it, so it's delivered straight away rather than waking the process up.
+
+use core::ptr::null;
+use core::sync::atomic::{AtomicI32, AtomicU32, Ordering};
+use kidneyos_syscalls::{SigAction, SigSet, EINTR, SIGTERM, SIG_BLOCK};
+
+/// The signal the handler was called with, or 0 if it hasn't been.
+static HANDLED: AtomicI32 = AtomicI32::new(0);
+/// The signals which were blocked while the handler ran.
+static BLOCKED_IN_HANDLER: AtomicU32 = AtomicU32::new(0);
+
+const SIGTERM_SET: SigSet = 1 << (SIGTERM - 1);
+
+fn blocked() -> SigSet {
+    let mut set = 0;
+    kidneyos_syscalls::sigprocmask(SIG_BLOCK, null(), &mut set);
+    set
+}
+
+extern "C" fn handler(signal: i32) {
+    BLOCKED_IN_HANDLER.store(blocked(), Ordering::SeqCst);
+    HANDLED.store(signal, Ordering::SeqCst);
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    let action = SigAction {
+        sa_handler: handler as usize,
+        ..SigAction::default()
+    };
+
+    // SAFETY: the action is a valid SigAction.
+    if unsafe { kidneyos_syscalls::sigaction(SIGTERM, &action, core::ptr::null_mut()) } != 0
+        || kidneyos_syscalls::sigprocmask(SIG_BLOCK, &SIGTERM_SET, core::ptr::null_mut()) != 0
+    {
+        kidneyos_syscalls::exit(0x100);
+    }
+
+    // It's blocked, so it stays pending rather than calling the handler.
+    if kidneyos_syscalls::kill(kidneyos_syscalls::getpid().into(), SIGTERM) != 0
+        || HANDLED.load(Ordering::SeqCst) != 0
+    {
+        kidneyos_syscalls::exit(0x200);
+    }
+
+    if kidneyos_syscalls::sigsuspend(&0) != -EINTR as i32 {
+        kidneyos_syscalls::exit(0x300);
+    }
+
+    if HANDLED.load(Ordering::SeqCst) != SIGTERM {
+        kidneyos_syscalls::exit(0x400);
+    }
+
+    // The handler blocks SIGTERM, on top of sigsuspend's mask, and once it returns SIGTERM is
+    // blocked again as it was before sigsuspend.
+    if BLOCKED_IN_HANDLER.load(Ordering::SeqCst) != SIGTERM_SET || blocked() != SIGTERM_SET {
+        kidneyos_syscalls::exit(0x500);
+    }
+
+    kidneyos_syscalls::exit(0);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
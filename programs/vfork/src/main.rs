@@ -0,0 +1,103 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+use core::ffi::c_char;
+use core::hint::black_box;
+use core::sync::atomic::{AtomicU32, Ordering};
+use kidneyos_syscalls::{Pid, O_CREATE};
+
+// Exits with EXIT_CODE.
+const TARGET_PROGRAM: &[u8] =
+    include_bytes!("../../exit_code_child/target/i686-unknown-linux-gnu/release/exit_code_child");
+
+const TARGET_PATH: *const c_char = c"/exit_code_child".as_ptr();
+
+/// Has to match `EXIT_CODE` in the exit_code_child program.
+const EXIT_CODE: i32 = 42;
+
+/// What a child which exits without exec'ing exits with.
+const CHILD_EXIT_CODE: i32 = 7;
+
+/// How many children have run. They share this process' memory, so it sees them change it.
+static CHILDREN_RAN: AtomicU32 = AtomicU32::new(0);
+
+fn wait_for_exit_code(pid: Pid) -> Option<i32> {
+    let mut status = 0;
+
+    if kidneyos_syscalls::waitpid(pid, &mut status, 0) != pid
+        || !kidneyos_syscalls::wifexited(status)
+    {
+        return None;
+    }
+
+    Some(kidneyos_syscalls::wifexitstatus(status))
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    let fd = kidneyos_syscalls::open(TARGET_PATH, O_CREATE);
+
+    if fd < 0 || kidneyos_syscalls::write(fd, TARGET_PROGRAM.as_ptr(), TARGET_PROGRAM.len()) < 0 {
+        kidneyos_syscalls::exit(0x100);
+    }
+
+    kidneyos_syscalls::close(fd);
+
+    // Kept on the stack, which the child runs on too.
+    let canary = black_box([0x5a_u8; 64]);
+
+    // SAFETY: the child only execs or exits.
+    let pid = unsafe { kidneyos_syscalls::vfork() };
+
+    if pid == 0 {
+        CHILDREN_RAN.fetch_add(1, Ordering::SeqCst);
+
+        let argv = [TARGET_PATH, core::ptr::null()];
+        let envp = [core::ptr::null()];
+
+        // Only returns if it fails.
+        kidneyos_syscalls::execve(TARGET_PATH, argv.as_ptr(), envp.as_ptr());
+
+        kidneyos_syscalls::exit(0x200);
+    }
+
+    // This process was suspended until the child exec'd.
+    if CHILDREN_RAN.load(Ordering::SeqCst) != 1 {
+        kidneyos_syscalls::exit(0x300);
+    }
+
+    if black_box(canary) != [0x5a; 64] {
+        kidneyos_syscalls::exit(0x400);
+    }
+
+    if wait_for_exit_code(pid) != Some(EXIT_CODE) {
+        kidneyos_syscalls::exit(0x500);
+    }
+
+    // SAFETY: the child only exits.
+    let pid = unsafe { kidneyos_syscalls::vfork() };
+
+    if pid == 0 {
+        CHILDREN_RAN.fetch_add(1, Ordering::SeqCst);
+        kidneyos_syscalls::exit(CHILD_EXIT_CODE);
+    }
+
+    // Or until it exited.
+    if CHILDREN_RAN.load(Ordering::SeqCst) != 2 || black_box(canary) != [0x5a; 64] {
+        kidneyos_syscalls::exit(0x600);
+    }
+
+    if wait_for_exit_code(pid) != Some(CHILD_EXIT_CODE) {
+        kidneyos_syscalls::exit(0x700);
+    }
+
+    kidneyos_syscalls::exit(0);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
@@ -0,0 +1,92 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+
+// There's no fork yet, so this process has no children of its own to wait for. It checks that
+// waitid rejects what it should, and, as with the waitpid test, a process to peek at with WNOWAIT
+// and then reap has to be hard coded in CHILD.
+
+use kidneyos_syscalls::{
+    Pid, SigInfo, CLD_EXITED, CLD_KILLED, ECHILD, EINVAL, P_PID, SIGCHLD, WEXITED, WNOWAIT,
+};
+
+/// The pid of a process which has exited but hasn't been waited on, if there is one.
+const CHILD: Option<Pid> = None;
+
+/// `waitid` option Linux has which isn't supported.
+const WNOHANG: i32 = 1;
+
+fn wait(idtype: i32, id: Pid, info: &mut SigInfo, options: i32) -> i32 {
+    kidneyos_syscalls::waitid(idtype, id, info, options)
+}
+
+fn peek_then_reap(child: Pid) {
+    let mut peeked = SigInfo::default();
+
+    if wait(P_PID, child, &mut peeked, WEXITED | WNOWAIT) != 0 {
+        kidneyos_syscalls::exit(0x500);
+    }
+
+    if peeked.si_signo != SIGCHLD
+        || peeked.si_pid != child
+        || !matches!(peeked.si_code, CLD_EXITED | CLD_KILLED)
+    {
+        kidneyos_syscalls::exit(0x600);
+    }
+
+    // Peeking left it to be reaped, with the same result.
+    let mut reaped = SigInfo::default();
+
+    if wait(P_PID, child, &mut reaped, WEXITED) != 0 {
+        kidneyos_syscalls::exit(0x700);
+    }
+
+    if reaped.si_code != peeked.si_code || reaped.si_status != peeked.si_status {
+        kidneyos_syscalls::exit(0x800);
+    }
+
+    // And now it's gone.
+    if wait(P_PID, child, &mut reaped, WEXITED) != -ECHILD as i32 {
+        kidneyos_syscalls::exit(0x900);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    let mut info = SigInfo::default();
+
+    // Only waiting for a process by pid to exit is supported.
+    let me = kidneyos_syscalls::getpid();
+
+    if wait(0, me, &mut info, WEXITED) != -EINVAL as i32 {
+        kidneyos_syscalls::exit(0x100);
+    }
+
+    if wait(P_PID, me, &mut info, WNOWAIT) != -EINVAL as i32
+        || wait(P_PID, me, &mut info, WEXITED | WNOHANG) != -EINVAL as i32
+    {
+        kidneyos_syscalls::exit(0x200);
+    }
+
+    // A process can't wait for itself, or one which doesn't exist.
+    if wait(P_PID, me, &mut info, WEXITED) != -ECHILD as i32 {
+        kidneyos_syscalls::exit(0x300);
+    }
+
+    if wait(P_PID, Pid::MAX, &mut info, WEXITED | WNOWAIT) != -ECHILD as i32 {
+        kidneyos_syscalls::exit(0x400);
+    }
+
+    if let Some(child) = CHILD {
+        peek_then_reap(child);
+    }
+
+    kidneyos_syscalls::exit(0);
+
+    loop {}
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
@@ -3,7 +3,7 @@
 
 use crate::{
     segment::{SegmentDescriptor, SegmentSelector},
-    task_state_segment::{TaskStateSegment, TASK_STATE_SEGMENT},
+    task_state_segment::{TaskStateSegment, DOUBLE_FAULT_TASK_STATE_SEGMENT, TASK_STATE_SEGMENT},
 };
 use core::{arch::asm, mem::size_of, ptr::addr_of};
 
@@ -14,7 +14,7 @@ struct GDTDescriptor {
     offset: u32,
 }
 
-const GDT_LEN: usize = 6;
+const GDT_LEN: usize = 7;
 
 static mut GDT: [SegmentDescriptor; GDT_LEN] = [
     // Null Descriptor
@@ -56,6 +56,12 @@ static mut GDT: [SegmentDescriptor; GDT_LEN] = [
         .with_executable(true)
         .with_limit(size_of::<TaskStateSegment>() as u32 - 1)
         .with_present(true),
+    // Double fault task
+    SegmentDescriptor::default()
+        .with_accessed(true)
+        .with_executable(true)
+        .with_limit(size_of::<TaskStateSegment>() as u32 - 1)
+        .with_present(true),
 ];
 
 pub const KERNEL_CODE_SELECTOR: u16 = SegmentSelector::default().with_index(1).load();
@@ -72,6 +78,11 @@ const TSS_INDEX: usize = 5;
 const TSS_SELECTOR: u16 = SegmentSelector::default()
     .with_index(TSS_INDEX as u16)
     .load();
+const DOUBLE_FAULT_TSS_INDEX: usize = 6;
+/// Selector for [`DOUBLE_FAULT_TASK_STATE_SEGMENT`], for the double fault handler's task gate.
+pub const DOUBLE_FAULT_TSS_SELECTOR: u16 = SegmentSelector::default()
+    .with_index(DOUBLE_FAULT_TSS_INDEX as u16)
+    .load();
 
 static mut GDT_DESCRIPTOR: GDTDescriptor = GDTDescriptor {
     size: size_of::<[SegmentDescriptor; GDT_LEN]>() as u16 - 1,
@@ -84,6 +95,8 @@ static mut GDT_DESCRIPTOR: GDTDescriptor = GDTDescriptor {
 /// they are above in GDT.
 pub unsafe fn load() {
     GDT[TSS_INDEX] = GDT[TSS_INDEX].with_base(addr_of!(TASK_STATE_SEGMENT).cast::<u8>() as u32);
+    GDT[DOUBLE_FAULT_TSS_INDEX] = GDT[DOUBLE_FAULT_TSS_INDEX]
+        .with_base(addr_of!(DOUBLE_FAULT_TASK_STATE_SEGMENT).cast::<u8>() as u32);
     GDT_DESCRIPTOR.offset = GDT.as_ptr() as u32;
 
     // We need to use att_syntax since Rust doesn't appear to understand Intel long jump syntax...
@@ -0,0 +1,136 @@
+use core::fmt;
+
+/// Size of the kernel log in bytes. Once it's full, the oldest output is overwritten.
+pub const KERNEL_LOG_SIZE: usize = 16 * 1024;
+
+/// Ring buffer holding the most recent kernel output, so that userspace can read it back with
+/// the `syslog` syscall (as `dmesg` does).
+pub struct KernelLog {
+    buf: [u8; KERNEL_LOG_SIZE],
+    /// Where the next byte of output goes
+    end: usize,
+    /// How many bytes of output the buffer holds, up to [`KERNEL_LOG_SIZE`]
+    len: usize,
+}
+
+impl KernelLog {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; KERNEL_LOG_SIZE],
+            end: 0,
+            len: 0,
+        }
+    }
+
+    /// Number of bytes of output held.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Forget everything logged so far.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Copy the most recent output into `out`, oldest first, returning how many bytes were
+    /// copied. If the output doesn't all fit, the oldest part is left out.
+    pub fn read_recent(&self, out: &mut [u8]) -> usize {
+        let len = self.len.min(out.len());
+        let start = (self.end + KERNEL_LOG_SIZE - len) % KERNEL_LOG_SIZE;
+        // The output either runs straight through, or wraps around to the start of the buffer.
+        let first = len.min(KERNEL_LOG_SIZE - start);
+        out[..first].copy_from_slice(&self.buf[start..start + first]);
+        out[first..len].copy_from_slice(&self.buf[..len - first]);
+        len
+    }
+}
+
+impl Default for KernelLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Write for KernelLog {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut bytes = s.as_bytes();
+        // Only the end of anything longer than the whole buffer would survive anyway.
+        if bytes.len() > KERNEL_LOG_SIZE {
+            bytes = &bytes[bytes.len() - KERNEL_LOG_SIZE..];
+        }
+        let first = bytes.len().min(KERNEL_LOG_SIZE - self.end);
+        self.buf[self.end..self.end + first].copy_from_slice(&bytes[..first]);
+        self.buf[..bytes.len() - first].copy_from_slice(&bytes[first..]);
+        self.end = (self.end + bytes.len()) % KERNEL_LOG_SIZE;
+        self.len = (self.len + bytes.len()).min(KERNEL_LOG_SIZE);
+        Ok(())
+    }
+}
+
+/// Everything printed with the `print!` family of macros.
+pub static mut KERNEL_LOG: KernelLog = KernelLog::new();
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use super::*;
+    use core::fmt::Write;
+
+    fn read_all(log: &KernelLog) -> ([u8; KERNEL_LOG_SIZE], usize) {
+        let mut out = [0; KERNEL_LOG_SIZE];
+        let len = log.read_recent(&mut out);
+        (out, len)
+    }
+
+    #[test]
+    fn lines_come_back_in_order() {
+        let mut log = KernelLog::new();
+        for i in 0..3 {
+            writeln!(log, "line {i}").unwrap();
+        }
+        let (out, len) = read_all(&log);
+        assert_eq!(&out[..len], b"line 0\nline 1\nline 2\n");
+
+        // only the most recent output if it doesn't all fit
+        let mut out = [0; 7];
+        assert_eq!(log.read_recent(&mut out), 7);
+        assert_eq!(&out, b"line 2\n");
+
+        log.clear();
+        assert!(log.is_empty());
+        assert_eq!(read_all(&log).1, 0);
+    }
+
+    #[test]
+    fn wraps_around() {
+        let mut log = KernelLog::new();
+        let line = "0123456789abcdef\n";
+        let lines = KERNEL_LOG_SIZE / line.len() + 10;
+        for _ in 0..lines {
+            log.write_str(line).unwrap();
+        }
+        assert_eq!(log.len(), KERNEL_LOG_SIZE);
+        let (out, len) = read_all(&log);
+        assert_eq!(len, KERNEL_LOG_SIZE);
+        // the newest output is at the end, with the oldest overwritten
+        let expected = line.repeat(lines);
+        assert_eq!(
+            &out[..],
+            &expected.as_bytes()[expected.len() - KERNEL_LOG_SIZE..]
+        );
+
+        // output longer than the whole buffer
+        let long = "x".repeat(KERNEL_LOG_SIZE + 5);
+        log.write_str(&long).unwrap();
+        log.write_str("end").unwrap();
+        let (out, len) = read_all(&log);
+        assert_eq!(len, KERNEL_LOG_SIZE);
+        assert!(out[..len - 3].iter().all(|&byte| byte == b'x'));
+        assert_eq!(&out[len - 3..], b"end");
+    }
+}
@@ -7,6 +7,7 @@
 
 pub mod bit_array;
 pub mod global_descriptor_table;
+pub mod klog;
 pub mod macros;
 pub mod mem;
 pub mod paging;
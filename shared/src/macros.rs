@@ -14,6 +14,7 @@ macro_rules! print {
         unsafe {
             write!($crate::video_memory::VIDEO_MEMORY_WRITER, "{}", format_args!($($arg)*)).unwrap();
             write!($crate::serial::SERIAL_WRITER, "{}", format_args!($($arg)*)).unwrap();
+            write!($crate::klog::KERNEL_LOG, "{}", format_args!($($arg)*)).unwrap();
         }
     }};
 }
@@ -26,17 +27,19 @@ macro_rules! println {
         unsafe {
             write!($crate::video_memory::VIDEO_MEMORY_WRITER, "\n").unwrap();
             write!($crate::serial::SERIAL_WRITER, "\n").unwrap();
+            write!($crate::klog::KERNEL_LOG, "\n").unwrap();
         }
     }};
     ($($arg:tt)*) => {{
         use core::fmt::Write;
-        use $crate::{serial::SERIAL_WRITER, video_memory::VIDEO_MEMORY_WRITER};
+        use $crate::{klog::KERNEL_LOG, serial::SERIAL_WRITER, video_memory::VIDEO_MEMORY_WRITER};
         // SAFETY: Single core, no interrupts.
         unsafe {
             write!(VIDEO_MEMORY_WRITER, "{}", format_args!($($arg)*)).unwrap();
             write!(VIDEO_MEMORY_WRITER, "\n").unwrap();
             write!(SERIAL_WRITER, "{}", format_args!($($arg)*)).unwrap();
             write!(SERIAL_WRITER, "\n").unwrap();
+            write!(KERNEL_LOG, "{}\n", format_args!($($arg)*)).unwrap();
         }
     }};
 }
@@ -53,6 +56,7 @@ macro_rules! eprint {
             write!(VIDEO_MEMORY_WRITER, "{}", format_args!($($arg)*)).unwrap();
             VIDEO_MEMORY_WRITER.attribute = prev_attribute;
             write!($crate::serial::SERIAL_WRITER, "{}", format_args!($($arg)*)).unwrap();
+            write!($crate::klog::KERNEL_LOG, "{}", format_args!($($arg)*)).unwrap();
         }
     }};
 }
@@ -65,11 +69,12 @@ macro_rules! eprintln {
         unsafe {
             write!($crate::video_memory::VIDEO_MEMORY_WRITER, "\n").unwrap();
             write!($crate::serial::SERIAL_WRITER, "\n").unwrap();
+            write!($crate::klog::KERNEL_LOG, "\n").unwrap();
         }
     }};
     ($($arg:tt)*) => {{
         use core::fmt::Write;
-        use $crate::{serial::SERIAL_WRITER, video_memory::{Attribute, Colour, VIDEO_MEMORY_WRITER}};
+        use $crate::{klog::KERNEL_LOG, serial::SERIAL_WRITER, video_memory::{Attribute, Colour, VIDEO_MEMORY_WRITER}};
         // SAFETY: Single core, no interrupts.
         unsafe {
             let prev_attribute = VIDEO_MEMORY_WRITER.attribute;
@@ -79,6 +84,7 @@ macro_rules! eprintln {
             VIDEO_MEMORY_WRITER.attribute = prev_attribute;
             write!(SERIAL_WRITER, "{}", format_args!($($arg)*)).unwrap();
             write!(SERIAL_WRITER, "\n").unwrap();
+            write!(KERNEL_LOG, "{}\n", format_args!($($arg)*)).unwrap();
         }
     }};
 }
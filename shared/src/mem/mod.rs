@@ -69,6 +69,10 @@ pub mod virt {
 // Any virtual address at or above OFFSET is a kernel address.
 pub const OFFSET: usize = 0x80000000;
 
+/// The last 4MB of virtual memory aren't mapped by `kernel_mapping_ranges`, so that the kernel can
+/// map device memory (e.g. the APIC's registers) there.
+pub const DEVICE_MEMORY_START: usize = usize::MAX - HUGE_PAGE_SIZE + 1;
+
 // TODO: Figure out how to detect kernel stack overflows.
 pub const MAIN_STACK_SIZE: usize = 2 * MB;
 pub const TRAMPOLINE_HEAP_SIZE: usize = 8 * MB;
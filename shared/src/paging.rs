@@ -6,7 +6,7 @@ use crate::{
     bitfield,
     mem::{
         phys::{kernel_data_start, kernel_end, kernel_start, main_stack_top, trampoline_heap_top},
-        virt, HUGE_PAGE_SIZE, OFFSET, PAGE_FRAME_SIZE,
+        virt, DEVICE_MEMORY_START, HUGE_PAGE_SIZE, OFFSET, PAGE_FRAME_SIZE,
     },
     video_memory::{VIDEO_MEMORY_BASE, VIDEO_MEMORY_SIZE},
 };
@@ -137,6 +137,8 @@ pub struct PageManager<A: Allocator> {
     root: NonNull<PageDirectory>,
     alloc: A,
     phys_to_alloc_addr_offset: usize,
+    /// Whether the page tables belong to another `PageManager`, so aren't freed with this one.
+    borrowed: bool,
 }
 
 const PAGE_DIRECTORY_LAYOUT: Layout = Layout::new::<PageDirectory>();
@@ -155,6 +157,23 @@ impl<A: Allocator> PageManager<A> {
             root,
             alloc,
             phys_to_alloc_addr_offset: alloc_addr_to_phys_offset,
+            borrowed: false,
+        }
+    }
+
+    /// Returns a `PageManager` for the same page tables as this one, so that mappings made
+    /// through either are seen by both. The page tables aren't freed when it's dropped.
+    ///
+    /// # Safety
+    ///
+    /// The returned `PageManager` must not be used once this one has been dropped.
+    pub unsafe fn borrow(&self) -> Self
+    where
+        A: Copy,
+    {
+        Self {
+            borrowed: true,
+            ..*self
         }
     }
 
@@ -376,6 +395,99 @@ impl<A: Allocator> PageManager<A> {
         }
     }
 
+    /// Removes the mapping for the page starting at `virt_addr`, which must be
+    /// page-frame-aligned, and returns the physical address it was mapped to.
+    /// Returns `None` if the page was not mapped, or is part of a huge page.
+    ///
+    /// The TLB entry for `virt_addr` is invalidated, so if these page tables
+    /// are loaded the mapping stops taking effect immediately.
+    ///
+    /// # Safety
+    ///
+    /// There must be no remaining pointers into the unmapped page.
+    pub unsafe fn unmap(&mut self, virt_addr: usize) -> Option<usize> {
+        assert_eq!(
+            virt_addr % PAGE_FRAME_SIZE,
+            0,
+            "virt_addr was not page-frame-aligned"
+        );
+
+        let page_directory = self.root.as_mut();
+        let (pdi, pti) = virt_parts(virt_addr);
+
+        if !page_directory[pdi].present() || page_directory[pdi].page_size() {
+            return None;
+        }
+
+        let page_table = &mut *page_directory.page_table(pdi, self.phys_to_alloc_addr_offset);
+        if !page_table[pti].present() {
+            return None;
+        }
+
+        let phys_addr = page_table[pti].page_table_frame() as usize * PAGE_FRAME_SIZE;
+        page_table[pti] = PageTableEntry::default();
+        asm!("invlpg [{}]", in(reg) virt_addr, options(nostack));
+
+        Some(phys_addr)
+    }
+
+    /// If the page at `virt_addr` has been written to since it was mapped, or since the last call
+    /// to this, clears its dirty bit and returns the physical address it's mapped to.
+    ///
+    /// Huge pages are never reported as dirty.
+    pub fn take_dirty(&mut self, virt_addr: usize) -> Option<usize> {
+        assert_eq!(
+            virt_addr % PAGE_FRAME_SIZE,
+            0,
+            "virt_addr was not page-frame-aligned"
+        );
+
+        // SAFETY: The page directory and tables are owned by this PageManager.
+        let page_directory = unsafe { self.root.as_mut() };
+        let (pdi, pti) = virt_parts(virt_addr);
+
+        if !page_directory[pdi].present() || page_directory[pdi].page_size() {
+            return None;
+        }
+
+        let page_table =
+            unsafe { &mut *page_directory.page_table(pdi, self.phys_to_alloc_addr_offset) };
+        if !page_table[pti].present() || !page_table[pti].dirty() {
+            return None;
+        }
+
+        page_table[pti] = page_table[pti].with_dirty(false);
+        // The TLB caches the dirty bit, so without this, later writes wouldn't set it again.
+        unsafe { asm!("invlpg [{}]", in(reg) virt_addr, options(nostack)) };
+
+        Some(page_table[pti].page_table_frame() as usize * PAGE_FRAME_SIZE)
+    }
+
+    /// Set the dirty bit of the page at `virt_addr` again, after [`take_dirty`](Self::take_dirty)
+    /// if what it returned couldn't be written back after all. Does nothing if the page isn't
+    /// mapped.
+    pub fn set_dirty(&mut self, virt_addr: usize) {
+        assert_eq!(
+            virt_addr % PAGE_FRAME_SIZE,
+            0,
+            "virt_addr was not page-frame-aligned"
+        );
+
+        // SAFETY: The page directory and tables are owned by this PageManager.
+        let page_directory = unsafe { self.root.as_mut() };
+        let (pdi, pti) = virt_parts(virt_addr);
+
+        if !page_directory[pdi].present() || page_directory[pdi].page_size() {
+            return;
+        }
+
+        let page_table =
+            unsafe { &mut *page_directory.page_table(pdi, self.phys_to_alloc_addr_offset) };
+        if page_table[pti].present() {
+            page_table[pti] = page_table[pti].with_dirty(true);
+        }
+    }
+
     /// Like `map_range` except phys_start and virt_start are both `start`.
     ///
     /// # Safety
@@ -418,6 +530,33 @@ impl<A: Allocator> PageManager<A> {
         !write || entry.read_write()
     }
 
+    /// Returns the physical address `pointer` is mapped to, or `None` if it isn't mapped.
+    pub fn translate(&self, pointer: usize) -> Option<usize> {
+        let (pdi, pti) = virt_parts(pointer);
+
+        let page_directory = unsafe { self.root.as_ref() };
+
+        let entry = &page_directory.0[pdi];
+
+        if !entry.present() {
+            return None;
+        }
+
+        if entry.page_size() {
+            // Huge page
+            let frame = entry.page_table_frame() as usize * PAGE_FRAME_SIZE;
+            return Some(frame + pointer % HUGE_PAGE_SIZE);
+        }
+
+        let page_table =
+            unsafe { &*page_directory.page_table(pdi, self.phys_to_alloc_addr_offset) };
+        let entry = &page_table.0[pti];
+        if !entry.present() {
+            return None;
+        }
+        Some(entry.page_table_frame() as usize * PAGE_FRAME_SIZE + pointer % PAGE_FRAME_SIZE)
+    }
+
     /// Returns whether `pointer..pointer+count` is valid for reads if `write = false`, and writes if `write = true`.
     pub fn can_access_range(&self, pointer: usize, count: usize, write: bool) -> bool {
         let Some(end) = pointer.checked_add(count) else {
@@ -464,12 +603,20 @@ impl<A: Allocator + Copy> Clone for PageManager<A> {
         let mut root = root_addr.cast::<PageDirectory>();
         unsafe { *root.as_mut() = (*self.root.as_ptr()).clone() };
 
-        Self { root, ..*self }
+        Self {
+            root,
+            borrowed: false,
+            ..*self
+        }
     }
 }
 
 impl<A: Allocator> Drop for PageManager<A> {
     fn drop(&mut self) {
+        if self.borrowed {
+            return;
+        }
+
         assert!(!self.is_loaded(), "page manager dropped while still loaded");
 
         for pde in unsafe { &self.root.as_ref().0 } {
@@ -637,7 +784,8 @@ pub fn kernel_mapping_ranges() -> [MappingRange; 5] {
         MappingRange {
             phys_start: trampoline_heap_top(),
             virt_start: virt::trampoline_heap_top(),
-            len: (usize::MAX - OFFSET - trampoline_heap_top()).next_multiple_of(PAGE_FRAME_SIZE),
+            len: (DEVICE_MEMORY_START - OFFSET - trampoline_heap_top())
+                .next_multiple_of(PAGE_FRAME_SIZE),
             write: true,
             user: false,
         },
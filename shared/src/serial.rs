@@ -4,14 +4,22 @@ pub struct SerialWriter {
     initialized: bool,
 }
 
+/// The I/O port base of COM1, which the kernel logs to.
 const IO_BASE: u16 = 0x3f8;
-const RBR: u16 = IO_BASE; // Receiver Buffer Reg (read-only)
-const THR: u16 = IO_BASE; // Transmitter Holding Reg (write-only)
-const IER: u16 = IO_BASE + 1; // Interrupt Enable Reg
-const FCR: u16 = IO_BASE + 2; // FIFO Control Reg (write-only)
-const LCR: u16 = IO_BASE + 3; // Line Control Register
-const MCR: u16 = IO_BASE + 4; // MODEM Control Register
-const LSR: u16 = IO_BASE + 5; // Line Status Register (read-only)
+/// The I/O port base of COM2.
+pub const COM2_BASE: u16 = 0x2f8;
+
+// Registers, as offsets from a port's base
+const RBR: u16 = 0; // Receiver Buffer Reg (read-only)
+const THR: u16 = 0; // Transmitter Holding Reg (write-only)
+const IER: u16 = 1; // Interrupt Enable Reg
+const FCR: u16 = 2; // FIFO Control Reg (write-only)
+const LCR: u16 = 3; // Line Control Register
+const MCR: u16 = 4; // MODEM Control Register
+const LSR: u16 = 5; // Line Status Register (read-only)
+
+/// Sent through a port in loopback mode when it's set up, to check it works.
+const LOOPBACK_TEST_BYTE: u8 = 0xAE;
 
 /// # Safety
 ///
@@ -29,6 +37,22 @@ pub unsafe fn inb(port: u16) -> u8 {
     res
 }
 
+/// # Safety
+///
+/// Wrapper for the assembly function out, with a 32-bit value.
+pub unsafe fn outl(port: u16, value: u32) {
+    asm!("out dx, eax", in("dx") port, in("eax") value)
+}
+
+/// # Safety
+///
+/// Wrapper for the assembly function in, with a 32-bit value.
+pub unsafe fn inl(port: u16) -> u32 {
+    let res: u32;
+    asm!("in eax, dx", in("dx") port, out("eax") res);
+    res
+}
+
 /// Wrapper for assembly function insw - input from port to string.
 ///
 /// Input word from I/O port specified in DX into memory location specified in ES:EDI.
@@ -79,6 +103,72 @@ pub unsafe fn outsw(port: u16, buffer: *const u8, count: usize) {
     );
 }
 
+/// A serial port which is read and written a byte at a time by polling, e.g. for a debugger
+/// connection.
+pub struct SerialPort {
+    base: u16,
+}
+
+impl SerialPort {
+    /// The serial port at I/O port `base`.
+    pub const fn new(base: u16) -> Self {
+        Self { base }
+    }
+
+    /// Set the port up, checking it works by sending a byte through it in loopback mode. Returns
+    /// the byte which came back instead, if it didn't.
+    ///
+    /// # Safety
+    ///
+    /// Nothing else may be using the port.
+    pub unsafe fn init(&self) -> Result<(), u8> {
+        let base = self.base;
+        // https://wiki.osdev.org/Serial_Ports#Initialization
+
+        outb(base + IER, 0x00);
+        outb(base + LCR, 0x80);
+        outb(base + THR, 0x03);
+        outb(base + IER, 0x00);
+        outb(base + LCR, 0x03);
+        outb(base + FCR, 0xC7);
+        outb(base + MCR, 0x0B);
+
+        outb(base + MCR, 0x1E); // Enable loopback.
+
+        // Confirm that serial is working by writing a byte and reading it
+        // back.
+        outb(base + THR, LOOPBACK_TEST_BYTE);
+        let actual = inb(base + RBR);
+
+        outb(base + MCR, 0x0F); // Disable loopback.
+
+        if actual != LOOPBACK_TEST_BYTE {
+            return Err(actual);
+        }
+        Ok(())
+    }
+
+    /// Wait for a byte to come in, and return it.
+    ///
+    /// # Safety
+    ///
+    /// The port must have been set up with `init`.
+    pub unsafe fn read_byte(&self) -> u8 {
+        while inb(self.base + LSR) & 0x01 == 0 {}
+        inb(self.base + RBR)
+    }
+
+    /// Wait until the port's ready, then send `byte`.
+    ///
+    /// # Safety
+    ///
+    /// The port must have been set up with `init`.
+    pub unsafe fn write_byte(&self, byte: u8) {
+        while inb(self.base + LSR) & 0x20 == 0 {}
+        outb(self.base + THR, byte);
+    }
+}
+
 impl SerialWriter {
     fn ensure_initialized(&mut self) {
         if self.initialized {
@@ -86,33 +176,11 @@ impl SerialWriter {
         }
 
         // SAFETY: Follows the correct proceedure for initializing serial ports.
-        unsafe {
-            // https://wiki.osdev.org/Serial_Ports#Initialization
-
-            outb(IER, 0x00);
-            outb(LCR, 0x80);
-            outb(THR, 0x03);
-            outb(IER, 0x00);
-            outb(LCR, 0x03);
-            outb(FCR, 0xC7);
-            outb(MCR, 0x0B);
-
-            outb(MCR, 0x1E); // Enable loopback.
-
-            // Confirm that serial is working by writing a byte and reading it
-            // back.
-            const EXPECTED: u8 = 0xAE;
-            outb(THR, EXPECTED);
-            let actual = inb(RBR);
-            assert_eq!(
-                actual, EXPECTED,
-                "faulty serial, expected {EXPECTED:#X}, got {actual:#X}"
-            );
-
-            outb(MCR, 0x0F); // Disable loopback.
-
-            self.initialized = true;
+        if let Err(actual) = unsafe { SerialPort::new(IO_BASE).init() } {
+            panic!("faulty serial, expected {LOOPBACK_TEST_BYTE:#X}, got {actual:#X}");
         }
+
+        self.initialized = true;
     }
 }
 
@@ -122,12 +190,10 @@ impl fmt::Write for SerialWriter {
 
         self.ensure_initialized();
 
+        let port = SerialPort::new(IO_BASE);
         for b in s.bytes() {
             // SAFETY: Correctly waits before outputting byte to serial port.
-            unsafe {
-                while inb(LSR) & 0x20 == 0 {}
-                outb(THR, b);
-            }
+            unsafe { port.write_byte(b) };
         }
 
         Ok(())
@@ -1,6 +1,6 @@
 use core::mem::{size_of, transmute};
 
-use crate::global_descriptor_table::KERNEL_DATA_SELECTOR;
+use crate::global_descriptor_table::{KERNEL_CODE_SELECTOR, KERNEL_DATA_SELECTOR};
 
 #[allow(unused)]
 #[repr(C, packed)]
@@ -45,10 +45,60 @@ pub struct TaskStateSegment {
     pub ssp: u32,
 }
 
+impl TaskStateSegment {
+    const fn zeroed() -> Self {
+        let mut tss: TaskStateSegment = unsafe { transmute([0_u8; size_of::<TaskStateSegment>()]) };
+        tss.iopb = size_of::<TaskStateSegment>() as u16;
+        tss
+    }
+
+    /// A task which starts running `entry` in the kernel, on the stack ending at `stack_top`,
+    /// using the page directory at physical address `cr3`, with interrupts disabled.
+    pub const fn kernel_task(entry: u32, stack_top: u32, cr3: u32) -> Self {
+        let mut tss = Self::zeroed();
+        tss.eip = entry;
+        tss.esp = stack_top;
+        tss.cr3 = cr3;
+        // just the reserved bit, so interrupts are off
+        tss.eflags = 0x2;
+        tss.cs = KERNEL_CODE_SELECTOR;
+        tss.ss = KERNEL_DATA_SELECTOR;
+        tss.ds = KERNEL_DATA_SELECTOR;
+        tss.es = KERNEL_DATA_SELECTOR;
+        tss.fs = KERNEL_DATA_SELECTOR;
+        tss.gs = KERNEL_DATA_SELECTOR;
+        tss
+    }
+}
+
 pub static mut TASK_STATE_SEGMENT: TaskStateSegment = {
     // Initialize zeroed TSS and set only the relevant fields.
-    let mut tss: TaskStateSegment = unsafe { transmute([0_u8; size_of::<TaskStateSegment>()]) };
+    let mut tss = TaskStateSegment::zeroed();
     tss.ss0 = KERNEL_DATA_SELECTOR;
-    tss.iopb = size_of::<TaskStateSegment>() as u16;
     tss
 };
+
+/// The task double faults switch to, through a task gate, so that they're handled on a stack
+/// which is known to be good, even if the fault was caused by running out of kernel stack. (32-bit
+/// x86 has no interrupt stack table, which is how this is done in 64-bit mode.)
+///
+/// When it runs, the state of the task which faulted is in [`TASK_STATE_SEGMENT`].
+pub static mut DOUBLE_FAULT_TASK_STATE_SEGMENT: TaskStateSegment = TaskStateSegment::zeroed();
+
+#[cfg(test)]
+mod test {
+    use super::TaskStateSegment;
+    use crate::global_descriptor_table::{KERNEL_CODE_SELECTOR, KERNEL_DATA_SELECTOR};
+
+    #[test]
+    fn kernel_task() {
+        let tss = TaskStateSegment::kernel_task(0x8010_0000, 0x8020_0000, 0x3000);
+        assert_eq!({ tss.eip }, 0x8010_0000);
+        assert_eq!({ tss.esp }, 0x8020_0000);
+        assert_eq!({ tss.cr3 }, 0x3000);
+        assert_eq!({ tss.cs }, KERNEL_CODE_SELECTOR);
+        assert_eq!({ tss.ss }, KERNEL_DATA_SELECTOR);
+        // interrupts are off
+        assert_eq!({ tss.eflags } & (1 << 9), 0);
+    }
+}
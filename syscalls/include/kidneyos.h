@@ -8,24 +8,96 @@
 #ifndef KIDNEYOS_SYSCALLS_H
 #define KIDNEYOS_SYSCALLS_H
 
+#include <stdbool.h>
 #include <stdint.h>
 
 #define O_CREATE 64
 
+/**
+ * With `O_CREATE`, fail with `EEXIST` if the file already exists, rather than opening it.
+ */
+#define O_EXCL 128
+
+/**
+ * Special directory file descriptor for `*at` syscalls, meaning the working directory.
+ */
+#define AT_FDCWD -100
+
+/**
+ * `*at` syscall flag to operate on the directory file descriptor itself if the path is empty.
+ */
+#define AT_EMPTY_PATH 4096
+
+/**
+ * `unlinkat` flag to remove a directory, as `rmdir` does, rather than a file.
+ */
+#define AT_REMOVEDIR 512
+
+/**
+ * Maximum length of a path passed to a syscall, including the null terminator.
+ */
+#define PATH_MAX 4096
+
 #define SEEK_SET 0
 
 #define SEEK_CUR 1
 
 #define SEEK_END 2
 
+/**
+ * `getrlimit`/`setrlimit` resource for the number of open files (one more than the highest file
+ * descriptor a process can open).
+ */
+#define RLIMIT_NOFILE 7
+
+/**
+ * `getrlimit`/`setrlimit` resource for CPU time, in seconds. A process which uses more than its
+ * soft limit is killed, exiting with 128 + 24, as if by SIGXCPU.
+ */
+#define RLIMIT_CPU 0
+
+/**
+ * `getrlimit`/`setrlimit` resource for the size of a process' address space in bytes: the sum of
+ * the sizes of its stack, heap and memory mappings, but not the program itself. Mapping memory
+ * (or growing the heap) past the soft limit fails. The hard limit is always `RLIM_INFINITY`.
+ */
+#define RLIMIT_AS 9
+
+
+
+/**
+ * `getrusage` target for the calling process, including its threads which have exited.
+ */
+#define RUSAGE_SELF 0
+
+/**
+ * `getrusage` target for just the calling thread.
+ */
+#define RUSAGE_THREAD 1
+
+#define EPERM 1
+
 #define ENOENT 2
 
+#define ESRCH 3
+
+#define EINTR 4
+
 #define EIO 5
 
 #define ENOEXEC 8
 
 #define EBADF 9
 
+#define ECHILD 10
+
+#define EAGAIN 11
+
+/**
+ * The same as `EAGAIN`, as on Linux.
+ */
+#define EWOULDBLOCK EAGAIN
+
 #define ENOMEM 12
 
 #define EFAULT 14
@@ -46,6 +118,8 @@
 
 #define EMFILE 24
 
+#define ENOTTY 25
+
 #define ENOSPC 28
 
 #define ESPIPE 29
@@ -58,12 +132,21 @@
 
 #define ERANGE 34
 
+#define EDEADLK 35
+
+#define ENAMETOOLONG 36
+
 #define ENOSYS 38
 
 #define ENOTEMPTY 39
 
 #define ELOOP 40
 
+/**
+ * The operation isn't supported, e.g. by the file system. The same as `ENOTSUP`, as on Linux.
+ */
+#define EOPNOTSUPP 95
+
 #define SYS_EXIT 1
 
 #define SYS_FORK 2
@@ -92,8 +175,14 @@
 
 #define SYS_UNMOUNT 22
 
+#define SYS_PAUSE 29
+
+#define SYS_NICE 34
+
 #define SYS_SYNC 36
 
+#define SYS_KILL 37
+
 #define SYS_RENAME 38
 
 #define SYS_MKDIR 39
@@ -104,48 +193,415 @@
 
 #define SYS_PIPE 42
 
+#define SYS_BRK 45
+
+#define SYS_IOCTL 54
+
+#define SYS_SETPGID 57
+
+#define SYS_CHROOT 61
+
 #define SYS_DUP2 63
 
 #define SYS_GETPPID 64
 
+#define SYS_SIGACTION 67
+
+#define SYS_SETRLIMIT 75
+
+#define SYS_GETRLIMIT 76
+
+#define SYS_GETRUSAGE 77
+
 #define SYS_SYMLINK 83
 
 #define SYS_MMAP 90
 
+#define SYS_MUNMAP 91
+
+#define SYS_TRUNCATE 92
+
 #define SYS_FTRUNCATE 93
 
+#define SYS_SYSLOG 103
+
 #define SYS_FSTAT 108
 
+#define SYS_SIGRETURN 119
+
+#define SYS_SIGPROCMASK 126
+
+#define SYS_GETPGID 132
+
 #define SYS_LSEEK64 140
 
 #define SYS_GETDENTS 141
 
+#define SYS_FLOCK 143
+
+#define SYS_MSYNC 144
+
 #define SYS_NANOSLEEP 162
 
+#define SYS_SCHED_SETPARAM 154
+
+#define SYS_SCHED_GETPARAM 155
+
 #define SYS_SCHED_YIELD 158
 
+#define SYS_PRCTL 172
+
+#define SYS_RT_SIGSUSPEND 179
+
 #define SYS_GETCWD 183
 
+#define SYS_VFORK 190
+
+#define SYS_GETDENTS64 220
+
+#define SYS_FUTEX 240
+
+#define SYS_FADVISE64 250
+
+#define SYS_EPOLL_CREATE 254
+
+#define SYS_EPOLL_CTL 255
+
+#define SYS_EPOLL_WAIT 256
+
 #define SYS_CLOCK_GETTIME 265
 
+#define SYS_WAITID 284
+
+#define SYS_OPENAT 295
+
+#define SYS_MKDIRAT 296
+
+#define SYS_UNLINKAT 301
+
+#define SYS_SYMLINKAT 304
+
+#define SYS_READLINKAT 305
+
+#define SYS_SIGNALFD4 327
+
 #define SYS_GETRANDOM 355
 
+#define SYS_EXECVEAT 358
+
+/**
+ * On Linux, `shm_open` is done by libc, with files in `/dev/shm`.
+ */
+#define SYS_SHM_OPEN 4096
+
+#define SYS_SHM_UNLINK 4097
+
+#define SYS_SCHED_STATS 4098
+
+#define SYS_THREAD_JOIN 4099
+
+#define SYS_THREAD_DETACH 4100
+
+/**
+ * Unlike Linux's `fallocate`, this doesn't take a `mode`.
+ */
+#define SYS_FALLOCATE 4101
+
+/**
+ * Like `getdents64`, but with entries sorted by name.
+ */
+#define SYS_GETDENTS64_SORTED 4102
+
+#define SYS_INTERRUPT_COUNTS 4103
+
+#define SYS_MEM_USAGE 4104
+
 #define S_REGULAR_FILE 1
 
 #define S_SYMLINK 2
 
 #define S_DIRECTORY 3
 
+/**
+ * The highest priority a thread can have.
+ */
+#define SCHED_PRIORITY_MIN -20
+
+/**
+ * The lowest priority a thread can have.
+ */
+#define SCHED_PRIORITY_MAX 19
+
+/**
+ * Number of interrupt vectors, i.e. the most counts `interrupt_counts` can return.
+ */
+#define INTERRUPT_VECTORS 256
+
 #define CLOCK_REALTIME 0
 
 #define CLOCK_MONOTONIC 1
 
+/**
+ * `waitid` id type for waiting on the process whose pid is given. It's the only one supported.
+ */
+#define P_PID 1
+
+/**
+ * `waitid` option to wait for the child to exit. It must be given.
+ */
+#define WEXITED 4
+
+/**
+ * `waitid` option to leave the child to be waited on again, rather than reaping it.
+ */
+#define WNOWAIT 16777216
+
+#define SIGINT 2
+
+#define SIGKILL 9
+
+#define SIGSEGV 11
+
+#define SIGTERM 15
+
+#define SIGCHLD 17
+
+#define SIGXCPU 24
+
+/**
+ * One more than the highest signal number.
+ */
+#define NSIG 32
+
+/**
+ * `sigprocmask` operation to block the signals given, along with those already blocked.
+ */
+#define SIG_BLOCK 0
+
+/**
+ * `sigprocmask` operation to unblock the signals given.
+ */
+#define SIG_UNBLOCK 1
+
+/**
+ * `sigprocmask` operation to block exactly the signals given.
+ */
+#define SIG_SETMASK 2
+
+/**
+ * `SigAction::sa_handler` for a signal's default action.
+ */
+#define SIG_DFL 0
+
+/**
+ * `SigAction::sa_handler` for ignoring a signal.
+ */
+#define SIG_IGN 1
+
+/**
+ * `SigAction::sa_flags` flag saying `sa_restorer` is set.
+ */
+#define SA_RESTORER 67108864
+
+/**
+ * `SigAction::sa_flags` flag to leave the signal unblocked while its handler runs.
+ */
+#define SA_NODEFER 1073741824
+
+/**
+ * `signalfd` flag to make reads fail with `EAGAIN` rather than wait when no signal is pending.
+ */
+#define SFD_NONBLOCK 2048
+
+/**
+ * `SigInfo::si_code` for a child which exited by itself.
+ */
+#define CLD_EXITED 1
+
+/**
+ * `SigInfo::si_code` for a child which was killed.
+ */
+#define CLD_KILLED 2
+
+/**
+ * `futex` operation to wait until woken, if the word still has the value given.
+ */
+#define FUTEX_WAIT 0
+
+/**
+ * `futex` operation to wake up to the number of waiters given.
+ */
+#define FUTEX_WAKE 1
+
+/**
+ * `futex` flag saying the word isn't shared with other processes, which makes no difference here.
+ */
+#define FUTEX_PRIVATE_FLAG 128
+
+/**
+ * `epoll_ctl` operation to start waiting for a file.
+ */
+#define EPOLL_CTL_ADD 1
+
+/**
+ * `epoll_ctl` operation to stop waiting for a file.
+ */
+#define EPOLL_CTL_DEL 2
+
+/**
+ * `epoll_ctl` operation to change the events being waited for on a file.
+ */
+#define EPOLL_CTL_MOD 3
+
+/**
+ * The file can be read from without blocking.
+ */
+#define EPOLLIN 1
+
+/**
+ * The file can be written to without blocking.
+ */
+#define EPOLLOUT 4
+
+/**
+ * Writing to the file will fail, e.g. because a pipe has no read ends. Always waited for.
+ */
+#define EPOLLERR 8
+
+/**
+ * The other end of the file has been closed, e.g. a pipe has no write ends. Always waited for.
+ */
+#define EPOLLHUP 16
+
 #define PROT_READ 1
 
 #define PROT_WRITE 2
 
 #define PROT_EXEC 4
 
+/**
+ * `mmap` flag to write changes to the mapping back to the file, with `msync` or `munmap`, or when
+ * the process exits.
+ */
+#define MAP_SHARED 1
+
+/**
+ * `mmap` flag to keep changes to the mapping to this process. This is the default.
+ */
+#define MAP_PRIVATE 2
+
+/**
+ * `msync` flag to start writing back changes. They're always written back before it returns.
+ */
+#define MS_ASYNC 1
+
+/**
+ * `msync` flag to invalidate other mappings of the file, which does nothing.
+ */
+#define MS_INVALIDATE 2
+
+/**
+ * `msync` flag to write back changes before returning.
+ */
+#define MS_SYNC 4
+
+/**
+ * `posix_fadvise` advice that there's nothing special about how the file will be read.
+ */
+#define POSIX_FADV_NORMAL 0
+
+/**
+ * `posix_fadvise` advice that the file will be read in no particular order.
+ */
+#define POSIX_FADV_RANDOM 1
+
+/**
+ * `posix_fadvise` advice that the file will be read from start to end.
+ */
+#define POSIX_FADV_SEQUENTIAL 2
+
+/**
+ * `posix_fadvise` advice that the range will be read soon.
+ */
+#define POSIX_FADV_WILLNEED 3
+
+/**
+ * `posix_fadvise` advice that the range won't be read again soon.
+ */
+#define POSIX_FADV_DONTNEED 4
+
+/**
+ * `posix_fadvise` advice that the range will only be read once.
+ */
+#define POSIX_FADV_NOREUSE 5
+
+/**
+ * `flock` operation to take a shared lock, which other file descriptors can hold at the same time.
+ */
+#define LOCK_SH 1
+
+/**
+ * `flock` operation to take an exclusive lock, which no other file descriptor can hold at the same
+ * time.
+ */
+#define LOCK_EX 2
+
+/**
+ * `flock` flag to fail with `EWOULDBLOCK` rather than waiting for a conflicting lock to be released.
+ */
+#define LOCK_NB 4
+
+/**
+ * `flock` operation to release the lock.
+ */
+#define LOCK_UN 8
+
+/**
+ * ioctl request to get the foreground process group of a terminal.
+ */
+#define TIOCGPGRP 21519
+
+/**
+ * ioctl request to set the foreground process group of a terminal.
+ */
+#define TIOCSPGRP 21520
+
+/**
+ * `prctl` option to set the calling process' name from a null-terminated string, which is
+ * truncated to `TASK_COMM_LEN - 1` bytes.
+ */
+#define PR_SET_NAME 15
+
+/**
+ * `prctl` option to get the calling process' name, into a buffer of `TASK_COMM_LEN` bytes.
+ */
+#define PR_GET_NAME 16
+
+/**
+ * Size of a process name, including the null terminator.
+ */
+#define TASK_COMM_LEN 16
+
+/**
+ * `syslog` action to read the most recent kernel log output, as much as fits in the buffer.
+ */
+#define SYSLOG_ACTION_READ_ALL 3
+
+/**
+ * Like `SYSLOG_ACTION_READ_ALL`, then clear the kernel log.
+ */
+#define SYSLOG_ACTION_READ_CLEAR 4
+
+/**
+ * `syslog` action to clear the kernel log.
+ */
+#define SYSLOG_ACTION_CLEAR 5
+
+/**
+ * `syslog` action returning the size of the kernel log's buffer.
+ */
+#define SYSLOG_ACTION_SIZE_BUFFER 10
+
 typedef uint16_t Pid;
 
 typedef struct Stat {
@@ -172,15 +628,193 @@ typedef struct Dirent {
   uint8_t name[0];
 } Dirent;
 
+typedef struct Dirent64 {
+  uint64_t d_ino;
+  /**
+   * Opaque offset value to be used with seekdir.
+   */
+  int64_t d_off;
+  /**
+   * Length of this directory entry in bytes.
+   */
+  uint16_t d_reclen;
+  uint8_t d_type;
+  /**
+   * Null-terminated file name
+   */
+  uint8_t d_name[0];
+} Dirent64;
+
+/**
+ * How a child process exited, as filled in by `waitid`.
+ */
+typedef struct SigInfo {
+  /**
+   * Always `SIGCHLD`.
+   */
+  int32_t si_signo;
+  /**
+   * `CLD_EXITED` if the child exited by itself, or `CLD_KILLED` if it was killed.
+   */
+  int32_t si_code;
+  Pid si_pid;
+  /**
+   * The child's exit code if it exited by itself, or the signal which killed it otherwise.
+   */
+  int32_t si_status;
+} SigInfo;
+
+/**
+ * Resource limit, as used by `getrlimit` and `setrlimit`.
+ */
+typedef struct RLimit {
+  /**
+   * Soft limit, which the process can change as long as it stays at most `rlim_max`.
+   */
+  uintptr_t rlim_cur;
+  /**
+   * Hard limit.
+   */
+  uintptr_t rlim_max;
+} RLimit;
+
+/**
+ * Resources used by a process or thread, as returned by `getrusage`.
+ */
+typedef struct RUsage {
+  /**
+   * Timer ticks spent running, in user or kernel mode.
+   */
+  uintptr_t cpu_ticks;
+  /**
+   * Page faults, e.g. from touching memory which is mapped in lazily.
+   */
+  uintptr_t page_faults;
+  /**
+   * Times the CPU was given up, e.g. with `sched_yield` or by waiting.
+   */
+  uintptr_t voluntary_switches;
+  /**
+   * Times the CPU was taken away, e.g. by the timer.
+   */
+  uintptr_t involuntary_switches;
+} RUsage;
+
+/**
+ * A file an `epoll` instance is interested in, or one which is ready.
+ */
+typedef struct EpollEvent {
+  /**
+   * The events (`EPOLLIN`, `EPOLLOUT`, ...) being waited for, or which are ready.
+   */
+  uint32_t events;
+  /**
+   * Passed back by `epoll_wait` as is, e.g. to say which file is ready.
+   */
+  uint64_t data;
+} EpollEvent;
+
+/**
+ * A set of signals, as used by `sigprocmask` and `signalfd`: bit `n - 1` is set for signal `n`.
+ */
+typedef uint32_t SigSet;
+
+/**
+ * What to do when a signal is delivered, as set with `sigaction`.
+ */
+typedef struct SigAction {
+  /**
+   * `SIG_DFL`, `SIG_IGN`, or the address of a function to call with the signal number.
+   */
+  uintptr_t sa_handler;
+  /**
+   * Signals to block while the handler runs, along with the signal itself.
+   */
+  SigSet sa_mask;
+  /**
+   * `SA_RESTORER`, which is required along with a handler, and optionally `SA_NODEFER`.
+   */
+  uint32_t sa_flags;
+  /**
+   * Where the handler returns to, which must call `sigreturn` without touching the stack. The
+   * `sigaction` wrapper fills this in.
+   */
+  uintptr_t sa_restorer;
+} SigAction;
+
 typedef struct Timespec {
   int64_t tv_sec;
   int64_t tv_nsec;
 } Timespec;
 
+/**
+ * Scheduling parameters, as used by `sched_setparam` and `sched_getparam`.
+ */
+typedef struct SchedParam {
+  /**
+   * From `SCHED_PRIORITY_MIN` (highest priority) to `SCHED_PRIORITY_MAX` (lowest), like a nice
+   * value.
+   */
+  int32_t sched_priority;
+} SchedParam;
+
+/**
+ * Scheduler counters, as returned by `sched_stats`.
+ */
+typedef struct SchedStats {
+  /**
+   * Switches from one thread to another since boot.
+   */
+  uintptr_t context_switches;
+  /**
+   * Threads waiting in the scheduler, including blocked ones.
+   */
+  uintptr_t run_queue_length;
+  /**
+   * Times a thread gave up the CPU itself, e.g. with `sched_yield` or by waiting.
+   */
+  uintptr_t voluntary_yields;
+  /**
+   * Times an interrupt (e.g. the timer) took the CPU away from a thread.
+   */
+  uintptr_t preemptions;
+  /**
+   * Times the idle thread halted the CPU because no other thread was ready.
+   */
+  uintptr_t idle_halts;
+} SchedStats;
+
+/**
+ * Memory used by a process, as returned by `mem_usage`.
+ */
+typedef struct MemUsage {
+  /**
+   * Resident set size: bytes of memory mapped into the process, counting shared pages in full.
+   */
+  uintptr_t rss;
+  /**
+   * Proportional set size: like `rss`, but each page is split evenly between everything
+   * mapping it, so a page mapped twice only counts for half as much each time.
+   */
+  uintptr_t pss;
+} MemUsage;
+
 void exit(int32_t code);
 
 Pid fork(void);
 
+/**
+ * Start a new process which shares the calling process' memory, then suspend the calling
+ * thread until the new process calls `execve` or `exit`. Returns 0 in the new process, and
+ * its pid in the calling one.
+ *
+ * # Safety
+ *
+ * The new process runs on the caller's stack, so until it execs or exits it mustn't return
+ * from the function which called `vfork`, or change anything the caller depends on.
+ */
+extern Pid vfork(void);
+
 int32_t read(int32_t fd, uint8_t *buffer, uintptr_t count);
 
 int32_t write(int32_t fd, const uint8_t *buffer, uintptr_t count);
@@ -195,6 +829,12 @@ int32_t getcwd(int8_t *buf, uintptr_t size);
 
 int32_t chdir(const char *path);
 
+/**
+ * Make `path` the root directory of this process, so that absolute paths start from it, and `..`
+ * can't go above it. The working directory is moved to the new root too.
+ */
+int32_t chroot(const char *path);
+
 int32_t mkdir(const char *path);
 
 int32_t fstat(int32_t fd, struct Stat *statbuf);
@@ -209,10 +849,51 @@ int32_t rename(const char *source, const char *dest);
 
 int32_t rmdir(const char *path);
 
+/**
+ * Like [`open`], but a relative `path` is relative to the directory open as `dirfd` (or the
+ * working directory if it's `AT_FDCWD`). The other `*at` syscalls treat `dirfd` the same way.
+ */
+int32_t openat(int32_t dirfd, const char *path, uintptr_t flags);
+
+int32_t mkdirat(int32_t dirfd, const char *path);
+
+/**
+ * Like [`unlink`], or [`rmdir`] if `flags` is `AT_REMOVEDIR`.
+ */
+int32_t unlinkat(int32_t dirfd, const char *path, int32_t flags);
+
+int32_t symlinkat(const char *source, int32_t dirfd, const char *dest);
+
+/**
+ * Copy the destination of the symlink at `path` into `buf`, truncated to `size` bytes. It isn't
+ * null-terminated. Returns the number of bytes copied.
+ */
+int32_t readlinkat(int32_t dirfd, const char *path, char *buf, uintptr_t size);
+
 int32_t getdents(int32_t fd, struct Dirent *output, uintptr_t size);
 
+int32_t getdents64(int32_t fd, struct Dirent64 *output, uintptr_t size);
+
+/**
+ * Like [`getdents64`], but the entries come back sorted by name. Each entry's `d_off` is its
+ * position in that order. If the directory changes between calls, entries may be skipped or
+ * repeated.
+ */
+int32_t getdents64_sorted(int32_t fd, struct Dirent64 *output, uintptr_t size);
+
+/**
+ * Truncate or extend the file at `path` to `size` bytes, like `ftruncate` without opening it.
+ */
+int32_t truncate(const char *path, uint64_t size);
+
 int32_t ftruncate(int32_t fd, uint64_t size);
 
+/**
+ * Allocate space for `len` bytes of `fd` starting at `offset`, so writing to them can't fail for
+ * lack of space. The file grows if that goes past its end, but never shrinks.
+ */
+int32_t fallocate(int32_t fd, uint64_t offset, uint64_t len);
+
 int32_t sync(void);
 
 int32_t unmount(const char *path);
@@ -221,26 +902,289 @@ int32_t mount(const char *device, const char *target, const char *filesystem_typ
 
 Pid waitpid(Pid pid, int32_t *stat, int32_t options);
 
+/**
+ * Whether `status`, from `waitpid`, is for a process which exited by itself, rather than being
+ * killed by a signal.
+ */
+bool wifexited(int32_t status);
+
+/**
+ * The exit code in `status`, from `waitpid`, if `wifexited(status)`.
+ */
+int32_t wifexitstatus(int32_t status);
+
+/**
+ * Whether `status`, from `waitpid`, is for a process which was killed by a signal.
+ */
+bool wifsignaled(int32_t status);
+
+/**
+ * The signal in `status`, from `waitpid`, which killed the process, if `wifsignaled(status)`.
+ */
+int32_t wtermsig(int32_t status);
+
+/**
+ * Wait for the process `id` to exit, if `idtype` is `P_PID`, filling in `infop` with how it
+ * exited. `options` must include `WEXITED`, and can include `WNOWAIT` to leave it to be waited on
+ * again.
+ */
+int32_t waitid(int32_t idtype, Pid id, struct SigInfo *infop, int32_t options);
+
 int32_t dup(int32_t fd);
 
 int32_t dup2(int32_t old_fd, int32_t new_fd);
 
+/**
+ * Get the limits on the resource `resource` for this process. Only `RLIMIT_NOFILE`,
+ * `RLIMIT_CPU` and `RLIMIT_AS` are supported.
+ */
+int32_t getrlimit(int32_t resource, struct RLimit *rlim);
+
+/**
+ * Set the limits on the resource `resource` for this process. Only `RLIMIT_NOFILE`,
+ * `RLIMIT_CPU` and `RLIMIT_AS` are supported, and only the hard limit of `RLIMIT_CPU` can be
+ * changed.
+ */
+int32_t setrlimit(int32_t resource, const struct RLimit *rlim);
+
+/**
+ * Get the resources used by the calling process (`RUSAGE_SELF`) or thread (`RUSAGE_THREAD`).
+ */
+int32_t getrusage(int32_t who, struct RUsage *usage);
+
 int32_t pipe(int32_t *fds);
 
+/**
+ * Wait or wake on the word at `uaddr`, depending on `op`:
+ * - `FUTEX_WAIT` waits until woken, if the word still has the value `val`.
+ *   Otherwise it returns `-EAGAIN` straight away.
+ * - `FUTEX_WAKE` wakes up to `val` of the threads waiting on the word, and returns how many it
+ *   woke.
+ */
+int32_t futex(uint32_t *uaddr, int32_t op, uint32_t val);
+
+/**
+ * Open the shared memory object called `name`, which is `/` followed by at least one other
+ * character. With `O_CREATE` in `flags`, it's created (with a size of 0) if it doesn't exist.
+ *
+ * Set its size with `ftruncate`, then `mmap` it to share memory with other processes.
+ */
+int32_t shm_open(const char *name, uintptr_t flags);
+
+/**
+ * Remove the name of the shared memory object called `name`. It's freed once nothing has it open
+ * or mapped.
+ */
+int32_t shm_unlink(const char *name);
+
+/**
+ * Advise the kernel how the `len` bytes of `fd` starting at `offset` are going to be read, with
+ * one of the `POSIX_FADV_*` constants. Unlike libc's, this returns the negated error number.
+ */
+int32_t posix_fadvise(int32_t fd, uint64_t offset, uintptr_t len, int32_t advice);
+
+/**
+ * Take a shared (`LOCK_SH`) or exclusive (`LOCK_EX`) advisory lock on the file open as `fd`, or
+ * release it (`LOCK_UN`). Waits for conflicting locks to be released, unless `LOCK_NB` is set.
+ */
+int32_t flock(int32_t fd, int32_t operation);
+
+/**
+ * Create an `epoll` instance, which can wait for any of a set of files to be ready. `size` is
+ * ignored, but must be positive.
+ */
+int32_t epoll_create(int32_t size);
+
+/**
+ * Add (`EPOLL_CTL_ADD`), change (`EPOLL_CTL_MOD`) or remove (`EPOLL_CTL_DEL`) `fd` from the files
+ * the `epoll` instance `epfd` is waiting for. `event` is ignored when removing.
+ */
+int32_t epoll_ctl(int32_t epfd, int32_t op, int32_t fd, const struct EpollEvent *event);
+
+/**
+ * Wait for any of the files the `epoll` instance `epfd` is waiting for to be ready, and write
+ * up to `max_events` of the ones which are to `events`. Returns how many were written.
+ *
+ * `timeout` is in milliseconds, after which 0 is returned if nothing is ready. A `timeout` of 0
+ * returns straight away, and a negative one waits as long as it takes.
+ */
+int32_t epoll_wait(int32_t epfd, struct EpollEvent *events, int32_t max_events, int32_t timeout);
+
+/**
+ * Send the signal `sig` to the process `pid`. A `sig` of 0 just checks that the process exists.
+ * Sending to a process group (a `pid` of 0 or less) isn't supported yet.
+ */
+int32_t kill(int32_t pid, int32_t sig);
+
+/**
+ * Change which signals the calling process has blocked, as given by `how` (`SIG_BLOCK`,
+ * `SIG_UNBLOCK` or `SIG_SETMASK`) and `set`, unless `set` is null. The signals which were blocked
+ * before are written to `oldset`, unless it's null. `SIGKILL` can't be blocked.
+ */
+int32_t sigprocmask(int32_t how, const SigSet *set, SigSet *oldset);
+
+extern void __kidneyos_sigreturn(void);
+
+/**
+ * Change what happens when the signal `sig` is delivered to `act`, unless it's null. What used to
+ * happen is written to `oldact`, unless it's null. `SIGKILL`'s action can't be changed.
+ *
+ * A handler is called with the signal number, then returns to whatever was interrupted.
+ */
+int32_t sigaction(int32_t sig, const struct SigAction *act, struct SigAction *oldact);
+
+/**
+ * Create a file which can be read to receive the signals in `mask`, once they're pending for the
+ * calling process, as `SignalfdSiginfo`s. The signals should be blocked with `sigprocmask`, so
+ * that they stay pending until they're read. `fd` must be -1, and `flags` can include
+ * `SFD_NONBLOCK`.
+ */
+int32_t signalfd(int32_t fd, const SigSet *mask, int32_t flags);
+
+/**
+ * Sleep until a signal which isn't blocked or ignored is delivered. Always returns `-EINTR`.
+ */
+int32_t pause(void);
+
+/**
+ * Sleep like `pause`, with just the signals in `mask` blocked until a signal is delivered. The
+ * signals which were blocked before are blocked again once any handler returns. Always returns
+ * `-EINTR`.
+ */
+int32_t sigsuspend(const SigSet *mask);
+
 int32_t execve(const char *filename, const char *const *argv, const char *const *envp);
 
+/**
+ * Execute the program at `pathname`, relative to the directory open as `dirfd` (or the working
+ * directory if it's `AT_FDCWD`). With `AT_EMPTY_PATH` in `flags` and an empty `pathname`, execute
+ * the file open as `dirfd` itself.
+ */
+int32_t execveat(int32_t dirfd,
+                 const char *pathname,
+                 const char *const *argv,
+                 const char *const *envp,
+                 int32_t flags);
+
+/**
+ * Execute the program open as `fd`.
+ */
+int32_t fexecve(int32_t fd, const char *const *argv, const char *const *envp);
+
+/**
+ * Sleep for `duration`. Nothing interrupts the sleep, so `remainder` is never written to.
+ */
 int32_t nanosleep(const struct Timespec *duration, struct Timespec *remainder);
 
 Pid getpid(void);
 
 Pid getppid(void);
 
+int32_t setpgid(Pid pid, Pid pgid);
+
+int32_t getpgid(Pid pid);
+
+int32_t ioctl(int32_t fd, uintptr_t request, void *arg);
+
+/**
+ * Returns the foreground process group of the terminal `fd`, or a negative error code.
+ */
+int32_t tcgetpgrp(int32_t fd);
+
+/**
+ * Makes `pgrp` the foreground process group of the terminal `fd`,
+ * so that it's interrupted when Ctrl-C is typed.
+ */
+int32_t tcsetpgrp(int32_t fd, Pid pgrp);
+
 int32_t scheduler_yield(void);
 
+/**
+ * Set the scheduling parameters of the calling thread. `pid` must be 0 or the calling process.
+ * The priority is clamped to the valid range.
+ */
+int32_t sched_setparam(Pid pid, const struct SchedParam *param);
+
+/**
+ * Get the scheduling parameters of the calling thread. `pid` must be 0 or the calling process.
+ */
+int32_t sched_getparam(Pid pid, struct SchedParam *param);
+
+/**
+ * Add `increment` to the calling thread's priority, clamping it to the valid range.
+ */
+int32_t nice(int32_t increment);
+
+/**
+ * Operations on the calling process. Only `PR_SET_NAME` and `PR_GET_NAME` are supported, which
+ * take a pointer to the name as `arg2`.
+ */
+int32_t prctl(int32_t option, uintptr_t arg2);
+
+/**
+ * Read or clear the kernel log, depending on `action` (one of the `SYSLOG_ACTION_*` constants),
+ * like `dmesg`. Reading copies the most recent output into `buf`, up to `len` bytes, and returns
+ * how many bytes were copied.
+ */
+int32_t klogctl(int32_t action, char *buf, int32_t len);
+
+/**
+ * Wait for the thread `tid` in this process to exit, storing its exit code in `retval` unless
+ * it's null. A thread can only be joined once, and not at all once it's been detached.
+ */
+int32_t thread_join(Pid tid, int32_t *retval);
+
+/**
+ * Have the thread `tid` in this process cleaned up as soon as it exits, rather than keeping its
+ * exit code for `thread_join`.
+ */
+int32_t thread_detach(Pid tid);
+
+/**
+ * Get counters from the scheduler, for debugging and tuning it.
+ */
+int32_t sched_stats(struct SchedStats *stats);
+
+/**
+ * Get the number of times each interrupt vector has fired, for debugging. `counts[v]` is set for
+ * each vector `v` below `len`, up to `INTERRUPT_VECTORS`. Returns how many counts were set.
+ */
+int32_t interrupt_counts(uintptr_t *counts, uintptr_t len);
+
+/**
+ * Get how much memory the calling process' stack, heap and memory mappings are using. Memory
+ * loaded from the executable isn't counted.
+ */
+int32_t mem_usage(struct MemUsage *usage);
+
 int32_t clock_gettime(int32_t clock_id, struct Timespec *timespec);
 
 int32_t getrandom(int8_t *buf, uintptr_t size, uintptr_t flags);
 
 void *mmap(void *addr, uintptr_t length, int32_t prot, int32_t flags, int32_t fd, int64_t offset);
 
+/**
+ * Remove the mappings in `addr..addr + length`, writing back any changes to `MAP_SHARED` file
+ * mappings first. Mappings can only be removed whole.
+ */
+int32_t munmap(void *addr, uintptr_t length);
+
+/**
+ * Write changes to the `MAP_SHARED` file mappings in `addr..addr + length` back to their files.
+ */
+int32_t msync(void *addr, uintptr_t length, int32_t flags);
+
+/**
+ * Sets the program break to `addr`, returning the new program break.
+ * If the break couldn't be moved, the current break is returned instead,
+ * so `brk(null)` can be used to query it.
+ */
+void *brk(void *addr);
+
+/**
+ * Moves the program break by `increment` bytes, returning the previous break,
+ * or `(void *)-1` if the heap couldn't be resized.
+ */
+void *sbrk(intptr_t increment);
+
 #endif  /* KIDNEYOS_SYSCALLS_H */
@@ -23,6 +23,125 @@ pub struct Dirent {
     pub name: [u8; 0],
 }
 
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Dirent64 {
+    pub d_ino: u64,
+    /// Opaque offset value to be used with seekdir.
+    pub d_off: i64,
+    /// Length of this directory entry in bytes.
+    pub d_reclen: u16,
+    pub d_type: u8,
+    /// Null-terminated file name
+    pub d_name: [u8; 0],
+}
+
+/// Resource limit, as used by `getrlimit` and `setrlimit`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct RLimit {
+    /// Soft limit, which the process can change as long as it stays at most `rlim_max`.
+    pub rlim_cur: usize,
+    /// Hard limit.
+    pub rlim_max: usize,
+}
+
+/// Scheduling parameters, as used by `sched_setparam` and `sched_getparam`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SchedParam {
+    /// From `SCHED_PRIORITY_MIN` (highest priority) to `SCHED_PRIORITY_MAX` (lowest), like a nice
+    /// value.
+    pub sched_priority: i32,
+}
+
+/// Scheduler counters, as returned by `sched_stats`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct SchedStats {
+    /// Switches from one thread to another since boot.
+    pub context_switches: usize,
+    /// Threads waiting in the scheduler, including blocked ones.
+    pub run_queue_length: usize,
+    /// Times a thread gave up the CPU itself, e.g. with `sched_yield` or by waiting.
+    pub voluntary_yields: usize,
+    /// Times an interrupt (e.g. the timer) took the CPU away from a thread.
+    pub preemptions: usize,
+    /// Times the idle thread halted the CPU because no other thread was ready.
+    pub idle_halts: usize,
+}
+
+/// Resources used by a process or thread, as returned by `getrusage`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RUsage {
+    /// Timer ticks spent running, in user or kernel mode.
+    pub cpu_ticks: usize,
+    /// Page faults, e.g. from touching memory which is mapped in lazily.
+    pub page_faults: usize,
+    /// Times the CPU was given up, e.g. with `sched_yield` or by waiting.
+    pub voluntary_switches: usize,
+    /// Times the CPU was taken away, e.g. by the timer.
+    pub involuntary_switches: usize,
+}
+
+/// Memory used by a process, as returned by `mem_usage`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MemUsage {
+    /// Resident set size: bytes of memory mapped into the process, counting shared pages in full.
+    pub rss: usize,
+    /// Proportional set size: like `rss`, but each page is split evenly between everything
+    /// mapping it, so a page mapped twice only counts for half as much each time.
+    pub pss: usize,
+}
+
+/// How a child process exited, as filled in by `waitid`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SigInfo {
+    /// Always `SIGCHLD`.
+    pub si_signo: i32,
+    /// `CLD_EXITED` if the child exited by itself, or `CLD_KILLED` if it was killed.
+    pub si_code: i32,
+    pub si_pid: crate::Pid,
+    /// The child's exit code if it exited by itself, or the signal which killed it otherwise.
+    pub si_status: i32,
+}
+
+/// What to do when a signal is delivered, as set with `sigaction`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SigAction {
+    /// `SIG_DFL`, `SIG_IGN`, or the address of a function to call with the signal number.
+    pub sa_handler: usize,
+    /// Signals to block while the handler runs, along with the signal itself.
+    pub sa_mask: SigSet,
+    /// `SA_RESTORER`, which is required along with a handler, and optionally `SA_NODEFER`.
+    pub sa_flags: u32,
+    /// Where the handler returns to, which must call `sigreturn` without touching the stack. The
+    /// `sigaction` wrapper fills this in.
+    pub sa_restorer: usize,
+}
+
+/// A signal read from a `signalfd`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SignalfdSiginfo {
+    /// The signal which was pending.
+    pub ssi_signo: u32,
+}
+
+/// A file an `epoll` instance is interested in, or one which is ready.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct EpollEvent {
+    /// The events (`EPOLLIN`, `EPOLLOUT`, ...) being waited for, or which are ready.
+    pub events: u32,
+    /// Passed back by `epoll_wait` as is, e.g. to say which file is ready.
+    pub data: u64,
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
 pub struct MMapOptions {
@@ -35,15 +154,52 @@ pub struct MMapOptions {
 }
 
 pub const O_CREATE: usize = 0x40;
+/// With `O_CREATE`, fail with `EEXIST` if the file already exists, rather than opening it.
+pub const O_EXCL: usize = 0x80;
+
+/// Special directory file descriptor for `*at` syscalls, meaning the working directory.
+pub const AT_FDCWD: i32 = -100;
+/// `*at` syscall flag to operate on the directory file descriptor itself if the path is empty.
+pub const AT_EMPTY_PATH: i32 = 0x1000;
+/// `unlinkat` flag to remove a directory, as `rmdir` does, rather than a file.
+pub const AT_REMOVEDIR: i32 = 0x200;
+
+/// Maximum length of a path passed to a syscall, including the null terminator.
+pub const PATH_MAX: usize = 4096;
 
 pub const SEEK_SET: i32 = 0;
 pub const SEEK_CUR: i32 = 1;
 pub const SEEK_END: i32 = 2;
 
+/// `getrlimit`/`setrlimit` resource for the number of open files (one more than the highest file
+/// descriptor a process can open).
+pub const RLIMIT_NOFILE: i32 = 7;
+/// `getrlimit`/`setrlimit` resource for CPU time, in seconds. A process which uses more than its
+/// soft limit is killed, exiting with 128 + 24, as if by SIGXCPU.
+pub const RLIMIT_CPU: i32 = 0;
+/// `getrlimit`/`setrlimit` resource for the size of a process' address space in bytes: the sum of
+/// the sizes of its stack, heap and memory mappings, but not the program itself. Mapping memory
+/// (or growing the heap) past the soft limit fails. The hard limit is always `RLIM_INFINITY`.
+pub const RLIMIT_AS: i32 = 9;
+/// Resource limit meaning there's no limit.
+pub const RLIM_INFINITY: usize = usize::MAX;
+
+/// `getrusage` target for the calling process, including its threads which have exited.
+pub const RUSAGE_SELF: i32 = 0;
+/// `getrusage` target for just the calling thread.
+pub const RUSAGE_THREAD: i32 = 1;
+
+pub const EPERM: isize = 1;
 pub const ENOENT: isize = 2;
+pub const ESRCH: isize = 3;
+pub const EINTR: isize = 4;
 pub const EIO: isize = 5;
 pub const ENOEXEC: isize = 8;
 pub const EBADF: isize = 9;
+pub const ECHILD: isize = 10;
+pub const EAGAIN: isize = 11;
+/// The same as `EAGAIN`, as on Linux.
+pub const EWOULDBLOCK: isize = EAGAIN;
 pub const ENOMEM: isize = 12;
 pub const EFAULT: isize = 14;
 pub const EBUSY: isize = 16;
@@ -54,15 +210,60 @@ pub const ENOTDIR: isize = 20;
 pub const EISDIR: isize = 21;
 pub const EINVAL: isize = 22;
 pub const EMFILE: isize = 24;
+pub const ENOTTY: isize = 25;
 pub const ENOSPC: isize = 28;
 pub const ESPIPE: isize = 29;
 pub const EROFS: isize = 30;
 pub const EMLINK: isize = 31;
 pub const EPIPE: isize = 32;
 pub const ERANGE: isize = 34;
+pub const EDEADLK: isize = 35;
+pub const ENAMETOOLONG: isize = 36;
 pub const ENOSYS: isize = 38;
 pub const ENOTEMPTY: isize = 39;
 pub const ELOOP: isize = 40;
+/// The operation isn't supported, e.g. by the file system. The same as `ENOTSUP`, as on Linux.
+pub const EOPNOTSUPP: isize = 95;
+
+/// The name of the error `errno` (positive, as syscalls return it negated), e.g. `"ENOENT"`, or
+/// `None` if it isn't one of the above. `EWOULDBLOCK` is `"EAGAIN"`, since they're the same.
+pub const fn errno_name(errno: isize) -> Option<&'static str> {
+    Some(match errno {
+        EPERM => "EPERM",
+        ENOENT => "ENOENT",
+        ESRCH => "ESRCH",
+        EINTR => "EINTR",
+        EIO => "EIO",
+        ENOEXEC => "ENOEXEC",
+        EBADF => "EBADF",
+        ECHILD => "ECHILD",
+        EAGAIN => "EAGAIN",
+        ENOMEM => "ENOMEM",
+        EFAULT => "EFAULT",
+        EBUSY => "EBUSY",
+        EEXIST => "EEXIST",
+        EXDEV => "EXDEV",
+        ENODEV => "ENODEV",
+        ENOTDIR => "ENOTDIR",
+        EISDIR => "EISDIR",
+        EINVAL => "EINVAL",
+        EMFILE => "EMFILE",
+        ENOTTY => "ENOTTY",
+        ENOSPC => "ENOSPC",
+        ESPIPE => "ESPIPE",
+        EROFS => "EROFS",
+        EMLINK => "EMLINK",
+        EPIPE => "EPIPE",
+        ERANGE => "ERANGE",
+        EDEADLK => "EDEADLK",
+        ENAMETOOLONG => "ENAMETOOLONG",
+        ENOSYS => "ENOSYS",
+        ENOTEMPTY => "ENOTEMPTY",
+        ELOOP => "ELOOP",
+        EOPNOTSUPP => "EOPNOTSUPP",
+        _ => return None,
+    })
+}
 
 pub const SYS_EXIT: usize = 0x1;
 pub const SYS_FORK: usize = 0x2;
@@ -78,33 +279,213 @@ pub const SYS_CHDIR: usize = 0xc;
 pub const SYS_GETPID: usize = 0x14;
 pub const SYS_MOUNT: usize = 0x15;
 pub const SYS_UNMOUNT: usize = 0x16;
+pub const SYS_PAUSE: usize = 0x1d;
+pub const SYS_NICE: usize = 0x22;
 pub const SYS_SYNC: usize = 0x24;
+pub const SYS_KILL: usize = 0x25;
 pub const SYS_RENAME: usize = 0x26;
 pub const SYS_MKDIR: usize = 0x27;
 pub const SYS_RMDIR: usize = 0x28;
 pub const SYS_DUP: usize = 0x29;
 pub const SYS_PIPE: usize = 0x2A;
+pub const SYS_BRK: usize = 0x2D;
+pub const SYS_IOCTL: usize = 0x36;
+pub const SYS_SETPGID: usize = 0x39;
+pub const SYS_CHROOT: usize = 0x3d;
 pub const SYS_DUP2: usize = 0x3F;
 pub const SYS_GETPPID: usize = 0x40;
+pub const SYS_SIGACTION: usize = 0x43;
+pub const SYS_SETRLIMIT: usize = 0x4b;
+pub const SYS_GETRLIMIT: usize = 0x4c;
+pub const SYS_GETRUSAGE: usize = 0x4d;
 pub const SYS_SYMLINK: usize = 0x53;
 pub const SYS_MMAP: usize = 0x5a;
+pub const SYS_MUNMAP: usize = 0x5b;
+pub const SYS_TRUNCATE: usize = 0x5c;
 pub const SYS_FTRUNCATE: usize = 0x5d;
+pub const SYS_SYSLOG: usize = 0x67;
 pub const SYS_FSTAT: usize = 0x6c;
+pub const SYS_SIGRETURN: usize = 0x77;
+pub const SYS_SIGPROCMASK: usize = 0x7e;
+pub const SYS_GETPGID: usize = 0x84;
 pub const SYS_LSEEK64: usize = 0x8c;
 pub const SYS_GETDENTS: usize = 0x8d;
+pub const SYS_FLOCK: usize = 0x8f;
+pub const SYS_MSYNC: usize = 0x90;
 pub const SYS_NANOSLEEP: usize = 0xa2;
+pub const SYS_SCHED_SETPARAM: usize = 0x9a;
+pub const SYS_SCHED_GETPARAM: usize = 0x9b;
 pub const SYS_SCHED_YIELD: usize = 0x9e;
+pub const SYS_PRCTL: usize = 0xac;
+pub const SYS_RT_SIGSUSPEND: usize = 0xb3;
 pub const SYS_GETCWD: usize = 0xb7;
+pub const SYS_VFORK: usize = 0xbe;
+pub const SYS_GETDENTS64: usize = 0xdc;
+pub const SYS_FUTEX: usize = 0xf0;
+pub const SYS_FADVISE64: usize = 0xfa;
+pub const SYS_EPOLL_CREATE: usize = 0xfe;
+pub const SYS_EPOLL_CTL: usize = 0xff;
+pub const SYS_EPOLL_WAIT: usize = 0x100;
 pub const SYS_CLOCK_GETTIME: usize = 0x109;
+pub const SYS_WAITID: usize = 0x11c;
+pub const SYS_OPENAT: usize = 0x127;
+pub const SYS_MKDIRAT: usize = 0x128;
+pub const SYS_UNLINKAT: usize = 0x12d;
+pub const SYS_SYMLINKAT: usize = 0x130;
+pub const SYS_READLINKAT: usize = 0x131;
+pub const SYS_SIGNALFD4: usize = 0x147;
 pub const SYS_GETRANDOM: usize = 0x163;
+pub const SYS_EXECVEAT: usize = 0x166;
+// KidneyOS-specific syscalls, numbered well past Linux's
+/// On Linux, `shm_open` is done by libc, with files in `/dev/shm`.
+pub const SYS_SHM_OPEN: usize = 0x1000;
+pub const SYS_SHM_UNLINK: usize = 0x1001;
+pub const SYS_SCHED_STATS: usize = 0x1002;
+pub const SYS_THREAD_JOIN: usize = 0x1003;
+pub const SYS_THREAD_DETACH: usize = 0x1004;
+/// Unlike Linux's `fallocate`, this doesn't take a `mode`.
+pub const SYS_FALLOCATE: usize = 0x1005;
+/// Like `getdents64`, but with entries sorted by name.
+pub const SYS_GETDENTS64_SORTED: usize = 0x1006;
+pub const SYS_INTERRUPT_COUNTS: usize = 0x1007;
+pub const SYS_MEM_USAGE: usize = 0x1008;
 
 pub const S_REGULAR_FILE: u8 = 1;
 pub const S_SYMLINK: u8 = 2;
 pub const S_DIRECTORY: u8 = 3;
 
+/// The highest priority a thread can have.
+pub const SCHED_PRIORITY_MIN: i32 = -20;
+/// The lowest priority a thread can have.
+pub const SCHED_PRIORITY_MAX: i32 = 19;
+
+/// Number of interrupt vectors, i.e. the most counts `interrupt_counts` can return.
+pub const INTERRUPT_VECTORS: usize = 256;
+
 pub const CLOCK_REALTIME: usize = 0;
 pub const CLOCK_MONOTONIC: usize = 1;
 
+/// `waitid` id type for waiting on the process whose pid is given. It's the only one supported.
+pub const P_PID: i32 = 1;
+/// `waitid` option to wait for the child to exit. It must be given.
+pub const WEXITED: i32 = 4;
+/// `waitid` option to leave the child to be waited on again, rather than reaping it.
+pub const WNOWAIT: i32 = 0x0100_0000;
+
+// Processes killed by a signal exit with 128 plus the signal, which `waitid` reports. Unless a
+// handler is installed with `sigaction`, every signal but `SIGCHLD` kills the process it's sent to.
+pub const SIGINT: i32 = 2;
+pub const SIGKILL: i32 = 9;
+pub const SIGSEGV: i32 = 11;
+pub const SIGTERM: i32 = 15;
+pub const SIGCHLD: i32 = 17;
+pub const SIGXCPU: i32 = 24;
+/// One more than the highest signal number.
+pub const NSIG: i32 = 32;
+/// A set of signals, as used by `sigprocmask` and `signalfd`: bit `n - 1` is set for signal `n`.
+pub type SigSet = u32;
+/// `sigprocmask` operation to block the signals given, along with those already blocked.
+pub const SIG_BLOCK: i32 = 0;
+/// `sigprocmask` operation to unblock the signals given.
+pub const SIG_UNBLOCK: i32 = 1;
+/// `sigprocmask` operation to block exactly the signals given.
+pub const SIG_SETMASK: i32 = 2;
+/// `SigAction::sa_handler` for a signal's default action.
+pub const SIG_DFL: usize = 0;
+/// `SigAction::sa_handler` for ignoring a signal.
+pub const SIG_IGN: usize = 1;
+/// `SigAction::sa_flags` flag saying `sa_restorer` is set.
+pub const SA_RESTORER: u32 = 0x0400_0000;
+/// `SigAction::sa_flags` flag to leave the signal unblocked while its handler runs.
+pub const SA_NODEFER: u32 = 0x4000_0000;
+/// `signalfd` flag to make reads fail with `EAGAIN` rather than wait when no signal is pending.
+pub const SFD_NONBLOCK: i32 = 0o4000;
+/// `SigInfo::si_code` for a child which exited by itself.
+pub const CLD_EXITED: i32 = 1;
+/// `SigInfo::si_code` for a child which was killed.
+pub const CLD_KILLED: i32 = 2;
+
+/// `futex` operation to wait until woken, if the word still has the value given.
+pub const FUTEX_WAIT: i32 = 0;
+/// `futex` operation to wake up to the number of waiters given.
+pub const FUTEX_WAKE: i32 = 1;
+/// `futex` flag saying the word isn't shared with other processes, which makes no difference here.
+pub const FUTEX_PRIVATE_FLAG: i32 = 128;
+
+/// `epoll_ctl` operation to start waiting for a file.
+pub const EPOLL_CTL_ADD: i32 = 1;
+/// `epoll_ctl` operation to stop waiting for a file.
+pub const EPOLL_CTL_DEL: i32 = 2;
+/// `epoll_ctl` operation to change the events being waited for on a file.
+pub const EPOLL_CTL_MOD: i32 = 3;
+
+/// The file can be read from without blocking.
+pub const EPOLLIN: u32 = 0x1;
+/// The file can be written to without blocking.
+pub const EPOLLOUT: u32 = 0x4;
+/// Writing to the file will fail, e.g. because a pipe has no read ends. Always waited for.
+pub const EPOLLERR: u32 = 0x8;
+/// The other end of the file has been closed, e.g. a pipe has no write ends. Always waited for.
+pub const EPOLLHUP: u32 = 0x10;
+
 pub const PROT_READ: i32 = 1;
 pub const PROT_WRITE: i32 = 2;
 pub const PROT_EXEC: i32 = 4;
+
+/// `mmap` flag to write changes to the mapping back to the file, with `msync` or `munmap`, or when
+/// the process exits.
+pub const MAP_SHARED: i32 = 0x01;
+/// `mmap` flag to keep changes to the mapping to this process. This is the default.
+pub const MAP_PRIVATE: i32 = 0x02;
+
+/// `msync` flag to start writing back changes. They're always written back before it returns.
+pub const MS_ASYNC: i32 = 1;
+/// `msync` flag to invalidate other mappings of the file, which does nothing.
+pub const MS_INVALIDATE: i32 = 2;
+/// `msync` flag to write back changes before returning.
+pub const MS_SYNC: i32 = 4;
+
+/// `posix_fadvise` advice that there's nothing special about how the file will be read.
+pub const POSIX_FADV_NORMAL: i32 = 0;
+/// `posix_fadvise` advice that the file will be read in no particular order.
+pub const POSIX_FADV_RANDOM: i32 = 1;
+/// `posix_fadvise` advice that the file will be read from start to end.
+pub const POSIX_FADV_SEQUENTIAL: i32 = 2;
+/// `posix_fadvise` advice that the range will be read soon.
+pub const POSIX_FADV_WILLNEED: i32 = 3;
+/// `posix_fadvise` advice that the range won't be read again soon.
+pub const POSIX_FADV_DONTNEED: i32 = 4;
+/// `posix_fadvise` advice that the range will only be read once.
+pub const POSIX_FADV_NOREUSE: i32 = 5;
+
+/// `flock` operation to take a shared lock, which other file descriptors can hold at the same time.
+pub const LOCK_SH: i32 = 1;
+/// `flock` operation to take an exclusive lock, which no other file descriptor can hold at the same
+/// time.
+pub const LOCK_EX: i32 = 2;
+/// `flock` flag to fail with `EWOULDBLOCK` rather than waiting for a conflicting lock to be released.
+pub const LOCK_NB: i32 = 4;
+/// `flock` operation to release the lock.
+pub const LOCK_UN: i32 = 8;
+
+/// ioctl request to get the foreground process group of a terminal.
+pub const TIOCGPGRP: usize = 0x540F;
+/// ioctl request to set the foreground process group of a terminal.
+pub const TIOCSPGRP: usize = 0x5410;
+
+/// `prctl` option to set the calling process' name from a null-terminated string, which is
+/// truncated to `TASK_COMM_LEN - 1` bytes.
+pub const PR_SET_NAME: i32 = 15;
+/// `prctl` option to get the calling process' name, into a buffer of `TASK_COMM_LEN` bytes.
+pub const PR_GET_NAME: i32 = 16;
+/// Size of a process name, including the null terminator.
+pub const TASK_COMM_LEN: usize = 16;
+
+/// `syslog` action to read the most recent kernel log output, as much as fits in the buffer.
+pub const SYSLOG_ACTION_READ_ALL: i32 = 3;
+/// Like `SYSLOG_ACTION_READ_ALL`, then clear the kernel log.
+pub const SYSLOG_ACTION_READ_CLEAR: i32 = 4;
+/// `syslog` action to clear the kernel log.
+pub const SYSLOG_ACTION_CLEAR: i32 = 5;
+/// `syslog` action returning the size of the kernel log's buffer.
+pub const SYSLOG_ACTION_SIZE_BUFFER: i32 = 10;
@@ -1,6 +1,6 @@
 #![no_std]
 
-use core::arch::asm;
+use core::arch::{asm, global_asm};
 use core::ffi::{c_char, c_void};
 
 pub type Pid = u16;
@@ -43,6 +43,30 @@ pub extern "C" fn fork() -> Pid {
     result as Pid
 }
 
+// The child returns from vfork first, and can overwrite the return address on the stack before
+// the parent does, so it's kept in ecx, which the syscall preserves for both of them. This can't
+// be a Rust function, since the child would return through its stack frame too.
+global_asm!(
+    ".globl vfork",
+    "vfork:",
+    "pop ecx",
+    "mov eax, 0xbe",
+    "int 0x80",
+    "jmp ecx",
+);
+
+extern "C" {
+    /// Start a new process which shares the calling process' memory, then suspend the calling
+    /// thread until the new process calls `execve` or `exit`. Returns 0 in the new process, and
+    /// its pid in the calling one.
+    ///
+    /// # Safety
+    ///
+    /// The new process runs on the caller's stack, so until it execs or exits it mustn't return
+    /// from the function which called `vfork`, or change anything the caller depends on.
+    pub fn vfork() -> Pid;
+}
+
 #[no_mangle]
 pub extern "C" fn read(fd: i32, buffer: *mut u8, count: usize) -> i32 {
     let result;
@@ -127,6 +151,19 @@ pub extern "C" fn chdir(path: *const c_char) -> i32 {
     result
 }
 
+/// Make `path` the root directory of this process, so that absolute paths start from it, and `..`
+/// can't go above it. The working directory is moved to the new root too.
+#[no_mangle]
+pub extern "C" fn chroot(path: *const c_char) -> i32 {
+    let result;
+    unsafe {
+        asm!("
+            int 0x80
+        ", in("eax") SYS_CHROOT, in("ebx") path, lateout("eax") result);
+    }
+    result
+}
+
 #[no_mangle]
 pub extern "C" fn mkdir(path: *const c_char) -> i32 {
     let result;
@@ -204,6 +241,80 @@ pub extern "C" fn rmdir(path: *const c_char) -> i32 {
     result
 }
 
+/// Like [`open`], but a relative `path` is relative to the directory open as `dirfd` (or the
+/// working directory if it's `AT_FDCWD`). The other `*at` syscalls treat `dirfd` the same way.
+#[no_mangle]
+pub extern "C" fn openat(dirfd: i32, path: *const c_char, flags: usize) -> i32 {
+    let result;
+    unsafe {
+        asm!("
+            int 0x80
+        ", in("eax") SYS_OPENAT, in("ebx") dirfd, in("ecx") path, in("edx") flags, lateout("eax") result);
+    }
+    result
+}
+
+#[no_mangle]
+pub extern "C" fn mkdirat(dirfd: i32, path: *const c_char) -> i32 {
+    let result;
+    unsafe {
+        asm!("
+            int 0x80
+        ", in("eax") SYS_MKDIRAT, in("ebx") dirfd, in("ecx") path, lateout("eax") result);
+    }
+    result
+}
+
+/// Like [`unlink`], or [`rmdir`] if `flags` is `AT_REMOVEDIR`.
+#[no_mangle]
+pub extern "C" fn unlinkat(dirfd: i32, path: *const c_char, flags: i32) -> i32 {
+    let result;
+    unsafe {
+        asm!("
+            int 0x80
+        ", in("eax") SYS_UNLINKAT, in("ebx") dirfd, in("ecx") path, in("edx") flags, lateout("eax") result);
+    }
+    result
+}
+
+#[no_mangle]
+pub extern "C" fn symlinkat(source: *const c_char, dirfd: i32, dest: *const c_char) -> i32 {
+    let result;
+    unsafe {
+        asm!("
+            int 0x80
+        ", in("eax") SYS_SYMLINKAT, in("ebx") source, in("ecx") dirfd, in("edx") dest, lateout("eax") result);
+    }
+    result
+}
+
+/// Copy the destination of the symlink at `path` into `buf`, truncated to `size` bytes. It isn't
+/// null-terminated. Returns the number of bytes copied.
+#[no_mangle]
+pub extern "C" fn readlinkat(
+    dirfd: i32,
+    path: *const c_char,
+    buf: *mut c_char,
+    size: usize,
+) -> i32 {
+    let result;
+    unsafe {
+        // LLVM reserves esi, so swap it in and out around the call ourselves
+        asm!(
+            "xchg esi, {size}",
+            "int 0x80",
+            "xchg esi, {size}",
+            size = in(reg) size,
+            in("eax") SYS_READLINKAT,
+            in("ebx") dirfd,
+            in("ecx") path,
+            in("edx") buf,
+            lateout("eax") result
+        );
+    }
+    result
+}
+
 #[no_mangle]
 pub extern "C" fn getdents(fd: i32, output: *mut Dirent, size: usize) -> i32 {
     let result;
@@ -215,6 +326,46 @@ pub extern "C" fn getdents(fd: i32, output: *mut Dirent, size: usize) -> i32 {
     result
 }
 
+#[no_mangle]
+pub extern "C" fn getdents64(fd: i32, output: *mut Dirent64, size: usize) -> i32 {
+    let result;
+    unsafe {
+        asm!("
+            int 0x80
+        ", in("eax") SYS_GETDENTS64, in("ebx") fd, in("ecx") output, in("edx") size, lateout("eax") result);
+    }
+    result
+}
+
+/// Like [`getdents64`], but the entries come back sorted by name. Each entry's `d_off` is its
+/// position in that order. If the directory changes between calls, entries may be skipped or
+/// repeated.
+#[no_mangle]
+pub extern "C" fn getdents64_sorted(fd: i32, output: *mut Dirent64, size: usize) -> i32 {
+    let result;
+    unsafe {
+        asm!("
+            int 0x80
+        ", in("eax") SYS_GETDENTS64_SORTED, in("ebx") fd, in("ecx") output, in("edx") size, lateout("eax") result);
+    }
+    result
+}
+
+/// Truncate or extend the file at `path` to `size` bytes, like `ftruncate` without opening it.
+#[no_mangle]
+pub extern "C" fn truncate(path: *const c_char, size: u64) -> i32 {
+    let result;
+    #[allow(clippy::cast_possible_truncation)]
+    let size_lo = size as u32;
+    let size_hi = (size >> 32) as u32;
+    unsafe {
+        asm!("
+            int 0x80
+        ", in("eax") SYS_TRUNCATE, in("ebx") path, in("ecx") size_lo, in("edx") size_hi, lateout("eax") result);
+    }
+    result
+}
+
 #[no_mangle]
 pub extern "C" fn ftruncate(fd: i32, size: u64) -> i32 {
     let result;
@@ -229,6 +380,35 @@ pub extern "C" fn ftruncate(fd: i32, size: u64) -> i32 {
     result
 }
 
+/// Allocate space for `len` bytes of `fd` starting at `offset`, so writing to them can't fail for
+/// lack of space. The file grows if that goes past its end, but never shrinks.
+#[no_mangle]
+pub extern "C" fn fallocate(fd: i32, offset: u64, len: u64) -> i32 {
+    let result;
+    #[allow(clippy::cast_possible_truncation)]
+    let offset_lo = offset as u32;
+    let offset_hi = (offset >> 32) as u32;
+    #[allow(clippy::cast_possible_truncation)]
+    let len_lo = len as u32;
+    let len_hi = (len >> 32) as u32;
+    unsafe {
+        // LLVM reserves esi, so swap it in and out around the call ourselves
+        asm!(
+            "xchg esi, {len_lo}",
+            "int 0x80",
+            "xchg esi, {len_lo}",
+            len_lo = in(reg) len_lo,
+            in("eax") SYS_FALLOCATE,
+            in("ebx") fd,
+            in("ecx") offset_lo,
+            in("edx") offset_hi,
+            in("edi") len_hi,
+            lateout("eax") result
+        );
+    }
+    result
+}
+
 #[no_mangle]
 pub extern "C" fn sync() -> i32 {
     let result;
@@ -282,6 +462,56 @@ pub extern "C" fn waitpid(pid: Pid, stat: *mut i32, options: i32) -> Pid {
     result as Pid
 }
 
+/// Whether `status`, from `waitpid`, is for a process which exited by itself, rather than being
+/// killed by a signal.
+#[no_mangle]
+pub extern "C" fn wifexited(status: i32) -> bool {
+    status & 0x7f == 0
+}
+
+/// The exit code in `status`, from `waitpid`, if `wifexited(status)`.
+#[no_mangle]
+pub extern "C" fn wifexitstatus(status: i32) -> i32 {
+    (status >> 8) & 0xff
+}
+
+/// Whether `status`, from `waitpid`, is for a process which was killed by a signal.
+#[no_mangle]
+pub extern "C" fn wifsignaled(status: i32) -> bool {
+    status & 0x7f != 0
+}
+
+/// The signal in `status`, from `waitpid`, which killed the process, if `wifsignaled(status)`.
+#[no_mangle]
+pub extern "C" fn wtermsig(status: i32) -> i32 {
+    status & 0x7f
+}
+
+/// Wait for the process `id` to exit, if `idtype` is `P_PID`, filling in `infop` with how it
+/// exited. `options` must include `WEXITED`, and can include `WNOWAIT` to leave it to be waited on
+/// again.
+#[no_mangle]
+pub extern "C" fn waitid(idtype: i32, id: Pid, infop: *mut SigInfo, options: i32) -> i32 {
+    let result: i32;
+
+    unsafe {
+        // LLVM reserves esi, so swap it in and out around the call ourselves
+        asm!(
+            "xchg esi, {flags}",
+            "int 0x80",
+            "xchg esi, {flags}",
+            flags = in(reg) options,
+            in("eax") SYS_WAITID,
+            in("ebx") idtype,
+            in("ecx") id as usize,
+            in("edx") infop,
+            lateout("eax") result,
+        );
+    }
+
+    result
+}
+
 #[no_mangle]
 pub extern "C" fn dup(fd: i32) -> i32 {
     let result: i32;
@@ -314,6 +544,63 @@ pub extern "C" fn dup2(old_fd: i32, new_fd: i32) -> i32 {
 
     result
 }
+
+/// Get the limits on the resource `resource` for this process. Only `RLIMIT_NOFILE`,
+/// `RLIMIT_CPU` and `RLIMIT_AS` are supported.
+#[no_mangle]
+pub extern "C" fn getrlimit(resource: i32, rlim: *mut RLimit) -> i32 {
+    let result: i32;
+
+    unsafe {
+        asm!(
+            "int 0x80",
+            in("eax") SYS_GETRLIMIT,
+            in("ebx") resource,
+            in("ecx") rlim,
+            lateout("eax") result,
+        );
+    }
+
+    result
+}
+
+/// Set the limits on the resource `resource` for this process. Only `RLIMIT_NOFILE`,
+/// `RLIMIT_CPU` and `RLIMIT_AS` are supported, and only the hard limit of `RLIMIT_CPU` can be
+/// changed.
+#[no_mangle]
+pub extern "C" fn setrlimit(resource: i32, rlim: *const RLimit) -> i32 {
+    let result: i32;
+
+    unsafe {
+        asm!(
+            "int 0x80",
+            in("eax") SYS_SETRLIMIT,
+            in("ebx") resource,
+            in("ecx") rlim,
+            lateout("eax") result,
+        );
+    }
+
+    result
+}
+
+/// Get the resources used by the calling process (`RUSAGE_SELF`) or thread (`RUSAGE_THREAD`).
+#[no_mangle]
+pub extern "C" fn getrusage(who: i32, usage: *mut RUsage) -> i32 {
+    let result: i32;
+
+    unsafe {
+        asm!(
+            "int 0x80",
+            in("eax") SYS_GETRUSAGE,
+            in("ebx") who,
+            in("ecx") usage,
+            lateout("eax") result,
+        );
+    }
+
+    result
+}
 #[no_mangle]
 pub extern "C" fn pipe(fds: *mut i32) -> i32 {
     let result: i32;
@@ -330,131 +617,752 @@ pub extern "C" fn pipe(fds: *mut i32) -> i32 {
     result
 }
 
+/// Wait or wake on the word at `uaddr`, depending on `op`:
+/// - `FUTEX_WAIT` waits until woken, if the word still has the value `val`.
+///   Otherwise it returns `-EAGAIN` straight away.
+/// - `FUTEX_WAKE` wakes up to `val` of the threads waiting on the word, and returns how many it
+///   woke.
 #[no_mangle]
-pub extern "C" fn execve(
-    filename: *const c_char,
-    argv: *const *const c_char,
-    envp: *const *const c_char,
-) -> i32 {
+pub extern "C" fn futex(uaddr: *mut u32, op: i32, val: u32) -> i32 {
     let result: i32;
 
     unsafe {
         asm!(
             "int 0x80",
-            in("eax") SYS_EXECVE,
-            in("ebx") filename,
-            in("ecx") argv,
-            in("edx") envp,
-            lateout("eax") result
-        )
+            in("eax") SYS_FUTEX,
+            in("ebx") uaddr,
+            in("ecx") op,
+            in("edx") val,
+            lateout("eax") result,
+        );
     }
 
     result
 }
 
-// Seems to reference __kernel_timespec as the inputs for this syscall.
-// Not sure if we have this implemented.
+/// Open the shared memory object called `name`, which is `/` followed by at least one other
+/// character. With `O_CREATE` in `flags`, it's created (with a size of 0) if it doesn't exist.
+///
+/// Set its size with `ftruncate`, then `mmap` it to share memory with other processes.
 #[no_mangle]
-pub extern "C" fn nanosleep(duration: *const Timespec, remainder: *mut Timespec) -> i32 {
+pub extern "C" fn shm_open(name: *const c_char, flags: usize) -> i32 {
     let result: i32;
+
     unsafe {
         asm!(
-            "
-            mov eax, 0xA2
-            int 0x80
-            ", 
-            in("ebx") duration,
-            in("ecx") remainder,
+            "int 0x80",
+            in("eax") SYS_SHM_OPEN,
+            in("ebx") name,
+            in("ecx") flags,
             lateout("eax") result,
         );
     }
+
     result
 }
 
+/// Remove the name of the shared memory object called `name`. It's freed once nothing has it open
+/// or mapped.
 #[no_mangle]
-#[allow(clippy::cast_possible_truncation)]
-pub extern "C" fn getpid() -> Pid {
+pub extern "C" fn shm_unlink(name: *const c_char) -> i32 {
     let result: i32;
+
     unsafe {
         asm!(
-            "
-            mov eax, 0x14
-            int 0x80
-            ",
-            lateout("eax") result
-        )
+            "int 0x80",
+            in("eax") SYS_SHM_UNLINK,
+            in("ebx") name,
+            lateout("eax") result,
+        );
     }
-    result as Pid
+
+    result
 }
 
+/// Advise the kernel how the `len` bytes of `fd` starting at `offset` are going to be read, with
+/// one of the `POSIX_FADV_*` constants. Unlike libc's, this returns the negated error number.
 #[no_mangle]
-#[allow(clippy::cast_possible_truncation)]
-pub extern "C" fn getppid() -> Pid {
-    let result: i32;
+pub extern "C" fn posix_fadvise(fd: i32, offset: u64, len: usize, advice: i32) -> i32 {
+    let result;
+    #[allow(clippy::cast_possible_truncation)]
+    let offset_lo = offset as u32;
+    let offset_hi = (offset >> 32) as u32;
     unsafe {
+        // LLVM reserves esi, so swap it in and out around the call ourselves
         asm!(
-            "
-            mov eax, 0x40
-            int 0x80
-            ",
+            "xchg esi, {len}",
+            "int 0x80",
+            "xchg esi, {len}",
+            len = in(reg) len,
+            in("eax") SYS_FADVISE64,
+            in("ebx") fd,
+            in("ecx") offset_lo,
+            in("edx") offset_hi,
+            in("edi") advice,
             lateout("eax") result
-        )
+        );
     }
-    result as Pid
+    result
 }
 
+/// Take a shared (`LOCK_SH`) or exclusive (`LOCK_EX`) advisory lock on the file open as `fd`, or
+/// release it (`LOCK_UN`). Waits for conflicting locks to be released, unless `LOCK_NB` is set.
 #[no_mangle]
-pub extern "C" fn scheduler_yield() -> i32 {
+pub extern "C" fn flock(fd: i32, operation: i32) -> i32 {
     let result: i32;
+
     unsafe {
         asm!(
-            "
-            mov eax, 0x9E
-            int 0x80
-            ", 
+            "int 0x80",
+            in("eax") SYS_FLOCK,
+            in("ebx") fd,
+            in("ecx") operation,
             lateout("eax") result,
         );
     }
+
     result
 }
 
+/// Create an `epoll` instance, which can wait for any of a set of files to be ready. `size` is
+/// ignored, but must be positive.
 #[no_mangle]
-pub extern "C" fn clock_gettime(clock_id: i32, timespec: *mut Timespec) -> i32 {
+pub extern "C" fn epoll_create(size: i32) -> i32 {
     let result: i32;
+
     unsafe {
         asm!(
-            "
-            mov eax, 0x109
-            int 0x80
-            ",
-            in("ebx") clock_id,
-            in("ecx") timespec,
+            "int 0x80",
+            in("eax") SYS_EPOLL_CREATE,
+            in("ebx") size,
             lateout("eax") result,
-        )
+        );
     }
+
     result
 }
 
+/// Add (`EPOLL_CTL_ADD`), change (`EPOLL_CTL_MOD`) or remove (`EPOLL_CTL_DEL`) `fd` from the files
+/// the `epoll` instance `epfd` is waiting for. `event` is ignored when removing.
 #[no_mangle]
-pub extern "C" fn getrandom(buf: *mut i8, size: usize, flags: usize) -> i32 {
+pub extern "C" fn epoll_ctl(epfd: i32, op: i32, fd: i32, event: *const EpollEvent) -> i32 {
     let result: i32;
+
     unsafe {
+        // LLVM reserves esi, so swap it in and out around the call ourselves
         asm!(
-            "
-            mov eax, 0x163
-            int 0x80
-            ",
-            in("ebx") buf,
-            in("ecx") size,
-            in("edx") flags,
+            "xchg esi, {event}",
+            "int 0x80",
+            "xchg esi, {event}",
+            event = in(reg) event,
+            in("eax") SYS_EPOLL_CTL,
+            in("ebx") epfd,
+            in("ecx") op,
+            in("edx") fd,
             lateout("eax") result,
-        )
+        );
     }
+
     result
 }
 
+/// Wait for any of the files the `epoll` instance `epfd` is waiting for to be ready, and write
+/// up to `max_events` of the ones which are to `events`. Returns how many were written.
+///
+/// `timeout` is in milliseconds, after which 0 is returned if nothing is ready. A `timeout` of 0
+/// returns straight away, and a negative one waits as long as it takes.
 #[no_mangle]
-pub extern "C" fn mmap(
+pub extern "C" fn epoll_wait(
+    epfd: i32,
+    events: *mut EpollEvent,
+    max_events: i32,
+    timeout: i32,
+) -> i32 {
+    let result: i32;
+
+    unsafe {
+        // LLVM reserves esi, so swap it in and out around the call ourselves
+        asm!(
+            "xchg esi, {timeout}",
+            "int 0x80",
+            "xchg esi, {timeout}",
+            timeout = in(reg) timeout,
+            in("eax") SYS_EPOLL_WAIT,
+            in("ebx") epfd,
+            in("ecx") events,
+            in("edx") max_events,
+            lateout("eax") result,
+        );
+    }
+
+    result
+}
+
+/// Send the signal `sig` to the process `pid`. A `sig` of 0 just checks that the process exists.
+/// Sending to a process group (a `pid` of 0 or less) isn't supported yet.
+#[no_mangle]
+pub extern "C" fn kill(pid: i32, sig: i32) -> i32 {
+    let result: i32;
+
+    unsafe {
+        asm!(
+            "int 0x80",
+            in("eax") SYS_KILL,
+            in("ebx") pid,
+            in("ecx") sig,
+            lateout("eax") result,
+        );
+    }
+
+    result
+}
+
+/// Change which signals the calling process has blocked, as given by `how` (`SIG_BLOCK`,
+/// `SIG_UNBLOCK` or `SIG_SETMASK`) and `set`, unless `set` is null. The signals which were blocked
+/// before are written to `oldset`, unless it's null. `SIGKILL` can't be blocked.
+#[no_mangle]
+pub extern "C" fn sigprocmask(how: i32, set: *const SigSet, oldset: *mut SigSet) -> i32 {
+    let result: i32;
+
+    unsafe {
+        asm!(
+            "int 0x80",
+            in("eax") SYS_SIGPROCMASK,
+            in("ebx") how,
+            in("ecx") set,
+            in("edx") oldset,
+            lateout("eax") result,
+        );
+    }
+
+    result
+}
+
+// Signal handlers return here. The handler's `ret` leaves the stack pointer where `sigreturn`
+// (0x77) expects it, so this mustn't touch the stack.
+global_asm!(
+    ".globl __kidneyos_sigreturn",
+    "__kidneyos_sigreturn:",
+    "mov eax, 0x77",
+    "int 0x80",
+);
+
+extern "C" {
+    fn __kidneyos_sigreturn();
+}
+
+/// Change what happens when the signal `sig` is delivered to `act`, unless it's null. What used to
+/// happen is written to `oldact`, unless it's null. `SIGKILL`'s action can't be changed.
+///
+/// A handler is called with the signal number, then returns to whatever was interrupted.
+///
+/// # Safety
+///
+/// `act` must be null or point to a `SigAction`, which is read here to add the restorer.
+#[no_mangle]
+pub unsafe extern "C" fn sigaction(sig: i32, act: *const SigAction, oldact: *mut SigAction) -> i32 {
+    let with_restorer;
+    let act = if act.is_null() {
+        act
+    } else {
+        // SAFETY: act isn't null, so the caller promises it points to a SigAction.
+        let act = unsafe { act.read() };
+        with_restorer = SigAction {
+            sa_flags: act.sa_flags | SA_RESTORER,
+            sa_restorer: __kidneyos_sigreturn as usize,
+            ..act
+        };
+        &with_restorer
+    };
+    let result: i32;
+
+    unsafe {
+        asm!(
+            "int 0x80",
+            in("eax") SYS_SIGACTION,
+            in("ebx") sig,
+            in("ecx") act,
+            in("edx") oldact,
+            lateout("eax") result,
+        );
+    }
+
+    result
+}
+
+/// Create a file which can be read to receive the signals in `mask`, once they're pending for the
+/// calling process, as `SignalfdSiginfo`s. The signals should be blocked with `sigprocmask`, so
+/// that they stay pending until they're read. `fd` must be -1, and `flags` can include
+/// `SFD_NONBLOCK`.
+#[no_mangle]
+pub extern "C" fn signalfd(fd: i32, mask: *const SigSet, flags: i32) -> i32 {
+    let result: i32;
+
+    unsafe {
+        // LLVM reserves esi, so swap it in and out around the call ourselves
+        asm!(
+            "xchg esi, {flags}",
+            "int 0x80",
+            "xchg esi, {flags}",
+            flags = in(reg) flags,
+            in("eax") SYS_SIGNALFD4,
+            in("ebx") fd,
+            in("ecx") mask,
+            in("edx") core::mem::size_of::<SigSet>(),
+            lateout("eax") result,
+        );
+    }
+
+    result
+}
+
+/// Sleep until a signal which isn't blocked or ignored is delivered. Always returns `-EINTR`.
+#[no_mangle]
+pub extern "C" fn pause() -> i32 {
+    let result: i32;
+
+    unsafe {
+        asm!(
+            "int 0x80",
+            in("eax") SYS_PAUSE,
+            lateout("eax") result,
+        );
+    }
+
+    result
+}
+
+/// Sleep like `pause`, with just the signals in `mask` blocked until a signal is delivered. The
+/// signals which were blocked before are blocked again once any handler returns. Always returns
+/// `-EINTR`.
+#[no_mangle]
+pub extern "C" fn sigsuspend(mask: *const SigSet) -> i32 {
+    let result: i32;
+
+    unsafe {
+        asm!(
+            "int 0x80",
+            in("eax") SYS_RT_SIGSUSPEND,
+            in("ebx") mask,
+            in("ecx") core::mem::size_of::<SigSet>(),
+            lateout("eax") result,
+        );
+    }
+
+    result
+}
+
+#[no_mangle]
+pub extern "C" fn execve(
+    filename: *const c_char,
+    argv: *const *const c_char,
+    envp: *const *const c_char,
+) -> i32 {
+    let result: i32;
+
+    unsafe {
+        asm!(
+            "int 0x80",
+            in("eax") SYS_EXECVE,
+            in("ebx") filename,
+            in("ecx") argv,
+            in("edx") envp,
+            lateout("eax") result
+        )
+    }
+
+    result
+}
+
+/// Execute the program at `pathname`, relative to the directory open as `dirfd` (or the working
+/// directory if it's `AT_FDCWD`). With `AT_EMPTY_PATH` in `flags` and an empty `pathname`, execute
+/// the file open as `dirfd` itself.
+#[no_mangle]
+pub extern "C" fn execveat(
+    dirfd: i32,
+    pathname: *const c_char,
+    argv: *const *const c_char,
+    envp: *const *const c_char,
+    flags: i32,
+) -> i32 {
+    let result: i32;
+
+    unsafe {
+        // LLVM reserves esi, so swap it in and out around the call ourselves
+        asm!(
+            "xchg esi, {envp}",
+            "int 0x80",
+            "xchg esi, {envp}",
+            envp = in(reg) envp,
+            in("eax") SYS_EXECVEAT,
+            in("ebx") dirfd,
+            in("ecx") pathname,
+            in("edx") argv,
+            in("edi") flags,
+            lateout("eax") result
+        )
+    }
+
+    result
+}
+
+/// Execute the program open as `fd`.
+#[no_mangle]
+pub extern "C" fn fexecve(fd: i32, argv: *const *const c_char, envp: *const *const c_char) -> i32 {
+    execveat(fd, c"".as_ptr(), argv, envp, AT_EMPTY_PATH)
+}
+
+/// Sleep for `duration`. Nothing interrupts the sleep, so `remainder` is never written to.
+#[no_mangle]
+pub extern "C" fn nanosleep(duration: *const Timespec, remainder: *mut Timespec) -> i32 {
+    let result: i32;
+    unsafe {
+        asm!(
+            "
+            mov eax, 0xA2
+            int 0x80
+            ", 
+            in("ebx") duration,
+            in("ecx") remainder,
+            lateout("eax") result,
+        );
+    }
+    result
+}
+
+#[no_mangle]
+#[allow(clippy::cast_possible_truncation)]
+pub extern "C" fn getpid() -> Pid {
+    let result: i32;
+    unsafe {
+        asm!(
+            "
+            mov eax, 0x14
+            int 0x80
+            ",
+            lateout("eax") result
+        )
+    }
+    result as Pid
+}
+
+#[no_mangle]
+#[allow(clippy::cast_possible_truncation)]
+pub extern "C" fn getppid() -> Pid {
+    let result: i32;
+    unsafe {
+        asm!(
+            "
+            mov eax, 0x40
+            int 0x80
+            ",
+            lateout("eax") result
+        )
+    }
+    result as Pid
+}
+
+#[no_mangle]
+pub extern "C" fn setpgid(pid: Pid, pgid: Pid) -> i32 {
+    let result: i32;
+    unsafe {
+        asm!(
+            "int 0x80",
+            in("eax") SYS_SETPGID,
+            in("ebx") pid as usize,
+            in("ecx") pgid as usize,
+            lateout("eax") result,
+        )
+    }
+    result
+}
+
+#[no_mangle]
+pub extern "C" fn getpgid(pid: Pid) -> i32 {
+    let result: i32;
+    unsafe {
+        asm!(
+            "int 0x80",
+            in("eax") SYS_GETPGID,
+            in("ebx") pid as usize,
+            lateout("eax") result,
+        )
+    }
+    result
+}
+
+#[no_mangle]
+pub extern "C" fn ioctl(fd: i32, request: usize, arg: *mut c_void) -> i32 {
+    let result: i32;
+    unsafe {
+        asm!(
+            "int 0x80",
+            in("eax") SYS_IOCTL,
+            in("ebx") fd,
+            in("ecx") request,
+            in("edx") arg,
+            lateout("eax") result,
+        )
+    }
+    result
+}
+
+/// Returns the foreground process group of the terminal `fd`, or a negative error code.
+#[no_mangle]
+pub extern "C" fn tcgetpgrp(fd: i32) -> i32 {
+    let mut pgrp: i32 = 0;
+    let result = ioctl(fd, TIOCGPGRP, core::ptr::addr_of_mut!(pgrp).cast());
+    if result < 0 {
+        return result;
+    }
+    pgrp
+}
+
+/// Makes `pgrp` the foreground process group of the terminal `fd`,
+/// so that it's interrupted when Ctrl-C is typed.
+#[no_mangle]
+pub extern "C" fn tcsetpgrp(fd: i32, pgrp: Pid) -> i32 {
+    let mut pgrp = i32::from(pgrp);
+    ioctl(fd, TIOCSPGRP, core::ptr::addr_of_mut!(pgrp).cast())
+}
+
+#[no_mangle]
+pub extern "C" fn scheduler_yield() -> i32 {
+    let result: i32;
+    unsafe {
+        asm!(
+            "
+            mov eax, 0x9E
+            int 0x80
+            ", 
+            lateout("eax") result,
+        );
+    }
+    result
+}
+
+/// Set the scheduling parameters of the calling thread. `pid` must be 0 or the calling process.
+/// The priority is clamped to the valid range.
+#[no_mangle]
+pub extern "C" fn sched_setparam(pid: Pid, param: *const SchedParam) -> i32 {
+    let result: i32;
+
+    unsafe {
+        asm!(
+            "int 0x80",
+            in("eax") SYS_SCHED_SETPARAM,
+            in("ebx") pid as usize,
+            in("ecx") param,
+            lateout("eax") result,
+        );
+    }
+
+    result
+}
+
+/// Get the scheduling parameters of the calling thread. `pid` must be 0 or the calling process.
+#[no_mangle]
+pub extern "C" fn sched_getparam(pid: Pid, param: *mut SchedParam) -> i32 {
+    let result: i32;
+
+    unsafe {
+        asm!(
+            "int 0x80",
+            in("eax") SYS_SCHED_GETPARAM,
+            in("ebx") pid as usize,
+            in("ecx") param,
+            lateout("eax") result,
+        );
+    }
+
+    result
+}
+
+/// Add `increment` to the calling thread's priority, clamping it to the valid range.
+#[no_mangle]
+pub extern "C" fn nice(increment: i32) -> i32 {
+    let result: i32;
+
+    unsafe {
+        asm!(
+            "int 0x80",
+            in("eax") SYS_NICE,
+            in("ebx") increment,
+            lateout("eax") result,
+        );
+    }
+
+    result
+}
+
+/// Operations on the calling process. Only `PR_SET_NAME` and `PR_GET_NAME` are supported, which
+/// take a pointer to the name as `arg2`.
+#[no_mangle]
+pub extern "C" fn prctl(option: i32, arg2: usize) -> i32 {
+    let result: i32;
+
+    unsafe {
+        asm!(
+            "int 0x80",
+            in("eax") SYS_PRCTL,
+            in("ebx") option,
+            in("ecx") arg2,
+            lateout("eax") result,
+        );
+    }
+
+    result
+}
+
+/// Read or clear the kernel log, depending on `action` (one of the `SYSLOG_ACTION_*` constants),
+/// like `dmesg`. Reading copies the most recent output into `buf`, up to `len` bytes, and returns
+/// how many bytes were copied.
+#[no_mangle]
+pub extern "C" fn klogctl(action: i32, buf: *mut c_char, len: i32) -> i32 {
+    let result: i32;
+
+    unsafe {
+        asm!(
+            "int 0x80",
+            in("eax") SYS_SYSLOG,
+            in("ebx") action,
+            in("ecx") buf,
+            in("edx") len,
+            lateout("eax") result,
+        );
+    }
+
+    result
+}
+
+/// Wait for the thread `tid` in this process to exit, storing its exit code in `retval` unless
+/// it's null. A thread can only be joined once, and not at all once it's been detached.
+#[no_mangle]
+pub extern "C" fn thread_join(tid: Pid, retval: *mut i32) -> i32 {
+    let result: i32;
+
+    unsafe {
+        asm!(
+            "int 0x80",
+            in("eax") SYS_THREAD_JOIN,
+            in("ebx") tid as usize,
+            in("ecx") retval,
+            lateout("eax") result,
+        );
+    }
+
+    result
+}
+
+/// Have the thread `tid` in this process cleaned up as soon as it exits, rather than keeping its
+/// exit code for `thread_join`.
+#[no_mangle]
+pub extern "C" fn thread_detach(tid: Pid) -> i32 {
+    let result: i32;
+
+    unsafe {
+        asm!(
+            "int 0x80",
+            in("eax") SYS_THREAD_DETACH,
+            in("ebx") tid as usize,
+            lateout("eax") result,
+        );
+    }
+
+    result
+}
+
+/// Get counters from the scheduler, for debugging and tuning it.
+#[no_mangle]
+pub extern "C" fn sched_stats(stats: *mut SchedStats) -> i32 {
+    let result: i32;
+
+    unsafe {
+        asm!(
+            "int 0x80",
+            in("eax") SYS_SCHED_STATS,
+            in("ebx") stats,
+            lateout("eax") result,
+        );
+    }
+
+    result
+}
+
+/// Get the number of times each interrupt vector has fired, for debugging. `counts[v]` is set for
+/// each vector `v` below `len`, up to `INTERRUPT_VECTORS`. Returns how many counts were set.
+#[no_mangle]
+pub extern "C" fn interrupt_counts(counts: *mut usize, len: usize) -> i32 {
+    let result: i32;
+
+    unsafe {
+        asm!(
+            "int 0x80",
+            in("eax") SYS_INTERRUPT_COUNTS,
+            in("ebx") counts,
+            in("ecx") len,
+            lateout("eax") result,
+        );
+    }
+
+    result
+}
+
+/// Get how much memory the calling process' stack, heap and memory mappings are using. Memory
+/// loaded from the executable isn't counted.
+#[no_mangle]
+pub extern "C" fn mem_usage(usage: *mut MemUsage) -> i32 {
+    let result: i32;
+
+    unsafe {
+        asm!(
+            "int 0x80",
+            in("eax") SYS_MEM_USAGE,
+            in("ebx") usage,
+            lateout("eax") result,
+        );
+    }
+
+    result
+}
+
+#[no_mangle]
+pub extern "C" fn clock_gettime(clock_id: i32, timespec: *mut Timespec) -> i32 {
+    let result: i32;
+    unsafe {
+        asm!(
+            "
+            mov eax, 0x109
+            int 0x80
+            ",
+            in("ebx") clock_id,
+            in("ecx") timespec,
+            lateout("eax") result,
+        )
+    }
+    result
+}
+
+#[no_mangle]
+pub extern "C" fn getrandom(buf: *mut i8, size: usize, flags: usize) -> i32 {
+    let result: i32;
+    unsafe {
+        asm!(
+            "
+            mov eax, 0x163
+            int 0x80
+            ",
+            in("ebx") buf,
+            in("ecx") size,
+            in("edx") flags,
+            lateout("eax") result,
+        )
+    }
+    result
+}
+
+#[no_mangle]
+pub extern "C" fn mmap(
     addr: *mut c_void,
     length: usize,
     prot: i32,
@@ -483,3 +1391,69 @@ pub extern "C" fn mmap(
     }
     result
 }
+
+/// Remove the mappings in `addr..addr + length`, writing back any changes to `MAP_SHARED` file
+/// mappings first. Mappings can only be removed whole.
+#[no_mangle]
+pub extern "C" fn munmap(addr: *mut c_void, length: usize) -> i32 {
+    let result: i32;
+    unsafe {
+        asm!(
+            "int 0x80",
+            in("eax") SYS_MUNMAP,
+            in("ebx") addr,
+            in("ecx") length,
+            lateout("eax") result,
+        )
+    }
+    result
+}
+
+/// Write changes to the `MAP_SHARED` file mappings in `addr..addr + length` back to their files.
+#[no_mangle]
+pub extern "C" fn msync(addr: *mut c_void, length: usize, flags: i32) -> i32 {
+    let result: i32;
+    unsafe {
+        asm!(
+            "int 0x80",
+            in("eax") SYS_MSYNC,
+            in("ebx") addr,
+            in("ecx") length,
+            in("edx") flags,
+            lateout("eax") result,
+        )
+    }
+    result
+}
+
+/// Sets the program break to `addr`, returning the new program break.
+/// If the break couldn't be moved, the current break is returned instead,
+/// so `brk(null)` can be used to query it.
+#[no_mangle]
+pub extern "C" fn brk(addr: *mut c_void) -> *mut c_void {
+    let result: *mut c_void;
+    unsafe {
+        asm!(
+            "int 0x80",
+            in("eax") SYS_BRK,
+            in("ebx") addr,
+            lateout("eax") result,
+        )
+    }
+    result
+}
+
+/// Moves the program break by `increment` bytes, returning the previous break,
+/// or `(void *)-1` if the heap couldn't be resized.
+#[no_mangle]
+pub extern "C" fn sbrk(increment: isize) -> *mut c_void {
+    let old = brk(core::ptr::null_mut());
+    if increment == 0 {
+        return old;
+    }
+    let new = old.wrapping_byte_offset(increment);
+    if brk(new) != new {
+        return usize::MAX as *mut c_void;
+    }
+    old
+}
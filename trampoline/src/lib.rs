@@ -5,7 +5,7 @@
 
 mod multiboot2;
 
-use core::{arch::asm, ptr::NonNull};
+use core::{arch::asm, ffi::CStr, ptr::NonNull};
 use kidneyos_shared::{
     global_descriptor_table,
     mem::{
@@ -25,6 +25,9 @@ use multiboot2::{
     EXPECTED_MAGIC,
 };
 
+/// The longest kernel command line passed on to the kernel; any more is cut off.
+const MAX_CMDLINE_LEN: usize = 256;
+
 #[cfg(target_os = "none")]
 #[panic_handler]
 fn panic(args: &core::panic::PanicInfo) -> ! {
@@ -64,6 +67,21 @@ unsafe extern "C" fn trampoline(magic: usize, multiboot2_info: *mut Info) {
         })
         .expect("Didn't find memory info!");
 
+    // The multiboot info won't be mapped once paging is enabled, so the command line is copied
+    // into this stack frame, which stays mapped for the kernel, and is never popped.
+    let mut cmdline = [0; MAX_CMDLINE_LEN];
+    let cmdline_len = (*multiboot2_info)
+        .iter()
+        .find_map(|tag| match tag {
+            InfoTag::Commandline(t) => Some(<&CStr>::from(t).to_bytes()),
+            _ => None,
+        })
+        .map_or(0, |bytes| {
+            let len = bytes.len().min(MAX_CMDLINE_LEN);
+            cmdline[..len].copy_from_slice(&bytes[..len]);
+            len
+        });
+
     println!("Setting up GDTR");
     global_descriptor_table::load();
     println!("GDTR set up!");
@@ -106,7 +124,12 @@ unsafe extern "C" fn trampoline(magic: usize, multiboot2_info: *mut Info) {
     println!("Starting kernel...");
 
     extern "C" {
-        fn main(mem_upper: usize, video_memory_skip_lines: usize) -> !;
+        fn main(
+            mem_upper: usize,
+            video_memory_skip_lines: usize,
+            cmdline: *const u8,
+            cmdline_len: usize,
+        ) -> !;
     }
 
     asm!(
@@ -114,8 +137,12 @@ unsafe extern "C" fn trampoline(magic: usize, multiboot2_info: *mut Info) {
         add esp, {offset} // make stack a kernel virtual address
         push {}
         push {}
+        push {}
+        push {}
         call {}
         ",
+        in(reg) cmdline_len,
+        in(reg) cmdline.as_ptr() as usize + OFFSET,
         in(reg) VIDEO_MEMORY_WRITER.cursor.div_ceil(VIDEO_MEMORY_COLS),
         in(reg) mem_upper as usize,
         sym main,